@@ -0,0 +1,652 @@
+//! Little-endian cursor helpers used to decode and encode the variable-length
+//! buffers returned by the native APIs.
+//!
+//! The typed wrappers are built on these, and they are public so that callers
+//! of the raw bindings can decode buffers the same way. Everything here works
+//! on slices it is given, except the `unsafe` copies out of native memory and
+//! the pointer [`unicode_string`] hands out.
+
+use windows::{
+    Win32::Foundation::UNICODE_STRING,
+    core::{GUID, PWSTR},
+};
+
+/// Why a buffer could not be decoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The buffer ended before `needed` bytes could be read at `offset`.
+    Truncated { offset: usize, needed: usize },
+    /// The bytes at `offset` do not describe a valid `what`.
+    Invalid { offset: usize, what: &'static str },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated { offset, needed } => {
+                write!(
+                    f,
+                    "buffer truncated: {needed} bytes needed at offset {offset}"
+                )
+            }
+            Self::Invalid { offset, what } => write!(f, "invalid {what} at offset {offset}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A cursor reading little-endian values from a byte slice.
+///
+/// Reads past the end fail with [`DecodeError::Truncated`] at the position
+/// they started from.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// A reader starting at `pos`, which may be past the end.
+    pub const fn at(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    /// The whole slice, whatever has been read.
+    pub const fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub const fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub const fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Fails unless `count` entries of `size` bytes fit in what is left,
+    /// without reading them. Decoders call this before allocating for a
+    /// count that comes from the data itself.
+    pub fn check_count(&self, count: usize, size: usize) -> Result<(), DecodeError> {
+        let needed = count.saturating_mul(size);
+        if needed > self.remaining() {
+            return Err(DecodeError::Truncated {
+                offset: self.pos,
+                needed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Moves to `pos`, which may be the end but not past it.
+    pub fn seek(&mut self, pos: usize) -> Result<(), DecodeError> {
+        if pos > self.data.len() {
+            return Err(DecodeError::Truncated {
+                offset: pos,
+                needed: 0,
+            });
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<(), DecodeError> {
+        self.bytes(count).map(|_| ())
+    }
+
+    /// Skips to the next multiple of `alignment` from the start of the slice.
+    pub fn align(&mut self, alignment: usize) -> Result<(), DecodeError> {
+        let padding = (alignment - self.pos % alignment) % alignment;
+        self.skip(padding)
+    }
+
+    /// An error for an invalid `what` at the current position.
+    pub fn invalid(&self, what: &'static str) -> DecodeError {
+        DecodeError::Invalid {
+            offset: self.pos,
+            what,
+        }
+    }
+
+    /// Borrows the next `count` bytes.
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .pos
+            .checked_add(count)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(DecodeError::Truncated {
+                offset: self.pos,
+                needed: count,
+            })?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, DecodeError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    /// A pointer-sized value, in the layout of this target.
+    pub fn usize(&mut self) -> Result<usize, DecodeError> {
+        self.array().map(usize::from_le_bytes)
+    }
+
+    pub fn i32(&mut self) -> Result<i32, DecodeError> {
+        self.array().map(i32::from_le_bytes)
+    }
+
+    pub fn i64(&mut self) -> Result<i64, DecodeError> {
+        self.array().map(i64::from_le_bytes)
+    }
+
+    /// Reads a `GUID` in its in-memory layout.
+    pub fn guid(&mut self) -> Result<GUID, DecodeError> {
+        let data1 = self.u32()?;
+        let data2 = self.u16()?;
+        let data3 = self.u16()?;
+        let data4 = self.array()?;
+        Ok(GUID::from_values(data1, data2, data3, data4))
+    }
+
+    /// Reads `count` UTF-16 code units and decodes them lossily.
+    pub fn utf16(&mut self, count: usize) -> Result<String, DecodeError> {
+        let bytes = self.bytes(count.checked_mul(2).ok_or(self.invalid("string length"))?)?;
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    /// Reads a NUL-terminated UTF-16 string, consuming the terminator.
+    pub fn utf16z(&mut self) -> Result<String, DecodeError> {
+        let start = self.pos;
+        let mut units = Vec::new();
+        loop {
+            match self.u16() {
+                Ok(0) => return Ok(String::from_utf16_lossy(&units)),
+                Ok(unit) => units.push(unit),
+                Err(_) => {
+                    return Err(DecodeError::Truncated {
+                        offset: start,
+                        needed: (units.len() + 1) * 2,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Reads a NUL-terminated byte string, consuming the terminator.
+    pub fn cstr(&mut self) -> Result<&'a [u8], DecodeError> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(DecodeError::Truncated {
+                offset: self.pos,
+                needed: rest.len() + 1,
+            })?;
+        let bytes = self.bytes(len)?;
+        self.pos += 1;
+        Ok(bytes)
+    }
+}
+
+/// A growable buffer of little-endian values, the counterpart of [`Reader`].
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub const fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn zeros(&mut self, count: usize) -> &mut Self {
+        self.buf.resize(self.buf.len() + count, 0);
+        self
+    }
+
+    /// Pads with zeros to the next multiple of `alignment`.
+    pub fn align(&mut self, alignment: usize) -> &mut Self {
+        let padding = (alignment - self.buf.len() % alignment) % alignment;
+        self.zeros(padding)
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// Writes a `GUID` in its in-memory layout.
+    pub fn guid(&mut self, value: &GUID) -> &mut Self {
+        self.u32(value.data1)
+            .u16(value.data2)
+            .u16(value.data3)
+            .bytes(&value.data4)
+    }
+
+    /// Writes `value` as UTF-16 followed by a NUL terminator.
+    pub fn utf16z(&mut self, value: &str) -> &mut Self {
+        for unit in value.encode_utf16() {
+            self.u16(unit);
+        }
+        self.u16(0)
+    }
+
+    /// Overwrites the `u16` written at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// If the bytes at `offset` have not been written yet.
+    pub fn patch_u16(&mut self, offset: usize, value: u16) {
+        self.buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Overwrites the `u32` written at `offset`, panicking as
+    /// [`patch_u16`](Self::patch_u16) does.
+    pub fn patch_u32(&mut self, offset: usize, value: u32) {
+        self.buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Decodes a fixed-size `WCHAR` array up to its first NUL.
+pub fn from_wide(units: &[u16]) -> String {
+    let len = units
+        .iter()
+        .position(|unit| *unit == 0)
+        .unwrap_or(units.len());
+    String::from_utf16_lossy(&units[..len])
+}
+
+/// Decodes a fixed-size `CHAR` array up to its first NUL.
+pub fn from_ansi(bytes: &[u8]) -> String {
+    let len = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Encodes `value` into a fixed-size `WCHAR` array, truncating so that a NUL
+/// terminator always fits.
+pub fn to_wide_array<const N: usize>(value: &str) -> [u16; N] {
+    let mut array = [0; N];
+    for (slot, unit) in array
+        .iter_mut()
+        .take(N.saturating_sub(1))
+        .zip(value.encode_utf16())
+    {
+        *slot = unit;
+    }
+    array
+}
+
+/// Encodes `value` as a NUL-terminated UTF-16 string.
+pub fn to_wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Copies a counted UTF-16 string, `length` in bytes, out of native memory.
+///
+/// # Safety
+///
+/// `buffer` must be null or valid for reads of `length` bytes.
+pub unsafe fn from_counted_wide(buffer: *const u16, length: u16) -> String {
+    if buffer.is_null() || length == 0 {
+        return String::new();
+    }
+    String::from_utf16_lossy(std::slice::from_raw_parts(buffer, length as usize / 2))
+}
+
+/// Copies the contents of a `UNICODE_STRING` out of native memory.
+///
+/// # Safety
+///
+/// `value.Buffer` must be null or valid for reads of `value.Length` bytes.
+pub unsafe fn from_unicode_string(value: &UNICODE_STRING) -> String {
+    from_counted_wide(value.Buffer.0, value.Length)
+}
+
+/// Describes `units` as a `UNICODE_STRING`, not counting a trailing NUL.
+/// Lengths past what a `UNICODE_STRING` can count are cut to the longest it
+/// can.
+///
+/// The result points into `units` without borrowing it. Making it is safe,
+/// but anything reading through the pointer, such as a native call given the
+/// result, must do so while `units` is alive and unmoved.
+pub fn unicode_string(units: &[u16]) -> UNICODE_STRING {
+    const MAX: usize = u16::MAX as usize & !1;
+    let len = units
+        .iter()
+        .position(|unit| *unit == 0)
+        .unwrap_or(units.len());
+    UNICODE_STRING {
+        Length: (len * 2).min(MAX) as u16,
+        MaximumLength: (units.len() * 2).min(MAX) as u16,
+        Buffer: PWSTR(units.as_ptr() as *mut u16),
+    }
+}
+
+/// Formats a GUID in the registry form without braces, e.g.
+/// `bf967aba-0de6-11d0-a285-00aa003049e2`.
+pub fn format_guid(guid: &GUID) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        guid.data1,
+        guid.data2,
+        guid.data3,
+        guid.data4[0],
+        guid.data4[1],
+        guid.data4[2],
+        guid.data4[3],
+        guid.data4[4],
+        guid.data4[5],
+        guid.data4[6],
+        guid.data4[7]
+    )
+}
+
+/// Parses a GUID in the registry form, with or without braces.
+pub fn parse_guid(value: &str) -> Option<GUID> {
+    let value = value
+        .strip_prefix('{')
+        .and_then(|value| value.strip_suffix('}'))
+        .unwrap_or(value);
+    let parts: Vec<&str> = value.split('-').collect();
+    let lengths = [8, 4, 4, 4, 12];
+    if parts.len() != lengths.len()
+        || parts
+            .iter()
+            .zip(lengths)
+            .any(|(part, len)| part.len() != len || !part.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        return None;
+    }
+    let tail = u64::from_str_radix(&format!("{}{}", parts[3], parts[4]), 16).ok()?;
+    Some(GUID::from_values(
+        u32::from_str_radix(parts[0], 16).ok()?,
+        u16::from_str_radix(parts[1], 16).ok()?,
+        u16::from_str_radix(parts[2], 16).ok()?,
+        tail.to_be_bytes(),
+    ))
+}
+
+/// Decodes a fixture written as hex digits, ignoring whitespace.
+#[cfg(test)]
+pub(crate) fn from_hex(text: &str) -> Vec<u8> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID_TEXT: &str = "bf967aba-0de6-11d0-a285-00aa003049e2";
+
+    #[test]
+    fn reads_little_endian_values() {
+        let bytes = from_hex(
+            "7f 3412 78563412 efcdab8967452301 feffffff ffffffffffffff7f
+             ba7a96bf e60d d011 a28500aa003049e2",
+        );
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.u8(), Ok(0x7f));
+        assert_eq!(reader.u16(), Ok(0x1234));
+        assert_eq!(reader.u32(), Ok(0x1234_5678));
+        assert_eq!(reader.u64(), Ok(0x0123_4567_89ab_cdef));
+        assert_eq!(reader.i32(), Ok(-2));
+        assert_eq!(reader.i64(), Ok(i64::MAX));
+        assert_eq!(reader.guid(), Ok(parse_guid(GUID_TEXT).unwrap()));
+        assert!(reader.is_empty());
+        assert_eq!(reader.position(), bytes.len());
+        assert_eq!(reader.data(), &bytes[..]);
+
+        let mut reader = Reader::at(&bytes, 1);
+        assert_eq!(
+            reader.usize(),
+            Ok(usize::from_le_bytes(bytes[1..9].try_into().unwrap()))
+        );
+    }
+
+    #[test]
+    fn reports_where_reads_fail() {
+        let bytes = [1, 2, 3, 4, 5];
+        let mut reader = Reader::new(&bytes);
+        reader.skip(3).unwrap();
+        assert_eq!(
+            reader.u32(),
+            Err(DecodeError::Truncated {
+                offset: 3,
+                needed: 4
+            })
+        );
+        assert_eq!(reader.position(), 3);
+        assert_eq!(reader.remaining(), 2);
+        assert_eq!(reader.check_count(2, 1), Ok(()));
+        assert_eq!(
+            reader.check_count(usize::MAX, 16),
+            Err(DecodeError::Truncated {
+                offset: 3,
+                needed: usize::MAX
+            })
+        );
+        assert_eq!(
+            reader.bytes(usize::MAX),
+            Err(DecodeError::Truncated {
+                offset: 3,
+                needed: usize::MAX
+            })
+        );
+        assert_eq!(
+            reader.align(8),
+            Err(DecodeError::Truncated {
+                offset: 3,
+                needed: 5
+            })
+        );
+        reader.align(1).unwrap();
+        assert_eq!(reader.position(), 3);
+        assert_eq!(
+            reader.invalid("kind"),
+            DecodeError::Invalid {
+                offset: 3,
+                what: "kind"
+            }
+        );
+
+        reader.seek(5).unwrap();
+        assert!(reader.is_empty());
+        assert!(reader.seek(6).is_err());
+        assert_eq!(Reader::at(&bytes, 9).remaining(), 0);
+        assert!(Reader::at(&bytes, 9).u8().is_err());
+    }
+
+    #[test]
+    fn reads_strings() {
+        let bytes = from_hex("410042000000 4300 6869 00 6a");
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.utf16z().as_deref(), Ok("AB"));
+        assert_eq!(reader.utf16(1).as_deref(), Ok("C"));
+        assert_eq!(reader.cstr(), Ok(&b"hi"[..]));
+        assert_eq!(
+            reader.cstr(),
+            Err(DecodeError::Truncated {
+                offset: 11,
+                needed: 2
+            })
+        );
+
+        let unterminated = from_hex("41004200");
+        assert_eq!(
+            Reader::new(&unterminated).utf16z(),
+            Err(DecodeError::Truncated {
+                offset: 0,
+                needed: 6
+            })
+        );
+        assert!(Reader::new(&unterminated).utf16(usize::MAX).is_err());
+        // Unpaired surrogates decode lossily.
+        assert_eq!(
+            Reader::new(&from_hex("00d84100")).utf16(2).as_deref(),
+            Ok("\u{fffd}A")
+        );
+    }
+
+    #[test]
+    fn writes_what_reader_reads() {
+        let guid = parse_guid(GUID_TEXT).unwrap();
+        let mut writer = Writer::new();
+        assert!(writer.is_empty());
+        writer
+            .u8(0x7f)
+            .align(4)
+            .u16(0x1234)
+            .u32(0)
+            .u64(u64::MAX)
+            .i64(-3)
+            .guid(&guid)
+            .utf16z("Zé")
+            .zeros(2)
+            .bytes(b"end");
+        writer.patch_u32(6, 0xdead_beef);
+        writer.patch_u16(0, 0x0102);
+        assert_eq!(writer.len(), 53);
+        assert_eq!(&writer.as_slice()[..6], &[2, 1, 0, 0, 0x34, 0x12]);
+
+        let bytes = writer.into_inner();
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.u16(), Ok(0x0102));
+        reader.align(4).unwrap();
+        assert_eq!(reader.u16(), Ok(0x1234));
+        assert_eq!(reader.u32(), Ok(0xdead_beef));
+        assert_eq!(reader.u64(), Ok(u64::MAX));
+        assert_eq!(reader.i64(), Ok(-3));
+        assert_eq!(reader.guid(), Ok(guid));
+        assert_eq!(reader.utf16z().as_deref(), Ok("Zé"));
+        assert_eq!(reader.bytes(5), Ok(&b"\0\0end"[..]));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn patches_only_written_bytes() {
+        Writer::new().u16(1).patch_u32(0, 2);
+    }
+
+    #[test]
+    fn converts_wide_strings() {
+        assert_eq!(from_wide(&[0x41, 0x42, 0, 0x43]), "AB");
+        assert_eq!(from_wide(&[0x41, 0x42]), "AB");
+        assert_eq!(from_ansi(b"ntdll\0junk"), "ntdll");
+        assert_eq!(to_wide("AB"), [0x41, 0x42, 0]);
+        assert_eq!(to_wide_array::<3>("ABCD"), [0x41, 0x42, 0]);
+        assert_eq!(to_wide_array::<4>("A"), [0x41, 0, 0, 0]);
+        assert_eq!(to_wide_array::<0>("A"), []);
+
+        let units = to_wide("name");
+        let string = unicode_string(&units);
+        assert_eq!((string.Length, string.MaximumLength), (8, 10));
+        assert_eq!(string.Buffer.0.cast_const(), units.as_ptr());
+        assert_eq!(unsafe { from_unicode_string(&string) }, "name");
+
+        let long = vec![0x41; 0x8000];
+        let string = unicode_string(&long);
+        assert_eq!((string.Length, string.MaximumLength), (0xfffe, 0xfffe));
+
+        assert_eq!(unsafe { from_counted_wide(std::ptr::null(), 8) }, "");
+        assert_eq!(unsafe { from_counted_wide(units.as_ptr(), 0) }, "");
+        assert_eq!(unsafe { from_counted_wide(units.as_ptr(), 5) }, "na");
+    }
+
+    #[test]
+    fn formats_and_parses_guids() {
+        let guid = parse_guid(GUID_TEXT).unwrap();
+        assert_eq!(guid.data1, 0xbf96_7aba);
+        assert_eq!(guid.data4, [0xa2, 0x85, 0, 0xaa, 0, 0x30, 0x49, 0xe2]);
+        assert_eq!(format_guid(&guid), GUID_TEXT);
+        assert_eq!(
+            parse_guid("{BF967ABA-0DE6-11D0-A285-00AA003049E2}"),
+            Some(guid)
+        );
+        for bad in [
+            "",
+            "{bf967aba-0de6-11d0-a285-00aa003049e2",
+            "bf967aba-0de6-11d0-a28500aa003049e2",
+            "bf967aba-0de6-11d0-a285-00aa003049e",
+            "bf967abg-0de6-11d0-a285-00aa003049e2",
+            "+f967aba-0de6-11d0-a285-00aa003049e2",
+        ] {
+            assert_eq!(parse_guid(bad), None, "{bad}");
+        }
+    }
+}
@@ -0,0 +1,1139 @@
+use std::{fmt, mem};
+
+use windows::Win32::Foundation::{NTSTATUS, STATUS_BUFFER_TOO_SMALL, STATUS_NOT_FOUND};
+
+use super::{DevicePath, Error};
+use crate::{
+    buffer::{DecodeError, Reader, Writer},
+    check,
+    ntexapi::{
+        BOOT_ENTRY, BOOT_OPTIONS, EFI_DRIVER_ENTRY, FILE_PATH, NtAddBootEntry, NtAddDriverEntry,
+        NtDeleteBootEntry, NtDeleteDriverEntry, NtEnumerateBootEntries, NtEnumerateDriverEntries,
+        NtModifyBootEntry, NtModifyDriverEntry, NtQueryBootEntryOrder, NtQueryBootOptions,
+        NtQueryDriverEntryOrder, NtSetBootEntryOrder, NtSetBootOptions, NtSetDriverEntryOrder,
+        NtTranslateFilePath,
+    },
+};
+
+pub const BOOT_ENTRY_VERSION: u32 = 1;
+pub const BOOT_OPTIONS_VERSION: u32 = 1;
+pub const FILE_PATH_VERSION: u32 = 1;
+pub const EFI_DRIVER_ENTRY_VERSION: u32 = 1;
+pub const WINDOWS_OS_OPTIONS_VERSION: u32 = 1;
+pub const WINDOWS_OS_OPTIONS_SIGNATURE: &[u8; 8] = b"WINDOWS\0";
+
+pub const FILE_PATH_TYPE_ARC: u32 = 1;
+pub const FILE_PATH_TYPE_ARC_SIGNATURE: u32 = 2;
+pub const FILE_PATH_TYPE_NT: u32 = 3;
+pub const FILE_PATH_TYPE_EFI: u32 = 4;
+
+/// `FILE_PATH`: a path in one of the formats `NtTranslateFilePath` converts
+/// between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilePath {
+    Arc(String),
+    ArcSignature(String),
+    Nt(String),
+    Efi(DevicePath),
+    /// A type this crate does not know, or a payload that does not decode
+    /// as its type, kept as stored.
+    Other {
+        kind: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl FilePath {
+    /// The `FILE_PATH_TYPE_*` of the path.
+    pub fn kind(&self) -> u32 {
+        match self {
+            Self::Arc(_) => FILE_PATH_TYPE_ARC,
+            Self::ArcSignature(_) => FILE_PATH_TYPE_ARC_SIGNATURE,
+            Self::Nt(_) => FILE_PATH_TYPE_NT,
+            Self::Efi(_) => FILE_PATH_TYPE_EFI,
+            Self::Other { kind, .. } => *kind,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let _version = reader.u32()?;
+        let length = reader.u32()? as usize;
+        let kind = reader.u32()?;
+        let data = length
+            .checked_sub(mem::offset_of!(FILE_PATH, FilePath))
+            .ok_or(DecodeError::Invalid {
+                offset: 4,
+                what: "FILE_PATH length",
+            })
+            .and_then(|size| reader.bytes(size))?;
+        let string = |data: &[u8]| {
+            let mut reader = Reader::new(data);
+            reader.utf16z().ok().filter(|_| reader.is_empty())
+        };
+        let decoded = match kind {
+            FILE_PATH_TYPE_ARC => string(data).map(Self::Arc),
+            FILE_PATH_TYPE_ARC_SIGNATURE => string(data).map(Self::ArcSignature),
+            FILE_PATH_TYPE_NT => string(data).map(Self::Nt),
+            FILE_PATH_TYPE_EFI => {
+                let mut reader = Reader::new(data);
+                DevicePath::read(&mut reader)
+                    .ok()
+                    .filter(|_| reader.is_empty())
+                    .map(Self::Efi)
+            }
+            _ => None,
+        };
+        Ok(decoded.unwrap_or_else(|| Self::Other {
+            kind,
+            data: data.to_vec(),
+        }))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        let start = writer.len();
+        writer.u32(FILE_PATH_VERSION).u32(0).u32(self.kind());
+        match self {
+            Self::Arc(path) | Self::ArcSignature(path) | Self::Nt(path) => {
+                writer.utf16z(path);
+            }
+            Self::Efi(path) => path.write(writer),
+            Self::Other { data, .. } => {
+                writer.bytes(data);
+            }
+        }
+        let length = writer.len() - start;
+        writer.patch_u32(start + 4, length as u32);
+    }
+}
+
+impl fmt::Display for FilePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Arc(path) | Self::ArcSignature(path) | Self::Nt(path) => f.write_str(path),
+            Self::Efi(path) => path.fmt(f),
+            Self::Other { kind, data } => write!(f, "<type {kind}, {} bytes>", data.len()),
+        }
+    }
+}
+
+/// `WINDOWS_OS_OPTIONS`: the loader path and options of a Windows entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowsOsOptions {
+    /// Options passed to the loader, e.g. `/NOEXECUTE=OPTIN`.
+    pub load_options: String,
+    pub load_path: FilePath,
+}
+
+/// The `OsOptions` of a boot entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OsOptions {
+    Windows(WindowsOsOptions),
+    Raw(Vec<u8>),
+}
+
+impl OsOptions {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if !bytes.starts_with(WINDOWS_OS_OPTIONS_SIGNATURE) {
+            return Ok(Self::Raw(bytes.to_vec()));
+        }
+        let mut reader = Reader::at(bytes, WINDOWS_OS_OPTIONS_SIGNATURE.len());
+        let _version = reader.u32()?;
+        let length = reader.u32()? as usize;
+        let bytes = Reader::new(bytes).bytes(length)?;
+        let load_path_offset = reader.u32()? as usize;
+        let load_options = reader.utf16z()?;
+        Ok(Self::Windows(WindowsOsOptions {
+            load_options,
+            load_path: FilePath::from_bytes(tail(bytes, load_path_offset)?)?,
+        }))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Raw(bytes) => bytes.clone(),
+            Self::Windows(options) => {
+                let mut writer = Writer::new();
+                writer
+                    .bytes(WINDOWS_OS_OPTIONS_SIGNATURE)
+                    .u32(WINDOWS_OS_OPTIONS_VERSION)
+                    .u32(0)
+                    .u32(0)
+                    .utf16z(&options.load_options)
+                    .align(4);
+                let load_path_offset = writer.len();
+                options.load_path.write(&mut writer);
+                let length = writer.len();
+                writer.patch_u32(12, length as u32);
+                writer.patch_u32(16, load_path_offset as u32);
+                writer.into_inner()
+            }
+        }
+    }
+}
+
+/// `BOOT_ENTRY_ATTRIBUTE_*`.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct BootEntryAttributes(pub u32);
+
+impl BootEntryAttributes {
+    pub const ACTIVE: Self = Self(0x1);
+    pub const DEFAULT: Self = Self(0x2);
+    pub const WINDOWS: Self = Self(0x4);
+    pub const REMOVABLE_MEDIA: Self = Self(0x8);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for BootEntryAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for BootEntryAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Debug for BootEntryAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BootEntryAttributes({:#x})", self.0)
+    }
+}
+
+/// An owned `BOOT_ENTRY`.
+///
+/// `id` is assigned by the system when the entry is added and identifies
+/// the entry to modify otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootEntry {
+    pub id: u32,
+    pub attributes: BootEntryAttributes,
+    pub friendly_name: String,
+    pub boot_file_path: FilePath,
+    pub os_options: OsOptions,
+}
+
+impl BootEntry {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let _version = reader.u32()?;
+        let length = reader.u32()? as usize;
+        let bytes = Reader::new(bytes).bytes(length)?;
+        let id = reader.u32()?;
+        let attributes = BootEntryAttributes(reader.u32()?);
+        let friendly_name_offset = reader.u32()? as usize;
+        let boot_file_path_offset = reader.u32()? as usize;
+        let os_options_length = reader.u32()? as usize;
+        let os_options = OsOptions::from_bytes(reader.bytes(os_options_length)?)?;
+        Ok(Self {
+            id,
+            attributes,
+            friendly_name: Reader::at(bytes, friendly_name_offset).utf16z()?,
+            boot_file_path: FilePath::from_bytes(tail(bytes, boot_file_path_offset)?)?,
+            os_options,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        let start = writer.len();
+        let os_options = self.os_options.to_bytes();
+        writer
+            .u32(BOOT_ENTRY_VERSION)
+            .u32(0)
+            .u32(self.id)
+            .u32(self.attributes.0)
+            .u32(0)
+            .u32(0)
+            .u32(os_options.len() as u32)
+            .bytes(&os_options)
+            .align(4);
+        let friendly_name_offset = writer.len() - start;
+        writer.utf16z(&self.friendly_name).align(4);
+        let boot_file_path_offset = writer.len() - start;
+        self.boot_file_path.write(writer);
+        let length = writer.len() - start;
+        writer.patch_u32(start + 4, length as u32);
+        writer.patch_u32(start + 16, friendly_name_offset as u32);
+        writer.patch_u32(start + 20, boot_file_path_offset as u32);
+    }
+
+    /// Decodes the `BOOT_ENTRY_LIST` returned by `NtEnumerateBootEntries`.
+    pub fn parse_list(bytes: &[u8]) -> Result<Vec<Self>, DecodeError> {
+        parse_list(bytes, Self::from_bytes)
+    }
+
+    pub fn encode_list(entries: &[Self]) -> Vec<u8> {
+        encode_list(entries, Self::write)
+    }
+
+    /// The Windows loader options, for Windows entries.
+    pub fn windows_options(&self) -> Option<&WindowsOsOptions> {
+        match &self.os_options {
+            OsOptions::Windows(options) => Some(options),
+            OsOptions::Raw(_) => None,
+        }
+    }
+}
+
+/// An owned `EFI_DRIVER_ENTRY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverEntry {
+    pub id: u32,
+    pub friendly_name: String,
+    pub driver_file_path: FilePath,
+}
+
+impl DriverEntry {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let _version = reader.u32()?;
+        let length = reader.u32()? as usize;
+        let bytes = Reader::new(bytes).bytes(length)?;
+        let id = reader.u32()?;
+        let friendly_name_offset = reader.u32()? as usize;
+        let driver_file_path_offset = reader.u32()? as usize;
+        Ok(Self {
+            id,
+            friendly_name: Reader::at(bytes, friendly_name_offset).utf16z()?,
+            driver_file_path: FilePath::from_bytes(tail(bytes, driver_file_path_offset)?)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        let start = writer.len();
+        writer
+            .u32(EFI_DRIVER_ENTRY_VERSION)
+            .u32(0)
+            .u32(self.id)
+            .u32(0)
+            .u32(0);
+        let friendly_name_offset = writer.len() - start;
+        writer.utf16z(&self.friendly_name).align(4);
+        let driver_file_path_offset = writer.len() - start;
+        self.driver_file_path.write(writer);
+        let length = writer.len() - start;
+        writer.patch_u32(start + 4, length as u32);
+        writer.patch_u32(start + 12, friendly_name_offset as u32);
+        writer.patch_u32(start + 16, driver_file_path_offset as u32);
+    }
+
+    /// Decodes the `EFI_DRIVER_ENTRY_LIST` returned by
+    /// `NtEnumerateDriverEntries`.
+    pub fn parse_list(bytes: &[u8]) -> Result<Vec<Self>, DecodeError> {
+        parse_list(bytes, Self::from_bytes)
+    }
+
+    pub fn encode_list(entries: &[Self]) -> Vec<u8> {
+        encode_list(entries, Self::write)
+    }
+}
+
+/// `bytes` from `offset` on.
+fn tail(bytes: &[u8], offset: usize) -> Result<&[u8], DecodeError> {
+    bytes
+        .get(offset..)
+        .ok_or(DecodeError::Truncated { offset, needed: 0 })
+}
+
+/// Walks a `NextEntryOffset` chained list whose entries follow the offset.
+fn parse_list<T>(
+    bytes: &[u8],
+    decode: impl Fn(&[u8]) -> Result<T, DecodeError>,
+) -> Result<Vec<T>, DecodeError> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let next = Reader::at(bytes, offset).u32()? as usize;
+        entries.push(decode(tail(bytes, offset + 4)?)?);
+        if next == 0 {
+            break;
+        }
+        offset += next;
+    }
+    Ok(entries)
+}
+
+fn encode_list<T>(entries: &[T], write: impl Fn(&T, &mut Writer)) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let mut previous: Option<usize> = None;
+    for entry in entries {
+        writer.align(8);
+        let start = writer.len();
+        if let Some(previous) = previous {
+            writer.patch_u32(previous, (start - previous) as u32);
+        }
+        writer.u32(0);
+        write(entry, &mut writer);
+        previous = Some(start);
+    }
+    writer.into_inner()
+}
+
+/// `BOOT_OPTIONS_FIELD_*`: the fields `NtSetBootOptions` applies.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct BootOptionsFields(pub u32);
+
+impl BootOptionsFields {
+    pub const TIMEOUT: Self = Self(0x1);
+    pub const NEXT_BOOT_ENTRY_ID: Self = Self(0x2);
+    pub const HEADLESS_REDIRECTION: Self = Self(0x4);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for BootOptionsFields {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for BootOptionsFields {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Debug for BootOptionsFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BootOptionsFields({:#x})", self.0)
+    }
+}
+
+/// An owned `BOOT_OPTIONS`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BootOptions {
+    /// Seconds the boot menu waits.
+    pub timeout: u32,
+    pub current_boot_entry_id: u32,
+    pub next_boot_entry_id: u32,
+    pub headless_redirection: String,
+}
+
+impl BootOptions {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let _version = reader.u32()?;
+        let length = reader.u32()? as usize;
+        let timeout = reader.u32()?;
+        let current_boot_entry_id = reader.u32()?;
+        let next_boot_entry_id = reader.u32()?;
+        let headless_redirection = if length > reader.position() {
+            Reader::at(Reader::new(bytes).bytes(length)?, reader.position()).utf16z()?
+        } else {
+            String::new()
+        };
+        Ok(Self {
+            timeout,
+            current_boot_entry_id,
+            next_boot_entry_id,
+            headless_redirection,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer
+            .u32(BOOT_OPTIONS_VERSION)
+            .u32(0)
+            .u32(self.timeout)
+            .u32(self.current_boot_entry_id)
+            .u32(self.next_boot_entry_id)
+            .utf16z(&self.headless_redirection);
+        let length = writer.len();
+        writer.patch_u32(4, length as u32);
+        writer.into_inner()
+    }
+}
+
+/// Which list an operation of [`BootStore`] applies to.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum EntryKind {
+    Boot,
+    Driver,
+}
+
+/// The firmware boot and driver entries.
+///
+/// Implementors provide the raw operations over serialized `BOOT_ENTRY`,
+/// `EFI_DRIVER_ENTRY`, `BOOT_OPTIONS` and `FILE_PATH` buffers; the typed
+/// accessors are built on top of them.
+pub trait BootStore {
+    /// The raw `BOOT_ENTRY_LIST` or `EFI_DRIVER_ENTRY_LIST`.
+    fn enumerate(&self, kind: EntryKind) -> Result<Vec<u8>, NTSTATUS>;
+
+    /// Adds a serialized entry, returning the id it was given.
+    fn add(&self, kind: EntryKind, entry: &[u8]) -> Result<u32, NTSTATUS>;
+
+    /// Replaces the entry with the id of the serialized `entry`.
+    fn modify(&self, kind: EntryKind, entry: &[u8]) -> Result<(), NTSTATUS>;
+
+    fn delete(&self, kind: EntryKind, id: u32) -> Result<(), NTSTATUS>;
+
+    fn order(&self, kind: EntryKind) -> Result<Vec<u32>, NTSTATUS>;
+
+    fn set_order(&self, kind: EntryKind, ids: &[u32]) -> Result<(), NTSTATUS>;
+
+    /// The raw `BOOT_OPTIONS`.
+    fn options(&self) -> Result<Vec<u8>, NTSTATUS>;
+
+    fn set_options(&self, options: &[u8], fields: BootOptionsFields) -> Result<(), NTSTATUS>;
+
+    /// Converts a serialized `FILE_PATH` to the `FILE_PATH_TYPE_*` `kind`.
+    fn translate(&self, path: &[u8], kind: u32) -> Result<Vec<u8>, NTSTATUS>;
+
+    fn boot_entries(&self) -> Result<Vec<BootEntry>, Error> {
+        Ok(BootEntry::parse_list(&self.enumerate(EntryKind::Boot)?)?)
+    }
+
+    fn boot_entry(&self, id: u32) -> Result<Option<BootEntry>, Error> {
+        Ok(self
+            .boot_entries()?
+            .into_iter()
+            .find(|entry| entry.id == id))
+    }
+
+    /// Adds `entry`, ignoring its `id`, and returns the id it was given.
+    fn add_boot_entry(&self, entry: &BootEntry) -> Result<u32, NTSTATUS> {
+        self.add(EntryKind::Boot, &entry.to_bytes())
+    }
+
+    fn modify_boot_entry(&self, entry: &BootEntry) -> Result<(), NTSTATUS> {
+        self.modify(EntryKind::Boot, &entry.to_bytes())
+    }
+
+    fn delete_boot_entry(&self, id: u32) -> Result<(), NTSTATUS> {
+        self.delete(EntryKind::Boot, id)
+    }
+
+    /// Applies `edit` to the entry `id` and writes it back.
+    fn edit_boot_entry(&self, id: u32, edit: impl FnOnce(&mut BootEntry)) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let mut entry = self
+            .boot_entry(id)?
+            .ok_or(Error::Status(STATUS_NOT_FOUND))?;
+        edit(&mut entry);
+        Ok(self.modify_boot_entry(&entry)?)
+    }
+
+    fn rename_boot_entry(&self, id: u32, friendly_name: &str) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        self.edit_boot_entry(id, |entry| entry.friendly_name = friendly_name.to_owned())
+    }
+
+    /// Replaces the loader options of a Windows entry. Other entries are
+    /// left untouched.
+    fn set_load_options(&self, id: u32, load_options: &str) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        self.edit_boot_entry(id, |entry| {
+            if let OsOptions::Windows(options) = &mut entry.os_options {
+                options.load_options = load_options.to_owned();
+            }
+        })
+    }
+
+    fn boot_order(&self) -> Result<Vec<u32>, NTSTATUS> {
+        self.order(EntryKind::Boot)
+    }
+
+    fn set_boot_order(&self, ids: &[u32]) -> Result<(), NTSTATUS> {
+        self.set_order(EntryKind::Boot, ids)
+    }
+
+    fn driver_entries(&self) -> Result<Vec<DriverEntry>, Error> {
+        Ok(DriverEntry::parse_list(
+            &self.enumerate(EntryKind::Driver)?,
+        )?)
+    }
+
+    fn add_driver_entry(&self, entry: &DriverEntry) -> Result<u32, NTSTATUS> {
+        self.add(EntryKind::Driver, &entry.to_bytes())
+    }
+
+    fn modify_driver_entry(&self, entry: &DriverEntry) -> Result<(), NTSTATUS> {
+        self.modify(EntryKind::Driver, &entry.to_bytes())
+    }
+
+    fn delete_driver_entry(&self, id: u32) -> Result<(), NTSTATUS> {
+        self.delete(EntryKind::Driver, id)
+    }
+
+    fn driver_order(&self) -> Result<Vec<u32>, NTSTATUS> {
+        self.order(EntryKind::Driver)
+    }
+
+    fn set_driver_order(&self, ids: &[u32]) -> Result<(), NTSTATUS> {
+        self.set_order(EntryKind::Driver, ids)
+    }
+
+    fn boot_options(&self) -> Result<BootOptions, Error> {
+        Ok(BootOptions::from_bytes(&self.options()?)?)
+    }
+
+    /// Writes the `fields` of `options`.
+    fn set_boot_options(
+        &self,
+        options: &BootOptions,
+        fields: BootOptionsFields,
+    ) -> Result<(), NTSTATUS> {
+        self.set_options(&options.to_bytes(), fields)
+    }
+
+    fn set_timeout(&self, timeout: u32) -> Result<(), NTSTATUS> {
+        let options = BootOptions {
+            timeout,
+            ..Default::default()
+        };
+        self.set_boot_options(&options, BootOptionsFields::TIMEOUT)
+    }
+
+    /// Boots entry `id` on the next restart only.
+    fn set_next_boot_entry(&self, id: u32) -> Result<(), NTSTATUS> {
+        let options = BootOptions {
+            next_boot_entry_id: id,
+            ..Default::default()
+        };
+        self.set_boot_options(&options, BootOptionsFields::NEXT_BOOT_ENTRY_ID)
+    }
+
+    fn translate_file_path(&self, path: &FilePath, kind: u32) -> Result<FilePath, Error> {
+        Ok(FilePath::from_bytes(
+            &self.translate(&path.to_bytes(), kind)?,
+        )?)
+    }
+}
+
+/// The [`BootStore`] backed by the firmware. The caller needs
+/// `SeSystemEnvironmentPrivilege`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeBoot;
+
+/// Copies `bytes` into a `ULONG` aligned buffer.
+fn aligned(bytes: &[u8]) -> Vec<u32> {
+    let mut buffer = vec![0u32; bytes.len().div_ceil(4)];
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.as_mut_ptr().cast(), bytes.len())
+    };
+    buffer
+}
+
+fn into_bytes(buffer: &[u32], length: usize) -> Vec<u8> {
+    buffer
+        .iter()
+        .flat_map(|value| value.to_ne_bytes())
+        .take(length)
+        .collect()
+}
+
+/// Calls `query` with a growing buffer until it fits, returning the bytes
+/// it reported.
+fn query_buffer(
+    mut query: impl FnMut(*mut u32, &mut u32) -> NTSTATUS,
+) -> Result<Vec<u8>, NTSTATUS> {
+    let mut buffer = vec![0u32; 0x400];
+    loop {
+        let mut length = (buffer.len() * 4) as u32;
+        match query(buffer.as_mut_ptr(), &mut length) {
+            STATUS_BUFFER_TOO_SMALL => {
+                let grown = (length as usize).div_ceil(4).max(buffer.len() * 2);
+                buffer.resize(grown, 0);
+            }
+            status => {
+                check(status)?;
+                return Ok(into_bytes(&buffer, length as usize));
+            }
+        }
+    }
+}
+
+impl BootStore for NativeBoot {
+    fn enumerate(&self, kind: EntryKind) -> Result<Vec<u8>, NTSTATUS> {
+        query_buffer(|buffer, length| unsafe {
+            match kind {
+                EntryKind::Boot => NtEnumerateBootEntries(buffer.cast(), length),
+                EntryKind::Driver => NtEnumerateDriverEntries(buffer.cast(), length),
+            }
+        })
+    }
+
+    fn add(&self, kind: EntryKind, entry: &[u8]) -> Result<u32, NTSTATUS> {
+        let mut entry = aligned(entry);
+        let mut id = 0;
+        check(unsafe {
+            match kind {
+                EntryKind::Boot => NtAddBootEntry(entry.as_mut_ptr().cast::<BOOT_ENTRY>(), &mut id),
+                EntryKind::Driver => {
+                    NtAddDriverEntry(entry.as_mut_ptr().cast::<EFI_DRIVER_ENTRY>(), &mut id)
+                }
+            }
+        })?;
+        Ok(id)
+    }
+
+    fn modify(&self, kind: EntryKind, entry: &[u8]) -> Result<(), NTSTATUS> {
+        let mut entry = aligned(entry);
+        check(unsafe {
+            match kind {
+                EntryKind::Boot => NtModifyBootEntry(entry.as_mut_ptr().cast::<BOOT_ENTRY>()),
+                EntryKind::Driver => {
+                    NtModifyDriverEntry(entry.as_mut_ptr().cast::<EFI_DRIVER_ENTRY>())
+                }
+            }
+        })
+    }
+
+    fn delete(&self, kind: EntryKind, id: u32) -> Result<(), NTSTATUS> {
+        check(unsafe {
+            match kind {
+                EntryKind::Boot => NtDeleteBootEntry(id),
+                EntryKind::Driver => NtDeleteDriverEntry(id),
+            }
+        })
+    }
+
+    fn order(&self, kind: EntryKind) -> Result<Vec<u32>, NTSTATUS> {
+        let mut ids = vec![0u32; 0x40];
+        loop {
+            let mut count = ids.len() as u32;
+            let status = unsafe {
+                match kind {
+                    EntryKind::Boot => NtQueryBootEntryOrder(ids.as_mut_ptr(), &mut count),
+                    EntryKind::Driver => NtQueryDriverEntryOrder(ids.as_mut_ptr(), &mut count),
+                }
+            };
+            match status {
+                STATUS_BUFFER_TOO_SMALL => ids.resize((count as usize).max(ids.len() * 2), 0),
+                status => {
+                    check(status)?;
+                    ids.truncate(count as usize);
+                    return Ok(ids);
+                }
+            }
+        }
+    }
+
+    fn set_order(&self, kind: EntryKind, ids: &[u32]) -> Result<(), NTSTATUS> {
+        let mut ids = ids.to_vec();
+        check(unsafe {
+            match kind {
+                EntryKind::Boot => NtSetBootEntryOrder(ids.as_mut_ptr(), ids.len() as u32),
+                EntryKind::Driver => NtSetDriverEntryOrder(ids.as_mut_ptr(), ids.len() as u32),
+            }
+        })
+    }
+
+    fn options(&self) -> Result<Vec<u8>, NTSTATUS> {
+        query_buffer(|buffer, length| unsafe {
+            NtQueryBootOptions(buffer.cast::<BOOT_OPTIONS>(), length)
+        })
+    }
+
+    fn set_options(&self, options: &[u8], fields: BootOptionsFields) -> Result<(), NTSTATUS> {
+        let mut options = aligned(options);
+        check(unsafe { NtSetBootOptions(options.as_mut_ptr().cast::<BOOT_OPTIONS>(), fields.0) })
+    }
+
+    fn translate(&self, path: &[u8], kind: u32) -> Result<Vec<u8>, NTSTATUS> {
+        let mut input = aligned(path);
+        query_buffer(|buffer, length| unsafe {
+            NtTranslateFilePath(
+                input.as_mut_ptr().cast::<FILE_PATH>(),
+                kind,
+                buffer.cast::<FILE_PATH>(),
+                length,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{buffer::from_hex, efi::DeviceNode};
+
+    /// A `BOOT_ENTRY_LIST` of Windows Boot Manager, with its `WINDOWS`
+    /// options, followed by a firmware entry without OS options.
+    fn boot_entry_list() -> Vec<u8> {
+        from_hex(
+            "
+            28 01 00 00 01 00 00 00 1e 01 00 00 03 00 00 00
+            05 00 00 00 9c 00 00 00 c8 00 00 00 80 00 00 00
+            57 49 4e 44 4f 57 53 00 01 00 00 00 80 00 00 00
+            38 00 00 00 2f 00 4e 00 4f 00 45 00 58 00 45 00
+            43 00 55 00 54 00 45 00 3d 00 4f 00 50 00 54 00
+            49 00 4e 00 00 00 00 00 01 00 00 00 48 00 00 00
+            03 00 00 00 5c 00 57 00 69 00 6e 00 64 00 6f 00
+            77 00 73 00 5c 00 73 00 79 00 73 00 74 00 65 00
+            6d 00 33 00 32 00 5c 00 77 00 69 00 6e 00 6c 00
+            6f 00 61 00 64 00 2e 00 65 00 66 00 69 00 00 00
+            57 00 69 00 6e 00 64 00 6f 00 77 00 73 00 20 00
+            42 00 6f 00 6f 00 74 00 20 00 4d 00 61 00 6e 00
+            61 00 67 00 65 00 72 00 00 00 00 00 01 00 00 00
+            56 00 00 00 04 00 00 00 04 04 46 00 5c 00 45 00
+            46 00 49 00 5c 00 4d 00 69 00 63 00 72 00 6f 00
+            73 00 6f 00 66 00 74 00 5c 00 42 00 6f 00 6f 00
+            74 00 5c 00 62 00 6f 00 6f 00 74 00 6d 00 67 00
+            66 00 77 00 2e 00 65 00 66 00 69 00 00 00 7f ff
+            04 00 00 00 00 00 00 00 00 00 00 00 01 00 00 00
+            6c 00 00 00 01 00 00 00 01 00 00 00 1c 00 00 00
+            2c 00 00 00 00 00 00 00 55 00 45 00 46 00 49 00
+            20 00 4f 00 53 00 00 00 01 00 00 00 40 00 00 00
+            04 00 00 00 04 04 30 00 5c 00 45 00 46 00 49 00
+            5c 00 42 00 4f 00 4f 00 54 00 5c 00 42 00 4f 00
+            4f 00 54 00 58 00 36 00 34 00 2e 00 45 00 46 00
+            49 00 00 00 7f ff 04 00
+            ",
+        )
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn decodes_boot_entry_list() {
+        let bytes = boot_entry_list();
+        let entries = BootEntry::parse_list(&bytes).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let manager = &entries[0];
+        assert_eq!(manager.id, 3);
+        assert!(manager.attributes.contains(BootEntryAttributes::ACTIVE));
+        assert!(manager.attributes.contains(BootEntryAttributes::WINDOWS));
+        assert!(!manager.attributes.contains(BootEntryAttributes::DEFAULT));
+        assert_eq!(manager.friendly_name, "Windows Boot Manager");
+        let FilePath::Efi(path) = &manager.boot_file_path else {
+            panic!("{:?}", manager.boot_file_path);
+        };
+        assert_eq!(path.file_path(), Some(r"\EFI\Microsoft\Boot\bootmgfw.efi"));
+        assert_eq!(
+            manager.windows_options(),
+            Some(&WindowsOsOptions {
+                load_options: "/NOEXECUTE=OPTIN".into(),
+                load_path: FilePath::Nt(r"\Windows\system32\winload.efi".into()),
+            })
+        );
+
+        let firmware = &entries[1];
+        assert_eq!(firmware.id, 1);
+        assert_eq!(firmware.attributes, BootEntryAttributes::ACTIVE);
+        assert_eq!(firmware.friendly_name, "UEFI OS");
+        assert_eq!(firmware.os_options, OsOptions::Raw(Vec::new()));
+        assert_eq!(firmware.windows_options(), None);
+        assert_eq!(
+            firmware.boot_file_path.to_string(),
+            r"\EFI\BOOT\BOOTX64.EFI"
+        );
+
+        assert_eq!(BootEntry::encode_list(&entries), bytes);
+        assert_eq!(BootEntry::from_bytes(&bytes[4..]).unwrap(), entries[0]);
+        assert!(BootEntry::parse_list(&[]).unwrap().is_empty());
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Write {
+        Modify(EntryKind, Vec<u8>),
+        SetOptions(Vec<u8>, BootOptionsFields),
+    }
+
+    /// A store over [`boot_entry_list`] that records what is written.
+    #[derive(Default)]
+    struct Store {
+        writes: RefCell<Vec<Write>>,
+    }
+
+    impl BootStore for Store {
+        fn enumerate(&self, kind: EntryKind) -> Result<Vec<u8>, NTSTATUS> {
+            assert_eq!(kind, EntryKind::Boot);
+            Ok(boot_entry_list())
+        }
+
+        fn add(&self, _: EntryKind, _: &[u8]) -> Result<u32, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn modify(&self, kind: EntryKind, entry: &[u8]) -> Result<(), NTSTATUS> {
+            self.writes
+                .borrow_mut()
+                .push(Write::Modify(kind, entry.to_vec()));
+            Ok(())
+        }
+
+        fn delete(&self, _: EntryKind, _: u32) -> Result<(), NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn order(&self, _: EntryKind) -> Result<Vec<u32>, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn set_order(&self, _: EntryKind, _: &[u32]) -> Result<(), NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn options(&self) -> Result<Vec<u8>, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn set_options(&self, options: &[u8], fields: BootOptionsFields) -> Result<(), NTSTATUS> {
+            self.writes
+                .borrow_mut()
+                .push(Write::SetOptions(options.to_vec(), fields));
+            Ok(())
+        }
+
+        fn translate(&self, _: &[u8], _: u32) -> Result<Vec<u8>, NTSTATUS> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn edits_write_back_one_entry() {
+        let entries = BootEntry::parse_list(&boot_entry_list()).unwrap();
+        let store = Store::default();
+        store
+            .edit_boot_entry(3, |entry| entry.attributes |= BootEntryAttributes::DEFAULT)
+            .unwrap();
+        let mut expected = entries[0].clone();
+        expected.attributes |= BootEntryAttributes::DEFAULT;
+        assert_eq!(
+            store.writes.take(),
+            [Write::Modify(EntryKind::Boot, expected.to_bytes())]
+        );
+
+        // A missing entry is not written.
+        assert_eq!(
+            store.edit_boot_entry(7, |_| unreachable!()),
+            Err(Error::Status(STATUS_NOT_FOUND))
+        );
+        assert!(store.writes.borrow().is_empty());
+
+        store.set_load_options(3, "/DEBUG").unwrap();
+        let mut expected = entries[0].clone();
+        let OsOptions::Windows(options) = &mut expected.os_options else {
+            unreachable!();
+        };
+        options.load_options = "/DEBUG".into();
+        assert_eq!(
+            store.writes.take(),
+            [Write::Modify(EntryKind::Boot, expected.to_bytes())]
+        );
+        // The firmware entry has no loader options and is written back as
+        // it was.
+        store.set_load_options(1, "/DEBUG").unwrap();
+        assert_eq!(
+            store.writes.take(),
+            [Write::Modify(EntryKind::Boot, entries[1].to_bytes())]
+        );
+    }
+
+    #[test]
+    fn sets_only_the_timeout() {
+        let store = Store::default();
+        store.set_timeout(10).unwrap();
+        let writes = store.writes.take();
+        let [Write::SetOptions(bytes, fields)] = &writes[..] else {
+            panic!("{writes:?}");
+        };
+        assert_eq!(*fields, BootOptionsFields::TIMEOUT);
+        assert_eq!(BootOptions::from_bytes(bytes).unwrap().timeout, 10);
+    }
+
+    #[test]
+    fn relays_out_edited_entries() {
+        let mut entries = BootEntry::parse_list(&boot_entry_list()).unwrap();
+        let manager = &mut entries[0];
+        manager.friendly_name = "Windows".into();
+        let OsOptions::Windows(options) = &mut manager.os_options else {
+            unreachable!();
+        };
+        options.load_options = "/NOEXECUTE=OPTOUT /DEBUG".into();
+
+        let bytes = manager.to_bytes();
+        // The options grow from 56 to 72 bytes before the load path, and
+        // the friendly name shrinks from 42 to 16 bytes.
+        let os_options_length = 72 + 72;
+        let friendly_name_offset = 28 + os_options_length;
+        let boot_file_path_offset = friendly_name_offset + 16;
+        let length = boot_file_path_offset + 0x56;
+        assert_eq!(bytes.len(), length);
+        assert_eq!(u32_at(&bytes, 4) as usize, length);
+        assert_eq!(u32_at(&bytes, 16) as usize, friendly_name_offset);
+        assert_eq!(u32_at(&bytes, 20) as usize, boot_file_path_offset);
+        assert_eq!(u32_at(&bytes, 24) as usize, os_options_length);
+        // WINDOWS_OS_OPTIONS::Length and OsLoadPathOffset.
+        assert_eq!(u32_at(&bytes, 28 + 12) as usize, os_options_length);
+        assert_eq!(u32_at(&bytes, 28 + 16), 72);
+        assert_eq!(u32_at(&bytes, 28 + 72 + 8), FILE_PATH_TYPE_NT);
+        assert_eq!(BootEntry::from_bytes(&bytes).unwrap(), *manager);
+
+        manager.os_options = OsOptions::Raw(vec![1, 2, 3]);
+        let bytes = manager.to_bytes();
+        assert_eq!(u32_at(&bytes, 24), 3);
+        // The friendly name stays 4 byte aligned after odd options.
+        assert_eq!(u32_at(&bytes, 16), 32);
+        assert_eq!(BootEntry::from_bytes(&bytes).unwrap(), *manager);
+
+        // The next entry follows the 134 byte entry and its offset at the
+        // next 8 byte boundary.
+        let list = BootEntry::encode_list(&entries);
+        assert_eq!(u32_at(&list, 0), 144);
+        assert_eq!(BootEntry::parse_list(&list).unwrap(), entries);
+    }
+
+    #[test]
+    fn rejects_truncated_entries() {
+        let bytes = boot_entry_list();
+        assert!(BootEntry::parse_list(&bytes[..0x100]).is_err());
+        assert!(BootEntry::from_bytes(&bytes[4..24]).is_err());
+        // A friendly name offset past the entry.
+        let mut entry = bytes[4..0x128].to_vec();
+        entry[16..20].copy_from_slice(&0x200u32.to_le_bytes());
+        assert!(BootEntry::from_bytes(&entry).is_err());
+    }
+
+    #[test]
+    fn decodes_file_paths() {
+        for path in [
+            FilePath::Arc(r"multi(0)disk(0)rdisk(0)partition(1)\WINDOWS".into()),
+            FilePath::ArcSignature(r"signature(1234abcd)disk(0)rdisk(0)".into()),
+            FilePath::Nt(r"\Device\HarddiskVolume1\EFI\Microsoft\Boot".into()),
+            FilePath::Efi(DevicePath::new(vec![
+                DeviceNode::Pci {
+                    function: 0,
+                    device: 0x1d,
+                },
+                DeviceNode::FilePath(r"\EFI\BOOT\BOOTX64.EFI".into()),
+            ])),
+            FilePath::Other {
+                kind: 77,
+                data: vec![1, 2],
+            },
+        ] {
+            let bytes = path.to_bytes();
+            assert_eq!(u32_at(&bytes, 0), FILE_PATH_VERSION);
+            assert_eq!(u32_at(&bytes, 4) as usize, bytes.len());
+            assert_eq!(u32_at(&bytes, 8), path.kind());
+            assert_eq!(FilePath::from_bytes(&bytes).unwrap(), path);
+        }
+
+        assert_eq!(
+            FilePath::from_bytes(&from_hex("01000000 12000000 03000000 6100 6200 0000")).unwrap(),
+            FilePath::Nt("ab".into())
+        );
+        // A string without its terminator is kept as stored.
+        assert_eq!(
+            FilePath::from_bytes(&from_hex("01000000 10000000 03000000 6100 6200")).unwrap(),
+            FilePath::Other {
+                kind: FILE_PATH_TYPE_NT,
+                data: from_hex("6100 6200"),
+            }
+        );
+        assert_eq!(
+            FilePath::from_bytes(&from_hex("01000000 08000000 03000000")),
+            Err(DecodeError::Invalid {
+                offset: 4,
+                what: "FILE_PATH length",
+            })
+        );
+        assert!(FilePath::from_bytes(&from_hex("01000000 20000000 03000000 6100")).is_err());
+    }
+
+    #[test]
+    fn round_trips_driver_entries() {
+        let entries = [
+            DriverEntry {
+                id: 1,
+                friendly_name: "drv".into(),
+                driver_file_path: FilePath::Arc("multi(0)".into()),
+            },
+            DriverEntry {
+                id: 9,
+                friendly_name: "x".into(),
+                driver_file_path: FilePath::Other {
+                    kind: 77,
+                    data: vec![1, 2],
+                },
+            },
+        ];
+        let bytes = entries[0].to_bytes();
+        // "drv" ends at 28, where the file path starts.
+        assert_eq!(u32_at(&bytes, 12), 20);
+        assert_eq!(u32_at(&bytes, 16), 28);
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len());
+        assert_eq!(
+            DriverEntry::parse_list(&DriverEntry::encode_list(&entries)).unwrap(),
+            entries
+        );
+    }
+
+    #[test]
+    fn decodes_boot_options() {
+        let bytes = from_hex(
+            "
+            01 00 00 00 1e 00 00 00 1e 00 00 00 03 00 00 00
+            03 00 00 00 43 00 4f 00 4d 00 31 00 00 00
+            ",
+        );
+        let options = BootOptions::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            options,
+            BootOptions {
+                timeout: 30,
+                current_boot_entry_id: 3,
+                next_boot_entry_id: 3,
+                headless_redirection: "COM1".into(),
+            }
+        );
+        assert_eq!(options.to_bytes(), bytes);
+
+        // Without a headless redirection the structure ends at it.
+        let short = from_hex("01000000 14000000 05000000 01000000 02000000");
+        assert_eq!(
+            BootOptions::from_bytes(&short).unwrap(),
+            BootOptions {
+                timeout: 5,
+                current_boot_entry_id: 1,
+                next_boot_entry_id: 2,
+                headless_redirection: String::new(),
+            }
+        );
+        assert!(BootOptions::from_bytes(&bytes[..16]).is_err());
+    }
+}
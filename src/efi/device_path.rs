@@ -0,0 +1,864 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use windows::core::GUID;
+
+use crate::buffer::{DecodeError, Reader, Writer, format_guid};
+
+pub const HARDWARE_DEVICE_PATH: u8 = 0x01;
+pub const ACPI_DEVICE_PATH: u8 = 0x02;
+pub const MESSAGING_DEVICE_PATH: u8 = 0x03;
+pub const MEDIA_DEVICE_PATH: u8 = 0x04;
+pub const BBS_DEVICE_PATH: u8 = 0x05;
+pub const END_DEVICE_PATH: u8 = 0x7f;
+
+pub const HW_PCI_DP: u8 = 0x01;
+pub const HW_VENDOR_DP: u8 = 0x04;
+pub const HW_CONTROLLER_DP: u8 = 0x05;
+pub const ACPI_DP: u8 = 0x01;
+pub const MSG_SCSI_DP: u8 = 0x02;
+pub const MSG_USB_DP: u8 = 0x05;
+pub const MSG_VENDOR_DP: u8 = 0x0a;
+pub const MSG_MAC_ADDR_DP: u8 = 0x0b;
+pub const MSG_IPV4_DP: u8 = 0x0c;
+pub const MSG_IPV6_DP: u8 = 0x0d;
+pub const MSG_SATA_DP: u8 = 0x12;
+pub const MSG_NVME_NAMESPACE_DP: u8 = 0x17;
+pub const MEDIA_HARDDRIVE_DP: u8 = 0x01;
+pub const MEDIA_VENDOR_DP: u8 = 0x03;
+pub const MEDIA_FILEPATH_DP: u8 = 0x04;
+pub const MEDIA_PROTOCOL_DP: u8 = 0x05;
+pub const MEDIA_PIWG_FW_FILE_DP: u8 = 0x06;
+pub const MEDIA_PIWG_FW_VOL_DP: u8 = 0x07;
+pub const END_INSTANCE_DEVICE_PATH_SUBTYPE: u8 = 0x01;
+pub const END_ENTIRE_DEVICE_PATH_SUBTYPE: u8 = 0xff;
+
+/// `EFI_IP_PROTO_TCP` and `EFI_IP_PROTO_UDP` as found in the IP nodes.
+const IP_PROTOCOL_TCP: u16 = 6;
+const IP_PROTOCOL_UDP: u16 = 17;
+
+/// The partition signature of a [`HardDrive`] node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PartitionSignature {
+    /// The 32-bit MBR disk signature.
+    Mbr(u32),
+    /// The GPT partition GUID.
+    Gpt(GUID),
+    /// No signature, or a combination of format and signature type the
+    /// specification does not define, kept as stored.
+    Other {
+        format: u8,
+        signature_type: u8,
+        signature: [u8; 16],
+    },
+}
+
+/// `HARDDRIVE_DEVICE_PATH`: a partition, located in 512-byte or native
+/// sectors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HardDrive {
+    pub partition_number: u32,
+    pub start: u64,
+    pub size: u64,
+    pub signature: PartitionSignature,
+}
+
+impl HardDrive {
+    fn read(data: &mut Reader) -> Result<Self, DecodeError> {
+        let partition_number = data.u32()?;
+        let start = data.u64()?;
+        let size = data.u64()?;
+        let raw = data.array::<16>()?;
+        let format = data.u8()?;
+        let signature_type = data.u8()?;
+        let signature = match (format, signature_type) {
+            (1, 1) if raw[4..] == [0; 12] => {
+                PartitionSignature::Mbr(u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]))
+            }
+            (2, 2) => PartitionSignature::Gpt(Reader::new(&raw).guid()?),
+            _ => PartitionSignature::Other {
+                format,
+                signature_type,
+                signature: raw,
+            },
+        };
+        Ok(Self {
+            partition_number,
+            start,
+            size,
+            signature,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer
+            .u32(self.partition_number)
+            .u64(self.start)
+            .u64(self.size);
+        match self.signature {
+            PartitionSignature::Mbr(signature) => writer.u32(signature).zeros(12).u8(1).u8(1),
+            PartitionSignature::Gpt(guid) => writer.guid(&guid).u8(2).u8(2),
+            PartitionSignature::Other {
+                format,
+                signature_type,
+                signature,
+            } => writer.bytes(&signature).u8(format).u8(signature_type),
+        };
+    }
+}
+
+/// One node of a UEFI device path.
+///
+/// Nodes of a known type whose length does not match the layout the
+/// specification gives them decode as [`DeviceNode::Unknown`], so every
+/// path serializes back to the bytes it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceNode {
+    Pci {
+        function: u8,
+        device: u8,
+    },
+    VendorHardware {
+        vendor: GUID,
+        data: Vec<u8>,
+    },
+    Controller(u32),
+    /// An ACPI device by `_HID` and `_UID`, the root of most paths.
+    Acpi {
+        hid: u32,
+        uid: u32,
+    },
+    Scsi {
+        target: u16,
+        lun: u16,
+    },
+    Usb {
+        parent_port: u8,
+        interface: u8,
+    },
+    VendorMessaging {
+        vendor: GUID,
+        data: Vec<u8>,
+    },
+    Mac {
+        address: [u8; 32],
+        interface_type: u8,
+    },
+    Ipv4 {
+        local: Ipv4Addr,
+        remote: Ipv4Addr,
+        local_port: u16,
+        remote_port: u16,
+        protocol: u16,
+        static_address: bool,
+        gateway: Ipv4Addr,
+        subnet_mask: Ipv4Addr,
+    },
+    Ipv6 {
+        local: Ipv6Addr,
+        remote: Ipv6Addr,
+        local_port: u16,
+        remote_port: u16,
+        protocol: u16,
+        /// 0 for a static address, 1 and 2 for stateless and stateful
+        /// autoconfiguration.
+        origin: u8,
+        prefix_length: u8,
+        gateway: Ipv6Addr,
+    },
+    Sata {
+        hba_port: u16,
+        port_multiplier_port: u16,
+        lun: u16,
+    },
+    Nvme {
+        namespace_id: u32,
+        /// The IEEE extended unique identifier, as stored.
+        eui64: [u8; 8],
+    },
+    HardDrive(HardDrive),
+    VendorMedia {
+        vendor: GUID,
+        data: Vec<u8>,
+    },
+    FilePath(String),
+    MediaProtocol(GUID),
+    FirmwareFile(GUID),
+    FirmwareVolume(GUID),
+    /// Separates the instances of a multi-instance path.
+    EndInstance,
+    /// Terminates a path.
+    End,
+    Unknown {
+        kind: u8,
+        sub_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl DeviceNode {
+    /// Reads one node, header included.
+    pub fn read(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let start = reader.position();
+        let kind = reader.u8()?;
+        let sub_type = reader.u8()?;
+        let length = reader.u16()? as usize;
+        if length < 4 {
+            return Err(DecodeError::Invalid {
+                offset: start,
+                what: "device path node length",
+            });
+        }
+        let body = reader.bytes(length - 4)?;
+        Ok(Self::decode(kind, sub_type, body)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| Self::Unknown {
+                kind,
+                sub_type,
+                data: body.to_vec(),
+            }))
+    }
+
+    /// Decodes a known node whose body has the expected layout.
+    fn decode(kind: u8, sub_type: u8, body: &[u8]) -> Result<Option<Self>, DecodeError> {
+        let mut data = Reader::new(body);
+        let fixed = |len: usize| body.len() == len;
+        let node = match (kind, sub_type) {
+            (HARDWARE_DEVICE_PATH, HW_PCI_DP) if fixed(2) => Self::Pci {
+                function: data.u8()?,
+                device: data.u8()?,
+            },
+            (HARDWARE_DEVICE_PATH, HW_VENDOR_DP) if body.len() >= 16 => Self::VendorHardware {
+                vendor: data.guid()?,
+                data: body[16..].to_vec(),
+            },
+            (HARDWARE_DEVICE_PATH, HW_CONTROLLER_DP) if fixed(4) => Self::Controller(data.u32()?),
+            (ACPI_DEVICE_PATH, ACPI_DP) if fixed(8) => Self::Acpi {
+                hid: data.u32()?,
+                uid: data.u32()?,
+            },
+            (MESSAGING_DEVICE_PATH, MSG_SCSI_DP) if fixed(4) => Self::Scsi {
+                target: data.u16()?,
+                lun: data.u16()?,
+            },
+            (MESSAGING_DEVICE_PATH, MSG_USB_DP) if fixed(2) => Self::Usb {
+                parent_port: data.u8()?,
+                interface: data.u8()?,
+            },
+            (MESSAGING_DEVICE_PATH, MSG_VENDOR_DP) if body.len() >= 16 => Self::VendorMessaging {
+                vendor: data.guid()?,
+                data: body[16..].to_vec(),
+            },
+            (MESSAGING_DEVICE_PATH, MSG_MAC_ADDR_DP) if fixed(33) => Self::Mac {
+                address: data.array()?,
+                interface_type: data.u8()?,
+            },
+            (MESSAGING_DEVICE_PATH, MSG_IPV4_DP) if fixed(23) => Self::Ipv4 {
+                local: Ipv4Addr::from(data.array::<4>()?),
+                remote: Ipv4Addr::from(data.array::<4>()?),
+                local_port: data.u16()?,
+                remote_port: data.u16()?,
+                protocol: data.u16()?,
+                static_address: data.u8()? != 0,
+                gateway: Ipv4Addr::from(data.array::<4>()?),
+                subnet_mask: Ipv4Addr::from(data.array::<4>()?),
+            },
+            (MESSAGING_DEVICE_PATH, MSG_IPV6_DP) if fixed(56) => Self::Ipv6 {
+                local: Ipv6Addr::from(data.array::<16>()?),
+                remote: Ipv6Addr::from(data.array::<16>()?),
+                local_port: data.u16()?,
+                remote_port: data.u16()?,
+                protocol: data.u16()?,
+                origin: data.u8()?,
+                prefix_length: data.u8()?,
+                gateway: Ipv6Addr::from(data.array::<16>()?),
+            },
+            (MESSAGING_DEVICE_PATH, MSG_SATA_DP) if fixed(6) => Self::Sata {
+                hba_port: data.u16()?,
+                port_multiplier_port: data.u16()?,
+                lun: data.u16()?,
+            },
+            (MESSAGING_DEVICE_PATH, MSG_NVME_NAMESPACE_DP) if fixed(12) => Self::Nvme {
+                namespace_id: data.u32()?,
+                eui64: data.array()?,
+            },
+            (MEDIA_DEVICE_PATH, MEDIA_HARDDRIVE_DP) if fixed(38) => {
+                Self::HardDrive(HardDrive::read(&mut data)?)
+            }
+            (MEDIA_DEVICE_PATH, MEDIA_VENDOR_DP) if body.len() >= 16 => Self::VendorMedia {
+                vendor: data.guid()?,
+                data: body[16..].to_vec(),
+            },
+            (MEDIA_DEVICE_PATH, MEDIA_FILEPATH_DP) if body.len().is_multiple_of(2) => {
+                let path = data.utf16z()?;
+                // Anything after the terminator would be lost on the way
+                // back.
+                if !data.is_empty() {
+                    return Ok(None);
+                }
+                Self::FilePath(path)
+            }
+            (MEDIA_DEVICE_PATH, MEDIA_PROTOCOL_DP) if fixed(16) => {
+                Self::MediaProtocol(data.guid()?)
+            }
+            (MEDIA_DEVICE_PATH, MEDIA_PIWG_FW_FILE_DP) if fixed(16) => {
+                Self::FirmwareFile(data.guid()?)
+            }
+            (MEDIA_DEVICE_PATH, MEDIA_PIWG_FW_VOL_DP) if fixed(16) => {
+                Self::FirmwareVolume(data.guid()?)
+            }
+            (END_DEVICE_PATH, END_INSTANCE_DEVICE_PATH_SUBTYPE) if fixed(0) => Self::EndInstance,
+            (END_DEVICE_PATH, END_ENTIRE_DEVICE_PATH_SUBTYPE) if fixed(0) => Self::End,
+            _ => return Ok(None),
+        };
+        Ok(Some(node))
+    }
+
+    /// The node type and sub-type.
+    pub fn kind(&self) -> (u8, u8) {
+        match self {
+            Self::Pci { .. } => (HARDWARE_DEVICE_PATH, HW_PCI_DP),
+            Self::VendorHardware { .. } => (HARDWARE_DEVICE_PATH, HW_VENDOR_DP),
+            Self::Controller(_) => (HARDWARE_DEVICE_PATH, HW_CONTROLLER_DP),
+            Self::Acpi { .. } => (ACPI_DEVICE_PATH, ACPI_DP),
+            Self::Scsi { .. } => (MESSAGING_DEVICE_PATH, MSG_SCSI_DP),
+            Self::Usb { .. } => (MESSAGING_DEVICE_PATH, MSG_USB_DP),
+            Self::VendorMessaging { .. } => (MESSAGING_DEVICE_PATH, MSG_VENDOR_DP),
+            Self::Mac { .. } => (MESSAGING_DEVICE_PATH, MSG_MAC_ADDR_DP),
+            Self::Ipv4 { .. } => (MESSAGING_DEVICE_PATH, MSG_IPV4_DP),
+            Self::Ipv6 { .. } => (MESSAGING_DEVICE_PATH, MSG_IPV6_DP),
+            Self::Sata { .. } => (MESSAGING_DEVICE_PATH, MSG_SATA_DP),
+            Self::Nvme { .. } => (MESSAGING_DEVICE_PATH, MSG_NVME_NAMESPACE_DP),
+            Self::HardDrive(_) => (MEDIA_DEVICE_PATH, MEDIA_HARDDRIVE_DP),
+            Self::VendorMedia { .. } => (MEDIA_DEVICE_PATH, MEDIA_VENDOR_DP),
+            Self::FilePath(_) => (MEDIA_DEVICE_PATH, MEDIA_FILEPATH_DP),
+            Self::MediaProtocol(_) => (MEDIA_DEVICE_PATH, MEDIA_PROTOCOL_DP),
+            Self::FirmwareFile(_) => (MEDIA_DEVICE_PATH, MEDIA_PIWG_FW_FILE_DP),
+            Self::FirmwareVolume(_) => (MEDIA_DEVICE_PATH, MEDIA_PIWG_FW_VOL_DP),
+            Self::EndInstance => (END_DEVICE_PATH, END_INSTANCE_DEVICE_PATH_SUBTYPE),
+            Self::End => (END_DEVICE_PATH, END_ENTIRE_DEVICE_PATH_SUBTYPE),
+            Self::Unknown { kind, sub_type, .. } => (*kind, *sub_type),
+        }
+    }
+
+    /// Writes the node, header included.
+    pub fn write(&self, writer: &mut Writer) {
+        let (kind, sub_type) = self.kind();
+        let start = writer.len();
+        writer.u8(kind).u8(sub_type).u16(0);
+        match self {
+            Self::Pci { function, device } => {
+                writer.u8(*function).u8(*device);
+            }
+            Self::VendorHardware { vendor, data }
+            | Self::VendorMessaging { vendor, data }
+            | Self::VendorMedia { vendor, data } => {
+                writer.guid(vendor).bytes(data);
+            }
+            Self::Controller(controller) => {
+                writer.u32(*controller);
+            }
+            Self::Acpi { hid, uid } => {
+                writer.u32(*hid).u32(*uid);
+            }
+            Self::Scsi { target, lun } => {
+                writer.u16(*target).u16(*lun);
+            }
+            Self::Usb {
+                parent_port,
+                interface,
+            } => {
+                writer.u8(*parent_port).u8(*interface);
+            }
+            Self::Mac {
+                address,
+                interface_type,
+            } => {
+                writer.bytes(address).u8(*interface_type);
+            }
+            Self::Ipv4 {
+                local,
+                remote,
+                local_port,
+                remote_port,
+                protocol,
+                static_address,
+                gateway,
+                subnet_mask,
+            } => {
+                writer
+                    .bytes(&local.octets())
+                    .bytes(&remote.octets())
+                    .u16(*local_port)
+                    .u16(*remote_port)
+                    .u16(*protocol)
+                    .u8(*static_address as u8)
+                    .bytes(&gateway.octets())
+                    .bytes(&subnet_mask.octets());
+            }
+            Self::Ipv6 {
+                local,
+                remote,
+                local_port,
+                remote_port,
+                protocol,
+                origin,
+                prefix_length,
+                gateway,
+            } => {
+                writer
+                    .bytes(&local.octets())
+                    .bytes(&remote.octets())
+                    .u16(*local_port)
+                    .u16(*remote_port)
+                    .u16(*protocol)
+                    .u8(*origin)
+                    .u8(*prefix_length)
+                    .bytes(&gateway.octets());
+            }
+            Self::Sata {
+                hba_port,
+                port_multiplier_port,
+                lun,
+            } => {
+                writer.u16(*hba_port).u16(*port_multiplier_port).u16(*lun);
+            }
+            Self::Nvme {
+                namespace_id,
+                eui64,
+            } => {
+                writer.u32(*namespace_id).bytes(eui64);
+            }
+            Self::HardDrive(drive) => drive.write(writer),
+            Self::FilePath(path) => {
+                writer.utf16z(path);
+            }
+            Self::MediaProtocol(guid) | Self::FirmwareFile(guid) | Self::FirmwareVolume(guid) => {
+                writer.guid(guid);
+            }
+            Self::EndInstance | Self::End => {}
+            Self::Unknown { data, .. } => {
+                writer.bytes(data);
+            }
+        }
+        let length = writer.len() - start;
+        writer.patch_u16(start + 2, length as u16);
+    }
+}
+
+/// Appends `bytes` as hex digits.
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+}
+
+fn write_vendor(f: &mut fmt::Formatter<'_>, name: &str, vendor: &GUID, data: &[u8]) -> fmt::Result {
+    write!(f, "{name}({}", format_guid(vendor))?;
+    if !data.is_empty() {
+        f.write_str(",")?;
+        write_hex(f, data)?;
+    }
+    f.write_str(")")
+}
+
+fn protocol_name(protocol: u16) -> String {
+    match protocol {
+        IP_PROTOCOL_TCP => "TCP".to_owned(),
+        IP_PROTOCOL_UDP => "UDP".to_owned(),
+        other => format!("0x{other:x}"),
+    }
+}
+
+/// The text form from the UEFI specification, e.g. `Pci(0x1,0x0)`.
+impl fmt::Display for DeviceNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pci { function, device } => write!(f, "Pci(0x{device:x},0x{function:x})"),
+            Self::VendorHardware { vendor, data } => write_vendor(f, "VenHw", vendor, data),
+            Self::Controller(controller) => write!(f, "Ctrl(0x{controller:x})"),
+            Self::Acpi { hid, uid } => {
+                // EISA compressed "PNP" in the low word, the device number
+                // in the high word.
+                if hid & 0xffff == 0x41d0 {
+                    match hid >> 16 {
+                        0x0a03 => write!(f, "PciRoot(0x{uid:x})"),
+                        0x0a08 => write!(f, "PcieRoot(0x{uid:x})"),
+                        0x0604 => write!(f, "Floppy(0x{uid:x})"),
+                        0x0301 => write!(f, "Keyboard(0x{uid:x})"),
+                        0x0501 => write!(f, "Serial(0x{uid:x})"),
+                        0x0401 => write!(f, "ParallelPort(0x{uid:x})"),
+                        number => write!(f, "Acpi(PNP{number:04X},0x{uid:x})"),
+                    }
+                } else {
+                    write!(f, "Acpi(0x{hid:08x},0x{uid:x})")
+                }
+            }
+            Self::Scsi { target, lun } => write!(f, "Scsi(0x{target:x},0x{lun:x})"),
+            Self::Usb {
+                parent_port,
+                interface,
+            } => write!(f, "USB(0x{parent_port:x},0x{interface:x})"),
+            Self::VendorMessaging { vendor, data } => write_vendor(f, "VenMsg", vendor, data),
+            Self::Mac {
+                address,
+                interface_type,
+            } => {
+                // Ethernet and IEEE 802 addresses only use six bytes.
+                let size = if *interface_type <= 1 { 6 } else { 32 };
+                f.write_str("MAC(")?;
+                write_hex(f, &address[..size])?;
+                write!(f, ",0x{interface_type:x})")
+            }
+            Self::Ipv4 {
+                local,
+                remote,
+                protocol,
+                static_address,
+                gateway,
+                subnet_mask,
+                ..
+            } => write!(
+                f,
+                "IPv4({remote},{},{},{local},{gateway},{subnet_mask})",
+                protocol_name(*protocol),
+                if *static_address { "Static" } else { "DHCP" },
+            ),
+            Self::Ipv6 {
+                local,
+                remote,
+                protocol,
+                origin,
+                prefix_length,
+                gateway,
+                ..
+            } => {
+                let origin = match origin {
+                    0 => "Static".to_owned(),
+                    1 => "StatelessAutoConfigure".to_owned(),
+                    2 => "StatefulAutoConfigure".to_owned(),
+                    other => format!("0x{other:x}"),
+                };
+                write!(
+                    f,
+                    "IPv6({remote},{},{origin},{local},0x{prefix_length:x},{gateway})",
+                    protocol_name(*protocol),
+                )
+            }
+            Self::Sata {
+                hba_port,
+                port_multiplier_port,
+                lun,
+            } => write!(
+                f,
+                "Sata(0x{hba_port:x},0x{port_multiplier_port:x},0x{lun:x})"
+            ),
+            Self::Nvme {
+                namespace_id,
+                eui64,
+            } => {
+                write!(f, "NVMe(0x{namespace_id:x},")?;
+                // The EUI-64 is stored least significant byte first.
+                for (index, byte) in eui64.iter().rev().enumerate() {
+                    if index > 0 {
+                        f.write_str("-")?;
+                    }
+                    write!(f, "{byte:02X}")?;
+                }
+                f.write_str(")")
+            }
+            Self::HardDrive(drive) => {
+                write!(f, "HD({},", drive.partition_number)?;
+                match drive.signature {
+                    PartitionSignature::Mbr(signature) => write!(f, "MBR,0x{signature:08x}")?,
+                    PartitionSignature::Gpt(guid) => write!(f, "GPT,{}", format_guid(&guid))?,
+                    PartitionSignature::Other { signature_type, .. } => {
+                        write!(f, "{signature_type},0")?
+                    }
+                }
+                write!(f, ",0x{:x},0x{:x})", drive.start, drive.size)
+            }
+            Self::VendorMedia { vendor, data } => write_vendor(f, "VenMedia", vendor, data),
+            Self::FilePath(path) => f.write_str(path),
+            Self::MediaProtocol(guid) => write!(f, "Media({})", format_guid(guid)),
+            Self::FirmwareFile(guid) => write!(f, "FvFile({})", format_guid(guid)),
+            Self::FirmwareVolume(guid) => write!(f, "Fv({})", format_guid(guid)),
+            Self::EndInstance => f.write_str(","),
+            Self::End => Ok(()),
+            Self::Unknown {
+                kind,
+                sub_type,
+                data,
+            } => {
+                write!(f, "Path({kind},{sub_type}")?;
+                if !data.is_empty() {
+                    f.write_str(",")?;
+                    write_hex(f, data)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// An owned UEFI device path.
+///
+/// `nodes` holds every node up to, but not including, the terminating
+/// [`DeviceNode::End`]; the instances of a multi-instance path are separated
+/// by [`DeviceNode::EndInstance`].
+///
+/// ```
+/// use windows_native::efi::{DeviceNode, DevicePath};
+///
+/// let path = DevicePath::new(vec![
+///     DeviceNode::Acpi { hid: 0x0a0341d0, uid: 0 },
+///     DeviceNode::Pci { function: 0, device: 0x1d },
+///     DeviceNode::FilePath(r"\EFI\BOOT\BOOTX64.EFI".into()),
+/// ]);
+/// assert_eq!(path.to_string(), r"PciRoot(0x0)/Pci(0x1d,0x0)/\EFI\BOOT\BOOTX64.EFI");
+/// assert_eq!(DevicePath::from_bytes(&path.to_bytes()).unwrap(), path);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DevicePath {
+    pub nodes: Vec<DeviceNode>,
+}
+
+impl DevicePath {
+    pub fn new(nodes: Vec<DeviceNode>) -> Self {
+        Self { nodes }
+    }
+
+    /// Parses one path from the start of `bytes`, up to its end node.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::read(&mut Reader::new(bytes))
+    }
+
+    /// Parses back to back paths, such as an `EFI_LOAD_OPTION` file path
+    /// list.
+    pub fn parse_list(bytes: &[u8]) -> Result<Vec<Self>, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let mut paths = Vec::new();
+        while !reader.is_empty() {
+            paths.push(Self::read(&mut reader)?);
+        }
+        Ok(paths)
+    }
+
+    pub fn encode_list(paths: &[Self]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for path in paths {
+            path.write(&mut writer);
+        }
+        writer.into_inner()
+    }
+
+    pub fn read(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut nodes = Vec::new();
+        loop {
+            match DeviceNode::read(reader)? {
+                DeviceNode::End => return Ok(Self { nodes }),
+                node => nodes.push(node),
+            }
+        }
+    }
+
+    pub fn write(&self, writer: &mut Writer) {
+        for node in &self.nodes {
+            node.write(writer);
+        }
+        DeviceNode::End.write(writer);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    /// The nodes of each instance, without the separators.
+    pub fn instances(&self) -> impl Iterator<Item = &[DeviceNode]> {
+        self.nodes
+            .split(|node| *node == DeviceNode::EndInstance)
+            .filter(|instance| !instance.is_empty())
+    }
+
+    /// The media file path of the first instance, as Windows Boot Manager
+    /// entries end in.
+    pub fn file_path(&self) -> Option<&str> {
+        self.instances().next()?.iter().find_map(|node| match node {
+            DeviceNode::FilePath(path) => Some(path.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The partition the first instance points into.
+    pub fn hard_drive(&self) -> Option<&HardDrive> {
+        self.instances().next()?.iter().find_map(|node| match node {
+            DeviceNode::HardDrive(drive) => Some(drive),
+            _ => None,
+        })
+    }
+}
+
+/// The text form from the UEFI specification: nodes separated by `/`,
+/// instances by `,`.
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for node in &self.nodes {
+            if *node == DeviceNode::EndInstance {
+                f.write_str(",")?;
+                separator = "";
+                continue;
+            }
+            write!(f, "{separator}{node}")?;
+            separator = "/";
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::from_hex;
+
+    /// Decodes the single node `hex`, checks it serializes back to the same
+    /// bytes and returns its text form.
+    fn text(hex: &str) -> String {
+        let bytes = from_hex(hex);
+        let node = DeviceNode::read(&mut Reader::new(&bytes)).unwrap();
+        let mut writer = Writer::new();
+        node.write(&mut writer);
+        assert_eq!(writer.into_inner(), bytes, "{node:?}");
+        node.to_string()
+    }
+
+    #[test]
+    fn formats_acpi_nodes() {
+        assert_eq!(text("02 01 0c 00 d0 41 03 0a 00 00 00 00"), "PciRoot(0x0)");
+        assert_eq!(text("02 01 0c 00 d0 41 08 0a 01 00 00 00"), "PcieRoot(0x1)");
+        assert_eq!(text("02 01 0c 00 d0 41 01 03 00 00 00 00"), "Keyboard(0x0)");
+        assert_eq!(
+            text("02 01 0c 00 d0 41 05 0a 00 00 00 00"),
+            "Acpi(PNP0A05,0x0)"
+        );
+        assert_eq!(
+            text("02 01 0c 00 d0 41 0f 0c 02 00 00 00"),
+            "Acpi(PNP0C0F,0x2)"
+        );
+        assert_eq!(
+            text("02 01 0c 00 01 02 03 04 00 00 00 00"),
+            "Acpi(0x04030201,0x0)"
+        );
+    }
+
+    #[test]
+    fn formats_storage_nodes() {
+        assert_eq!(text("01 01 06 00 00 17"), "Pci(0x17,0x0)");
+        assert_eq!(
+            text("03 12 0a 00 02 00 ff ff 00 00"),
+            "Sata(0x2,0xffff,0x0)"
+        );
+        assert_eq!(text("03 02 08 00 01 00 00 00"), "Scsi(0x1,0x0)");
+        assert_eq!(text("03 05 06 00 03 00"), "USB(0x3,0x0)");
+        assert_eq!(
+            text("03 17 10 00 01 00 00 00 0c 3f b2 71 b5 38 25 00"),
+            "NVMe(0x1,00-25-38-B5-71-B2-3F-0C)"
+        );
+    }
+
+    #[test]
+    fn formats_network_nodes() {
+        let mut mac = String::from("03 0b 25 00 00 15 5d 01 02 03");
+        mac.push_str(&" 00".repeat(26));
+        mac.push_str(" 01");
+        assert_eq!(text(&mac), "MAC(00155d010203,0x1)");
+
+        assert_eq!(
+            text(
+                "03 0c 1b 00 c0 a8 01 0a c0 a8 01 01 00 00 45 00 11 00 00
+                 c0 a8 01 01 ff ff ff 00"
+            ),
+            "IPv4(192.168.1.1,UDP,DHCP,192.168.1.10,192.168.1.1,255.255.255.0)"
+        );
+        assert_eq!(
+            text(
+                "03 0c 1b 00 0a 00 00 05 0a 00 00 01 00 00 bc 0c 06 00 01
+                 0a 00 00 01 ff 00 00 00"
+            ),
+            "IPv4(10.0.0.1,TCP,Static,10.0.0.5,10.0.0.1,255.0.0.0)"
+        );
+        assert_eq!(
+            text(
+                "03 0d 3c 00
+                 fe 80 00 00 00 00 00 00 00 00 00 00 00 00 00 05
+                 20 01 0d b8 00 00 00 00 00 00 00 00 00 00 00 01
+                 00 00 50 00 06 00 01 40
+                 fe 80 00 00 00 00 00 00 00 00 00 00 00 00 00 01"
+            ),
+            "IPv6(2001:db8::1,TCP,StatelessAutoConfigure,fe80::5,0x40,fe80::1)"
+        );
+    }
+
+    #[test]
+    fn formats_hard_drive_nodes() {
+        let mbr = text(
+            "04 01 2a 00 01 00 00 00 00 08 00 00 00 00 00 00 00 00 10 00 00 00 00 00
+             cd ab 34 12 00 00 00 00 00 00 00 00 00 00 00 00 01 01",
+        );
+        assert_eq!(mbr, "HD(1,MBR,0x1234abcd,0x800,0x100000)");
+        let gpt = text(
+            "04 01 2a 00 02 00 00 00 00 28 03 00 00 00 00 00 00 00 00 0e 00 00 00 00
+             c7 1a 8a 4c 1d 62 4f 4c 9a 7e 55 c8 2f 0b a5 37 02 02",
+        );
+        assert_eq!(
+            gpt,
+            "HD(2,GPT,4c8a1ac7-621d-4c4f-9a7e-55c82f0ba537,0x32800,0xe000000)"
+        );
+        // An MBR signature with stray bytes after it is kept as stored.
+        let other = text(
+            "04 01 2a 00 01 00 00 00 00 08 00 00 00 00 00 00 00 00 10 00 00 00 00 00
+             cd ab 34 12 01 00 00 00 00 00 00 00 00 00 00 00 01 01",
+        );
+        assert_eq!(other, "HD(1,1,0,0x800,0x100000)");
+    }
+
+    #[test]
+    fn keeps_unexpected_layouts() {
+        // A PCI node one byte too long.
+        assert_eq!(text("01 01 07 00 00 17 00"), "Path(1,1,001700)");
+        // A file path with bytes after its terminator.
+        assert_eq!(
+            text("04 04 0a 00 41 00 00 00 42 00"),
+            "Path(4,4,410000004200)"
+        );
+        assert_eq!(
+            DeviceNode::read(&mut Reader::new(&from_hex("01 01 03 00"))),
+            Err(DecodeError::Invalid {
+                offset: 0,
+                what: "device path node length",
+            })
+        );
+        assert!(DeviceNode::read(&mut Reader::new(&from_hex("01 01 06 00 00"))).is_err());
+    }
+
+    #[test]
+    fn formats_multi_instance_paths() {
+        let bytes = from_hex(
+            "02 01 0c 00 d0 41 03 0a 00 00 00 00 01 01 06 00 00 1f 7f 01 04 00
+             02 01 0c 00 d0 41 03 0a 00 00 00 00 01 01 06 00 00 14 7f ff 04 00",
+        );
+        let path = DevicePath::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            path.to_string(),
+            "PciRoot(0x0)/Pci(0x1f,0x0),PciRoot(0x0)/Pci(0x14,0x0)"
+        );
+        assert_eq!(path.instances().count(), 2);
+        assert_eq!(path.to_bytes(), bytes);
+        assert!(path.file_path().is_none() && path.hard_drive().is_none());
+        // A path without its end node.
+        assert!(DevicePath::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use windows::Win32::Foundation::{NTSTATUS, STATUS_INVALID_PARAMETER};
+
+use super::DevicePath;
+use crate::buffer::{DecodeError, Reader, Writer};
+
+/// Decodes `BootOrder` or `DriverOrder`: an array of load option numbers.
+pub fn parse_boot_order(value: &[u8]) -> Result<Vec<u16>, DecodeError> {
+    if !value.len().is_multiple_of(2) {
+        return Err(DecodeError::Invalid {
+            offset: value.len() - 1,
+            what: "boot order",
+        });
+    }
+    Ok(value
+        .chunks_exact(2)
+        .map(|number| u16::from_le_bytes([number[0], number[1]]))
+        .collect())
+}
+
+pub fn encode_boot_order(order: &[u16]) -> Vec<u8> {
+    order
+        .iter()
+        .flat_map(|number| number.to_le_bytes())
+        .collect()
+}
+
+/// The variables holding `EFI_LOAD_OPTION`s.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum LoadOptionKind {
+    Boot,
+    Driver,
+    SysPrep,
+    PlatformRecovery,
+}
+
+impl LoadOptionKind {
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::Boot => "Boot",
+            Self::Driver => "Driver",
+            Self::SysPrep => "SysPrep",
+            Self::PlatformRecovery => "PlatformRecovery",
+        }
+    }
+
+    /// The variable holding option `number`, e.g. `Boot0001`.
+    pub fn variable_name(self, number: u16) -> String {
+        format!("{}{number:04X}", self.prefix())
+    }
+
+    /// Splits a variable name such as `Driver000A` into its kind and number.
+    pub fn parse_variable_name(name: &str) -> Option<(Self, u16)> {
+        [
+            Self::Boot,
+            Self::Driver,
+            Self::SysPrep,
+            Self::PlatformRecovery,
+        ]
+        .into_iter()
+        .find_map(|kind| {
+            let digits = name.strip_prefix(kind.prefix())?;
+            // The specification only allows upper case hex digits.
+            let hex = |b: u8| b.is_ascii_digit() || (b'A'..=b'F').contains(&b);
+            if digits.len() != 4 || !digits.bytes().all(hex) {
+                return None;
+            }
+            Some((kind, u16::from_str_radix(digits, 16).ok()?))
+        })
+    }
+}
+
+/// `EFI_LOAD_OPTION::Attributes`.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct LoadOptionAttributes(pub u32);
+
+impl LoadOptionAttributes {
+    pub const ACTIVE: Self = Self(0x1);
+    pub const FORCE_RECONNECT: Self = Self(0x2);
+    pub const HIDDEN: Self = Self(0x8);
+    pub const CATEGORY_BOOT: Self = Self(0x0);
+    pub const CATEGORY_APP: Self = Self(0x100);
+    /// The bits holding the category.
+    pub const CATEGORY: Self = Self(0x1f00);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn category(self) -> Self {
+        Self(self.0 & Self::CATEGORY.0)
+    }
+}
+
+impl std::ops::BitOr for LoadOptionAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for LoadOptionAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::fmt::Debug for LoadOptionAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LoadOptionAttributes({:#x})", self.0)
+    }
+}
+
+/// An `EFI_LOAD_OPTION`, the value of `Boot####` and `Driver####`.
+///
+/// `file_path_list` is kept as the packed device paths it is stored as;
+/// [`LoadOption::device_paths`] and [`LoadOption::set_device_paths`]
+/// convert it from and to [`DevicePath`]s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadOption {
+    pub attributes: LoadOptionAttributes,
+    pub description: String,
+    pub file_path_list: Vec<u8>,
+    /// Whatever follows the file paths, for Windows Boot Manager a
+    /// `WINDOWS` BCD object reference.
+    pub optional_data: Vec<u8>,
+}
+
+impl LoadOption {
+    pub fn is_active(&self) -> bool {
+        self.attributes.contains(LoadOptionAttributes::ACTIVE)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let attributes = LoadOptionAttributes(reader.u32()?);
+        let file_path_list_length = reader.u16()? as usize;
+        let description = reader.utf16z()?;
+        let file_path_list = reader.bytes(file_path_list_length)?.to_vec();
+        let optional_data = reader.bytes(reader.remaining())?.to_vec();
+        Ok(Self {
+            attributes,
+            description,
+            file_path_list,
+            optional_data,
+        })
+    }
+
+    /// Encodes the option, failing with `STATUS_INVALID_PARAMETER` if
+    /// `file_path_list` is too long for its 16-bit length.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, NTSTATUS> {
+        let file_path_list_length =
+            u16::try_from(self.file_path_list.len()).map_err(|_| STATUS_INVALID_PARAMETER)?;
+        let mut writer = Writer::new();
+        writer
+            .u32(self.attributes.0)
+            .u16(file_path_list_length)
+            .utf16z(&self.description)
+            .bytes(&self.file_path_list)
+            .bytes(&self.optional_data);
+        Ok(writer.into_inner())
+    }
+
+    /// The device paths of `file_path_list`. The first one locates the
+    /// image.
+    pub fn device_paths(&self) -> Result<Vec<DevicePath>, DecodeError> {
+        DevicePath::parse_list(&self.file_path_list)
+    }
+
+    pub fn set_device_paths(&mut self, paths: &[DevicePath]) {
+        self.file_path_list = DevicePath::encode_list(paths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{format_guid, from_hex},
+        efi::{DeviceNode, PartitionSignature},
+    };
+
+    /// `Boot0000` of a GPT disk: Windows Boot Manager on partition 1, with
+    /// the `WINDOWS` BCD object reference as optional data.
+    fn boot0000() -> Vec<u8> {
+        from_hex(
+            "
+            01 00 00 00 74 00 57 00 69 00 6e 00 64 00 6f 00
+            77 00 73 00 20 00 42 00 6f 00 6f 00 74 00 20 00
+            4d 00 61 00 6e 00 61 00 67 00 65 00 72 00 00 00
+            04 01 2a 00 01 00 00 00 00 08 00 00 00 00 00 00
+            00 20 03 00 00 00 00 00 c7 1a 8a 4c 1d 62 4f 4c
+            9a 7e 55 c8 2f 0b a5 37 02 02 04 04 46 00 5c 00
+            45 00 46 00 49 00 5c 00 4d 00 69 00 63 00 72 00
+            6f 00 73 00 6f 00 66 00 74 00 5c 00 42 00 6f 00
+            6f 00 74 00 5c 00 62 00 6f 00 6f 00 74 00 6d 00
+            67 00 66 00 77 00 2e 00 65 00 66 00 69 00 00 00
+            7f ff 04 00 57 49 4e 44 4f 57 53 00 01 00 00 00
+            88 00 00 00 78 00 00 00 42 00 43 00 44 00 4f 00
+            42 00 4a 00 45 00 43 00 54 00 3d 00 7b 00 39 00
+            64 00 65 00 61 00 38 00 36 00 32 00 63 00 2d 00
+            35 00 63 00 64 00 64 00 2d 00 34 00 65 00 37 00
+            30 00 2d 00 61 00 63 00 63 00 31 00 2d 00 66 00
+            33 00 32 00 62 00 33 00 34 00 34 00 64 00 34 00
+            37 00 39 00 35 00 7d 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00
+            ",
+        )
+    }
+
+    #[test]
+    fn decodes_boot_order() {
+        assert_eq!(
+            parse_boot_order(&from_hex("0300 0000 0100 8000")).unwrap(),
+            [3, 0, 1, 0x80]
+        );
+        assert_eq!(parse_boot_order(&[]).unwrap(), []);
+        assert_eq!(
+            parse_boot_order(&[3, 0, 1]),
+            Err(DecodeError::Invalid {
+                offset: 2,
+                what: "boot order",
+            })
+        );
+        assert_eq!(
+            encode_boot_order(&[3, 0, 0x2001]),
+            from_hex("0300 0000 0120")
+        );
+    }
+
+    #[test]
+    fn decodes_windows_boot_manager() {
+        let bytes = boot0000();
+        let option = LoadOption::from_bytes(&bytes).unwrap();
+        assert!(option.is_active());
+        assert_eq!(
+            option.attributes.category(),
+            LoadOptionAttributes::CATEGORY_BOOT
+        );
+        assert_eq!(option.description, "Windows Boot Manager");
+        assert_eq!(option.file_path_list.len(), 0x74);
+        assert!(option.optional_data.starts_with(b"WINDOWS\0"));
+        assert_eq!(option.optional_data.len(), 0x88);
+
+        let paths = option.device_paths().unwrap();
+        assert_eq!(paths.len(), 1);
+        let drive = paths[0].hard_drive().unwrap();
+        assert_eq!(
+            (drive.partition_number, drive.start, drive.size),
+            (1, 2048, 204800)
+        );
+        let PartitionSignature::Gpt(guid) = drive.signature else {
+            panic!("{:?}", drive.signature);
+        };
+        assert_eq!(format_guid(&guid), "4c8a1ac7-621d-4c4f-9a7e-55c82f0ba537");
+        assert_eq!(
+            paths[0].file_path(),
+            Some(r"\EFI\Microsoft\Boot\bootmgfw.efi")
+        );
+        assert_eq!(
+            paths[0].to_string(),
+            r"HD(1,GPT,4c8a1ac7-621d-4c4f-9a7e-55c82f0ba537,0x800,0x32000)/\EFI\Microsoft\Boot\bootmgfw.efi"
+        );
+        assert_eq!(option.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn edits_load_options() {
+        let mut option = LoadOption::from_bytes(&boot0000()).unwrap();
+        option.description = "Windows".into();
+        let mut paths = option.device_paths().unwrap();
+        paths[0].nodes[1] = DeviceNode::FilePath(r"\EFI\BOOT\BOOTX64.EFI".into());
+        option.set_device_paths(&paths);
+        let bytes = option.to_bytes().unwrap();
+        // The file path list length follows the shorter path.
+        assert_eq!(
+            u16::from_le_bytes([bytes[4], bytes[5]]) as usize,
+            42 + 48 + 4
+        );
+        let decoded = LoadOption::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, option);
+        assert_eq!(
+            decoded.device_paths().unwrap()[0].file_path(),
+            Some(r"\EFI\BOOT\BOOTX64.EFI")
+        );
+
+        assert!(LoadOption::from_bytes(&boot0000()[..100]).is_err());
+        assert!(LoadOption::from_bytes(&[1, 0, 0, 0]).is_err());
+
+        // A file path list past 64 KiB has no valid length to encode.
+        option.file_path_list = vec![0x7f; 0x1_0000];
+        assert_eq!(option.to_bytes(), Err(STATUS_INVALID_PARAMETER));
+        option.file_path_list.pop();
+        assert_eq!(option.to_bytes().unwrap()[4..6], [0xff, 0xff]);
+    }
+
+    #[test]
+    fn names_option_variables() {
+        assert_eq!(LoadOptionKind::Boot.variable_name(0), "Boot0000");
+        assert_eq!(LoadOptionKind::Driver.variable_name(0xab), "Driver00AB");
+        assert_eq!(
+            LoadOptionKind::parse_variable_name("Boot000A"),
+            Some((LoadOptionKind::Boot, 10))
+        );
+        assert_eq!(
+            LoadOptionKind::parse_variable_name("PlatformRecovery0001"),
+            Some((LoadOptionKind::PlatformRecovery, 1))
+        );
+        for name in ["Boot000a", "BootOrder", "Boot00001", "Boot", "SysPrep+001"] {
+            assert_eq!(LoadOptionKind::parse_variable_name(name), None, "{name}");
+        }
+    }
+}
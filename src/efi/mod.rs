@@ -0,0 +1,87 @@
+//! UEFI variables through the `NtQuerySystemEnvironmentValueEx` family.
+//!
+//! A [`VariableStore`] reads, writes and enumerates variables;
+//! [`NativeVariables`] issues the system calls, which need
+//! `SeSystemEnvironmentPrivilege`. The payload decoders, for `BootOrder`,
+//! [`LoadOption`] and [`SignatureList`], are pure Rust and parse from and
+//! serialize to the bytes stored in the variables. [`DevicePath`] models the
+//! device paths load options and boot entries point at, with the text form
+//! of the UEFI specification.
+//!
+//! The Windows view of the same entries, through `NtEnumerateBootEntries`
+//! and its siblings, is a [`BootStore`]: [`BootEntry`], [`DriverEntry`] and
+//! [`BootOptions`] decode and serialize the `BOOT_ENTRY`, `EFI_DRIVER_ENTRY`
+//! and `BOOT_OPTIONS` buffers, and [`NativeBoot`] issues the system calls.
+//!
+//! ```no_run
+//! use windows_native::efi::{NativeVariables, VariableStore};
+//!
+//! for number in NativeVariables.boot_order()? {
+//!     let option = NativeVariables.boot_option(number)?;
+//!     for path in option.device_paths()? {
+//!         println!("Boot{number:04X} {} {path}", option.description);
+//!     }
+//! }
+//! # Ok::<(), windows_native::efi::Error>(())
+//! ```
+
+mod boot_entry;
+mod device_path;
+mod load_option;
+mod signature;
+mod variable;
+
+pub use boot_entry::*;
+pub use device_path::*;
+pub use load_option::*;
+pub use signature::*;
+pub use variable::*;
+use windows::{Win32::Foundation::NTSTATUS, core::GUID};
+
+use crate::buffer::DecodeError;
+
+/// `EFI_GLOBAL_VARIABLE`, the vendor of the variables defined by the UEFI
+/// specification such as `BootOrder` and `SecureBoot`.
+pub const EFI_GLOBAL_VARIABLE: GUID = GUID::from_values(
+    0x8be4df61,
+    0x93ca,
+    0x11d2,
+    [0xaa, 0x0d, 0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c],
+);
+
+/// `EFI_IMAGE_SECURITY_DATABASE_GUID`, the vendor of `db` and `dbx`.
+pub const EFI_IMAGE_SECURITY_DATABASE: GUID = GUID::from_values(
+    0xd719b2cb,
+    0x3d3a,
+    0x4596,
+    [0xa3, 0xbc, 0xda, 0xd0, 0x0e, 0x67, 0x65, 0x6f],
+);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    Status(NTSTATUS),
+    Decode(DecodeError),
+}
+
+impl From<NTSTATUS> for Error {
+    fn from(status: NTSTATUS) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "NTSTATUS {:#010x}", status.0),
+            Self::Decode(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,332 @@
+use windows::core::GUID;
+
+use crate::buffer::{DecodeError, Reader, Writer};
+
+pub const EFI_CERT_SHA1_GUID: GUID = GUID::from_values(
+    0x826ca512,
+    0xcf10,
+    0x4ac9,
+    [0xb1, 0x87, 0xbe, 0x01, 0x49, 0x66, 0x31, 0xbd],
+);
+pub const EFI_CERT_SHA256_GUID: GUID = GUID::from_values(
+    0xc1c41626,
+    0x504c,
+    0x4092,
+    [0xac, 0xa9, 0x41, 0xf9, 0x36, 0x93, 0x43, 0x28],
+);
+pub const EFI_CERT_SHA384_GUID: GUID = GUID::from_values(
+    0xff3e5307,
+    0x9fd0,
+    0x48c9,
+    [0x85, 0xf1, 0x8a, 0xd5, 0x6c, 0x70, 0x1e, 0x01],
+);
+pub const EFI_CERT_SHA512_GUID: GUID = GUID::from_values(
+    0x093e0fae,
+    0xa6c4,
+    0x4f50,
+    [0x9f, 0x1b, 0xd4, 0x1e, 0x2b, 0x89, 0xc1, 0x9a],
+);
+pub const EFI_CERT_RSA2048_GUID: GUID = GUID::from_values(
+    0x3c5766e8,
+    0x269c,
+    0x4e34,
+    [0xaa, 0x14, 0xed, 0x77, 0x6e, 0x85, 0xb3, 0xb6],
+);
+pub const EFI_CERT_X509_GUID: GUID = GUID::from_values(
+    0xa5c059a1,
+    0x94e4,
+    0x4aa7,
+    [0x87, 0xb5, 0xab, 0x15, 0x5c, 0x2b, 0xf0, 0x72],
+);
+pub const EFI_CERT_X509_SHA256_GUID: GUID = GUID::from_values(
+    0x3bd2a492,
+    0x96c0,
+    0x4079,
+    [0xb4, 0x20, 0xfc, 0xf9, 0x8e, 0xf1, 0x03, 0xed],
+);
+
+/// Size of the fixed `EFI_SIGNATURE_LIST` header.
+const LIST_HEADER_SIZE: usize = 28;
+
+/// What the entries of an `EFI_SIGNATURE_LIST` hold, from its
+/// `SignatureType`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum SignatureKind {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    Rsa2048,
+    /// A DER encoded certificate.
+    X509,
+    /// The SHA-256 digest of a certificate's TBS data.
+    X509Sha256,
+    Other(GUID),
+}
+
+impl From<GUID> for SignatureKind {
+    fn from(value: GUID) -> Self {
+        match value {
+            EFI_CERT_SHA1_GUID => Self::Sha1,
+            EFI_CERT_SHA256_GUID => Self::Sha256,
+            EFI_CERT_SHA384_GUID => Self::Sha384,
+            EFI_CERT_SHA512_GUID => Self::Sha512,
+            EFI_CERT_RSA2048_GUID => Self::Rsa2048,
+            EFI_CERT_X509_GUID => Self::X509,
+            EFI_CERT_X509_SHA256_GUID => Self::X509Sha256,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<SignatureKind> for GUID {
+    fn from(value: SignatureKind) -> Self {
+        match value {
+            SignatureKind::Sha1 => EFI_CERT_SHA1_GUID,
+            SignatureKind::Sha256 => EFI_CERT_SHA256_GUID,
+            SignatureKind::Sha384 => EFI_CERT_SHA384_GUID,
+            SignatureKind::Sha512 => EFI_CERT_SHA512_GUID,
+            SignatureKind::Rsa2048 => EFI_CERT_RSA2048_GUID,
+            SignatureKind::X509 => EFI_CERT_X509_GUID,
+            SignatureKind::X509Sha256 => EFI_CERT_X509_SHA256_GUID,
+            SignatureKind::Other(guid) => guid,
+        }
+    }
+}
+
+/// An `EFI_SIGNATURE_DATA` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureData {
+    pub owner: GUID,
+    pub data: Vec<u8>,
+}
+
+/// An `EFI_SIGNATURE_LIST`, the unit `db`, `dbx`, `KEK` and `PK` are made
+/// of.
+///
+/// Every entry of a list has the same size; [`SignatureList::to_bytes`]
+/// pads shorter entries with zeros to the longest one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureList {
+    pub signature_type: GUID,
+    pub header: Vec<u8>,
+    pub signatures: Vec<SignatureData>,
+}
+
+impl SignatureList {
+    pub fn kind(&self) -> SignatureKind {
+        SignatureKind::from(self.signature_type)
+    }
+
+    /// Decodes the concatenated lists of a signature database.
+    pub fn parse_all(bytes: &[u8]) -> Result<Vec<Self>, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let mut lists = Vec::new();
+        while !reader.is_empty() {
+            lists.push(Self::read(&mut reader)?);
+        }
+        Ok(lists)
+    }
+
+    pub fn encode_all(lists: &[Self]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for list in lists {
+            list.write(&mut writer);
+        }
+        writer.into_inner()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::read(&mut Reader::new(bytes))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let start = reader.position();
+        let signature_type = reader.guid()?;
+        let list_size = reader.u32()? as usize;
+        let header_size = reader.u32()? as usize;
+        let signature_size = reader.u32()? as usize;
+        let body_size = list_size
+            .checked_sub(LIST_HEADER_SIZE)
+            .and_then(|size| size.checked_sub(header_size))
+            .ok_or(DecodeError::Invalid {
+                offset: start,
+                what: "signature list size",
+            })?;
+        if signature_size < 16 || !body_size.is_multiple_of(signature_size) {
+            return Err(DecodeError::Invalid {
+                offset: start,
+                what: "signature size",
+            });
+        }
+        let header = reader.bytes(header_size)?.to_vec();
+        reader.check_count(body_size / signature_size, signature_size)?;
+        let mut signatures = Vec::with_capacity(body_size / signature_size);
+        for _ in 0..body_size / signature_size {
+            signatures.push(SignatureData {
+                owner: reader.guid()?,
+                data: reader.bytes(signature_size - 16)?.to_vec(),
+            });
+        }
+        Ok(Self {
+            signature_type,
+            header,
+            signatures,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        let data_size = self
+            .signatures
+            .iter()
+            .map(|signature| signature.data.len())
+            .max()
+            .unwrap_or(0);
+        let signature_size = 16 + data_size;
+        writer
+            .guid(&self.signature_type)
+            .u32(
+                (LIST_HEADER_SIZE + self.header.len() + signature_size * self.signatures.len())
+                    as u32,
+            )
+            .u32(self.header.len() as u32)
+            .u32(signature_size as u32)
+            .bytes(&self.header);
+        for signature in &self.signatures {
+            writer
+                .guid(&signature.owner)
+                .bytes(&signature.data)
+                .zeros(data_size - signature.data.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{format_guid, from_hex};
+
+    /// A `db` holding a Microsoft certificate, shortened to its first bytes,
+    /// and two SHA-256 image hashes.
+    fn db() -> Vec<u8> {
+        from_hex(
+            "
+            a1 59 c0 a5 e4 94 a7 4a 87 b5 ab 15 5c 2b f0 72
+            54 00 00 00 00 00 00 00 38 00 00 00 bd 9a fa 77
+            59 03 32 4d bd 60 28 f4 e7 8f 78 4b 30 82 05 d7
+            30 82 03 bf a0 03 02 01 02 02 0a 61 07 76 56 00
+            00 00 00 00 08 30 0d 06 09 2a 86 48 86 f7 0d 01
+            01 0b 05 00 26 16 c4 c1 4c 50 92 40 ac a9 41 f9
+            36 93 43 28 7c 00 00 00 00 00 00 00 30 00 00 00
+            bd 9a fa 77 59 03 32 4d bd 60 28 f4 e7 8f 78 4b
+            80 b4 d9 69 31 bf 0d 02 fd 91 a6 1e 19 d1 4f 1d
+            a4 52 e6 6d b2 40 8c a8 60 4d 41 1f 92 65 9f 0a
+            bd 9a fa 77 59 03 32 4d bd 60 28 f4 e7 8f 78 4b
+            f5 2f 83 a3 fa 9c fb d6 92 0f 72 28 24 db e4 03
+            45 34 d2 5b 85 07 24 6b 3b 95 7d ac 6e 1b ce 7a
+            ",
+        )
+    }
+
+    #[test]
+    fn decodes_signature_database() {
+        let bytes = db();
+        let lists = SignatureList::parse_all(&bytes).unwrap();
+        assert_eq!(lists.len(), 2);
+
+        assert_eq!(lists[0].kind(), SignatureKind::X509);
+        assert!(lists[0].header.is_empty());
+        assert_eq!(lists[0].signatures.len(), 1);
+        let certificate = &lists[0].signatures[0];
+        assert_eq!(
+            format_guid(&certificate.owner),
+            "77fa9abd-0359-4d32-bd60-28f4e78f784b"
+        );
+        assert_eq!(certificate.data.len(), 40);
+        assert_eq!(certificate.data[..4], [0x30, 0x82, 0x05, 0xd7]);
+
+        assert_eq!(lists[1].kind(), SignatureKind::Sha256);
+        assert_eq!(lists[1].signatures.len(), 2);
+        assert_eq!(lists[1].signatures[0].data[..4], [0x80, 0xb4, 0xd9, 0x69]);
+        assert_eq!(lists[1].signatures[1].data[..4], [0xf5, 0x2f, 0x83, 0xa3]);
+
+        assert_eq!(SignatureList::encode_all(&lists), bytes);
+        assert_eq!(SignatureList::from_bytes(&bytes).unwrap(), lists[0]);
+        assert_eq!(lists[1].to_bytes(), bytes[84..]);
+    }
+
+    #[test]
+    fn rejects_malformed_lists() {
+        let bytes = db();
+        assert!(SignatureList::parse_all(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(SignatureList::parse_all(&[]).unwrap(), []);
+
+        // A list size smaller than its header.
+        let mut short = bytes[84..].to_vec();
+        short[16..20].copy_from_slice(&20u32.to_le_bytes());
+        assert!(matches!(
+            SignatureList::from_bytes(&short),
+            Err(DecodeError::Invalid {
+                what: "signature list size",
+                ..
+            })
+        ));
+        // Entries that do not divide the body evenly.
+        let mut uneven = bytes[84..].to_vec();
+        uneven[24..28].copy_from_slice(&47u32.to_le_bytes());
+        assert!(matches!(
+            SignatureList::from_bytes(&uneven),
+            Err(DecodeError::Invalid {
+                what: "signature size",
+                ..
+            })
+        ));
+        // A list size claiming far more hashes than the variable holds fails
+        // before anything is allocated for them.
+        let mut oversized = bytes[84..].to_vec();
+        oversized[16..20].copy_from_slice(&(28 + 48 * 89_478_000u32).to_le_bytes());
+        assert_eq!(
+            SignatureList::from_bytes(&oversized),
+            Err(DecodeError::Truncated {
+                offset: 28,
+                needed: 48 * 89_478_000
+            })
+        );
+    }
+
+    #[test]
+    fn pads_entries_to_the_longest() {
+        let list = SignatureList {
+            signature_type: EFI_CERT_X509_GUID,
+            header: Vec::new(),
+            signatures: vec![
+                SignatureData {
+                    owner: GUID::from_u128(1),
+                    data: vec![1; 4],
+                },
+                SignatureData {
+                    owner: GUID::from_u128(2),
+                    data: vec![2; 8],
+                },
+            ],
+        };
+        let bytes = list.to_bytes();
+        assert_eq!(bytes.len(), 28 + 2 * 24);
+        let decoded = SignatureList::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.signatures[0].data, [1, 1, 1, 1, 0, 0, 0, 0]);
+        assert_eq!(decoded.signatures[1], list.signatures[1]);
+        for kind in [
+            SignatureKind::Sha1,
+            SignatureKind::X509Sha256,
+            SignatureKind::Other(GUID::from_u128(3)),
+        ] {
+            assert_eq!(SignatureKind::from(GUID::from(kind)), kind);
+        }
+    }
+}
@@ -0,0 +1,605 @@
+use windows::{
+    Win32::Foundation::{
+        NTSTATUS, STATUS_BUFFER_OVERFLOW, STATUS_BUFFER_TOO_SMALL, STATUS_VARIABLE_NOT_FOUND,
+    },
+    core::GUID,
+};
+
+use super::{
+    EFI_GLOBAL_VARIABLE, EFI_IMAGE_SECURITY_DATABASE, Error, LoadOption, LoadOptionKind,
+    SignatureList, encode_boot_order, parse_boot_order,
+};
+use crate::{
+    buffer::{DecodeError, Reader, to_wide, unicode_string},
+    check,
+    ntexapi::{
+        EFI_VARIABLE_APPEND_WRITE, EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS,
+        EFI_VARIABLE_BOOTSERVICE_ACCESS, EFI_VARIABLE_ENHANCED_AUTHENTICATED_ACCESS,
+        EFI_VARIABLE_HARDWARE_ERROR_RECORD, EFI_VARIABLE_NON_VOLATILE, EFI_VARIABLE_RUNTIME_ACCESS,
+        EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS, NtEnumerateSystemEnvironmentValuesEx,
+        NtQuerySystemEnvironmentValueEx, NtSetSystemEnvironmentValueEx,
+        SYSTEM_ENVIRONMENT_INFORMATION_CLASS,
+    },
+};
+
+/// The `EFI_VARIABLE_*` attributes of a variable.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct VariableAttributes(pub u32);
+
+impl VariableAttributes {
+    pub const NON_VOLATILE: Self = Self(EFI_VARIABLE_NON_VOLATILE);
+    pub const BOOTSERVICE_ACCESS: Self = Self(EFI_VARIABLE_BOOTSERVICE_ACCESS);
+    pub const RUNTIME_ACCESS: Self = Self(EFI_VARIABLE_RUNTIME_ACCESS);
+    pub const HARDWARE_ERROR_RECORD: Self = Self(EFI_VARIABLE_HARDWARE_ERROR_RECORD);
+    pub const AUTHENTICATED_WRITE_ACCESS: Self = Self(EFI_VARIABLE_AUTHENTICATED_WRITE_ACCESS);
+    pub const TIME_BASED_AUTHENTICATED_WRITE_ACCESS: Self =
+        Self(EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS);
+    pub const APPEND_WRITE: Self = Self(EFI_VARIABLE_APPEND_WRITE);
+    pub const ENHANCED_AUTHENTICATED_ACCESS: Self =
+        Self(EFI_VARIABLE_ENHANCED_AUTHENTICATED_ACCESS);
+
+    /// Non-volatile and visible at boot and run time, what the
+    /// specification requires of `BootOrder` and the load options.
+    pub const BOOT_VARIABLE: Self = Self(
+        EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS,
+    );
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for VariableAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for VariableAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::fmt::Debug for VariableAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VariableAttributes({:#x})", self.0)
+    }
+}
+
+/// A variable as listed by `SystemEnvironmentNameInformation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableName {
+    pub name: String,
+    pub vendor: GUID,
+}
+
+/// A variable with its value, as listed by
+/// `SystemEnvironmentValueInformation` or read back with
+/// [`VariableStore::get`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub vendor: GUID,
+    pub attributes: VariableAttributes,
+    pub value: Vec<u8>,
+}
+
+/// Walks the `VARIABLE_NAME` entries of a
+/// `SystemEnvironmentNameInformation` buffer.
+pub fn parse_variable_names(buffer: &[u8]) -> Result<Vec<VariableName>, DecodeError> {
+    let mut names = Vec::new();
+    walk_entries(buffer, |entry| {
+        let next = entry.u32()?;
+        let vendor = entry.guid()?;
+        names.push(VariableName {
+            name: entry.utf16z()?,
+            vendor,
+        });
+        Ok(next)
+    })?;
+    Ok(names)
+}
+
+/// Walks the `VARIABLE_NAME_AND_VALUE` entries of a
+/// `SystemEnvironmentValueInformation` buffer.
+pub fn parse_variables(buffer: &[u8]) -> Result<Vec<Variable>, DecodeError> {
+    let mut variables = Vec::new();
+    walk_entries(buffer, |entry| {
+        let start = entry.position();
+        let next = entry.u32()?;
+        let value_offset = entry.u32()? as usize;
+        let value_length = entry.u32()? as usize;
+        let attributes = VariableAttributes(entry.u32()?);
+        let vendor = entry.guid()?;
+        let name = entry.utf16z()?;
+        let value = Reader::at(entry.data(), start + value_offset)
+            .bytes(value_length)?
+            .to_vec();
+        variables.push(Variable {
+            name,
+            vendor,
+            attributes,
+            value,
+        });
+        Ok(next)
+    })?;
+    Ok(variables)
+}
+
+/// Calls `read` on each entry of a `NextEntryOffset` chained buffer. `read`
+/// returns the offset of the next entry, 0 for the last one.
+fn walk_entries(
+    buffer: &[u8],
+    mut read: impl FnMut(&mut Reader) -> Result<u32, DecodeError>,
+) -> Result<(), DecodeError> {
+    let mut offset = 0;
+    while offset < buffer.len() {
+        let next = read(&mut Reader::at(buffer, offset))? as usize;
+        if next == 0 {
+            break;
+        }
+        offset += next;
+    }
+    Ok(())
+}
+
+/// Decodes a one byte boolean variable such as `SecureBoot`.
+pub fn parse_bool(value: &[u8]) -> Result<bool, DecodeError> {
+    Reader::new(value).u8().map(|byte| byte != 0)
+}
+
+/// The Secure Boot variables of [`EFI_GLOBAL_VARIABLE`]. `None` where the
+/// firmware does not define the variable.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct SecureBootState {
+    pub secure_boot: Option<bool>,
+    pub setup_mode: Option<bool>,
+    pub audit_mode: Option<bool>,
+    pub deployed_mode: Option<bool>,
+}
+
+impl SecureBootState {
+    /// Secure Boot is enforced: enabled and not in setup mode.
+    pub fn enforced(&self) -> bool {
+        self.secure_boot == Some(true) && self.setup_mode != Some(true)
+    }
+}
+
+/// Reads and writes UEFI variables.
+///
+/// Implementors provide the three raw operations; the typed accessors are
+/// built on top of them.
+pub trait VariableStore {
+    fn read(&self, name: &str, vendor: &GUID) -> Result<(Vec<u8>, VariableAttributes), NTSTATUS>;
+
+    /// Writes `value`. An empty value deletes the variable.
+    fn write(
+        &self,
+        name: &str,
+        vendor: &GUID,
+        value: &[u8],
+        attributes: VariableAttributes,
+    ) -> Result<(), NTSTATUS>;
+
+    /// The raw enumeration buffer for `class`.
+    fn enumerate(&self, class: SYSTEM_ENVIRONMENT_INFORMATION_CLASS) -> Result<Vec<u8>, NTSTATUS>;
+
+    fn names(&self) -> Result<Vec<VariableName>, Error> {
+        let buffer =
+            self.enumerate(SYSTEM_ENVIRONMENT_INFORMATION_CLASS::SystemEnvironmentNameInformation)?;
+        Ok(parse_variable_names(&buffer)?)
+    }
+
+    fn variables(&self) -> Result<Vec<Variable>, Error> {
+        let buffer = self
+            .enumerate(SYSTEM_ENVIRONMENT_INFORMATION_CLASS::SystemEnvironmentValueInformation)?;
+        Ok(parse_variables(&buffer)?)
+    }
+
+    fn get(&self, name: &str, vendor: &GUID) -> Result<Variable, NTSTATUS> {
+        let (value, attributes) = self.read(name, vendor)?;
+        Ok(Variable {
+            name: name.to_owned(),
+            vendor: *vendor,
+            attributes,
+            value,
+        })
+    }
+
+    fn set(&self, variable: &Variable) -> Result<(), NTSTATUS> {
+        self.write(
+            &variable.name,
+            &variable.vendor,
+            &variable.value,
+            variable.attributes,
+        )
+    }
+
+    fn delete(&self, name: &str, vendor: &GUID) -> Result<(), NTSTATUS> {
+        self.write(name, vendor, &[], VariableAttributes::default())
+    }
+
+    fn boot_order(&self) -> Result<Vec<u16>, Error> {
+        let (value, _) = self.read("BootOrder", &EFI_GLOBAL_VARIABLE)?;
+        Ok(parse_boot_order(&value)?)
+    }
+
+    fn set_boot_order(&self, order: &[u16]) -> Result<(), NTSTATUS> {
+        self.write(
+            "BootOrder",
+            &EFI_GLOBAL_VARIABLE,
+            &encode_boot_order(order),
+            VariableAttributes::BOOT_VARIABLE,
+        )
+    }
+
+    /// Reads the `Boot####`, `Driver####`, ... variable `number` of `kind`.
+    fn load_option(&self, kind: LoadOptionKind, number: u16) -> Result<LoadOption, Error> {
+        let (value, _) = self.read(&kind.variable_name(number), &EFI_GLOBAL_VARIABLE)?;
+        Ok(LoadOption::from_bytes(&value)?)
+    }
+
+    fn set_load_option(
+        &self,
+        kind: LoadOptionKind,
+        number: u16,
+        option: &LoadOption,
+    ) -> Result<(), NTSTATUS> {
+        self.write(
+            &kind.variable_name(number),
+            &EFI_GLOBAL_VARIABLE,
+            &option.to_bytes()?,
+            VariableAttributes::BOOT_VARIABLE,
+        )
+    }
+
+    fn boot_option(&self, number: u16) -> Result<LoadOption, Error> {
+        self.load_option(LoadOptionKind::Boot, number)
+    }
+
+    fn driver_option(&self, number: u16) -> Result<LoadOption, Error> {
+        self.load_option(LoadOptionKind::Driver, number)
+    }
+
+    fn secure_boot(&self) -> Result<SecureBootState, Error> {
+        let flag = |name: &str| -> Result<Option<bool>, Error> {
+            match self.read(name, &EFI_GLOBAL_VARIABLE) {
+                Ok((value, _)) => Ok(Some(parse_bool(&value)?)),
+                Err(STATUS_VARIABLE_NOT_FOUND) => Ok(None),
+                Err(status) => Err(status.into()),
+            }
+        };
+        Ok(SecureBootState {
+            secure_boot: flag("SecureBoot")?,
+            setup_mode: flag("SetupMode")?,
+            audit_mode: flag("AuditMode")?,
+            deployed_mode: flag("DeployedMode")?,
+        })
+    }
+
+    /// Reads an image security database such as `db` or `dbx`.
+    fn signature_database(&self, name: &str) -> Result<Vec<SignatureList>, Error> {
+        let (value, _) = self.read(name, &EFI_IMAGE_SECURITY_DATABASE)?;
+        Ok(SignatureList::parse_all(&value)?)
+    }
+}
+
+/// The [`VariableStore`] backed by the firmware.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeVariables;
+
+impl VariableStore for NativeVariables {
+    fn read(&self, name: &str, vendor: &GUID) -> Result<(Vec<u8>, VariableAttributes), NTSTATUS> {
+        let units = to_wide(name);
+        let mut name = unicode_string(&units);
+        let mut value = vec![0u8; 0x400];
+        loop {
+            let mut length = value.len() as u32;
+            let mut attributes = 0;
+            let status = unsafe {
+                NtQuerySystemEnvironmentValueEx(
+                    &mut name,
+                    vendor,
+                    value.as_mut_ptr().cast(),
+                    &mut length,
+                    &mut attributes,
+                )
+            };
+            match status {
+                STATUS_BUFFER_TOO_SMALL | STATUS_BUFFER_OVERFLOW
+                    if length as usize > value.len() =>
+                {
+                    value.resize(length as usize, 0)
+                }
+                status if status.is_err() => return Err(status),
+                _ => {
+                    value.truncate(length as usize);
+                    return Ok((value, VariableAttributes(attributes)));
+                }
+            }
+        }
+    }
+
+    fn write(
+        &self,
+        name: &str,
+        vendor: &GUID,
+        value: &[u8],
+        attributes: VariableAttributes,
+    ) -> Result<(), NTSTATUS> {
+        let units = to_wide(name);
+        let mut name = unicode_string(&units);
+        let status = unsafe {
+            NtSetSystemEnvironmentValueEx(
+                &mut name,
+                vendor,
+                value.as_ptr() as *mut _,
+                value.len() as u32,
+                attributes.0,
+            )
+        };
+        check(status)
+    }
+
+    fn enumerate(&self, class: SYSTEM_ENVIRONMENT_INFORMATION_CLASS) -> Result<Vec<u8>, NTSTATUS> {
+        let mut buffer = vec![0u8; 0x4000];
+        loop {
+            let mut length = buffer.len() as u32;
+            let status = unsafe {
+                NtEnumerateSystemEnvironmentValuesEx(
+                    class as u32,
+                    buffer.as_mut_ptr().cast(),
+                    &mut length,
+                )
+            };
+            match status {
+                STATUS_BUFFER_TOO_SMALL | STATUS_BUFFER_OVERFLOW => {
+                    let grown = (length as usize).max(buffer.len() * 2);
+                    buffer.resize(grown, 0)
+                }
+                status if status.is_err() => return Err(status),
+                _ => {
+                    buffer.truncate(length as usize);
+                    return Ok(buffer);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use super::*;
+    use crate::{buffer::from_hex, efi::LoadOptionAttributes};
+
+    /// A `SystemEnvironmentValueInformation` buffer: `SecureBoot` and
+    /// `SetupMode`, each padded with `cc` to the next 8-byte boundary, then
+    /// `BootOrder`.
+    fn values() -> Vec<u8> {
+        from_hex(
+            "
+            38 00 00 00 36 00 00 00 01 00 00 00 06 00 00 00
+            61 df e4 8b ca 93 d2 11 aa 0d 00 e0 98 03 2b 8c
+            53 00 65 00 63 00 75 00 72 00 65 00 42 00 6f 00
+            6f 00 74 00 00 00 01 cc 38 00 00 00 34 00 00 00
+            01 00 00 00 06 00 00 00 61 df e4 8b ca 93 d2 11
+            aa 0d 00 e0 98 03 2b 8c 53 00 65 00 74 00 75 00
+            70 00 4d 00 6f 00 64 00 65 00 00 00 00 cc cc cc
+            00 00 00 00 34 00 00 00 06 00 00 00 07 00 00 00
+            61 df e4 8b ca 93 d2 11 aa 0d 00 e0 98 03 2b 8c
+            42 00 6f 00 6f 00 74 00 4f 00 72 00 64 00 65 00
+            72 00 00 00 03 00 00 00 01 00
+            ",
+        )
+    }
+
+    /// A `SystemEnvironmentNameInformation` buffer: `Boot0000`, `db` and
+    /// `Lang`, the first two padded with `cc` to a 4-byte boundary.
+    fn names() -> Vec<u8> {
+        from_hex(
+            "
+            28 00 00 00 61 df e4 8b ca 93 d2 11 aa 0d 00 e0
+            98 03 2b 8c 42 00 6f 00 6f 00 74 00 30 00 30 00
+            30 00 30 00 00 00 cc cc 1c 00 00 00 cb b2 19 d7
+            3a 3d 96 45 a3 bc da d0 0e 67 65 6f 64 00 62 00
+            00 00 cc cc 00 00 00 00 61 df e4 8b ca 93 d2 11
+            aa 0d 00 e0 98 03 2b 8c 4c 00 61 00 6e 00 67 00
+            00 00
+            ",
+        )
+    }
+
+    /// A firmware with a set of variables, recording the writes.
+    #[derive(Default)]
+    struct Mock {
+        variables: HashMap<String, Vec<u8>>,
+        writes: RefCell<Vec<(String, Vec<u8>, VariableAttributes)>>,
+    }
+
+    impl Mock {
+        fn with(variables: &[(&str, &[u8])]) -> Self {
+            Self {
+                variables: variables
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_vec()))
+                    .collect(),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl VariableStore for Mock {
+        fn read(&self, name: &str, _: &GUID) -> Result<(Vec<u8>, VariableAttributes), NTSTATUS> {
+            self.variables
+                .get(name)
+                .map(|value| (value.clone(), VariableAttributes::BOOT_VARIABLE))
+                .ok_or(STATUS_VARIABLE_NOT_FOUND)
+        }
+
+        fn write(
+            &self,
+            name: &str,
+            _: &GUID,
+            value: &[u8],
+            attributes: VariableAttributes,
+        ) -> Result<(), NTSTATUS> {
+            self.writes
+                .borrow_mut()
+                .push((name.to_owned(), value.to_vec(), attributes));
+            Ok(())
+        }
+
+        fn enumerate(
+            &self,
+            class: SYSTEM_ENVIRONMENT_INFORMATION_CLASS,
+        ) -> Result<Vec<u8>, NTSTATUS> {
+            Ok(match class {
+                SYSTEM_ENVIRONMENT_INFORMATION_CLASS::SystemEnvironmentNameInformation => names(),
+                _ => values(),
+            })
+        }
+    }
+
+    #[test]
+    fn walks_value_entries() {
+        let variables = parse_variables(&values()).unwrap();
+        let summary = variables
+            .iter()
+            .map(|variable| {
+                (
+                    variable.name.as_str(),
+                    variable.attributes.0,
+                    variable.value.as_slice(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("SecureBoot", 6, &[1][..]),
+                ("SetupMode", 6, &[0][..]),
+                ("BootOrder", 7, &[3, 0, 0, 0, 1, 0][..]),
+            ]
+        );
+        assert!(
+            variables
+                .iter()
+                .all(|variable| variable.vendor == EFI_GLOBAL_VARIABLE)
+        );
+        assert!(
+            variables[2]
+                .attributes
+                .contains(VariableAttributes::BOOT_VARIABLE)
+        );
+        assert!(
+            !variables[0]
+                .attributes
+                .contains(VariableAttributes::NON_VOLATILE)
+        );
+        assert_eq!(parse_variables(&[]).unwrap(), []);
+    }
+
+    #[test]
+    fn walks_name_entries() {
+        let names = parse_variable_names(&names()).unwrap();
+        let summary = names
+            .iter()
+            .map(|name| (name.name.as_str(), name.vendor))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("Boot0000", EFI_GLOBAL_VARIABLE),
+                ("db", EFI_IMAGE_SECURITY_DATABASE),
+                ("Lang", EFI_GLOBAL_VARIABLE),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_entries() {
+        let values = values();
+        // The value of the last entry runs past the end.
+        assert!(parse_variables(&values[..values.len() - 1]).is_err());
+        // A name without its terminator.
+        let names = names();
+        assert!(parse_variable_names(&names[..names.len() - 2]).is_err());
+        // A next entry offset pointing past the end ends the walk.
+        let mut chained = names[..40].to_vec();
+        chained[..4].copy_from_slice(&0x100u32.to_le_bytes());
+        assert_eq!(parse_variable_names(&chained).unwrap().len(), 1);
+        // A value offset pointing past the end.
+        let mut values = values;
+        values[4..8].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert!(matches!(
+            parse_variables(&values),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn reads_secure_boot_state() {
+        let mock = Mock::with(&[("SecureBoot", &[1]), ("SetupMode", &[0])]);
+        let state = mock.secure_boot().unwrap();
+        assert_eq!(
+            state,
+            SecureBootState {
+                secure_boot: Some(true),
+                setup_mode: Some(false),
+                audit_mode: None,
+                deployed_mode: None,
+            }
+        );
+        assert!(state.enforced());
+
+        let setup = Mock::with(&[("SecureBoot", &[1]), ("SetupMode", &[1])]);
+        assert!(!setup.secure_boot().unwrap().enforced());
+        assert!(!Mock::default().secure_boot().unwrap().enforced());
+        let empty = Mock::with(&[("SecureBoot", &[])]);
+        assert!(matches!(empty.secure_boot(), Err(Error::Decode(_))));
+        assert!(parse_bool(&[2]).unwrap());
+    }
+
+    #[test]
+    fn reads_and_writes_boot_variables() {
+        let mock = Mock::with(&[("BootOrder", &[3, 0, 0, 0, 1, 0]), ("Boot0001", &[1])]);
+        assert_eq!(mock.boot_order().unwrap(), [3, 0, 1]);
+        assert!(matches!(mock.boot_option(1), Err(Error::Decode(_))));
+        assert_eq!(
+            mock.boot_option(2),
+            Err(Error::Status(STATUS_VARIABLE_NOT_FOUND))
+        );
+        assert_eq!(mock.variables().unwrap().len(), 3);
+        assert_eq!(mock.names().unwrap()[1].name, "db");
+
+        mock.set_boot_order(&[1, 3]).unwrap();
+        let option = LoadOption {
+            attributes: LoadOptionAttributes::ACTIVE,
+            description: "Shell".into(),
+            ..Default::default()
+        };
+        mock.set_load_option(LoadOptionKind::Driver, 0xa, &option)
+            .unwrap();
+        mock.delete("Boot0001", &EFI_GLOBAL_VARIABLE).unwrap();
+        let writes = mock.writes.borrow();
+        assert_eq!(
+            writes[0],
+            (
+                "BootOrder".into(),
+                vec![1, 0, 3, 0],
+                VariableAttributes::BOOT_VARIABLE
+            )
+        );
+        assert_eq!(writes[1].0, "Driver000A");
+        assert_eq!(LoadOption::from_bytes(&writes[1].1).unwrap(), option);
+        assert_eq!(
+            writes[2],
+            ("Boot0001".into(), Vec::new(), VariableAttributes::default())
+        );
+    }
+}
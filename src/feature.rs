@@ -0,0 +1,1015 @@
+//! Feature configuration through the `RtlQueryFeatureConfiguration` family.
+//!
+//! Windows stages features behind numeric IDs whose state is decided by the
+//! configuration with the highest [`FeaturePriority`]. [`Features`] reads
+//! one feature or all of them, with the change stamp the state was read at,
+//! lists the features with usage subscriptions and applies overrides built
+//! from [`FeatureUpdate`]s. Boot configurations take effect on the next boot,
+//! runtime ones immediately.
+//!
+//! The calls go through a [`FeatureBackend`], [`NativeFeatures`] being the
+//! one that calls ntdll. The kernel also shares the configurations as
+//! sections described by `SystemFeatureConfigurationSectionInformation`;
+//! [`FeatureSections`] and [`parse_feature_table`] decode those buffers in
+//! plain Rust.
+//!
+//! ```no_run
+//! use windows_native::feature::{FeatureKind, FeatureState, Features, NativeFeatures};
+//!
+//! let features = Features::new(NativeFeatures);
+//! let (all, stamp) = features.query_all(FeatureKind::Runtime)?;
+//! let enabled = all.iter().filter(|f| f.state == FeatureState::Enabled).count();
+//! println!("{enabled} of {} features enabled at stamp {stamp}", all.len());
+//! # Ok::<(), windows::Win32::Foundation::NTSTATUS>(())
+//! ```
+
+use std::{collections::BTreeMap, fmt, mem};
+
+use windows::Win32::Foundation::{HANDLE, NTSTATUS, STATUS_BUFFER_TOO_SMALL};
+
+use crate::{
+    buffer::{DecodeError, Reader, Writer},
+    check,
+    ntexapi::{SYSTEM_FEATURE_CONFIGURATION_SECTIONS_INFORMATION, SYSTEM_INFORMATION_CLASS},
+    ntrtl::{
+        RTL_FEATURE_CONFIGURATION, RTL_FEATURE_CONFIGURATION_TYPE,
+        RtlQueryAllFeatureConfigurations, RtlQueryFeatureConfiguration,
+        RtlQueryFeatureConfigurationChangeStamp, RtlQueryFeatureUsageNotificationSubscriptions,
+        RtlSetFeatureConfigurations,
+    },
+    system::{Sizing, SystemInfo},
+};
+
+/// The size of an `RTL_FEATURE_CONFIGURATION`.
+pub const FEATURE_CONFIGURATION_SIZE: usize = 12;
+
+/// The size of an `RTL_FEATURE_CONFIGURATION_UPDATE`.
+pub const FEATURE_UPDATE_SIZE: usize = 32;
+
+/// Which configuration set a query or update applies to.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum FeatureKind {
+    /// Read at boot; changes take effect on the next boot.
+    Boot,
+    /// The live configuration.
+    #[default]
+    Runtime,
+}
+
+impl From<FeatureKind> for RTL_FEATURE_CONFIGURATION_TYPE {
+    fn from(kind: FeatureKind) -> Self {
+        match kind {
+            FeatureKind::Boot => Self::RtlFeatureConfigurationBoot,
+            FeatureKind::Runtime => Self::RtlFeatureConfigurationRuntime,
+        }
+    }
+}
+
+/// The priority of a configuration. Higher priorities win.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeaturePriority(pub u8);
+
+impl FeaturePriority {
+    pub const IMAGE_DEFAULT: Self = Self(0);
+    pub const ENROLLMENT: Self = Self(2);
+    pub const SERVICE: Self = Self(4);
+    pub const USER: Self = Self(8);
+    pub const USER_POLICY: Self = Self(10);
+    pub const TEST: Self = Self(12);
+    pub const IMAGE_OVERRIDE: Self = Self(15);
+}
+
+impl fmt::Debug for FeaturePriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FeaturePriority({})", self.0)
+    }
+}
+
+/// The `EnabledState` of a configuration.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum FeatureState {
+    #[default]
+    Default,
+    Disabled,
+    Enabled,
+    Unknown(u8),
+}
+
+impl FeatureState {
+    pub const fn from_raw(raw: u8) -> Self {
+        match raw {
+            0 => Self::Default,
+            1 => Self::Disabled,
+            2 => Self::Enabled,
+            raw => Self::Unknown(raw),
+        }
+    }
+
+    pub const fn to_raw(self) -> u8 {
+        match self {
+            Self::Default => 0,
+            Self::Disabled => 1,
+            Self::Enabled => 2,
+            Self::Unknown(raw) => raw,
+        }
+    }
+}
+
+/// How a variant payload is stored.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum PayloadKind {
+    #[default]
+    None,
+    /// The payload is the value itself.
+    Resident,
+    /// The payload refers to data stored elsewhere.
+    External,
+    Unknown(u8),
+}
+
+impl PayloadKind {
+    pub const fn from_raw(raw: u8) -> Self {
+        match raw {
+            0 => Self::None,
+            1 => Self::Resident,
+            2 => Self::External,
+            raw => Self::Unknown(raw),
+        }
+    }
+
+    pub const fn to_raw(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Resident => 1,
+            Self::External => 2,
+            Self::Unknown(raw) => raw,
+        }
+    }
+}
+
+/// A feature configuration, from an `RTL_FEATURE_CONFIGURATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct Feature {
+    pub id: u32,
+    pub priority: FeaturePriority,
+    pub state: FeatureState,
+    /// Set by an experiment rather than a fixed configuration.
+    pub is_wexp_configuration: bool,
+    pub has_subscriptions: bool,
+    pub variant: u8,
+    pub payload_kind: PayloadKind,
+    pub payload: u32,
+}
+
+impl Feature {
+    /// Splits the packed flags of a configuration.
+    pub const fn from_parts(id: u32, flags: u32, payload: u32) -> Self {
+        Self {
+            id,
+            priority: FeaturePriority((flags & 0xf) as u8),
+            state: FeatureState::from_raw(((flags >> 4) & 0x3) as u8),
+            is_wexp_configuration: flags & (1 << 6) != 0,
+            has_subscriptions: flags & (1 << 7) != 0,
+            variant: ((flags >> 8) & 0x3f) as u8,
+            payload_kind: PayloadKind::from_raw(((flags >> 14) & 0x3) as u8),
+            payload,
+        }
+    }
+
+    /// The packed flags of the configuration.
+    pub const fn flags(&self) -> u32 {
+        (self.priority.0 as u32 & 0xf)
+            | (self.state.to_raw() as u32 & 0x3) << 4
+            | (self.is_wexp_configuration as u32) << 6
+            | (self.has_subscriptions as u32) << 7
+            | (self.variant as u32 & 0x3f) << 8
+            | (self.payload_kind.to_raw() as u32 & 0x3) << 14
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state == FeatureState::Enabled
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let id = reader.u32()?;
+        let flags = reader.u32()?;
+        let payload = reader.u32()?;
+        Ok(Self::from_parts(id, flags, payload))
+    }
+}
+
+/// Decodes packed `RTL_FEATURE_CONFIGURATION` records.
+pub fn parse_features(bytes: &[u8]) -> Result<Vec<Feature>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let count = bytes.len() / FEATURE_CONFIGURATION_SIZE;
+    (0..count).map(|_| Feature::read(&mut reader)).collect()
+}
+
+/// Decodes a feature configuration table, the contents of a configuration
+/// section: a count followed by that many configurations, sorted by ID.
+pub fn parse_feature_table(bytes: &[u8]) -> Result<Vec<Feature>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let count = reader.u32()? as usize;
+    reader.check_count(count, FEATURE_CONFIGURATION_SIZE)?;
+    (0..count).map(|_| Feature::read(&mut reader)).collect()
+}
+
+/// A configuration section shared by the kernel.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSection {
+    pub change_stamp: u64,
+    /// The section handle, opened in the calling process.
+    pub section: HANDLE,
+    pub size: u64,
+}
+
+/// A `SYSTEM_FEATURE_CONFIGURATION_SECTIONS_INFORMATION`: the boot, runtime
+/// and usage subscription sections.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSections {
+    pub overall_change_stamp: u64,
+    pub sections: [FeatureSection; 3],
+}
+
+impl FeatureSections {
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let mut sections = Self {
+            overall_change_stamp: reader.u64()?,
+            ..Self::default()
+        };
+        for section in &mut sections.sections {
+            section.change_stamp = reader.u64()?;
+            section.section = HANDLE(reader.usize()? as isize);
+            reader.align(8)?;
+            section.size = reader.u64()?;
+        }
+        Ok(sections)
+    }
+
+    pub fn boot(&self) -> &FeatureSection {
+        &self.sections[0]
+    }
+
+    pub fn runtime(&self) -> &FeatureSection {
+        &self.sections[1]
+    }
+
+    pub fn usage_subscriptions(&self) -> &FeatureSection {
+        &self.sections[2]
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemFeatureConfigurationSectionInformation {}
+
+impl SystemInfo for SystemFeatureConfigurationSectionInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemFeatureConfigurationSectionInformation;
+    const SIZING: Sizing = Sizing::Fixed(mem::size_of::<
+        SYSTEM_FEATURE_CONFIGURATION_SECTIONS_INFORMATION,
+    >());
+    type Input = ();
+    type Output = FeatureSections;
+
+    fn decode(bytes: &[u8]) -> Result<FeatureSections, DecodeError> {
+        FeatureSections::parse(bytes)
+    }
+}
+
+/// What an update does to the configuration of its feature.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum UpdateOperation {
+    /// Sets the state and the variant.
+    #[default]
+    Set,
+    /// Removes the configuration at the priority.
+    Reset,
+    /// Sets only the state.
+    SetState,
+    /// Sets only the variant.
+    SetVariant,
+}
+
+/// A change to one feature at one priority, serialized as an
+/// `RTL_FEATURE_CONFIGURATION_UPDATE`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct FeatureUpdate {
+    pub id: u32,
+    pub priority: FeaturePriority,
+    pub state: FeatureState,
+    /// `FEATURE_ENABLED_STATE_OPTIONS`: 1 marks a configuration that may
+    /// be changed by the user.
+    pub state_options: u32,
+    pub variant: u8,
+    pub payload_kind: PayloadKind,
+    pub payload: u32,
+    pub operation: UpdateOperation,
+}
+
+impl FeatureUpdate {
+    /// Enables or disables `id` at `priority`.
+    pub fn set(id: u32, priority: FeaturePriority, enabled: bool) -> Self {
+        Self {
+            id,
+            priority,
+            state: if enabled {
+                FeatureState::Enabled
+            } else {
+                FeatureState::Disabled
+            },
+            operation: UpdateOperation::SetState,
+            ..Self::default()
+        }
+    }
+
+    /// Removes the configuration of `id` at `priority`.
+    pub fn reset(id: u32, priority: FeaturePriority) -> Self {
+        Self {
+            id,
+            priority,
+            operation: UpdateOperation::Reset,
+            ..Self::default()
+        }
+    }
+
+    pub fn write(&self, writer: &mut Writer) {
+        let operation = match self.operation {
+            UpdateOperation::Set => 0,
+            UpdateOperation::Reset => 1,
+            UpdateOperation::SetState => 2,
+            UpdateOperation::SetVariant => 4,
+        };
+        writer
+            .u32(self.id)
+            .u32(self.priority.0 as u32)
+            .u32(self.state.to_raw() as u32)
+            .u32(self.state_options)
+            .u8(self.variant)
+            .zeros(3)
+            .u32(self.payload_kind.to_raw() as u32)
+            .u32(self.payload)
+            .u32(operation);
+    }
+}
+
+/// Serializes `updates` into the array `RtlSetFeatureConfigurations` takes.
+pub fn encode_updates(updates: &[FeatureUpdate]) -> Vec<u8> {
+    let mut writer = Writer::default();
+    for update in updates {
+        update.write(&mut writer);
+    }
+    writer.into_inner()
+}
+
+/// The calls the feature API is built on.
+pub trait FeatureBackend {
+    /// Reads the configuration of `id`, returning it packed with the change
+    /// stamp it was read at.
+    fn query(&self, id: u32, kind: FeatureKind) -> Result<(Vec<u8>, u64), NTSTATUS>;
+
+    /// Reads every configuration, packed, with the change stamp.
+    fn query_all(&self, kind: FeatureKind) -> Result<(Vec<u8>, u64), NTSTATUS>;
+
+    /// Reads the configurations with usage subscriptions, packed.
+    fn subscriptions(&self) -> Result<Vec<u8>, NTSTATUS>;
+
+    fn change_stamp(&self) -> u64;
+
+    /// Applies `count` serialized updates, failing if the configuration
+    /// changed since `change_stamp` when one is given.
+    fn set(
+        &self,
+        kind: FeatureKind,
+        updates: &[u8],
+        count: u32,
+        change_stamp: Option<u64>,
+    ) -> Result<(), NTSTATUS>;
+}
+
+/// The [`FeatureBackend`] that calls ntdll.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeFeatures;
+
+/// Runs a query that reports its count, growing the buffer until the
+/// configurations fit.
+fn query_list(
+    mut query: impl FnMut(*mut RTL_FEATURE_CONFIGURATION, &mut u32) -> NTSTATUS,
+) -> Result<Vec<u8>, NTSTATUS> {
+    let mut count = 0u32;
+    loop {
+        let mut buffer = (0..count)
+            .map(|_| RTL_FEATURE_CONFIGURATION::default())
+            .collect::<Vec<_>>();
+        let capacity = count;
+        let pointer = if buffer.is_empty() {
+            std::ptr::null_mut()
+        } else {
+            buffer.as_mut_ptr()
+        };
+        let status = query(pointer, &mut count);
+        if status == STATUS_BUFFER_TOO_SMALL && count > capacity {
+            continue;
+        }
+        check(status)?;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                buffer.as_ptr().cast::<u8>(),
+                count.min(capacity) as usize * FEATURE_CONFIGURATION_SIZE,
+            )
+        };
+        return Ok(bytes.to_vec());
+    }
+}
+
+impl FeatureBackend for NativeFeatures {
+    fn query(&self, id: u32, kind: FeatureKind) -> Result<(Vec<u8>, u64), NTSTATUS> {
+        let mut stamp = 0;
+        let mut raw = RTL_FEATURE_CONFIGURATION::default();
+        check(unsafe { RtlQueryFeatureConfiguration(id, kind.into(), &mut stamp, &mut raw) })?;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                (&raw as *const RTL_FEATURE_CONFIGURATION).cast::<u8>(),
+                FEATURE_CONFIGURATION_SIZE,
+            )
+        };
+        Ok((bytes.to_vec(), stamp))
+    }
+
+    fn query_all(&self, kind: FeatureKind) -> Result<(Vec<u8>, u64), NTSTATUS> {
+        let mut stamp = 0;
+        let bytes = query_list(|buffer, count| unsafe {
+            RtlQueryAllFeatureConfigurations(kind.into(), &mut stamp, buffer, count)
+        })?;
+        Ok((bytes, stamp))
+    }
+
+    fn subscriptions(&self) -> Result<Vec<u8>, NTSTATUS> {
+        query_list(|buffer, count| unsafe {
+            RtlQueryFeatureUsageNotificationSubscriptions(buffer, count)
+        })
+    }
+
+    fn change_stamp(&self) -> u64 {
+        unsafe { RtlQueryFeatureConfigurationChangeStamp() }
+    }
+
+    fn set(
+        &self,
+        kind: FeatureKind,
+        updates: &[u8],
+        count: u32,
+        change_stamp: Option<u64>,
+    ) -> Result<(), NTSTATUS> {
+        // The binding names the configuration type, but the array is one of
+        // updates; copied so it is aligned.
+        let mut aligned = vec![0u32; updates.len().div_ceil(4)];
+        for (word, chunk) in aligned.iter_mut().zip(updates.chunks(4)) {
+            let mut bytes = [0; 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            *word = u32::from_le_bytes(bytes);
+        }
+        let mut stamp = change_stamp.unwrap_or_default();
+        let stamp = match change_stamp {
+            Some(_) => &mut stamp as *mut u64,
+            None => std::ptr::null_mut(),
+        };
+        check(unsafe {
+            RtlSetFeatureConfigurations(stamp, kind.into(), aligned.as_mut_ptr().cast(), count)
+        })
+    }
+}
+
+/// A change of one feature between two reads.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct FeatureChange {
+    pub id: u32,
+    pub before: Option<Feature>,
+    pub after: Option<Feature>,
+}
+
+/// Compares two reads of the configurations, by feature ID.
+pub fn diff_features(before: &[Feature], after: &[Feature]) -> Vec<FeatureChange> {
+    let mut features = BTreeMap::<u32, (Option<Feature>, Option<Feature>)>::new();
+    for feature in before {
+        features.entry(feature.id).or_default().0 = Some(*feature);
+    }
+    for feature in after {
+        features.entry(feature.id).or_default().1 = Some(*feature);
+    }
+    features
+        .into_iter()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(id, (before, after))| FeatureChange { id, before, after })
+        .collect()
+}
+
+/// The feature configuration API over a [`FeatureBackend`].
+#[derive(Debug, Clone, Default)]
+pub struct Features<B> {
+    backend: B,
+    seen_stamp: Option<u64>,
+}
+
+impl<B: FeatureBackend> Features<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            seen_stamp: None,
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// The configuration of `id` and the change stamp it was read at.
+    pub fn query(&self, id: u32, kind: FeatureKind) -> Result<(Feature, u64), NTSTATUS> {
+        let (bytes, stamp) = self.backend.query(id, kind)?;
+        let mut reader = Reader::new(&bytes);
+        let feature = Feature::read(&mut reader).map_err(|_| STATUS_BUFFER_TOO_SMALL)?;
+        Ok((feature, stamp))
+    }
+
+    /// Every configuration of `kind` and the change stamp they were read at.
+    pub fn query_all(&self, kind: FeatureKind) -> Result<(Vec<Feature>, u64), NTSTATUS> {
+        let (bytes, stamp) = self.backend.query_all(kind)?;
+        Ok((parse_features(&bytes).unwrap_or_default(), stamp))
+    }
+
+    /// The configurations with usage subscriptions.
+    pub fn subscriptions(&self) -> Result<Vec<Feature>, NTSTATUS> {
+        Ok(parse_features(&self.backend.subscriptions()?).unwrap_or_default())
+    }
+
+    pub fn change_stamp(&self) -> u64 {
+        self.backend.change_stamp()
+    }
+
+    /// The current change stamp if it moved since the last call; the first
+    /// call only records it.
+    pub fn poll_change(&mut self) -> Option<u64> {
+        let stamp = self.backend.change_stamp();
+        let previous = self.seen_stamp.replace(stamp);
+        previous
+            .filter(|previous| *previous != stamp)
+            .map(|_| stamp)
+    }
+
+    /// Applies `updates` to the `kind` configuration.
+    pub fn apply(&self, kind: FeatureKind, updates: &[FeatureUpdate]) -> Result<(), NTSTATUS> {
+        self.backend
+            .set(kind, &encode_updates(updates), updates.len() as u32, None)
+    }
+
+    /// Applies `updates` only if the configuration is still at
+    /// `change_stamp`, as read by a query.
+    pub fn apply_at(
+        &self,
+        kind: FeatureKind,
+        updates: &[FeatureUpdate],
+        change_stamp: u64,
+    ) -> Result<(), NTSTATUS> {
+        self.backend.set(
+            kind,
+            &encode_updates(updates),
+            updates.len() as u32,
+            Some(change_stamp),
+        )
+    }
+
+    /// Overrides `ids` in both the boot and runtime configurations, so the
+    /// change is live and survives a reboot.
+    pub fn override_features(
+        &self,
+        ids: &[u32],
+        priority: FeaturePriority,
+        enabled: bool,
+    ) -> Result<(), NTSTATUS> {
+        let updates = ids
+            .iter()
+            .map(|id| FeatureUpdate::set(*id, priority, enabled))
+            .collect::<Vec<_>>();
+        self.apply(FeatureKind::Runtime, &updates)?;
+        self.apply(FeatureKind::Boot, &updates)
+    }
+
+    /// Removes the overrides of `ids` at `priority` from both configurations.
+    pub fn reset_features(&self, ids: &[u32], priority: FeaturePriority) -> Result<(), NTSTATUS> {
+        let updates = ids
+            .iter()
+            .map(|id| FeatureUpdate::reset(*id, priority))
+            .collect::<Vec<_>>();
+        self.apply(FeatureKind::Runtime, &updates)?;
+        self.apply(FeatureKind::Boot, &updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use windows::Win32::Foundation::{STATUS_ACCESS_DENIED, STATUS_NOT_FOUND};
+
+    use super::*;
+    use crate::buffer::from_hex;
+
+    /// `SystemFeatureConfigurationSectionInformation` output: the boot,
+    /// runtime and usage subscription sections, the runtime one carrying
+    /// the overall change stamp.
+    fn sections() -> Vec<u8> {
+        from_hex(
+            "
+            31 00 00 00 00 00 00 00 0c 00 00 00 00 00 00 00
+            f4 02 00 00 00 00 00 00 00 10 00 00 00 00 00 00
+            31 00 00 00 00 00 00 00 00 03 00 00 00 00 00 00
+            00 30 00 00 00 00 00 00 02 00 00 00 00 00 00 00
+            04 03 00 00 00 00 00 00 00 10 00 00 00 00 00 00
+            ",
+        )
+    }
+
+    /// The boot section: one feature enabled by servicing and one forced on
+    /// by an image override.
+    fn boot_table() -> Vec<u8> {
+        from_hex(
+            "
+            02 00 00 00 fe dc 8c 01 24 00 00 00 00 00 00 00
+            b9 b9 2a 02 2f 00 00 00 00 00 00 00
+            ",
+        )
+    }
+
+    /// The runtime section: the servicing feature, a user-disabled
+    /// experiment with subscriptions and a resident variant payload, and a
+    /// feature with an external payload.
+    fn runtime_table() -> Vec<u8> {
+        from_hex(
+            "
+            03 00 00 00 fe dc 8c 01 24 00 00 00 00 00 00 00
+            b9 b9 2a 02 d8 43 00 00 2a 00 00 00 a9 79 6d 02
+            2f 80 00 00 00 01 00 00
+            ",
+        )
+    }
+
+    /// The usage subscriptions: packed configurations without a count.
+    fn subscriptions() -> Vec<u8> {
+        from_hex(
+            "
+            b9 b9 2a 02 80 00 00 00 00 00 00 00 37 0a e3 02
+            a0 00 00 00 00 00 00 00
+            ",
+        )
+    }
+
+    #[test]
+    fn decodes_sections() {
+        let sections =
+            SystemFeatureConfigurationSectionInformation::decode(&self::sections()).unwrap();
+        assert_eq!(sections.overall_change_stamp, 0x31);
+        assert_eq!(
+            *sections.boot(),
+            FeatureSection {
+                change_stamp: 0x0c,
+                section: HANDLE(0x2f4),
+                size: 0x1000,
+            }
+        );
+        assert_eq!(
+            *sections.runtime(),
+            FeatureSection {
+                change_stamp: 0x31,
+                section: HANDLE(0x300),
+                size: 0x3000,
+            }
+        );
+        assert_eq!(
+            *sections.usage_subscriptions(),
+            FeatureSection {
+                change_stamp: 2,
+                section: HANDLE(0x304),
+                size: 0x1000,
+            }
+        );
+        assert_eq!(
+            FeatureSections::parse(&self::sections()[..72]),
+            Err(DecodeError::Truncated {
+                offset: 72,
+                needed: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_boot_table() {
+        assert_eq!(
+            parse_feature_table(&boot_table()).unwrap(),
+            [
+                Feature {
+                    id: 26008830,
+                    priority: FeaturePriority::SERVICE,
+                    state: FeatureState::Enabled,
+                    ..Feature::default()
+                },
+                Feature {
+                    id: 36354489,
+                    priority: FeaturePriority::IMAGE_OVERRIDE,
+                    state: FeatureState::Enabled,
+                    ..Feature::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_runtime_table() {
+        let features = parse_feature_table(&runtime_table()).unwrap();
+        assert_eq!(features.len(), 3);
+        assert_eq!(
+            features[1],
+            Feature {
+                id: 36354489,
+                priority: FeaturePriority::USER,
+                state: FeatureState::Disabled,
+                is_wexp_configuration: true,
+                has_subscriptions: true,
+                variant: 3,
+                payload_kind: PayloadKind::Resident,
+                payload: 0x2a,
+            }
+        );
+        assert_eq!(features[2].payload_kind, PayloadKind::External);
+        assert_eq!(features[2].payload, 0x100);
+        assert!(features.iter().all(|feature| {
+            Feature::from_parts(feature.id, feature.flags(), feature.payload) == *feature
+        }));
+        assert!(parse_feature_table(&[0; 4]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_short_tables() {
+        let table = runtime_table();
+        assert_eq!(
+            parse_feature_table(&table[..table.len() - 1]),
+            Err(DecodeError::Truncated {
+                offset: 4,
+                needed: 36,
+            })
+        );
+        // A count no section could hold.
+        assert_eq!(
+            parse_feature_table(&[0xff, 0xff, 0xff, 0xff, 0, 0]),
+            Err(DecodeError::Truncated {
+                offset: 4,
+                needed: 0xffff_ffff * FEATURE_CONFIGURATION_SIZE,
+            })
+        );
+        assert!(parse_feature_table(&[1, 0]).is_err());
+    }
+
+    #[test]
+    fn decodes_subscriptions() {
+        let features = parse_features(&subscriptions()).unwrap();
+        assert_eq!(
+            features
+                .iter()
+                .map(|feature| (feature.id, feature.state, feature.has_subscriptions))
+                .collect::<Vec<_>>(),
+            [
+                (36354489, FeatureState::Default, true),
+                (48433719, FeatureState::Enabled, true),
+            ]
+        );
+        // A partial trailing record is not a configuration.
+        let mut bytes = subscriptions();
+        bytes.extend([1, 2, 3]);
+        assert_eq!(parse_features(&bytes).unwrap(), features);
+        assert!(parse_features(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn packs_flags() {
+        let flags = 10 | 2 << 4 | 1 << 6 | 0x2a << 8 | 2 << 14;
+        let feature = Feature::from_parts(5, flags, 3);
+        assert_eq!(feature.priority, FeaturePriority::USER_POLICY);
+        assert!(feature.is_enabled());
+        assert!(feature.is_wexp_configuration);
+        assert!(!feature.has_subscriptions);
+        assert_eq!(feature.variant, 0x2a);
+        assert_eq!(feature.flags(), flags);
+        // The reserved upper bits are dropped.
+        assert_eq!(Feature::from_parts(5, flags | 0xffff_0000, 3), feature);
+        assert_eq!(
+            Feature::from_parts(1, 3 << 4 | 3 << 14, 0).state,
+            FeatureState::Unknown(3)
+        );
+    }
+
+    fn updates() -> [FeatureUpdate; 4] {
+        [
+            FeatureUpdate::set(26008830, FeaturePriority::USER, false),
+            FeatureUpdate::reset(36354489, FeaturePriority::TEST),
+            FeatureUpdate {
+                id: 40729001,
+                priority: FeaturePriority::SERVICE,
+                state: FeatureState::Enabled,
+                state_options: 1,
+                variant: 3,
+                payload_kind: PayloadKind::Resident,
+                payload: 0x2a,
+                operation: UpdateOperation::Set,
+            },
+            FeatureUpdate {
+                id: 48433719,
+                priority: FeaturePriority::USER,
+                variant: 5,
+                operation: UpdateOperation::SetVariant,
+                ..FeatureUpdate::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn encodes_updates() {
+        let bytes = encode_updates(&updates());
+        assert_eq!(bytes.len(), 4 * FEATURE_UPDATE_SIZE);
+        assert_eq!(
+            bytes,
+            from_hex(
+                "
+                fe dc 8c 01 08 00 00 00 01 00 00 00 00 00 00 00
+                00 00 00 00 00 00 00 00 00 00 00 00 02 00 00 00
+                b9 b9 2a 02 0c 00 00 00 00 00 00 00 00 00 00 00
+                00 00 00 00 00 00 00 00 00 00 00 00 01 00 00 00
+                a9 79 6d 02 04 00 00 00 02 00 00 00 01 00 00 00
+                03 00 00 00 01 00 00 00 2a 00 00 00 00 00 00 00
+                37 0a e3 02 08 00 00 00 00 00 00 00 00 00 00 00
+                05 00 00 00 00 00 00 00 00 00 00 00 04 00 00 00
+                ",
+            )
+        );
+        assert!(encode_updates(&[]).is_empty());
+    }
+
+    /// A call to [`FeatureBackend::set`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Set {
+        kind: FeatureKind,
+        updates: Vec<u8>,
+        count: u32,
+        change_stamp: Option<u64>,
+    }
+
+    /// Serves the fixture sections as the configurations and records
+    /// updates.
+    #[derive(Default)]
+    struct Recorded {
+        stamp: Cell<u64>,
+        status: Cell<NTSTATUS>,
+        sets: RefCell<Vec<Set>>,
+    }
+
+    impl FeatureBackend for &Recorded {
+        fn query(&self, id: u32, kind: FeatureKind) -> Result<(Vec<u8>, u64), NTSTATUS> {
+            let (table, _) = self.query_all(kind)?;
+            let index = parse_features(&table)
+                .unwrap()
+                .iter()
+                .position(|feature| feature.id == id)
+                .ok_or(STATUS_NOT_FOUND)?;
+            let start = index * FEATURE_CONFIGURATION_SIZE;
+            Ok((
+                table[start..start + FEATURE_CONFIGURATION_SIZE].to_vec(),
+                self.stamp.get(),
+            ))
+        }
+
+        fn query_all(&self, kind: FeatureKind) -> Result<(Vec<u8>, u64), NTSTATUS> {
+            let table = match kind {
+                FeatureKind::Boot => boot_table(),
+                FeatureKind::Runtime => runtime_table(),
+            };
+            Ok((table[4..].to_vec(), self.stamp.get()))
+        }
+
+        fn subscriptions(&self) -> Result<Vec<u8>, NTSTATUS> {
+            Ok(subscriptions())
+        }
+
+        fn change_stamp(&self) -> u64 {
+            self.stamp.get()
+        }
+
+        fn set(
+            &self,
+            kind: FeatureKind,
+            updates: &[u8],
+            count: u32,
+            change_stamp: Option<u64>,
+        ) -> Result<(), NTSTATUS> {
+            self.sets.borrow_mut().push(Set {
+                kind,
+                updates: updates.to_vec(),
+                count,
+                change_stamp,
+            });
+            check(self.status.get())
+        }
+    }
+
+    #[test]
+    fn queries_through_the_backend() {
+        let backend = Recorded::default();
+        backend.stamp.set(0x31);
+        let features = Features::new(&backend);
+        let (feature, stamp) = features.query(40729001, FeatureKind::Runtime).unwrap();
+        assert_eq!(stamp, 0x31);
+        assert_eq!(feature.payload, 0x100);
+        assert_eq!(
+            features.query(40729001, FeatureKind::Boot),
+            Err(STATUS_NOT_FOUND)
+        );
+        let (boot, stamp) = features.query_all(FeatureKind::Boot).unwrap();
+        assert_eq!(stamp, 0x31);
+        assert_eq!(boot, parse_feature_table(&boot_table()).unwrap());
+        assert_eq!(features.subscriptions().unwrap().len(), 2);
+        assert_eq!(
+            diff_features(&boot, &features.query_all(FeatureKind::Runtime).unwrap().0)
+                .iter()
+                .map(|change| (change.id, change.before.is_some(), change.after.is_some()))
+                .collect::<Vec<_>>(),
+            [(36354489, true, true), (40729001, false, true)]
+        );
+    }
+
+    #[test]
+    fn polls_change_stamps() {
+        let backend = Recorded::default();
+        backend.stamp.set(0x31);
+        let mut features = Features::new(&backend);
+        // The first poll only records the stamp.
+        assert_eq!(features.poll_change(), None);
+        assert_eq!(features.poll_change(), None);
+        backend.stamp.set(0x32);
+        assert_eq!(features.poll_change(), Some(0x32));
+        assert_eq!(features.poll_change(), None);
+        // Any move counts, not only forward ones.
+        backend.stamp.set(0x31);
+        assert_eq!(features.poll_change(), Some(0x31));
+        assert_eq!(features.change_stamp(), 0x31);
+    }
+
+    #[test]
+    fn applies_updates() {
+        let backend = Recorded::default();
+        let features = Features::new(&backend);
+        let updates = updates();
+        features
+            .apply_at(FeatureKind::Runtime, &updates, 0x31)
+            .unwrap();
+        features
+            .override_features(&[26008830], FeaturePriority::USER, false)
+            .unwrap();
+        features
+            .reset_features(&[36354489], FeaturePriority::TEST)
+            .unwrap();
+        let sets = backend.sets.take();
+        assert_eq!(
+            sets[0],
+            Set {
+                kind: FeatureKind::Runtime,
+                updates: encode_updates(&updates),
+                count: 4,
+                change_stamp: Some(0x31),
+            }
+        );
+        let applied = sets[1..]
+            .iter()
+            .map(|set| (set.kind, set.count, set.change_stamp))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            applied,
+            [
+                (FeatureKind::Runtime, 1, None),
+                (FeatureKind::Boot, 1, None),
+                (FeatureKind::Runtime, 1, None),
+                (FeatureKind::Boot, 1, None),
+            ]
+        );
+        assert_eq!(sets[1].updates, sets[0].updates[..FEATURE_UPDATE_SIZE]);
+        assert_eq!(sets[2].updates, sets[1].updates);
+        assert_eq!(
+            sets[3].updates,
+            sets[0].updates[FEATURE_UPDATE_SIZE..2 * FEATURE_UPDATE_SIZE]
+        );
+
+        // A failed runtime update leaves the boot configuration alone.
+        backend.status.set(STATUS_ACCESS_DENIED);
+        assert_eq!(
+            features.override_features(&[1], FeaturePriority::USER, true),
+            Err(STATUS_ACCESS_DENIED)
+        );
+        assert_eq!(backend.sets.take().len(), 1);
+    }
+}
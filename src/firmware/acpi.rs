@@ -0,0 +1,817 @@
+use crate::buffer::{DecodeError, Reader};
+
+/// The size of the header every ACPI system description table starts with.
+pub const ACPI_HEADER_SIZE: usize = 36;
+
+/// The common header of an ACPI system description table.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct AcpiHeader {
+    pub signature: [u8; 4],
+    /// The table length, header included.
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: [u8; 4],
+    pub creator_revision: u32,
+}
+
+impl AcpiHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        Ok(Self {
+            signature: reader.array()?,
+            length: reader.u32()?,
+            revision: reader.u8()?,
+            checksum: reader.u8()?,
+            oem_id: reader.array()?,
+            oem_table_id: reader.array()?,
+            oem_revision: reader.u32()?,
+            creator_id: reader.array()?,
+            creator_revision: reader.u32()?,
+        })
+    }
+
+    pub fn signature(&self) -> String {
+        String::from_utf8_lossy(&self.signature).into_owned()
+    }
+
+    pub fn oem_id(&self) -> String {
+        String::from_utf8_lossy(&self.oem_id).trim_end().to_owned()
+    }
+
+    pub fn oem_table_id(&self) -> String {
+        String::from_utf8_lossy(&self.oem_table_id)
+            .trim_end()
+            .to_owned()
+    }
+}
+
+/// An ACPI table: its header and the whole table, header included, so body
+/// offsets match the specification.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AcpiTable {
+    pub header: AcpiHeader,
+    pub data: Vec<u8>,
+}
+
+impl AcpiTable {
+    /// Reads the table at the start of `bytes`, cut to the length its header
+    /// gives.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let header = AcpiHeader::parse(bytes)?;
+        let length = header.length as usize;
+        if length < ACPI_HEADER_SIZE {
+            return Err(DecodeError::Invalid {
+                offset: 4,
+                what: "ACPI table length",
+            });
+        }
+        let data = Reader::new(bytes).bytes(length)?.to_vec();
+        Ok(Self { header, data })
+    }
+
+    /// Whether the table bytes sum to zero, as the checksum field makes them.
+    pub fn checksum_ok(&self) -> bool {
+        self.data
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+            == 0
+    }
+
+    /// A reader over the body of a table with `signature`.
+    fn body(&self, signature: &[u8; 4]) -> Result<Reader<'_>, DecodeError> {
+        if self.header.signature != *signature {
+            return Err(DecodeError::Invalid {
+                offset: 0,
+                what: "ACPI table signature",
+            });
+        }
+        Ok(Reader::at(&self.data, ACPI_HEADER_SIZE))
+    }
+}
+
+/// An ACPI Generic Address Structure.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct GenericAddress {
+    /// 0 for system memory, 1 for system I/O, 2 for PCI configuration space.
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            address_space: reader.u8()?,
+            bit_width: reader.u8()?,
+            bit_offset: reader.u8()?,
+            access_size: reader.u8()?,
+            address: reader.u64()?,
+        })
+    }
+}
+
+/// The Fixed ACPI Description Table, signature `FACP`. Fields past the end of
+/// an older, shorter table are zero.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct Fadt {
+    pub firmware_ctrl: u32,
+    pub dsdt: u32,
+    pub preferred_pm_profile: u8,
+    pub sci_interrupt: u16,
+    pub smi_command: u32,
+    pub pm_timer_block: u32,
+    pub iapc_boot_arch: u16,
+    pub flags: u32,
+    pub reset_register: GenericAddress,
+    pub reset_value: u8,
+    pub arm_boot_arch: u16,
+    pub minor_version: u8,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+}
+
+impl Fadt {
+    pub fn parse(table: &AcpiTable) -> Result<Self, DecodeError> {
+        table.body(b"FACP")?;
+        // Tables grew across revisions; read what this one has.
+        let mut data = table.data.clone();
+        data.resize(data.len().max(148), 0);
+        let at = |offset| Reader::at(&data, offset);
+        Ok(Self {
+            firmware_ctrl: at(36).u32()?,
+            dsdt: at(40).u32()?,
+            preferred_pm_profile: at(45).u8()?,
+            sci_interrupt: at(46).u16()?,
+            smi_command: at(48).u32()?,
+            pm_timer_block: at(76).u32()?,
+            iapc_boot_arch: at(109).u16()?,
+            flags: at(112).u32()?,
+            reset_register: GenericAddress::read(&mut at(116))?,
+            reset_value: at(128).u8()?,
+            arm_boot_arch: at(129).u16()?,
+            minor_version: at(131).u8()?,
+            x_firmware_ctrl: at(132).u64()?,
+            x_dsdt: at(140).u64()?,
+        })
+    }
+
+    /// The DSDT address, preferring the 64-bit field when set.
+    pub fn dsdt_address(&self) -> u64 {
+        if self.x_dsdt != 0 {
+            self.x_dsdt
+        } else {
+            self.dsdt as u64
+        }
+    }
+}
+
+/// An interrupt controller structure of the MADT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MadtEntry {
+    LocalApic {
+        processor_uid: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        gsi_base: u32,
+    },
+    InterruptOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        flags: u16,
+    },
+    LocalApicNmi {
+        processor_uid: u8,
+        flags: u16,
+        lint: u8,
+    },
+    LocalApicAddressOverride {
+        address: u64,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        flags: u32,
+        processor_uid: u32,
+    },
+    /// Any other structure, with the bytes after its type and length.
+    Other {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl MadtEntry {
+    fn read(kind: u8, mut reader: Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(match kind {
+            0 => Self::LocalApic {
+                processor_uid: reader.u8()?,
+                apic_id: reader.u8()?,
+                flags: reader.u32()?,
+            },
+            1 => {
+                let id = reader.u8()?;
+                reader.skip(1)?;
+                Self::IoApic {
+                    id,
+                    address: reader.u32()?,
+                    gsi_base: reader.u32()?,
+                }
+            }
+            2 => Self::InterruptOverride {
+                bus: reader.u8()?,
+                source: reader.u8()?,
+                gsi: reader.u32()?,
+                flags: reader.u16()?,
+            },
+            4 => Self::LocalApicNmi {
+                processor_uid: reader.u8()?,
+                flags: reader.u16()?,
+                lint: reader.u8()?,
+            },
+            5 => {
+                reader.skip(2)?;
+                Self::LocalApicAddressOverride {
+                    address: reader.u64()?,
+                }
+            }
+            9 => {
+                reader.skip(2)?;
+                Self::LocalX2Apic {
+                    x2apic_id: reader.u32()?,
+                    flags: reader.u32()?,
+                    processor_uid: reader.u32()?,
+                }
+            }
+            kind => Self::Other {
+                kind,
+                data: reader.bytes(reader.remaining())?.to_vec(),
+            },
+        })
+    }
+}
+
+/// The Multiple APIC Description Table, signature `APIC`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Madt {
+    pub local_apic_address: u32,
+    /// Bit 0 set when the system also has dual 8259 PICs.
+    pub flags: u32,
+    pub entries: Vec<MadtEntry>,
+}
+
+impl Madt {
+    pub fn parse(table: &AcpiTable) -> Result<Self, DecodeError> {
+        let mut reader = table.body(b"APIC")?;
+        let local_apic_address = reader.u32()?;
+        let flags = reader.u32()?;
+        let mut entries = Vec::new();
+        while !reader.is_empty() {
+            let start = reader.position();
+            let kind = reader.u8()?;
+            let length = reader.u8()? as usize;
+            if length < 2 {
+                return Err(DecodeError::Invalid {
+                    offset: start,
+                    what: "MADT entry length",
+                });
+            }
+            let body = reader.bytes(length - 2)?;
+            entries.push(MadtEntry::read(kind, Reader::new(body))?);
+        }
+        Ok(Self {
+            local_apic_address,
+            flags,
+            entries,
+        })
+    }
+
+    /// The enabled processors' local APIC IDs, x2APIC ones included.
+    pub fn processor_ids(&self) -> Vec<u32> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match *entry {
+                MadtEntry::LocalApic { apic_id, flags, .. } if flags & 1 != 0 => {
+                    Some(apic_id as u32)
+                }
+                MadtEntry::LocalX2Apic {
+                    x2apic_id, flags, ..
+                } if flags & 1 != 0 => Some(x2apic_id),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A PCI Express enhanced configuration space range from the MCFG.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct McfgAllocation {
+    pub base_address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// The PCI Express memory-mapped configuration table, signature `MCFG`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mcfg {
+    pub allocations: Vec<McfgAllocation>,
+}
+
+impl Mcfg {
+    pub fn parse(table: &AcpiTable) -> Result<Self, DecodeError> {
+        let mut reader = table.body(b"MCFG")?;
+        reader.skip(8)?;
+        let mut allocations = Vec::new();
+        while reader.remaining() >= 16 {
+            let base_address = reader.u64()?;
+            let segment = reader.u16()?;
+            let start_bus = reader.u8()?;
+            let end_bus = reader.u8()?;
+            reader.skip(4)?;
+            allocations.push(McfgAllocation {
+                base_address,
+                segment,
+                start_bus,
+                end_bus,
+            });
+        }
+        Ok(Self { allocations })
+    }
+}
+
+/// A device scope of a DMAR remapping structure.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceScope {
+    /// 1 for a PCI endpoint, 2 for a PCI bridge, 3 for an IOAPIC, 4 for an
+    /// HPET.
+    pub kind: u8,
+    pub enumeration_id: u8,
+    pub start_bus: u8,
+    /// The (device, function) hops from the start bus.
+    pub path: Vec<(u8, u8)>,
+}
+
+impl DeviceScope {
+    fn read_all(mut reader: Reader<'_>) -> Result<Vec<Self>, DecodeError> {
+        let mut scopes = Vec::new();
+        while !reader.is_empty() {
+            let start = reader.position();
+            let kind = reader.u8()?;
+            let length = reader.u8()? as usize;
+            if length < 6 {
+                return Err(DecodeError::Invalid {
+                    offset: start,
+                    what: "DMAR device scope length",
+                });
+            }
+            reader.skip(2)?;
+            let enumeration_id = reader.u8()?;
+            let start_bus = reader.u8()?;
+            let path = reader
+                .bytes(length - 6)?
+                .chunks_exact(2)
+                .map(|hop| (hop[0], hop[1]))
+                .collect();
+            scopes.push(Self {
+                kind,
+                enumeration_id,
+                start_bus,
+                path,
+            });
+        }
+        Ok(scopes)
+    }
+}
+
+/// A remapping structure of the DMAR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DmarEntry {
+    /// A DMA remapping hardware unit.
+    Drhd {
+        /// Bit 0 set when the unit covers every device not listed elsewhere
+        /// in the segment.
+        flags: u8,
+        /// The register set size, as a power of two of 4 KiB pages.
+        size: u8,
+        segment: u16,
+        register_base: u64,
+        scopes: Vec<DeviceScope>,
+    },
+    /// A reserved memory region the devices keep using.
+    Rmrr {
+        segment: u16,
+        base_address: u64,
+        limit_address: u64,
+        scopes: Vec<DeviceScope>,
+    },
+    /// Any other structure, with the bytes after its type and length.
+    Other { kind: u16, data: Vec<u8> },
+}
+
+/// The DMA Remapping table, signature `DMAR`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dmar {
+    /// The DMA physical address width, minus one.
+    pub host_address_width: u8,
+    pub flags: u8,
+    pub entries: Vec<DmarEntry>,
+}
+
+impl Dmar {
+    pub fn parse(table: &AcpiTable) -> Result<Self, DecodeError> {
+        let mut reader = table.body(b"DMAR")?;
+        let host_address_width = reader.u8()?;
+        let flags = reader.u8()?;
+        reader.skip(10)?;
+        let mut entries = Vec::new();
+        while !reader.is_empty() {
+            let start = reader.position();
+            let kind = reader.u16()?;
+            let length = reader.u16()? as usize;
+            if length < 4 {
+                return Err(DecodeError::Invalid {
+                    offset: start,
+                    what: "DMAR structure length",
+                });
+            }
+            let mut body = Reader::new(reader.bytes(length - 4)?);
+            entries.push(match kind {
+                0 => {
+                    let flags = body.u8()?;
+                    let size = body.u8()?;
+                    let segment = body.u16()?;
+                    let register_base = body.u64()?;
+                    DmarEntry::Drhd {
+                        flags,
+                        size,
+                        segment,
+                        register_base,
+                        scopes: DeviceScope::read_all(body)?,
+                    }
+                }
+                1 => {
+                    body.skip(2)?;
+                    let segment = body.u16()?;
+                    let base_address = body.u64()?;
+                    let limit_address = body.u64()?;
+                    DmarEntry::Rmrr {
+                        segment,
+                        base_address,
+                        limit_address,
+                        scopes: DeviceScope::read_all(body)?,
+                    }
+                }
+                kind => DmarEntry::Other {
+                    kind,
+                    data: body.data().to_vec(),
+                },
+            });
+        }
+        Ok(Self {
+            host_address_width,
+            flags,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::from_hex;
+
+    fn table(hex: &str) -> AcpiTable {
+        let table = AcpiTable::parse(&from_hex(hex)).unwrap();
+        assert!(table.checksum_ok(), "{}", table.header.signature());
+        table
+    }
+
+    /// A revision 6.4 FADT of a desktop board.
+    fn facp() -> AcpiTable {
+        table(
+            "
+            46 41 43 50 14 01 00 00 06 ef 41 4c 41 53 4b 41
+            41 4c 41 53 4b 41 20 20 09 20 07 01 41 4d 49 20
+            13 00 01 00 00 00 00 00 00 d0 9f 7a 00 02 09 00
+            b2 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 08 18 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 13 00 00
+            a5 84 03 00 01 08 00 00 f9 0c 00 00 00 00 00 00
+            06 00 00 04 00 00 00 00 00 00 00 00 00 d0 9f 7a
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00
+            ",
+        )
+    }
+
+    #[test]
+    fn decodes_headers() {
+        let facp = facp();
+        assert_eq!(
+            facp.header,
+            AcpiHeader {
+                signature: *b"FACP",
+                length: 276,
+                revision: 6,
+                checksum: 0xef,
+                oem_id: *b"ALASKA",
+                oem_table_id: *b"ALASKA  ",
+                oem_revision: 0x0107_2009,
+                creator_id: *b"AMI ",
+                creator_revision: 0x1_0013,
+            }
+        );
+        assert_eq!(facp.header.signature(), "FACP");
+        assert_eq!(facp.header.oem_id(), "ALASKA");
+        assert_eq!(facp.header.oem_table_id(), "ALASKA");
+        assert_eq!(facp.data.len(), 276);
+    }
+
+    #[test]
+    fn validates_checksums() {
+        let mut table = facp();
+        table.data[200] ^= 1;
+        assert!(!table.checksum_ok());
+
+        // Bytes after the table length are not part of it.
+        let mut bytes = facp().data;
+        bytes.extend_from_slice(&[1, 2, 3]);
+        let table = AcpiTable::parse(&bytes).unwrap();
+        assert_eq!(table.data.len(), 276);
+        assert!(table.checksum_ok());
+
+        assert!(AcpiTable::parse(&facp().data[..275]).is_err());
+        let mut bytes = facp().data;
+        bytes[4..8].copy_from_slice(&35u32.to_le_bytes());
+        assert_eq!(
+            AcpiTable::parse(&bytes),
+            Err(DecodeError::Invalid {
+                offset: 4,
+                what: "ACPI table length",
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_fadt() {
+        let fadt = Fadt::parse(&facp()).unwrap();
+        assert_eq!(
+            fadt,
+            Fadt {
+                firmware_ctrl: 0,
+                dsdt: 0x7a9f_d000,
+                preferred_pm_profile: 2,
+                sci_interrupt: 9,
+                smi_command: 0xb2,
+                pm_timer_block: 0x1808,
+                iapc_boot_arch: 0x13,
+                flags: 0x3_84a5,
+                reset_register: GenericAddress {
+                    address_space: 1,
+                    bit_width: 8,
+                    bit_offset: 0,
+                    access_size: 0,
+                    address: 0xcf9,
+                },
+                reset_value: 6,
+                arm_boot_arch: 0,
+                minor_version: 4,
+                x_firmware_ctrl: 0,
+                x_dsdt: 0x7a9f_d000,
+            }
+        );
+        assert_eq!(fadt.dsdt_address(), 0x7a9f_d000);
+
+        // A revision 1 table ends before the extended fields.
+        let mut bytes = facp().data[..116].to_vec();
+        bytes[4..8].copy_from_slice(&116u32.to_le_bytes());
+        let fadt = Fadt::parse(&AcpiTable::parse(&bytes).unwrap()).unwrap();
+        assert_eq!((fadt.flags, fadt.x_dsdt), (0x3_84a5, 0));
+        assert_eq!(fadt.reset_register, GenericAddress::default());
+        assert_eq!(fadt.dsdt_address(), 0x7a9f_d000);
+
+        assert_eq!(
+            Madt::parse(&facp()),
+            Err(DecodeError::Invalid {
+                offset: 0,
+                what: "ACPI table signature",
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_madt() {
+        let madt = Madt::parse(&table(
+            "
+            41 50 49 43 7e 00 00 00 05 54 41 4c 41 53 4b 41
+            41 4c 41 53 4b 41 20 20 09 20 07 01 41 4d 49 20
+            13 00 01 00 00 00 e0 fe 01 00 00 00 00 08 00 00
+            01 00 00 00 00 08 01 02 01 00 00 00 00 08 02 04
+            00 00 00 00 01 0c 08 00 00 00 c0 fe 00 00 00 00
+            02 0a 00 00 02 00 00 00 00 00 02 0a 00 09 09 00
+            00 00 0d 00 04 06 ff 05 00 01 09 10 00 00 00 01
+            00 00 01 00 00 00 03 00 00 00 7f 04 cd ab
+            ",
+        ))
+        .unwrap();
+        assert_eq!(madt.local_apic_address, 0xfee0_0000);
+        assert_eq!(madt.flags, 1);
+        assert_eq!(
+            madt.entries,
+            [
+                MadtEntry::LocalApic {
+                    processor_uid: 0,
+                    apic_id: 0,
+                    flags: 1,
+                },
+                MadtEntry::LocalApic {
+                    processor_uid: 1,
+                    apic_id: 2,
+                    flags: 1,
+                },
+                MadtEntry::LocalApic {
+                    processor_uid: 2,
+                    apic_id: 4,
+                    flags: 0,
+                },
+                MadtEntry::IoApic {
+                    id: 8,
+                    address: 0xfec0_0000,
+                    gsi_base: 0,
+                },
+                MadtEntry::InterruptOverride {
+                    bus: 0,
+                    source: 0,
+                    gsi: 2,
+                    flags: 0,
+                },
+                MadtEntry::InterruptOverride {
+                    bus: 0,
+                    source: 9,
+                    gsi: 9,
+                    flags: 0xd,
+                },
+                MadtEntry::LocalApicNmi {
+                    processor_uid: 0xff,
+                    flags: 5,
+                    lint: 1,
+                },
+                MadtEntry::LocalX2Apic {
+                    x2apic_id: 0x100,
+                    flags: 1,
+                    processor_uid: 3,
+                },
+                MadtEntry::Other {
+                    kind: 0x7f,
+                    data: vec![0xcd, 0xab],
+                },
+            ]
+        );
+        // The disabled processor is left out.
+        assert_eq!(madt.processor_ids(), [0, 2, 0x100]);
+    }
+
+    #[test]
+    fn decodes_mcfg() {
+        let mcfg = Mcfg::parse(&table(
+            "
+            4d 43 46 47 3c 00 00 00 01 f1 41 4c 41 53 4b 41
+            41 4c 41 53 4b 41 20 20 09 20 07 01 41 4d 49 20
+            13 00 01 00 00 00 00 00 00 00 00 00 00 00 00 e0
+            00 00 00 00 00 00 00 ff 00 00 00 00
+            ",
+        ))
+        .unwrap();
+        assert_eq!(
+            mcfg.allocations,
+            [McfgAllocation {
+                base_address: 0xe000_0000,
+                segment: 0,
+                start_bus: 0,
+                end_bus: 0xff,
+            }]
+        );
+    }
+
+    #[test]
+    fn decodes_dmar() {
+        let dmar = Dmar::parse(&table(
+            "
+            44 4d 41 52 90 00 00 00 01 71 41 4c 41 53 4b 41
+            49 4e 54 45 4c 20 20 20 09 20 07 01 41 4d 49 20
+            13 00 01 00 26 05 00 00 00 00 00 00 00 00 00 00
+            00 00 18 00 00 00 00 00 00 00 d9 fe 00 00 00 00
+            01 08 00 00 00 00 02 00 00 00 20 00 01 00 00 00
+            00 10 d9 fe 00 00 00 00 03 08 00 00 02 f0 1f 00
+            04 08 00 00 00 00 1f 00 01 00 20 00 00 00 00 00
+            00 00 00 7a 00 00 00 00 ff ff 1f 7a 00 00 00 00
+            01 08 00 00 00 00 14 00 04 00 08 00 34 12 00 00
+            ",
+        ))
+        .unwrap();
+        assert_eq!((dmar.host_address_width, dmar.flags), (38, 5));
+        let scope = |kind, enumeration_id, start_bus, path: &[(u8, u8)]| DeviceScope {
+            kind,
+            enumeration_id,
+            start_bus,
+            path: path.to_vec(),
+        };
+        assert_eq!(
+            dmar.entries,
+            [
+                DmarEntry::Drhd {
+                    flags: 0,
+                    size: 0,
+                    segment: 0,
+                    register_base: 0xfed9_0000,
+                    scopes: vec![scope(1, 0, 0, &[(2, 0)])],
+                },
+                DmarEntry::Drhd {
+                    flags: 1,
+                    size: 0,
+                    segment: 0,
+                    register_base: 0xfed9_1000,
+                    scopes: vec![
+                        scope(3, 2, 0xf0, &[(0x1f, 0)]),
+                        scope(4, 0, 0, &[(0x1f, 0)])
+                    ],
+                },
+                DmarEntry::Rmrr {
+                    segment: 0,
+                    base_address: 0x7a00_0000,
+                    limit_address: 0x7a1f_ffff,
+                    scopes: vec![scope(1, 0, 0, &[(0x14, 0)])],
+                },
+                DmarEntry::Other {
+                    kind: 4,
+                    data: vec![0x34, 0x12, 0, 0],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_entry_lengths() {
+        let mut apic = from_hex(
+            "
+            41 50 49 43 7e 00 00 00 05 54 41 4c 41 53 4b 41
+            41 4c 41 53 4b 41 20 20 09 20 07 01 41 4d 49 20
+            13 00 01 00 00 00 e0 fe 01 00 00 00 00 08 00 00
+            01 00 00 00 00 08 01 02 01 00 00 00 00 08 02 04
+            00 00 00 00 01 0c 08 00 00 00 c0 fe 00 00 00 00
+            02 0a 00 00 02 00 00 00 00 00 02 0a 00 09 09 00
+            00 00 0d 00 04 06 ff 05 00 01 09 10 00 00 00 01
+            00 00 01 00 00 00 03 00 00 00 7f 04 cd ab
+            ",
+        );
+        // The first entry claims a length of one.
+        apic[45] = 1;
+        assert_eq!(
+            Madt::parse(&AcpiTable::parse(&apic).unwrap()),
+            Err(DecodeError::Invalid {
+                offset: 44,
+                what: "MADT entry length",
+            })
+        );
+        let mut dmar = from_hex(
+            "
+            44 4d 41 52 90 00 00 00 01 71 41 4c 41 53 4b 41
+            49 4e 54 45 4c 20 20 20 09 20 07 01 41 4d 49 20
+            13 00 01 00 26 05 00 00 00 00 00 00 00 00 00 00
+            00 00 18 00 00 00 00 00 00 00 d9 fe 00 00 00 00
+            01 08 00 00 00 00 02 00 00 00 20 00 01 00 00 00
+            00 10 d9 fe 00 00 00 00 03 08 00 00 02 f0 1f 00
+            04 08 00 00 00 00 1f 00 01 00 20 00 00 00 00 00
+            00 00 00 7a 00 00 00 00 ff ff 1f 7a 00 00 00 00
+            01 08 00 00 00 00 14 00 04 00 08 00 34 12 00 00
+            ",
+        );
+        // The first device scope claims a length of four.
+        dmar[65] = 4;
+        assert!(matches!(
+            Dmar::parse(&AcpiTable::parse(&dmar).unwrap()),
+            Err(DecodeError::Invalid {
+                what: "DMAR device scope length",
+                ..
+            })
+        ));
+    }
+}
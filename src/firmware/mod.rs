@@ -0,0 +1,176 @@
+//! Firmware tables through `SystemFirmwareTableInformation`.
+//!
+//! Each [`Provider`] serves a family of tables by ID: `ACPI` tables by
+//! signature, the raw SMBIOS data from `RSMB` and the legacy BIOS areas from
+//! `FIRM`. [`table_ids`] lists the IDs a provider has and [`table`] fetches
+//! one; both run on a [`SystemBackend`], growing the buffer as the system
+//! asks.
+//!
+//! The decoders are pure Rust and work on table dumps as well: [`Smbios`]
+//! splits the SMBIOS structure table into records and decodes the BIOS,
+//! system, baseboard, processor and memory device ones; [`AcpiTable`] reads
+//! the common ACPI header and [`Fadt`], [`Madt`], [`Mcfg`] and [`Dmar`] the
+//! bodies of those tables.
+//!
+//! ```no_run
+//! use windows_native::{firmware::*, system::NativeSystem};
+//!
+//! let smbios = Smbios::parse(&table(&NativeSystem, Provider::RSMB, 0)?)?;
+//! if let Some(system) = smbios.system() {
+//!     println!("{} {}", system.manufacturer, system.product);
+//! }
+//! let madt = Madt::parse(&AcpiTable::parse(&acpi_table(&NativeSystem, b"APIC")?)?)?;
+//! println!("{} interrupt controller entries", madt.entries.len());
+//! # Ok::<(), windows_native::system::Error>(())
+//! ```
+
+mod acpi;
+mod smbios;
+
+use std::{fmt, mem};
+
+pub use acpi::*;
+pub use smbios::*;
+use windows::Win32::Foundation::STATUS_BUFFER_TOO_SMALL;
+
+use crate::{
+    buffer::{DecodeError, Reader, Writer},
+    ntexapi::{SYSTEM_FIRMWARE_TABLE_ACTION, SYSTEM_INFORMATION_CLASS},
+    system::{Error, MAX_BUFFER, SystemBackend},
+};
+
+/// The size of the `SYSTEM_FIRMWARE_TABLE_INFORMATION` header in front of
+/// the table data.
+pub const FIRMWARE_TABLE_HEADER: usize = 16;
+
+/// A firmware table provider signature.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Provider(pub u32);
+
+impl Provider {
+    pub const ACPI: Self = Self::new(b"ACPI");
+    pub const FIRM: Self = Self::new(b"FIRM");
+    pub const RSMB: Self = Self::new(b"RSMB");
+
+    /// The provider written as its four characters. Providers are compared
+    /// as big-endian multi-character constants.
+    pub const fn new(signature: &[u8; 4]) -> Self {
+        Self(u32::from_be_bytes(*signature))
+    }
+}
+
+impl fmt::Debug for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signature = self.0.to_be_bytes();
+        write!(f, "Provider({:?})", String::from_utf8_lossy(&signature))
+    }
+}
+
+/// The ID of the ACPI table with `signature`, e.g. `b"FACP"`.
+pub const fn acpi_table_id(signature: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*signature)
+}
+
+/// Builds a `SYSTEM_FIRMWARE_TABLE_INFORMATION` request with room for
+/// `capacity` bytes of table data.
+pub fn firmware_request(
+    provider: Provider,
+    action: SYSTEM_FIRMWARE_TABLE_ACTION,
+    table_id: u32,
+    capacity: usize,
+) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer
+        .u32(provider.0)
+        .u32(action as u32)
+        .u32(table_id)
+        .u32(capacity as u32)
+        .zeros(capacity);
+    writer.into_inner()
+}
+
+/// Splits a `SYSTEM_FIRMWARE_TABLE_INFORMATION` response into the table
+/// length it reports and the data present in `bytes`. After a
+/// `STATUS_BUFFER_TOO_SMALL` the length is the one needed.
+pub fn parse_firmware_response(bytes: &[u8]) -> Result<(usize, &[u8]), DecodeError> {
+    let mut reader = Reader::new(bytes);
+    reader.skip(12)?;
+    let length = reader.u32()? as usize;
+    let data = &bytes[FIRMWARE_TABLE_HEADER..];
+    Ok((length, &data[..length.min(data.len())]))
+}
+
+fn query<B: SystemBackend + ?Sized>(
+    backend: &B,
+    provider: Provider,
+    action: SYSTEM_FIRMWARE_TABLE_ACTION,
+    table_id: u32,
+) -> Result<Vec<u8>, Error> {
+    let mut capacity = 0x1000;
+    loop {
+        let request = firmware_request(provider, action, table_id, capacity);
+        let mut buffer = vec![0u64; request.len().div_ceil(mem::size_of::<u64>())];
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(buffer.as_mut_ptr().cast::<u8>(), request.len())
+        };
+        bytes.copy_from_slice(&request);
+        let result = backend.query(
+            SYSTEM_INFORMATION_CLASS::SystemFirmwareTableInformation,
+            None,
+            &mut buffer,
+        );
+        let bytes =
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), request.len()) };
+        let (length, data) = parse_firmware_response(bytes)?;
+        match result {
+            Ok(_) => return Ok(data.to_vec()),
+            Err(failure) if failure.status == STATUS_BUFFER_TOO_SMALL => {
+                if capacity >= MAX_BUFFER {
+                    return Err(Error::Status(failure.status));
+                }
+                capacity = length.max(capacity * 2).min(MAX_BUFFER);
+            }
+            Err(failure) => return Err(Error::Status(failure.status)),
+        }
+    }
+}
+
+/// The IDs of the tables `provider` has. ACPI tables that occur more than
+/// once, such as `SSDT`, are listed once per copy.
+pub fn table_ids<B: SystemBackend + ?Sized>(
+    backend: &B,
+    provider: Provider,
+) -> Result<Vec<u32>, Error> {
+    let data = query(
+        backend,
+        provider,
+        SYSTEM_FIRMWARE_TABLE_ACTION::SystemFirmwareTableEnumerate,
+        0,
+    )?;
+    Ok(data
+        .chunks_exact(4)
+        .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+        .collect())
+}
+
+/// The table `table_id` of `provider`, without the request header.
+pub fn table<B: SystemBackend + ?Sized>(
+    backend: &B,
+    provider: Provider,
+    table_id: u32,
+) -> Result<Vec<u8>, Error> {
+    query(
+        backend,
+        provider,
+        SYSTEM_FIRMWARE_TABLE_ACTION::SystemFirmwareTableGet,
+        table_id,
+    )
+}
+
+/// The first ACPI table with `signature`, header included.
+pub fn acpi_table<B: SystemBackend + ?Sized>(
+    backend: &B,
+    signature: &[u8; 4],
+) -> Result<Vec<u8>, Error> {
+    table(backend, Provider::ACPI, acpi_table_id(signature))
+}
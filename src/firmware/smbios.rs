@@ -0,0 +1,664 @@
+use windows::core::GUID;
+
+use crate::buffer::{DecodeError, Reader};
+
+/// SMBIOS structure types decoded by [`Record`].
+pub const SMBIOS_BIOS: u8 = 0;
+pub const SMBIOS_SYSTEM: u8 = 1;
+pub const SMBIOS_BASEBOARD: u8 = 2;
+pub const SMBIOS_PROCESSOR: u8 = 4;
+pub const SMBIOS_MEMORY_DEVICE: u8 = 17;
+/// Marks the end of the structure table.
+pub const SMBIOS_END_OF_TABLE: u8 = 127;
+
+/// One SMBIOS structure: the formatted area and its strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Structure {
+    pub kind: u8,
+    pub handle: u16,
+    /// The formatted area, header included, so offsets match the
+    /// specification.
+    pub formatted: Vec<u8>,
+    pub strings: Vec<String>,
+}
+
+impl Structure {
+    pub fn byte(&self, offset: usize) -> Option<u8> {
+        self.formatted.get(offset).copied()
+    }
+
+    pub fn word(&self, offset: usize) -> Option<u16> {
+        let bytes = self.formatted.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn dword(&self, offset: usize) -> Option<u32> {
+        let bytes = self.formatted.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn qword(&self, offset: usize) -> Option<u64> {
+        let bytes = self.formatted.get(offset..offset + 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// The string whose 1-based index is stored at `offset`, empty for index
+    /// zero or a structure too short to have the field.
+    pub fn string(&self, offset: usize) -> String {
+        self.byte(offset)
+            .and_then(|index| self.strings.get((index as usize).checked_sub(1)?))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Reads one structure and the string set after it.
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let start = reader.position();
+        let kind = reader.u8()?;
+        let length = reader.u8()? as usize;
+        let handle = reader.u16()?;
+        if length < 4 {
+            return Err(DecodeError::Invalid {
+                offset: start,
+                what: "SMBIOS structure length",
+            });
+        }
+        reader.seek(start)?;
+        let formatted = reader.bytes(length)?.to_vec();
+        let mut strings = Vec::new();
+        // The string set ends with an empty string, so a structure without
+        // strings is followed by two NULs.
+        loop {
+            let string = reader.cstr()?;
+            if string.is_empty() {
+                if strings.is_empty() {
+                    reader.cstr()?;
+                }
+                break;
+            }
+            strings.push(String::from_utf8_lossy(string).into_owned());
+        }
+        Ok(Self {
+            kind,
+            handle,
+            formatted,
+            strings,
+        })
+    }
+}
+
+/// Type 0, BIOS information.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BiosInformation {
+    pub vendor: String,
+    pub version: String,
+    pub release_date: String,
+    pub starting_segment: u16,
+    /// In 64 KiB units minus one; 0xff means the extended size applies.
+    pub rom_size: u8,
+    pub characteristics: u64,
+    pub major_release: u8,
+    pub minor_release: u8,
+    pub embedded_controller_major: u8,
+    pub embedded_controller_minor: u8,
+}
+
+impl From<&Structure> for BiosInformation {
+    fn from(s: &Structure) -> Self {
+        Self {
+            vendor: s.string(0x04),
+            version: s.string(0x05),
+            starting_segment: s.word(0x06).unwrap_or_default(),
+            release_date: s.string(0x08),
+            rom_size: s.byte(0x09).unwrap_or_default(),
+            characteristics: s.qword(0x0a).unwrap_or_default(),
+            major_release: s.byte(0x14).unwrap_or_default(),
+            minor_release: s.byte(0x15).unwrap_or_default(),
+            embedded_controller_major: s.byte(0x16).unwrap_or_default(),
+            embedded_controller_minor: s.byte(0x17).unwrap_or_default(),
+        }
+    }
+}
+
+/// Type 1, system information.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemInformation {
+    pub manufacturer: String,
+    pub product: String,
+    pub version: String,
+    pub serial_number: String,
+    /// Stored with its first three fields little-endian, as a `GUID`.
+    pub uuid: GUID,
+    pub wake_up_type: u8,
+    pub sku: String,
+    pub family: String,
+}
+
+impl From<&Structure> for SystemInformation {
+    fn from(s: &Structure) -> Self {
+        let uuid = s
+            .formatted
+            .get(0x08..0x18)
+            .map(|bytes| Reader::new(bytes).guid().unwrap_or_default())
+            .unwrap_or_default();
+        Self {
+            manufacturer: s.string(0x04),
+            product: s.string(0x05),
+            version: s.string(0x06),
+            serial_number: s.string(0x07),
+            uuid,
+            wake_up_type: s.byte(0x18).unwrap_or_default(),
+            sku: s.string(0x19),
+            family: s.string(0x1a),
+        }
+    }
+}
+
+/// Type 2, baseboard information.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BaseboardInformation {
+    pub manufacturer: String,
+    pub product: String,
+    pub version: String,
+    pub serial_number: String,
+    pub asset_tag: String,
+    pub feature_flags: u8,
+    pub location_in_chassis: String,
+    pub chassis_handle: u16,
+    pub board_type: u8,
+}
+
+impl From<&Structure> for BaseboardInformation {
+    fn from(s: &Structure) -> Self {
+        Self {
+            manufacturer: s.string(0x04),
+            product: s.string(0x05),
+            version: s.string(0x06),
+            serial_number: s.string(0x07),
+            asset_tag: s.string(0x08),
+            feature_flags: s.byte(0x09).unwrap_or_default(),
+            location_in_chassis: s.string(0x0a),
+            chassis_handle: s.word(0x0b).unwrap_or_default(),
+            board_type: s.byte(0x0d).unwrap_or_default(),
+        }
+    }
+}
+
+/// Type 4, processor information. Speeds are in MHz.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessorInformation {
+    pub socket: String,
+    pub processor_type: u8,
+    pub family: u16,
+    pub manufacturer: String,
+    /// The CPUID signature and feature flags.
+    pub id: u64,
+    pub version: String,
+    pub voltage: u8,
+    pub external_clock: u16,
+    pub max_speed: u16,
+    pub current_speed: u16,
+    pub status: u8,
+    pub upgrade: u8,
+    pub l1_cache_handle: u16,
+    pub l2_cache_handle: u16,
+    pub l3_cache_handle: u16,
+    pub serial_number: String,
+    pub asset_tag: String,
+    pub part_number: String,
+    pub core_count: u16,
+    pub cores_enabled: u16,
+    pub thread_count: u16,
+    pub characteristics: u16,
+}
+
+impl From<&Structure> for ProcessorInformation {
+    fn from(s: &Structure) -> Self {
+        // Counts past 255 and families past 253 move to wider fields.
+        let wide = |short: Option<u8>, offset: usize| match short {
+            Some(0xff) => s.word(offset).unwrap_or(0xff),
+            short => short.unwrap_or_default() as u16,
+        };
+        let family = match s.byte(0x06) {
+            Some(0xfe) => s.word(0x28).unwrap_or(0xfe),
+            family => family.unwrap_or_default() as u16,
+        };
+        Self {
+            socket: s.string(0x04),
+            processor_type: s.byte(0x05).unwrap_or_default(),
+            family,
+            manufacturer: s.string(0x07),
+            id: s.qword(0x08).unwrap_or_default(),
+            version: s.string(0x10),
+            voltage: s.byte(0x11).unwrap_or_default(),
+            external_clock: s.word(0x12).unwrap_or_default(),
+            max_speed: s.word(0x14).unwrap_or_default(),
+            current_speed: s.word(0x16).unwrap_or_default(),
+            status: s.byte(0x18).unwrap_or_default(),
+            upgrade: s.byte(0x19).unwrap_or_default(),
+            l1_cache_handle: s.word(0x1a).unwrap_or_default(),
+            l2_cache_handle: s.word(0x1c).unwrap_or_default(),
+            l3_cache_handle: s.word(0x1e).unwrap_or_default(),
+            serial_number: s.string(0x20),
+            asset_tag: s.string(0x21),
+            part_number: s.string(0x22),
+            core_count: wide(s.byte(0x23), 0x2a),
+            cores_enabled: wide(s.byte(0x24), 0x2c),
+            thread_count: wide(s.byte(0x25), 0x2e),
+            characteristics: s.word(0x26).unwrap_or_default(),
+        }
+    }
+}
+
+/// Type 17, memory device. Speeds are in MT/s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryDevice {
+    pub physical_array_handle: u16,
+    pub total_width: u16,
+    pub data_width: u16,
+    /// Installed size in bytes, `None` when unknown; zero for an empty slot.
+    pub size: Option<u64>,
+    pub form_factor: u8,
+    pub device_locator: String,
+    pub bank_locator: String,
+    pub memory_type: u8,
+    pub type_detail: u16,
+    pub speed: u16,
+    pub manufacturer: String,
+    pub serial_number: String,
+    pub asset_tag: String,
+    pub part_number: String,
+    pub rank: u8,
+    pub configured_speed: u16,
+}
+
+impl From<&Structure> for MemoryDevice {
+    fn from(s: &Structure) -> Self {
+        let size = match s.word(0x0c) {
+            None | Some(0xffff) => None,
+            // Sizes of 32 GiB and more are in the extended field, in MiB.
+            Some(0x7fff) => s
+                .dword(0x1c)
+                .map(|mib| (mib & 0x7fff_ffff) as u64 * 1024 * 1024),
+            Some(size) if size & 0x8000 != 0 => Some((size & 0x7fff) as u64 * 1024),
+            Some(size) => Some(size as u64 * 1024 * 1024),
+        };
+        Self {
+            physical_array_handle: s.word(0x04).unwrap_or_default(),
+            total_width: s.word(0x08).unwrap_or_default(),
+            data_width: s.word(0x0a).unwrap_or_default(),
+            size,
+            form_factor: s.byte(0x0e).unwrap_or_default(),
+            device_locator: s.string(0x10),
+            bank_locator: s.string(0x11),
+            memory_type: s.byte(0x12).unwrap_or_default(),
+            type_detail: s.word(0x13).unwrap_or_default(),
+            speed: s.word(0x15).unwrap_or_default(),
+            manufacturer: s.string(0x17),
+            serial_number: s.string(0x18),
+            asset_tag: s.string(0x19),
+            part_number: s.string(0x1a),
+            rank: s.byte(0x1b).map_or(0, |attributes| attributes & 0xf),
+            configured_speed: s.word(0x20).unwrap_or_default(),
+        }
+    }
+}
+
+/// A decoded SMBIOS structure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Bios(BiosInformation),
+    System(SystemInformation),
+    Baseboard(BaseboardInformation),
+    Processor(ProcessorInformation),
+    MemoryDevice(MemoryDevice),
+    Other(Structure),
+}
+
+impl From<&Structure> for Record {
+    fn from(s: &Structure) -> Self {
+        match s.kind {
+            SMBIOS_BIOS => Self::Bios(s.into()),
+            SMBIOS_SYSTEM => Self::System(s.into()),
+            SMBIOS_BASEBOARD => Self::Baseboard(s.into()),
+            SMBIOS_PROCESSOR => Self::Processor(s.into()),
+            SMBIOS_MEMORY_DEVICE => Self::MemoryDevice(s.into()),
+            _ => Self::Other(s.clone()),
+        }
+    }
+}
+
+/// The SMBIOS structure table, as returned by the `RSMB` provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Smbios {
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub dmi_revision: u8,
+    pub structures: Vec<Structure>,
+}
+
+impl Smbios {
+    /// Decodes the `RawSMBIOSData` returned for table 0 of `RSMB`: a version
+    /// header followed by the structure table.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let _calling_method = reader.u8()?;
+        let major_version = reader.u8()?;
+        let minor_version = reader.u8()?;
+        let dmi_revision = reader.u8()?;
+        let length = reader.u32()? as usize;
+        let table = reader.bytes(length)?;
+        Ok(Self {
+            major_version,
+            minor_version,
+            dmi_revision,
+            structures: Self::parse_table(table)?,
+        })
+    }
+
+    /// Splits a bare structure table, up to its end-of-table structure.
+    pub fn parse_table(bytes: &[u8]) -> Result<Vec<Structure>, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let mut structures = Vec::new();
+        // Some firmware pads the table; a header no longer fits there.
+        while reader.remaining() >= 4 {
+            let structure = Structure::read(&mut reader)?;
+            if structure.kind == SMBIOS_END_OF_TABLE {
+                break;
+            }
+            structures.push(structure);
+        }
+        Ok(structures)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        self.structures.iter().map(Record::from)
+    }
+
+    pub fn of_kind(&self, kind: u8) -> impl Iterator<Item = &Structure> + '_ {
+        self.structures.iter().filter(move |s| s.kind == kind)
+    }
+
+    pub fn bios(&self) -> Option<BiosInformation> {
+        self.of_kind(SMBIOS_BIOS).next().map(Into::into)
+    }
+
+    pub fn system(&self) -> Option<SystemInformation> {
+        self.of_kind(SMBIOS_SYSTEM).next().map(Into::into)
+    }
+
+    pub fn baseboards(&self) -> Vec<BaseboardInformation> {
+        self.of_kind(SMBIOS_BASEBOARD).map(Into::into).collect()
+    }
+
+    pub fn processors(&self) -> Vec<ProcessorInformation> {
+        self.of_kind(SMBIOS_PROCESSOR).map(Into::into).collect()
+    }
+
+    pub fn memory_devices(&self) -> Vec<MemoryDevice> {
+        self.of_kind(SMBIOS_MEMORY_DEVICE).map(Into::into).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{format_guid, from_hex};
+
+    /// `RawSMBIOSData` of SMBIOS 3.6 from a desktop board: BIOS, system,
+    /// baseboard, processor, an installed and an empty memory slot, and a
+    /// system boot structure without strings before the end of the table.
+    fn rsmb() -> Vec<u8> {
+        from_hex(
+            "
+            00 03 06 00 79 02 00 00 00 1a 00 00 01 02 00 f0
+            03 ff a0 18 00 00 98 ff 0b 00 03 0d 05 1a ff ff
+            20 00 41 6d 65 72 69 63 61 6e 20 4d 65 67 61 74
+            72 65 6e 64 73 20 49 6e 74 65 72 6e 61 74 69 6f
+            6e 61 6c 2c 20 4c 4c 43 2e 00 31 2e 34 30 00 30
+            33 2f 31 35 2f 32 30 32 34 00 00 01 1b 01 00 01
+            02 03 04 78 56 34 12 34 12 78 56 90 ab cd ef 01
+            23 45 67 06 05 06 4d 69 63 72 6f 2d 53 74 61 72
+            20 49 6e 74 65 72 6e 61 74 69 6f 6e 61 6c 20 43
+            6f 2e 2c 20 4c 74 64 2e 00 4d 53 2d 37 44 37 35
+            00 31 2e 30 00 54 6f 20 62 65 20 66 69 6c 6c 65
+            64 20 62 79 20 4f 2e 45 2e 4d 2e 00 44 65 66 61
+            75 6c 74 20 73 74 72 69 6e 67 20 53 4b 55 00 44
+            65 73 6b 74 6f 70 00 00 02 0f 02 00 01 02 03 04
+            05 09 06 03 00 0a 00 4d 69 63 72 6f 2d 53 74 61
+            72 20 49 6e 74 65 72 6e 61 74 69 6f 6e 61 6c 20
+            43 6f 2e 2c 20 4c 74 64 2e 00 50 52 4f 20 5a 37
+            39 30 2d 41 20 57 49 46 49 20 28 4d 53 2d 37 44
+            37 35 29 00 31 2e 30 00 30 37 44 37 35 31 31 5f
+            4e 39 31 45 31 32 33 34 35 36 00 44 65 66 61 75
+            6c 74 20 73 74 72 69 6e 67 00 44 65 66 61 75 6c
+            74 20 73 74 72 69 6e 67 00 00 04 30 40 00 01 03
+            b3 02 f0 06 0a 00 ff fb eb bf 03 90 64 00 a0 0f
+            34 08 41 3e 45 00 46 00 47 00 00 00 04 ff ff ff
+            fc 00 b3 00 80 00 78 00 00 01 43 50 55 30 00 49
+            6e 74 65 6c 28 52 29 20 43 6f 72 70 6f 72 61 74
+            69 6f 6e 00 49 6e 74 65 6c 28 52 29 20 58 65 6f
+            6e 28 52 29 20 77 39 2d 33 34 39 35 58 00 54 6f
+            20 42 65 20 46 69 6c 6c 65 64 20 42 79 20 4f 2e
+            45 2e 4d 2e 00 00 11 28 11 00 10 00 fe ff 40 00
+            40 00 00 40 09 00 01 02 22 80 00 e0 15 03 04 00
+            05 02 00 00 00 00 50 14 4c 04 4c 04 4c 04 44 49
+            4d 4d 41 31 00 50 30 20 43 48 41 4e 4e 45 4c 20
+            41 00 4b 69 6e 67 73 74 6f 6e 00 31 41 32 42 33
+            43 34 44 00 4b 46 35 35 36 43 34 30 2d 31 36 00
+            00 11 28 12 00 10 00 fe ff ff ff ff ff 00 00 02
+            00 01 02 02 04 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 44 49 4d 4d 41 32 00
+            50 30 20 43 48 41 4e 4e 45 4c 20 41 00 00 20 0b
+            20 00 00 00 00 00 00 00 00 00 00 7f 04 ff fe 00
+            00
+            ",
+        )
+    }
+
+    #[test]
+    fn splits_structures() {
+        let smbios = Smbios::parse(&rsmb()).unwrap();
+        assert_eq!(
+            (
+                smbios.major_version,
+                smbios.minor_version,
+                smbios.dmi_revision
+            ),
+            (3, 6, 0)
+        );
+        let kinds = smbios
+            .structures
+            .iter()
+            .map(|s| (s.kind, s.handle, s.formatted.len(), s.strings.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (SMBIOS_BIOS, 0, 0x1a, 3),
+                (SMBIOS_SYSTEM, 1, 0x1b, 6),
+                (SMBIOS_BASEBOARD, 2, 0x0f, 6),
+                (SMBIOS_PROCESSOR, 0x40, 0x30, 4),
+                (SMBIOS_MEMORY_DEVICE, 0x11, 0x28, 5),
+                (SMBIOS_MEMORY_DEVICE, 0x12, 0x28, 2),
+                // Two NULs end a structure without strings.
+                (32, 0x20, 0x0b, 0),
+            ]
+        );
+        assert!(matches!(
+            smbios.records().last(),
+            Some(Record::Other(Structure { kind: 32, .. }))
+        ));
+    }
+
+    #[test]
+    fn decodes_bios_and_system() {
+        let smbios = Smbios::parse(&rsmb()).unwrap();
+        assert_eq!(
+            smbios.bios().unwrap(),
+            BiosInformation {
+                vendor: "American Megatrends International, LLC.".into(),
+                version: "1.40".into(),
+                release_date: "03/15/2024".into(),
+                starting_segment: 0xf000,
+                rom_size: 0xff,
+                characteristics: 0x000b_ff98_0000_18a0,
+                major_release: 5,
+                minor_release: 0x1a,
+                embedded_controller_major: 0xff,
+                embedded_controller_minor: 0xff,
+            }
+        );
+        let system = smbios.system().unwrap();
+        assert_eq!(system.manufacturer, "Micro-Star International Co., Ltd.");
+        assert_eq!(system.product, "MS-7D75");
+        assert_eq!(system.version, "1.0");
+        assert_eq!(system.serial_number, "To be filled by O.E.M.");
+        // The first three fields are stored little-endian.
+        assert_eq!(
+            format_guid(&system.uuid),
+            "12345678-1234-5678-90ab-cdef01234567"
+        );
+        assert_eq!(system.wake_up_type, 6);
+        assert_eq!(system.sku, "Default string SKU");
+        assert_eq!(system.family, "Desktop");
+    }
+
+    #[test]
+    fn decodes_baseboard() {
+        assert_eq!(
+            Smbios::parse(&rsmb()).unwrap().baseboards(),
+            [BaseboardInformation {
+                manufacturer: "Micro-Star International Co., Ltd.".into(),
+                product: "PRO Z790-A WIFI (MS-7D75)".into(),
+                version: "1.0".into(),
+                serial_number: "07D7511_N91E123456".into(),
+                asset_tag: "Default string".into(),
+                feature_flags: 0x09,
+                location_in_chassis: "Default string".into(),
+                chassis_handle: 3,
+                board_type: 0x0a,
+            }]
+        );
+    }
+
+    #[test]
+    fn decodes_processors() {
+        let smbios = Smbios::parse(&rsmb()).unwrap();
+        let processors = smbios.processors();
+        assert_eq!(
+            processors,
+            [ProcessorInformation {
+                socket: "CPU0".into(),
+                processor_type: 3,
+                family: 0xb3,
+                manufacturer: "Intel(R) Corporation".into(),
+                id: 0xbfeb_fbff_000a_06f0,
+                version: "Intel(R) Xeon(R) w9-3495X".into(),
+                voltage: 0x90,
+                external_clock: 100,
+                max_speed: 4000,
+                current_speed: 2100,
+                status: 0x41,
+                upgrade: 0x3e,
+                l1_cache_handle: 0x45,
+                l2_cache_handle: 0x46,
+                l3_cache_handle: 0x47,
+                // String index zero: not set.
+                serial_number: String::new(),
+                asset_tag: String::new(),
+                part_number: "To Be Filled By O.E.M.".into(),
+                // The short fields hold 0xff, so the counts come from the
+                // wide ones.
+                core_count: 0x80,
+                cores_enabled: 0x78,
+                thread_count: 0x100,
+                characteristics: 0xfc,
+            }]
+        );
+
+        // A family past 253 moves to the second family field.
+        let mut structure = smbios.of_kind(SMBIOS_PROCESSOR).next().unwrap().clone();
+        structure.formatted[0x06] = 0xfe;
+        structure.formatted[0x28..0x2a].copy_from_slice(&0x0101u16.to_le_bytes());
+        assert_eq!(ProcessorInformation::from(&structure).family, 0x101);
+        // An SMBIOS 2.0 structure stops before the strings and counts.
+        structure.formatted.truncate(0x1a);
+        let short = ProcessorInformation::from(&structure);
+        assert_eq!(short.max_speed, 4000);
+        assert_eq!((short.part_number.as_str(), short.core_count), ("", 0));
+    }
+
+    #[test]
+    fn decodes_memory_devices() {
+        let smbios = Smbios::parse(&rsmb()).unwrap();
+        let devices = smbios.memory_devices();
+        assert_eq!(
+            devices[0],
+            MemoryDevice {
+                physical_array_handle: 0x10,
+                total_width: 64,
+                data_width: 64,
+                size: Some(16 << 30),
+                form_factor: 0x09,
+                device_locator: "DIMMA1".into(),
+                bank_locator: "P0 CHANNEL A".into(),
+                memory_type: 0x22,
+                type_detail: 0x80,
+                speed: 5600,
+                manufacturer: "Kingston".into(),
+                serial_number: "1A2B3C4D".into(),
+                asset_tag: String::new(),
+                part_number: "KF556C40-16".into(),
+                rank: 2,
+                configured_speed: 5200,
+            }
+        );
+        // An empty slot has no size, an unknown width and no part strings.
+        assert_eq!(devices[1].size, Some(0));
+        assert_eq!(devices[1].total_width, 0xffff);
+        assert_eq!(devices[1].device_locator, "DIMMA2");
+        assert_eq!(devices[1].manufacturer, "");
+
+        let mut structure = smbios.of_kind(SMBIOS_MEMORY_DEVICE).next().unwrap().clone();
+        let size = |structure: &Structure| MemoryDevice::from(structure).size;
+        // Sizes in KiB, unknown, and 32 GiB and up in the extended field.
+        structure.formatted[0x0c..0x0e].copy_from_slice(&0x8200u16.to_le_bytes());
+        assert_eq!(size(&structure), Some(512 * 1024));
+        structure.formatted[0x0c..0x0e].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert_eq!(size(&structure), None);
+        structure.formatted[0x0c..0x0e].copy_from_slice(&0x7fffu16.to_le_bytes());
+        structure.formatted[0x1c..0x20].copy_from_slice(&0x1_0000u32.to_le_bytes());
+        assert_eq!(size(&structure), Some(64 << 30));
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        let bytes = rsmb();
+        // Cut inside the string set of the last structure.
+        let mut short = bytes[..bytes.len() - 8].to_vec();
+        let length = short.len() as u32 - 8;
+        short[4..8].copy_from_slice(&length.to_le_bytes());
+        assert!(Smbios::parse(&short).is_err());
+        // A length past the buffer.
+        assert!(Smbios::parse(&bytes[..bytes.len() - 1]).is_err());
+        // A formatted area shorter than its header.
+        assert_eq!(
+            Smbios::parse_table(&from_hex("00 02 00 00 00 00")),
+            Err(DecodeError::Invalid {
+                offset: 0,
+                what: "SMBIOS structure length",
+            })
+        );
+        // Padding after the table that cannot hold a header is ignored.
+        assert_eq!(Smbios::parse_table(&[0, 0, 0]).unwrap(), []);
+        // String indexes past the set read as empty.
+        let structure = Structure {
+            formatted: from_hex("01 05 00 00 07"),
+            strings: vec!["a".into()],
+            ..Structure::default()
+        };
+        assert_eq!(structure.string(4), "");
+        assert_eq!(structure.string(9), "");
+    }
+}
@@ -0,0 +1,622 @@
+//! Scoped suspension of processes and threads.
+//!
+//! [`ProcessFreeze`] and [`ThreadFreeze`] suspend their target when created
+//! and resume it when dropped. They are built on process and thread state
+//! change objects (`NtCreateProcessStateChange`): the suspension belongs to
+//! the object, so the kernel undoes it when the object is closed, including
+//! when the owning process dies. Where those objects are not available,
+//! before Windows 11, the guards fall back to `NtSuspendProcess` and
+//! `NtSuspendThread`, which have no such cleanup. The state change calls are
+//! looked up in ntdll at runtime, as importing them would keep binaries from
+//! loading on those systems.
+//!
+//! The calls go through a [`FreezeBackend`], so the guards can be driven by
+//! a recording backend.
+//!
+//! ```no_run
+//! use windows::Win32::Foundation::HANDLE;
+//! use windows_native::freeze::ProcessFreeze;
+//!
+//! # let process = HANDLE(0);
+//! let freeze = ProcessFreeze::new(process)?;
+//! // Inspect the process while none of its threads run.
+//! freeze.thaw()?;
+//! # Ok::<(), windows::Win32::Foundation::NTSTATUS>(())
+//! ```
+
+use std::{ffi::c_void, fmt, marker::PhantomData, mem, ptr, sync::OnceLock};
+
+use windows::{
+    Wdk::Foundation::{NtClose, OBJECT_ATTRIBUTES},
+    Win32::{
+        Foundation::{
+            HANDLE, NTSTATUS, STATUS_INVALID_SYSTEM_SERVICE, STATUS_NOT_IMPLEMENTED,
+            STATUS_NOT_SUPPORTED, STATUS_PROCEDURE_NOT_FOUND,
+        },
+        System::Kernel::STRING,
+    },
+    core::{PSTR, PWSTR},
+};
+
+use crate::{
+    buffer::unicode_string,
+    check,
+    ntldr::{LdrGetDllHandle, LdrGetProcedureAddress},
+    ntpsapi::{
+        NtResumeProcess, NtResumeThread, NtSuspendProcess, NtSuspendThread,
+        PROCESS_STATE_CHANGE_TYPE, THREAD_STATE_CHANGE_TYPE,
+    },
+};
+
+/// `PROCESS_STATE_ALL_ACCESS` and `THREAD_STATE_ALL_ACCESS`.
+pub const STATE_CHANGE_ALL_ACCESS: u32 = 0x1f_0001;
+
+/// What a state change object or a guard acts on.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum FreezeTargetKind {
+    Process,
+    Thread,
+}
+
+/// Marker for the kind of object a [`Freeze`] suspends.
+pub trait FreezeTarget {
+    const KIND: FreezeTargetKind;
+}
+
+#[derive(Debug)]
+pub enum Process {}
+
+#[derive(Debug)]
+pub enum Thread {}
+
+impl FreezeTarget for Process {
+    const KIND: FreezeTargetKind = FreezeTargetKind::Process;
+}
+
+impl FreezeTarget for Thread {
+    const KIND: FreezeTargetKind = FreezeTargetKind::Thread;
+}
+
+/// The suspend and resume operations the guards are built on.
+pub trait FreezeBackend {
+    /// Creates a state change object for `target`.
+    fn create_state_change(
+        &self,
+        kind: FreezeTargetKind,
+        target: HANDLE,
+    ) -> Result<HANDLE, NTSTATUS>;
+
+    /// Suspends or resumes `target` on behalf of `state_change`.
+    fn change_state(
+        &self,
+        kind: FreezeTargetKind,
+        state_change: HANDLE,
+        target: HANDLE,
+        suspend: bool,
+    ) -> Result<(), NTSTATUS>;
+
+    /// Suspends `target` without a state change object.
+    fn suspend(&self, kind: FreezeTargetKind, target: HANDLE) -> Result<(), NTSTATUS>;
+
+    fn resume(&self, kind: FreezeTargetKind, target: HANDLE) -> Result<(), NTSTATUS>;
+
+    /// Closes a handle returned by [`FreezeBackend::create_state_change`].
+    fn close(&self, handle: HANDLE);
+}
+
+/// Whether a failure to create a state change object means the system does
+/// not have them.
+pub fn is_unavailable(status: NTSTATUS) -> bool {
+    matches!(
+        status,
+        STATUS_NOT_IMPLEMENTED
+            | STATUS_NOT_SUPPORTED
+            | STATUS_INVALID_SYSTEM_SERVICE
+            | STATUS_PROCEDURE_NOT_FOUND
+    )
+}
+
+/// How a [`Freeze`] holds its target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FreezeState {
+    /// Suspended through the state change object.
+    StateChange(HANDLE),
+    /// Suspended directly, without automatic cleanup.
+    Suspended,
+    /// Resumed, or released with [`Freeze::leak`].
+    Released,
+}
+
+/// Keeps a process or thread suspended until dropped.
+pub struct Freeze<K: FreezeTarget, B: FreezeBackend = NativeFreeze> {
+    backend: B,
+    target: HANDLE,
+    state: FreezeState,
+    kind: PhantomData<K>,
+}
+
+pub type ProcessFreeze<B = NativeFreeze> = Freeze<Process, B>;
+pub type ThreadFreeze<B = NativeFreeze> = Freeze<Thread, B>;
+
+impl<K: FreezeTarget> Freeze<K> {
+    /// Suspends `target`, a handle with `PROCESS_SUSPEND_RESUME` or
+    /// `THREAD_SUSPEND_RESUME` access. The handle is not closed and must
+    /// outlive the guard.
+    pub fn new(target: HANDLE) -> Result<Self, NTSTATUS> {
+        Self::with_backend(NativeFreeze, target)
+    }
+}
+
+impl<K: FreezeTarget, B: FreezeBackend> Freeze<K, B> {
+    pub fn with_backend(backend: B, target: HANDLE) -> Result<Self, NTSTATUS> {
+        let state = match backend.create_state_change(K::KIND, target) {
+            Ok(state_change) => {
+                if let Err(status) = backend.change_state(K::KIND, state_change, target, true) {
+                    backend.close(state_change);
+                    return Err(status);
+                }
+                FreezeState::StateChange(state_change)
+            }
+            Err(status) if is_unavailable(status) => {
+                backend.suspend(K::KIND, target)?;
+                FreezeState::Suspended
+            }
+            Err(status) => return Err(status),
+        };
+        Ok(Self {
+            backend,
+            target,
+            state,
+            kind: PhantomData,
+        })
+    }
+
+    pub fn target(&self) -> HANDLE {
+        self.target
+    }
+
+    pub fn state(&self) -> FreezeState {
+        self.state
+    }
+
+    /// Whether the suspension is undone by the kernel if this process dies.
+    pub fn is_crash_safe(&self) -> bool {
+        matches!(self.state, FreezeState::StateChange(_))
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Resumes the target, reporting failures that dropping would ignore.
+    pub fn thaw(mut self) -> Result<(), NTSTATUS> {
+        self.release()
+    }
+
+    /// Gives up the guard, leaving the target suspended. A state change
+    /// object is returned, still open; closing it resumes the target.
+    pub fn leak(mut self) -> Option<HANDLE> {
+        match mem::replace(&mut self.state, FreezeState::Released) {
+            FreezeState::StateChange(state_change) => Some(state_change),
+            _ => None,
+        }
+    }
+
+    fn release(&mut self) -> Result<(), NTSTATUS> {
+        match mem::replace(&mut self.state, FreezeState::Released) {
+            FreezeState::StateChange(state_change) => {
+                let result = self
+                    .backend
+                    .change_state(K::KIND, state_change, self.target, false);
+                self.backend.close(state_change);
+                result
+            }
+            FreezeState::Suspended => self.backend.resume(K::KIND, self.target),
+            FreezeState::Released => Ok(()),
+        }
+    }
+}
+
+impl<K: FreezeTarget, B: FreezeBackend> Drop for Freeze<K, B> {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+impl<K: FreezeTarget, B: FreezeBackend> fmt::Debug for Freeze<K, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Freeze")
+            .field("kind", &K::KIND)
+            .field("target", &self.target)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+type CreateStateChange =
+    unsafe extern "system" fn(*mut HANDLE, u32, *mut OBJECT_ATTRIBUTES, HANDLE, u64) -> NTSTATUS;
+type ChangeState<T> =
+    unsafe extern "system" fn(HANDLE, HANDLE, T, *mut c_void, usize, u64) -> NTSTATUS;
+
+/// The state change calls of ntdll, which only exports them from Windows 11
+/// on.
+struct StateChangeProcedures {
+    create_process: CreateStateChange,
+    change_process: ChangeState<PROCESS_STATE_CHANGE_TYPE>,
+    create_thread: CreateStateChange,
+    change_thread: ChangeState<THREAD_STATE_CHANGE_TYPE>,
+}
+
+impl StateChangeProcedures {
+    /// Looks the procedures up on first use. A system without them fails
+    /// with `STATUS_PROCEDURE_NOT_FOUND`.
+    fn get() -> Result<&'static Self, NTSTATUS> {
+        static PROCEDURES: OnceLock<Option<StateChangeProcedures>> = OnceLock::new();
+        PROCEDURES
+            .get_or_init(|| Self::find().ok())
+            .as_ref()
+            .ok_or(STATUS_PROCEDURE_NOT_FOUND)
+    }
+
+    fn find() -> Result<Self, NTSTATUS> {
+        let name = "ntdll.dll\0".encode_utf16().collect::<Vec<_>>();
+        let mut ntdll = ptr::null_mut();
+        check(unsafe {
+            LdrGetDllHandle(
+                PWSTR::null(),
+                ptr::null_mut(),
+                &mut unicode_string(&name),
+                &mut ntdll,
+            )
+        })?;
+        let procedure = |name: &str| {
+            let mut name = STRING {
+                Length: name.len() as u16,
+                MaximumLength: name.len() as u16,
+                Buffer: PSTR(name.as_ptr() as *mut u8),
+            };
+            let mut address = ptr::null_mut();
+            check(unsafe { LdrGetProcedureAddress(ntdll, &mut name, 0, &mut address) })?;
+            Ok::<_, NTSTATUS>(address)
+        };
+        // SAFETY: the exports have the signatures of the `ntpsapi`
+        // declarations.
+        unsafe {
+            Ok(Self {
+                create_process: mem::transmute::<*mut c_void, CreateStateChange>(procedure(
+                    "NtCreateProcessStateChange",
+                )?),
+                change_process: mem::transmute::<*mut c_void, ChangeState<PROCESS_STATE_CHANGE_TYPE>>(
+                    procedure("NtChangeProcessState")?,
+                ),
+                create_thread: mem::transmute::<*mut c_void, CreateStateChange>(procedure(
+                    "NtCreateThreadStateChange",
+                )?),
+                change_thread: mem::transmute::<*mut c_void, ChangeState<THREAD_STATE_CHANGE_TYPE>>(
+                    procedure("NtChangeThreadState")?,
+                ),
+            })
+        }
+    }
+}
+
+/// The [`FreezeBackend`] that calls ntdll.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeFreeze;
+
+impl FreezeBackend for NativeFreeze {
+    fn create_state_change(
+        &self,
+        kind: FreezeTargetKind,
+        target: HANDLE,
+    ) -> Result<HANDLE, NTSTATUS> {
+        let procedures = StateChangeProcedures::get()?;
+        let mut handle = HANDLE::default();
+        check(unsafe {
+            match kind {
+                FreezeTargetKind::Process => (procedures.create_process)(
+                    &mut handle,
+                    STATE_CHANGE_ALL_ACCESS,
+                    ptr::null_mut(),
+                    target,
+                    0,
+                ),
+                FreezeTargetKind::Thread => (procedures.create_thread)(
+                    &mut handle,
+                    STATE_CHANGE_ALL_ACCESS,
+                    ptr::null_mut(),
+                    target,
+                    0,
+                ),
+            }
+        })?;
+        Ok(handle)
+    }
+
+    fn change_state(
+        &self,
+        kind: FreezeTargetKind,
+        state_change: HANDLE,
+        target: HANDLE,
+        suspend: bool,
+    ) -> Result<(), NTSTATUS> {
+        let procedures = StateChangeProcedures::get()?;
+        check(unsafe {
+            match kind {
+                FreezeTargetKind::Process => (procedures.change_process)(
+                    state_change,
+                    target,
+                    if suspend {
+                        PROCESS_STATE_CHANGE_TYPE::ProcessStateChangeSuspend
+                    } else {
+                        PROCESS_STATE_CHANGE_TYPE::ProcessStateChangeResume
+                    },
+                    ptr::null_mut(),
+                    0,
+                    0,
+                ),
+                FreezeTargetKind::Thread => (procedures.change_thread)(
+                    state_change,
+                    target,
+                    if suspend {
+                        THREAD_STATE_CHANGE_TYPE::ThreadStateChangeSuspend
+                    } else {
+                        THREAD_STATE_CHANGE_TYPE::ThreadStateChangeResume
+                    },
+                    ptr::null_mut(),
+                    0,
+                    0,
+                ),
+            }
+        })
+    }
+
+    fn suspend(&self, kind: FreezeTargetKind, target: HANDLE) -> Result<(), NTSTATUS> {
+        check(unsafe {
+            match kind {
+                FreezeTargetKind::Process => NtSuspendProcess(target),
+                FreezeTargetKind::Thread => NtSuspendThread(target, ptr::null_mut()),
+            }
+        })
+    }
+
+    fn resume(&self, kind: FreezeTargetKind, target: HANDLE) -> Result<(), NTSTATUS> {
+        check(unsafe {
+            match kind {
+                FreezeTargetKind::Process => NtResumeProcess(target),
+                FreezeTargetKind::Thread => NtResumeThread(target, ptr::null_mut()),
+            }
+        })
+    }
+
+    fn close(&self, handle: HANDLE) {
+        unsafe { NtClose(handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::Foundation::STATUS_ACCESS_DENIED;
+
+    use super::*;
+
+    /// Records the calls made and fails the ones it is told to.
+    #[derive(Default)]
+    struct Recording {
+        calls: RefCell<Vec<String>>,
+        create: Option<NTSTATUS>,
+        fail_suspend: bool,
+        fail_resume: bool,
+    }
+
+    impl Recording {
+        fn failing_create(status: NTSTATUS) -> Self {
+            Self {
+                create: Some(status),
+                ..Self::default()
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.borrow().clone()
+        }
+
+        fn record(&self, call: String) {
+            self.calls.borrow_mut().push(call);
+        }
+
+        fn outcome(&self, suspend: bool) -> Result<(), NTSTATUS> {
+            if (suspend && self.fail_suspend) || (!suspend && self.fail_resume) {
+                Err(STATUS_ACCESS_DENIED)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl FreezeBackend for &Recording {
+        fn create_state_change(
+            &self,
+            kind: FreezeTargetKind,
+            target: HANDLE,
+        ) -> Result<HANDLE, NTSTATUS> {
+            self.record(format!("create {kind:?} {}", target.0));
+            match self.create {
+                Some(status) => Err(status),
+                None => Ok(HANDLE(0x40)),
+            }
+        }
+
+        fn change_state(
+            &self,
+            kind: FreezeTargetKind,
+            state_change: HANDLE,
+            target: HANDLE,
+            suspend: bool,
+        ) -> Result<(), NTSTATUS> {
+            self.record(format!(
+                "change {kind:?} {} {} {suspend}",
+                state_change.0, target.0
+            ));
+            self.outcome(suspend)
+        }
+
+        fn suspend(&self, kind: FreezeTargetKind, target: HANDLE) -> Result<(), NTSTATUS> {
+            self.record(format!("suspend {kind:?} {}", target.0));
+            self.outcome(true)
+        }
+
+        fn resume(&self, kind: FreezeTargetKind, target: HANDLE) -> Result<(), NTSTATUS> {
+            self.record(format!("resume {kind:?} {}", target.0));
+            self.outcome(false)
+        }
+
+        fn close(&self, handle: HANDLE) {
+            self.record(format!("close {}", handle.0));
+        }
+    }
+
+    #[test]
+    fn suspends_through_state_change() {
+        let backend = Recording::default();
+        let freeze = ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap();
+        assert_eq!(freeze.state(), FreezeState::StateChange(HANDLE(0x40)));
+        assert!(freeze.is_crash_safe());
+        assert_eq!(freeze.target(), HANDLE(8));
+        freeze.thaw().unwrap();
+        assert_eq!(
+            backend.calls(),
+            [
+                "create Process 8",
+                "change Process 64 8 true",
+                "change Process 64 8 false",
+                "close 64",
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_without_state_change() {
+        for status in [STATUS_NOT_IMPLEMENTED, STATUS_PROCEDURE_NOT_FOUND] {
+            let backend = Recording::failing_create(status);
+            let freeze = ThreadFreeze::with_backend(&backend, HANDLE(12)).unwrap();
+            assert_eq!(freeze.state(), FreezeState::Suspended);
+            assert!(!freeze.is_crash_safe());
+            freeze.thaw().unwrap();
+            assert_eq!(
+                backend.calls(),
+                ["create Thread 12", "suspend Thread 12", "resume Thread 12"]
+            );
+        }
+
+        // Other failures are not a missing feature and are reported.
+        let backend = Recording::failing_create(STATUS_ACCESS_DENIED);
+        assert_eq!(
+            ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap_err(),
+            STATUS_ACCESS_DENIED
+        );
+        assert_eq!(backend.calls(), ["create Process 8"]);
+    }
+
+    #[test]
+    fn closes_state_change_when_suspend_fails() {
+        let backend = Recording {
+            fail_suspend: true,
+            ..Recording::default()
+        };
+        assert_eq!(
+            ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap_err(),
+            STATUS_ACCESS_DENIED
+        );
+        assert_eq!(
+            backend.calls(),
+            ["create Process 8", "change Process 64 8 true", "close 64"]
+        );
+
+        let backend = Recording {
+            create: Some(STATUS_NOT_IMPLEMENTED),
+            fail_suspend: true,
+            ..Recording::default()
+        };
+        assert!(ProcessFreeze::with_backend(&backend, HANDLE(8)).is_err());
+        assert_eq!(backend.calls(), ["create Process 8", "suspend Process 8"]);
+    }
+
+    #[test]
+    fn thaw_reports_resume_errors() {
+        let backend = Recording {
+            fail_resume: true,
+            ..Recording::default()
+        };
+        let freeze = ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap();
+        assert_eq!(freeze.thaw(), Err(STATUS_ACCESS_DENIED));
+        // The state change object is closed anyway, and nothing is retried
+        // when the guard goes away.
+        assert_eq!(
+            backend.calls(),
+            [
+                "create Process 8",
+                "change Process 64 8 true",
+                "change Process 64 8 false",
+                "close 64",
+            ]
+        );
+
+        let backend = Recording {
+            create: Some(STATUS_NOT_IMPLEMENTED),
+            fail_resume: true,
+            ..Recording::default()
+        };
+        let freeze = ThreadFreeze::with_backend(&backend, HANDLE(12)).unwrap();
+        assert_eq!(freeze.thaw(), Err(STATUS_ACCESS_DENIED));
+        assert_eq!(
+            backend.calls(),
+            ["create Thread 12", "suspend Thread 12", "resume Thread 12"]
+        );
+    }
+
+    #[test]
+    fn leak_keeps_the_target_suspended() {
+        let backend = Recording::default();
+        let freeze = ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap();
+        assert_eq!(freeze.leak(), Some(HANDLE(0x40)));
+        assert_eq!(
+            backend.calls(),
+            ["create Process 8", "change Process 64 8 true"]
+        );
+
+        let backend = Recording::failing_create(STATUS_NOT_IMPLEMENTED);
+        let freeze = ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap();
+        assert_eq!(freeze.leak(), None);
+        assert_eq!(backend.calls(), ["create Process 8", "suspend Process 8"]);
+    }
+
+    #[test]
+    fn drop_resumes_once() {
+        let backend = Recording::default();
+        drop(ThreadFreeze::with_backend(&backend, HANDLE(12)).unwrap());
+        assert_eq!(
+            backend.calls(),
+            [
+                "create Thread 12",
+                "change Thread 64 12 true",
+                "change Thread 64 12 false",
+                "close 64",
+            ]
+        );
+
+        let backend = Recording::failing_create(STATUS_NOT_IMPLEMENTED);
+        drop(ProcessFreeze::with_backend(&backend, HANDLE(8)).unwrap());
+        let calls = backend.calls();
+        assert_eq!(
+            calls
+                .iter()
+                .filter(|call| call.starts_with("resume"))
+                .count(),
+            1
+        );
+        assert_eq!(calls.last().unwrap(), "resume Process 8");
+    }
+}
@@ -0,0 +1,668 @@
+use std::fmt;
+
+use windows::Win32::System::{
+    JobObjects::{
+        JOB_OBJECT_CPU_RATE_CONTROL, JOB_OBJECT_CPU_RATE_CONTROL_ENABLE,
+        JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP, JOB_OBJECT_CPU_RATE_CONTROL_MIN_MAX_RATE,
+        JOB_OBJECT_CPU_RATE_CONTROL_NOTIFY, JOB_OBJECT_CPU_RATE_CONTROL_WEIGHT_BASED,
+        JOB_OBJECT_IO_RATE_CONTROL_ENABLE, JOB_OBJECT_IO_RATE_CONTROL_FLAGS, JOB_OBJECT_LIMIT,
+        JOB_OBJECT_NET_RATE_CONTROL_DSCP_TAG, JOB_OBJECT_NET_RATE_CONTROL_ENABLE,
+        JOB_OBJECT_NET_RATE_CONTROL_FLAGS, JOB_OBJECT_NET_RATE_CONTROL_MAX_BANDWIDTH,
+        JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION, JOBOBJECT_BASIC_LIMIT_INFORMATION,
+        JOBOBJECT_CPU_RATE_CONTROL_INFORMATION, JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0,
+        JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0_0, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOBOBJECT_IO_RATE_CONTROL_INFORMATION_NATIVE_V1, JOBOBJECT_JOBSET_INFORMATION,
+        JOBOBJECT_NET_RATE_CONTROL_INFORMATION, JOBOBJECTINFOCLASS,
+        JobObjectBasicAndIoAccountingInformation, JobObjectBasicLimitInformation,
+        JobObjectCpuRateControlInformation, JobObjectExtendedLimitInformation,
+        JobObjectJobSetInformation, JobObjectNetRateControlInformation,
+    },
+    Threading::IO_COUNTERS,
+};
+
+use crate::ntpsapi::{
+    JOBOBJECT_FREEZE_INFORMATION, JOBOBJECT_MEMORY_USAGE_INFORMATION, JOBOBJECT_WAKE_FILTER,
+    JOBOBJECT_WAKE_INFORMATION, JobObjectIoRateControlInformation, JobObjectMemoryUsageInformation,
+    JobObjectWakeInformation,
+};
+
+/// An information class that can be queried with [`super::Job::query`].
+pub trait JobInformation: Sized {
+    const CLASS: JOBOBJECTINFOCLASS;
+    type Raw: Default;
+
+    fn decode(raw: &Self::Raw) -> Self;
+}
+
+/// An information class that can also be set with [`super::Job::set`].
+pub trait SetJobInformation: JobInformation {
+    fn encode(&self) -> Self::Raw;
+}
+
+/// `JOB_OBJECT_LIMIT_*`: which limits of [`BasicLimits`] and
+/// [`ExtendedLimits`] are in effect.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct LimitFlags(pub u32);
+
+impl LimitFlags {
+    pub const WORKINGSET: Self = Self(0x1);
+    pub const PROCESS_TIME: Self = Self(0x2);
+    pub const JOB_TIME: Self = Self(0x4);
+    pub const ACTIVE_PROCESS: Self = Self(0x8);
+    pub const AFFINITY: Self = Self(0x10);
+    pub const PRIORITY_CLASS: Self = Self(0x20);
+    pub const PRESERVE_JOB_TIME: Self = Self(0x40);
+    pub const SCHEDULING_CLASS: Self = Self(0x80);
+    pub const PROCESS_MEMORY: Self = Self(0x100);
+    pub const JOB_MEMORY: Self = Self(0x200);
+    pub const DIE_ON_UNHANDLED_EXCEPTION: Self = Self(0x400);
+    pub const BREAKAWAY_OK: Self = Self(0x800);
+    pub const SILENT_BREAKAWAY_OK: Self = Self(0x1000);
+    pub const KILL_ON_JOB_CLOSE: Self = Self(0x2000);
+    pub const SUBSET_AFFINITY: Self = Self(0x4000);
+    pub const JOB_MEMORY_LOW: Self = Self(0x8000);
+    pub const SILO_READY: Self = Self(0x40_0000);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for LimitFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for LimitFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Debug for LimitFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LimitFlags({:#x})", self.0)
+    }
+}
+
+/// `JobObjectBasicLimitInformation`. Times are in 100ns units; each field
+/// only applies when its flag is set in `flags`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BasicLimits {
+    pub flags: LimitFlags,
+    pub per_process_user_time: i64,
+    pub per_job_user_time: i64,
+    pub minimum_working_set: usize,
+    pub maximum_working_set: usize,
+    pub active_processes: u32,
+    pub affinity: usize,
+    pub priority_class: u32,
+    pub scheduling_class: u32,
+}
+
+impl From<&JOBOBJECT_BASIC_LIMIT_INFORMATION> for BasicLimits {
+    fn from(value: &JOBOBJECT_BASIC_LIMIT_INFORMATION) -> Self {
+        Self {
+            flags: LimitFlags(value.LimitFlags.0),
+            per_process_user_time: value.PerProcessUserTimeLimit,
+            per_job_user_time: value.PerJobUserTimeLimit,
+            minimum_working_set: value.MinimumWorkingSetSize,
+            maximum_working_set: value.MaximumWorkingSetSize,
+            active_processes: value.ActiveProcessLimit,
+            affinity: value.Affinity,
+            priority_class: value.PriorityClass,
+            scheduling_class: value.SchedulingClass,
+        }
+    }
+}
+
+impl From<&BasicLimits> for JOBOBJECT_BASIC_LIMIT_INFORMATION {
+    fn from(value: &BasicLimits) -> Self {
+        Self {
+            PerProcessUserTimeLimit: value.per_process_user_time,
+            PerJobUserTimeLimit: value.per_job_user_time,
+            LimitFlags: JOB_OBJECT_LIMIT(value.flags.0),
+            MinimumWorkingSetSize: value.minimum_working_set,
+            MaximumWorkingSetSize: value.maximum_working_set,
+            ActiveProcessLimit: value.active_processes,
+            Affinity: value.affinity,
+            PriorityClass: value.priority_class,
+            SchedulingClass: value.scheduling_class,
+        }
+    }
+}
+
+impl JobInformation for BasicLimits {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectBasicLimitInformation;
+    type Raw = JOBOBJECT_BASIC_LIMIT_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self::from(raw)
+    }
+}
+
+impl SetJobInformation for BasicLimits {
+    fn encode(&self) -> Self::Raw {
+        self.into()
+    }
+}
+
+/// `JobObjectExtendedLimitInformation`: the basic limits with the memory
+/// limits and peaks. The peaks are ignored when setting.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ExtendedLimits {
+    pub basic: BasicLimits,
+    pub process_memory: usize,
+    pub job_memory: usize,
+    pub peak_process_memory_used: usize,
+    pub peak_job_memory_used: usize,
+}
+
+impl ExtendedLimits {
+    /// Limits the committed memory of each process to `bytes`, or removes
+    /// the limit.
+    pub fn set_process_memory(&mut self, bytes: Option<usize>) {
+        self.process_memory = bytes.unwrap_or(0);
+        self.basic.flags.remove(LimitFlags::PROCESS_MEMORY);
+        if bytes.is_some() {
+            self.basic.flags |= LimitFlags::PROCESS_MEMORY;
+        }
+    }
+
+    /// Limits the memory committed by the whole job to `bytes`, or removes
+    /// the limit.
+    pub fn set_job_memory(&mut self, bytes: Option<usize>) {
+        self.job_memory = bytes.unwrap_or(0);
+        self.basic.flags.remove(LimitFlags::JOB_MEMORY);
+        if bytes.is_some() {
+            self.basic.flags |= LimitFlags::JOB_MEMORY;
+        }
+    }
+
+    /// Limits the number of processes alive at once, or removes the limit.
+    pub fn set_active_processes(&mut self, count: Option<u32>) {
+        self.basic.active_processes = count.unwrap_or(0);
+        self.basic.flags.remove(LimitFlags::ACTIVE_PROCESS);
+        if count.is_some() {
+            self.basic.flags |= LimitFlags::ACTIVE_PROCESS;
+        }
+    }
+}
+
+impl JobInformation for ExtendedLimits {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectExtendedLimitInformation;
+    type Raw = JOBOBJECT_EXTENDED_LIMIT_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self {
+            basic: BasicLimits::from(&raw.BasicLimitInformation),
+            process_memory: raw.ProcessMemoryLimit,
+            job_memory: raw.JobMemoryLimit,
+            peak_process_memory_used: raw.PeakProcessMemoryUsed,
+            peak_job_memory_used: raw.PeakJobMemoryUsed,
+        }
+    }
+}
+
+impl SetJobInformation for ExtendedLimits {
+    fn encode(&self) -> Self::Raw {
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION {
+            BasicLimitInformation: (&self.basic).into(),
+            ProcessMemoryLimit: self.process_memory,
+            JobMemoryLimit: self.job_memory,
+            ..Default::default()
+        }
+    }
+}
+
+/// `JobObjectCpuRateControlInformation`. Rates are in hundredths of a
+/// percent of the whole machine, so `10000` is all processors.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum CpuRateControl {
+    #[default]
+    Disabled,
+    /// A share of the processor time, enforced over the scheduling interval
+    /// or, with `hard_cap`, strictly.
+    Rate {
+        rate: u32,
+        hard_cap: bool,
+        notify: bool,
+    },
+    /// A relative weight from 1 to 9 against other jobs.
+    Weight(u32),
+    /// A reserved minimum and a hard maximum.
+    MinMax { min: u16, max: u16 },
+}
+
+impl JobInformation for CpuRateControl {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectCpuRateControlInformation;
+    type Raw = JOBOBJECT_CPU_RATE_CONTROL_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        let flags = raw.ControlFlags;
+        if !flags.contains(JOB_OBJECT_CPU_RATE_CONTROL_ENABLE) {
+            return Self::Disabled;
+        }
+        unsafe {
+            if flags.contains(JOB_OBJECT_CPU_RATE_CONTROL_WEIGHT_BASED) {
+                Self::Weight(raw.Anonymous.Weight)
+            } else if flags.contains(JOB_OBJECT_CPU_RATE_CONTROL_MIN_MAX_RATE) {
+                Self::MinMax {
+                    min: raw.Anonymous.Anonymous.MinRate,
+                    max: raw.Anonymous.Anonymous.MaxRate,
+                }
+            } else {
+                Self::Rate {
+                    rate: raw.Anonymous.CpuRate,
+                    hard_cap: flags.contains(JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP),
+                    notify: flags.contains(JOB_OBJECT_CPU_RATE_CONTROL_NOTIFY),
+                }
+            }
+        }
+    }
+}
+
+impl SetJobInformation for CpuRateControl {
+    fn encode(&self) -> Self::Raw {
+        let (flags, value) = match *self {
+            Self::Disabled => (JOB_OBJECT_CPU_RATE_CONTROL(0), Default::default()),
+            Self::Rate {
+                rate,
+                hard_cap,
+                notify,
+            } => {
+                let mut flags = JOB_OBJECT_CPU_RATE_CONTROL_ENABLE;
+                if hard_cap {
+                    flags |= JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP;
+                }
+                if notify {
+                    flags |= JOB_OBJECT_CPU_RATE_CONTROL_NOTIFY;
+                }
+                (
+                    flags,
+                    JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0 { CpuRate: rate },
+                )
+            }
+            Self::Weight(weight) => (
+                JOB_OBJECT_CPU_RATE_CONTROL_ENABLE | JOB_OBJECT_CPU_RATE_CONTROL_WEIGHT_BASED,
+                JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0 { Weight: weight },
+            ),
+            Self::MinMax { min, max } => (
+                JOB_OBJECT_CPU_RATE_CONTROL_ENABLE | JOB_OBJECT_CPU_RATE_CONTROL_MIN_MAX_RATE,
+                JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0 {
+                    Anonymous: JOBOBJECT_CPU_RATE_CONTROL_INFORMATION_0_0 {
+                        MinRate: min,
+                        MaxRate: max,
+                    },
+                },
+            ),
+        };
+        JOBOBJECT_CPU_RATE_CONTROL_INFORMATION {
+            ControlFlags: flags,
+            Anonymous: value,
+        }
+    }
+}
+
+/// `JobObjectNetRateControlInformation`. Control is disabled when neither
+/// field is set.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct NetRateControl {
+    /// Outgoing bandwidth in bytes per second.
+    pub max_bandwidth: Option<u64>,
+    /// DSCP value to tag outgoing packets with.
+    pub dscp_tag: Option<u8>,
+}
+
+impl JobInformation for NetRateControl {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectNetRateControlInformation;
+    type Raw = JOBOBJECT_NET_RATE_CONTROL_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        let flags = raw.ControlFlags;
+        if !flags.contains(JOB_OBJECT_NET_RATE_CONTROL_ENABLE) {
+            return Self::default();
+        }
+        Self {
+            max_bandwidth: flags
+                .contains(JOB_OBJECT_NET_RATE_CONTROL_MAX_BANDWIDTH)
+                .then_some(raw.MaxBandwidth),
+            dscp_tag: flags
+                .contains(JOB_OBJECT_NET_RATE_CONTROL_DSCP_TAG)
+                .then_some(raw.DscpTag),
+        }
+    }
+}
+
+impl SetJobInformation for NetRateControl {
+    fn encode(&self) -> Self::Raw {
+        let mut flags = JOB_OBJECT_NET_RATE_CONTROL_FLAGS(0);
+        if self.max_bandwidth.is_some() {
+            flags |= JOB_OBJECT_NET_RATE_CONTROL_MAX_BANDWIDTH;
+        }
+        if self.dscp_tag.is_some() {
+            flags |= JOB_OBJECT_NET_RATE_CONTROL_DSCP_TAG;
+        }
+        if flags.0 != 0 {
+            flags |= JOB_OBJECT_NET_RATE_CONTROL_ENABLE;
+        }
+        JOBOBJECT_NET_RATE_CONTROL_INFORMATION {
+            MaxBandwidth: self.max_bandwidth.unwrap_or(0),
+            ControlFlags: flags,
+            DscpTag: self.dscp_tag.unwrap_or(0),
+        }
+    }
+}
+
+/// `JobObjectIoRateControlInformation` for all the volumes of the system.
+/// Bandwidth is in bytes per second and `0` leaves a field unlimited.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct IoRateControl {
+    pub enabled: bool,
+    pub max_iops: i64,
+    pub max_bandwidth: i64,
+    pub reservation_iops: i64,
+    /// Size an I/O is normalized to when counting operations.
+    pub base_io_size: u32,
+}
+
+impl JobInformation for IoRateControl {
+    const CLASS: JOBOBJECTINFOCLASS = JOBOBJECTINFOCLASS(JobObjectIoRateControlInformation as i32);
+    type Raw = JOBOBJECT_IO_RATE_CONTROL_INFORMATION_NATIVE_V1;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self {
+            enabled: raw.ControlFlags.contains(JOB_OBJECT_IO_RATE_CONTROL_ENABLE),
+            max_iops: raw.MaxIops,
+            max_bandwidth: raw.MaxBandwidth,
+            reservation_iops: raw.ReservationIops,
+            base_io_size: raw.BaseIoSize,
+        }
+    }
+}
+
+impl SetJobInformation for IoRateControl {
+    fn encode(&self) -> Self::Raw {
+        JOBOBJECT_IO_RATE_CONTROL_INFORMATION_NATIVE_V1 {
+            MaxIops: self.max_iops,
+            MaxBandwidth: self.max_bandwidth,
+            ReservationIops: self.reservation_iops,
+            BaseIoSize: self.base_io_size,
+            ControlFlags: if self.enabled {
+                JOB_OBJECT_IO_RATE_CONTROL_ENABLE
+            } else {
+                JOB_OBJECT_IO_RATE_CONTROL_FLAGS(0)
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct IoCounters {
+    pub read_operations: u64,
+    pub write_operations: u64,
+    pub other_operations: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub other_bytes: u64,
+}
+
+impl From<&IO_COUNTERS> for IoCounters {
+    fn from(value: &IO_COUNTERS) -> Self {
+        Self {
+            read_operations: value.ReadOperationCount,
+            write_operations: value.WriteOperationCount,
+            other_operations: value.OtherOperationCount,
+            read_bytes: value.ReadTransferCount,
+            write_bytes: value.WriteTransferCount,
+            other_bytes: value.OtherTransferCount,
+        }
+    }
+}
+
+/// `JobObjectBasicAndIoAccountingInformation`. Times are in 100ns units.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct Accounting {
+    pub total_user_time: i64,
+    pub total_kernel_time: i64,
+    /// Times since the per-job time limit was last set.
+    pub period_user_time: i64,
+    pub period_kernel_time: i64,
+    pub page_faults: u32,
+    pub total_processes: u32,
+    pub active_processes: u32,
+    pub terminated_processes: u32,
+    pub io: IoCounters,
+}
+
+impl JobInformation for Accounting {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectBasicAndIoAccountingInformation;
+    type Raw = JOBOBJECT_BASIC_AND_IO_ACCOUNTING_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        let basic = &raw.BasicInfo;
+        Self {
+            total_user_time: basic.TotalUserTime,
+            total_kernel_time: basic.TotalKernelTime,
+            period_user_time: basic.ThisPeriodTotalUserTime,
+            period_kernel_time: basic.ThisPeriodTotalKernelTime,
+            page_faults: basic.TotalPageFaultCount,
+            total_processes: basic.TotalProcesses,
+            active_processes: basic.ActiveProcesses,
+            terminated_processes: basic.TotalTerminatedProcesses,
+            io: IoCounters::from(&raw.IoInfo),
+        }
+    }
+}
+
+/// `JobObjectMemoryUsageInformation`, in bytes.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub job_memory: u64,
+    pub peak_job_memory_used: u64,
+}
+
+impl JobInformation for MemoryUsage {
+    const CLASS: JOBOBJECTINFOCLASS = JOBOBJECTINFOCLASS(JobObjectMemoryUsageInformation as i32);
+    type Raw = JOBOBJECT_MEMORY_USAGE_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self {
+            job_memory: raw.JobMemory,
+            peak_job_memory_used: raw.PeakJobMemoryUsed,
+        }
+    }
+}
+
+/// `JobObjectWakeInformation`: the wake counters of the job, indexed by
+/// wake reason.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct WakeCounters(pub [u64; 7]);
+
+impl JobInformation for WakeCounters {
+    const CLASS: JOBOBJECTINFOCLASS = JOBOBJECTINFOCLASS(JobObjectWakeInformation as i32);
+    type Raw = JOBOBJECT_WAKE_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self(raw.WakeCounters)
+    }
+}
+
+/// `JobObjectJobSetInformation`: the level of the job in its job set.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct JobSetLevel(pub u32);
+
+impl JobInformation for JobSetLevel {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectJobSetInformation;
+    type Raw = JOBOBJECT_JOBSET_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self(raw.MemberLevel)
+    }
+}
+
+/// `JOBOBJECT_WAKE_FILTER`: the wake reasons, as bits, that are let
+/// through while the job is frozen.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct WakeFilter {
+    pub high_edge: u32,
+    pub low_edge: u32,
+}
+
+/// A change applied through `JobObjectFreezeInformation`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum FreezeOperation {
+    Freeze,
+    Thaw,
+    SetWakeFilter(WakeFilter),
+}
+
+impl FreezeOperation {
+    const FREEZE: u32 = 0x1;
+    const FILTER: u32 = 0x2;
+
+    pub fn to_raw(&self) -> JOBOBJECT_FREEZE_INFORMATION {
+        let mut raw = JOBOBJECT_FREEZE_INFORMATION::default();
+        match self {
+            Self::Freeze | Self::Thaw => {
+                raw.Anonymous1.union_field = Self::FREEZE;
+                raw.Freeze = (*self == Self::Freeze).into();
+            }
+            Self::SetWakeFilter(filter) => {
+                raw.Anonymous1.union_field = Self::FILTER;
+                raw.WakeFilter = JOBOBJECT_WAKE_FILTER {
+                    HighEdgeFilter: filter.high_edge,
+                    LowEdgeFilter: filter.low_edge,
+                };
+            }
+        }
+        raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_cpu_rate_control() {
+        for (control, flags) in [
+            (CpuRateControl::Disabled, 0),
+            (
+                CpuRateControl::Rate {
+                    rate: 2500,
+                    hard_cap: true,
+                    notify: false,
+                },
+                0x5,
+            ),
+            (
+                CpuRateControl::Rate {
+                    rate: 100,
+                    hard_cap: false,
+                    notify: true,
+                },
+                0x9,
+            ),
+            (CpuRateControl::Weight(7), 0x3),
+            (CpuRateControl::MinMax { min: 10, max: 9000 }, 0x11),
+        ] {
+            let raw = control.encode();
+            assert_eq!(raw.ControlFlags.0, flags, "{control:?}");
+            assert_eq!(CpuRateControl::decode(&raw), control);
+        }
+        // MinRate is the low word of the union.
+        let raw = CpuRateControl::MinMax { min: 10, max: 9000 }.encode();
+        assert_eq!(unsafe { raw.Anonymous.CpuRate }, 9000 << 16 | 10);
+        // Without the enable flag the rest is ignored.
+        let mut raw = CpuRateControl::Weight(7).encode();
+        raw.ControlFlags = JOB_OBJECT_CPU_RATE_CONTROL_WEIGHT_BASED;
+        assert_eq!(CpuRateControl::decode(&raw), CpuRateControl::Disabled);
+    }
+
+    #[test]
+    fn round_trips_net_rate_control() {
+        for (control, flags) in [
+            (NetRateControl::default(), 0),
+            (
+                NetRateControl {
+                    max_bandwidth: Some(1 << 20),
+                    dscp_tag: Some(46),
+                },
+                0x7,
+            ),
+            (
+                NetRateControl {
+                    max_bandwidth: None,
+                    dscp_tag: Some(0),
+                },
+                0x5,
+            ),
+        ] {
+            let raw = control.encode();
+            assert_eq!(raw.ControlFlags.0, flags, "{control:?}");
+            assert_eq!(NetRateControl::decode(&raw), control);
+        }
+        let raw = JOBOBJECT_NET_RATE_CONTROL_INFORMATION {
+            MaxBandwidth: 5,
+            ControlFlags: JOB_OBJECT_NET_RATE_CONTROL_MAX_BANDWIDTH,
+            DscpTag: 0,
+        };
+        assert_eq!(NetRateControl::decode(&raw), NetRateControl::default());
+    }
+
+    #[test]
+    fn round_trips_io_rate_control() {
+        let control = IoRateControl {
+            enabled: true,
+            max_iops: 500,
+            max_bandwidth: 10 << 20,
+            reservation_iops: 50,
+            base_io_size: 4096,
+        };
+        let raw = control.encode();
+        assert_eq!(raw.ControlFlags, JOB_OBJECT_IO_RATE_CONTROL_ENABLE);
+        // All volumes.
+        assert!(raw.VolumeName.is_null());
+        assert_eq!(IoRateControl::decode(&raw), control);
+
+        let disabled = IoRateControl::default();
+        assert_eq!(disabled.encode().ControlFlags.0, 0);
+        assert_eq!(IoRateControl::decode(&disabled.encode()), disabled);
+    }
+
+    #[test]
+    fn encodes_freeze_operations() {
+        let flags = |raw: &JOBOBJECT_FREEZE_INFORMATION| unsafe { *raw.Anonymous1.Flags.as_ref() };
+
+        let raw = FreezeOperation::Freeze.to_raw();
+        assert_eq!(flags(&raw), 0x1);
+        assert!(raw.Freeze.as_bool());
+        let fields = unsafe { raw.Anonymous1.Anonymous1.as_ref() };
+        assert_eq!((fields.FreezeOperation(), fields.FilterOperation()), (1, 0));
+
+        let raw = FreezeOperation::Thaw.to_raw();
+        assert_eq!(flags(&raw), 0x1);
+        assert!(!raw.Freeze.as_bool());
+
+        let raw = FreezeOperation::SetWakeFilter(WakeFilter {
+            high_edge: 0x8,
+            low_edge: 0x3,
+        })
+        .to_raw();
+        assert_eq!(flags(&raw), 0x2);
+        assert!(!raw.Freeze.as_bool());
+        assert_eq!(
+            (raw.WakeFilter.HighEdgeFilter, raw.WakeFilter.LowEdgeFilter),
+            (0x8, 0x3)
+        );
+    }
+}
@@ -0,0 +1,540 @@
+//! Job objects through the `NtCreateJobObject` family.
+//!
+//! A [`Job`] owns a job handle and exposes typed queries and updates of its
+//! information classes over a [`JobBackend`], [`NativeJobs`] unless one is
+//! given, through [`JobInformation`] and [`SetJobInformation`]: limits
+//! ([`ExtendedLimits`], [`CpuRateControl`], [`NetRateControl`],
+//! [`IoRateControl`]), [`Accounting`] and [`MemoryUsage`]. The information
+//! types convert to and from the native structures in plain Rust, independently
+//! of any job.
+//!
+//! A job can also be turned into a silo. [`ServerSilo`] runs the sequence
+//! that makes a server silo out of a new job: creating the silo, its root
+//! object directory and system root, then initializing it; [`SiloBasicInfo`],
+//! [`ServerSiloInfo`] and [`SiloSharedData`] describe the result.
+//!
+//! ```no_run
+//! use windows::Win32::Foundation::HANDLE;
+//! use windows_native::job::*;
+//!
+//! let job = Job::create(None)?;
+//! job.update_limits(|limits| {
+//!     limits.set_job_memory(Some(512 << 20));
+//!     limits.basic.flags |= LimitFlags::KILL_ON_JOB_CLOSE;
+//! })?;
+//! job.set(&CpuRateControl::Rate { rate: 2500, hard_cap: true, notify: false })?;
+//! // The pseudo-handle for the current process.
+//! job.assign_process(HANDLE(-1))?;
+//! println!("{:?}", job.process_ids()?);
+//! # Ok::<(), windows::Win32::Foundation::NTSTATUS>(())
+//! ```
+
+mod info;
+mod silo;
+
+use std::{
+    ffi::c_void,
+    mem::{self, ManuallyDrop},
+    ptr, slice,
+};
+
+pub use info::*;
+pub use silo::*;
+use windows::{
+    Wdk::Foundation::{NtClose, OBJECT_ATTRIBUTES},
+    Win32::{
+        Foundation::{
+            HANDLE, NTSTATUS, STATUS_BUFFER_OVERFLOW, STATUS_PROCESS_IN_JOB,
+            STATUS_PROCESS_NOT_IN_JOB, UNICODE_STRING,
+        },
+        System::JobObjects::{
+            JOB_SET_ARRAY, JOBOBJECT_BASIC_PROCESS_ID_LIST, JOBOBJECTINFOCLASS,
+            JobObjectBasicProcessIdList, JobObjectCreateSilo,
+        },
+    },
+};
+
+use crate::{
+    buffer::{to_wide, unicode_string},
+    check,
+    ntpsapi::{
+        JOB_OBJECT_ALL_ACCESS, JobObjectFreezeInformation, NtAssignProcessToJobObject,
+        NtCreateJobObject, NtCreateJobSet, NtIsProcessInJob, NtOpenJobObject,
+        NtQueryInformationJobObject, NtSetInformationJobObject, NtTerminateJobObject,
+    },
+};
+
+/// `OBJ_CASE_INSENSITIVE`.
+const OBJ_CASE_INSENSITIVE: u32 = 0x40;
+
+/// Calls `f` with object attributes naming `name`, or no name.
+fn with_attributes<T>(name: Option<&str>, f: impl FnOnce(*mut OBJECT_ATTRIBUTES) -> T) -> T {
+    let name = name.map(to_wide);
+    let mut name = name.as_deref().map(unicode_string);
+    let mut attributes = OBJECT_ATTRIBUTES {
+        Length: mem::size_of::<OBJECT_ATTRIBUTES>() as u32,
+        ObjectName: name
+            .as_mut()
+            .map_or(ptr::null_mut(), |name| name as *mut UNICODE_STRING),
+        Attributes: OBJ_CASE_INSENSITIVE,
+        ..Default::default()
+    };
+    f(&mut attributes)
+}
+
+/// The calls [`Job`] is built on.
+pub trait JobBackend {
+    /// Creates a job with all access, named `name` when set.
+    fn create(&self, name: Option<&str>) -> Result<HANDLE, NTSTATUS>;
+
+    fn open(&self, name: &str, desired_access: u32) -> Result<HANDLE, NTSTATUS>;
+
+    fn close(&self, job: HANDLE);
+
+    /// Queries `class` into `buffer`, which is aligned for the class
+    /// structure, returning the length written.
+    fn query(
+        &self,
+        job: HANDLE,
+        class: JOBOBJECTINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<usize, NTSTATUS>;
+
+    /// Sets `class` from `buffer`, the bytes of the class structure. Any
+    /// pointers in it stay valid for the call.
+    fn set(&self, job: HANDLE, class: JOBOBJECTINFOCLASS, buffer: &[u8]) -> Result<(), NTSTATUS>;
+
+    fn assign_process(&self, job: HANDLE, process: HANDLE) -> Result<(), NTSTATUS>;
+
+    /// Whether `process` is in `job` or one of its children.
+    fn contains_process(&self, job: HANDLE, process: HANDLE) -> Result<bool, NTSTATUS>;
+
+    fn terminate(&self, job: HANDLE, exit_status: NTSTATUS) -> Result<(), NTSTATUS>;
+}
+
+/// The [`JobBackend`] that calls the `Nt*JobObject` functions.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeJobs;
+
+impl JobBackend for NativeJobs {
+    fn create(&self, name: Option<&str>) -> Result<HANDLE, NTSTATUS> {
+        let mut handle = HANDLE::default();
+        check(with_attributes(name, |attributes| unsafe {
+            NtCreateJobObject(&mut handle, JOB_OBJECT_ALL_ACCESS, attributes)
+        }))?;
+        Ok(handle)
+    }
+
+    fn open(&self, name: &str, desired_access: u32) -> Result<HANDLE, NTSTATUS> {
+        let mut handle = HANDLE::default();
+        check(with_attributes(Some(name), |attributes| unsafe {
+            NtOpenJobObject(&mut handle, desired_access, attributes)
+        }))?;
+        Ok(handle)
+    }
+
+    fn close(&self, job: HANDLE) {
+        unsafe { NtClose(job) };
+    }
+
+    fn query(
+        &self,
+        job: HANDLE,
+        class: JOBOBJECTINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<usize, NTSTATUS> {
+        let mut return_length = 0;
+        check(unsafe {
+            NtQueryInformationJobObject(
+                job,
+                class,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u32,
+                &mut return_length,
+            )
+        })?;
+        Ok(return_length as usize)
+    }
+
+    fn set(&self, job: HANDLE, class: JOBOBJECTINFOCLASS, buffer: &[u8]) -> Result<(), NTSTATUS> {
+        // Classes without input, such as `JobObjectCreateSilo`, take no
+        // buffer at all.
+        let pointer = if buffer.is_empty() {
+            ptr::null_mut()
+        } else {
+            buffer.as_ptr().cast_mut().cast::<c_void>()
+        };
+        check(unsafe { NtSetInformationJobObject(job, class, pointer, buffer.len() as u32) })
+    }
+
+    fn assign_process(&self, job: HANDLE, process: HANDLE) -> Result<(), NTSTATUS> {
+        check(unsafe { NtAssignProcessToJobObject(job, process) })
+    }
+
+    fn contains_process(&self, job: HANDLE, process: HANDLE) -> Result<bool, NTSTATUS> {
+        match unsafe { NtIsProcessInJob(process, job) } {
+            STATUS_PROCESS_IN_JOB => Ok(true),
+            STATUS_PROCESS_NOT_IN_JOB => Ok(false),
+            status => Err(status),
+        }
+    }
+
+    fn terminate(&self, job: HANDLE, exit_status: NTSTATUS) -> Result<(), NTSTATUS> {
+        check(unsafe { NtTerminateJobObject(job, exit_status) })
+    }
+}
+
+/// The bytes of `value`, to pass a native structure to the backend.
+fn bytes_of<T: ?Sized>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(ptr::from_ref(value).cast(), mem::size_of_val(value)) }
+}
+
+/// The bytes of `value`, for the backend to fill in.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid `T`, as the OS writes whatever it
+/// writes.
+unsafe fn bytes_of_mut<T: ?Sized>(value: &mut T) -> &mut [u8] {
+    let length = mem::size_of_val(value);
+    unsafe { slice::from_raw_parts_mut(ptr::from_mut(value).cast(), length) }
+}
+
+/// An open job object, closed on drop.
+#[derive(Debug)]
+pub struct Job<B: JobBackend = NativeJobs> {
+    backend: B,
+    handle: HANDLE,
+}
+
+impl Job {
+    /// Creates a job, named in the object namespace when `name` is set, e.g.
+    /// `\BaseNamedObjects\Sandbox`.
+    pub fn create(name: Option<&str>) -> Result<Self, NTSTATUS> {
+        Self::create_with(NativeJobs, name)
+    }
+
+    pub fn open(name: &str, desired_access: u32) -> Result<Self, NTSTATUS> {
+        Self::open_with(NativeJobs, name, desired_access)
+    }
+}
+
+impl<B: JobBackend> Job<B> {
+    pub fn create_with(backend: B, name: Option<&str>) -> Result<Self, NTSTATUS> {
+        let handle = backend.create(name)?;
+        Ok(Self::from_raw(backend, handle))
+    }
+
+    pub fn open_with(backend: B, name: &str, desired_access: u32) -> Result<Self, NTSTATUS> {
+        let handle = backend.open(name, desired_access)?;
+        Ok(Self::from_raw(backend, handle))
+    }
+
+    /// Takes ownership of a job handle opened through `backend`.
+    pub fn from_raw(backend: B, handle: HANDLE) -> Self {
+        Self { backend, handle }
+    }
+
+    pub fn as_raw(&self) -> HANDLE {
+        self.handle
+    }
+
+    /// Releases ownership of the handle without closing it, handing back the
+    /// backend it was opened through; the inverse of [`Job::from_raw`].
+    pub fn into_raw(self) -> (B, HANDLE) {
+        let this = ManuallyDrop::new(self);
+        // `Drop` never runs for `this`, so the backend is moved out exactly
+        // once.
+        (unsafe { ptr::read(&this.backend) }, this.handle)
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn query<T: JobInformation>(&self) -> Result<T, NTSTATUS> {
+        let mut raw = Box::<T::Raw>::default();
+        // The class structures hold only integers, so any bytes are valid.
+        self.query_raw(T::CLASS, unsafe { bytes_of_mut(&mut *raw) })?;
+        Ok(T::decode(&raw))
+    }
+
+    pub fn set<T: SetJobInformation>(&self, value: &T) -> Result<(), NTSTATUS> {
+        self.set_raw(T::CLASS, bytes_of(&value.encode()))
+    }
+
+    /// Reads the extended limits, applies `update` and writes them back.
+    pub fn update_limits(&self, update: impl FnOnce(&mut ExtendedLimits)) -> Result<(), NTSTATUS> {
+        let mut limits = self.query::<ExtendedLimits>()?;
+        update(&mut limits);
+        self.set(&limits)
+    }
+
+    /// Queries `class` into `buffer`, returning the length written.
+    fn query_raw(&self, class: JOBOBJECTINFOCLASS, buffer: &mut [u8]) -> Result<usize, NTSTATUS> {
+        self.backend.query(self.handle, class, buffer)
+    }
+
+    fn set_raw(&self, class: JOBOBJECTINFOCLASS, buffer: &[u8]) -> Result<(), NTSTATUS> {
+        self.backend.set(self.handle, class, buffer)
+    }
+
+    /// Assigns a process, which needs `PROCESS_SET_QUOTA` and
+    /// `PROCESS_TERMINATE` access. A process already in a job is nested
+    /// under it when this job can be a child of that one.
+    pub fn assign_process(&self, process: HANDLE) -> Result<(), NTSTATUS> {
+        self.backend.assign_process(self.handle, process)
+    }
+
+    /// Whether `process` is in this job or one of its children.
+    pub fn contains_process(&self, process: HANDLE) -> Result<bool, NTSTATUS> {
+        self.backend.contains_process(self.handle, process)
+    }
+
+    /// Terminates every process of the job with `exit_status`.
+    pub fn terminate(&self, exit_status: NTSTATUS) -> Result<(), NTSTATUS> {
+        self.backend.terminate(self.handle, exit_status)
+    }
+
+    /// The ids of the processes in the job, including those of child jobs.
+    pub fn process_ids(&self) -> Result<Vec<usize>, NTSTATUS> {
+        let header = mem::size_of::<JOBOBJECT_BASIC_PROCESS_ID_LIST>() - mem::size_of::<usize>();
+        let mut capacity = 64;
+        loop {
+            let mut buffer = vec![0usize; header / mem::size_of::<usize>() + capacity];
+            let status = self
+                .query_raw(JobObjectBasicProcessIdList, unsafe {
+                    bytes_of_mut(&mut buffer[..])
+                })
+                .err();
+            let list = unsafe { &*buffer.as_ptr().cast::<JOBOBJECT_BASIC_PROCESS_ID_LIST>() };
+            let assigned = list.NumberOfAssignedProcesses as usize;
+            let listed = list.NumberOfProcessIdsInList as usize;
+            match status {
+                Some(STATUS_BUFFER_OVERFLOW) => {}
+                Some(status) => return Err(status),
+                None if listed >= assigned => {
+                    let start = header / mem::size_of::<usize>();
+                    return Ok(buffer[start..start + listed.min(capacity)].to_vec());
+                }
+                None => {}
+            }
+            capacity = capacity.max(assigned) + 16;
+        }
+    }
+
+    pub fn freeze(&self) -> Result<(), NTSTATUS> {
+        self.apply(FreezeOperation::Freeze)
+    }
+
+    pub fn thaw(&self) -> Result<(), NTSTATUS> {
+        self.apply(FreezeOperation::Thaw)
+    }
+
+    /// Sets the wake reasons let through while the job is frozen.
+    pub fn set_wake_filter(&self, filter: WakeFilter) -> Result<(), NTSTATUS> {
+        self.apply(FreezeOperation::SetWakeFilter(filter))
+    }
+
+    pub fn apply(&self, operation: FreezeOperation) -> Result<(), NTSTATUS> {
+        self.set_raw(
+            JOBOBJECTINFOCLASS(JobObjectFreezeInformation as i32),
+            bytes_of(&operation.to_raw()),
+        )
+    }
+
+    /// Turns the job into a silo. The job must not have processes yet.
+    pub fn create_silo(&self) -> Result<(), NTSTATUS> {
+        self.set_raw(JobObjectCreateSilo, &[])
+    }
+}
+
+impl<B: JobBackend> Drop for Job<B> {
+    fn drop(&mut self) {
+        if !self.handle.is_invalid() {
+            self.backend.close(self.handle);
+        }
+    }
+}
+
+/// Groups `members` into a job set. Each job comes with its member level;
+/// a process can only move to a job of a higher level in the same set.
+pub fn create_job_set(members: &[(&Job, u32)]) -> Result<(), NTSTATUS> {
+    let mut array = members
+        .iter()
+        .map(|(job, level)| JOB_SET_ARRAY {
+            JobHandle: job.as_raw(),
+            MemberLevel: *level,
+            Flags: 0,
+        })
+        .collect::<Vec<_>>();
+    check(unsafe { NtCreateJobSet(array.len() as u32, array.as_mut_ptr(), 0) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use windows::Win32::{
+        Foundation::STATUS_ACCESS_DENIED,
+        System::JobObjects::{
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectExtendedLimitInformation,
+        },
+    };
+
+    use super::*;
+
+    /// A job whose state is shared with the test, like the kernel's.
+    #[derive(Clone, Default)]
+    struct Mock {
+        state: Rc<RefCell<State>>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        processes: Vec<usize>,
+        limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        /// The buffer length of each query.
+        lengths: Vec<usize>,
+        sets: Vec<(JOBOBJECTINFOCLASS, Vec<u8>)>,
+        closed: Vec<isize>,
+        fail: Option<NTSTATUS>,
+    }
+
+    impl JobBackend for Mock {
+        fn create(&self, _: Option<&str>) -> Result<HANDLE, NTSTATUS> {
+            Ok(HANDLE(0x10))
+        }
+
+        fn open(&self, _: &str, _: u32) -> Result<HANDLE, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn close(&self, job: HANDLE) {
+            self.state.borrow_mut().closed.push(job.0);
+        }
+
+        fn query(
+            &self,
+            _: HANDLE,
+            class: JOBOBJECTINFOCLASS,
+            buffer: &mut [u8],
+        ) -> Result<usize, NTSTATUS> {
+            let mut state = self.state.borrow_mut();
+            state.lengths.push(buffer.len());
+            if let Some(status) = state.fail {
+                return Err(status);
+            }
+            match class {
+                JobObjectBasicProcessIdList => {
+                    // As many ids as fit after the two counts, and
+                    // STATUS_BUFFER_OVERFLOW when some are left out.
+                    let fit = (buffer.len() - 8) / 8;
+                    let listed = state.processes.len().min(fit);
+                    buffer[..4].copy_from_slice(&(state.processes.len() as u32).to_le_bytes());
+                    buffer[4..8].copy_from_slice(&(listed as u32).to_le_bytes());
+                    for (chunk, id) in buffer[8..].chunks_exact_mut(8).zip(&state.processes) {
+                        chunk.copy_from_slice(&id.to_le_bytes());
+                    }
+                    if listed < state.processes.len() {
+                        return Err(STATUS_BUFFER_OVERFLOW);
+                    }
+                    Ok(8 + 8 * listed)
+                }
+                JobObjectExtendedLimitInformation => {
+                    let bytes = bytes_of(&state.limits);
+                    buffer[..bytes.len()].copy_from_slice(bytes);
+                    Ok(bytes.len())
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn set(&self, _: HANDLE, class: JOBOBJECTINFOCLASS, buffer: &[u8]) -> Result<(), NTSTATUS> {
+            self.state.borrow_mut().sets.push((class, buffer.to_vec()));
+            Ok(())
+        }
+
+        fn assign_process(&self, _: HANDLE, _: HANDLE) -> Result<(), NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn contains_process(&self, _: HANDLE, _: HANDLE) -> Result<bool, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn terminate(&self, _: HANDLE, _: NTSTATUS) -> Result<(), NTSTATUS> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn regrows_the_process_id_list() {
+        let mock = Mock::default();
+        let job = Job::create_with(mock.clone(), None).unwrap();
+        mock.state.borrow_mut().processes = (1..=100).map(|id| id * 4).collect();
+        let ids = job.process_ids().unwrap();
+        assert_eq!(ids.len(), 100);
+        assert_eq!((ids[0], ids[99]), (4, 400));
+        // 64 ids first, then the 100 assigned with room for 16 more.
+        assert_eq!(mock.state.borrow().lengths, [8 + 64 * 8, 8 + 116 * 8]);
+
+        mock.state.borrow_mut().lengths.clear();
+        mock.state.borrow_mut().processes.truncate(3);
+        assert_eq!(job.process_ids().unwrap(), [4, 8, 12]);
+        assert_eq!(mock.state.borrow().lengths.len(), 1);
+
+        mock.state.borrow_mut().fail = Some(STATUS_ACCESS_DENIED);
+        assert_eq!(job.process_ids(), Err(STATUS_ACCESS_DENIED));
+    }
+
+    #[test]
+    fn writes_back_updated_limits() {
+        let mock = Mock::default();
+        mock.state.borrow_mut().limits.PeakJobMemoryUsed = 4096;
+        let job = Job::create_with(mock.clone(), None).unwrap();
+        assert_eq!(
+            job.query::<ExtendedLimits>().unwrap().peak_job_memory_used,
+            4096
+        );
+        job.update_limits(|limits| limits.set_job_memory(Some(1 << 20)))
+            .unwrap();
+
+        let state = mock.state.borrow();
+        let [(class, bytes)] = &state.sets[..] else {
+            panic!("{} sets", state.sets.len());
+        };
+        assert_eq!(*class, JobObjectExtendedLimitInformation);
+        assert_eq!(
+            bytes.len(),
+            mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>()
+        );
+        let written = unsafe {
+            ptr::read_unaligned(
+                bytes
+                    .as_ptr()
+                    .cast::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>(),
+            )
+        };
+        assert_eq!(written.JobMemoryLimit, 1 << 20);
+        assert_eq!(
+            written.BasicLimitInformation.LimitFlags.0,
+            LimitFlags::JOB_MEMORY.0
+        );
+        // Peaks are read-only and not written back.
+        assert_eq!(written.PeakJobMemoryUsed, 0);
+    }
+
+    #[test]
+    fn closes_each_job_once() {
+        let mock = Mock::default();
+        drop(Job::create_with(mock.clone(), None).unwrap());
+        assert_eq!(mock.state.borrow().closed, [0x10]);
+
+        let (backend, handle) = Job::create_with(mock.clone(), None).unwrap().into_raw();
+        assert_eq!(mock.state.borrow().closed, [0x10]);
+        drop(Job::from_raw(backend, handle));
+        assert_eq!(mock.state.borrow().closed, [0x10, 0x10]);
+        assert_eq!(Rc::strong_count(&mock.state), 1);
+    }
+}
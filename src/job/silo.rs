@@ -0,0 +1,602 @@
+use std::{mem, ptr};
+
+use windows::{
+    Wdk::{
+        Foundation::NtClose,
+        System::Threading::{NtQueryInformationProcess, ProcessBasicInformation},
+    },
+    Win32::{
+        Foundation::{BOOLEAN, HANDLE, NTSTATUS, STATUS_BUFFER_TOO_SMALL, UNICODE_STRING},
+        System::{
+            JobObjects::{JOBOBJECTINFOCLASS, JobObjectSiloBasicInformation},
+            Kernel::NT_PRODUCT_TYPE,
+            SystemServices::{SERVERSILO_BASIC_INFORMATION, SILOOBJECT_BASIC_INFORMATION},
+            Threading::PROCESS_BASIC_INFORMATION,
+        },
+    },
+};
+
+use super::{Job, JobBackend, JobInformation, NativeJobs, bytes_of, bytes_of_mut, with_attributes};
+use crate::{
+    buffer::{from_unicode_string, from_wide, to_wide, unicode_string},
+    check,
+    ntobapi::{DIRECTORY_ALL_ACCESS, NtCreateDirectoryObjectEx},
+    ntpebteb::PEB,
+    ntpsapi::{
+        JobObjectServerSiloBasicInformation, JobObjectServerSiloInitialize,
+        JobObjectServerSiloUserSharedData, JobObjectSiloRootDirectory, JobObjectSiloSystemRoot,
+        SERVERSILO_INIT_INFORMATION, SILO_OBJECT_ROOT_DIRECTORY_INITIALIZE,
+        SILO_OBJECT_ROOT_DIRECTORY_SHADOW_DOS_DEVICES, SILO_OBJECT_ROOT_DIRECTORY_SHADOW_ROOT,
+        SILO_USER_SHARED_DATA, SILOOBJECT_ROOT_DIRECTORY,
+    },
+};
+
+/// `SILO_OBJECT_ROOT_DIRECTORY_*` combined: create the root directory,
+/// shadowing the host's root and `\DosDevices`.
+pub const SILO_OBJECT_ROOT_DIRECTORY_ALL: u32 = SILO_OBJECT_ROOT_DIRECTORY_SHADOW_ROOT
+    | SILO_OBJECT_ROOT_DIRECTORY_INITIALIZE
+    | SILO_OBJECT_ROOT_DIRECTORY_SHADOW_DOS_DEVICES;
+
+/// `JobObjectSiloBasicInformation`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct SiloBasicInfo {
+    pub silo_id: u32,
+    pub parent_id: u32,
+    pub processes: u32,
+    pub in_server_silo: bool,
+}
+
+impl JobInformation for SiloBasicInfo {
+    const CLASS: JOBOBJECTINFOCLASS = JobObjectSiloBasicInformation;
+    type Raw = SILOOBJECT_BASIC_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self {
+            silo_id: raw.SiloId,
+            parent_id: raw.SiloParentId,
+            processes: raw.NumberOfProcesses,
+            in_server_silo: raw.IsInServerSilo.as_bool(),
+        }
+    }
+}
+
+/// `SERVERSILO_STATE`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum ServerSiloState {
+    Initializing,
+    Started,
+    ShuttingDown,
+    Terminating,
+    Terminated,
+    Unknown(i32),
+}
+
+impl From<i32> for ServerSiloState {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Initializing,
+            1 => Self::Started,
+            2 => Self::ShuttingDown,
+            3 => Self::Terminating,
+            4 => Self::Terminated,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// `JobObjectServerSiloBasicInformation`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ServerSiloInfo {
+    pub service_session_id: u32,
+    pub state: ServerSiloState,
+    pub exit_status: NTSTATUS,
+    pub is_downlevel_container: bool,
+}
+
+impl JobInformation for ServerSiloInfo {
+    const CLASS: JOBOBJECTINFOCLASS =
+        JOBOBJECTINFOCLASS(JobObjectServerSiloBasicInformation as i32);
+    type Raw = SERVERSILO_BASIC_INFORMATION;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        Self {
+            service_session_id: raw.ServiceSessionId,
+            state: ServerSiloState::from(raw.State.0),
+            exit_status: NTSTATUS(raw.ExitStatus as i32),
+            is_downlevel_container: raw.IsDownlevelContainer.as_bool(),
+        }
+    }
+}
+
+/// `SILO_USER_SHARED_DATA`: the per silo part of `KUSER_SHARED_DATA`.
+/// Time zone values are in 100ns units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiloSharedData {
+    pub service_session_id: u32,
+    pub active_console_id: u32,
+    pub console_foreground_process_id: i64,
+    pub product_type: NT_PRODUCT_TYPE,
+    pub suite_mask: u32,
+    pub shared_user_session_id: u32,
+    pub multi_session_sku: bool,
+    /// e.g. `C:\Windows`.
+    pub system_root: String,
+    pub user_mode_global_logger: [u16; 16],
+    pub time_zone_id: u32,
+    pub time_zone_bias: i64,
+    pub time_zone_bias_effective_start: i64,
+    pub time_zone_bias_effective_end: i64,
+}
+
+impl SiloSharedData {
+    /// The data of the silo the current process runs in, read through
+    /// `PEB::SharedData`.
+    pub fn current() -> Result<Option<Self>, NTSTATUS> {
+        let mut basic = PROCESS_BASIC_INFORMATION::default();
+        check(unsafe {
+            NtQueryInformationProcess(
+                HANDLE(-1),
+                ProcessBasicInformation,
+                ptr::addr_of_mut!(basic).cast(),
+                mem::size_of_val(&basic) as u32,
+                ptr::null_mut(),
+            )
+        })?;
+        let peb = basic.PebBaseAddress.cast::<PEB>();
+        let shared = unsafe { (*peb).SharedData };
+        Ok((!shared.is_null()).then(|| Self::decode(unsafe { &*shared })))
+    }
+}
+
+impl JobInformation for SiloSharedData {
+    const CLASS: JOBOBJECTINFOCLASS = JOBOBJECTINFOCLASS(JobObjectServerSiloUserSharedData as i32);
+    type Raw = SILO_USER_SHARED_DATA;
+
+    fn decode(raw: &Self::Raw) -> Self {
+        let bias = &raw.TimeZoneBias;
+        Self {
+            service_session_id: raw.ServiceSessionId,
+            active_console_id: raw.ActiveConsoleId,
+            console_foreground_process_id: raw.ConsoleSessionForegroundProcessId,
+            product_type: raw.NtProductType,
+            suite_mask: raw.SuiteMask,
+            shared_user_session_id: raw.SharedUserSessionId,
+            multi_session_sku: raw.IsMultiSessionSku.as_bool(),
+            system_root: from_wide(&raw.NtSystemRoot),
+            user_mode_global_logger: raw.UserModeGlobalLogger,
+            time_zone_id: raw.TimeZoneId,
+            time_zone_bias: ((bias.High1Time as i64) << 32) | bias.LowPart as i64,
+            time_zone_bias_effective_start: raw.TimeZoneBiasEffectiveStart,
+            time_zone_bias_effective_end: raw.TimeZoneBiasEffectiveEnd,
+        }
+    }
+}
+
+/// An object directory, closed on drop.
+#[derive(Debug)]
+pub struct ObjectDirectory(HANDLE);
+
+impl ObjectDirectory {
+    /// Creates the directory `name`. Lookups that miss in it fall through
+    /// to `shadow` when set.
+    pub fn create(name: &str, shadow: Option<&Self>, flags: u32) -> Result<Self, NTSTATUS> {
+        let mut handle = HANDLE::default();
+        check(with_attributes(Some(name), |attributes| unsafe {
+            NtCreateDirectoryObjectEx(
+                &mut handle,
+                DIRECTORY_ALL_ACCESS,
+                attributes,
+                shadow.map_or(HANDLE::default(), Self::as_raw),
+                flags,
+            )
+        }))?;
+        Ok(Self(handle))
+    }
+
+    pub fn as_raw(&self) -> HANDLE {
+        self.0
+    }
+}
+
+impl Drop for ObjectDirectory {
+    fn drop(&mut self) {
+        if !self.0.is_invalid() {
+            unsafe { NtClose(self.0) };
+        }
+    }
+}
+
+/// How [`ServerSilo::create`] sets up a server silo.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerSilo {
+    /// Name of the job in the object namespace.
+    pub name: Option<String>,
+    /// `SILO_OBJECT_ROOT_DIRECTORY_*` flags; [`SILO_OBJECT_ROOT_DIRECTORY_ALL`]
+    /// when zero.
+    pub root_flags: u32,
+    /// The Windows directory of the silo, e.g. `\??\C:\Silo\Windows`.
+    pub system_root: Option<String>,
+    /// Event signaled when the silo is torn down.
+    pub delete_event: Option<HANDLE>,
+    pub is_downlevel_container: bool,
+}
+
+impl ServerSilo {
+    /// Creates the job, turns it into a silo, creates its root directory,
+    /// sets its system root and initializes it as a server silo. Processes
+    /// assigned afterwards run inside it.
+    pub fn create(&self) -> Result<Job, NTSTATUS> {
+        self.create_with(NativeJobs)
+    }
+
+    pub fn create_with<B: JobBackend>(&self, backend: B) -> Result<Job<B>, NTSTATUS> {
+        let job = Job::create_with(backend, self.name.as_deref())?;
+        job.create_silo()?;
+        job.initialize_root_directory(if self.root_flags == 0 {
+            SILO_OBJECT_ROOT_DIRECTORY_ALL
+        } else {
+            self.root_flags
+        })?;
+        if let Some(system_root) = &self.system_root {
+            job.set_system_root(system_root)?;
+        }
+        job.initialize_server_silo(self.delete_event, self.is_downlevel_container)?;
+        Ok(job)
+    }
+}
+
+impl<B: JobBackend> Job<B> {
+    /// Creates the root object directory of the silo, `\Silos\<id>`.
+    pub fn initialize_root_directory(&self, flags: u32) -> Result<(), NTSTATUS> {
+        let mut raw = SILOOBJECT_ROOT_DIRECTORY::default();
+        raw.Anonymous1.ControlFlags = flags;
+        self.set_raw(
+            JOBOBJECTINFOCLASS(JobObjectSiloRootDirectory as i32),
+            bytes_of(&raw),
+        )
+    }
+
+    /// The path of the root object directory of the silo.
+    pub fn root_directory(&self) -> Result<String, NTSTATUS> {
+        let mut buffer = vec![0u64; 0x80];
+        loop {
+            match self.query_raw(
+                JOBOBJECTINFOCLASS(JobObjectSiloRootDirectory as i32),
+                unsafe { bytes_of_mut(&mut buffer[..]) },
+            ) {
+                Err(STATUS_BUFFER_TOO_SMALL) => buffer.resize(buffer.len() * 2, 0),
+                result => {
+                    result?;
+                    let raw = unsafe { &*buffer.as_ptr().cast::<SILOOBJECT_ROOT_DIRECTORY>() };
+                    return Ok(unsafe { from_unicode_string(&raw.Anonymous1.Path) });
+                }
+            }
+        }
+    }
+
+    /// Creates `name`, relative to the root directory of the silo,
+    /// shadowing `shadow`.
+    pub fn create_directory(
+        &self,
+        name: &str,
+        shadow: Option<&ObjectDirectory>,
+    ) -> Result<ObjectDirectory, NTSTATUS> {
+        let path = format!("{}\\{name}", self.root_directory()?);
+        ObjectDirectory::create(&path, shadow, 0)
+    }
+
+    pub fn set_system_root(&self, path: &str) -> Result<(), NTSTATUS> {
+        let path = to_wide(path);
+        let raw: UNICODE_STRING = unicode_string(&path);
+        self.set_raw(
+            JOBOBJECTINFOCLASS(JobObjectSiloSystemRoot as i32),
+            bytes_of(&raw),
+        )
+    }
+
+    /// Promotes the silo to a server silo, with its own
+    /// `SILO_USER_SHARED_DATA` and session space.
+    pub fn initialize_server_silo(
+        &self,
+        delete_event: Option<HANDLE>,
+        is_downlevel_container: bool,
+    ) -> Result<(), NTSTATUS> {
+        let raw = SERVERSILO_INIT_INFORMATION {
+            DeleteEvent: delete_event.unwrap_or_default(),
+            IsDownlevelContainer: BOOLEAN(is_downlevel_container as u8),
+        };
+        self.set_raw(
+            JOBOBJECTINFOCLASS(JobObjectServerSiloInitialize as i32),
+            bytes_of(&raw),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use windows::Win32::{
+        Foundation::{STATUS_ACCESS_DENIED, STATUS_PENDING},
+        System::JobObjects::JobObjectCreateSilo,
+    };
+
+    use super::*;
+    use crate::{buffer::Writer, ntpsapi::SILOOBJECT_ROOT_DIRECTORY_1};
+
+    /// Decodes `T` from the bytes of its native structure.
+    fn decode<T: JobInformation>(bytes: &[u8]) -> T {
+        let mut raw = T::Raw::default();
+        let target = unsafe { bytes_of_mut(&mut raw) };
+        assert_eq!(target.len(), bytes.len());
+        target.copy_from_slice(bytes);
+        T::decode(&raw)
+    }
+
+    #[test]
+    fn decodes_silo_basic_info() {
+        let mut bytes = Writer::new();
+        bytes.u32(12).u32(0).u32(3).u8(1).zeros(3);
+        assert_eq!(
+            decode::<SiloBasicInfo>(bytes.as_slice()),
+            SiloBasicInfo {
+                silo_id: 12,
+                parent_id: 0,
+                processes: 3,
+                in_server_silo: true,
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_server_silo_info() {
+        let mut bytes = Writer::new();
+        bytes
+            .u32(2)
+            .u32(1)
+            .u32(STATUS_PENDING.0 as u32)
+            .u8(0)
+            .align(mem::size_of::<usize>())
+            // ApiSetSchema and HostApiSetSchema.
+            .zeros(2 * mem::size_of::<usize>());
+        assert_eq!(
+            decode::<ServerSiloInfo>(bytes.as_slice()),
+            ServerSiloInfo {
+                service_session_id: 2,
+                state: ServerSiloState::Started,
+                exit_status: STATUS_PENDING,
+                is_downlevel_container: false,
+            }
+        );
+        assert_eq!(ServerSiloState::from(9), ServerSiloState::Unknown(9));
+    }
+
+    #[test]
+    fn decodes_silo_shared_data() {
+        let mut bytes = Writer::new();
+        bytes
+            .u32(2)
+            .u32(1)
+            .i64(4242)
+            .u32(3)
+            .u32(0x110)
+            .u32(5)
+            .u8(1)
+            .align(2)
+            .utf16z(r"C:\Windows")
+            .zeros(2 * (260 - 11));
+        for unit in 1..=16 {
+            bytes.u16(unit);
+        }
+        bytes
+            .align(4)
+            .u32(2)
+            // TimeZoneBiasStamp, then the KSYSTEM_TIME with High2Time.
+            .u32(7)
+            .u32(0x1234_5678)
+            .u32(-2i32 as u32)
+            .u32(-2i32 as u32)
+            .align(8)
+            .i64(100)
+            .i64(200);
+        assert_eq!(
+            decode::<SiloSharedData>(bytes.as_slice()),
+            SiloSharedData {
+                service_session_id: 2,
+                active_console_id: 1,
+                console_foreground_process_id: 4242,
+                product_type: NT_PRODUCT_TYPE(3),
+                suite_mask: 0x110,
+                shared_user_session_id: 5,
+                multi_session_sku: true,
+                system_root: r"C:\Windows".into(),
+                user_mode_global_logger: std::array::from_fn(|index| index as u16 + 1),
+                time_zone_id: 2,
+                time_zone_bias: (-2 << 32) | 0x1234_5678,
+                time_zone_bias_effective_start: 100,
+                time_zone_bias_effective_end: 200,
+            }
+        );
+    }
+
+    /// Records the calls made to set up a silo.
+    #[derive(Clone, Debug, Default)]
+    struct Mock {
+        calls: Rc<RefCell<Vec<String>>>,
+        fail: Option<JOBOBJECTINFOCLASS>,
+    }
+
+    impl Mock {
+        fn record(&self, call: String) {
+            self.calls.borrow_mut().push(call);
+        }
+    }
+
+    impl JobBackend for Mock {
+        fn create(&self, name: Option<&str>) -> Result<HANDLE, NTSTATUS> {
+            self.record(format!("create {}", name.unwrap_or("-")));
+            Ok(HANDLE(0x10))
+        }
+
+        fn open(&self, _: &str, _: u32) -> Result<HANDLE, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn close(&self, _: HANDLE) {
+            self.record("close".into());
+        }
+
+        fn query(
+            &self,
+            _: HANDLE,
+            class: JOBOBJECTINFOCLASS,
+            buffer: &mut [u8],
+        ) -> Result<usize, NTSTATUS> {
+            assert_eq!(class.0, JobObjectSiloRootDirectory as i32);
+            self.record(format!("query root directory {}", buffer.len()));
+            if buffer.len() < 0x800 {
+                return Err(STATUS_BUFFER_TOO_SMALL);
+            }
+            // The path follows the structure and points into the buffer,
+            // as the kernel returns it.
+            let path = to_wide(r"\Silos\12");
+            let offset = mem::size_of::<SILOOBJECT_ROOT_DIRECTORY>();
+            for (chunk, unit) in buffer[offset..].chunks_exact_mut(2).zip(&path) {
+                chunk.copy_from_slice(&unit.to_le_bytes());
+            }
+            let raw = SILOOBJECT_ROOT_DIRECTORY {
+                Anonymous1: SILOOBJECT_ROOT_DIRECTORY_1 {
+                    Path: unicode_string(unsafe {
+                        std::slice::from_raw_parts(
+                            buffer.as_ptr().add(offset).cast(),
+                            path.len() - 1,
+                        )
+                    }),
+                },
+            };
+            buffer[..offset].copy_from_slice(bytes_of(&raw));
+            Ok(offset + 2 * path.len())
+        }
+
+        fn set(&self, _: HANDLE, class: JOBOBJECTINFOCLASS, buffer: &[u8]) -> Result<(), NTSTATUS> {
+            let call = match class.0 {
+                _ if class == JobObjectCreateSilo => {
+                    assert!(buffer.is_empty());
+                    "create silo".into()
+                }
+                class if class == JobObjectSiloRootDirectory as i32 => {
+                    let flags = u32::from_le_bytes(buffer[..4].try_into().unwrap());
+                    format!("root directory {flags:#x}")
+                }
+                class if class == JobObjectSiloSystemRoot as i32 => {
+                    let raw =
+                        unsafe { ptr::read_unaligned(buffer.as_ptr().cast::<UNICODE_STRING>()) };
+                    format!("system root {}", unsafe { from_unicode_string(&raw) })
+                }
+                class if class == JobObjectServerSiloInitialize as i32 => {
+                    let raw = unsafe {
+                        ptr::read_unaligned(buffer.as_ptr().cast::<SERVERSILO_INIT_INFORMATION>())
+                    };
+                    format!(
+                        "initialize {:#x} {}",
+                        raw.DeleteEvent.0,
+                        raw.IsDownlevelContainer.as_bool()
+                    )
+                }
+                _ => unimplemented!(),
+            };
+            self.record(call);
+            match self.fail {
+                Some(fail) if fail == class => Err(STATUS_ACCESS_DENIED),
+                _ => Ok(()),
+            }
+        }
+
+        fn assign_process(&self, _: HANDLE, _: HANDLE) -> Result<(), NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn contains_process(&self, _: HANDLE, _: HANDLE) -> Result<bool, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn terminate(&self, _: HANDLE, _: NTSTATUS) -> Result<(), NTSTATUS> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn creates_server_silo_in_order() {
+        let silo = ServerSilo {
+            name: Some(r"\Silos\Test".into()),
+            system_root: Some(r"\??\C:\Silo\Windows".into()),
+            delete_event: Some(HANDLE(0x44)),
+            ..Default::default()
+        };
+        let mock = Mock::default();
+        let job = silo.create_with(mock.clone()).unwrap();
+        assert_eq!(
+            mock.calls.take(),
+            [
+                r"create \Silos\Test",
+                "create silo",
+                "root directory 0x7",
+                r"system root \??\C:\Silo\Windows",
+                "initialize 0x44 false",
+            ]
+        );
+        drop(job);
+        assert_eq!(mock.calls.take(), ["close"]);
+
+        // Without a system root that step is skipped, and the root flags
+        // are passed through.
+        let silo = ServerSilo {
+            root_flags: SILO_OBJECT_ROOT_DIRECTORY_INITIALIZE,
+            is_downlevel_container: true,
+            ..Default::default()
+        };
+        drop(silo.create_with(mock.clone()).unwrap());
+        assert_eq!(
+            mock.calls.take(),
+            [
+                "create -",
+                "create silo",
+                "root directory 0x2",
+                "initialize 0x0 true",
+                "close",
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_failing_step() {
+        let mock = Mock {
+            fail: Some(JOBOBJECTINFOCLASS(JobObjectSiloRootDirectory as i32)),
+            ..Default::default()
+        };
+        assert_eq!(
+            ServerSilo::default().create_with(mock.clone()).unwrap_err(),
+            STATUS_ACCESS_DENIED
+        );
+        // The half made job is closed.
+        assert_eq!(
+            mock.calls.take(),
+            ["create -", "create silo", "root directory 0x7", "close"]
+        );
+    }
+
+    #[test]
+    fn regrows_the_root_directory_query() {
+        let mock = Mock::default();
+        let job = Job::create_with(mock.clone(), None).unwrap();
+        assert_eq!(job.root_directory().unwrap(), r"\Silos\12");
+        assert_eq!(
+            mock.calls.take(),
+            [
+                "create -",
+                "query root directory 1024",
+                "query root directory 2048",
+            ]
+        );
+    }
+}
@@ -71,6 +71,13 @@
 #![warn(clippy::cargo)]
 
 pub mod bitfield;
+pub mod buffer;
+pub mod efi;
+pub mod feature;
+pub mod firmware;
+pub mod freeze;
+pub mod job;
+pub mod memory;
 pub mod ntbcd;
 pub mod ntdbg;
 pub mod ntexapi;
@@ -101,5 +108,20 @@ pub mod ntwow64;
 pub mod ntxcapi;
 pub mod ntzwapi;
 pub mod phnt_ntdef;
+pub mod process;
+pub mod sam;
+pub mod security;
+pub mod session;
+pub mod snapshot;
 pub mod subprocesstag;
+pub mod superfetch;
+pub mod system;
 pub mod winsta;
+
+use windows::Win32::Foundation::NTSTATUS;
+
+/// Turns an error `status` into `Err`, keeping success and informational
+/// statuses as `Ok`.
+pub(crate) fn check(status: NTSTATUS) -> Result<(), NTSTATUS> {
+    if status.is_err() { Err(status) } else { Ok(()) }
+}
@@ -0,0 +1,520 @@
+use std::fmt;
+
+use windows::Win32::{
+    Foundation::NTSTATUS,
+    System::Memory::{
+        MEM_COMMIT, MEM_FREE, MEM_IMAGE, MEM_MAPPED, MEM_PRIVATE, PAGE_EXECUTE, PAGE_EXECUTE_READ,
+        PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_PROTECTION_FLAGS, PAGE_READONLY,
+        PAGE_READWRITE, PAGE_TYPE, PAGE_WRITECOPY,
+    },
+};
+
+use super::{BasicInfo, ImageInfo, MemoryBackend, PAGE_SIZE, RegionInfo, WorkingSetPage};
+
+/// Resident bytes of a region, from the process working set.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct WorkingSetStats {
+    pub resident: usize,
+    pub shared: usize,
+    pub private: usize,
+}
+
+/// One allocation: the blocks sharing an allocation base, with whatever is
+/// known about what backs it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base: usize,
+    pub size: usize,
+    pub allocation_protect: PAGE_PROTECTION_FLAGS,
+    /// `MEM_PRIVATE`, `MEM_MAPPED` or `MEM_IMAGE`.
+    pub kind: PAGE_TYPE,
+    /// The reserved and committed runs making up the allocation, in order.
+    pub blocks: Vec<BasicInfo>,
+    pub info: Option<RegionInfo>,
+    /// NT path of the mapped file or image.
+    pub path: Option<String>,
+    pub image: Option<ImageInfo>,
+    /// `None` if the working set could not be queried.
+    pub working_set: Option<WorkingSetStats>,
+}
+
+impl MemoryRegion {
+    pub fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.base..self.end()).contains(&address)
+    }
+
+    pub fn committed(&self) -> usize {
+        self.blocks
+            .iter()
+            .filter(|block| block.state == MEM_COMMIT)
+            .map(|block| block.size)
+            .sum()
+    }
+}
+
+/// The allocations of a process address space, ordered by address.
+///
+/// Formatting with `{}` renders one line per block in the style of
+/// `/proc/<pid>/maps`: address range, permissions, offset into the
+/// allocation and the mapped path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub regions: Vec<MemoryRegion>,
+}
+
+impl MemoryMap {
+    /// Walks the address space and annotates every allocation.
+    ///
+    /// Only the walk itself fails the build. Annotations that cannot be
+    /// queried, for instance because the allocation was freed in the
+    /// meantime, are left empty.
+    pub fn build(backend: &impl MemoryBackend) -> Result<Self, NTSTATUS> {
+        let mut map = Self::from_blocks(walk(backend)?);
+        map.annotate(backend);
+        if let Ok(pages) = backend.working_set() {
+            map.apply_working_set(&pages);
+        }
+        Ok(map)
+    }
+
+    /// Merges consecutive blocks with the same allocation base into
+    /// regions, dropping free memory.
+    pub fn from_blocks(blocks: impl IntoIterator<Item = BasicInfo>) -> Self {
+        let mut regions: Vec<MemoryRegion> = Vec::new();
+        for block in blocks {
+            if block.state == MEM_FREE || block.size == 0 {
+                continue;
+            }
+            match regions.last_mut() {
+                Some(region)
+                    if region.base == block.allocation_base && region.end() == block.base =>
+                {
+                    region.size += block.size;
+                    region.blocks.push(block);
+                }
+                _ => regions.push(MemoryRegion {
+                    base: block.allocation_base,
+                    size: block.end() - block.allocation_base,
+                    allocation_protect: block.allocation_protect,
+                    kind: block.kind,
+                    blocks: vec![block],
+                    info: None,
+                    path: None,
+                    image: None,
+                    working_set: None,
+                }),
+            }
+        }
+        Self { regions }
+    }
+
+    /// Queries region, mapped file and image information for each region.
+    pub fn annotate(&mut self, backend: &impl MemoryBackend) {
+        for region in &mut self.regions {
+            region.info = backend.region(region.base).ok();
+            if region.kind == MEM_MAPPED || region.kind == MEM_IMAGE {
+                region.path = backend.mapped_filename(region.base).ok().flatten();
+            }
+            if region.kind == MEM_IMAGE {
+                region.image = backend.image(region.base).ok();
+            }
+        }
+    }
+
+    /// Attributes each working set page to the region containing it.
+    pub fn apply_working_set(&mut self, pages: &[WorkingSetPage]) {
+        let mut pages = pages.to_vec();
+        pages.sort_unstable_by_key(|page| page.address);
+        for region in &mut self.regions {
+            let start = pages.partition_point(|page| page.address < region.base);
+            let end = pages.partition_point(|page| page.address < region.end());
+            let mut stats = WorkingSetStats::default();
+            for page in &pages[start..end] {
+                stats.resident += PAGE_SIZE;
+                if page.shared {
+                    stats.shared += PAGE_SIZE;
+                } else {
+                    stats.private += PAGE_SIZE;
+                }
+            }
+            region.working_set = Some(stats);
+        }
+    }
+
+    /// The region containing `address`.
+    pub fn find(&self, address: usize) -> Option<&MemoryRegion> {
+        let index = self
+            .regions
+            .partition_point(|region| region.end() <= address);
+        self.regions
+            .get(index)
+            .filter(|region| region.contains(address))
+    }
+
+    pub fn committed(&self) -> usize {
+        self.regions.iter().map(MemoryRegion::committed).sum()
+    }
+}
+
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = 2 * std::mem::size_of::<usize>();
+        for region in &self.regions {
+            for block in &region.blocks {
+                write!(
+                    f,
+                    "{:0width$x}-{:0width$x} {} {:08x}",
+                    block.base,
+                    block.end(),
+                    permissions(block),
+                    block.base - region.base,
+                )?;
+                if let Some(path) = &region.path {
+                    write!(f, " {path}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn walk(backend: &impl MemoryBackend) -> Result<Vec<BasicInfo>, NTSTATUS> {
+    let mut blocks = Vec::new();
+    let mut address = 0usize;
+    while let Some(block) = backend.basic(address)? {
+        if block.size == 0 {
+            break;
+        }
+        blocks.push(block);
+        match block.base.checked_add(block.size) {
+            Some(next) if next > address => address = next,
+            _ => break,
+        }
+    }
+    Ok(blocks)
+}
+
+/// `rwxp` style permissions. Reserved pages have none; `p` marks private and
+/// copy-on-write pages, `s` shared ones.
+fn permissions(block: &BasicInfo) -> String {
+    let protect = PAGE_PROTECTION_FLAGS(block.protect.0 & 0xff);
+    let (read, write, execute) = if block.state != MEM_COMMIT {
+        (false, false, false)
+    } else {
+        match protect {
+            PAGE_READONLY => (true, false, false),
+            PAGE_READWRITE | PAGE_WRITECOPY => (true, true, false),
+            PAGE_EXECUTE => (false, false, true),
+            PAGE_EXECUTE_READ => (true, false, true),
+            PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY => (true, true, true),
+            _ => (false, false, false),
+        }
+    };
+    let private =
+        block.kind == MEM_PRIVATE || protect == PAGE_WRITECOPY || protect == PAGE_EXECUTE_WRITECOPY;
+    [
+        if read { 'r' } else { '-' },
+        if write { 'w' } else { '-' },
+        if execute { 'x' } else { '-' },
+        if private { 'p' } else { 's' },
+    ]
+    .iter()
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::{
+        Foundation::STATUS_ACCESS_DENIED,
+        System::Memory::{MEM_RESERVE, PAGE_NOACCESS, VIRTUAL_ALLOCATION_TYPE},
+    };
+
+    use super::*;
+    use crate::memory::PageRecord;
+
+    const NTDLL: &str = r"\Device\HarddiskVolume3\Windows\System32\ntdll.dll";
+
+    fn block(
+        base: usize,
+        allocation_base: usize,
+        size: usize,
+        state: VIRTUAL_ALLOCATION_TYPE,
+        protect: PAGE_PROTECTION_FLAGS,
+        kind: PAGE_TYPE,
+    ) -> BasicInfo {
+        BasicInfo {
+            base,
+            allocation_base,
+            allocation_protect: PAGE_READWRITE,
+            size,
+            state,
+            protect,
+            kind,
+        }
+    }
+
+    /// A heap allocation, a mapped view whose region cannot be queried and
+    /// an image, with free memory around them.
+    fn blocks() -> Vec<BasicInfo> {
+        vec![
+            block(0, 0, 0x10000, MEM_FREE, PAGE_NOACCESS, PAGE_TYPE(0)),
+            block(
+                0x10000,
+                0x10000,
+                0x1000,
+                MEM_COMMIT,
+                PAGE_READWRITE,
+                MEM_PRIVATE,
+            ),
+            block(
+                0x11000,
+                0x10000,
+                0xf000,
+                MEM_RESERVE,
+                PAGE_PROTECTION_FLAGS(0),
+                MEM_PRIVATE,
+            ),
+            block(
+                0x20000,
+                0x20000,
+                0x2000,
+                MEM_COMMIT,
+                PAGE_READONLY,
+                MEM_MAPPED,
+            ),
+            block(
+                0x22000,
+                0x22000,
+                0x1e000,
+                MEM_FREE,
+                PAGE_NOACCESS,
+                PAGE_TYPE(0),
+            ),
+            block(
+                0x40000,
+                0x40000,
+                0x1000,
+                MEM_COMMIT,
+                PAGE_READONLY,
+                MEM_IMAGE,
+            ),
+            block(
+                0x41000,
+                0x40000,
+                0x1000,
+                MEM_COMMIT,
+                PAGE_EXECUTE_READ,
+                MEM_IMAGE,
+            ),
+            block(
+                0x42000,
+                0x40000,
+                0x1000,
+                MEM_COMMIT,
+                PAGE_WRITECOPY,
+                MEM_IMAGE,
+            ),
+        ]
+    }
+
+    struct Mock(Vec<BasicInfo>);
+
+    impl MemoryBackend for Mock {
+        fn basic(&self, address: usize) -> Result<Option<BasicInfo>, NTSTATUS> {
+            Ok(self
+                .0
+                .iter()
+                .find(|block| block.base <= address && address < block.end())
+                .copied())
+        }
+
+        fn region(&self, address: usize) -> Result<RegionInfo, NTSTATUS> {
+            if address == 0x20000 {
+                return Err(STATUS_ACCESS_DENIED);
+            }
+            Ok(RegionInfo {
+                allocation_base: address,
+                ..Default::default()
+            })
+        }
+
+        fn mapped_filename(&self, _: usize) -> Result<Option<String>, NTSTATUS> {
+            Ok(Some(NTDLL.into()))
+        }
+
+        fn image(&self, address: usize) -> Result<ImageInfo, NTSTATUS> {
+            Ok(ImageInfo {
+                image_base: address,
+                size: 0x3000,
+                ..Default::default()
+            })
+        }
+
+        fn working_set(&self) -> Result<Vec<WorkingSetPage>, NTSTATUS> {
+            Ok(vec![
+                WorkingSetPage {
+                    address: 0x40000,
+                    shared: true,
+                    ..Default::default()
+                },
+                WorkingSetPage {
+                    address: 0x10000,
+                    ..Default::default()
+                },
+                WorkingSetPage {
+                    address: 0x41000,
+                    shared: true,
+                    ..Default::default()
+                },
+            ])
+        }
+
+        fn working_set_ex(&self, addresses: &[usize]) -> Result<Vec<PageRecord>, NTSTATUS> {
+            Ok(addresses
+                .iter()
+                .map(|&address| PageRecord::from_attributes(address, 0))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn merges_blocks_by_allocation_base() {
+        let map = MemoryMap::from_blocks(blocks());
+        let regions = map
+            .regions
+            .iter()
+            .map(|region| (region.base, region.size, region.blocks.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            regions,
+            [
+                (0x10000, 0x10000, 2),
+                (0x20000, 0x2000, 1),
+                (0x40000, 0x3000, 3)
+            ]
+        );
+        assert_eq!(map.regions[2].kind, MEM_IMAGE);
+        assert_eq!(map.regions[0].committed(), 0x1000);
+        assert_eq!(map.committed(), 0x1000 + 0x2000 + 0x3000);
+
+        // A block starting past the end of the last region opens a new one,
+        // even with the same allocation base.
+        let split = MemoryMap::from_blocks([
+            block(
+                0x10000,
+                0x10000,
+                0x1000,
+                MEM_COMMIT,
+                PAGE_READWRITE,
+                MEM_PRIVATE,
+            ),
+            block(
+                0x12000,
+                0x10000,
+                0x1000,
+                MEM_COMMIT,
+                PAGE_READWRITE,
+                MEM_PRIVATE,
+            ),
+        ]);
+        assert_eq!(split.regions.len(), 2);
+        assert_eq!(split.regions[1].size, 0x3000);
+        assert_eq!(MemoryMap::from_blocks([]), MemoryMap::default());
+    }
+
+    #[test]
+    fn builds_and_annotates() {
+        let map = MemoryMap::build(&Mock(blocks())).unwrap();
+        assert_eq!(map.regions.len(), 3);
+        assert_eq!(map.regions[0].info.unwrap().allocation_base, 0x10000);
+        assert!(map.regions[1].info.is_none());
+        assert_eq!(map.regions[0].path, None);
+        assert_eq!(map.regions[1].path.as_deref(), Some(NTDLL));
+        assert!(map.regions[1].image.is_none());
+        assert_eq!(map.regions[2].image.unwrap().size, 0x3000);
+        assert_eq!(
+            map.regions[0].working_set,
+            Some(WorkingSetStats {
+                resident: 0x1000,
+                shared: 0,
+                private: 0x1000,
+            })
+        );
+        assert_eq!(map.regions[1].working_set, Some(WorkingSetStats::default()));
+        assert_eq!(
+            map.regions[2].working_set,
+            Some(WorkingSetStats {
+                resident: 0x2000,
+                shared: 0x2000,
+                private: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn finds_containing_region() {
+        let map = MemoryMap::from_blocks(blocks());
+        assert_eq!(map.find(0x10000).unwrap().base, 0x10000);
+        assert_eq!(map.find(0x1ffff).unwrap().base, 0x10000);
+        assert_eq!(map.find(0x41800).unwrap().base, 0x40000);
+        assert!(map.find(0).is_none());
+        assert!(map.find(0x22000).is_none());
+        assert!(map.find(0x30000).is_none());
+        assert!(map.find(0x43000).is_none());
+        assert!(map.find(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn permissions_of_blocks() {
+        let cases = [
+            (MEM_COMMIT, PAGE_READONLY, MEM_PRIVATE, "r--p"),
+            (MEM_COMMIT, PAGE_READWRITE, MEM_MAPPED, "rw-s"),
+            (MEM_COMMIT, PAGE_WRITECOPY, MEM_IMAGE, "rw-p"),
+            (MEM_COMMIT, PAGE_EXECUTE, MEM_IMAGE, "--xs"),
+            (MEM_COMMIT, PAGE_EXECUTE_READ, MEM_IMAGE, "r-xs"),
+            (MEM_COMMIT, PAGE_EXECUTE_READWRITE, MEM_PRIVATE, "rwxp"),
+            (MEM_COMMIT, PAGE_EXECUTE_WRITECOPY, MEM_IMAGE, "rwxp"),
+            (MEM_COMMIT, PAGE_NOACCESS, MEM_MAPPED, "---s"),
+            // Modifiers above the low byte are ignored.
+            (
+                MEM_COMMIT,
+                PAGE_PROTECTION_FLAGS(0x104),
+                MEM_PRIVATE,
+                "rw-p",
+            ),
+            (MEM_RESERVE, PAGE_READWRITE, MEM_PRIVATE, "---p"),
+        ];
+        for (state, protect, kind, expected) in cases {
+            let block = block(0x10000, 0x10000, 0x1000, state, protect, kind);
+            assert_eq!(permissions(&block), expected, "{protect:?}");
+        }
+    }
+
+    #[test]
+    fn displays_one_line_per_block() {
+        let mut map = MemoryMap::from_blocks(blocks());
+        map.annotate(&Mock(Vec::new()));
+        let text = map.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        let width = 2 * std::mem::size_of::<usize>();
+        let range = |start: usize, end: usize| format!("{start:0width$x}-{end:0width$x}");
+        assert_eq!(
+            lines,
+            [
+                format!("{} rw-p 00000000", range(0x10000, 0x11000)),
+                format!("{} ---p 00001000", range(0x11000, 0x20000)),
+                format!("{} r--s 00000000 {NTDLL}", range(0x20000, 0x22000)),
+                format!("{} r--s 00000000 {NTDLL}", range(0x40000, 0x41000)),
+                format!("{} r-xs 00001000 {NTDLL}", range(0x41000, 0x42000)),
+                format!("{} rw-p 00002000 {NTDLL}", range(0x42000, 0x43000)),
+            ]
+        );
+        assert!(text.ends_with('\n'));
+        assert_eq!(MemoryMap::default().to_string(), "");
+    }
+}
@@ -0,0 +1,27 @@
+//! Process address space inspection over `NtQueryVirtualMemory`.
+//!
+//! The `MEMORY_INFORMATION_CLASS` queries are wrapped by a [`MemoryBackend`]
+//! that returns decoded records; [`NativeMemory`] issues the real calls for a
+//! process handle. Everything built on top, such as [`MemoryMap`], only sees
+//! the backend and can run against recorded data.
+//!
+//! [`WorkingSetQuery`] reads `MemoryWorkingSetExInformation` for page ranges
+//! in batches and [`WorkingSetSummary`] aggregates the decoded pages.
+//!
+//! ```no_run
+//! use windows::Win32::Foundation::HANDLE;
+//! use windows_native::memory::{MemoryMap, NativeMemory};
+//!
+//! // The pseudo-handle for the current process.
+//! let map = MemoryMap::build(&NativeMemory::new(HANDLE(-1)))?;
+//! print!("{map}");
+//! # Ok::<(), windows::Win32::Foundation::NTSTATUS>(())
+//! ```
+
+mod map;
+mod query;
+mod working_set;
+
+pub use map::*;
+pub use query::*;
+pub use working_set::*;
@@ -0,0 +1,426 @@
+use std::{ffi::c_void, mem, ptr};
+
+use windows::{
+    Wdk::Storage::FileSystem::{
+        MEMORY_INFORMATION_CLASS as INFORMATION_CLASS, NtQueryVirtualMemory,
+    },
+    Win32::{
+        Foundation::{
+            HANDLE, NTSTATUS, STATUS_BUFFER_OVERFLOW, STATUS_INFO_LENGTH_MISMATCH,
+            STATUS_INVALID_ADDRESS, STATUS_INVALID_PARAMETER, UNICODE_STRING,
+        },
+        System::Memory::{
+            MEMORY_BASIC_INFORMATION, PAGE_PROTECTION_FLAGS, PAGE_TYPE, VIRTUAL_ALLOCATION_TYPE,
+        },
+    },
+};
+
+use crate::{
+    buffer::from_unicode_string,
+    check,
+    ntmmapi::{
+        MEMORY_IMAGE_INFORMATION, MEMORY_INFORMATION_CLASS, MEMORY_REGION_INFORMATION,
+        MEMORY_WORKING_SET_BLOCK, MEMORY_WORKING_SET_EX_INFORMATION,
+        MEMORY_WORKING_SET_INFORMATION,
+    },
+};
+
+use super::PageRecord;
+
+/// The page size `MEMORY_WORKING_SET_BLOCK::VirtualPage` is counted in.
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// `MemoryBasicInformation`: a run of pages sharing state, protection and
+/// type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BasicInfo {
+    pub base: usize,
+    pub allocation_base: usize,
+    pub allocation_protect: PAGE_PROTECTION_FLAGS,
+    pub size: usize,
+    /// `MEM_COMMIT`, `MEM_RESERVE` or `MEM_FREE`.
+    pub state: VIRTUAL_ALLOCATION_TYPE,
+    pub protect: PAGE_PROTECTION_FLAGS,
+    /// `MEM_PRIVATE`, `MEM_MAPPED` or `MEM_IMAGE`, zero for free memory.
+    pub kind: PAGE_TYPE,
+}
+
+impl BasicInfo {
+    pub fn end(&self) -> usize {
+        self.base + self.size
+    }
+}
+
+impl From<&MEMORY_BASIC_INFORMATION> for BasicInfo {
+    fn from(value: &MEMORY_BASIC_INFORMATION) -> Self {
+        Self {
+            base: value.BaseAddress as usize,
+            allocation_base: value.AllocationBase as usize,
+            allocation_protect: value.AllocationProtect,
+            size: value.RegionSize,
+            state: value.State,
+            protect: value.Protect,
+            kind: value.Type,
+        }
+    }
+}
+
+/// The region type bits of `MEMORY_REGION_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct RegionFlags {
+    pub private: bool,
+    pub mapped_data_file: bool,
+    pub mapped_image: bool,
+    pub mapped_page_file: bool,
+    pub mapped_physical: bool,
+    pub direct_mapped: bool,
+    pub software_enclave: bool,
+    pub page_size_64k: bool,
+    pub placeholder_reservation: bool,
+    pub mapped_awe: bool,
+    pub mapped_write_watch: bool,
+    pub page_size_large: bool,
+    pub page_size_huge: bool,
+}
+
+/// `MemoryRegionInformation`: describes a whole allocation.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RegionInfo {
+    pub allocation_base: usize,
+    pub allocation_protect: u32,
+    pub flags: RegionFlags,
+    pub size: usize,
+    pub commit_size: usize,
+    pub partition_id: usize,
+    pub node_preference: usize,
+}
+
+impl From<&MEMORY_REGION_INFORMATION> for RegionInfo {
+    fn from(value: &MEMORY_REGION_INFORMATION) -> Self {
+        let bits = unsafe { value.Anonymous1.Anonymous1.as_ref() };
+        Self {
+            allocation_base: value.AllocationBase as usize,
+            allocation_protect: value.AllocationProtect,
+            flags: RegionFlags {
+                private: bits.Private() != 0,
+                mapped_data_file: bits.MappedDataFile() != 0,
+                mapped_image: bits.MappedImage() != 0,
+                mapped_page_file: bits.MappedPageFile() != 0,
+                mapped_physical: bits.MappedPhysical() != 0,
+                direct_mapped: bits.DirectMapped() != 0,
+                software_enclave: bits.SoftwareEnclave() != 0,
+                page_size_64k: bits.PageSize64K() != 0,
+                placeholder_reservation: bits.PlaceholderReservation() != 0,
+                mapped_awe: bits.MappedAwe() != 0,
+                mapped_write_watch: bits.MappedWriteWatch() != 0,
+                page_size_large: bits.PageSizeLarge() != 0,
+                page_size_huge: bits.PageSizeHuge() != 0,
+            },
+            size: value.RegionSize,
+            commit_size: value.CommitSize,
+            partition_id: value.PartitionId,
+            node_preference: value.NodePreference,
+        }
+    }
+}
+
+/// `MemoryImageInformation`: the image mapped at an address.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub image_base: usize,
+    pub size: usize,
+    pub partial_map: bool,
+    pub not_executable: bool,
+    /// One of the `SE_SIGNING_LEVEL_*` values.
+    pub signing_level: u8,
+}
+
+impl From<&MEMORY_IMAGE_INFORMATION> for ImageInfo {
+    fn from(value: &MEMORY_IMAGE_INFORMATION) -> Self {
+        let bits = unsafe { value.Anonymous1.Anonymous1.as_ref() };
+        Self {
+            image_base: value.ImageBase as usize,
+            size: value.SizeOfImage,
+            partial_map: bits.ImagePartialMap() != 0,
+            not_executable: bits.ImageNotExecutable() != 0,
+            signing_level: bits.ImageSigningLevel() as u8,
+        }
+    }
+}
+
+/// An entry of `MemoryWorkingSetInformation`: one resident page.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct WorkingSetPage {
+    pub address: usize,
+    /// The memory manager's 5-bit protection code, not a `PAGE_*` value.
+    pub protection: u8,
+    /// Number of working sets sharing the page, saturating at 7.
+    pub share_count: u8,
+    pub shared: bool,
+    pub node: u8,
+}
+
+impl From<&MEMORY_WORKING_SET_BLOCK> for WorkingSetPage {
+    fn from(value: &MEMORY_WORKING_SET_BLOCK) -> Self {
+        Self {
+            address: value.VirtualPage() * PAGE_SIZE,
+            protection: value.Protection() as u8,
+            share_count: value.ShareCount() as u8,
+            shared: value.Shared() != 0,
+            node: value.Node() as u8,
+        }
+    }
+}
+
+/// The `NtQueryVirtualMemory` classes the memory inspection APIs are built
+/// on.
+pub trait MemoryBackend {
+    /// Describes the pages at `address`, or `None` past the end of the user
+    /// address space.
+    fn basic(&self, address: usize) -> Result<Option<BasicInfo>, NTSTATUS>;
+
+    fn region(&self, address: usize) -> Result<RegionInfo, NTSTATUS>;
+
+    /// The NT path of the file mapped at `address`, if any.
+    fn mapped_filename(&self, address: usize) -> Result<Option<String>, NTSTATUS>;
+
+    fn image(&self, address: usize) -> Result<ImageInfo, NTSTATUS>;
+
+    /// Every page in the working set of the process.
+    fn working_set(&self) -> Result<Vec<WorkingSetPage>, NTSTATUS>;
+
+    /// Describes each of `addresses` in a single query.
+    fn working_set_ex(&self, addresses: &[usize]) -> Result<Vec<PageRecord>, NTSTATUS>;
+}
+
+/// The [`MemoryBackend`] that queries a process. The handle needs
+/// `PROCESS_QUERY_INFORMATION` and is not closed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NativeMemory {
+    process: HANDLE,
+}
+
+impl NativeMemory {
+    pub fn new(process: HANDLE) -> Self {
+        Self { process }
+    }
+
+    pub fn process(&self) -> HANDLE {
+        self.process
+    }
+
+    fn query<T: Default>(
+        &self,
+        address: usize,
+        class: MEMORY_INFORMATION_CLASS,
+    ) -> Result<T, NTSTATUS> {
+        let mut value = T::default();
+        self.query_into(
+            address,
+            class,
+            ptr::addr_of_mut!(value).cast(),
+            mem::size_of::<T>(),
+        )?;
+        Ok(value)
+    }
+
+    fn query_into(
+        &self,
+        address: usize,
+        class: MEMORY_INFORMATION_CLASS,
+        buffer: *mut c_void,
+        length: usize,
+    ) -> Result<usize, NTSTATUS> {
+        let mut return_length = 0;
+        let status = unsafe {
+            NtQueryVirtualMemory(
+                self.process,
+                Some(address as *const c_void),
+                INFORMATION_CLASS(class as i32),
+                buffer,
+                length,
+                Some(&mut return_length),
+            )
+        };
+        check(status).map(|()| return_length)
+    }
+}
+
+impl MemoryBackend for NativeMemory {
+    fn basic(&self, address: usize) -> Result<Option<BasicInfo>, NTSTATUS> {
+        match self.query::<MEMORY_BASIC_INFORMATION>(
+            address,
+            MEMORY_INFORMATION_CLASS::MemoryBasicInformation,
+        ) {
+            Ok(info) => Ok(Some(BasicInfo::from(&info))),
+            Err(STATUS_INVALID_PARAMETER) => Ok(None),
+            Err(status) => Err(status),
+        }
+    }
+
+    fn region(&self, address: usize) -> Result<RegionInfo, NTSTATUS> {
+        self.query::<MEMORY_REGION_INFORMATION>(
+            address,
+            MEMORY_INFORMATION_CLASS::MemoryRegionInformation,
+        )
+        .map(|info| RegionInfo::from(&info))
+    }
+
+    fn mapped_filename(&self, address: usize) -> Result<Option<String>, NTSTATUS> {
+        // UNICODE_STRING followed by the name; u64 units keep it aligned.
+        let mut buffer = vec![0u64; 0x110];
+        loop {
+            match self.query_into(
+                address,
+                MEMORY_INFORMATION_CLASS::MemoryMappedFilenameInformation,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() * 8,
+            ) {
+                Ok(_) => break,
+                Err(STATUS_INVALID_ADDRESS) => return Ok(None),
+                Err(STATUS_BUFFER_OVERFLOW | STATUS_INFO_LENGTH_MISMATCH) => {
+                    buffer.resize(buffer.len() * 2, 0)
+                }
+                Err(status) => return Err(status),
+            }
+        }
+        let name = unsafe { from_unicode_string(&*buffer.as_ptr().cast::<UNICODE_STRING>()) };
+        Ok(Some(name))
+    }
+
+    fn image(&self, address: usize) -> Result<ImageInfo, NTSTATUS> {
+        self.query::<MEMORY_IMAGE_INFORMATION>(
+            address,
+            MEMORY_INFORMATION_CLASS::MemoryImageInformation,
+        )
+        .map(|info| ImageInfo::from(&info))
+    }
+
+    fn working_set(&self) -> Result<Vec<WorkingSetPage>, NTSTATUS> {
+        let header = mem::size_of::<usize>();
+        let entry = mem::size_of::<MEMORY_WORKING_SET_BLOCK>();
+        let mut buffer = vec![0usize; 0x1000];
+        loop {
+            match self.query_into(
+                0,
+                MEMORY_INFORMATION_CLASS::MemoryWorkingSetInformation,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() * header,
+            ) {
+                Ok(_) => break,
+                Err(STATUS_INFO_LENGTH_MISMATCH) => {
+                    // NumberOfEntries is filled in; leave room for growth.
+                    let entries = buffer[0] + buffer[0] / 8 + 0x100;
+                    buffer.resize(1 + entries * entry / header, 0);
+                }
+                Err(status) => return Err(status),
+            }
+        }
+        let info = buffer.as_ptr().cast::<MEMORY_WORKING_SET_INFORMATION>();
+        let pages = unsafe {
+            std::slice::from_raw_parts(
+                ptr::addr_of!((*info).WorkingSetInfo).cast::<MEMORY_WORKING_SET_BLOCK>(),
+                (*info).NumberOfEntries,
+            )
+        };
+        Ok(pages.iter().map(WorkingSetPage::from).collect())
+    }
+
+    fn working_set_ex(&self, addresses: &[usize]) -> Result<Vec<PageRecord>, NTSTATUS> {
+        let mut entries: Vec<MEMORY_WORKING_SET_EX_INFORMATION> = addresses
+            .iter()
+            .map(|address| MEMORY_WORKING_SET_EX_INFORMATION {
+                VirtualAddress: *address as *mut c_void,
+                ..Default::default()
+            })
+            .collect();
+        self.query_into(
+            0,
+            MEMORY_INFORMATION_CLASS::MemoryWorkingSetExInformation,
+            entries.as_mut_ptr().cast(),
+            mem::size_of_val(entries.as_slice()),
+        )?;
+        Ok(entries.iter().map(PageRecord::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::System::Memory::{MEM_COMMIT, MEM_IMAGE, PAGE_EXECUTE_READ};
+
+    use super::*;
+
+    #[test]
+    fn decodes_basic_information() {
+        let raw = MEMORY_BASIC_INFORMATION {
+            BaseAddress: 0x7ff6_1000 as *mut c_void,
+            AllocationBase: 0x7ff6_0000 as *mut c_void,
+            AllocationProtect: PAGE_PROTECTION_FLAGS(0x80),
+            RegionSize: 0x3000,
+            State: MEM_COMMIT,
+            Protect: PAGE_EXECUTE_READ,
+            Type: MEM_IMAGE,
+            ..Default::default()
+        };
+        let info = BasicInfo::from(&raw);
+        assert_eq!(
+            (info.base, info.allocation_base),
+            (0x7ff6_1000, 0x7ff6_0000)
+        );
+        assert_eq!(info.end(), 0x7ff6_4000);
+        assert_eq!(
+            (info.state, info.protect, info.kind),
+            (MEM_COMMIT, PAGE_EXECUTE_READ, MEM_IMAGE)
+        );
+    }
+
+    #[test]
+    fn decodes_region_flags() {
+        let mut raw = MEMORY_REGION_INFORMATION {
+            AllocationBase: 0x20000 as *mut c_void,
+            RegionSize: 0x10000,
+            CommitSize: 0x1000,
+            ..Default::default()
+        };
+        let bits = unsafe { raw.Anonymous1.Anonymous1.as_mut() };
+        bits.set_MappedImage(1);
+        bits.set_PageSizeLarge(1);
+        let info = RegionInfo::from(&raw);
+        assert_eq!(
+            info.flags,
+            RegionFlags {
+                mapped_image: true,
+                page_size_large: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            (info.allocation_base, info.size, info.commit_size),
+            (0x20000, 0x10000, 0x1000)
+        );
+    }
+
+    #[test]
+    fn decodes_working_set_blocks() {
+        let mut raw = MEMORY_WORKING_SET_BLOCK::default();
+        raw.set_Protection(4);
+        raw.set_ShareCount(7);
+        raw.set_Shared(1);
+        raw.set_Node(2);
+        raw.set_VirtualPage(0x7ff61);
+        assert_eq!(
+            WorkingSetPage::from(&raw),
+            WorkingSetPage {
+                address: 0x7ff6_1000,
+                protection: 4,
+                share_count: 7,
+                shared: true,
+                node: 2,
+            }
+        );
+        assert_eq!(
+            WorkingSetPage::from(&MEMORY_WORKING_SET_BLOCK::default()),
+            WorkingSetPage::default()
+        );
+    }
+}
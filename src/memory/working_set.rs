@@ -0,0 +1,464 @@
+use std::collections::BTreeMap;
+
+use windows::Win32::{
+    Foundation::{NTSTATUS, STATUS_INVALID_PARAMETER},
+    System::Memory::{MEM_COMMIT, PAGE_PROTECTION_FLAGS},
+};
+
+use super::{MemoryBackend, MemoryRegion, PAGE_SIZE};
+use crate::ntmmapi::{MEMORY_WORKING_SET_EX_INFORMATION, MEMORY_WORKING_SET_EX_LOCATION};
+
+/// Addresses passed to a single `MemoryWorkingSetExInformation` query by
+/// default.
+pub const DEFAULT_WORKING_SET_BATCH: usize = 0x2000;
+
+/// One page as described by `MemoryWorkingSetExInformation`.
+///
+/// Fields only defined for valid pages are zero or `false` when `valid` is
+/// not set, and `page_table` and `modified_list` only apply to invalid pages.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PageRecord {
+    pub address: usize,
+    /// The page is in the working set.
+    pub valid: bool,
+    pub shared: bool,
+    pub shared_original: bool,
+    pub bad: bool,
+    /// Memory priority, 0 to 7, `MEMORY_PRIORITY_NORMAL` being 5.
+    pub priority: u8,
+    pub share_count: u8,
+    pub protection: PAGE_PROTECTION_FLAGS,
+    pub node: u8,
+    pub locked: bool,
+    pub large_page: bool,
+    pub location: MEMORY_WORKING_SET_EX_LOCATION,
+    pub page_table: bool,
+    pub modified_list: bool,
+}
+
+impl PageRecord {
+    /// Decodes the attributes returned for `address`.
+    pub fn from_attributes(address: usize, attributes: u64) -> Self {
+        let mut raw = MEMORY_WORKING_SET_EX_INFORMATION {
+            VirtualAddress: address as *mut _,
+            ..Default::default()
+        };
+        raw.u1.union_field = attributes;
+        Self::from(&raw)
+    }
+}
+
+impl From<&MEMORY_WORKING_SET_EX_INFORMATION> for PageRecord {
+    fn from(value: &MEMORY_WORKING_SET_EX_INFORMATION) -> Self {
+        let block = unsafe { &value.u1.VirtualAttributes.as_ref().Anonymous1 };
+        let valid = unsafe { block.Anonymous1.as_ref() };
+        let address = value.VirtualAddress as usize;
+        if valid.Valid() != 0 {
+            return Self {
+                address,
+                valid: true,
+                shared: valid.Shared() != 0,
+                shared_original: valid.SharedOriginal() != 0,
+                bad: valid.Bad() != 0,
+                priority: valid.Priority() as u8,
+                share_count: valid.ShareCount() as u8,
+                protection: PAGE_PROTECTION_FLAGS(valid.Win32Protection() as u32),
+                node: valid.Node() as u8,
+                locked: valid.Locked() != 0,
+                large_page: valid.LargePage() != 0,
+                location: MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationResident,
+                page_table: false,
+                modified_list: false,
+            };
+        }
+        let invalid = unsafe { block.Invalid.as_ref() };
+        Self {
+            address,
+            valid: false,
+            shared: invalid.Shared() != 0,
+            shared_original: invalid.SharedOriginal() != 0,
+            bad: invalid.Bad() != 0,
+            priority: invalid.Priority() as u8,
+            share_count: 0,
+            protection: PAGE_PROTECTION_FLAGS(0),
+            node: 0,
+            locked: false,
+            large_page: false,
+            location: match invalid.Location() {
+                1 => MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationResident,
+                2 => MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationPagefile,
+                3 => MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationReserved,
+                _ => MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationInvalid,
+            },
+            page_table: invalid.PageTable() != 0,
+            modified_list: invalid.ModifiedList() != 0,
+        }
+    }
+}
+
+/// Issues `MemoryWorkingSetExInformation` queries for page ranges, a batch
+/// of addresses at a time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WorkingSetQuery {
+    batch_size: usize,
+}
+
+impl Default for WorkingSetQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorkingSetQuery {
+    pub fn new() -> Self {
+        Self {
+            batch_size: DEFAULT_WORKING_SET_BATCH,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// One record per page overlapping `start..start + size`. Fails with
+    /// `STATUS_INVALID_PARAMETER` if the range wraps around the address
+    /// space.
+    pub fn range(
+        &self,
+        backend: &impl MemoryBackend,
+        start: usize,
+        size: usize,
+    ) -> Result<Vec<PageRecord>, NTSTATUS> {
+        let first = start / PAGE_SIZE;
+        let end = start.checked_add(size).ok_or(STATUS_INVALID_PARAMETER)?;
+        let last = end.div_ceil(PAGE_SIZE);
+        let addresses: Vec<usize> = (first..last).map(|page| page * PAGE_SIZE).collect();
+        let mut records = Vec::with_capacity(addresses.len());
+        for batch in addresses.chunks(self.batch_size) {
+            records.extend(backend.working_set_ex(batch)?);
+        }
+        Ok(records)
+    }
+
+    /// One record per committed page of `region`.
+    pub fn region(
+        &self,
+        backend: &impl MemoryBackend,
+        region: &MemoryRegion,
+    ) -> Result<Vec<PageRecord>, NTSTATUS> {
+        let mut records = Vec::new();
+        for block in region
+            .blocks
+            .iter()
+            .filter(|block| block.state == MEM_COMMIT)
+        {
+            records.extend(self.range(backend, block.base, block.size)?);
+        }
+        Ok(records)
+    }
+}
+
+/// Totals over a set of [`PageRecord`]s. Sizes are in bytes and only count
+/// valid pages, except `paged_out`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkingSetSummary {
+    pub pages: usize,
+    pub resident: usize,
+    pub private: usize,
+    pub shared: usize,
+    pub locked: usize,
+    pub large_page: usize,
+    pub bad: usize,
+    /// Bytes backed by the pagefile.
+    pub paged_out: usize,
+    /// Resident bytes per NUMA node.
+    pub per_node: BTreeMap<u8, usize>,
+    /// Resident bytes per memory priority.
+    pub per_priority: [usize; 8],
+}
+
+impl WorkingSetSummary {
+    pub fn from_pages<'a>(pages: impl IntoIterator<Item = &'a PageRecord>) -> Self {
+        let mut summary = Self::default();
+        for page in pages {
+            summary.add(page);
+        }
+        summary
+    }
+
+    pub fn add(&mut self, page: &PageRecord) {
+        self.pages += 1;
+        if !page.valid {
+            if page.location == MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationPagefile {
+                self.paged_out += PAGE_SIZE;
+            }
+            return;
+        }
+        self.resident += PAGE_SIZE;
+        if page.shared {
+            self.shared += PAGE_SIZE;
+        } else {
+            self.private += PAGE_SIZE;
+        }
+        if page.locked {
+            self.locked += PAGE_SIZE;
+        }
+        if page.large_page {
+            self.large_page += PAGE_SIZE;
+        }
+        if page.bad {
+            self.bad += PAGE_SIZE;
+        }
+        *self.per_node.entry(page.node).or_default() += PAGE_SIZE;
+        self.per_priority[page.priority as usize & 7] += PAGE_SIZE;
+    }
+
+    /// Summarizes `pages` per region, returning one summary for each of
+    /// `regions` in the same order.
+    pub fn per_region(regions: &[MemoryRegion], pages: &[PageRecord]) -> Vec<Self> {
+        let mut pages = pages.to_vec();
+        pages.sort_unstable_by_key(|page| page.address);
+        regions
+            .iter()
+            .map(|region| {
+                let start = pages.partition_point(|page| page.address < region.base);
+                let end = pages.partition_point(|page| page.address < region.end());
+                Self::from_pages(&pages[start..end])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::System::Memory::{
+        MEM_PRIVATE, MEM_RESERVE, PAGE_EXECUTE_READ, PAGE_READWRITE,
+    };
+
+    use super::*;
+    use crate::memory::{BasicInfo, ImageInfo, MemoryMap, RegionInfo, WorkingSetPage};
+
+    /// The attributes of a valid page with a share count of 2.
+    fn valid(
+        protection: u64,
+        shared: bool,
+        node: u64,
+        locked: bool,
+        large_page: bool,
+        priority: u64,
+    ) -> u64 {
+        1 | (2 << 1)
+            | (protection << 4)
+            | ((shared as u64) << 15)
+            | (node << 16)
+            | ((locked as u64) << 22)
+            | ((large_page as u64) << 23)
+            | (priority << 24)
+    }
+
+    #[derive(Default)]
+    struct Mock {
+        batches: RefCell<Vec<Vec<usize>>>,
+    }
+
+    impl MemoryBackend for Mock {
+        fn basic(&self, _: usize) -> Result<Option<BasicInfo>, NTSTATUS> {
+            Ok(None)
+        }
+
+        fn region(&self, _: usize) -> Result<RegionInfo, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn mapped_filename(&self, _: usize) -> Result<Option<String>, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn image(&self, _: usize) -> Result<ImageInfo, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn working_set(&self) -> Result<Vec<WorkingSetPage>, NTSTATUS> {
+            unimplemented!()
+        }
+
+        fn working_set_ex(&self, addresses: &[usize]) -> Result<Vec<PageRecord>, NTSTATUS> {
+            self.batches.borrow_mut().push(addresses.to_vec());
+            Ok(addresses
+                .iter()
+                .map(|&address| {
+                    let shared = address % 0x2000 == 0;
+                    PageRecord::from_attributes(address, valid(4, shared, 1, false, false, 5))
+                })
+                .collect())
+        }
+    }
+
+    fn private(base: usize, allocation_base: usize, size: usize, committed: bool) -> BasicInfo {
+        BasicInfo {
+            base,
+            allocation_base,
+            allocation_protect: PAGE_READWRITE,
+            size,
+            state: if committed { MEM_COMMIT } else { MEM_RESERVE },
+            protect: if committed {
+                PAGE_READWRITE
+            } else {
+                PAGE_PROTECTION_FLAGS(0)
+            },
+            kind: MEM_PRIVATE,
+        }
+    }
+
+    #[test]
+    fn decodes_valid_pages() {
+        let page = PageRecord::from_attributes(0x1000, valid(0x20, true, 3, true, true, 2));
+        assert_eq!(
+            page,
+            PageRecord {
+                address: 0x1000,
+                valid: true,
+                shared: true,
+                shared_original: false,
+                bad: false,
+                priority: 2,
+                share_count: 2,
+                protection: PAGE_EXECUTE_READ,
+                node: 3,
+                locked: true,
+                large_page: true,
+                location: MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationResident,
+                page_table: false,
+                modified_list: false,
+            }
+        );
+        let page = PageRecord::from_attributes(0, valid(4, false, 0, false, false, 5) | 3 << 30);
+        assert!(page.shared_original && page.bad);
+        // The upper half of the word is reserved.
+        let page = PageRecord::from_attributes(0, valid(4, false, 0, false, false, 5) | !0 << 32);
+        assert_eq!(
+            page,
+            PageRecord::from_attributes(0, valid(4, false, 0, false, false, 5))
+        );
+    }
+
+    #[test]
+    fn decodes_invalid_pages() {
+        let page = PageRecord::from_attributes(0x2000, (2 << 22) | (1 << 27) | (1 << 21));
+        assert!(!page.valid && page.modified_list && page.page_table);
+        assert_eq!(
+            page.location,
+            MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationPagefile
+        );
+        assert_eq!(page.protection, PAGE_PROTECTION_FLAGS(0));
+        assert!(!page.locked && !page.large_page);
+
+        for (location, expected) in [
+            (0, MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationInvalid),
+            (1, MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationResident),
+            (3, MEMORY_WORKING_SET_EX_LOCATION::MemoryLocationReserved),
+        ] {
+            let page = PageRecord::from_attributes(0, location << 22);
+            assert_eq!(page.location, expected);
+        }
+        // Bits only meaningful for valid pages are not reported.
+        let page = PageRecord::from_attributes(0, 0x3f << 16 | 1 << 15);
+        assert_eq!((page.node, page.share_count, page.shared), (0, 0, true));
+    }
+
+    #[test]
+    fn summarizes_pages() {
+        let pages = [
+            PageRecord::from_attributes(0x1000, valid(0x20, true, 3, true, true, 2)),
+            PageRecord::from_attributes(0x2000, (2 << 22) | (1 << 27) | (1 << 21)),
+            PageRecord::from_attributes(0x3000, valid(4, false, 0, false, false, 5)),
+        ];
+        let summary = WorkingSetSummary::from_pages(&pages);
+        assert_eq!(summary.pages, 3);
+        assert_eq!(
+            (summary.resident, summary.shared, summary.private),
+            (0x2000, 0x1000, 0x1000)
+        );
+        assert_eq!(
+            (summary.locked, summary.large_page, summary.paged_out),
+            (0x1000, 0x1000, 0x1000)
+        );
+        assert_eq!(summary.per_node, BTreeMap::from([(0, 0x1000), (3, 0x1000)]));
+        assert_eq!(summary.per_priority, [0, 0, 0x1000, 0, 0, 0x1000, 0, 0]);
+    }
+
+    #[test]
+    fn queries_ranges_in_batches() {
+        let mock = Mock::default();
+        let records = WorkingSetQuery::new()
+            .with_batch_size(3)
+            .range(&mock, 0x10800, 0x7000)
+            .unwrap();
+        assert_eq!(records.len(), 8);
+        assert_eq!(records[0].address, 0x10000);
+        assert_eq!(records[7].address, 0x17000);
+        let sizes = mock
+            .batches
+            .borrow()
+            .iter()
+            .map(Vec::len)
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [3, 3, 2]);
+
+        assert!(
+            WorkingSetQuery::new()
+                .range(&mock, 0x10000, 0)
+                .unwrap()
+                .is_empty()
+        );
+        let top = usize::MAX - PAGE_SIZE + 1;
+        assert_eq!(
+            WorkingSetQuery::new()
+                .range(&mock, top, PAGE_SIZE)
+                .unwrap_err(),
+            STATUS_INVALID_PARAMETER
+        );
+        assert_eq!(
+            WorkingSetQuery::new()
+                .range(&mock, top, usize::MAX)
+                .unwrap_err(),
+            STATUS_INVALID_PARAMETER
+        );
+    }
+
+    #[test]
+    fn summarizes_per_region() {
+        let map = MemoryMap::from_blocks([
+            private(0x10000, 0x10000, 0x4000, true),
+            private(0x14000, 0x10000, 0x4000, false),
+            private(0x18000, 0x18000, 0x1000, true),
+            private(0x30000, 0x30000, 0x2000, true),
+        ]);
+        let mock = Mock::default();
+        assert_eq!(
+            WorkingSetQuery::new()
+                .region(&mock, &map.regions[0])
+                .unwrap()
+                .len(),
+            4
+        );
+
+        // Pages out of order, one outside every region.
+        let mut pages = WorkingSetQuery::new()
+            .range(&mock, 0x10000, 0x9000)
+            .unwrap();
+        pages.push(PageRecord::from_attributes(
+            0x20000,
+            valid(4, false, 0, false, false, 5),
+        ));
+        pages.reverse();
+        let summaries = WorkingSetSummary::per_region(&map.regions, &pages);
+        assert_eq!(summaries.len(), 3);
+        assert_eq!((summaries[0].pages, summaries[0].shared), (8, 0x4000));
+        assert_eq!((summaries[1].pages, summaries[1].resident), (1, 0x1000));
+        assert_eq!(summaries[2], WorkingSetSummary::default());
+    }
+}
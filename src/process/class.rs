@@ -0,0 +1,1504 @@
+use std::{fmt, mem};
+
+use windows::{
+    Wdk::System::Threading::{PROCESSINFOCLASS, THREADINFOCLASS},
+    Win32::{
+        Foundation::{HANDLE, NTSTATUS, STATUS_PROCESS_IN_JOB, STATUS_PROCESS_NOT_IN_JOB},
+        System::Threading::{
+            PROCESS_ACCESS_RIGHTS, PROCESS_QUERY_INFORMATION, PROCESS_QUERY_LIMITED_INFORMATION,
+            PROCESS_SET_INFORMATION, THREAD_ACCESS_RIGHTS, THREAD_GET_CONTEXT,
+            THREAD_QUERY_INFORMATION, THREAD_QUERY_LIMITED_INFORMATION, THREAD_SET_INFORMATION,
+            THREAD_SET_LIMITED_INFORMATION,
+        },
+    },
+};
+
+use super::{
+    MitigationPolicy, PolicyState, ProcessInfo, SetProcessInfo, SetThreadInfo, ThreadInfo,
+};
+use crate::{
+    buffer::{DecodeError, Reader, Writer},
+    ntpsapi::{
+        NtIsProcessInJob, PROCESS_HANDLE_TRACE_TYPE_BADREF, PROCESS_HANDLE_TRACE_TYPE_CLOSE,
+        PROCESS_HANDLE_TRACE_TYPE_OPEN,
+    },
+    system::{GroupAffinity, InputBuffer, ProcessorNumber, QueryInput, Sizing},
+};
+
+/// The names of the `PROCESSINFOCLASS` values, indexed by value.
+pub const PROCESS_INFO_CLASSES: &[&str] = &[
+    "ProcessBasicInformation",
+    "ProcessQuotaLimits",
+    "ProcessIoCounters",
+    "ProcessVmCounters",
+    "ProcessTimes",
+    "ProcessBasePriority",
+    "ProcessRaisePriority",
+    "ProcessDebugPort",
+    "ProcessExceptionPort",
+    "ProcessAccessToken",
+    "ProcessLdtInformation",
+    "ProcessLdtSize",
+    "ProcessDefaultHardErrorMode",
+    "ProcessIoPortHandlers",
+    "ProcessPooledUsageAndLimits",
+    "ProcessWorkingSetWatch",
+    "ProcessUserModeIOPL",
+    "ProcessEnableAlignmentFaultFixup",
+    "ProcessPriorityClass",
+    "ProcessWx86Information",
+    "ProcessHandleCount",
+    "ProcessAffinityMask",
+    "ProcessPriorityBoost",
+    "ProcessDeviceMap",
+    "ProcessSessionInformation",
+    "ProcessForegroundInformation",
+    "ProcessWow64Information",
+    "ProcessImageFileName",
+    "ProcessLUIDDeviceMapsEnabled",
+    "ProcessBreakOnTermination",
+    "ProcessDebugObjectHandle",
+    "ProcessDebugFlags",
+    "ProcessHandleTracing",
+    "ProcessIoPriority",
+    "ProcessExecuteFlags",
+    "ProcessTlsInformation",
+    "ProcessCookie",
+    "ProcessImageInformation",
+    "ProcessCycleTime",
+    "ProcessPagePriority",
+    "ProcessInstrumentationCallback",
+    "ProcessThreadStackAllocation",
+    "ProcessWorkingSetWatchEx",
+    "ProcessImageFileNameWin32",
+    "ProcessImageFileMapping",
+    "ProcessAffinityUpdateMode",
+    "ProcessMemoryAllocationMode",
+    "ProcessGroupInformation",
+    "ProcessTokenVirtualizationEnabled",
+    "ProcessConsoleHostProcess",
+    "ProcessWindowInformation",
+    "ProcessHandleInformation",
+    "ProcessMitigationPolicy",
+    "ProcessDynamicFunctionTableInformation",
+    "ProcessHandleCheckingMode",
+    "ProcessKeepAliveCount",
+    "ProcessRevokeFileHandles",
+    "ProcessWorkingSetControl",
+    "ProcessHandleTable",
+    "ProcessCheckStackExtentsMode",
+    "ProcessCommandLineInformation",
+    "ProcessProtectionInformation",
+    "ProcessMemoryExhaustion",
+    "ProcessFaultInformation",
+    "ProcessTelemetryIdInformation",
+    "ProcessCommitReleaseInformation",
+    "ProcessDefaultCpuSetsInformation",
+    "ProcessAllowedCpuSetsInformation",
+    "ProcessSubsystemProcess",
+    "ProcessJobMemoryInformation",
+    "ProcessInPrivate",
+    "ProcessRaiseUMExceptionOnInvalidHandleClose",
+    "ProcessIumChallengeResponse",
+    "ProcessChildProcessInformation",
+    "ProcessHighGraphicsPriorityInformation",
+    "ProcessSubsystemInformation",
+    "ProcessEnergyValues",
+    "ProcessPowerThrottlingState",
+    "ProcessReserved3Information",
+    "ProcessWin32kSyscallFilterInformation",
+    "ProcessDisableSystemAllowedCpuSets",
+    "ProcessWakeInformation",
+    "ProcessEnergyTrackingState",
+    "ProcessManageWritesToExecutableMemory",
+    "ProcessCaptureTrustletLiveDump",
+    "ProcessTelemetryCoverage",
+    "ProcessEnclaveInformation",
+    "ProcessEnableReadWriteVmLogging",
+    "ProcessUptimeInformation",
+    "ProcessImageSection",
+    "ProcessDebugAuthInformation",
+    "ProcessSystemResourceManagement",
+    "ProcessSequenceNumber",
+    "ProcessLoaderDetour",
+    "ProcessSecurityDomainInformation",
+    "ProcessCombineSecurityDomainsInformation",
+    "ProcessEnableLogging",
+    "ProcessLeapSecondInformation",
+    "ProcessFiberShadowStackAllocation",
+    "ProcessFreeFiberShadowStackAllocation",
+    "ProcessAltSystemCallInformation",
+    "ProcessDynamicEHContinuationTargets",
+    "ProcessDynamicEnforcedCetCompatibleRanges",
+    "ProcessCreateStateChange",
+    "ProcessApplyStateChange",
+    "ProcessEnableOptionalXStateFeatures",
+    "ProcessAltPrefetchParam",
+    "ProcessAssignCpuPartitions",
+    "ProcessPriorityClassEx",
+    "ProcessMembershipInformation",
+    "ProcessEffectiveIoPriority",
+    "ProcessEffectivePagePriority",
+    "ProcessSchedulerSharedData",
+    "ProcessSlistRollbackInformation",
+    "ProcessNetworkIoCounters",
+    "ProcessFindFirstThreadByTcbValidation",
+    "ProcessEnclaveAddressSpaceRestriction",
+    "ProcessAvailableCpus",
+];
+
+/// The names of the `THREADINFOCLASS` values, indexed by value.
+pub const THREAD_INFO_CLASSES: &[&str] = &[
+    "ThreadBasicInformation",
+    "ThreadTimes",
+    "ThreadPriority",
+    "ThreadBasePriority",
+    "ThreadAffinityMask",
+    "ThreadImpersonationToken",
+    "ThreadDescriptorTableEntry",
+    "ThreadEnableAlignmentFaultFixup",
+    "ThreadEventPair",
+    "ThreadQuerySetWin32StartAddress",
+    "ThreadZeroTlsCell",
+    "ThreadPerformanceCount",
+    "ThreadAmILastThread",
+    "ThreadIdealProcessor",
+    "ThreadPriorityBoost",
+    "ThreadSetTlsArrayAddress",
+    "ThreadIsIoPending",
+    "ThreadHideFromDebugger",
+    "ThreadBreakOnTermination",
+    "ThreadSwitchLegacyState",
+    "ThreadIsTerminated",
+    "ThreadLastSystemCall",
+    "ThreadIoPriority",
+    "ThreadCycleTime",
+    "ThreadPagePriority",
+    "ThreadActualBasePriority",
+    "ThreadTebInformation",
+    "ThreadCSwitchMon",
+    "ThreadCSwitchPmu",
+    "ThreadWow64Context",
+    "ThreadGroupInformation",
+    "ThreadUmsInformation",
+    "ThreadCounterProfiling",
+    "ThreadIdealProcessorEx",
+    "ThreadCpuAccountingInformation",
+    "ThreadSuspendCount",
+    "ThreadHeterogeneousCpuPolicy",
+    "ThreadContainerId",
+    "ThreadNameInformation",
+    "ThreadSelectedCpuSets",
+    "ThreadSystemThreadInformation",
+    "ThreadActualGroupAffinity",
+    "ThreadDynamicCodePolicyInfo",
+    "ThreadExplicitCaseSensitivity",
+    "ThreadWorkOnBehalfTicket",
+    "ThreadSubsystemInformation",
+    "ThreadDbgkWerReportActive",
+    "ThreadAttachContainer",
+    "ThreadManageWritesToExecutableMemory",
+    "ThreadPowerThrottlingState",
+    "ThreadWorkloadClass",
+    "ThreadCreateStateChange",
+    "ThreadApplyStateChange",
+    "ThreadStrongerBadHandleChecks",
+    "ThreadEffectiveIoPriority",
+    "ThreadEffectivePagePriority",
+    "ThreadUpdateLockOwnership",
+    "ThreadSchedulerSharedDataSlot",
+    "ThreadTebInformationAtomic",
+    "ThreadIndexInformation",
+];
+
+pub fn process_class_name(class: PROCESSINFOCLASS) -> Option<&'static str> {
+    usize::try_from(class.0)
+        .ok()
+        .and_then(|index| PROCESS_INFO_CLASSES.get(index).copied())
+}
+
+pub fn process_class_from_name(name: &str) -> Option<PROCESSINFOCLASS> {
+    PROCESS_INFO_CLASSES
+        .iter()
+        .position(|class| *class == name)
+        .map(|index| PROCESSINFOCLASS(index as i32))
+}
+
+pub fn thread_class_name(class: THREADINFOCLASS) -> Option<&'static str> {
+    usize::try_from(class.0)
+        .ok()
+        .and_then(|index| THREAD_INFO_CLASSES.get(index).copied())
+}
+
+pub fn thread_class_from_name(name: &str) -> Option<THREADINFOCLASS> {
+    THREAD_INFO_CLASSES
+        .iter()
+        .position(|class| *class == name)
+        .map(|index| THREADINFOCLASS(index as i32))
+}
+
+/// A value of a fixed size in a class buffer.
+trait Fixed: Sized {
+    const SIZE: usize;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError>;
+}
+
+/// A value that can also be written to a class buffer.
+trait Encode {
+    fn write(&self, writer: &mut Writer);
+}
+
+impl Fixed for u32 {
+    const SIZE: usize = 4;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        reader.u32()
+    }
+}
+
+impl Encode for u32 {
+    fn write(&self, writer: &mut Writer) {
+        writer.u32(*self);
+    }
+}
+
+impl Fixed for i32 {
+    const SIZE: usize = 4;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        reader.i32()
+    }
+}
+
+impl Encode for i32 {
+    fn write(&self, writer: &mut Writer) {
+        writer.u32(*self as u32);
+    }
+}
+
+impl Fixed for u64 {
+    const SIZE: usize = 8;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        reader.u64()
+    }
+}
+
+impl Fixed for usize {
+    const SIZE: usize = mem::size_of::<usize>();
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        reader.usize()
+    }
+}
+
+/// A `BOOLEAN` widened to a `ULONG`, as the flag classes take it.
+impl Fixed for bool {
+    const SIZE: usize = 4;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(reader.u32()? != 0)
+    }
+}
+
+impl Encode for bool {
+    fn write(&self, writer: &mut Writer) {
+        writer.u32(*self as u32);
+    }
+}
+
+impl Fixed for ProcessorNumber {
+    const SIZE: usize = 4;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let group = reader.u16()?;
+        let number = reader.u8()?;
+        reader.skip(1)?;
+        Ok(Self { group, number })
+    }
+}
+
+impl Fixed for GroupAffinity {
+    const SIZE: usize = mem::size_of::<usize>() + 8;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let mask = reader.usize()? as u64;
+        let group = reader.u16()?;
+        reader.skip(6)?;
+        Ok(Self { group, mask })
+    }
+}
+
+/// `PROCESS_BASIC_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct ProcessBasic {
+    pub exit_status: i32,
+    pub peb: usize,
+    pub affinity_mask: usize,
+    pub base_priority: i32,
+    pub process_id: usize,
+    pub parent_process_id: usize,
+}
+
+impl Fixed for ProcessBasic {
+    const SIZE: usize = 6 * mem::size_of::<usize>();
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let exit_status = reader.i32()?;
+        reader.align(mem::size_of::<usize>())?;
+        let peb = reader.usize()?;
+        let affinity_mask = reader.usize()?;
+        let base_priority = reader.i32()?;
+        reader.align(mem::size_of::<usize>())?;
+        Ok(Self {
+            exit_status,
+            peb,
+            affinity_mask,
+            base_priority,
+            process_id: reader.usize()?,
+            parent_process_id: reader.usize()?,
+        })
+    }
+}
+
+/// `THREAD_BASIC_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct ThreadBasic {
+    pub exit_status: i32,
+    pub teb: usize,
+    pub process_id: usize,
+    pub thread_id: usize,
+    pub affinity_mask: usize,
+    pub priority: i32,
+    pub base_priority: i32,
+}
+
+impl Fixed for ThreadBasic {
+    const SIZE: usize =
+        (4 * mem::size_of::<usize>() + 12).next_multiple_of(mem::size_of::<usize>());
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let exit_status = reader.i32()?;
+        reader.align(mem::size_of::<usize>())?;
+        Ok(Self {
+            exit_status,
+            teb: reader.usize()?,
+            process_id: reader.usize()?,
+            thread_id: reader.usize()?,
+            affinity_mask: reader.usize()?,
+            priority: reader.i32()?,
+            base_priority: reader.i32()?,
+        })
+    }
+}
+
+/// `KERNEL_USER_TIMES`, in 100ns units; the creation and exit times are
+/// system times.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct Times {
+    pub creation: i64,
+    pub exit: i64,
+    pub kernel: i64,
+    pub user: i64,
+}
+
+impl Fixed for Times {
+    const SIZE: usize = 32;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            creation: reader.i64()?,
+            exit: reader.i64()?,
+            kernel: reader.i64()?,
+            user: reader.i64()?,
+        })
+    }
+}
+
+/// `PROCESS_HANDLE_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct HandleCount {
+    pub handles: u32,
+    pub high_watermark: u32,
+}
+
+impl Fixed for HandleCount {
+    const SIZE: usize = 8;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            handles: reader.u32()?,
+            high_watermark: reader.u32()?,
+        })
+    }
+}
+
+/// `PROCESS_CYCLE_TIME_INFORMATION` and `THREAD_CYCLE_TIME_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct CycleTime {
+    pub accumulated: u64,
+    pub current: u64,
+}
+
+impl Fixed for CycleTime {
+    const SIZE: usize = 16;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            accumulated: reader.u64()?,
+            current: reader.u64()?,
+        })
+    }
+}
+
+/// `PS_PROTECTION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct Protection {
+    /// A `PS_PROTECTED_TYPE`: none, light or full.
+    pub kind: u8,
+    pub audit: bool,
+    /// A `PS_PROTECTED_SIGNER`.
+    pub signer: u8,
+}
+
+impl Protection {
+    pub const fn from_raw(raw: u8) -> Self {
+        Self {
+            kind: raw & 0x7,
+            audit: raw & 0x8 != 0,
+            signer: raw >> 4,
+        }
+    }
+
+    pub const fn is_protected(&self) -> bool {
+        self.kind != 0
+    }
+
+    pub fn signer_name(&self) -> Option<&'static str> {
+        [
+            "None",
+            "Authenticode",
+            "CodeGen",
+            "Antimalware",
+            "Lsa",
+            "Windows",
+            "WinTcb",
+            "WinSystem",
+            "App",
+        ]
+        .get(self.signer as usize)
+        .copied()
+    }
+}
+
+/// Formats as e.g. `WinTcb-Light`, or `None`.
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            0 => return f.write_str("None"),
+            1 => "Light",
+            2 => "Full",
+            _ => "Unknown",
+        };
+        match self.signer_name() {
+            Some(signer) => write!(f, "{signer}-{kind}"),
+            None => write!(f, "{}-{kind}", self.signer),
+        }
+    }
+}
+
+impl Fixed for Protection {
+    const SIZE: usize = 1;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Self::from_raw(reader.u8()?))
+    }
+}
+
+/// `THREAD_LAST_SYSCALL_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct LastSystemCall {
+    pub first_argument: usize,
+    pub number: u16,
+    pub wait_time: u64,
+}
+
+impl Fixed for LastSystemCall {
+    const SIZE: usize = 2 * mem::size_of::<usize>() + 8;
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let first_argument = reader.usize()?;
+        let number = reader.u16()?;
+        reader.align(8)?;
+        Ok(Self {
+            first_argument,
+            number,
+            wait_time: reader.u64()?,
+        })
+    }
+}
+
+/// Declares classes decoding to one fixed-size value.
+macro_rules! fixed_classes {
+    ($trait:ident, $class:ident, $access:ident:
+        $($(#[$meta:meta])* $name:ident = $value:literal, $required:ident => $output:ty;)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl $trait for $name {
+            const CLASS: $class = $class($value);
+            const SIZING: Sizing = Sizing::Fixed(<$output as Fixed>::SIZE);
+            const ACCESS: $access = $required;
+            type Input = ();
+            type Output = $output;
+
+            fn decode(bytes: &[u8]) -> Result<$output, DecodeError> {
+                <$output as Fixed>::read(&mut Reader::new(bytes))
+            }
+        }
+    )*};
+}
+
+/// Makes query classes settable with the value they decode to.
+macro_rules! settable_classes {
+    ($trait:ident, $query:ident, $class:ident, $access:ident:
+        $($name:ident, $required:ident => $value:ty;)*) => {$(
+        impl $trait for $name {
+            const CLASS: $class = <$name as $query>::CLASS;
+            const ACCESS: $access = $required;
+            type Value = $value;
+
+            fn encode(value: &$value) -> Vec<u8> {
+                let mut writer = Writer::default();
+                value.write(&mut writer);
+                writer.into_inner()
+            }
+        }
+    )*};
+}
+
+fixed_classes! {
+    ProcessInfo, PROCESSINFOCLASS, PROCESS_ACCESS_RIGHTS:
+    ProcessBasicInformation = 0, PROCESS_QUERY_LIMITED_INFORMATION => ProcessBasic;
+    ProcessTimes = 4, PROCESS_QUERY_LIMITED_INFORMATION => Times;
+    ProcessHandleCount = 20, PROCESS_QUERY_LIMITED_INFORMATION => HandleCount;
+    /// Whether boosts are disabled.
+    ProcessPriorityBoost = 22, PROCESS_QUERY_LIMITED_INFORMATION => bool;
+    ProcessSessionInformation = 24, PROCESS_QUERY_LIMITED_INFORMATION => u32;
+    /// The address of the 32-bit PEB of a WOW64 process, zero otherwise.
+    ProcessWow64Information = 26, PROCESS_QUERY_LIMITED_INFORMATION => usize;
+    /// Whether ending the process bugchecks the system; setting it needs
+    /// `SeDebugPrivilege`.
+    ProcessBreakOnTermination = 29, PROCESS_QUERY_INFORMATION => bool;
+    /// An `IO_PRIORITY_HINT`.
+    ProcessIoPriority = 33, PROCESS_QUERY_LIMITED_INFORMATION => u32;
+    ProcessCycleTime = 38, PROCESS_QUERY_LIMITED_INFORMATION => CycleTime;
+    ProcessProtectionInformation = 61, PROCESS_QUERY_LIMITED_INFORMATION => Protection;
+    /// A `SUBSYSTEM_INFORMATION_TYPE`: Win32 or WSL.
+    ProcessSubsystemInformation = 75, PROCESS_QUERY_LIMITED_INFORMATION => u32;
+    /// The boot-unique sequence number of the process.
+    ProcessSequenceNumber = 92, PROCESS_QUERY_LIMITED_INFORMATION => u64;
+    /// The ID of the server silo the process belongs to, zero for the host.
+    ProcessMembershipInformation = 109, PROCESS_QUERY_LIMITED_INFORMATION => u32;
+}
+
+fixed_classes! {
+    ThreadInfo, THREADINFOCLASS, THREAD_ACCESS_RIGHTS:
+    ThreadBasicInformation = 0, THREAD_QUERY_LIMITED_INFORMATION => ThreadBasic;
+    ThreadTimes = 1, THREAD_QUERY_LIMITED_INFORMATION => Times;
+    ThreadQuerySetWin32StartAddress = 9, THREAD_QUERY_INFORMATION => usize;
+    ThreadIsIoPending = 16, THREAD_QUERY_INFORMATION => bool;
+    ThreadBreakOnTermination = 18, THREAD_QUERY_INFORMATION => bool;
+    ThreadIsTerminated = 20, THREAD_QUERY_LIMITED_INFORMATION => bool;
+    /// Only available while the thread is suspended or waiting.
+    ThreadLastSystemCall = 21, THREAD_GET_CONTEXT => LastSystemCall;
+    /// An `IO_PRIORITY_HINT`.
+    ThreadIoPriority = 22, THREAD_QUERY_LIMITED_INFORMATION => u32;
+    ThreadCycleTime = 23, THREAD_QUERY_LIMITED_INFORMATION => CycleTime;
+    ThreadGroupInformation = 30, THREAD_QUERY_LIMITED_INFORMATION => GroupAffinity;
+    ThreadIdealProcessorEx = 33, THREAD_QUERY_LIMITED_INFORMATION => ProcessorNumber;
+    ThreadSuspendCount = 35, THREAD_QUERY_LIMITED_INFORMATION => u32;
+}
+
+settable_classes! {
+    SetProcessInfo, ProcessInfo, PROCESSINFOCLASS, PROCESS_ACCESS_RIGHTS:
+    ProcessPriorityBoost, PROCESS_SET_INFORMATION => bool;
+    ProcessBreakOnTermination, PROCESS_SET_INFORMATION => bool;
+    ProcessIoPriority, PROCESS_SET_INFORMATION => u32;
+}
+
+settable_classes! {
+    SetThreadInfo, ThreadInfo, THREADINFOCLASS, THREAD_ACCESS_RIGHTS:
+    ThreadBreakOnTermination, THREAD_SET_INFORMATION => bool;
+    ThreadIoPriority, THREAD_SET_LIMITED_INFORMATION => u32;
+}
+
+/// Declares set-only thread classes taking one value.
+macro_rules! set_thread_classes {
+    ($($(#[$meta:meta])* $name:ident = $value:literal, $required:ident => $input:ty;)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl SetThreadInfo for $name {
+            const CLASS: THREADINFOCLASS = THREADINFOCLASS($value);
+            const ACCESS: THREAD_ACCESS_RIGHTS = $required;
+            type Value = $input;
+
+            fn encode(value: &$input) -> Vec<u8> {
+                let mut writer = Writer::default();
+                value.write(&mut writer);
+                writer.into_inner()
+            }
+        }
+    )*};
+}
+
+set_thread_classes! {
+    /// A `KPRIORITY`, the absolute priority.
+    ThreadPriority = 2, THREAD_SET_LIMITED_INFORMATION => i32;
+    /// The priority relative to the process base priority.
+    ThreadBasePriority = 3, THREAD_SET_LIMITED_INFORMATION => i32;
+}
+
+/// Takes no value: hides the thread from debuggers until it exits.
+#[derive(Debug)]
+pub enum ThreadHideFromDebugger {}
+
+impl SetThreadInfo for ThreadHideFromDebugger {
+    const CLASS: THREADINFOCLASS = THREADINFOCLASS(17);
+    const ACCESS: THREAD_ACCESS_RIGHTS = THREAD_SET_INFORMATION;
+    type Value = ();
+
+    fn encode(_: &()) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+/// The offset of `UNICODE_STRING::Buffer`.
+const UNICODE_STRING_BUFFER: usize = mem::size_of::<usize>();
+
+/// Decodes a `UNICODE_STRING` whose buffer pointer has been turned into an
+/// offset from the start of `bytes`.
+fn decode_unicode_string(bytes: &[u8]) -> Result<String, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let length = reader.u16()? as usize;
+    reader.seek(UNICODE_STRING_BUFFER)?;
+    let offset = reader.usize()?;
+    if length == 0 {
+        return Ok(String::new());
+    }
+    reader.seek(offset)?;
+    reader.utf16(length / 2)
+}
+
+/// Encodes a `UNICODE_STRING` followed by its characters, with the buffer
+/// pointer as an offset.
+fn encode_unicode_string(value: &str) -> Vec<u8> {
+    let units = value.encode_utf16().collect::<Vec<_>>();
+    let length = (units.len() * 2) as u16;
+    let mut writer = Writer::default();
+    writer
+        .u16(length)
+        .u16(length)
+        .align(mem::size_of::<usize>());
+    let header = 2 * mem::size_of::<usize>();
+    writer.bytes(&header.to_le_bytes());
+    for unit in units {
+        writer.u16(unit);
+    }
+    writer.into_inner()
+}
+
+/// Declares classes decoding to a `UNICODE_STRING` in the output buffer.
+macro_rules! string_classes {
+    ($trait:ident, $class:ident, $access:ident:
+        $($(#[$meta:meta])* $name:ident = $value:literal, $required:ident;)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl $trait for $name {
+            const CLASS: $class = $class($value);
+            const SIZING: Sizing = Sizing::Variable { initial: 0x210 };
+            const ACCESS: $access = $required;
+            const RELOCATIONS: &'static [usize] = &[UNICODE_STRING_BUFFER];
+            type Input = ();
+            type Output = String;
+
+            fn decode(bytes: &[u8]) -> Result<String, DecodeError> {
+                decode_unicode_string(bytes)
+            }
+        }
+    )*};
+}
+
+string_classes! {
+    ProcessInfo, PROCESSINFOCLASS, PROCESS_ACCESS_RIGHTS:
+    /// The image path in NT form, e.g. `\Device\HarddiskVolume3\Windows\explorer.exe`.
+    ProcessImageFileName = 27, PROCESS_QUERY_LIMITED_INFORMATION;
+    /// The image path in Win32 form.
+    ProcessImageFileNameWin32 = 43, PROCESS_QUERY_LIMITED_INFORMATION;
+    ProcessCommandLineInformation = 60, PROCESS_QUERY_LIMITED_INFORMATION;
+}
+
+string_classes! {
+    ThreadInfo, THREADINFOCLASS, THREAD_ACCESS_RIGHTS:
+    /// The thread description.
+    ThreadNameInformation = 38, THREAD_QUERY_LIMITED_INFORMATION;
+}
+
+impl SetThreadInfo for ThreadNameInformation {
+    const CLASS: THREADINFOCLASS = <Self as ThreadInfo>::CLASS;
+    const ACCESS: THREAD_ACCESS_RIGHTS = THREAD_SET_LIMITED_INFORMATION;
+    const RELOCATIONS: &'static [usize] = &[UNICODE_STRING_BUFFER];
+    type Value = str;
+
+    fn encode(value: &str) -> Vec<u8> {
+        encode_unicode_string(value)
+    }
+}
+
+/// Selects one policy.
+impl QueryInput for MitigationPolicy {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::InPlace(PolicyState::new(*self).to_bytes().to_vec())
+    }
+}
+
+/// Takes the policy to read.
+#[derive(Debug)]
+pub enum ProcessMitigationPolicy {}
+
+impl ProcessInfo for ProcessMitigationPolicy {
+    const CLASS: PROCESSINFOCLASS = PROCESSINFOCLASS(52);
+    const SIZING: Sizing = Sizing::Fixed(super::POLICY_INFORMATION_SIZE);
+    const ACCESS: PROCESS_ACCESS_RIGHTS = PROCESS_QUERY_LIMITED_INFORMATION;
+    type Input = MitigationPolicy;
+    type Output = PolicyState;
+
+    fn decode(bytes: &[u8]) -> Result<PolicyState, DecodeError> {
+        PolicyState::parse(bytes)
+    }
+}
+
+impl SetProcessInfo for ProcessMitigationPolicy {
+    const CLASS: PROCESSINFOCLASS = <Self as ProcessInfo>::CLASS;
+    const ACCESS: PROCESS_ACCESS_RIGHTS = PROCESS_SET_INFORMATION;
+    type Value = PolicyState;
+
+    fn encode(value: &PolicyState) -> Vec<u8> {
+        value.to_bytes().to_vec()
+    }
+}
+
+/// The number of frames recorded with each handle trace.
+pub const HANDLE_TRACE_FRAMES: usize = 16;
+
+/// `PROCESS_HANDLE_TRACE_TYPE_*`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum HandleTraceKind {
+    Open,
+    Close,
+    /// A use of an invalid handle.
+    BadReference,
+    Other(u32),
+}
+
+impl HandleTraceKind {
+    pub const fn from_raw(raw: u32) -> Self {
+        match raw {
+            PROCESS_HANDLE_TRACE_TYPE_OPEN => Self::Open,
+            PROCESS_HANDLE_TRACE_TYPE_CLOSE => Self::Close,
+            PROCESS_HANDLE_TRACE_TYPE_BADREF => Self::BadReference,
+            raw => Self::Other(raw),
+        }
+    }
+}
+
+/// `PROCESS_HANDLE_TRACING_ENTRY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandleTraceEntry {
+    pub handle: HANDLE,
+    pub process_id: usize,
+    pub thread_id: usize,
+    pub kind: HandleTraceKind,
+    /// The return addresses, innermost first, without the unused slots.
+    pub stack: Vec<usize>,
+}
+
+impl HandleTraceEntry {
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let handle = HANDLE(reader.usize()? as isize);
+        let process_id = reader.usize()?;
+        let thread_id = reader.usize()?;
+        let kind = HandleTraceKind::from_raw(reader.u32()?);
+        reader.align(mem::size_of::<usize>())?;
+        let mut stack = Vec::with_capacity(HANDLE_TRACE_FRAMES);
+        for _ in 0..HANDLE_TRACE_FRAMES {
+            let frame = reader.usize()?;
+            if frame != 0 {
+                stack.push(frame);
+            }
+        }
+        Ok(Self {
+            handle,
+            process_id,
+            thread_id,
+            kind,
+            stack,
+        })
+    }
+}
+
+/// Decodes a `PROCESS_HANDLE_TRACING_QUERY`, newest trace first.
+pub fn parse_handle_traces(bytes: &[u8]) -> Result<Vec<HandleTraceEntry>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    reader.usize()?;
+    let count = reader.u32()? as usize;
+    reader.align(mem::size_of::<usize>())?;
+    let mut entries = Vec::with_capacity(count.min(0x1_0000));
+    for _ in 0..count {
+        entries.push(HandleTraceEntry::read(&mut reader)?);
+    }
+    Ok(entries)
+}
+
+/// Takes the handle to return the traces of, or a null handle for all of
+/// them. Fails with `STATUS_INVALID_PARAMETER` unless tracing is enabled.
+#[derive(Debug)]
+pub enum ProcessHandleTracing {}
+
+impl ProcessInfo for ProcessHandleTracing {
+    const CLASS: PROCESSINFOCLASS = PROCESSINFOCLASS(32);
+    const SIZING: Sizing = Sizing::Variable { initial: 0x1_0000 };
+    const ACCESS: PROCESS_ACCESS_RIGHTS = PROCESS_QUERY_INFORMATION;
+    type Input = HANDLE;
+    type Output = Vec<HandleTraceEntry>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<HandleTraceEntry>, DecodeError> {
+        parse_handle_traces(bytes)
+    }
+}
+
+/// The value set through `ProcessHandleTracing`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum HandleTracing {
+    /// Stops tracing and frees the trace buffer.
+    #[default]
+    Disable,
+    /// Starts tracing with the default number of slots.
+    Enable,
+    /// Starts tracing into a ring of this many slots, up to
+    /// `PROCESS_HANDLE_TRACING_MAX_SLOTS`.
+    EnableWithSlots(u32),
+}
+
+impl SetProcessInfo for ProcessHandleTracing {
+    const CLASS: PROCESSINFOCLASS = <Self as ProcessInfo>::CLASS;
+    const ACCESS: PROCESS_ACCESS_RIGHTS = PROCESS_SET_INFORMATION;
+    type Value = HandleTracing;
+
+    fn encode(value: &HandleTracing) -> Vec<u8> {
+        let mut writer = Writer::default();
+        match value {
+            HandleTracing::Disable => {}
+            HandleTracing::Enable => {
+                writer.u32(0);
+            }
+            HandleTracing::EnableWithSlots(slots) => {
+                writer.u32(0).u32(*slots);
+            }
+        }
+        writer.into_inner()
+    }
+}
+
+/// Whether `process` is in a job, or in `job` when one is given.
+pub fn process_in_job(process: HANDLE, job: Option<HANDLE>) -> Result<bool, NTSTATUS> {
+    match unsafe { NtIsProcessInJob(process, job.unwrap_or_default()) } {
+        STATUS_PROCESS_IN_JOB => Ok(true),
+        STATUS_PROCESS_NOT_IN_JOB => Ok(false),
+        status => Err(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::BTreeMap};
+
+    use windows::Win32::{
+        Foundation::{STATUS_INFO_LENGTH_MISMATCH, STATUS_INVALID_INFO_CLASS},
+        System::Threading::THREAD_SET_LIMITED_INFORMATION,
+    };
+
+    use super::{
+        super::{POLICY_INFORMATION_SIZE, ProcessBackend},
+        *,
+    };
+    use crate::system::{Error, QueryFailure};
+
+    /// A query as the backend saw it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Query {
+        class: &'static str,
+        length: usize,
+        start: Vec<u8>,
+    }
+
+    /// Answers classes by name: the string classes with a `UNICODE_STRING`
+    /// pointing into the output buffer, the others with fixed bytes.
+    /// Buffers too small for the answer fail with
+    /// `STATUS_INFO_LENGTH_MISMATCH`.
+    #[derive(Default)]
+    struct Kernel {
+        strings: BTreeMap<&'static str, String>,
+        outputs: BTreeMap<&'static str, Vec<u8>>,
+        /// Whether failures report the length needed.
+        report: bool,
+        queries: RefCell<Vec<Query>>,
+        /// The class, bytes and address of each set.
+        sets: RefCell<Vec<(&'static str, Vec<u8>, usize)>>,
+    }
+
+    /// A `UNICODE_STRING` for a buffer at `base`, followed by the characters
+    /// and a terminator.
+    fn unicode_string_at(base: usize, value: &str) -> Vec<u8> {
+        let units = value.encode_utf16().collect::<Vec<_>>();
+        let length = (units.len() * 2) as u16;
+        let mut writer = Writer::default();
+        writer.u16(length).u16(length + 2).zeros(4);
+        if units.is_empty() {
+            writer.u64(0);
+        } else {
+            writer.u64((base + 16) as u64);
+        }
+        for unit in units {
+            writer.u16(unit);
+        }
+        writer.u16(0);
+        writer.into_inner()
+    }
+
+    impl Kernel {
+        fn answer(&self, class: &'static str, bytes: &mut [u8]) -> Result<usize, QueryFailure> {
+            let base = bytes.as_ptr() as usize;
+            self.queries.borrow_mut().push(Query {
+                class,
+                length: bytes.len(),
+                start: bytes[..bytes.len().min(8)].to_vec(),
+            });
+            let output = match (self.strings.get(class), self.outputs.get(class)) {
+                (Some(value), _) => unicode_string_at(base, value),
+                (None, Some(output)) => output.clone(),
+                (None, None) => {
+                    return Err(QueryFailure {
+                        status: STATUS_INVALID_INFO_CLASS,
+                        required: 0,
+                    });
+                }
+            };
+            if output.len() > bytes.len() {
+                return Err(QueryFailure {
+                    status: STATUS_INFO_LENGTH_MISMATCH,
+                    required: if self.report { output.len() } else { 0 },
+                });
+            }
+            bytes[..output.len()].copy_from_slice(&output);
+            Ok(output.len())
+        }
+
+        fn lengths(&self) -> Vec<usize> {
+            self.queries
+                .borrow()
+                .iter()
+                .map(|query| query.length)
+                .collect()
+        }
+    }
+
+    impl ProcessBackend for Kernel {
+        fn query_process(
+            &self,
+            _: HANDLE,
+            class: PROCESSINFOCLASS,
+            buffer: &mut [u8],
+        ) -> Result<usize, QueryFailure> {
+            self.answer(process_class_name(class).unwrap(), buffer)
+        }
+
+        fn set_process(
+            &self,
+            _: HANDLE,
+            class: PROCESSINFOCLASS,
+            buffer: &[u8],
+        ) -> Result<(), NTSTATUS> {
+            self.sets.borrow_mut().push((
+                process_class_name(class).unwrap(),
+                buffer.to_vec(),
+                buffer.as_ptr() as usize,
+            ));
+            Ok(())
+        }
+
+        fn query_thread(
+            &self,
+            _: HANDLE,
+            class: THREADINFOCLASS,
+            buffer: &mut [u8],
+        ) -> Result<usize, QueryFailure> {
+            self.answer(thread_class_name(class).unwrap(), buffer)
+        }
+
+        fn set_thread(
+            &self,
+            _: HANDLE,
+            class: THREADINFOCLASS,
+            buffer: &[u8],
+        ) -> Result<(), NTSTATUS> {
+            self.sets.borrow_mut().push((
+                thread_class_name(class).unwrap(),
+                buffer.to_vec(),
+                buffer.as_ptr() as usize,
+            ));
+            Ok(())
+        }
+    }
+
+    const PROCESS: HANDLE = HANDLE(-1);
+    const THREAD: HANDLE = HANDLE(-2);
+
+    #[test]
+    fn names_every_class() {
+        for (index, name) in PROCESS_INFO_CLASSES.iter().enumerate() {
+            assert_eq!(
+                process_class_from_name(name),
+                Some(PROCESSINFOCLASS(index as i32))
+            );
+        }
+        for (index, name) in THREAD_INFO_CLASSES.iter().enumerate() {
+            assert_eq!(
+                thread_class_from_name(name),
+                Some(THREADINFOCLASS(index as i32))
+            );
+        }
+        assert_eq!(process_class_name(PROCESSINFOCLASS(-1)), None);
+        assert_eq!(
+            process_class_name(PROCESSINFOCLASS(PROCESS_INFO_CLASSES.len() as i32)),
+            None
+        );
+        assert_eq!(thread_class_from_name("ProcessBasicInformation"), None);
+
+        // Each typed class is named after the value it uses.
+        macro_rules! assert_names {
+            ($trait:ident, $name:ident: $($class:ident),*) => {$(
+                assert_eq!(
+                    $name(<$class as $trait>::CLASS),
+                    Some(stringify!($class)),
+                );
+            )*};
+        }
+        assert_names!(ProcessInfo, process_class_name:
+            ProcessBasicInformation, ProcessTimes, ProcessHandleCount, ProcessPriorityBoost,
+            ProcessSessionInformation, ProcessWow64Information, ProcessBreakOnTermination,
+            ProcessIoPriority, ProcessCycleTime, ProcessProtectionInformation,
+            ProcessSubsystemInformation, ProcessSequenceNumber, ProcessMembershipInformation,
+            ProcessImageFileName, ProcessImageFileNameWin32, ProcessCommandLineInformation,
+            ProcessMitigationPolicy, ProcessHandleTracing);
+        assert_names!(ThreadInfo, thread_class_name:
+            ThreadBasicInformation, ThreadTimes, ThreadQuerySetWin32StartAddress,
+            ThreadIsIoPending, ThreadBreakOnTermination, ThreadIsTerminated,
+            ThreadLastSystemCall, ThreadIoPriority, ThreadCycleTime, ThreadGroupInformation,
+            ThreadIdealProcessorEx, ThreadSuspendCount, ThreadNameInformation);
+        assert_names!(SetThreadInfo, thread_class_name:
+            ThreadPriority, ThreadBasePriority, ThreadHideFromDebugger);
+    }
+
+    #[test]
+    fn requires_access_per_class() {
+        macro_rules! access {
+            ($trait:ident: $($class:ident),*) => {
+                [$((stringify!($class), <$class as $trait>::ACCESS.0)),*]
+            };
+        }
+        let limited = PROCESS_QUERY_LIMITED_INFORMATION.0;
+        let full = PROCESS_QUERY_INFORMATION.0;
+        assert_eq!(
+            access!(ProcessInfo:
+                ProcessBasicInformation, ProcessTimes, ProcessBreakOnTermination,
+                ProcessImageFileName, ProcessImageFileNameWin32, ProcessCommandLineInformation,
+                ProcessProtectionInformation, ProcessMitigationPolicy, ProcessHandleTracing),
+            [
+                ("ProcessBasicInformation", limited),
+                ("ProcessTimes", limited),
+                ("ProcessBreakOnTermination", full),
+                ("ProcessImageFileName", limited),
+                ("ProcessImageFileNameWin32", limited),
+                ("ProcessCommandLineInformation", limited),
+                ("ProcessProtectionInformation", limited),
+                ("ProcessMitigationPolicy", limited),
+                ("ProcessHandleTracing", full),
+            ]
+        );
+        let set = PROCESS_SET_INFORMATION.0;
+        assert_eq!(
+            access!(SetProcessInfo:
+                ProcessPriorityBoost, ProcessBreakOnTermination, ProcessIoPriority,
+                ProcessMitigationPolicy, ProcessHandleTracing),
+            [
+                ("ProcessPriorityBoost", set),
+                ("ProcessBreakOnTermination", set),
+                ("ProcessIoPriority", set),
+                ("ProcessMitigationPolicy", set),
+                ("ProcessHandleTracing", set),
+            ]
+        );
+        let limited = THREAD_QUERY_LIMITED_INFORMATION.0;
+        let full = THREAD_QUERY_INFORMATION.0;
+        assert_eq!(
+            access!(ThreadInfo:
+                ThreadBasicInformation, ThreadQuerySetWin32StartAddress, ThreadIsIoPending,
+                ThreadBreakOnTermination, ThreadIsTerminated, ThreadLastSystemCall,
+                ThreadNameInformation, ThreadSuspendCount),
+            [
+                ("ThreadBasicInformation", limited),
+                ("ThreadQuerySetWin32StartAddress", full),
+                ("ThreadIsIoPending", full),
+                ("ThreadBreakOnTermination", full),
+                ("ThreadIsTerminated", limited),
+                ("ThreadLastSystemCall", THREAD_GET_CONTEXT.0),
+                ("ThreadNameInformation", limited),
+                ("ThreadSuspendCount", limited),
+            ]
+        );
+        let limited = THREAD_SET_LIMITED_INFORMATION.0;
+        let full = THREAD_SET_INFORMATION.0;
+        assert_eq!(
+            access!(SetThreadInfo:
+                ThreadBreakOnTermination, ThreadIoPriority, ThreadPriority, ThreadBasePriority,
+                ThreadHideFromDebugger, ThreadNameInformation),
+            [
+                ("ThreadBreakOnTermination", full),
+                ("ThreadIoPriority", limited),
+                ("ThreadPriority", limited),
+                ("ThreadBasePriority", limited),
+                ("ThreadHideFromDebugger", full),
+                ("ThreadNameInformation", limited),
+            ]
+        );
+    }
+
+    #[test]
+    fn grows_to_the_required_length() {
+        let path = format!(r"\Device\HarddiskVolume3\{}\app.exe", "deep\\".repeat(100));
+        let kernel = Kernel {
+            strings: BTreeMap::from([("ProcessImageFileName", path.clone())]),
+            report: true,
+            ..Kernel::default()
+        };
+        let name = kernel
+            .query_process_info::<ProcessImageFileName>(PROCESS, &())
+            .unwrap();
+        assert_eq!(name, path);
+        let needed = 16 + 2 * path.len() + 2;
+        assert_eq!(kernel.lengths(), [0x210, needed.next_multiple_of(8)]);
+    }
+
+    #[test]
+    fn doubles_without_a_required_length() {
+        let command_line = format!("app.exe {}", "--flag ".repeat(100));
+        let kernel = Kernel {
+            strings: BTreeMap::from([("ProcessCommandLineInformation", command_line.clone())]),
+            ..Kernel::default()
+        };
+        let decoded = kernel
+            .query_process_info::<ProcessCommandLineInformation>(PROCESS, &())
+            .unwrap();
+        assert_eq!(decoded, command_line);
+        assert_eq!(kernel.lengths(), [0x210, 0x420, 0x840]);
+    }
+
+    #[test]
+    fn fails_fixed_classes_without_growing() {
+        let kernel = Kernel {
+            outputs: BTreeMap::from([("ProcessBasicInformation", vec![0; 64])]),
+            report: true,
+            ..Kernel::default()
+        };
+        assert_eq!(
+            kernel.query_process_info::<ProcessBasicInformation>(PROCESS, &()),
+            Err(Error::Status(STATUS_INFO_LENGTH_MISMATCH))
+        );
+        assert_eq!(kernel.lengths(), [48]);
+        assert_eq!(
+            kernel.query_thread_info::<ThreadSuspendCount>(THREAD, &()),
+            Err(Error::Status(STATUS_INVALID_INFO_CLASS))
+        );
+    }
+
+    #[test]
+    fn decodes_string_classes() {
+        let kernel = Kernel {
+            strings: BTreeMap::from([
+                (
+                    "ProcessImageFileName",
+                    r"\Device\HarddiskVolume3\Windows\explorer.exe".to_owned(),
+                ),
+                (
+                    "ProcessImageFileNameWin32",
+                    r"C:\Windows\explorer.exe".to_owned(),
+                ),
+                (
+                    "ProcessCommandLineInformation",
+                    "\"C:\\Program Files\\r\u{e9}seau\\app.exe\" /q \u{1f600}".to_owned(),
+                ),
+                ("ThreadNameInformation", "worker".to_owned()),
+            ]),
+            ..Kernel::default()
+        };
+        assert_eq!(
+            kernel
+                .query_process_info::<ProcessImageFileName>(PROCESS, &())
+                .unwrap(),
+            r"\Device\HarddiskVolume3\Windows\explorer.exe"
+        );
+        assert_eq!(
+            kernel
+                .query_process_info::<ProcessImageFileNameWin32>(PROCESS, &())
+                .unwrap(),
+            r"C:\Windows\explorer.exe"
+        );
+        assert_eq!(
+            kernel
+                .query_process_info::<ProcessCommandLineInformation>(PROCESS, &())
+                .unwrap(),
+            "\"C:\\Program Files\\r\u{e9}seau\\app.exe\" /q \u{1f600}"
+        );
+        assert_eq!(
+            kernel
+                .query_thread_info::<ThreadNameInformation>(THREAD, &())
+                .unwrap(),
+            "worker"
+        );
+    }
+
+    #[test]
+    fn decodes_empty_and_stray_strings() {
+        // An unnamed thread has no buffer at all.
+        let kernel = Kernel {
+            strings: BTreeMap::from([("ThreadNameInformation", String::new())]),
+            ..Kernel::default()
+        };
+        assert_eq!(
+            kernel
+                .query_thread_info::<ThreadNameInformation>(THREAD, &())
+                .unwrap(),
+            ""
+        );
+
+        // A pointer outside the output buffer is not followed.
+        let mut stray = Writer::default();
+        stray.u16(8).u16(8).zeros(4).u64(0x7ff6_1000_0000).zeros(8);
+        let kernel = Kernel {
+            outputs: BTreeMap::from([("ProcessImageFileName", stray.into_inner())]),
+            ..Kernel::default()
+        };
+        assert!(matches!(
+            kernel.query_process_info::<ProcessImageFileName>(PROCESS, &()),
+            Err(Error::Decode(DecodeError::Truncated { .. }))
+        ));
+
+        // A length past the end of the output.
+        let mut long = Writer::default();
+        long.u16(64).u16(64).zeros(4).u64(16).u16(u16::from(b'a'));
+        assert!(matches!(
+            decode_unicode_string(long.as_slice()),
+            Err(DecodeError::Truncated { offset: 16, .. })
+        ));
+    }
+
+    #[test]
+    fn sets_thread_names() {
+        let kernel = Kernel::default();
+        kernel
+            .set_thread_info::<ThreadNameInformation>(THREAD, "r\u{e9}seau")
+            .unwrap();
+        let sets = kernel.sets.take();
+        let (class, bytes, base) = &sets[0];
+        assert_eq!(*class, "ThreadNameInformation");
+        assert_eq!(bytes.len(), 16 + 12);
+        assert_eq!(bytes[..8], [12, 0, 12, 0, 0, 0, 0, 0]);
+        // The buffer pointer is made an address within the set buffer.
+        assert_eq!(
+            usize::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            base + 16
+        );
+        assert_eq!(
+            decode_unicode_string(&encode_unicode_string("r\u{e9}seau")).unwrap(),
+            "r\u{e9}seau"
+        );
+        assert_eq!(
+            bytes[16..],
+            [b'r', 0, 0xe9, 0, b's', 0, b'e', 0, b'a', 0, b'u', 0]
+        );
+    }
+
+    #[test]
+    fn decodes_basic_information() {
+        let mut process = Writer::default();
+        process
+            .u32(0x103)
+            .zeros(4)
+            .u64(0xa1_2345_6000)
+            .u64(0xff)
+            .u32(8)
+            .zeros(4)
+            .u64(0x1a2c)
+            .u64(0x2f8);
+        let mut thread = Writer::default();
+        thread
+            .u32(0)
+            .zeros(4)
+            .u64(0xa1_2345_8000)
+            .u64(0x1a2c)
+            .u64(0x1b40)
+            .u64(0x3)
+            .u32(10)
+            .u32(8);
+        let kernel = Kernel {
+            outputs: BTreeMap::from([
+                ("ProcessBasicInformation", process.into_inner()),
+                ("ThreadBasicInformation", thread.into_inner()),
+                ("ProcessProtectionInformation", vec![0x61]),
+            ]),
+            ..Kernel::default()
+        };
+        assert_eq!(
+            kernel
+                .query_process_info::<ProcessBasicInformation>(PROCESS, &())
+                .unwrap(),
+            ProcessBasic {
+                exit_status: 0x103,
+                peb: 0xa1_2345_6000,
+                affinity_mask: 0xff,
+                base_priority: 8,
+                process_id: 0x1a2c,
+                parent_process_id: 0x2f8,
+            }
+        );
+        assert_eq!(
+            kernel
+                .query_thread_info::<ThreadBasicInformation>(THREAD, &())
+                .unwrap(),
+            ThreadBasic {
+                exit_status: 0,
+                teb: 0xa1_2345_8000,
+                process_id: 0x1a2c,
+                thread_id: 0x1b40,
+                affinity_mask: 0x3,
+                priority: 10,
+                base_priority: 8,
+            }
+        );
+        let protection = kernel
+            .query_process_info::<ProcessProtectionInformation>(PROCESS, &())
+            .unwrap();
+        assert_eq!(protection.to_string(), "WinTcb-Light");
+        assert_eq!(kernel.lengths(), [48, 48, 1]);
+    }
+
+    #[test]
+    fn writes_inputs_in_place() {
+        let kernel = Kernel {
+            outputs: BTreeMap::from([("ProcessMitigationPolicy", vec![1, 0, 0, 0, 5, 0, 0, 0])]),
+            ..Kernel::default()
+        };
+        let state = kernel
+            .query_process_info::<ProcessMitigationPolicy>(PROCESS, &MitigationPolicy::DynamicCode)
+            .unwrap();
+        assert_eq!(state.flags, 5);
+        assert_eq!(
+            kernel.queries.borrow()[0],
+            Query {
+                class: "ProcessMitigationPolicy",
+                length: POLICY_INFORMATION_SIZE,
+                start: vec![2, 0, 0, 0, 0, 0, 0, 0],
+            }
+        );
+    }
+
+    #[test]
+    fn encodes_set_values() {
+        let kernel = Kernel::default();
+        kernel
+            .set_process_info::<ProcessBreakOnTermination>(PROCESS, &true)
+            .unwrap();
+        kernel
+            .set_process_info::<ProcessHandleTracing>(PROCESS, &HandleTracing::EnableWithSlots(64))
+            .unwrap();
+        kernel
+            .set_process_info::<ProcessHandleTracing>(PROCESS, &HandleTracing::Disable)
+            .unwrap();
+        kernel
+            .set_thread_info::<ThreadBasePriority>(THREAD, &-2)
+            .unwrap();
+        kernel
+            .set_thread_info::<ThreadHideFromDebugger>(THREAD, &())
+            .unwrap();
+        let sets = kernel
+            .sets
+            .take()
+            .into_iter()
+            .map(|(class, bytes, _)| (class, bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sets,
+            [
+                ("ProcessBreakOnTermination", vec![1, 0, 0, 0]),
+                ("ProcessHandleTracing", vec![0, 0, 0, 0, 64, 0, 0, 0]),
+                ("ProcessHandleTracing", vec![]),
+                ("ThreadBasePriority", vec![0xfe, 0xff, 0xff, 0xff]),
+                ("ThreadHideFromDebugger", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_handle_traces() {
+        let mut traces = Writer::default();
+        traces.u64(0).u32(2).zeros(4);
+        for (kind, stack) in [
+            (2, &[0x7ffa_0000_1000_u64, 0x7ffa_0000_2000][..]),
+            (1, &[0x7ffa_0000_3000][..]),
+        ] {
+            traces.u64(0x1f4).u64(0x1a2c).u64(0x1b40).u32(kind).zeros(4);
+            for frame in 0..HANDLE_TRACE_FRAMES {
+                traces.u64(stack.get(frame).copied().unwrap_or(0));
+            }
+        }
+        let kernel = Kernel {
+            outputs: BTreeMap::from([("ProcessHandleTracing", traces.into_inner())]),
+            ..Kernel::default()
+        };
+        let entries = kernel
+            .query_process_info::<ProcessHandleTracing>(PROCESS, &HANDLE(0x1f4))
+            .unwrap();
+        assert_eq!(kernel.queries.borrow()[0].start, 0x1f4_u64.to_le_bytes());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, HandleTraceKind::Close);
+        assert_eq!(entries[0].stack, [0x7ffa_0000_1000, 0x7ffa_0000_2000]);
+        assert_eq!(
+            entries[1],
+            HandleTraceEntry {
+                handle: HANDLE(0x1f4),
+                process_id: 0x1a2c,
+                thread_id: 0x1b40,
+                kind: HandleTraceKind::Open,
+                stack: vec![0x7ffa_0000_3000],
+            }
+        );
+        assert!(parse_handle_traces(&[0; 12]).is_err());
+    }
+}
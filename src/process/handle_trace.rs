@@ -0,0 +1,288 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use windows::Win32::Foundation::{HANDLE, NTSTATUS};
+
+use super::{
+    HandleTraceEntry, HandleTraceKind, HandleTracing, ProcessBackend, ProcessHandleTracing,
+};
+use crate::{ntpsapi::PROCESS_HANDLE_TRACING_MAX_SLOTS, system::Error};
+
+/// What the trace ring says about the handles of a process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeakReport {
+    /// The opens without a later close, oldest first.
+    pub leaked: Vec<HandleTraceEntry>,
+    /// The closes of handles opened before the oldest trace in the ring.
+    pub unmatched_closes: Vec<HandleTraceEntry>,
+    pub bad_references: Vec<HandleTraceEntry>,
+    pub opens: usize,
+    pub closes: usize,
+}
+
+/// The leaked handles allocated from one stack.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeakSite {
+    pub stack: Vec<usize>,
+    /// The handles, oldest first.
+    pub handles: Vec<HANDLE>,
+}
+
+impl LeakReport {
+    /// Groups the leaked handles by allocation stack, the sites leaking the
+    /// most first.
+    pub fn by_stack(&self) -> Vec<LeakSite> {
+        let mut sites = BTreeMap::<&[usize], Vec<HANDLE>>::new();
+        for entry in &self.leaked {
+            sites.entry(&entry.stack).or_default().push(entry.handle);
+        }
+        let mut sites = sites
+            .into_iter()
+            .map(|(stack, handles)| LeakSite {
+                stack: stack.to_vec(),
+                handles,
+            })
+            .collect::<Vec<_>>();
+        sites.sort_by_key(|site| Reverse(site.handles.len()));
+        sites
+    }
+}
+
+/// Pairs the opens in `traces`, newest first as the kernel returns them,
+/// with the closes of the same handle value.
+///
+/// The ring drops its oldest traces, so the close of a handle opened before
+/// the oldest trace has no open. A handle value opened again without a
+/// close in between had its close dropped, and the earlier open is not
+/// counted as leaked.
+pub fn find_leaks(traces: &[HandleTraceEntry]) -> LeakReport {
+    let mut report = LeakReport::default();
+    let mut open = HashMap::<isize, (usize, &HandleTraceEntry)>::new();
+    for (index, entry) in traces.iter().rev().enumerate() {
+        match entry.kind {
+            HandleTraceKind::Open => {
+                report.opens += 1;
+                open.insert(entry.handle.0, (index, entry));
+            }
+            HandleTraceKind::Close => {
+                report.closes += 1;
+                if open.remove(&entry.handle.0).is_none() {
+                    report.unmatched_closes.push(entry.clone());
+                }
+            }
+            HandleTraceKind::BadReference => report.bad_references.push(entry.clone()),
+            HandleTraceKind::Other(_) => {}
+        }
+    }
+    let mut leaked = open.into_values().collect::<Vec<_>>();
+    leaked.sort_by_key(|(index, _)| *index);
+    report.leaked = leaked.into_iter().map(|(_, entry)| entry.clone()).collect();
+    report
+}
+
+/// Handle tracing of one process over a [`ProcessBackend`].
+///
+/// Tracing records every open and close of a handle in the process, with
+/// the stack it happened on, into a ring in the kernel until it is disabled
+/// or the process exits.
+#[derive(Debug, Clone)]
+pub struct HandleTracer<B> {
+    backend: B,
+    process: HANDLE,
+}
+
+impl<B: ProcessBackend> HandleTracer<B> {
+    /// Traces `process`, which needs `PROCESS_QUERY_INFORMATION` and
+    /// `PROCESS_SET_INFORMATION`.
+    pub fn new(backend: B, process: HANDLE) -> Self {
+        Self { backend, process }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn process(&self) -> HANDLE {
+        self.process
+    }
+
+    /// Starts tracing into a ring of `slots` traces, clamped to
+    /// `PROCESS_HANDLE_TRACING_MAX_SLOTS`, or of the default size. Enabling
+    /// it again clears the ring.
+    pub fn enable(&self, slots: Option<u32>) -> Result<(), NTSTATUS> {
+        let value = match slots {
+            Some(slots) => {
+                HandleTracing::EnableWithSlots(slots.min(PROCESS_HANDLE_TRACING_MAX_SLOTS))
+            }
+            None => HandleTracing::Enable,
+        };
+        self.backend
+            .set_process_info::<ProcessHandleTracing>(self.process, &value)
+    }
+
+    pub fn disable(&self) -> Result<(), NTSTATUS> {
+        self.backend
+            .set_process_info::<ProcessHandleTracing>(self.process, &HandleTracing::Disable)
+    }
+
+    /// The traces in the ring, newest first.
+    pub fn traces(&self) -> Result<Vec<HandleTraceEntry>, Error> {
+        self.traces_of(HANDLE::default())
+    }
+
+    /// The traces of `handle`, newest first.
+    pub fn traces_of(&self, handle: HANDLE) -> Result<Vec<HandleTraceEntry>, Error> {
+        self.backend
+            .query_process_info::<ProcessHandleTracing>(self.process, &handle)
+    }
+
+    /// Reads the ring and pairs its opens and closes.
+    pub fn leaks(&self) -> Result<LeakReport, Error> {
+        Ok(find_leaks(&self.traces()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(handle: isize, kind: HandleTraceKind, stack: &[usize]) -> HandleTraceEntry {
+        HandleTraceEntry {
+            handle: HANDLE(handle),
+            process_id: 0x1c4,
+            thread_id: 0x2a8,
+            kind,
+            stack: stack.to_vec(),
+        }
+    }
+
+    /// The traces in `oldest_first` in the order the kernel returns them.
+    fn newest_first(mut oldest_first: Vec<HandleTraceEntry>) -> Vec<HandleTraceEntry> {
+        oldest_first.reverse();
+        oldest_first
+    }
+
+    fn handles(entries: &[HandleTraceEntry]) -> Vec<isize> {
+        entries.iter().map(|entry| entry.handle.0).collect()
+    }
+
+    #[test]
+    fn reads_traces_newest_first() {
+        use HandleTraceKind::*;
+
+        let traces = newest_first(vec![
+            trace(0x20, Open, &[1]),
+            trace(0x24, Open, &[2]),
+            trace(0x20, Close, &[3]),
+        ]);
+        let report = find_leaks(&traces);
+        assert_eq!(handles(&report.leaked), [0x24]);
+        assert!(report.unmatched_closes.is_empty());
+        assert_eq!((report.opens, report.closes), (2, 1));
+
+        // Read oldest first, the close would come before its open.
+        let report = find_leaks(&newest_first(traces));
+        assert_eq!(handles(&report.leaked), [0x24, 0x20]);
+        assert_eq!(handles(&report.unmatched_closes), [0x20]);
+    }
+
+    #[test]
+    fn keeps_closes_of_handles_opened_before_the_ring() {
+        use HandleTraceKind::*;
+
+        let report = find_leaks(&newest_first(vec![
+            trace(0x10, Close, &[9]),
+            trace(0x20, Open, &[1]),
+            trace(0x14, Close, &[8]),
+            trace(0x20, Close, &[2]),
+        ]));
+        assert!(report.leaked.is_empty());
+        assert_eq!(handles(&report.unmatched_closes), [0x10, 0x14]);
+        assert_eq!(report.unmatched_closes[1].stack, [8]);
+        assert_eq!((report.opens, report.closes), (1, 3));
+    }
+
+    #[test]
+    fn pairs_reused_handle_values() {
+        use HandleTraceKind::*;
+
+        let report = find_leaks(&newest_first(vec![
+            trace(0x30, Open, &[1]),
+            trace(0x30, Close, &[2]),
+            trace(0x30, Open, &[3]),
+            // The close of the first open fell out of the ring.
+            trace(0x40, Open, &[4]),
+            trace(0x40, Open, &[5]),
+            trace(0x40, Close, &[6]),
+            trace(0x44, Open, &[7]),
+            trace(0x44, Open, &[8]),
+        ]));
+        assert_eq!(handles(&report.leaked), [0x30, 0x44]);
+        assert_eq!(report.leaked[0].stack, [3]);
+        assert_eq!(report.leaked[1].stack, [8]);
+        assert!(report.unmatched_closes.is_empty());
+        assert_eq!((report.opens, report.closes), (6, 2));
+    }
+
+    #[test]
+    fn collects_bad_references() {
+        use HandleTraceKind::*;
+
+        let report = find_leaks(&newest_first(vec![
+            trace(0x50, Open, &[1]),
+            trace(0x50, BadReference, &[2]),
+            trace(0x5c, BadReference, &[3]),
+            trace(0x50, Close, &[4]),
+            trace(0x50, Other(7), &[5]),
+        ]));
+        assert!(report.leaked.is_empty());
+        assert!(report.unmatched_closes.is_empty());
+        assert_eq!(handles(&report.bad_references), [0x50, 0x5c]);
+        assert_eq!(report.bad_references[1].stack, [3]);
+        assert_eq!((report.opens, report.closes), (1, 1));
+
+        assert_eq!(find_leaks(&[]), LeakReport::default());
+    }
+
+    #[test]
+    fn groups_leaks_by_stack() {
+        use HandleTraceKind::*;
+
+        let report = find_leaks(&newest_first(vec![
+            trace(0x60, Open, &[3, 1]),
+            trace(0x64, Open, &[2, 1]),
+            trace(0x68, Open, &[3, 1]),
+            trace(0x6c, Open, &[4]),
+            trace(0x70, Open, &[2, 1]),
+            trace(0x74, Open, &[3, 1]),
+            trace(0x64, Close, &[5]),
+            trace(0x78, Open, &[]),
+        ]));
+        let sites = report.by_stack();
+        assert_eq!(
+            sites,
+            [
+                LeakSite {
+                    stack: vec![3, 1],
+                    handles: vec![HANDLE(0x60), HANDLE(0x68), HANDLE(0x74)],
+                },
+                // Sites leaking as many are in stack order.
+                LeakSite {
+                    stack: vec![],
+                    handles: vec![HANDLE(0x78)],
+                },
+                LeakSite {
+                    stack: vec![2, 1],
+                    handles: vec![HANDLE(0x70)],
+                },
+                LeakSite {
+                    stack: vec![4],
+                    handles: vec![HANDLE(0x6c)],
+                },
+            ]
+        );
+        assert!(LeakReport::default().by_stack().is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use std::mem;
+
+use windows::{
+    Wdk::System::Threading::{
+        NtQueryInformationProcess, NtQueryInformationThread, NtSetInformationThread,
+        PROCESSINFOCLASS, THREADINFOCLASS,
+    },
+    Win32::{
+        Foundation::{HANDLE, NTSTATUS},
+        System::Threading::{PROCESS_ACCESS_RIGHTS, THREAD_ACCESS_RIGHTS},
+    },
+};
+
+use crate::{
+    buffer::DecodeError,
+    check,
+    ntpsapi::NtSetInformationProcess,
+    system::{Error, InputBuffer, QueryFailure, QueryInput, Sizing, query_with},
+};
+
+/// A `PROCESSINFOCLASS` that can be queried.
+///
+/// Process classes read their input from the start of the output buffer,
+/// so the input of a class is copied there whichever way it is given.
+pub trait ProcessInfo {
+    const CLASS: PROCESSINFOCLASS;
+    const SIZING: Sizing;
+    /// The access the process handle needs.
+    const ACCESS: PROCESS_ACCESS_RIGHTS;
+    /// Offsets of pointer-sized fields that point into the output buffer,
+    /// turned into offsets from its start before decoding.
+    const RELOCATIONS: &'static [usize] = &[];
+    type Input: QueryInput;
+    type Output;
+
+    fn decode(bytes: &[u8]) -> Result<Self::Output, DecodeError>;
+}
+
+/// A `PROCESSINFOCLASS` that can be set.
+pub trait SetProcessInfo {
+    const CLASS: PROCESSINFOCLASS;
+    /// The access the process handle needs.
+    const ACCESS: PROCESS_ACCESS_RIGHTS;
+    /// Offsets of pointer-sized fields holding offsets into the encoded
+    /// buffer, turned into addresses before the call.
+    const RELOCATIONS: &'static [usize] = &[];
+    type Value: ?Sized;
+
+    fn encode(value: &Self::Value) -> Vec<u8>;
+}
+
+/// A `THREADINFOCLASS` that can be queried, with the same conventions as
+/// [`ProcessInfo`].
+pub trait ThreadInfo {
+    const CLASS: THREADINFOCLASS;
+    const SIZING: Sizing;
+    /// The access the thread handle needs.
+    const ACCESS: THREAD_ACCESS_RIGHTS;
+    const RELOCATIONS: &'static [usize] = &[];
+    type Input: QueryInput;
+    type Output;
+
+    fn decode(bytes: &[u8]) -> Result<Self::Output, DecodeError>;
+}
+
+/// A `THREADINFOCLASS` that can be set, with the same conventions as
+/// [`SetProcessInfo`].
+pub trait SetThreadInfo {
+    const CLASS: THREADINFOCLASS;
+    /// The access the thread handle needs.
+    const ACCESS: THREAD_ACCESS_RIGHTS;
+    const RELOCATIONS: &'static [usize] = &[];
+    type Value: ?Sized;
+
+    fn encode(value: &Self::Value) -> Vec<u8>;
+}
+
+fn prefix(input: InputBuffer) -> Vec<u8> {
+    match input {
+        InputBuffer::None => Vec::new(),
+        InputBuffer::Separate(bytes) | InputBuffer::InPlace(bytes) => bytes,
+    }
+}
+
+/// Turns the pointers at `offsets` of a buffer that lived at `base` into
+/// offsets from its start; pointers outside it are left alone.
+fn unrelocate(bytes: &mut [u8], base: usize, length: usize, offsets: &[usize]) {
+    for &offset in offsets {
+        let Some(field) = bytes.get_mut(offset..offset + mem::size_of::<usize>()) else {
+            continue;
+        };
+        let pointer = usize::from_le_bytes(field.try_into().unwrap());
+        if (base..base + length).contains(&pointer) {
+            field.copy_from_slice(&(pointer - base).to_le_bytes());
+        }
+    }
+}
+
+/// Copies `bytes` into an aligned buffer, turning the offsets at `offsets`
+/// into addresses within it.
+fn relocate(bytes: &[u8], offsets: &[usize]) -> Vec<u64> {
+    let mut buffer = vec![0u64; bytes.len().div_ceil(mem::size_of::<u64>())];
+    let base = buffer.as_ptr() as usize;
+    let target = as_bytes_mut(&mut buffer);
+    target[..bytes.len()].copy_from_slice(bytes);
+    for &offset in offsets {
+        if let Some(field) = target.get_mut(offset..offset + mem::size_of::<usize>()) {
+            let relative = usize::from_le_bytes(field.try_into().unwrap());
+            field.copy_from_slice(&(base + relative).to_le_bytes());
+        }
+    }
+    buffer
+}
+
+fn as_bytes_mut(buffer: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr().cast(), mem::size_of_val(buffer)) }
+}
+
+/// Runs a query through `query`, undoing the pointers at `relocations`.
+///
+/// Most fixed classes fail with `STATUS_INFO_LENGTH_MISMATCH` unless the
+/// length is exactly theirs, so they are not given the padding of the
+/// aligned buffer.
+fn query_relocated(
+    sizing: Sizing,
+    prefix: &[u8],
+    relocations: &[usize],
+    mut query: impl FnMut(&mut [u8]) -> Result<usize, QueryFailure>,
+) -> Result<Vec<u8>, NTSTATUS> {
+    let mut base = 0;
+    let mut length = 0;
+    let mut bytes = query_with(sizing, prefix, |buffer| {
+        let buffer = as_bytes_mut(buffer);
+        let end = match sizing {
+            Sizing::Fixed(fixed) => fixed.max(prefix.len()),
+            Sizing::Variable { .. } => buffer.len(),
+        };
+        base = buffer.as_ptr() as usize;
+        length = end;
+        query(&mut buffer[..end])
+    })?;
+    unrelocate(&mut bytes, base, length, relocations);
+    Ok(bytes)
+}
+
+/// The calls the process and thread queries are built on.
+pub trait ProcessBackend {
+    /// Queries `class` of `process` into `buffer`, returning the length
+    /// written. The buffer is 8-byte aligned.
+    fn query_process(
+        &self,
+        process: HANDLE,
+        class: PROCESSINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<usize, QueryFailure>;
+
+    fn set_process(
+        &self,
+        process: HANDLE,
+        class: PROCESSINFOCLASS,
+        buffer: &[u8],
+    ) -> Result<(), NTSTATUS>;
+
+    /// Queries `class` of `thread` into `buffer`, returning the length
+    /// written. The buffer is 8-byte aligned.
+    fn query_thread(
+        &self,
+        thread: HANDLE,
+        class: THREADINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<usize, QueryFailure>;
+
+    fn set_thread(
+        &self,
+        thread: HANDLE,
+        class: THREADINFOCLASS,
+        buffer: &[u8],
+    ) -> Result<(), NTSTATUS>;
+
+    /// Queries `T` of `process` with `input`, growing the buffer as needed.
+    fn query_process_info<T: ProcessInfo>(
+        &self,
+        process: HANDLE,
+        input: &T::Input,
+    ) -> Result<T::Output, Error> {
+        let bytes = query_relocated(
+            T::SIZING,
+            &prefix(input.to_input()),
+            T::RELOCATIONS,
+            |buffer| self.query_process(process, T::CLASS, buffer),
+        )?;
+        Ok(T::decode(&bytes)?)
+    }
+
+    fn set_process_info<T: SetProcessInfo>(
+        &self,
+        process: HANDLE,
+        value: &T::Value,
+    ) -> Result<(), NTSTATUS> {
+        let bytes = T::encode(value);
+        let mut buffer = relocate(&bytes, T::RELOCATIONS);
+        self.set_process(process, T::CLASS, &as_bytes_mut(&mut buffer)[..bytes.len()])
+    }
+
+    /// Queries `T` of `thread` with `input`, growing the buffer as needed.
+    fn query_thread_info<T: ThreadInfo>(
+        &self,
+        thread: HANDLE,
+        input: &T::Input,
+    ) -> Result<T::Output, Error> {
+        let bytes = query_relocated(
+            T::SIZING,
+            &prefix(input.to_input()),
+            T::RELOCATIONS,
+            |buffer| self.query_thread(thread, T::CLASS, buffer),
+        )?;
+        Ok(T::decode(&bytes)?)
+    }
+
+    fn set_thread_info<T: SetThreadInfo>(
+        &self,
+        thread: HANDLE,
+        value: &T::Value,
+    ) -> Result<(), NTSTATUS> {
+        let bytes = T::encode(value);
+        let mut buffer = relocate(&bytes, T::RELOCATIONS);
+        self.set_thread(thread, T::CLASS, &as_bytes_mut(&mut buffer)[..bytes.len()])
+    }
+}
+
+/// The [`ProcessBackend`] that calls ntdll.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeProcess;
+
+fn failure(status: NTSTATUS, return_length: u32) -> Result<usize, QueryFailure> {
+    if status.is_err() {
+        Err(QueryFailure {
+            status,
+            required: return_length as usize,
+        })
+    } else {
+        Ok(return_length as usize)
+    }
+}
+
+impl ProcessBackend for NativeProcess {
+    fn query_process(
+        &self,
+        process: HANDLE,
+        class: PROCESSINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<usize, QueryFailure> {
+        let mut return_length = 0;
+        let status = unsafe {
+            NtQueryInformationProcess(
+                process,
+                class,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u32,
+                &mut return_length,
+            )
+        };
+        failure(status, return_length)
+    }
+
+    fn set_process(
+        &self,
+        process: HANDLE,
+        class: PROCESSINFOCLASS,
+        buffer: &[u8],
+    ) -> Result<(), NTSTATUS> {
+        check(unsafe {
+            NtSetInformationProcess(
+                process,
+                class,
+                buffer.as_ptr().cast_mut().cast(),
+                buffer.len() as u32,
+            )
+        })
+    }
+
+    fn query_thread(
+        &self,
+        thread: HANDLE,
+        class: THREADINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<usize, QueryFailure> {
+        let mut return_length = 0;
+        let status = unsafe {
+            NtQueryInformationThread(
+                thread,
+                class,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u32,
+                &mut return_length,
+            )
+        };
+        failure(status, return_length)
+    }
+
+    fn set_thread(
+        &self,
+        thread: HANDLE,
+        class: THREADINFOCLASS,
+        buffer: &[u8],
+    ) -> Result<(), NTSTATUS> {
+        check(unsafe {
+            NtSetInformationThread(thread, class, buffer.as_ptr().cast(), buffer.len() as u32)
+        })
+    }
+}
+
+/// Queries `T`, which takes no input, of `process`.
+pub fn query_process<T: ProcessInfo<Input = ()>>(process: HANDLE) -> Result<T::Output, Error> {
+    NativeProcess.query_process_info::<T>(process, &())
+}
+
+/// Queries `T` of `process` with `input`.
+pub fn query_process_ex<T: ProcessInfo>(
+    process: HANDLE,
+    input: &T::Input,
+) -> Result<T::Output, Error> {
+    NativeProcess.query_process_info::<T>(process, input)
+}
+
+pub fn set_process<T: SetProcessInfo>(process: HANDLE, value: &T::Value) -> Result<(), NTSTATUS> {
+    NativeProcess.set_process_info::<T>(process, value)
+}
+
+/// Queries `T`, which takes no input, of `thread`.
+pub fn query_thread<T: ThreadInfo<Input = ()>>(thread: HANDLE) -> Result<T::Output, Error> {
+    NativeProcess.query_thread_info::<T>(thread, &())
+}
+
+/// Queries `T` of `thread` with `input`.
+pub fn query_thread_ex<T: ThreadInfo>(
+    thread: HANDLE,
+    input: &T::Input,
+) -> Result<T::Output, Error> {
+    NativeProcess.query_thread_info::<T>(thread, input)
+}
+
+pub fn set_thread<T: SetThreadInfo>(thread: HANDLE, value: &T::Value) -> Result<(), NTSTATUS> {
+    NativeProcess.set_thread_info::<T>(thread, value)
+}
+
+/// Reads any process class as bytes, growing the buffer as needed.
+pub fn query_process_class(process: HANDLE, class: PROCESSINFOCLASS) -> Result<Vec<u8>, NTSTATUS> {
+    query_with(Sizing::Variable { initial: 0x100 }, &[], |buffer| {
+        NativeProcess.query_process(process, class, as_bytes_mut(buffer))
+    })
+}
+
+/// Reads any thread class as bytes, growing the buffer as needed.
+pub fn query_thread_class(thread: HANDLE, class: THREADINFOCLASS) -> Result<Vec<u8>, NTSTATUS> {
+    query_with(Sizing::Variable { initial: 0x100 }, &[], |buffer| {
+        NativeProcess.query_thread(thread, class, as_bytes_mut(buffer))
+    })
+}
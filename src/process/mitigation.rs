@@ -0,0 +1,1091 @@
+use std::{fmt, ptr};
+
+use windows::{
+    Wdk::System::Threading::{NtQueryInformationProcess, ProcessMitigationPolicy},
+    Win32::{
+        Foundation::{HANDLE, NTSTATUS},
+        System::Threading::PROCESS_MITIGATION_POLICY,
+    },
+};
+
+use crate::{
+    buffer::{DecodeError, Reader, Writer},
+    check,
+    ntpsapi::NtSetInformationProcess,
+    system::{DecodedFlags, FlagField},
+};
+
+/// The size of a `PROCESS_MITIGATION_POLICY_INFORMATION`: the policy and
+/// its flags word.
+pub const POLICY_INFORMATION_SIZE: usize = 8;
+
+/// A mitigation policy that `ProcessMitigationPolicy` reads and writes.
+/// DEP is left out: it goes through `ProcessExecuteFlags` instead.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MitigationPolicy {
+    Aslr,
+    DynamicCode,
+    StrictHandleCheck,
+    SystemCallDisable,
+    ExtensionPointDisable,
+    ControlFlowGuard,
+    Signature,
+    FontDisable,
+    ImageLoad,
+    SystemCallFilter,
+    PayloadRestriction,
+    ChildProcess,
+    SideChannelIsolation,
+    UserShadowStack,
+    RedirectionTrust,
+    UserPointerAuth,
+    Sehop,
+}
+
+impl MitigationPolicy {
+    pub const ALL: [Self; 17] = [
+        Self::Aslr,
+        Self::DynamicCode,
+        Self::StrictHandleCheck,
+        Self::SystemCallDisable,
+        Self::ExtensionPointDisable,
+        Self::ControlFlowGuard,
+        Self::Signature,
+        Self::FontDisable,
+        Self::ImageLoad,
+        Self::SystemCallFilter,
+        Self::PayloadRestriction,
+        Self::ChildProcess,
+        Self::SideChannelIsolation,
+        Self::UserShadowStack,
+        Self::RedirectionTrust,
+        Self::UserPointerAuth,
+        Self::Sehop,
+    ];
+
+    pub const fn to_raw(self) -> PROCESS_MITIGATION_POLICY {
+        PROCESS_MITIGATION_POLICY(match self {
+            Self::Aslr => 1,
+            Self::DynamicCode => 2,
+            Self::StrictHandleCheck => 3,
+            Self::SystemCallDisable => 4,
+            Self::ExtensionPointDisable => 6,
+            Self::ControlFlowGuard => 7,
+            Self::Signature => 8,
+            Self::FontDisable => 9,
+            Self::ImageLoad => 10,
+            Self::SystemCallFilter => 11,
+            Self::PayloadRestriction => 12,
+            Self::ChildProcess => 13,
+            Self::SideChannelIsolation => 14,
+            Self::UserShadowStack => 15,
+            Self::RedirectionTrust => 16,
+            Self::UserPointerAuth => 17,
+            Self::Sehop => 18,
+        })
+    }
+
+    pub fn from_raw(raw: PROCESS_MITIGATION_POLICY) -> Option<Self> {
+        Self::ALL.into_iter().find(|policy| policy.to_raw() == raw)
+    }
+
+    /// The fields of the policy's flags word.
+    pub const fn fields(self) -> &'static [FlagField] {
+        match self {
+            Self::Aslr => ASLR_POLICY_FIELDS,
+            Self::DynamicCode => DYNAMIC_CODE_POLICY_FIELDS,
+            Self::StrictHandleCheck => STRICT_HANDLE_CHECK_POLICY_FIELDS,
+            Self::SystemCallDisable => SYSTEM_CALL_DISABLE_POLICY_FIELDS,
+            Self::ExtensionPointDisable => EXTENSION_POINT_DISABLE_POLICY_FIELDS,
+            Self::ControlFlowGuard => CONTROL_FLOW_GUARD_POLICY_FIELDS,
+            Self::Signature => SIGNATURE_POLICY_FIELDS,
+            Self::FontDisable => FONT_DISABLE_POLICY_FIELDS,
+            Self::ImageLoad => IMAGE_LOAD_POLICY_FIELDS,
+            Self::SystemCallFilter => SYSTEM_CALL_FILTER_POLICY_FIELDS,
+            Self::PayloadRestriction => PAYLOAD_RESTRICTION_POLICY_FIELDS,
+            Self::ChildProcess => CHILD_PROCESS_POLICY_FIELDS,
+            Self::SideChannelIsolation => SIDE_CHANNEL_ISOLATION_POLICY_FIELDS,
+            Self::UserShadowStack => USER_SHADOW_STACK_POLICY_FIELDS,
+            Self::RedirectionTrust => REDIRECTION_TRUST_POLICY_FIELDS,
+            Self::UserPointerAuth => USER_POINTER_AUTH_POLICY_FIELDS,
+            Self::Sehop => SEHOP_POLICY_FIELDS,
+        }
+    }
+}
+
+pub const ASLR_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("enable_bottom_up_randomization", 0),
+    FlagField::bit("enable_force_relocate_images", 1),
+    FlagField::bit("enable_high_entropy", 2),
+    FlagField::bit("disallow_stripped_images", 3),
+];
+
+pub const DYNAMIC_CODE_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("prohibit_dynamic_code", 0),
+    FlagField::bit("allow_thread_opt_out", 1),
+    FlagField::bit("allow_remote_downgrade", 2),
+    FlagField::bit("audit_prohibit_dynamic_code", 3),
+];
+
+pub const STRICT_HANDLE_CHECK_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("raise_exception_on_invalid_handle_reference", 0),
+    FlagField::bit("handle_exceptions_permanently_enabled", 1),
+];
+
+pub const SYSTEM_CALL_DISABLE_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("disallow_win32k_system_calls", 0),
+    FlagField::bit("audit_disallow_win32k_system_calls", 1),
+    FlagField::bit("disallow_fsctl_system_calls", 2),
+    FlagField::bit("audit_disallow_fsctl_system_calls", 3),
+];
+
+pub const EXTENSION_POINT_DISABLE_POLICY_FIELDS: &[FlagField] =
+    &[FlagField::bit("disable_extension_points", 0)];
+
+pub const CONTROL_FLOW_GUARD_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("enable_control_flow_guard", 0),
+    FlagField::bit("enable_export_suppression", 1),
+    FlagField::bit("strict_mode", 2),
+    FlagField::bit("enable_xfg", 3),
+    FlagField::bit("enable_xfg_audit_mode", 4),
+];
+
+pub const SIGNATURE_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("microsoft_signed_only", 0),
+    FlagField::bit("store_signed_only", 1),
+    FlagField::bit("mitigation_opt_in", 2),
+    FlagField::bit("audit_microsoft_signed_only", 3),
+    FlagField::bit("audit_store_signed_only", 4),
+];
+
+pub const FONT_DISABLE_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("disable_non_system_fonts", 0),
+    FlagField::bit("audit_non_system_font_loading", 1),
+];
+
+pub const IMAGE_LOAD_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("no_remote_images", 0),
+    FlagField::bit("no_low_mandatory_label_images", 1),
+    FlagField::bit("prefer_system32_images", 2),
+    FlagField::bit("audit_no_remote_images", 3),
+    FlagField::bit("audit_no_low_mandatory_label_images", 4),
+];
+
+pub const SYSTEM_CALL_FILTER_POLICY_FIELDS: &[FlagField] = &[FlagField::field("filter_id", 0, 4)];
+
+pub const PAYLOAD_RESTRICTION_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("enable_export_address_filter", 0),
+    FlagField::bit("audit_export_address_filter", 1),
+    FlagField::bit("enable_export_address_filter_plus", 2),
+    FlagField::bit("audit_export_address_filter_plus", 3),
+    FlagField::bit("enable_import_address_filter", 4),
+    FlagField::bit("audit_import_address_filter", 5),
+    FlagField::bit("enable_rop_stack_pivot", 6),
+    FlagField::bit("audit_rop_stack_pivot", 7),
+    FlagField::bit("enable_rop_caller_check", 8),
+    FlagField::bit("audit_rop_caller_check", 9),
+    FlagField::bit("enable_rop_sim_exec", 10),
+    FlagField::bit("audit_rop_sim_exec", 11),
+];
+
+pub const CHILD_PROCESS_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("no_child_process_creation", 0),
+    FlagField::bit("audit_no_child_process_creation", 1),
+    FlagField::bit("allow_secure_process_creation", 2),
+];
+
+pub const SIDE_CHANNEL_ISOLATION_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("smt_branch_target_isolation", 0),
+    FlagField::bit("isolate_security_domain", 1),
+    FlagField::bit("disable_page_combine", 2),
+    FlagField::bit("speculative_store_bypass_disable", 3),
+    FlagField::bit("restrict_core_sharing", 4),
+];
+
+pub const USER_SHADOW_STACK_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("enable_user_shadow_stack", 0),
+    FlagField::bit("audit_user_shadow_stack", 1),
+    FlagField::bit("set_context_ip_validation", 2),
+    FlagField::bit("audit_set_context_ip_validation", 3),
+    FlagField::bit("enable_user_shadow_stack_strict_mode", 4),
+    FlagField::bit("block_non_cet_binaries", 5),
+    FlagField::bit("block_non_cet_binaries_non_ehcont", 6),
+    FlagField::bit("audit_block_non_cet_binaries", 7),
+    FlagField::bit("cet_dynamic_apis_out_of_proc_only", 8),
+    FlagField::bit("set_context_ip_validation_relaxed_mode", 9),
+];
+
+pub const REDIRECTION_TRUST_POLICY_FIELDS: &[FlagField] = &[
+    FlagField::bit("enforce_redirection_trust", 0),
+    FlagField::bit("audit_redirection_trust", 1),
+];
+
+pub const USER_POINTER_AUTH_POLICY_FIELDS: &[FlagField] =
+    &[FlagField::bit("enable_pointer_auth_user_ip", 0)];
+
+pub const SEHOP_POLICY_FIELDS: &[FlagField] = &[FlagField::bit("enable_sehop", 0)];
+
+/// One policy of a process and its flags word.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct PolicyState {
+    pub policy: MitigationPolicy,
+    pub flags: u32,
+}
+
+impl PolicyState {
+    pub const fn new(policy: MitigationPolicy) -> Self {
+        Self { policy, flags: 0 }
+    }
+
+    pub fn decode(&self) -> DecodedFlags {
+        DecodedFlags::new(self.flags as u64, self.policy.fields())
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.field(name)
+            .map(|field| field.extract(self.flags as u64))
+    }
+
+    pub fn is_set(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|value| value != 0)
+    }
+
+    /// Sets the field `name` to `value`, returning `false` when the policy
+    /// has no such field or the value does not fit.
+    pub fn set(&mut self, name: &str, value: u32) -> bool {
+        let Some(field) = self.field(name) else {
+            return false;
+        };
+        let mask = (1u32 << field.width) - 1;
+        if value > mask {
+            return false;
+        }
+        self.flags = (self.flags & !(mask << field.shift)) | value << field.shift;
+        true
+    }
+
+    /// [`set`](Self::set) with the field on or off, for chaining.
+    pub fn with(mut self, name: &str, enabled: bool) -> Self {
+        self.set(name, enabled as u32);
+        self
+    }
+
+    fn field(&self, name: &str) -> Option<&'static FlagField> {
+        self.policy.fields().iter().find(|field| field.name == name)
+    }
+
+    /// Decodes a `PROCESS_MITIGATION_POLICY_INFORMATION`.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let raw = PROCESS_MITIGATION_POLICY(reader.i32()?);
+        let policy = MitigationPolicy::from_raw(raw).ok_or(reader.invalid("mitigation policy"))?;
+        Ok(Self {
+            policy,
+            flags: reader.u32()?,
+        })
+    }
+
+    /// Encodes a `PROCESS_MITIGATION_POLICY_INFORMATION`.
+    pub fn to_bytes(&self) -> [u8; POLICY_INFORMATION_SIZE] {
+        let mut writer = Writer::default();
+        writer.u32(self.policy.to_raw().0 as u32).u32(self.flags);
+        let mut bytes = [0; POLICY_INFORMATION_SIZE];
+        bytes.copy_from_slice(writer.as_slice());
+        bytes
+    }
+
+    /// Reads `policy` of `process`, which needs
+    /// `PROCESS_QUERY_LIMITED_INFORMATION` access.
+    pub fn query(process: HANDLE, policy: MitigationPolicy) -> Result<Self, NTSTATUS> {
+        let mut bytes = Self::new(policy).to_bytes();
+        check(unsafe {
+            NtQueryInformationProcess(
+                process,
+                ProcessMitigationPolicy,
+                bytes.as_mut_ptr().cast(),
+                bytes.len() as u32,
+                ptr::null_mut(),
+            )
+        })?;
+        Ok(Self {
+            policy,
+            flags: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+
+    /// Applies the policy to `process`, which needs `PROCESS_SET_INFORMATION`
+    /// access. Most policies can only be turned on, and some only for the
+    /// current process.
+    pub fn apply(&self, process: HANDLE) -> Result<(), NTSTATUS> {
+        let mut bytes = self.to_bytes();
+        check(unsafe {
+            NtSetInformationProcess(
+                process,
+                ProcessMitigationPolicy,
+                bytes.as_mut_ptr().cast(),
+                bytes.len() as u32,
+            )
+        })
+    }
+}
+
+/// Every mitigation policy of a process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mitigations {
+    pub policies: Vec<PolicyState>,
+    /// The policies that could not be read, e.g. on systems that predate
+    /// them.
+    pub unavailable: Vec<(MitigationPolicy, NTSTATUS)>,
+}
+
+impl Mitigations {
+    pub fn query(process: HANDLE) -> Self {
+        let mut mitigations = Self::default();
+        for policy in MitigationPolicy::ALL {
+            match PolicyState::query(process, policy) {
+                Ok(state) => mitigations.policies.push(state),
+                Err(status) => mitigations.unavailable.push((policy, status)),
+            }
+        }
+        mitigations
+    }
+
+    pub fn get(&self, policy: MitigationPolicy) -> Option<&PolicyState> {
+        self.policies.iter().find(|state| state.policy == policy)
+    }
+
+    /// Whether the field `name` of `policy` is set.
+    pub fn is_set(&self, policy: MitigationPolicy, name: &str) -> bool {
+        self.get(policy).is_some_and(|state| state.is_set(name))
+    }
+}
+
+/// The state of one option in a mitigation options map.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub enum OptionState {
+    /// Left to the image and system defaults.
+    #[default]
+    Defer,
+    AlwaysOn,
+    AlwaysOff,
+    /// The option's third state, e.g. requiring relocations for
+    /// `FORCE_RELOCATE_IMAGES`, allowing thread opt-out for
+    /// `PROHIBIT_DYNAMIC_CODE`, strict mode for `CET_USER_SHADOW_STACKS` or
+    /// audit mode for `LOADER_INTEGRITY_CONTINUITY`.
+    Extended,
+    Other(u8),
+}
+
+impl OptionState {
+    pub const fn from_nibble(nibble: u8) -> Self {
+        match nibble & 0xf {
+            0 => Self::Defer,
+            1 => Self::AlwaysOn,
+            2 => Self::AlwaysOff,
+            3 => Self::Extended,
+            nibble => Self::Other(nibble),
+        }
+    }
+
+    pub const fn to_nibble(self) -> u8 {
+        match self {
+            Self::Defer => 0,
+            Self::AlwaysOn => 1,
+            Self::AlwaysOff => 2,
+            Self::Extended => 3,
+            Self::Other(nibble) => nibble & 0xf,
+        }
+    }
+}
+
+/// The position of an option in a mitigation options map, in nibbles.
+///
+/// Positions follow the `PROCESS_CREATION_MITIGATION_POLICY_*` and
+/// `PROCESS_CREATION_MITIGATION_POLICY2_*` layout, which is what
+/// `PS_ATTRIBUTE_MITIGATION_OPTIONS` carries: the second word starts at 16.
+/// Nibble 0 holds the DEP and SEHOP bits instead of a state; see
+/// [`MitigationOptions::dep`].
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MitigationOption(pub u8);
+
+impl MitigationOption {
+    pub const FORCE_RELOCATE_IMAGES: Self = Self(2);
+    pub const HEAP_TERMINATE: Self = Self(3);
+    pub const BOTTOM_UP_ASLR: Self = Self(4);
+    pub const HIGH_ENTROPY_ASLR: Self = Self(5);
+    pub const STRICT_HANDLE_CHECKS: Self = Self(6);
+    pub const WIN32K_SYSTEM_CALL_DISABLE: Self = Self(7);
+    pub const EXTENSION_POINT_DISABLE: Self = Self(8);
+    pub const PROHIBIT_DYNAMIC_CODE: Self = Self(9);
+    pub const CONTROL_FLOW_GUARD: Self = Self(10);
+    pub const BLOCK_NON_MICROSOFT_BINARIES: Self = Self(11);
+    pub const FONT_DISABLE: Self = Self(12);
+    pub const IMAGE_LOAD_NO_REMOTE: Self = Self(13);
+    pub const IMAGE_LOAD_NO_LOW_LABEL: Self = Self(14);
+    pub const IMAGE_LOAD_PREFER_SYSTEM32: Self = Self(15);
+    pub const LOADER_INTEGRITY_CONTINUITY: Self = Self(17);
+    pub const STRICT_CONTROL_FLOW_GUARD: Self = Self(18);
+    pub const MODULE_TAMPERING_PROTECTION: Self = Self(19);
+    pub const RESTRICT_INDIRECT_BRANCH_PREDICTION: Self = Self(20);
+    pub const ALLOW_DOWNGRADE_DYNAMIC_CODE_POLICY: Self = Self(21);
+    pub const SPECULATIVE_STORE_BYPASS_DISABLE: Self = Self(22);
+    pub const CET_USER_SHADOW_STACKS: Self = Self(23);
+    pub const USER_CET_SET_CONTEXT_IP_VALIDATION: Self = Self(24);
+    pub const BLOCK_NON_CET_BINARIES: Self = Self(25);
+    pub const XTENDED_CONTROL_FLOW_GUARD: Self = Self(26);
+    pub const POINTER_AUTH_USER_IP: Self = Self(27);
+    pub const CET_DYNAMIC_APIS_OUT_OF_PROC_ONLY: Self = Self(28);
+    pub const RESTRICT_CORE_SHARING: Self = Self(29);
+    pub const FSCTL_SYSTEM_CALL_DISABLE: Self = Self(30);
+
+    /// The map word and bit shift of the option.
+    pub const fn position(self) -> (usize, u32) {
+        (self.0 as usize / 16, (self.0 as u32 % 16) * 4)
+    }
+
+    pub fn name(self) -> Option<&'static str> {
+        OPTION_NAMES
+            .iter()
+            .find(|(option, _)| *option == self)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl fmt::Debug for MitigationOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "MitigationOption::{name}"),
+            None => write!(f, "MitigationOption({})", self.0),
+        }
+    }
+}
+
+const OPTION_NAMES: &[(MitigationOption, &str)] = &[
+    (
+        MitigationOption::FORCE_RELOCATE_IMAGES,
+        "FORCE_RELOCATE_IMAGES",
+    ),
+    (MitigationOption::HEAP_TERMINATE, "HEAP_TERMINATE"),
+    (MitigationOption::BOTTOM_UP_ASLR, "BOTTOM_UP_ASLR"),
+    (MitigationOption::HIGH_ENTROPY_ASLR, "HIGH_ENTROPY_ASLR"),
+    (
+        MitigationOption::STRICT_HANDLE_CHECKS,
+        "STRICT_HANDLE_CHECKS",
+    ),
+    (
+        MitigationOption::WIN32K_SYSTEM_CALL_DISABLE,
+        "WIN32K_SYSTEM_CALL_DISABLE",
+    ),
+    (
+        MitigationOption::EXTENSION_POINT_DISABLE,
+        "EXTENSION_POINT_DISABLE",
+    ),
+    (
+        MitigationOption::PROHIBIT_DYNAMIC_CODE,
+        "PROHIBIT_DYNAMIC_CODE",
+    ),
+    (MitigationOption::CONTROL_FLOW_GUARD, "CONTROL_FLOW_GUARD"),
+    (
+        MitigationOption::BLOCK_NON_MICROSOFT_BINARIES,
+        "BLOCK_NON_MICROSOFT_BINARIES",
+    ),
+    (MitigationOption::FONT_DISABLE, "FONT_DISABLE"),
+    (
+        MitigationOption::IMAGE_LOAD_NO_REMOTE,
+        "IMAGE_LOAD_NO_REMOTE",
+    ),
+    (
+        MitigationOption::IMAGE_LOAD_NO_LOW_LABEL,
+        "IMAGE_LOAD_NO_LOW_LABEL",
+    ),
+    (
+        MitigationOption::IMAGE_LOAD_PREFER_SYSTEM32,
+        "IMAGE_LOAD_PREFER_SYSTEM32",
+    ),
+    (
+        MitigationOption::LOADER_INTEGRITY_CONTINUITY,
+        "LOADER_INTEGRITY_CONTINUITY",
+    ),
+    (
+        MitigationOption::STRICT_CONTROL_FLOW_GUARD,
+        "STRICT_CONTROL_FLOW_GUARD",
+    ),
+    (
+        MitigationOption::MODULE_TAMPERING_PROTECTION,
+        "MODULE_TAMPERING_PROTECTION",
+    ),
+    (
+        MitigationOption::RESTRICT_INDIRECT_BRANCH_PREDICTION,
+        "RESTRICT_INDIRECT_BRANCH_PREDICTION",
+    ),
+    (
+        MitigationOption::ALLOW_DOWNGRADE_DYNAMIC_CODE_POLICY,
+        "ALLOW_DOWNGRADE_DYNAMIC_CODE_POLICY",
+    ),
+    (
+        MitigationOption::SPECULATIVE_STORE_BYPASS_DISABLE,
+        "SPECULATIVE_STORE_BYPASS_DISABLE",
+    ),
+    (
+        MitigationOption::CET_USER_SHADOW_STACKS,
+        "CET_USER_SHADOW_STACKS",
+    ),
+    (
+        MitigationOption::USER_CET_SET_CONTEXT_IP_VALIDATION,
+        "USER_CET_SET_CONTEXT_IP_VALIDATION",
+    ),
+    (
+        MitigationOption::BLOCK_NON_CET_BINARIES,
+        "BLOCK_NON_CET_BINARIES",
+    ),
+    (
+        MitigationOption::XTENDED_CONTROL_FLOW_GUARD,
+        "XTENDED_CONTROL_FLOW_GUARD",
+    ),
+    (
+        MitigationOption::POINTER_AUTH_USER_IP,
+        "POINTER_AUTH_USER_IP",
+    ),
+    (
+        MitigationOption::CET_DYNAMIC_APIS_OUT_OF_PROC_ONLY,
+        "CET_DYNAMIC_APIS_OUT_OF_PROC_ONLY",
+    ),
+    (
+        MitigationOption::RESTRICT_CORE_SHARING,
+        "RESTRICT_CORE_SHARING",
+    ),
+    (
+        MitigationOption::FSCTL_SYSTEM_CALL_DISABLE,
+        "FSCTL_SYSTEM_CALL_DISABLE",
+    ),
+];
+
+/// How an option follows from a policy: on when `field` is set, and in its
+/// extended state when `extended` is set too.
+struct OptionSource {
+    option: MitigationOption,
+    policy: MitigationPolicy,
+    field: &'static str,
+    extended: Option<&'static str>,
+}
+
+const fn source(
+    option: MitigationOption,
+    policy: MitigationPolicy,
+    field: &'static str,
+    extended: Option<&'static str>,
+) -> OptionSource {
+    OptionSource {
+        option,
+        policy,
+        field,
+        extended,
+    }
+}
+
+const OPTION_SOURCES: &[OptionSource] = {
+    use MitigationOption as O;
+    use MitigationPolicy as P;
+    &[
+        source(
+            O::FORCE_RELOCATE_IMAGES,
+            P::Aslr,
+            "enable_force_relocate_images",
+            Some("disallow_stripped_images"),
+        ),
+        source(
+            O::BOTTOM_UP_ASLR,
+            P::Aslr,
+            "enable_bottom_up_randomization",
+            None,
+        ),
+        source(O::HIGH_ENTROPY_ASLR, P::Aslr, "enable_high_entropy", None),
+        source(
+            O::STRICT_HANDLE_CHECKS,
+            P::StrictHandleCheck,
+            "raise_exception_on_invalid_handle_reference",
+            None,
+        ),
+        source(
+            O::WIN32K_SYSTEM_CALL_DISABLE,
+            P::SystemCallDisable,
+            "disallow_win32k_system_calls",
+            None,
+        ),
+        source(
+            O::EXTENSION_POINT_DISABLE,
+            P::ExtensionPointDisable,
+            "disable_extension_points",
+            None,
+        ),
+        source(
+            O::PROHIBIT_DYNAMIC_CODE,
+            P::DynamicCode,
+            "prohibit_dynamic_code",
+            Some("allow_thread_opt_out"),
+        ),
+        source(
+            O::CONTROL_FLOW_GUARD,
+            P::ControlFlowGuard,
+            "enable_control_flow_guard",
+            Some("enable_export_suppression"),
+        ),
+        source(
+            O::BLOCK_NON_MICROSOFT_BINARIES,
+            P::Signature,
+            "microsoft_signed_only",
+            Some("store_signed_only"),
+        ),
+        source(
+            O::FONT_DISABLE,
+            P::FontDisable,
+            "disable_non_system_fonts",
+            None,
+        ),
+        source(
+            O::IMAGE_LOAD_NO_REMOTE,
+            P::ImageLoad,
+            "no_remote_images",
+            None,
+        ),
+        source(
+            O::IMAGE_LOAD_NO_LOW_LABEL,
+            P::ImageLoad,
+            "no_low_mandatory_label_images",
+            None,
+        ),
+        source(
+            O::IMAGE_LOAD_PREFER_SYSTEM32,
+            P::ImageLoad,
+            "prefer_system32_images",
+            None,
+        ),
+        source(
+            O::STRICT_CONTROL_FLOW_GUARD,
+            P::ControlFlowGuard,
+            "strict_mode",
+            None,
+        ),
+        source(
+            O::RESTRICT_INDIRECT_BRANCH_PREDICTION,
+            P::SideChannelIsolation,
+            "smt_branch_target_isolation",
+            None,
+        ),
+        source(
+            O::ALLOW_DOWNGRADE_DYNAMIC_CODE_POLICY,
+            P::DynamicCode,
+            "allow_remote_downgrade",
+            None,
+        ),
+        source(
+            O::SPECULATIVE_STORE_BYPASS_DISABLE,
+            P::SideChannelIsolation,
+            "speculative_store_bypass_disable",
+            None,
+        ),
+        source(
+            O::CET_USER_SHADOW_STACKS,
+            P::UserShadowStack,
+            "enable_user_shadow_stack",
+            Some("enable_user_shadow_stack_strict_mode"),
+        ),
+        source(
+            O::USER_CET_SET_CONTEXT_IP_VALIDATION,
+            P::UserShadowStack,
+            "set_context_ip_validation",
+            Some("set_context_ip_validation_relaxed_mode"),
+        ),
+        source(
+            O::BLOCK_NON_CET_BINARIES,
+            P::UserShadowStack,
+            "block_non_cet_binaries",
+            Some("block_non_cet_binaries_non_ehcont"),
+        ),
+        source(
+            O::XTENDED_CONTROL_FLOW_GUARD,
+            P::ControlFlowGuard,
+            "enable_xfg",
+            None,
+        ),
+        source(
+            O::POINTER_AUTH_USER_IP,
+            P::UserPointerAuth,
+            "enable_pointer_auth_user_ip",
+            None,
+        ),
+        source(
+            O::CET_DYNAMIC_APIS_OUT_OF_PROC_ONLY,
+            P::UserShadowStack,
+            "cet_dynamic_apis_out_of_proc_only",
+            None,
+        ),
+        source(
+            O::RESTRICT_CORE_SHARING,
+            P::SideChannelIsolation,
+            "restrict_core_sharing",
+            None,
+        ),
+        source(
+            O::FSCTL_SYSTEM_CALL_DISABLE,
+            P::SystemCallDisable,
+            "disallow_fsctl_system_calls",
+            None,
+        ),
+    ]
+};
+
+/// A mitigation options map, as `PS_MITIGATION_OPTIONS_MAP` and
+/// `PS_MITIGATION_AUDIT_OPTIONS_MAP`: up to three words of 4-bit options.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct MitigationOptions {
+    pub map: [u64; 3],
+}
+
+impl MitigationOptions {
+    /// `PROCESS_CREATION_MITIGATION_POLICY_DEP_ENABLE`.
+    pub const DEP_ENABLE: u64 = 0x1;
+    /// `PROCESS_CREATION_MITIGATION_POLICY_DEP_ATL_THUNK_ENABLE`.
+    pub const DEP_ATL_THUNK_ENABLE: u64 = 0x2;
+    /// `PROCESS_CREATION_MITIGATION_POLICY_SEHOP_ENABLE`.
+    pub const SEHOP_ENABLE: u64 = 0x4;
+
+    pub fn get(&self, option: MitigationOption) -> OptionState {
+        let (word, shift) = option.position();
+        match self.map.get(word) {
+            Some(value) => OptionState::from_nibble((value >> shift) as u8),
+            None => OptionState::Defer,
+        }
+    }
+
+    /// Sets `option`, returning `false` when it is past the end of the map
+    /// or is nibble 0.
+    pub fn set(&mut self, option: MitigationOption, state: OptionState) -> bool {
+        let (word, shift) = option.position();
+        let Some(value) = self.map.get_mut(word).filter(|_| option.0 != 0) else {
+            return false;
+        };
+        *value = (*value & !(0xf << shift)) | (state.to_nibble() as u64) << shift;
+        true
+    }
+
+    /// [`set`](Self::set), for chaining.
+    pub fn with(mut self, option: MitigationOption, state: OptionState) -> Self {
+        self.set(option, state);
+        self
+    }
+
+    /// The DEP and SEHOP bits of nibble 0.
+    pub fn dep(&self) -> u64 {
+        self.map[0] & 0xf
+    }
+
+    pub fn set_dep(&mut self, bits: u64) {
+        self.map[0] = (self.map[0] & !0xf) | (bits & 0xf);
+    }
+
+    /// The options that are not deferred, in map order.
+    pub fn options(&self) -> Vec<(MitigationOption, OptionState)> {
+        (1..self.map.len() as u8 * 16)
+            .map(MitigationOption)
+            .map(|option| (option, self.get(option)))
+            .filter(|(_, state)| *state != OptionState::Defer)
+            .collect()
+    }
+
+    /// The options that turn on the mitigations enabled in `policies`, so a
+    /// new process gets the mitigations of a running one. Options without a
+    /// policy to follow, like `HEAP_TERMINATE`, stay deferred.
+    pub fn from_policies(policies: &[PolicyState]) -> Self {
+        let mut options = Self::default();
+        if policies
+            .iter()
+            .any(|state| state.policy == MitigationPolicy::Sehop && state.is_set("enable_sehop"))
+        {
+            options.set_dep(Self::SEHOP_ENABLE);
+        }
+        for source in OPTION_SOURCES {
+            let Some(state) = policies.iter().find(|state| state.policy == source.policy) else {
+                continue;
+            };
+            if !state.is_set(source.field) {
+                continue;
+            }
+            let extended = source.extended.is_some_and(|field| state.is_set(field));
+            options.set(
+                source.option,
+                if extended {
+                    OptionState::Extended
+                } else {
+                    OptionState::AlwaysOn
+                },
+            );
+        }
+        options
+    }
+
+    /// The attribute value: as many words as the options use, at least one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words = self
+            .map
+            .iter()
+            .rposition(|word| *word != 0)
+            .map_or(1, |last| last + 1);
+        let mut writer = Writer::default();
+        for word in &self.map[..words] {
+            writer.u64(*word);
+        }
+        writer.into_inner()
+    }
+
+    /// Decodes an attribute value of one to three words.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        if bytes.is_empty() || !bytes.len().is_multiple_of(8) || bytes.len() > 24 {
+            return Err(reader.invalid("mitigation options length"));
+        }
+        let mut options = Self::default();
+        for word in options.map.iter_mut().take(bytes.len() / 8) {
+            *word = reader.u64()?;
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATES: [OptionState; 4] = [
+        OptionState::Defer,
+        OptionState::AlwaysOn,
+        OptionState::AlwaysOff,
+        OptionState::Extended,
+    ];
+
+    /// Every nibble but 0 of a full three-word map.
+    fn every_option() -> impl Iterator<Item = MitigationOption> {
+        (1..48).map(MitigationOption)
+    }
+
+    #[test]
+    fn places_every_option() {
+        for option in every_option() {
+            for state in STATES {
+                let options = MitigationOptions::default().with(option, state);
+                let (word, shift) = option.position();
+                let mut expected = [0; 3];
+                expected[word] = (state.to_nibble() as u64) << shift;
+                assert_eq!(options.map, expected, "{option:?} {state:?}");
+                assert_eq!(options.get(option), state);
+                assert_eq!(
+                    options.options(),
+                    if state == OptionState::Defer {
+                        vec![]
+                    } else {
+                        vec![(option, state)]
+                    }
+                );
+                // A deferred option leaves the map empty, which still takes a
+                // word.
+                let bytes = options.to_bytes();
+                let words = if state == OptionState::Defer {
+                    1
+                } else {
+                    word + 1
+                };
+                assert_eq!(bytes.len(), 8 * words);
+                assert_eq!(MitigationOptions::parse(&bytes).unwrap(), options);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_other_options_alone() {
+        // Every slot starts in a state no option uses, so a write that
+        // spills into a neighbouring nibble shows.
+        let background = MitigationOptions {
+            map: [0xeeee_eeee_eeee_eeee; 3],
+        };
+        for option in every_option() {
+            for state in STATES {
+                let mut options = background;
+                assert!(options.set(option, state));
+                assert_eq!(options.get(option), state);
+                for other in every_option().filter(|other| *other != option) {
+                    assert_eq!(options.get(other), OptionState::Other(0xe), "{other:?}");
+                }
+                assert_eq!(options.dep(), 0xe);
+                assert!(options.set(option, OptionState::Other(0xe)));
+                assert_eq!(options, background);
+                let parsed = MitigationOptions::parse(&options.to_bytes()).unwrap();
+                assert_eq!(parsed, options);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_nibble_zero_for_dep() {
+        let mut options = MitigationOptions::default();
+        assert!(!options.set(MitigationOption(0), OptionState::AlwaysOn));
+        assert!(!options.set(MitigationOption(48), OptionState::AlwaysOn));
+        assert_eq!(options.get(MitigationOption(48)), OptionState::Defer);
+        assert_eq!(options, MitigationOptions::default());
+
+        options.set_dep(MitigationOptions::DEP_ENABLE | MitigationOptions::SEHOP_ENABLE);
+        options.set(MitigationOption(1), OptionState::AlwaysOff);
+        assert_eq!(options.dep(), 0x5);
+        options.set_dep(MitigationOptions::DEP_ATL_THUNK_ENABLE | 0xf0);
+        assert_eq!(options.map, [0x22, 0, 0]);
+        assert_eq!(
+            options.options(),
+            [(MitigationOption(1), OptionState::AlwaysOff)]
+        );
+    }
+
+    #[test]
+    fn masks_nibbles() {
+        assert_eq!(OptionState::from_nibble(0x21), OptionState::AlwaysOn);
+        assert_eq!(OptionState::from_nibble(0xf3), OptionState::Extended);
+        assert_eq!(OptionState::from_nibble(0x7), OptionState::Other(7));
+        assert_eq!(OptionState::Other(0x1e).to_nibble(), 0xe);
+        for nibble in 0..16 {
+            assert_eq!(OptionState::from_nibble(nibble).to_nibble(), nibble);
+        }
+        let options = MitigationOptions::default()
+            .with(MitigationOption::HEAP_TERMINATE, OptionState::Other(0x1f));
+        assert_eq!(options.map, [0xf000, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_known_maps() {
+        // PROCESS_CREATION_MITIGATION_POLICY_BLOCK_NON_MICROSOFT_BINARIES_ALWAYS_ON,
+        // the usual guard against injected DLLs.
+        let options = MitigationOptions::parse(&0x1000_0000_0000_u64.to_le_bytes()).unwrap();
+        assert_eq!(
+            options.options(),
+            [(
+                MitigationOption::BLOCK_NON_MICROSOFT_BINARIES,
+                OptionState::AlwaysOn,
+            )]
+        );
+        assert_eq!(options.to_bytes(), 0x1000_0000_0000_u64.to_le_bytes());
+
+        // DEP and SEHOP, FORCE_RELOCATE_IMAGES_ALWAYS_ON_REQ_RELOCS,
+        // HEAP_TERMINATE, BOTTOM_UP_ASLR, HIGH_ENTROPY_ASLR and
+        // STRICT_HANDLE_CHECKS on, PROHIBIT_DYNAMIC_CODE_ALWAYS_OFF,
+        // CONTROL_FLOW_GUARD_EXPORT_SUPPRESSION,
+        // BLOCK_NON_MICROSOFT_BINARIES_ALLOW_STORE, IMAGE_LOAD_NO_REMOTE and
+        // IMAGE_LOAD_PREFER_SYSTEM32 on; then in the second word
+        // LOADER_INTEGRITY_CONTINUITY_AUDIT, STRICT_CONTROL_FLOW_GUARD on,
+        // MODULE_TAMPERING_PROTECTION_NOINHERIT,
+        // CET_USER_SHADOW_STACKS_STRICT_MODE,
+        // USER_CET_SET_CONTEXT_IP_VALIDATION_ALWAYS_OFF and
+        // FSCTL_SYSTEM_CALL_DISABLE on.
+        let bytes = [
+            0x05, 0x13, 0x11, 0x01, 0x20, 0x33, 0x10, 0x10, 0x30, 0x31, 0x00, 0x30, 0x02, 0x00,
+            0x00, 0x01,
+        ];
+        let options = MitigationOptions::parse(&bytes).unwrap();
+        assert_eq!(
+            options.map,
+            [0x1010_3320_0111_1305, 0x0100_0002_3000_3130, 0]
+        );
+        assert_eq!(
+            options.dep(),
+            MitigationOptions::DEP_ENABLE | MitigationOptions::SEHOP_ENABLE
+        );
+        use MitigationOption as O;
+        use OptionState as S;
+        let expected = [
+            (O::FORCE_RELOCATE_IMAGES, S::Extended),
+            (O::HEAP_TERMINATE, S::AlwaysOn),
+            (O::BOTTOM_UP_ASLR, S::AlwaysOn),
+            (O::HIGH_ENTROPY_ASLR, S::AlwaysOn),
+            (O::STRICT_HANDLE_CHECKS, S::AlwaysOn),
+            (O::PROHIBIT_DYNAMIC_CODE, S::AlwaysOff),
+            (O::CONTROL_FLOW_GUARD, S::Extended),
+            (O::BLOCK_NON_MICROSOFT_BINARIES, S::Extended),
+            (O::IMAGE_LOAD_NO_REMOTE, S::AlwaysOn),
+            (O::IMAGE_LOAD_PREFER_SYSTEM32, S::AlwaysOn),
+            (O::LOADER_INTEGRITY_CONTINUITY, S::Extended),
+            (O::STRICT_CONTROL_FLOW_GUARD, S::AlwaysOn),
+            (O::MODULE_TAMPERING_PROTECTION, S::Extended),
+            (O::CET_USER_SHADOW_STACKS, S::Extended),
+            (O::USER_CET_SET_CONTEXT_IP_VALIDATION, S::AlwaysOff),
+            (O::FSCTL_SYSTEM_CALL_DISABLE, S::AlwaysOn),
+        ];
+        assert_eq!(options.options(), expected);
+
+        let mut built = MitigationOptions::default();
+        built.set_dep(MitigationOptions::DEP_ENABLE | MitigationOptions::SEHOP_ENABLE);
+        for (option, state) in expected {
+            built.set(option, state);
+        }
+        assert_eq!(built, options);
+        assert_eq!(built.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn sizes_attribute_values() {
+        assert_eq!(MitigationOptions::default().to_bytes(), [0; 8]);
+        let third = MitigationOptions::default().with(MitigationOption(40), OptionState::AlwaysOn);
+        assert_eq!(third.to_bytes().len(), 24);
+        assert_eq!(MitigationOptions::parse(&third.to_bytes()).unwrap(), third);
+        for length in [0, 4, 12, 32] {
+            assert!(
+                matches!(
+                    MitigationOptions::parse(&vec![0; length]),
+                    Err(DecodeError::Invalid { offset: 0, .. })
+                ),
+                "{length}"
+            );
+        }
+    }
+
+    #[test]
+    fn follows_running_policies() {
+        let policies = [
+            PolicyState::new(MitigationPolicy::Sehop).with("enable_sehop", true),
+            PolicyState::new(MitigationPolicy::Aslr)
+                .with("enable_bottom_up_randomization", true)
+                .with("enable_force_relocate_images", true)
+                .with("disallow_stripped_images", true),
+            PolicyState::new(MitigationPolicy::DynamicCode).with("prohibit_dynamic_code", true),
+            PolicyState::new(MitigationPolicy::ImageLoad).with("prefer_system32_images", true),
+            PolicyState::new(MitigationPolicy::ControlFlowGuard).with("strict_mode", true),
+            PolicyState::new(MitigationPolicy::UserShadowStack)
+                .with("enable_user_shadow_stack", true)
+                .with("enable_user_shadow_stack_strict_mode", true),
+            PolicyState::new(MitigationPolicy::SideChannelIsolation),
+        ];
+        assert_eq!(
+            MitigationOptions::from_policies(&policies).map,
+            [0x1000_0010_0001_0304, 0x3000_0100, 0]
+        );
+        assert_eq!(
+            MitigationOptions::from_policies(&[]),
+            MitigationOptions::default()
+        );
+    }
+
+    #[test]
+    fn names_options() {
+        assert_eq!(
+            format!("{:?}", MitigationOption::CET_USER_SHADOW_STACKS),
+            "MitigationOption::CET_USER_SHADOW_STACKS"
+        );
+        assert_eq!(
+            format!("{:?}", MitigationOption(16)),
+            "MitigationOption(16)"
+        );
+        assert_eq!(
+            MitigationOption::FSCTL_SYSTEM_CALL_DISABLE.position(),
+            (1, 56)
+        );
+        assert_eq!(
+            MitigationOption::IMAGE_LOAD_PREFER_SYSTEM32.position(),
+            (0, 60)
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Processes through the `NtQueryInformationProcess` family.
+//!
+//! [`Mitigations`] reads every mitigation policy of a process and
+//! [`PolicyState`] decodes one policy's flags by name and writes it back.
+//! [`MitigationOptions`] encodes the option maps that
+//! `PS_ATTRIBUTE_MITIGATION_OPTIONS` and
+//! `PS_ATTRIBUTE_MITIGATION_AUDIT_OPTIONS` take at process creation, one
+//! nibble per option, and can be derived from the policies of a running
+//! process.
+//!
+//! Each information class with a known layout is a type implementing
+//! [`ProcessInfo`] or [`ThreadInfo`] for queries and [`SetProcessInfo`] or
+//! [`SetThreadInfo`] for sets, naming its input, its output and the access
+//! the handle needs. [`ProcessBackend`] negotiates the buffer size and
+//! decodes the result; strings the kernel returns as a `UNICODE_STRING`
+//! into the same buffer come back as `String`.
+//!
+//! [`HandleTracer`] turns on the kernel's handle tracing for a process and
+//! reads back the ring of opens and closes with their stacks;
+//! [`find_leaks`] pairs them up to find the handles that were never closed.
+//!
+//! ```no_run
+//! use windows::Win32::Foundation::HANDLE;
+//! use windows_native::process::*;
+//!
+//! // The pseudo-handle for the current process.
+//! let mitigations = Mitigations::query(HANDLE(-1));
+//! for policy in &mitigations.policies {
+//!     println!("{:?}: {:?}", policy.policy, policy.decode().fields);
+//! }
+//! let options = MitigationOptions::from_policies(&mitigations.policies)
+//!     .with(MitigationOption::HEAP_TERMINATE, OptionState::AlwaysOn);
+//! println!("{:02x?}", options.to_bytes());
+//!
+//! let image = query_process::<ProcessImageFileName>(HANDLE(-1))?;
+//! let command_line = query_process::<ProcessCommandLineInformation>(HANDLE(-1))?;
+//! let protection = query_process::<ProcessProtectionInformation>(HANDLE(-1))?;
+//! println!("{image} ({protection}): {command_line}");
+//! // The pseudo-handle for the current thread.
+//! if let Err(status) = set_thread::<ThreadNameInformation>(HANDLE(-2), "worker") {
+//!     eprintln!("cannot name the thread: {status:?}");
+//! }
+//! println!("{}", query_thread::<ThreadNameInformation>(HANDLE(-2))?);
+//!
+//! let tracer = HandleTracer::new(NativeProcess, HANDLE(-1));
+//! if tracer.enable(None).is_ok() {
+//!     // ... let the process run ...
+//!     for site in tracer.leaks()?.by_stack() {
+//!         println!("{} handles leaked from {:x?}", site.handles.len(), site.stack);
+//!     }
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod class;
+mod handle_trace;
+mod info;
+mod mitigation;
+
+pub use class::*;
+pub use handle_trace::*;
+pub use info::*;
+pub use mitigation::*;
@@ -0,0 +1,1809 @@
+//! Safe wrappers over the SAM client API bound in [`crate::ntsam`].
+//!
+//! Server, domain, user, group and alias handles close themselves on drop,
+//! enumerations page through the `Sam*Enumerate*` functions with resume
+//! handles, and each supported information class is an owned struct read
+//! with [`SamHandle::query`] and written with [`SamHandle::set`]. Every call
+//! goes through a [`SamBackend`]; [`NativeSam`] is the real implementation
+//! and tests can substitute their own.
+//!
+//! ```no_run
+//! use windows_native::sam::*;
+//!
+//! let server = SamServer::connect(None, SAM_SERVER_LOOKUP_DOMAIN | SAM_SERVER_ENUMERATE_DOMAINS)?;
+//! let domain = server.open_domain_by_name("Builtin", DOMAIN_LIST_ACCOUNTS)?;
+//! for alias in domain.aliases() {
+//!     println!("{:?}", alias?);
+//! }
+//! # Ok::<(), windows::Win32::Foundation::NTSTATUS>(())
+//! ```
+
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ptr,
+};
+
+use windows::{
+    Wdk::Foundation::OBJECT_ATTRIBUTES,
+    Win32::{
+        Foundation::{
+            BOOLEAN, NTSTATUS, PSID, STATUS_INVALID_INFO_CLASS, STATUS_INVALID_PARAMETER,
+            STATUS_MORE_ENTRIES, STATUS_NO_MORE_ENTRIES, STATUS_NONE_MAPPED, UNICODE_STRING,
+        },
+        Security::{
+            Authentication::Identity::{
+                DOMAIN_PASSWORD_INFORMATION, DOMAIN_PASSWORD_PROPERTIES, LOGON_HOURS,
+                LSA_UNICODE_STRING, USER_ALL_INFORMATION,
+            },
+            SID_NAME_USE, SidTypeUnknown,
+        },
+    },
+};
+
+pub use crate::ntsam::{
+    ALIAS_INFORMATION_CLASS, DOMAIN_ALL_ACCESS, DOMAIN_INFORMATION_CLASS, DOMAIN_LIST_ACCOUNTS,
+    DOMAIN_LOOKUP, DOMAIN_READ_OTHER_PARAMETERS, DOMAIN_READ_PASSWORD_PARAMETERS,
+    DOMAIN_SERVER_ENABLE_STATE, DOMAIN_SERVER_ROLE, GROUP_INFORMATION_CLASS, SAM_SERVER_CONNECT,
+    SAM_SERVER_ENUMERATE_DOMAINS, SAM_SERVER_LOOKUP_DOMAIN, USER_INFORMATION_CLASS,
+};
+use crate::{
+    buffer::{from_counted_wide, from_unicode_string, to_wide, unicode_string},
+    check,
+    ntsam::*,
+    security::Sid,
+};
+
+/// A handle as returned by `SamConnect` and the `SamOpen*` functions.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct RawSamHandle(pub *mut c_void);
+
+/// Preferred page size passed to the enumeration functions.
+pub const DEFAULT_PREFERRED_MAXIMUM_LENGTH: u32 = 0x10000;
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum SamObjectType {
+    Server,
+    Domain,
+    User,
+    Group,
+    Alias,
+}
+
+/// Marker for the kind of object a [`SamHandle`] refers to.
+pub trait SamObject {
+    const TYPE: SamObjectType;
+}
+
+#[derive(Debug)]
+pub enum Server {}
+
+#[derive(Debug)]
+pub enum Domain {}
+
+#[derive(Debug)]
+pub enum User {}
+
+#[derive(Debug)]
+pub enum Group {}
+
+#[derive(Debug)]
+pub enum Alias {}
+
+impl SamObject for Server {
+    const TYPE: SamObjectType = SamObjectType::Server;
+}
+
+impl SamObject for Domain {
+    const TYPE: SamObjectType = SamObjectType::Domain;
+}
+
+impl SamObject for User {
+    const TYPE: SamObjectType = SamObjectType::User;
+}
+
+impl SamObject for Group {
+    const TYPE: SamObjectType = SamObjectType::Group;
+}
+
+impl SamObject for Alias {
+    const TYPE: SamObjectType = SamObjectType::Alias;
+}
+
+/// An information class together with the object type it applies to.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum SamInformationClass {
+    Domain(DOMAIN_INFORMATION_CLASS),
+    User(USER_INFORMATION_CLASS),
+    Group(GROUP_INFORMATION_CLASS),
+    Alias(ALIAS_INFORMATION_CLASS),
+}
+
+/// What an enumeration lists.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum EnumerationKind {
+    /// Domains of a server. Entries carry a relative ID of zero.
+    Domains,
+    /// Users of a domain whose account control matches any bit of
+    /// `account_control`, or all users when it is zero.
+    Users {
+        account_control: u32,
+    },
+    Groups,
+    Aliases,
+}
+
+/// An entry returned by an enumeration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamEntry {
+    pub rid: u32,
+    pub name: String,
+}
+
+/// One call's worth of enumeration results.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnumerationPage {
+    pub entries: Vec<SamEntry>,
+    /// Whether the call returned `STATUS_MORE_ENTRIES`.
+    pub more: bool,
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct GroupMembership {
+    pub rid: u32,
+    pub attributes: u32,
+}
+
+/// The SAM operations the safe wrappers are built on.
+///
+/// Buffers returned by [`SamBackend::query_information`] are released with
+/// [`SamBackend::free`]; everything else is returned owned.
+pub trait SamBackend: Clone {
+    fn connect(&self, server: Option<&str>, desired_access: u32) -> Result<RawSamHandle, NTSTATUS>;
+
+    fn close(&self, handle: RawSamHandle);
+
+    fn lookup_domain(&self, server: RawSamHandle, name: &str) -> Result<Sid, NTSTATUS>;
+
+    fn open_domain(
+        &self,
+        server: RawSamHandle,
+        desired_access: u32,
+        domain: &Sid,
+    ) -> Result<RawSamHandle, NTSTATUS>;
+
+    /// Opens the user, group or alias `rid` of `domain`.
+    fn open_account(
+        &self,
+        domain: RawSamHandle,
+        object: SamObjectType,
+        desired_access: u32,
+        rid: u32,
+    ) -> Result<RawSamHandle, NTSTATUS>;
+
+    /// Returns the page starting at `*context` and advances `context` past it.
+    fn enumerate(
+        &self,
+        handle: RawSamHandle,
+        kind: EnumerationKind,
+        context: &mut u32,
+        preferred_maximum_length: u32,
+    ) -> Result<EnumerationPage, NTSTATUS>;
+
+    /// Resolves account names; unmapped names yield `None`.
+    fn lookup_names(
+        &self,
+        domain: RawSamHandle,
+        names: &[&str],
+    ) -> Result<Vec<Option<(u32, SID_NAME_USE)>>, NTSTATUS>;
+
+    /// Resolves relative IDs; unmapped IDs yield `None`.
+    fn lookup_ids(
+        &self,
+        domain: RawSamHandle,
+        rids: &[u32],
+    ) -> Result<Vec<Option<(String, SID_NAME_USE)>>, NTSTATUS>;
+
+    fn group_members(&self, group: RawSamHandle) -> Result<Vec<GroupMembership>, NTSTATUS>;
+
+    fn alias_members(&self, alias: RawSamHandle) -> Result<Vec<Sid>, NTSTATUS>;
+
+    fn user_groups(&self, user: RawSamHandle) -> Result<Vec<GroupMembership>, NTSTATUS>;
+
+    fn query_information(
+        &self,
+        handle: RawSamHandle,
+        class: SamInformationClass,
+    ) -> Result<*mut c_void, NTSTATUS>;
+
+    /// # Safety
+    ///
+    /// `buffer` must point to the structure `class` expects.
+    unsafe fn set_information(
+        &self,
+        handle: RawSamHandle,
+        class: SamInformationClass,
+        buffer: *mut c_void,
+    ) -> Result<(), NTSTATUS>;
+
+    /// # Safety
+    ///
+    /// `buffer` must have been returned by [`SamBackend::query_information`]
+    /// and not freed already.
+    unsafe fn free(&self, buffer: *mut c_void);
+}
+
+/// An information class that can be queried from a `T::Object` handle.
+pub trait SamInformation: Sized {
+    type Object: SamObject;
+    const CLASS: SamInformationClass;
+
+    /// Copies the class out of the buffer returned by the query.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must point to the structure `CLASS` returns.
+    unsafe fn from_raw(buffer: *const c_void) -> Self;
+}
+
+/// An information class that can also be set.
+pub trait SetSamInformation: SamInformation {
+    /// Calls `f` with the native structure for `self`, which stays valid for
+    /// the duration of the call.
+    fn with_raw(
+        &self,
+        f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+    ) -> Result<(), NTSTATUS>;
+}
+
+/// An open SAM handle, closed with `SamCloseHandle` on drop.
+pub struct SamHandle<K: SamObject, B: SamBackend = NativeSam> {
+    backend: B,
+    raw: RawSamHandle,
+    kind: PhantomData<K>,
+}
+
+pub type SamServer<B = NativeSam> = SamHandle<Server, B>;
+pub type SamDomain<B = NativeSam> = SamHandle<Domain, B>;
+pub type SamUser<B = NativeSam> = SamHandle<User, B>;
+pub type SamGroup<B = NativeSam> = SamHandle<Group, B>;
+pub type SamAlias<B = NativeSam> = SamHandle<Alias, B>;
+
+impl<K: SamObject, B: SamBackend> SamHandle<K, B> {
+    /// Takes ownership of `raw`, which must be a handle of kind `K` opened
+    /// through `backend`.
+    pub fn from_raw(backend: B, raw: RawSamHandle) -> Self {
+        Self {
+            backend,
+            raw,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn as_raw(&self) -> RawSamHandle {
+        self.raw
+    }
+
+    /// Releases ownership of the handle without closing it, handing back the
+    /// backend it was opened through; the inverse of [`SamHandle::from_raw`].
+    pub fn into_raw(self) -> (B, RawSamHandle) {
+        let this = ManuallyDrop::new(self);
+        // `Drop` never runs for `this`, so the backend is moved out exactly
+        // once.
+        (unsafe { ptr::read(&this.backend) }, this.raw)
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn query<T: SamInformation<Object = K>>(&self) -> Result<T, NTSTATUS> {
+        let buffer = self.backend.query_information(self.raw, T::CLASS)?;
+        unsafe {
+            let value = T::from_raw(buffer);
+            self.backend.free(buffer);
+            Ok(value)
+        }
+    }
+
+    pub fn set<T: SetSamInformation<Object = K>>(&self, value: &T) -> Result<(), NTSTATUS> {
+        value.with_raw(&mut |buffer| unsafe {
+            self.backend.set_information(self.raw, T::CLASS, buffer)
+        })
+    }
+
+    fn open<C: SamObject>(
+        &self,
+        desired_access: u32,
+        rid: u32,
+    ) -> Result<SamHandle<C, B>, NTSTATUS> {
+        let raw = self
+            .backend
+            .open_account(self.raw, C::TYPE, desired_access, rid)?;
+        Ok(SamHandle::from_raw(self.backend.clone(), raw))
+    }
+
+    fn enumeration(&self, kind: EnumerationKind) -> Enumeration<'_, B> {
+        Enumeration {
+            backend: &self.backend,
+            handle: self.raw,
+            kind,
+            context: 0,
+            preferred_maximum_length: DEFAULT_PREFERRED_MAXIMUM_LENGTH,
+            buffered: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+impl<K: SamObject, B: SamBackend> Drop for SamHandle<K, B> {
+    fn drop(&mut self) {
+        self.backend.close(self.raw);
+    }
+}
+
+impl<K: SamObject, B: SamBackend> std::fmt::Debug for SamHandle<K, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SamHandle({:?}, {:p})", K::TYPE, self.raw.0)
+    }
+}
+
+impl SamServer {
+    /// Connects to the SAM server on `server`, or the local machine.
+    pub fn connect(server: Option<&str>, desired_access: u32) -> Result<Self, NTSTATUS> {
+        Self::connect_with(NativeSam, server, desired_access)
+    }
+}
+
+impl<B: SamBackend> SamServer<B> {
+    pub fn connect_with(
+        backend: B,
+        server: Option<&str>,
+        desired_access: u32,
+    ) -> Result<Self, NTSTATUS> {
+        let raw = backend.connect(server, desired_access)?;
+        Ok(Self::from_raw(backend, raw))
+    }
+
+    /// Returns the SID of the domain called `name`, e.g. `Builtin`.
+    pub fn lookup_domain(&self, name: &str) -> Result<Sid, NTSTATUS> {
+        self.backend.lookup_domain(self.raw, name)
+    }
+
+    pub fn open_domain(&self, domain: &Sid, desired_access: u32) -> Result<SamDomain<B>, NTSTATUS> {
+        let raw = self.backend.open_domain(self.raw, desired_access, domain)?;
+        Ok(SamHandle::from_raw(self.backend.clone(), raw))
+    }
+
+    pub fn open_domain_by_name(
+        &self,
+        name: &str,
+        desired_access: u32,
+    ) -> Result<SamDomain<B>, NTSTATUS> {
+        self.open_domain(&self.lookup_domain(name)?, desired_access)
+    }
+
+    pub fn domains(&self) -> Enumeration<'_, B> {
+        self.enumeration(EnumerationKind::Domains)
+    }
+}
+
+impl<B: SamBackend> SamDomain<B> {
+    pub fn open_user(&self, rid: u32, desired_access: u32) -> Result<SamUser<B>, NTSTATUS> {
+        self.open(desired_access, rid)
+    }
+
+    pub fn open_group(&self, rid: u32, desired_access: u32) -> Result<SamGroup<B>, NTSTATUS> {
+        self.open(desired_access, rid)
+    }
+
+    pub fn open_alias(&self, rid: u32, desired_access: u32) -> Result<SamAlias<B>, NTSTATUS> {
+        self.open(desired_access, rid)
+    }
+
+    /// Enumerates users whose `USER_*_ACCOUNT` control bits match any of
+    /// `account_control`, or all users when it is zero.
+    pub fn users(&self, account_control: u32) -> Enumeration<'_, B> {
+        self.enumeration(EnumerationKind::Users { account_control })
+    }
+
+    pub fn groups(&self) -> Enumeration<'_, B> {
+        self.enumeration(EnumerationKind::Groups)
+    }
+
+    pub fn aliases(&self) -> Enumeration<'_, B> {
+        self.enumeration(EnumerationKind::Aliases)
+    }
+
+    pub fn lookup_names(
+        &self,
+        names: &[&str],
+    ) -> Result<Vec<Option<(u32, SID_NAME_USE)>>, NTSTATUS> {
+        self.backend.lookup_names(self.raw, names)
+    }
+
+    pub fn lookup_ids(
+        &self,
+        rids: &[u32],
+    ) -> Result<Vec<Option<(String, SID_NAME_USE)>>, NTSTATUS> {
+        self.backend.lookup_ids(self.raw, rids)
+    }
+}
+
+impl<B: SamBackend> SamUser<B> {
+    /// The global groups the user belongs to, `SamGetGroupsForUser`.
+    pub fn groups(&self) -> Result<Vec<GroupMembership>, NTSTATUS> {
+        self.backend.user_groups(self.raw)
+    }
+
+    /// Sets the password in clear text, `UserSetPasswordInformation`.
+    pub fn set_password(&self, password: &str, expired: bool) -> Result<(), NTSTATUS> {
+        let password = to_wide(password);
+        let mut raw = USER_SET_PASSWORD_INFORMATION {
+            Password: unicode_string(&password),
+            PasswordExpired: BOOLEAN(expired as u8),
+        };
+        unsafe {
+            self.backend.set_information(
+                self.raw,
+                SamInformationClass::User(USER_INFORMATION_CLASS::UserSetPasswordInformation),
+                ptr::addr_of_mut!(raw).cast(),
+            )
+        }
+    }
+}
+
+impl<B: SamBackend> SamGroup<B> {
+    pub fn members(&self) -> Result<Vec<GroupMembership>, NTSTATUS> {
+        self.backend.group_members(self.raw)
+    }
+}
+
+impl<B: SamBackend> SamAlias<B> {
+    pub fn members(&self) -> Result<Vec<Sid>, NTSTATUS> {
+        self.backend.alias_members(self.raw)
+    }
+}
+
+/// Iterator over the entries of a server or domain, fetching a page at a
+/// time.
+///
+/// [`Enumeration::resume_handle`] can be saved at a page boundary and passed
+/// to [`Enumeration::resume_at`] later to continue from there.
+#[derive(Debug)]
+pub struct Enumeration<'a, B: SamBackend> {
+    backend: &'a B,
+    handle: RawSamHandle,
+    kind: EnumerationKind,
+    context: u32,
+    preferred_maximum_length: u32,
+    buffered: std::vec::IntoIter<SamEntry>,
+    done: bool,
+}
+
+impl<B: SamBackend> Enumeration<'_, B> {
+    pub fn resume_at(mut self, resume_handle: u32) -> Self {
+        self.context = resume_handle;
+        self
+    }
+
+    pub fn with_preferred_maximum_length(mut self, preferred_maximum_length: u32) -> Self {
+        self.preferred_maximum_length = preferred_maximum_length;
+        self
+    }
+
+    /// The enumeration context to pass to the next call.
+    pub fn resume_handle(&self) -> u32 {
+        self.context
+    }
+
+    /// Fetches the next page, discarding anything still buffered from the
+    /// previous one.
+    pub fn next_page(&mut self) -> Option<Result<Vec<SamEntry>, NTSTATUS>> {
+        self.buffered = Vec::new().into_iter();
+        if self.done {
+            return None;
+        }
+        match self.backend.enumerate(
+            self.handle,
+            self.kind,
+            &mut self.context,
+            self.preferred_maximum_length,
+        ) {
+            Ok(page) => {
+                self.done = !page.more;
+                Some(Ok(page.entries))
+            }
+            Err(status) => {
+                self.done = true;
+                Some(Err(status))
+            }
+        }
+    }
+}
+
+impl<B: SamBackend> Iterator for Enumeration<'_, B> {
+    type Item = Result<SamEntry, NTSTATUS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.buffered.next() {
+                return Some(Ok(entry));
+            }
+            match self.next_page()? {
+                Ok(entries) => self.buffered = entries.into_iter(),
+                Err(status) => return Some(Err(status)),
+            }
+        }
+    }
+}
+
+/// A user's permitted logon times, one bit per unit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogonHours {
+    /// Number of units in a week, usually `SAM_HOURS_PER_WEEK`.
+    pub units_per_week: u16,
+    pub bitmap: Vec<u8>,
+}
+
+impl LogonHours {
+    unsafe fn from_raw(raw: LOGON_HOURS) -> Self {
+        let len = (raw.UnitsPerWeek as usize).div_ceil(8);
+        Self {
+            units_per_week: raw.UnitsPerWeek,
+            bitmap: if raw.LogonHours.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(raw.LogonHours, len).to_vec()
+            },
+        }
+    }
+
+    fn to_raw(&self) -> LOGON_HOURS {
+        LOGON_HOURS {
+            UnitsPerWeek: self.units_per_week,
+            LogonHours: if self.bitmap.is_empty() {
+                ptr::null_mut()
+            } else {
+                self.bitmap.as_ptr() as *mut u8
+            },
+        }
+    }
+}
+
+unsafe fn from_lsa_string(value: LSA_UNICODE_STRING) -> String {
+    from_counted_wide(value.Buffer.0, value.Length)
+}
+
+fn lsa_string(units: &[u16]) -> LSA_UNICODE_STRING {
+    let value = unicode_string(units);
+    LSA_UNICODE_STRING {
+        Length: value.Length,
+        MaximumLength: value.MaximumLength,
+        Buffer: value.Buffer,
+    }
+}
+
+// Classes with a single string or scalar field share their implementation.
+macro_rules! string_information {
+    ($name:ident, $object:ty, $class:expr, $raw:ident, $raw_field:ident, $field:ident) => {
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        pub struct $name {
+            pub $field: String,
+        }
+
+        impl SamInformation for $name {
+            type Object = $object;
+            const CLASS: SamInformationClass = $class;
+
+            unsafe fn from_raw(buffer: *const c_void) -> Self {
+                let raw = &*buffer.cast::<$raw>();
+                Self {
+                    $field: from_unicode_string(&raw.$raw_field),
+                }
+            }
+        }
+
+        impl SetSamInformation for $name {
+            fn with_raw(
+                &self,
+                f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+            ) -> Result<(), NTSTATUS> {
+                let units = to_wide(&self.$field);
+                let mut raw = $raw {
+                    $raw_field: unicode_string(&units),
+                };
+                f(ptr::addr_of_mut!(raw).cast())
+            }
+        }
+    };
+}
+
+macro_rules! scalar_information {
+    ($name:ident, $object:ty, $class:expr, $raw:ident, $raw_field:ident, $field:ident: $ty:ty) => {
+        #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+        pub struct $name {
+            pub $field: $ty,
+        }
+
+        impl SamInformation for $name {
+            type Object = $object;
+            const CLASS: SamInformationClass = $class;
+
+            unsafe fn from_raw(buffer: *const c_void) -> Self {
+                Self {
+                    $field: buffer.cast::<$raw>().read_unaligned().$raw_field,
+                }
+            }
+        }
+
+        impl SetSamInformation for $name {
+            fn with_raw(
+                &self,
+                f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+            ) -> Result<(), NTSTATUS> {
+                let mut raw = $raw {
+                    $raw_field: self.$field,
+                };
+                f(ptr::addr_of_mut!(raw).cast())
+            }
+        }
+    };
+}
+
+/// `DomainPasswordInformation`. Ages are negative 100ns intervals.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DomainPasswordInformation {
+    pub min_password_length: u16,
+    pub password_history_length: u16,
+    /// `DOMAIN_PASSWORD_*` flags.
+    pub password_properties: u32,
+    pub max_password_age: i64,
+    pub min_password_age: i64,
+}
+
+impl SamInformation for DomainPasswordInformation {
+    type Object = Domain;
+    const CLASS: SamInformationClass =
+        SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainPasswordInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = &*buffer.cast::<DOMAIN_PASSWORD_INFORMATION>();
+        Self {
+            min_password_length: raw.MinPasswordLength,
+            password_history_length: raw.PasswordHistoryLength,
+            password_properties: raw.PasswordProperties.0,
+            max_password_age: raw.MaxPasswordAge,
+            min_password_age: raw.MinPasswordAge,
+        }
+    }
+}
+
+impl SetSamInformation for DomainPasswordInformation {
+    fn with_raw(
+        &self,
+        f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+    ) -> Result<(), NTSTATUS> {
+        let mut raw = DOMAIN_PASSWORD_INFORMATION {
+            MinPasswordLength: self.min_password_length,
+            PasswordHistoryLength: self.password_history_length,
+            PasswordProperties: DOMAIN_PASSWORD_PROPERTIES(self.password_properties),
+            MaxPasswordAge: self.max_password_age,
+            MinPasswordAge: self.min_password_age,
+        };
+        f(ptr::addr_of_mut!(raw).cast())
+    }
+}
+
+/// `DomainGeneralInformation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainGeneralInformation {
+    pub force_logoff: i64,
+    pub oem_information: String,
+    pub domain_name: String,
+    pub replica_source_node_name: String,
+    pub domain_modified_count: i64,
+    pub server_state: DOMAIN_SERVER_ENABLE_STATE,
+    pub server_role: DOMAIN_SERVER_ROLE,
+    pub uas_compatibility_required: bool,
+    pub user_count: u32,
+    pub group_count: u32,
+    pub alias_count: u32,
+}
+
+impl SamInformation for DomainGeneralInformation {
+    type Object = Domain;
+    const CLASS: SamInformationClass =
+        SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainGeneralInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = buffer.cast::<DOMAIN_GENERAL_INFORMATION>().read_unaligned();
+        let (oem_information, domain_name, replica_source_node_name) = (
+            raw.OemInformation,
+            raw.DomainName,
+            raw.ReplicaSourceNodeName,
+        );
+        Self {
+            force_logoff: raw.ForceLogoff,
+            oem_information: from_unicode_string(&oem_information),
+            domain_name: from_unicode_string(&domain_name),
+            replica_source_node_name: from_unicode_string(&replica_source_node_name),
+            domain_modified_count: raw.DomainModifiedCount,
+            server_state: raw.DomainServerState,
+            server_role: raw.DomainServerRole,
+            uas_compatibility_required: raw.UasCompatibilityRequired.0 != 0,
+            user_count: raw.UserCount,
+            group_count: raw.GroupCount,
+            alias_count: raw.AliasCount,
+        }
+    }
+}
+
+/// `DomainLockoutInformation`. Durations are negative 100ns intervals.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DomainLockoutInformation {
+    pub lockout_duration: i64,
+    pub lockout_observation_window: i64,
+    pub lockout_threshold: u16,
+}
+
+impl SamInformation for DomainLockoutInformation {
+    type Object = Domain;
+    const CLASS: SamInformationClass =
+        SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainLockoutInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = &*buffer.cast::<DOMAIN_LOCKOUT_INFORMATION>();
+        Self {
+            lockout_duration: raw.LockoutDuration,
+            lockout_observation_window: raw.LockoutObservationWindow,
+            lockout_threshold: raw.LockoutThreshold,
+        }
+    }
+}
+
+impl SetSamInformation for DomainLockoutInformation {
+    fn with_raw(
+        &self,
+        f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+    ) -> Result<(), NTSTATUS> {
+        let mut raw = DOMAIN_LOCKOUT_INFORMATION {
+            LockoutDuration: self.lockout_duration,
+            LockoutObservationWindow: self.lockout_observation_window,
+            LockoutThreshold: self.lockout_threshold,
+        };
+        f(ptr::addr_of_mut!(raw).cast())
+    }
+}
+
+scalar_information!(
+    DomainLogoffInformation,
+    Domain,
+    SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainLogoffInformation),
+    DOMAIN_LOGOFF_INFORMATION,
+    ForceLogoff,
+    force_logoff: i64
+);
+
+string_information!(
+    DomainOemInformation,
+    Domain,
+    SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainOemInformation),
+    DOMAIN_OEM_INFORMATION,
+    OemInformation,
+    oem_information
+);
+
+string_information!(
+    DomainNameInformation,
+    Domain,
+    SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainNameInformation),
+    DOMAIN_NAME_INFORMATION,
+    DomainName,
+    domain_name
+);
+
+/// `UserAllInformation`. On set, only the fields selected by `which_fields`
+/// (`USER_ALL_*`) are written. Password material and the security
+/// descriptor are not exposed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserAllInformation {
+    pub last_logon: i64,
+    pub last_logoff: i64,
+    pub password_last_set: i64,
+    pub account_expires: i64,
+    pub password_can_change: i64,
+    pub password_must_change: i64,
+    pub user_name: String,
+    pub full_name: String,
+    pub home_directory: String,
+    pub home_directory_drive: String,
+    pub script_path: String,
+    pub profile_path: String,
+    pub admin_comment: String,
+    pub workstations: String,
+    pub user_comment: String,
+    pub parameters: String,
+    pub user_id: u32,
+    pub primary_group_id: u32,
+    pub user_account_control: u32,
+    pub which_fields: u32,
+    pub logon_hours: LogonHours,
+    pub bad_password_count: u16,
+    pub logon_count: u16,
+    pub country_code: u16,
+    pub code_page: u16,
+    pub lm_password_present: bool,
+    pub nt_password_present: bool,
+    pub password_expired: bool,
+}
+
+impl SamInformation for UserAllInformation {
+    type Object = User;
+    const CLASS: SamInformationClass =
+        SamInformationClass::User(USER_INFORMATION_CLASS::UserAllInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        // USER_ALL_INFORMATION is packed, so copy it out rather than borrow.
+        let raw = buffer.cast::<USER_ALL_INFORMATION>().read_unaligned();
+        Self {
+            last_logon: raw.LastLogon,
+            last_logoff: raw.LastLogoff,
+            password_last_set: raw.PasswordLastSet,
+            account_expires: raw.AccountExpires,
+            password_can_change: raw.PasswordCanChange,
+            password_must_change: raw.PasswordMustChange,
+            user_name: from_lsa_string(raw.UserName),
+            full_name: from_lsa_string(raw.FullName),
+            home_directory: from_lsa_string(raw.HomeDirectory),
+            home_directory_drive: from_lsa_string(raw.HomeDirectoryDrive),
+            script_path: from_lsa_string(raw.ScriptPath),
+            profile_path: from_lsa_string(raw.ProfilePath),
+            admin_comment: from_lsa_string(raw.AdminComment),
+            workstations: from_lsa_string(raw.WorkStations),
+            user_comment: from_lsa_string(raw.UserComment),
+            parameters: from_lsa_string(raw.Parameters),
+            user_id: raw.UserId,
+            primary_group_id: raw.PrimaryGroupId,
+            user_account_control: raw.UserAccountControl,
+            which_fields: raw.WhichFields,
+            logon_hours: LogonHours::from_raw(raw.LogonHours),
+            bad_password_count: raw.BadPasswordCount,
+            logon_count: raw.LogonCount,
+            country_code: raw.CountryCode,
+            code_page: raw.CodePage,
+            lm_password_present: raw.LmPasswordPresent.0 != 0,
+            nt_password_present: raw.NtPasswordPresent.0 != 0,
+            password_expired: raw.PasswordExpired.0 != 0,
+        }
+    }
+}
+
+impl SetSamInformation for UserAllInformation {
+    fn with_raw(
+        &self,
+        f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+    ) -> Result<(), NTSTATUS> {
+        let strings = [
+            &self.user_name,
+            &self.full_name,
+            &self.home_directory,
+            &self.home_directory_drive,
+            &self.script_path,
+            &self.profile_path,
+            &self.admin_comment,
+            &self.workstations,
+            &self.user_comment,
+            &self.parameters,
+        ]
+        .map(|value| to_wide(value));
+        let mut raw: USER_ALL_INFORMATION = unsafe { mem::zeroed() };
+        raw.LastLogon = self.last_logon;
+        raw.LastLogoff = self.last_logoff;
+        raw.PasswordLastSet = self.password_last_set;
+        raw.AccountExpires = self.account_expires;
+        raw.PasswordCanChange = self.password_can_change;
+        raw.PasswordMustChange = self.password_must_change;
+        raw.UserName = lsa_string(&strings[0]);
+        raw.FullName = lsa_string(&strings[1]);
+        raw.HomeDirectory = lsa_string(&strings[2]);
+        raw.HomeDirectoryDrive = lsa_string(&strings[3]);
+        raw.ScriptPath = lsa_string(&strings[4]);
+        raw.ProfilePath = lsa_string(&strings[5]);
+        raw.AdminComment = lsa_string(&strings[6]);
+        raw.WorkStations = lsa_string(&strings[7]);
+        raw.UserComment = lsa_string(&strings[8]);
+        raw.Parameters = lsa_string(&strings[9]);
+        raw.UserId = self.user_id;
+        raw.PrimaryGroupId = self.primary_group_id;
+        raw.UserAccountControl = self.user_account_control;
+        raw.WhichFields = self.which_fields;
+        raw.LogonHours = self.logon_hours.to_raw();
+        raw.BadPasswordCount = self.bad_password_count;
+        raw.LogonCount = self.logon_count;
+        raw.CountryCode = self.country_code;
+        raw.CodePage = self.code_page;
+        raw.PasswordExpired = BOOLEAN(self.password_expired as u8);
+        f(ptr::addr_of_mut!(raw).cast())
+    }
+}
+
+/// `UserGeneralInformation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserGeneralInformation {
+    pub user_name: String,
+    pub full_name: String,
+    pub primary_group_id: u32,
+    pub admin_comment: String,
+    pub user_comment: String,
+}
+
+impl SamInformation for UserGeneralInformation {
+    type Object = User;
+    const CLASS: SamInformationClass =
+        SamInformationClass::User(USER_INFORMATION_CLASS::UserGeneralInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = &*buffer.cast::<USER_GENERAL_INFORMATION>();
+        Self {
+            user_name: from_unicode_string(&raw.UserName),
+            full_name: from_unicode_string(&raw.FullName),
+            primary_group_id: raw.PrimaryGroupId,
+            admin_comment: from_unicode_string(&raw.AdminComment),
+            user_comment: from_unicode_string(&raw.UserComment),
+        }
+    }
+}
+
+/// `UserNameInformation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserNameInformation {
+    pub user_name: String,
+    pub full_name: String,
+}
+
+impl SamInformation for UserNameInformation {
+    type Object = User;
+    const CLASS: SamInformationClass =
+        SamInformationClass::User(USER_INFORMATION_CLASS::UserNameInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = &*buffer.cast::<USER_NAME_INFORMATION>();
+        Self {
+            user_name: from_unicode_string(&raw.UserName),
+            full_name: from_unicode_string(&raw.FullName),
+        }
+    }
+}
+
+impl SetSamInformation for UserNameInformation {
+    fn with_raw(
+        &self,
+        f: &mut dyn FnMut(*mut c_void) -> Result<(), NTSTATUS>,
+    ) -> Result<(), NTSTATUS> {
+        let (user_name, full_name) = (to_wide(&self.user_name), to_wide(&self.full_name));
+        let mut raw = USER_NAME_INFORMATION {
+            UserName: unicode_string(&user_name),
+            FullName: unicode_string(&full_name),
+        };
+        f(ptr::addr_of_mut!(raw).cast())
+    }
+}
+
+string_information!(
+    UserAccountNameInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserAccountNameInformation),
+    USER_ACCOUNT_NAME_INFORMATION,
+    UserName,
+    user_name
+);
+
+string_information!(
+    UserFullNameInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserFullNameInformation),
+    USER_FULL_NAME_INFORMATION,
+    FullName,
+    full_name
+);
+
+string_information!(
+    UserAdminCommentInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserAdminCommentInformation),
+    USER_ADMIN_COMMENT_INFORMATION,
+    AdminComment,
+    admin_comment
+);
+
+string_information!(
+    UserProfileInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserProfileInformation),
+    USER_PROFILE_INFORMATION,
+    ProfilePath,
+    profile_path
+);
+
+scalar_information!(
+    UserPrimaryGroupInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserPrimaryGroupInformation),
+    USER_PRIMARY_GROUP_INFORMATION,
+    PrimaryGroupId,
+    primary_group_id: u32
+);
+
+scalar_information!(
+    UserControlInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserControlInformation),
+    USER_CONTROL_INFORMATION,
+    UserAccountControl,
+    user_account_control: u32
+);
+
+scalar_information!(
+    UserExpiresInformation,
+    User,
+    SamInformationClass::User(USER_INFORMATION_CLASS::UserExpiresInformation),
+    USER_EXPIRES_INFORMATION,
+    AccountExpires,
+    account_expires: i64
+);
+
+/// `GroupGeneralInformation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupGeneralInformation {
+    pub name: String,
+    pub attributes: u32,
+    pub member_count: u32,
+    pub admin_comment: String,
+}
+
+impl SamInformation for GroupGeneralInformation {
+    type Object = Group;
+    const CLASS: SamInformationClass =
+        SamInformationClass::Group(GROUP_INFORMATION_CLASS::GroupGeneralInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = &*buffer.cast::<GROUP_GENERAL_INFORMATION>();
+        Self {
+            name: from_unicode_string(&raw.Name),
+            attributes: raw.Attributes,
+            member_count: raw.MemberCount,
+            admin_comment: from_unicode_string(&raw.AdminComment),
+        }
+    }
+}
+
+string_information!(
+    GroupNameInformation,
+    Group,
+    SamInformationClass::Group(GROUP_INFORMATION_CLASS::GroupNameInformation),
+    GROUP_NAME_INFORMATION,
+    Name,
+    name
+);
+
+scalar_information!(
+    GroupAttributeInformation,
+    Group,
+    SamInformationClass::Group(GROUP_INFORMATION_CLASS::GroupAttributeInformation),
+    GROUP_ATTRIBUTE_INFORMATION,
+    Attributes,
+    attributes: u32
+);
+
+string_information!(
+    GroupAdminCommentInformation,
+    Group,
+    SamInformationClass::Group(GROUP_INFORMATION_CLASS::GroupAdminCommentInformation),
+    GROUP_ADM_COMMENT_INFORMATION,
+    AdminComment,
+    admin_comment
+);
+
+/// `AliasGeneralInformation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AliasGeneralInformation {
+    pub name: String,
+    pub member_count: u32,
+    pub admin_comment: String,
+}
+
+impl SamInformation for AliasGeneralInformation {
+    type Object = Alias;
+    const CLASS: SamInformationClass =
+        SamInformationClass::Alias(ALIAS_INFORMATION_CLASS::AliasGeneralInformation);
+
+    unsafe fn from_raw(buffer: *const c_void) -> Self {
+        let raw = &*buffer.cast::<ALIAS_GENERAL_INFORMATION>();
+        Self {
+            name: from_unicode_string(&raw.Name),
+            member_count: raw.MemberCount,
+            admin_comment: from_unicode_string(&raw.AdminComment),
+        }
+    }
+}
+
+string_information!(
+    AliasNameInformation,
+    Alias,
+    SamInformationClass::Alias(ALIAS_INFORMATION_CLASS::AliasNameInformation),
+    ALIAS_NAME_INFORMATION,
+    Name,
+    name
+);
+
+string_information!(
+    AliasAdminCommentInformation,
+    Alias,
+    SamInformationClass::Alias(ALIAS_INFORMATION_CLASS::AliasAdminCommentInformation),
+    ALIAS_ADM_COMMENT_INFORMATION,
+    AdminComment,
+    admin_comment
+);
+
+/// The [`SamBackend`] that calls the SAM client functions.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeSam;
+
+/// Whether an enumeration call that returned `status` left entries for
+/// another call.
+fn more_entries(status: NTSTATUS) -> Result<bool, NTSTATUS> {
+    match status {
+        STATUS_MORE_ENTRIES => Ok(true),
+        STATUS_NO_MORE_ENTRIES => Ok(false),
+        status => check(status).map(|()| false),
+    }
+}
+
+/// A buffer returned by a SAM call, released with `SamFreeMemory` on drop
+/// so that every return path frees it.
+struct SamBuffer(*mut c_void);
+
+impl Drop for SamBuffer {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { SamFreeMemory(self.0) };
+        }
+    }
+}
+
+impl SamBackend for NativeSam {
+    fn connect(&self, server: Option<&str>, desired_access: u32) -> Result<RawSamHandle, NTSTATUS> {
+        let server = server.map(to_wide);
+        let mut server = server.as_deref().map(unicode_string);
+        let mut attributes = OBJECT_ATTRIBUTES {
+            Length: mem::size_of::<OBJECT_ATTRIBUTES>() as u32,
+            ..Default::default()
+        };
+        let mut handle = ptr::null_mut();
+        check(unsafe {
+            SamConnect(
+                server
+                    .as_mut()
+                    .map_or(ptr::null_mut(), |server| server as *mut UNICODE_STRING),
+                &mut handle,
+                desired_access,
+                &mut attributes,
+            )
+        })?;
+        Ok(RawSamHandle(handle))
+    }
+
+    fn close(&self, handle: RawSamHandle) {
+        unsafe { SamCloseHandle(handle.0) };
+    }
+
+    fn lookup_domain(&self, server: RawSamHandle, name: &str) -> Result<Sid, NTSTATUS> {
+        let name = to_wide(name);
+        let mut name = unicode_string(&name);
+        let mut sid = PSID::default();
+        check(unsafe { SamLookupDomainInSamServer(server.0, &mut name, &mut sid) })?;
+        let _sid = SamBuffer(sid.0);
+        unsafe { Sid::from_raw(sid) }.map_err(|_| STATUS_INVALID_PARAMETER)
+    }
+
+    fn open_domain(
+        &self,
+        server: RawSamHandle,
+        desired_access: u32,
+        domain: &Sid,
+    ) -> Result<RawSamHandle, NTSTATUS> {
+        let mut sid = domain.to_bytes();
+        let mut handle = ptr::null_mut();
+        check(unsafe {
+            SamOpenDomain(
+                server.0,
+                desired_access,
+                PSID(sid.as_mut_ptr().cast()),
+                &mut handle,
+            )
+        })?;
+        Ok(RawSamHandle(handle))
+    }
+
+    fn open_account(
+        &self,
+        domain: RawSamHandle,
+        object: SamObjectType,
+        desired_access: u32,
+        rid: u32,
+    ) -> Result<RawSamHandle, NTSTATUS> {
+        let open = match object {
+            SamObjectType::User => SamOpenUser,
+            SamObjectType::Group => SamOpenGroup,
+            SamObjectType::Alias => SamOpenAlias,
+            _ => return Err(STATUS_INVALID_PARAMETER),
+        };
+        let mut handle = ptr::null_mut();
+        check(unsafe { open(domain.0, desired_access, rid, &mut handle) })?;
+        Ok(RawSamHandle(handle))
+    }
+
+    fn enumerate(
+        &self,
+        handle: RawSamHandle,
+        kind: EnumerationKind,
+        context: &mut u32,
+        preferred_maximum_length: u32,
+    ) -> Result<EnumerationPage, NTSTATUS> {
+        let mut buffer = ptr::null_mut();
+        let mut count = 0;
+        let status = unsafe {
+            match kind {
+                EnumerationKind::Domains => SamEnumerateDomainsInSamServer(
+                    handle.0,
+                    context,
+                    &mut buffer,
+                    preferred_maximum_length,
+                    &mut count,
+                ),
+                EnumerationKind::Users { account_control } => SamEnumerateUsersInDomain(
+                    handle.0,
+                    context,
+                    account_control,
+                    &mut buffer,
+                    preferred_maximum_length,
+                    &mut count,
+                ),
+                EnumerationKind::Groups => SamEnumerateGroupsInDomain(
+                    handle.0,
+                    context,
+                    &mut buffer,
+                    preferred_maximum_length,
+                    &mut count,
+                ),
+                EnumerationKind::Aliases => SamEnumerateAliasesInDomain(
+                    handle.0,
+                    context,
+                    &mut buffer,
+                    preferred_maximum_length,
+                    &mut count,
+                ),
+            }
+        };
+        let buffer = SamBuffer(buffer);
+        let more = more_entries(status)?;
+        let entries = if buffer.0.is_null() {
+            Vec::new()
+        } else {
+            let raw = unsafe {
+                std::slice::from_raw_parts(buffer.0.cast::<SAM_RID_ENUMERATION>(), count as usize)
+            };
+            raw.iter()
+                .map(|entry| SamEntry {
+                    rid: entry.RelativeId,
+                    name: unsafe { from_unicode_string(&entry.Name) },
+                })
+                .collect()
+        };
+        Ok(EnumerationPage { entries, more })
+    }
+
+    fn lookup_names(
+        &self,
+        domain: RawSamHandle,
+        names: &[&str],
+    ) -> Result<Vec<Option<(u32, SID_NAME_USE)>>, NTSTATUS> {
+        let units: Vec<Vec<u16>> = names.iter().map(|name| to_wide(name)).collect();
+        let mut strings: Vec<UNICODE_STRING> =
+            units.iter().map(|units| unicode_string(units)).collect();
+        let mut rids = ptr::null_mut();
+        let mut uses = ptr::null_mut();
+        let status = unsafe {
+            SamLookupNamesInDomain(
+                domain.0,
+                strings.len() as u32,
+                strings.as_mut_ptr(),
+                &mut rids,
+                &mut uses,
+            )
+        };
+        let _rids = SamBuffer(rids.cast());
+        let _uses = SamBuffer(uses.cast());
+        if status == STATUS_NONE_MAPPED {
+            return Ok(vec![None; names.len()]);
+        }
+        check(status)?;
+        Ok(unsafe {
+            let rids = std::slice::from_raw_parts(rids, names.len());
+            let uses = std::slice::from_raw_parts(uses, names.len());
+            rids.iter()
+                .zip(uses)
+                .map(|(rid, name_use)| (*name_use != SidTypeUnknown).then_some((*rid, *name_use)))
+                .collect()
+        })
+    }
+
+    fn lookup_ids(
+        &self,
+        domain: RawSamHandle,
+        rids: &[u32],
+    ) -> Result<Vec<Option<(String, SID_NAME_USE)>>, NTSTATUS> {
+        let mut rids = rids.to_vec();
+        let mut names = ptr::null_mut();
+        let mut uses = ptr::null_mut();
+        let status = unsafe {
+            SamLookupIdsInDomain(
+                domain.0,
+                rids.len() as u32,
+                rids.as_mut_ptr(),
+                &mut names,
+                &mut uses,
+            )
+        };
+        let _names = SamBuffer(names.cast());
+        let _uses = SamBuffer(uses.cast());
+        if status == STATUS_NONE_MAPPED {
+            return Ok(vec![None; rids.len()]);
+        }
+        check(status)?;
+        Ok(unsafe {
+            let names = std::slice::from_raw_parts(names, rids.len());
+            let uses = std::slice::from_raw_parts(uses, rids.len());
+            names
+                .iter()
+                .zip(uses)
+                .map(|(name, name_use)| {
+                    (*name_use != SidTypeUnknown).then(|| (from_unicode_string(name), *name_use))
+                })
+                .collect()
+        })
+    }
+
+    fn group_members(&self, group: RawSamHandle) -> Result<Vec<GroupMembership>, NTSTATUS> {
+        let mut ids = ptr::null_mut();
+        let mut attributes = ptr::null_mut();
+        let mut count = 0;
+        check(unsafe { SamGetMembersInGroup(group.0, &mut ids, &mut attributes, &mut count) })?;
+        let _ids = SamBuffer(ids.cast());
+        let _attributes = SamBuffer(attributes.cast());
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(unsafe {
+            let ids = std::slice::from_raw_parts(ids, count as usize);
+            let attributes = std::slice::from_raw_parts(attributes, count as usize);
+            ids.iter()
+                .zip(attributes)
+                .map(|(rid, attributes)| GroupMembership {
+                    rid: *rid,
+                    attributes: *attributes,
+                })
+                .collect()
+        })
+    }
+
+    fn alias_members(&self, alias: RawSamHandle) -> Result<Vec<Sid>, NTSTATUS> {
+        let mut sids = ptr::null_mut();
+        let mut count = 0;
+        check(unsafe { SamGetMembersInAlias(alias.0, &mut sids, &mut count) })?;
+        let _sids = SamBuffer(sids.cast());
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        unsafe { std::slice::from_raw_parts(sids, count as usize) }
+            .iter()
+            .map(|sid| unsafe { Sid::from_raw(*sid) })
+            .collect::<Result<_, _>>()
+            .map_err(|_| STATUS_INVALID_PARAMETER)
+    }
+
+    fn user_groups(&self, user: RawSamHandle) -> Result<Vec<GroupMembership>, NTSTATUS> {
+        let mut groups = ptr::null_mut();
+        let mut count = 0;
+        check(unsafe { SamGetGroupsForUser(user.0, &mut groups, &mut count) })?;
+        let _groups = SamBuffer(groups.cast());
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(
+            unsafe { std::slice::from_raw_parts(groups, count as usize) }
+                .iter()
+                .map(|group| GroupMembership {
+                    rid: group.RelativeId,
+                    attributes: group.Attributes,
+                })
+                .collect(),
+        )
+    }
+
+    fn query_information(
+        &self,
+        handle: RawSamHandle,
+        class: SamInformationClass,
+    ) -> Result<*mut c_void, NTSTATUS> {
+        let mut buffer = ptr::null_mut();
+        check(unsafe {
+            match class {
+                SamInformationClass::Domain(class) => {
+                    SamQueryInformationDomain(handle.0, class, &mut buffer)
+                }
+                SamInformationClass::User(class) => {
+                    SamQueryInformationUser(handle.0, class, &mut buffer)
+                }
+                SamInformationClass::Group(class) => {
+                    SamQueryInformationGroup(handle.0, class, &mut buffer)
+                }
+                SamInformationClass::Alias(class) => {
+                    SamQueryInformationAlias(handle.0, class, &mut buffer)
+                }
+            }
+        })?;
+        if buffer.is_null() {
+            return Err(STATUS_INVALID_INFO_CLASS);
+        }
+        Ok(buffer)
+    }
+
+    unsafe fn set_information(
+        &self,
+        handle: RawSamHandle,
+        class: SamInformationClass,
+        buffer: *mut c_void,
+    ) -> Result<(), NTSTATUS> {
+        check(unsafe {
+            match class {
+                SamInformationClass::Domain(class) => {
+                    SamSetInformationDomain(handle.0, class, buffer)
+                }
+                SamInformationClass::User(class) => SamSetInformationUser(handle.0, class, buffer),
+                SamInformationClass::Group(class) => {
+                    SamSetInformationGroup(handle.0, class, buffer)
+                }
+                SamInformationClass::Alias(class) => {
+                    SamSetInformationAlias(handle.0, class, buffer)
+                }
+            }
+        })
+    }
+
+    unsafe fn free(&self, buffer: *mut c_void) {
+        unsafe { SamFreeMemory(buffer) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use windows::Win32::{
+        Foundation::{STATUS_ACCESS_DENIED, STATUS_SUCCESS},
+        Security::SidTypeUser,
+    };
+
+    use super::*;
+
+    /// Pages through `total` accounts three at a time, recording calls.
+    #[derive(Clone, Default)]
+    struct Mock {
+        state: Rc<RefCell<State>>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        total: u32,
+        next_handle: usize,
+        closed: Vec<usize>,
+        contexts: Vec<u32>,
+        fail_at: Option<u32>,
+        strings: Vec<Vec<u16>>,
+        freed: usize,
+        set: Vec<u32>,
+    }
+
+    impl SamBackend for Mock {
+        fn connect(&self, _: Option<&str>, _: u32) -> Result<RawSamHandle, NTSTATUS> {
+            let mut state = self.state.borrow_mut();
+            state.next_handle += 1;
+            Ok(RawSamHandle(state.next_handle as *mut c_void))
+        }
+
+        fn close(&self, handle: RawSamHandle) {
+            self.state.borrow_mut().closed.push(handle.0 as usize);
+        }
+
+        fn lookup_domain(&self, _: RawSamHandle, _: &str) -> Result<Sid, NTSTATUS> {
+            Ok(Sid::new(5, &[32]))
+        }
+
+        fn open_domain(
+            &self,
+            _: RawSamHandle,
+            access: u32,
+            _: &Sid,
+        ) -> Result<RawSamHandle, NTSTATUS> {
+            self.connect(None, access)
+        }
+
+        fn open_account(
+            &self,
+            _: RawSamHandle,
+            _: SamObjectType,
+            access: u32,
+            _: u32,
+        ) -> Result<RawSamHandle, NTSTATUS> {
+            self.connect(None, access)
+        }
+
+        fn enumerate(
+            &self,
+            _: RawSamHandle,
+            _: EnumerationKind,
+            context: &mut u32,
+            _: u32,
+        ) -> Result<EnumerationPage, NTSTATUS> {
+            let mut state = self.state.borrow_mut();
+            state.contexts.push(*context);
+            let start = *context;
+            let end = (start + 3).min(state.total);
+            let status = if state.fail_at == Some(start) {
+                STATUS_ACCESS_DENIED
+            } else if start >= state.total {
+                STATUS_NO_MORE_ENTRIES
+            } else if end < state.total {
+                STATUS_MORE_ENTRIES
+            } else {
+                STATUS_SUCCESS
+            };
+            let more = more_entries(status)?;
+            *context = end;
+            Ok(EnumerationPage {
+                entries: (start..end)
+                    .map(|index| SamEntry {
+                        rid: 1000 + index,
+                        name: format!("user{index}"),
+                    })
+                    .collect(),
+                more,
+            })
+        }
+
+        fn lookup_names(
+            &self,
+            _: RawSamHandle,
+            names: &[&str],
+        ) -> Result<Vec<Option<(u32, SID_NAME_USE)>>, NTSTATUS> {
+            Ok(names.iter().map(|_| None).collect())
+        }
+
+        fn lookup_ids(
+            &self,
+            _: RawSamHandle,
+            rids: &[u32],
+        ) -> Result<Vec<Option<(String, SID_NAME_USE)>>, NTSTATUS> {
+            Ok(rids
+                .iter()
+                .map(|rid| Some((rid.to_string(), SidTypeUser)))
+                .collect())
+        }
+
+        fn group_members(&self, _: RawSamHandle) -> Result<Vec<GroupMembership>, NTSTATUS> {
+            Ok(Vec::new())
+        }
+
+        fn alias_members(&self, _: RawSamHandle) -> Result<Vec<Sid>, NTSTATUS> {
+            Ok(vec![Sid::everyone()])
+        }
+
+        fn user_groups(&self, _: RawSamHandle) -> Result<Vec<GroupMembership>, NTSTATUS> {
+            Ok(vec![GroupMembership {
+                rid: 513,
+                attributes: 7,
+            }])
+        }
+
+        fn query_information(
+            &self,
+            _: RawSamHandle,
+            class: SamInformationClass,
+        ) -> Result<*mut c_void, NTSTATUS> {
+            let mut state = self.state.borrow_mut();
+            let mut string = |value: &str| {
+                state.strings.push(to_wide(value));
+                unicode_string(state.strings.last().unwrap())
+            };
+            match class {
+                SamInformationClass::User(USER_INFORMATION_CLASS::UserGeneralInformation) => {
+                    let raw = USER_GENERAL_INFORMATION {
+                        UserName: string("jdoe"),
+                        FullName: string("Jane Doe"),
+                        PrimaryGroupId: 513,
+                        AdminComment: string(""),
+                        UserComment: string("on leave"),
+                    };
+                    Ok(Box::into_raw(Box::new(raw)).cast())
+                }
+                SamInformationClass::Group(GROUP_INFORMATION_CLASS::GroupGeneralInformation) => {
+                    let raw = GROUP_GENERAL_INFORMATION {
+                        Name: string("Domain Users"),
+                        Attributes: 7,
+                        MemberCount: 42,
+                        AdminComment: string("All domain users"),
+                    };
+                    Ok(Box::into_raw(Box::new(raw)).cast())
+                }
+                SamInformationClass::Domain(DOMAIN_INFORMATION_CLASS::DomainLockoutInformation) => {
+                    let raw = DOMAIN_LOCKOUT_INFORMATION {
+                        LockoutDuration: -5,
+                        LockoutObservationWindow: -6,
+                        LockoutThreshold: 3,
+                    };
+                    Ok(Box::into_raw(Box::new(raw)).cast())
+                }
+                _ => Err(STATUS_INVALID_INFO_CLASS),
+            }
+        }
+
+        unsafe fn set_information(
+            &self,
+            _: RawSamHandle,
+            _: SamInformationClass,
+            buffer: *mut c_void,
+        ) -> Result<(), NTSTATUS> {
+            let control =
+                unsafe { (*buffer.cast::<USER_CONTROL_INFORMATION>()).UserAccountControl };
+            self.state.borrow_mut().set.push(control);
+            Ok(())
+        }
+
+        unsafe fn free(&self, _: *mut c_void) {
+            self.state.borrow_mut().freed += 1;
+        }
+    }
+
+    fn with_accounts(total: u32) -> Mock {
+        let mock = Mock::default();
+        mock.state.borrow_mut().total = total;
+        mock
+    }
+
+    #[test]
+    fn more_entries_statuses() {
+        assert_eq!(more_entries(STATUS_MORE_ENTRIES), Ok(true));
+        assert_eq!(more_entries(STATUS_NO_MORE_ENTRIES), Ok(false));
+        assert_eq!(more_entries(STATUS_SUCCESS), Ok(false));
+        assert_eq!(
+            more_entries(STATUS_ACCESS_DENIED),
+            Err(STATUS_ACCESS_DENIED)
+        );
+    }
+
+    #[test]
+    fn pages_until_the_last_page() {
+        let mock = with_accounts(7);
+        let server = SamServer::connect_with(mock.clone(), None, 0).unwrap();
+        let domain = server.open_domain_by_name("Builtin", 0).unwrap();
+        let users = domain.users(0).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(users.len(), 7);
+        assert_eq!(users[6].rid, 1006);
+        // The resume handle carries across STATUS_MORE_ENTRIES and the final
+        // STATUS_SUCCESS ends the enumeration without another call.
+        assert_eq!(mock.state.borrow().contexts, [0, 3, 6]);
+    }
+
+    #[test]
+    fn resumes_and_stops_on_errors() {
+        let mock = with_accounts(7);
+        let domain = SamServer::connect_with(mock.clone(), None, 0)
+            .unwrap()
+            .open_domain_by_name("Builtin", 0)
+            .unwrap();
+        let mut groups = domain.groups().resume_at(3);
+        assert_eq!(groups.next_page().unwrap().unwrap().len(), 3);
+        assert_eq!(groups.resume_handle(), 6);
+        assert_eq!(groups.next_page().unwrap().unwrap().len(), 1);
+        assert!(groups.next_page().is_none());
+        mock.state.borrow_mut().fail_at = Some(3);
+        let aliases = domain.aliases().collect::<Vec<_>>();
+        assert_eq!(aliases.len(), 4);
+        assert_eq!(aliases[3], Err(STATUS_ACCESS_DENIED));
+        // An empty domain answers STATUS_NO_MORE_ENTRIES straight away.
+        let empty = with_accounts(0);
+        let domain = SamServer::connect_with(empty.clone(), None, 0)
+            .unwrap()
+            .open_domain_by_name("Builtin", 0)
+            .unwrap();
+        assert_eq!(domain.users(0).count(), 0);
+        assert_eq!(empty.state.borrow().contexts, [0]);
+    }
+
+    #[test]
+    fn closes_each_handle_once() {
+        let mock = with_accounts(0);
+        let (backend, raw);
+        {
+            let server = SamServer::connect_with(mock.clone(), None, 0).unwrap();
+            let domain = server.open_domain_by_name("Builtin", 0).unwrap();
+            let _user = domain.open_user(500, 0).unwrap();
+            (backend, raw) = domain.open_alias(544, 0).unwrap().into_raw();
+            drop(domain.open_group(513, 0).unwrap());
+            assert_eq!(mock.state.borrow().closed, [5]);
+        }
+        let mut closed = mock.state.borrow().closed.clone();
+        closed.sort();
+        assert_eq!(closed, [1, 2, 3, 5]);
+        assert_eq!(raw.0 as usize, 4);
+        // The released backend is the only one left besides `mock`, and
+        // adopting the handle again closes it exactly once.
+        assert_eq!(Rc::strong_count(&mock.state), 2);
+        drop(SamAlias::from_raw(backend, raw));
+        assert_eq!(mock.state.borrow().closed.len(), 5);
+        assert_eq!(Rc::strong_count(&mock.state), 1);
+    }
+
+    #[test]
+    fn decodes_information_buffers() {
+        let mock = with_accounts(0);
+        let domain = SamServer::connect_with(mock.clone(), None, 0)
+            .unwrap()
+            .open_domain_by_name("Builtin", 0)
+            .unwrap();
+        let user = domain.open_user(500, 0).unwrap();
+        assert_eq!(
+            user.query::<UserGeneralInformation>().unwrap(),
+            UserGeneralInformation {
+                user_name: "jdoe".into(),
+                full_name: "Jane Doe".into(),
+                primary_group_id: 513,
+                admin_comment: String::new(),
+                user_comment: "on leave".into(),
+            }
+        );
+        let group = domain.open_group(513, 0).unwrap();
+        assert_eq!(
+            group.query::<GroupGeneralInformation>().unwrap(),
+            GroupGeneralInformation {
+                name: "Domain Users".into(),
+                attributes: 7,
+                member_count: 42,
+                admin_comment: "All domain users".into(),
+            }
+        );
+        assert_eq!(
+            domain
+                .query::<DomainLockoutInformation>()
+                .unwrap()
+                .lockout_threshold,
+            3
+        );
+        assert_eq!(mock.state.borrow().freed, 3);
+        assert_eq!(
+            user.query::<UserNameInformation>(),
+            Err(STATUS_INVALID_INFO_CLASS)
+        );
+        assert_eq!(mock.state.borrow().freed, 3);
+        user.set(&UserControlInformation {
+            user_account_control: 0x10,
+        })
+        .unwrap();
+        assert_eq!(mock.state.borrow().set, [0x10]);
+        assert_eq!(user.groups().unwrap()[0].rid, 513);
+    }
+}
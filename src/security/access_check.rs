@@ -0,0 +1,963 @@
+//! A pure Rust model of `NtAccessCheck`, `NtAccessCheckByType` and
+//! `NtAccessCheckByTypeResultList`, for predicting the outcome of an access
+//! check without a live token.
+//!
+//! The evaluation follows the documented kernel algorithm: generic mapping,
+//! privilege grants, implicit owner rights (suppressed by `OWNER RIGHTS`
+//! ACEs), an ordered DACL walk with per-object-type propagation, conditional
+//! callback ACEs, the restricted SID and AppContainer second passes, and the
+//! mandatory integrity policy. Generic rights in ACE masks are mapped as
+//! well, so hand-written descriptors evaluate as if created by
+//! `RtlNewSecurityObject`. Auditing is not modelled.
+
+use windows::{
+    Win32::{
+        Foundation::{NTSTATUS, STATUS_ACCESS_DENIED, STATUS_PRIVILEGE_NOT_HELD, STATUS_SUCCESS},
+        Security::GENERIC_MAPPING,
+    },
+    core::GUID,
+};
+
+use super::{
+    Ace, AceType, Acl, ClaimAttribute, EvaluationContext, SECURITY_APP_PACKAGE_AUTHORITY,
+    SECURITY_APP_PACKAGE_BASE_RID, SECURITY_CREATOR_SID_AUTHORITY, SECURITY_NT_AUTHORITY,
+    SYSTEM_MANDATORY_LABEL_NO_EXECUTE_UP, SYSTEM_MANDATORY_LABEL_NO_READ_UP,
+    SYSTEM_MANDATORY_LABEL_NO_WRITE_UP, SecurityDescriptor, Sid, Tristate,
+};
+
+pub const DELETE: u32 = 0x0001_0000;
+pub const READ_CONTROL: u32 = 0x0002_0000;
+pub const WRITE_DAC: u32 = 0x0004_0000;
+pub const WRITE_OWNER: u32 = 0x0008_0000;
+pub const SYNCHRONIZE: u32 = 0x0010_0000;
+pub const ACCESS_SYSTEM_SECURITY: u32 = 0x0100_0000;
+pub const MAXIMUM_ALLOWED: u32 = 0x0200_0000;
+pub const GENERIC_ALL: u32 = 0x1000_0000;
+pub const GENERIC_EXECUTE: u32 = 0x2000_0000;
+pub const GENERIC_WRITE: u32 = 0x4000_0000;
+pub const GENERIC_READ: u32 = 0x8000_0000;
+
+pub const SE_GROUP_MANDATORY: u32 = 0x0000_0001;
+pub const SE_GROUP_ENABLED_BY_DEFAULT: u32 = 0x0000_0002;
+pub const SE_GROUP_ENABLED: u32 = 0x0000_0004;
+pub const SE_GROUP_OWNER: u32 = 0x0000_0008;
+pub const SE_GROUP_USE_FOR_DENY_ONLY: u32 = 0x0000_0010;
+pub const SE_GROUP_INTEGRITY: u32 = 0x0000_0020;
+pub const SE_GROUP_INTEGRITY_ENABLED: u32 = 0x0000_0040;
+pub const SE_GROUP_RESOURCE: u32 = 0x2000_0000;
+pub const SE_GROUP_LOGON_ID: u32 = 0xC000_0000;
+
+pub const SE_SECURITY_PRIVILEGE: u32 = 8;
+pub const SE_TAKE_OWNERSHIP_PRIVILEGE: u32 = 9;
+
+pub const TOKEN_MANDATORY_POLICY_OFF: u32 = 0;
+pub const TOKEN_MANDATORY_POLICY_NO_WRITE_UP: u32 = 1;
+pub const TOKEN_MANDATORY_POLICY_NEW_PROCESS_MIN: u32 = 2;
+
+pub const SECURITY_MANDATORY_UNTRUSTED_RID: u32 = 0x0000;
+pub const SECURITY_MANDATORY_LOW_RID: u32 = 0x1000;
+pub const SECURITY_MANDATORY_MEDIUM_RID: u32 = 0x2000;
+pub const SECURITY_MANDATORY_MEDIUM_PLUS_RID: u32 = 0x2100;
+pub const SECURITY_MANDATORY_HIGH_RID: u32 = 0x3000;
+pub const SECURITY_MANDATORY_SYSTEM_RID: u32 = 0x4000;
+pub const SECURITY_MANDATORY_PROTECTED_PROCESS_RID: u32 = 0x5000;
+
+/// A group in a token together with its `SE_GROUP_*` attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenGroup {
+    pub sid: Sid,
+    pub attributes: u32,
+}
+
+impl TokenGroup {
+    pub fn enabled(sid: Sid) -> Self {
+        Self {
+            sid,
+            attributes: SE_GROUP_MANDATORY | SE_GROUP_ENABLED_BY_DEFAULT | SE_GROUP_ENABLED,
+        }
+    }
+
+    pub fn deny_only(sid: Sid) -> Self {
+        Self {
+            sid,
+            attributes: SE_GROUP_USE_FOR_DENY_ONLY,
+        }
+    }
+
+    fn matches_allow(&self, sid: &Sid) -> bool {
+        self.sid == *sid
+            && self.attributes & SE_GROUP_ENABLED != 0
+            && self.attributes & SE_GROUP_USE_FOR_DENY_ONLY == 0
+    }
+
+    fn matches_deny(&self, sid: &Sid) -> bool {
+        self.sid == *sid && self.attributes & (SE_GROUP_ENABLED | SE_GROUP_USE_FOR_DENY_ONLY) != 0
+    }
+}
+
+/// The parts of a token that take part in an access check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    pub user: Sid,
+    pub groups: Vec<TokenGroup>,
+    /// Low parts of the LUIDs of the enabled privileges, e.g.
+    /// [`SE_SECURITY_PRIVILEGE`].
+    pub privileges: Vec<u32>,
+    /// The `SECURITY_MANDATORY_*_RID` of the token.
+    pub integrity_level: u32,
+    /// `TOKEN_MANDATORY_POLICY_*` flags.
+    pub mandatory_policy: u32,
+    /// Restricting SIDs; a non-empty list makes this a restricted token.
+    pub restricted_sids: Vec<TokenGroup>,
+    /// Whether the restricting SIDs only apply to write access.
+    pub write_restricted: bool,
+    /// The package SID of an AppContainer token.
+    pub app_container: Option<Sid>,
+    pub capabilities: Vec<TokenGroup>,
+    pub user_claims: Vec<ClaimAttribute>,
+    pub device_claims: Vec<ClaimAttribute>,
+    pub device_groups: Vec<TokenGroup>,
+}
+
+impl AccessToken {
+    /// A medium integrity token for `user` with no groups or privileges.
+    pub fn new(user: Sid) -> Self {
+        Self {
+            user,
+            groups: Vec::new(),
+            privileges: Vec::new(),
+            integrity_level: SECURITY_MANDATORY_MEDIUM_RID,
+            mandatory_policy: TOKEN_MANDATORY_POLICY_NO_WRITE_UP
+                | TOKEN_MANDATORY_POLICY_NEW_PROCESS_MIN,
+            restricted_sids: Vec::new(),
+            write_restricted: false,
+            app_container: None,
+            capabilities: Vec::new(),
+            user_claims: Vec::new(),
+            device_claims: Vec::new(),
+            device_groups: Vec::new(),
+        }
+    }
+
+    pub fn with_group(mut self, group: TokenGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    pub fn with_privilege(mut self, privilege: u32) -> Self {
+        self.privileges.push(privilege);
+        self
+    }
+
+    pub fn with_integrity_level(mut self, integrity_level: u32) -> Self {
+        self.integrity_level = integrity_level;
+        self
+    }
+
+    pub fn with_restricted_sid(mut self, group: TokenGroup) -> Self {
+        self.restricted_sids.push(group);
+        self
+    }
+
+    pub fn with_app_container(mut self, package: Sid, capabilities: Vec<TokenGroup>) -> Self {
+        self.app_container = Some(package);
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_user_claim(mut self, claim: ClaimAttribute) -> Self {
+        self.user_claims.push(claim);
+        self
+    }
+
+    pub fn with_device_claim(mut self, claim: ClaimAttribute) -> Self {
+        self.device_claims.push(claim);
+        self
+    }
+
+    pub fn has_privilege(&self, privilege: u32) -> bool {
+        self.privileges.contains(&privilege)
+    }
+
+    /// The user plus every enabled group, the set `Member_of` tests against.
+    pub fn enabled_sids(&self) -> Vec<Sid> {
+        std::iter::once(self.user.clone())
+            .chain(
+                self.groups
+                    .iter()
+                    .filter(|group| group.matches_allow(&group.sid))
+                    .map(|group| group.sid.clone()),
+            )
+            .collect()
+    }
+}
+
+/// An entry of an `OBJECT_TYPE_LIST`. The first entry must be the level 0
+/// root and levels of later entries describe the tree in pre-order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ObjectTypeEntry {
+    pub level: u16,
+    pub object_type: GUID,
+}
+
+/// Outcome for one entry of the object type list.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ObjectTypeResult {
+    pub granted_access: u32,
+    pub status: NTSTATUS,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessCheckResult {
+    pub granted_access: u32,
+    /// `STATUS_SUCCESS`, `STATUS_ACCESS_DENIED` or
+    /// `STATUS_PRIVILEGE_NOT_HELD`, as returned in `AccessStatus`.
+    pub status: NTSTATUS,
+    /// One result per object type entry, as returned by
+    /// `NtAccessCheckByTypeResultList`. Empty without an object type list.
+    pub results: Vec<ObjectTypeResult>,
+    /// The privileges that contributed to the granted access.
+    pub privileges_used: Vec<u32>,
+}
+
+impl AccessCheckResult {
+    pub fn is_granted(&self) -> bool {
+        self.status == STATUS_SUCCESS
+    }
+}
+
+/// Maps generic rights in `mask` to specific rights, `RtlMapGenericMask`.
+pub fn map_generic_mask(mask: u32, mapping: &GENERIC_MAPPING) -> u32 {
+    let mut mapped = mask & !(GENERIC_READ | GENERIC_WRITE | GENERIC_EXECUTE | GENERIC_ALL);
+    for (generic, specific) in [
+        (GENERIC_READ, mapping.GenericRead),
+        (GENERIC_WRITE, mapping.GenericWrite),
+        (GENERIC_EXECUTE, mapping.GenericExecute),
+        (GENERIC_ALL, mapping.GenericAll),
+    ] {
+        if mask & generic != 0 {
+            mapped |= specific;
+        }
+    }
+    mapped
+}
+
+/// Access check parameters beyond the token and desired access.
+#[derive(Debug, Clone)]
+pub struct AccessCheck<'a> {
+    pub security_descriptor: &'a SecurityDescriptor,
+    pub generic_mapping: GENERIC_MAPPING,
+    pub object_types: Vec<ObjectTypeEntry>,
+    /// Replaces `PRINCIPAL_SELF` (`S-1-5-10`) in ACEs.
+    pub principal_self: Option<Sid>,
+    /// Claims visible to `@Local` attribute references.
+    pub local_claims: Vec<ClaimAttribute>,
+}
+
+impl<'a> AccessCheck<'a> {
+    pub fn new(
+        security_descriptor: &'a SecurityDescriptor,
+        generic_mapping: GENERIC_MAPPING,
+    ) -> Self {
+        Self {
+            security_descriptor,
+            generic_mapping,
+            object_types: Vec::new(),
+            principal_self: None,
+            local_claims: Vec::new(),
+        }
+    }
+
+    pub fn with_object_types(mut self, object_types: Vec<ObjectTypeEntry>) -> Self {
+        self.object_types = object_types;
+        self
+    }
+
+    pub fn with_principal_self(mut self, sid: Sid) -> Self {
+        self.principal_self = Some(sid);
+        self
+    }
+
+    pub fn with_local_claims(mut self, claims: Vec<ClaimAttribute>) -> Self {
+        self.local_claims = claims;
+        self
+    }
+
+    /// Evaluates `desired_access` for `token`, `NtAccessCheckByTypeResultList`
+    /// semantics. `MAXIMUM_ALLOWED` may be combined with specific rights.
+    pub fn check(&self, token: &AccessToken, desired_access: u32) -> AccessCheckResult {
+        let sd = self.security_descriptor;
+        let mapping = &self.generic_mapping;
+        let maximum_allowed = desired_access & MAXIMUM_ALLOWED != 0;
+        let desired = map_generic_mask(desired_access & !MAXIMUM_ALLOWED, mapping);
+        let node_count = self.object_types.len().max(1);
+        let mut privileges_used = Vec::new();
+
+        // ACCESS_SYSTEM_SECURITY can only be granted by privilege.
+        let mut privileged = 0;
+        if desired & ACCESS_SYSTEM_SECURITY != 0 {
+            if !token.has_privilege(SE_SECURITY_PRIVILEGE) {
+                return self.failure(STATUS_PRIVILEGE_NOT_HELD, privileges_used);
+            }
+            privileged |= ACCESS_SYSTEM_SECURITY;
+            privileges_used.push(SE_SECURITY_PRIVILEGE);
+        }
+        if (desired & WRITE_OWNER != 0 || maximum_allowed)
+            && token.has_privilege(SE_TAKE_OWNERSHIP_PRIVILEGE)
+        {
+            privileged |= WRITE_OWNER;
+            privileges_used.push(SE_TAKE_OWNERSHIP_PRIVILEGE);
+        }
+
+        let resource_claims: Vec<ClaimAttribute> = sd
+            .sacl
+            .iter()
+            .flat_map(|sacl| &sacl.aces)
+            .filter(|ace| !ace.is_inherit_only())
+            .filter_map(|ace| ace.claim().ok().flatten())
+            .collect();
+        let member_sids = token.enabled_sids();
+        let device_sids: Vec<Sid> = token
+            .device_groups
+            .iter()
+            .filter(|group| group.matches_allow(&group.sid))
+            .map(|group| group.sid.clone())
+            .collect();
+        let context = EvaluationContext {
+            user_claims: &token.user_claims,
+            device_claims: &token.device_claims,
+            resource_claims: &resource_claims,
+            local_claims: &self.local_claims,
+            member_sids: &member_sids,
+            device_sids: &device_sids,
+        };
+
+        let user = [TokenGroup::enabled(token.user.clone())];
+        let normal = Principal {
+            groups: [&user[..], &token.groups[..]],
+        };
+        let mut granted = self.evaluate(&normal, &context);
+
+        if !token.restricted_sids.is_empty() {
+            let restricted = Principal {
+                groups: [&token.restricted_sids[..], &[]],
+            };
+            let restricted = self.evaluate(&restricted, &context);
+            let unrestricted = if token.write_restricted {
+                !map_generic_mask(GENERIC_WRITE, mapping) & !(DELETE | WRITE_DAC | WRITE_OWNER)
+            } else {
+                0
+            };
+            for (granted, restricted) in granted.iter_mut().zip(restricted) {
+                *granted &= restricted | unrestricted;
+            }
+        }
+
+        if let Some(package) = &token.app_container {
+            let package = [
+                TokenGroup::enabled(package.clone()),
+                TokenGroup::enabled(Sid::new(
+                    SECURITY_APP_PACKAGE_AUTHORITY,
+                    &[SECURITY_APP_PACKAGE_BASE_RID, 1],
+                )),
+            ];
+            let app_container = Principal {
+                groups: [&package[..], &token.capabilities[..]],
+            };
+            let app_container = self.evaluate(&app_container, &context);
+            for (granted, app_container) in granted.iter_mut().zip(app_container) {
+                *granted &= app_container;
+            }
+        }
+
+        let mandatory_denied = self.mandatory_denied(token);
+        let results: Vec<ObjectTypeResult> = granted
+            .into_iter()
+            .map(|granted| {
+                let granted = (granted | privileged) & !mandatory_denied;
+                if maximum_allowed {
+                    let granted = granted | (desired & privileged);
+                    let status = if granted != 0 && desired & !granted == 0 {
+                        STATUS_SUCCESS
+                    } else {
+                        STATUS_ACCESS_DENIED
+                    };
+                    ObjectTypeResult {
+                        granted_access: if status == STATUS_SUCCESS { granted } else { 0 },
+                        status,
+                    }
+                } else if desired & !granted == 0 {
+                    ObjectTypeResult {
+                        granted_access: desired,
+                        status: STATUS_SUCCESS,
+                    }
+                } else {
+                    ObjectTypeResult {
+                        granted_access: 0,
+                        status: STATUS_ACCESS_DENIED,
+                    }
+                }
+            })
+            .collect();
+        debug_assert_eq!(results.len(), node_count);
+        AccessCheckResult {
+            granted_access: results[0].granted_access,
+            status: results[0].status,
+            results: if self.object_types.is_empty() {
+                Vec::new()
+            } else {
+                results
+            },
+            privileges_used,
+        }
+    }
+
+    fn failure(&self, status: NTSTATUS, privileges_used: Vec<u32>) -> AccessCheckResult {
+        AccessCheckResult {
+            granted_access: 0,
+            status,
+            results: self
+                .object_types
+                .iter()
+                .map(|_| ObjectTypeResult {
+                    granted_access: 0,
+                    status,
+                })
+                .collect(),
+            privileges_used,
+        }
+    }
+
+    /// Rights taken away by the mandatory integrity policy.
+    fn mandatory_denied(&self, token: &AccessToken) -> u32 {
+        let label = self
+            .security_descriptor
+            .sacl
+            .iter()
+            .flat_map(|sacl| &sacl.aces)
+            .find(|ace| {
+                ace.ace_type == AceType::SystemMandatoryLabel
+                    && !ace.is_inherit_only()
+                    && ace.sid.is_integrity_level()
+            });
+        let (level, policy) = match label {
+            Some(ace) => (ace.sid.rid().unwrap_or_default(), ace.mask),
+            None => (
+                SECURITY_MANDATORY_MEDIUM_RID,
+                SYSTEM_MANDATORY_LABEL_NO_WRITE_UP,
+            ),
+        };
+        if token.integrity_level >= level {
+            return 0;
+        }
+        let mapping = &self.generic_mapping;
+        let mut denied = 0;
+        if policy & SYSTEM_MANDATORY_LABEL_NO_WRITE_UP != 0
+            && token.mandatory_policy & TOKEN_MANDATORY_POLICY_NO_WRITE_UP != 0
+        {
+            denied |= mapping.GenericWrite | DELETE | WRITE_DAC | WRITE_OWNER;
+        }
+        if policy & SYSTEM_MANDATORY_LABEL_NO_READ_UP != 0 {
+            denied |= mapping.GenericRead & !(READ_CONTROL | SYNCHRONIZE);
+        }
+        if policy & SYSTEM_MANDATORY_LABEL_NO_EXECUTE_UP != 0 {
+            denied |= mapping.GenericExecute & !(READ_CONTROL | SYNCHRONIZE);
+        }
+        denied
+    }
+
+    /// Walks the DACL for one set of SIDs and returns the access granted to
+    /// each object type node.
+    fn evaluate(&self, principal: &Principal, context: &EvaluationContext) -> Vec<u32> {
+        let sd = self.security_descriptor;
+        let mapping = &self.generic_mapping;
+        let mut tree = Tree::new(&self.object_types);
+
+        let Some(dacl) = &sd.dacl else {
+            let all = map_generic_mask(GENERIC_ALL, mapping)
+                | map_generic_mask(GENERIC_READ | GENERIC_WRITE | GENERIC_EXECUTE, mapping)
+                | DELETE
+                | READ_CONTROL
+                | WRITE_DAC
+                | WRITE_OWNER
+                | SYNCHRONIZE;
+            return vec![all; tree.nodes.len()];
+        };
+
+        let owner_rights = Sid::new(SECURITY_CREATOR_SID_AUTHORITY, &[4]);
+        let is_owner = sd
+            .owner
+            .as_ref()
+            .is_some_and(|owner| principal.matches_allow(owner));
+        if is_owner && !has_effective_ace_for(dacl, &owner_rights) {
+            tree.allow(0, READ_CONTROL | WRITE_DAC);
+        }
+
+        let principal_self = Sid::new(SECURITY_NT_AUTHORITY, &[10]);
+        for ace in dacl.aces.iter().filter(|ace| !ace.is_inherit_only()) {
+            let allow = ace.ace_type.is_allowed();
+            if !allow && !ace.ace_type.is_denied() {
+                continue;
+            }
+            let sid = match &self.principal_self {
+                Some(sid) if ace.sid == principal_self => sid,
+                _ => &ace.sid,
+            };
+            let matched = if *sid == owner_rights {
+                is_owner
+            } else if allow {
+                principal.matches_allow(sid)
+            } else {
+                principal.matches_deny(sid)
+            };
+            if !matched {
+                continue;
+            }
+            if ace.ace_type.is_callback() {
+                let condition = match ace.condition() {
+                    Ok(Some(expr)) => expr.evaluate(context),
+                    Ok(None) => Tristate::True,
+                    Err(_) => Tristate::Unknown,
+                };
+                if condition == Tristate::False || (allow && condition == Tristate::Unknown) {
+                    continue;
+                }
+            }
+            let node = match (ace.ace_type.is_object(), &ace.object_type) {
+                (true, Some(object_type)) => {
+                    match self
+                        .object_types
+                        .iter()
+                        .position(|entry| entry.object_type == *object_type)
+                    {
+                        Some(node) => node,
+                        None => continue,
+                    }
+                }
+                _ => 0,
+            };
+            let mask = map_generic_mask(ace.mask, mapping);
+            if allow {
+                tree.allow(node, mask);
+            } else {
+                tree.deny(node, mask);
+            }
+        }
+        tree.nodes.iter().map(|node| node.granted).collect()
+    }
+}
+
+/// Evaluates `desired_access` against `sd`, `NtAccessCheck` semantics.
+pub fn access_check(
+    sd: &SecurityDescriptor,
+    token: &AccessToken,
+    desired_access: u32,
+    generic_mapping: GENERIC_MAPPING,
+) -> AccessCheckResult {
+    AccessCheck::new(sd, generic_mapping).check(token, desired_access)
+}
+
+fn has_effective_ace_for(acl: &Acl, sid: &Sid) -> bool {
+    acl.aces
+        .iter()
+        .any(|ace: &Ace| !ace.is_inherit_only() && ace.sid == *sid)
+}
+
+/// The SIDs one pass of the DACL walk matches against.
+struct Principal<'a> {
+    groups: [&'a [TokenGroup]; 2],
+}
+
+impl Principal<'_> {
+    fn matches_allow(&self, sid: &Sid) -> bool {
+        self.groups
+            .iter()
+            .flat_map(|groups| groups.iter())
+            .any(|group| group.matches_allow(sid))
+    }
+
+    fn matches_deny(&self, sid: &Sid) -> bool {
+        self.groups
+            .iter()
+            .flat_map(|groups| groups.iter())
+            .any(|group| group.matches_deny(sid))
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Node {
+    level: u16,
+    granted: u32,
+    denied: u32,
+}
+
+/// Object type tree in pre-order, as described by `OBJECT_TYPE_LIST`.
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new(object_types: &[ObjectTypeEntry]) -> Self {
+        let nodes = if object_types.is_empty() {
+            vec![Node::default()]
+        } else {
+            object_types
+                .iter()
+                .map(|entry| Node {
+                    level: entry.level,
+                    ..Node::default()
+                })
+                .collect()
+        };
+        Self { nodes }
+    }
+
+    fn subtree(&self, index: usize) -> std::ops::Range<usize> {
+        let level = self.nodes[index].level;
+        let end = self.nodes[index + 1..]
+            .iter()
+            .position(|node| node.level <= level)
+            .map_or(self.nodes.len(), |offset| index + 1 + offset);
+        index..end
+    }
+
+    fn parent(&self, index: usize) -> Option<usize> {
+        let level = self.nodes[index].level;
+        self.nodes[..index]
+            .iter()
+            .rposition(|node| node.level < level)
+    }
+
+    fn children(&self, index: usize) -> Vec<usize> {
+        let level = self.nodes[index].level;
+        self.subtree(index)
+            .skip(1)
+            .filter(|child| self.parent(*child) == Some(index) && self.nodes[*child].level > level)
+            .collect()
+    }
+
+    fn allow(&mut self, index: usize, mask: u32) {
+        let subtree = self.subtree(index);
+        for node in &mut self.nodes[subtree] {
+            node.granted |= mask & !node.denied;
+        }
+        // A parent is granted whatever all of its children are granted.
+        let mut current = index;
+        while let Some(parent) = self.parent(current) {
+            let common = self
+                .children(parent)
+                .iter()
+                .fold(u32::MAX, |acc, child| acc & self.nodes[*child].granted);
+            let node = &mut self.nodes[parent];
+            node.granted |= common & !node.denied;
+            current = parent;
+        }
+    }
+
+    fn deny(&mut self, index: usize, mask: u32) {
+        let subtree = self.subtree(index);
+        for node in &mut self.nodes[subtree] {
+            node.denied |= mask & !node.granted;
+        }
+        // Denying part of an object denies the whole.
+        let mut current = index;
+        while let Some(parent) = self.parent(current) {
+            let node = &mut self.nodes[parent];
+            node.denied |= mask & !node.granted;
+            current = parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::{
+        Win32::Foundation::{STATUS_ACCESS_DENIED, STATUS_PRIVILEGE_NOT_HELD, STATUS_SUCCESS},
+        core::GUID,
+    };
+
+    use super::*;
+    use crate::{
+        buffer::format_guid,
+        security::{AceFlags, AceType, ClaimValues},
+    };
+
+    const FILE: GENERIC_MAPPING = GENERIC_MAPPING {
+        GenericRead: 0x120089,
+        GenericWrite: 0x120116,
+        GenericExecute: 0x1200a0,
+        GenericAll: 0x1f01ff,
+    };
+
+    fn user() -> Sid {
+        "S-1-5-21-1-2-3-1001".parse().unwrap()
+    }
+
+    fn token() -> AccessToken {
+        AccessToken::new(user())
+            .with_group(TokenGroup::enabled(Sid::everyone()))
+            .with_group(TokenGroup::enabled(Sid::builtin(545)))
+    }
+
+    fn sd(sddl: &str) -> SecurityDescriptor {
+        SecurityDescriptor::from_sddl(sddl).unwrap()
+    }
+
+    #[test]
+    fn dacl_order() {
+        let result = access_check(
+            &sd("O:BAG:SYD:(A;;FR;;;BU)"),
+            &token(),
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x120089);
+        let result = access_check(&sd("O:BAG:SYD:(A;;FR;;;BU)"), &token(), GENERIC_WRITE, FILE);
+        assert_eq!(result.status, STATUS_ACCESS_DENIED);
+        let result = access_check(
+            &sd("O:BAG:SYD:(D;;FW;;;WD)(A;;FA;;;BU)"),
+            &token(),
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x1f01ff & !0x120116);
+        // A deny after the allow that granted the bits has no effect.
+        let result = access_check(
+            &sd("O:BAG:SYD:(A;;FA;;;BU)(D;;FW;;;WD)"),
+            &token(),
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x1f01ff);
+        assert!(access_check(&sd("D:NO_ACCESS_CONTROL"), &token(), GENERIC_ALL, FILE).is_granted());
+        assert!(!access_check(&sd("O:BAD:"), &token(), READ_CONTROL, FILE).is_granted());
+    }
+
+    #[test]
+    fn owner_rights() {
+        // The owner is implicitly granted READ_CONTROL and WRITE_DAC...
+        let result = access_check(
+            &sd(&format!("O:{}D:", user())),
+            &token(),
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, READ_CONTROL | WRITE_DAC);
+        // ...unless an OWNER RIGHTS ACE says otherwise.
+        let result = access_check(
+            &sd(&format!("O:{}D:(A;;FR;;;OW)", user())),
+            &token(),
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x120089);
+        let result = access_check(&sd("O:BAD:"), &token(), ACCESS_SYSTEM_SECURITY, FILE);
+        assert_eq!(result.status, STATUS_PRIVILEGE_NOT_HELD);
+        let privileged = token()
+            .with_privilege(SE_SECURITY_PRIVILEGE)
+            .with_privilege(SE_TAKE_OWNERSHIP_PRIVILEGE);
+        let result = access_check(
+            &sd("O:BAD:"),
+            &privileged,
+            ACCESS_SYSTEM_SECURITY | WRITE_OWNER,
+            FILE,
+        );
+        assert!(result.is_granted());
+        assert_eq!(
+            result.privileges_used,
+            [SE_SECURITY_PRIVILEGE, SE_TAKE_OWNERSHIP_PRIVILEGE]
+        );
+    }
+
+    #[test]
+    fn deny_only_and_integrity() {
+        let token_with_deny = AccessToken::new(user())
+            .with_group(TokenGroup::deny_only(Sid::builtin(544)))
+            .with_group(TokenGroup::enabled(Sid::everyone()));
+        let result = access_check(
+            &sd("D:(A;;FA;;;BA)(A;;FR;;;WD)"),
+            &token_with_deny,
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x120089);
+        // No write up to the implicit medium label.
+        let low = token().with_integrity_level(SECURITY_MANDATORY_LOW_RID);
+        let result = access_check(&sd("D:(A;;FA;;;WD)"), &low, MAXIMUM_ALLOWED, FILE);
+        assert_eq!(result.granted_access & 0x116, 0);
+        assert_ne!(result.granted_access & 1, 0);
+        let result = access_check(
+            &sd("D:(A;;FA;;;WD)S:(ML;;NW;;;LW)"),
+            &low,
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x1f01ff);
+        let result = access_check(
+            &sd("D:(A;;FA;;;WD)S:(ML;;NWNR;;;HI)"),
+            &token(),
+            GENERIC_READ,
+            FILE,
+        );
+        assert!(!result.is_granted());
+    }
+
+    #[test]
+    fn restricted_second_pass() {
+        // Only what both the normal and the restricting SIDs grant.
+        let restricted = token().with_restricted_sid(TokenGroup::enabled(Sid::everyone()));
+        let result = access_check(
+            &sd("D:(A;;FA;;;BU)(A;;FR;;;WD)"),
+            &restricted,
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x120089);
+        let result = access_check(&sd("D:(A;;FA;;;BU)"), &restricted, MAXIMUM_ALLOWED, FILE);
+        assert!(!result.is_granted());
+    }
+
+    #[test]
+    fn app_container_second_pass() {
+        let package = "S-1-15-2-1-2-3-4-5-6-7".parse::<Sid>().unwrap();
+        let capability = "S-1-15-3-1".parse::<Sid>().unwrap();
+        let contained = token().with_app_container(
+            package.clone(),
+            vec![TokenGroup::enabled(capability.clone())],
+        );
+        let result = access_check(&sd("D:(A;;FA;;;BU)"), &contained, MAXIMUM_ALLOWED, FILE);
+        assert!(!result.is_granted());
+        let result = access_check(
+            &sd("D:(A;;FA;;;BU)(A;;FR;;;AC)"),
+            &contained,
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x120089);
+        let result = access_check(
+            &sd(&format!("D:(A;;FA;;;BU)(A;;FX;;;{package})")),
+            &contained,
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x1200a0);
+        let result = access_check(
+            &sd(&format!("D:(A;;FA;;;BU)(A;;FW;;;{capability})")),
+            &contained,
+            MAXIMUM_ALLOWED,
+            FILE,
+        );
+        assert_eq!(result.granted_access, 0x120116);
+    }
+
+    fn conditional_ace(allow: bool) -> Ace {
+        let mut data = b"artx".to_vec();
+        let name = "clearance"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        data.push(0xf9);
+        data.extend((name.len() as u32).to_le_bytes());
+        data.extend(name);
+        data.push(0x04);
+        data.extend(5i64.to_le_bytes());
+        data.extend([3, 2, 0x85]);
+        data.resize(data.len().next_multiple_of(4), 0);
+        let kind = if allow {
+            AceType::AccessAllowedCallback
+        } else {
+            AceType::AccessDeniedCallback
+        };
+        let mut ace = Ace::new(kind, AceFlags(0), 0x1f01ff, Sid::everyone());
+        ace.application_data = data;
+        ace
+    }
+
+    #[test]
+    fn conditional_aces() {
+        let cleared = token().with_user_claim(ClaimAttribute::new(
+            "clearance",
+            ClaimValues::Int64(vec![7]),
+        ));
+        let uncleared = token().with_user_claim(ClaimAttribute::new(
+            "clearance",
+            ClaimValues::Int64(vec![3]),
+        ));
+        let allow = SecurityDescriptor::new().with_dacl(Acl::new().with_ace(conditional_ace(true)));
+        assert!(access_check(&allow, &cleared, GENERIC_ALL, FILE).is_granted());
+        assert!(!access_check(&allow, &uncleared, GENERIC_ALL, FILE).is_granted());
+        assert!(!access_check(&allow, &token(), GENERIC_ALL, FILE).is_granted());
+        // A deny callback ACE applies when its condition is unknown.
+        let deny = SecurityDescriptor::new().with_dacl(
+            Acl::new()
+                .with_ace(conditional_ace(false))
+                .with_ace(Ace::allowed(Sid::everyone(), 0x1f01ff)),
+        );
+        assert!(!access_check(&deny, &token(), GENERIC_ALL, FILE).is_granted());
+        assert!(!access_check(&deny, &cleared, GENERIC_ALL, FILE).is_granted());
+        assert!(access_check(&deny, &uncleared, GENERIC_ALL, FILE).is_granted());
+    }
+
+    #[test]
+    fn object_type_tree() {
+        const DS: GENERIC_MAPPING = GENERIC_MAPPING {
+            GenericRead: 0x20094,
+            GenericWrite: 0x20028,
+            GenericExecute: 0x20004,
+            GenericAll: 0xf01ff,
+        };
+        let root = GUID::from_u128(0xbf967aba_0de6_11d0_a285_00aa003049e2);
+        let set = GUID::from_u128(0x4828cc14_1437_45bc_9b07_ad6f015e5f28);
+        let first = GUID::from_u128(1);
+        let second = GUID::from_u128(2);
+        let tree = vec![
+            ObjectTypeEntry {
+                level: 0,
+                object_type: root,
+            },
+            ObjectTypeEntry {
+                level: 1,
+                object_type: set,
+            },
+            ObjectTypeEntry {
+                level: 2,
+                object_type: first,
+            },
+            ObjectTypeEntry {
+                level: 2,
+                object_type: second,
+            },
+        ];
+        let granted = |sddl: String| {
+            AccessCheck::new(&sd(&sddl), DS)
+                .with_object_types(tree.clone())
+                .check(&token(), 0x10)
+                .results
+                .iter()
+                .map(|result| result.status == STATUS_SUCCESS)
+                .collect::<Vec<_>>()
+        };
+        // A grant on one leaf only grants that leaf.
+        assert_eq!(
+            granted(format!("D:(OA;;RP;{};;WD)", format_guid(&first))),
+            [false, false, true, false]
+        );
+        // Grants on every child propagate up to the parents.
+        assert_eq!(
+            granted(format!(
+                "D:(OA;;RP;{};;WD)(OA;;RP;{};;WD)",
+                format_guid(&first),
+                format_guid(&second)
+            )),
+            [true; 4]
+        );
+        // A deny on one child denies it and its parents.
+        assert_eq!(
+            granted(format!(
+                "D:(OD;;RP;{};;WD)(A;;RP;;;WD)",
+                format_guid(&second)
+            )),
+            [false, false, true, false]
+        );
+    }
+}
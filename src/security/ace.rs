@@ -0,0 +1,542 @@
+use windows::core::GUID;
+
+use super::{ClaimAttribute, Error, Expr, Sid};
+use crate::buffer::{Reader, Writer};
+
+pub const ACE_OBJECT_TYPE_PRESENT: u32 = 1;
+pub const ACE_INHERITED_OBJECT_TYPE_PRESENT: u32 = 2;
+
+pub const SYSTEM_MANDATORY_LABEL_NO_WRITE_UP: u32 = 1;
+pub const SYSTEM_MANDATORY_LABEL_NO_READ_UP: u32 = 2;
+pub const SYSTEM_MANDATORY_LABEL_NO_EXECUTE_UP: u32 = 4;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum AceType {
+    AccessAllowed = 0,
+    AccessDenied = 1,
+    SystemAudit = 2,
+    SystemAlarm = 3,
+    AccessAllowedObject = 5,
+    AccessDeniedObject = 6,
+    SystemAuditObject = 7,
+    SystemAlarmObject = 8,
+    AccessAllowedCallback = 9,
+    AccessDeniedCallback = 10,
+    AccessAllowedCallbackObject = 11,
+    AccessDeniedCallbackObject = 12,
+    SystemAuditCallback = 13,
+    SystemAlarmCallback = 14,
+    SystemAuditCallbackObject = 15,
+    SystemAlarmCallbackObject = 16,
+    SystemMandatoryLabel = 17,
+    SystemResourceAttribute = 18,
+    SystemScopedPolicyId = 19,
+    SystemProcessTrustLabel = 20,
+    SystemAccessFilter = 21,
+}
+
+impl AceType {
+    pub fn from_raw(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::AccessAllowed,
+            1 => Self::AccessDenied,
+            2 => Self::SystemAudit,
+            3 => Self::SystemAlarm,
+            5 => Self::AccessAllowedObject,
+            6 => Self::AccessDeniedObject,
+            7 => Self::SystemAuditObject,
+            8 => Self::SystemAlarmObject,
+            9 => Self::AccessAllowedCallback,
+            10 => Self::AccessDeniedCallback,
+            11 => Self::AccessAllowedCallbackObject,
+            12 => Self::AccessDeniedCallbackObject,
+            13 => Self::SystemAuditCallback,
+            14 => Self::SystemAlarmCallback,
+            15 => Self::SystemAuditCallbackObject,
+            16 => Self::SystemAlarmCallbackObject,
+            17 => Self::SystemMandatoryLabel,
+            18 => Self::SystemResourceAttribute,
+            19 => Self::SystemScopedPolicyId,
+            20 => Self::SystemProcessTrustLabel,
+            21 => Self::SystemAccessFilter,
+            _ => return None,
+        })
+    }
+
+    /// Whether the ACE carries object type GUIDs.
+    pub fn is_object(self) -> bool {
+        matches!(
+            self,
+            Self::AccessAllowedObject
+                | Self::AccessDeniedObject
+                | Self::SystemAuditObject
+                | Self::SystemAlarmObject
+                | Self::AccessAllowedCallbackObject
+                | Self::AccessDeniedCallbackObject
+                | Self::SystemAuditCallbackObject
+                | Self::SystemAlarmCallbackObject
+        )
+    }
+
+    /// Whether the ACE may carry a conditional expression as application
+    /// data.
+    pub fn is_callback(self) -> bool {
+        matches!(
+            self,
+            Self::AccessAllowedCallback
+                | Self::AccessDeniedCallback
+                | Self::AccessAllowedCallbackObject
+                | Self::AccessDeniedCallbackObject
+                | Self::SystemAuditCallback
+                | Self::SystemAlarmCallback
+                | Self::SystemAuditCallbackObject
+                | Self::SystemAlarmCallbackObject
+                | Self::SystemAccessFilter
+        )
+    }
+
+    pub fn is_allowed(self) -> bool {
+        matches!(
+            self,
+            Self::AccessAllowed
+                | Self::AccessAllowedObject
+                | Self::AccessAllowedCallback
+                | Self::AccessAllowedCallbackObject
+        )
+    }
+
+    pub fn is_denied(self) -> bool {
+        matches!(
+            self,
+            Self::AccessDenied
+                | Self::AccessDeniedObject
+                | Self::AccessDeniedCallback
+                | Self::AccessDeniedCallbackObject
+        )
+    }
+
+    pub fn is_audit(self) -> bool {
+        matches!(
+            self,
+            Self::SystemAudit
+                | Self::SystemAuditObject
+                | Self::SystemAuditCallback
+                | Self::SystemAuditCallbackObject
+        )
+    }
+}
+
+/// `ACE_HEADER::AceFlags`.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct AceFlags(pub u8);
+
+impl AceFlags {
+    pub const OBJECT_INHERIT: Self = Self(0x1);
+    pub const CONTAINER_INHERIT: Self = Self(0x2);
+    pub const NO_PROPAGATE_INHERIT: Self = Self(0x4);
+    pub const INHERIT_ONLY: Self = Self(0x8);
+    pub const INHERITED: Self = Self(0x10);
+    pub const CRITICAL: Self = Self(0x20);
+    pub const SUCCESSFUL_ACCESS: Self = Self(0x40);
+    pub const FAILED_ACCESS: Self = Self(0x80);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for AceFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for AceFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::fmt::Debug for AceFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AceFlags({:#04x})", self.0)
+    }
+}
+
+/// An owned access control entry.
+///
+/// Every supported ACE type shares the `mask` + `SID` layout, object ACEs add
+/// the two optional GUIDs before the SID, and callback, resource attribute
+/// and access filter ACEs carry trailing application data after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ace {
+    pub ace_type: AceType,
+    pub flags: AceFlags,
+    pub mask: u32,
+    pub object_type: Option<GUID>,
+    pub inherited_object_type: Option<GUID>,
+    pub sid: Sid,
+    pub application_data: Vec<u8>,
+}
+
+impl Ace {
+    pub fn new(ace_type: AceType, flags: AceFlags, mask: u32, sid: Sid) -> Self {
+        Self {
+            ace_type,
+            flags,
+            mask,
+            object_type: None,
+            inherited_object_type: None,
+            sid,
+            application_data: Vec::new(),
+        }
+    }
+
+    pub fn allowed(sid: Sid, mask: u32) -> Self {
+        Self::new(AceType::AccessAllowed, AceFlags::default(), mask, sid)
+    }
+
+    pub fn denied(sid: Sid, mask: u32) -> Self {
+        Self::new(AceType::AccessDenied, AceFlags::default(), mask, sid)
+    }
+
+    /// A `SYSTEM_AUDIT_ACE` auditing successful and/or failed accesses.
+    pub fn audit(sid: Sid, mask: u32, success: bool, failure: bool) -> Self {
+        let mut flags = AceFlags::default();
+        if success {
+            flags |= AceFlags::SUCCESSFUL_ACCESS;
+        }
+        if failure {
+            flags |= AceFlags::FAILED_ACCESS;
+        }
+        Self::new(AceType::SystemAudit, flags, mask, sid)
+    }
+
+    /// An object ACE of `ace_type`, which must be one of the object types.
+    pub fn object(
+        ace_type: AceType,
+        mask: u32,
+        object_type: Option<GUID>,
+        inherited_object_type: Option<GUID>,
+        sid: Sid,
+    ) -> Self {
+        debug_assert!(ace_type.is_object());
+        Self {
+            object_type,
+            inherited_object_type,
+            ..Self::new(ace_type, AceFlags::default(), mask, sid)
+        }
+    }
+
+    /// A mandatory label for `integrity_level` with the
+    /// `SYSTEM_MANDATORY_LABEL_*` `policy`.
+    pub fn mandatory_label(integrity_level: u32, policy: u32) -> Self {
+        Self::new(
+            AceType::SystemMandatoryLabel,
+            AceFlags::default(),
+            policy,
+            Sid::integrity_level(integrity_level),
+        )
+    }
+
+    /// A resource attribute ACE for `attribute`, granted to Everyone as
+    /// `RtlAddResourceAttributeAce` does.
+    pub fn resource_attribute(flags: AceFlags, attribute: &ClaimAttribute) -> Self {
+        Self {
+            application_data: attribute.to_relative(),
+            ..Self::new(AceType::SystemResourceAttribute, flags, 0, Sid::everyone())
+        }
+    }
+
+    /// A callback ACE of `ace_type` guarded by `condition`.
+    pub fn conditional(
+        ace_type: AceType,
+        flags: AceFlags,
+        mask: u32,
+        sid: Sid,
+        condition: &Expr,
+    ) -> Self {
+        debug_assert!(ace_type.is_callback());
+        Self {
+            application_data: condition.to_bytes(),
+            ..Self::new(ace_type, flags, mask, sid)
+        }
+    }
+
+    /// Decodes the conditional expression carried by a callback ACE.
+    pub fn condition(&self) -> Result<Option<Expr>, Error> {
+        if !self.ace_type.is_callback() || self.application_data.is_empty() {
+            return Ok(None);
+        }
+        Expr::from_bytes(&self.application_data).map(Some)
+    }
+
+    /// Decodes the claim carried by a resource attribute ACE.
+    pub fn claim(&self) -> Result<Option<ClaimAttribute>, Error> {
+        if self.ace_type != AceType::SystemResourceAttribute {
+            return Ok(None);
+        }
+        ClaimAttribute::from_relative(&self.application_data).map(Some)
+    }
+
+    pub fn is_inherit_only(&self) -> bool {
+        self.flags.contains(AceFlags::INHERIT_ONLY)
+    }
+
+    pub fn is_inherited(&self) -> bool {
+        self.flags.contains(AceFlags::INHERITED)
+    }
+
+    /// Size of the binary form, including the header.
+    pub fn len(&self) -> usize {
+        let mut len = 8 + self.sid.len() + self.application_data.len();
+        if self.ace_type.is_object() {
+            len += 4 + 16
+                * (self.object_type.is_some() as usize
+                    + self.inherited_object_type.is_some() as usize);
+        }
+        (len + 3) & !3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::read(&mut Reader::new(bytes))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut writer = Writer::new();
+        self.write(&mut writer)?;
+        Ok(writer.into_inner())
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, Error> {
+        let start = reader.position();
+        let raw_type = reader.u8()?;
+        let ace_type = AceType::from_raw(raw_type).ok_or(Error::UnsupportedAceType(raw_type))?;
+        let flags = AceFlags(reader.u8()?);
+        let size = reader.u16()? as usize;
+        if size < 8 {
+            return Err(reader.invalid("ACE size").into());
+        }
+        let body = Reader::at(reader.data(), start).bytes(size)?;
+        let mut body = Reader::at(body, 4);
+        let mask = body.u32()?;
+
+        let mut object_type = None;
+        let mut inherited_object_type = None;
+        if ace_type.is_object() {
+            let object_flags = body.u32()?;
+            if object_flags & ACE_OBJECT_TYPE_PRESENT != 0 {
+                object_type = Some(body.guid()?);
+            }
+            if object_flags & ACE_INHERITED_OBJECT_TYPE_PRESENT != 0 {
+                inherited_object_type = Some(body.guid()?);
+            }
+        }
+        let sid = Sid::read(&mut body)?;
+        let application_data =
+            if ace_type.is_callback() || ace_type == AceType::SystemResourceAttribute {
+                body.bytes(body.remaining())?.to_vec()
+            } else {
+                Vec::new()
+            };
+        reader.seek(start + size)?;
+        Ok(Self {
+            ace_type,
+            flags,
+            mask,
+            object_type,
+            inherited_object_type,
+            sid,
+            application_data,
+        })
+    }
+
+    pub(crate) fn write(&self, writer: &mut Writer) -> Result<(), Error> {
+        let len = self.len();
+        if len > u16::MAX as usize {
+            return Err(Error::TooLarge);
+        }
+        let start = writer.len();
+        writer
+            .u8(self.ace_type as u8)
+            .u8(self.flags.0)
+            .u16(len as u16)
+            .u32(self.mask);
+        if self.ace_type.is_object() {
+            let mut object_flags = 0;
+            if self.object_type.is_some() {
+                object_flags |= ACE_OBJECT_TYPE_PRESENT;
+            }
+            if self.inherited_object_type.is_some() {
+                object_flags |= ACE_INHERITED_OBJECT_TYPE_PRESENT;
+            }
+            writer.u32(object_flags);
+            for guid in [&self.object_type, &self.inherited_object_type]
+                .into_iter()
+                .flatten()
+            {
+                writer.guid(guid);
+            }
+        }
+        self.sid.write(writer);
+        writer.bytes(&self.application_data);
+        writer.zeros(start + len - writer.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{DecodeError, parse_guid},
+        security::ClaimValues,
+    };
+
+    fn guid(text: &str) -> GUID {
+        parse_guid(text).unwrap()
+    }
+
+    fn round_trip(ace: &Ace) -> Vec<u8> {
+        let bytes = ace.to_bytes().unwrap();
+        assert_eq!(bytes.len(), ace.len());
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(
+            u16::from_le_bytes([bytes[2], bytes[3]]) as usize,
+            bytes.len()
+        );
+        assert_eq!(Ace::from_bytes(&bytes).as_ref(), Ok(ace));
+        bytes
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        for raw in 0..=u8::MAX {
+            match AceType::from_raw(raw) {
+                Some(ace_type) => assert_eq!(ace_type as u8, raw),
+                None => assert!(raw == 4 || raw > 21, "{raw}"),
+            }
+        }
+
+        let users = Sid::builtin(545);
+        let aces = [
+            Ace::allowed(users.clone(), 0x1200a9),
+            Ace::denied(Sid::everyone(), 0x10000),
+            Ace::audit(users.clone(), 0x1f01ff, true, false),
+            Ace::audit(users.clone(), 0x1f01ff, true, true),
+            Ace::object(
+                AceType::AccessAllowedObject,
+                0x30,
+                Some(guid("bf967aba-0de6-11d0-a285-00aa003049e2")),
+                Some(guid("4828cc14-1437-45bc-9b07-ad6f015e5f28")),
+                users.clone(),
+            ),
+            Ace::object(
+                AceType::SystemAuditObject,
+                0x10,
+                None,
+                Some(guid("4828cc14-1437-45bc-9b07-ad6f015e5f28")),
+                users.clone(),
+            ),
+            Ace::object(AceType::AccessDeniedObject, 0x20, None, None, users),
+            Ace::mandatory_label(0x2000, SYSTEM_MANDATORY_LABEL_NO_WRITE_UP),
+            Ace {
+                application_data: vec![1, 2, 3, 4],
+                ..Ace::new(
+                    AceType::AccessAllowedCallback,
+                    AceFlags::CONTAINER_INHERIT | AceFlags::INHERIT_ONLY,
+                    1,
+                    Sid::local_system(),
+                )
+            },
+        ];
+        for ace in &aces {
+            round_trip(ace);
+        }
+        assert_eq!(aces[3].flags, AceFlags(0xc0));
+        assert!(aces[8].is_inherit_only() && !aces[8].is_inherited());
+    }
+
+    #[test]
+    fn writes_object_layout() {
+        let ace = Ace::object(
+            AceType::AccessAllowedObject,
+            0x100,
+            None,
+            Some(guid("bf967aba-0de6-11d0-a285-00aa003049e2")),
+            Sid::everyone(),
+        );
+        let bytes = round_trip(&ace);
+        assert_eq!(&bytes[..12], &[5, 0, 40, 0, 0, 1, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(&bytes[12..16], &[0xba, 0x7a, 0x96, 0xbf]);
+        assert_eq!(&bytes[28..], &Sid::everyone().to_bytes()[..]);
+    }
+
+    #[test]
+    fn carries_conditions_and_claims() {
+        let condition = Expr::from_sddl("(@User.clearance >= 5)").unwrap();
+        let ace = Ace::conditional(
+            AceType::AccessAllowedCallback,
+            AceFlags::default(),
+            0x1f01ff,
+            Sid::everyone(),
+            &condition,
+        );
+        round_trip(&ace);
+        assert_eq!(ace.condition(), Ok(Some(condition)));
+        assert_eq!(ace.claim(), Ok(None));
+        assert_eq!(Ace::allowed(Sid::everyone(), 1).condition(), Ok(None));
+
+        let claim = ClaimAttribute::new("Project", ClaimValues::String(vec!["Ax".into()]));
+        let ace = Ace::resource_attribute(AceFlags::OBJECT_INHERIT, &claim);
+        let parsed = Ace::from_bytes(&ace.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.ace_type, AceType::SystemResourceAttribute);
+        assert_eq!(parsed.sid, Sid::everyone());
+        assert_eq!(parsed.claim(), Ok(Some(claim)));
+        assert_eq!(ace.condition(), Ok(None));
+    }
+
+    #[test]
+    fn rejects_bad_aces() {
+        let bytes = Ace::allowed(Sid::everyone(), 1).to_bytes().unwrap();
+        let mut unknown = bytes.clone();
+        unknown[0] = 4;
+        assert_eq!(Ace::from_bytes(&unknown), Err(Error::UnsupportedAceType(4)));
+
+        let mut short = bytes.clone();
+        short[2] = 4;
+        assert_eq!(
+            Ace::from_bytes(&short),
+            Err(Error::Decode(DecodeError::Invalid {
+                offset: 4,
+                what: "ACE size"
+            }))
+        );
+        assert!(matches!(
+            Ace::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Decode(DecodeError::Truncated { offset: 0, .. }))
+        ));
+        // A size too small for the SID it claims to hold.
+        let mut cut = bytes;
+        cut[2] = 12;
+        assert!(Ace::from_bytes(&cut).is_err());
+
+        let huge = Ace {
+            application_data: vec![0; 0x10000],
+            ..Ace::new(
+                AceType::AccessAllowedCallback,
+                AceFlags::default(),
+                0,
+                Sid::everyone(),
+            )
+        };
+        assert_eq!(huge.to_bytes(), Err(Error::TooLarge));
+    }
+}
@@ -0,0 +1,123 @@
+use super::{Ace, Error};
+use crate::buffer::{Reader, Writer};
+
+pub const ACL_REVISION: u8 = 2;
+pub const ACL_REVISION_DS: u8 = 4;
+
+/// An owned access control list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl {
+    /// The revision read from the binary form. Serialization raises it to
+    /// [`ACL_REVISION_DS`] when the list holds object ACEs.
+    pub revision: u8,
+    pub aces: Vec<Ace>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Acl {
+    pub const fn new() -> Self {
+        Self {
+            revision: ACL_REVISION,
+            aces: Vec::new(),
+        }
+    }
+
+    pub fn with_ace(mut self, ace: Ace) -> Self {
+        self.aces.push(ace);
+        self
+    }
+
+    pub fn push(&mut self, ace: Ace) {
+        self.aces.push(ace);
+    }
+
+    /// The revision the binary form will carry.
+    pub fn effective_revision(&self) -> u8 {
+        if self.aces.iter().any(|ace| ace.ace_type.is_object()) {
+            self.revision.max(ACL_REVISION_DS)
+        } else {
+            self.revision.max(ACL_REVISION)
+        }
+    }
+
+    /// Size of the binary form, `ACL::AclSize`.
+    pub fn len(&self) -> usize {
+        8 + self.aces.iter().map(Ace::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aces.is_empty()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::read(&mut Reader::new(bytes))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut writer = Writer::new();
+        self.write(&mut writer)?;
+        Ok(writer.into_inner())
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, Error> {
+        let start = reader.position();
+        let revision = reader.u8()?;
+        if !(ACL_REVISION..=ACL_REVISION_DS).contains(&revision) {
+            return Err(Error::InvalidRevision(revision));
+        }
+        reader.skip(1)?;
+        let size = reader.u16()? as usize;
+        let count = reader.u16()? as usize;
+        reader.skip(2)?;
+        let body = Reader::at(reader.data(), start).bytes(size)?;
+        let mut body = Reader::at(body, 8);
+        let aces = (0..count)
+            .map(|_| Ace::read(&mut body))
+            .collect::<Result<_, _>>()?;
+        reader.seek(start + size)?;
+        Ok(Self { revision, aces })
+    }
+
+    pub(crate) fn write(&self, writer: &mut Writer) -> Result<(), Error> {
+        let len = self.len();
+        if len > u16::MAX as usize || self.aces.len() > u16::MAX as usize {
+            return Err(Error::TooLarge);
+        }
+        writer
+            .u8(self.effective_revision())
+            .u8(0)
+            .u16(len as u16)
+            .u16(self.aces.len() as u16)
+            .u16(0);
+        for ace in &self.aces {
+            ace.write(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::Sid;
+
+    fn sid(value: &str) -> Sid {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn binary_layout() {
+        let acl = Acl::new().with_ace(Ace::allowed(sid("S-1-1-0"), 0x10000000));
+        let bytes = acl.to_bytes().unwrap();
+        assert_eq!(bytes.len(), acl.len());
+        assert_eq!(&bytes[..8], &[2, 0, 0x1c, 0, 1, 0, 0, 0]);
+        assert_eq!(Acl::from_bytes(&bytes).unwrap(), acl);
+        assert!(Acl::from_bytes(&[9, 0, 8, 0, 0, 0, 0, 0]).is_err());
+        assert!(Acl::from_bytes(&[2, 0, 0x1c, 0, 1, 0, 0, 0]).is_err());
+    }
+}
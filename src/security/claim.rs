@@ -0,0 +1,304 @@
+use super::{Error, Sid};
+use crate::{
+    buffer::{Reader, Writer},
+    ntseapi::{
+        TOKEN_SECURITY_ATTRIBUTE_TYPE_BOOLEAN, TOKEN_SECURITY_ATTRIBUTE_TYPE_FQBN,
+        TOKEN_SECURITY_ATTRIBUTE_TYPE_INT64, TOKEN_SECURITY_ATTRIBUTE_TYPE_OCTET_STRING,
+        TOKEN_SECURITY_ATTRIBUTE_TYPE_SID, TOKEN_SECURITY_ATTRIBUTE_TYPE_STRING,
+        TOKEN_SECURITY_ATTRIBUTE_TYPE_UINT64,
+    },
+};
+
+/// The values of a claim (security attribute), all of a single type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClaimValues {
+    Int64(Vec<i64>),
+    Uint64(Vec<u64>),
+    String(Vec<String>),
+    Fqbn(Vec<(u64, String)>),
+    Sid(Vec<Sid>),
+    Boolean(Vec<bool>),
+    OctetString(Vec<Vec<u8>>),
+}
+
+impl ClaimValues {
+    /// The `TOKEN_SECURITY_ATTRIBUTE_TYPE_*` value of this set.
+    pub fn value_type(&self) -> u16 {
+        (match self {
+            Self::Int64(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_INT64,
+            Self::Uint64(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_UINT64,
+            Self::String(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_STRING,
+            Self::Fqbn(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_FQBN,
+            Self::Sid(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_SID,
+            Self::Boolean(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_BOOLEAN,
+            Self::OctetString(_) => TOKEN_SECURITY_ATTRIBUTE_TYPE_OCTET_STRING,
+        }) as u16
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Int64(values) => values.len(),
+            Self::Uint64(values) => values.len(),
+            Self::String(values) => values.len(),
+            Self::Fqbn(values) => values.len(),
+            Self::Sid(values) => values.len(),
+            Self::Boolean(values) => values.len(),
+            Self::OctetString(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A claim as stored in a resource attribute ACE or carried by a token, the
+/// owned form of `CLAIM_SECURITY_ATTRIBUTE_RELATIVE_V1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimAttribute {
+    pub name: String,
+    /// `TOKEN_SECURITY_ATTRIBUTE_*` flags.
+    pub flags: u32,
+    pub values: ClaimValues,
+}
+
+impl ClaimAttribute {
+    pub fn new(name: impl Into<String>, values: ClaimValues) -> Self {
+        Self {
+            name: name.into(),
+            flags: 0,
+            values,
+        }
+    }
+
+    /// Decodes the self-relative `CLAIM_SECURITY_ATTRIBUTE_RELATIVE_V1`
+    /// layout, where every pointer is an offset from the start of `bytes`.
+    pub fn from_relative(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        let name_offset = reader.u32()? as usize;
+        let value_type = reader.u16()? as u32;
+        reader.skip(2)?;
+        let flags = reader.u32()?;
+        let count = reader.u32()? as usize;
+        let offsets = (0..count)
+            .map(|_| reader.u32().map(|offset| offset as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        let name = Reader::at(bytes, name_offset).utf16z()?;
+
+        let octets = |offset: usize| -> Result<Vec<u8>, Error> {
+            let mut reader = Reader::at(bytes, offset);
+            let len = reader.u32()? as usize;
+            Ok(reader.bytes(len)?.to_vec())
+        };
+        let values = match value_type {
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_INT64 => ClaimValues::Int64(
+                offsets
+                    .iter()
+                    .map(|offset| Reader::at(bytes, *offset).i64())
+                    .collect::<Result<_, _>>()?,
+            ),
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_UINT64 => ClaimValues::Uint64(
+                offsets
+                    .iter()
+                    .map(|offset| Reader::at(bytes, *offset).u64())
+                    .collect::<Result<_, _>>()?,
+            ),
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_BOOLEAN => ClaimValues::Boolean(
+                offsets
+                    .iter()
+                    .map(|offset| Reader::at(bytes, *offset).u64().map(|value| value != 0))
+                    .collect::<Result<_, _>>()?,
+            ),
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_STRING => ClaimValues::String(
+                offsets
+                    .iter()
+                    .map(|offset| Reader::at(bytes, *offset).utf16z())
+                    .collect::<Result<_, _>>()?,
+            ),
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_FQBN => ClaimValues::Fqbn(
+                offsets
+                    .iter()
+                    .map(|offset| -> Result<_, Error> {
+                        let mut reader = Reader::at(bytes, *offset);
+                        let version = reader.u64()?;
+                        let name = Reader::at(bytes, reader.u32()? as usize).utf16z()?;
+                        Ok((version, name))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_SID => ClaimValues::Sid(
+                offsets
+                    .iter()
+                    .map(|offset| Sid::from_bytes(&octets(*offset)?))
+                    .collect::<Result<_, _>>()?,
+            ),
+            TOKEN_SECURITY_ATTRIBUTE_TYPE_OCTET_STRING => ClaimValues::OctetString(
+                offsets
+                    .iter()
+                    .map(|offset| octets(*offset))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(Error::UnsupportedClaimType(value_type as u16)),
+        };
+        Ok(Self {
+            name,
+            flags,
+            values,
+        })
+    }
+
+    /// Encodes the claim in the self-relative
+    /// `CLAIM_SECURITY_ATTRIBUTE_RELATIVE_V1` layout.
+    pub fn to_relative(&self) -> Vec<u8> {
+        let count = self.values.len();
+        let mut writer = Writer::new();
+        writer
+            .u32(0)
+            .u16(self.values.value_type())
+            .u16(0)
+            .u32(self.flags)
+            .u32(count as u32)
+            .zeros(4 * count);
+        let name_offset = writer.len();
+        writer.utf16z(&self.name);
+        writer.patch_u32(0, name_offset as u32);
+
+        let value_offset = |writer: &mut Writer, index: usize, alignment: usize| {
+            writer.align(alignment);
+            let offset = writer.len() as u32;
+            writer.patch_u32(16 + 4 * index, offset);
+        };
+        match &self.values {
+            ClaimValues::Int64(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 8);
+                    writer.i64(*value);
+                }
+            }
+            ClaimValues::Uint64(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 8);
+                    writer.u64(*value);
+                }
+            }
+            ClaimValues::Boolean(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 8);
+                    writer.u64(*value as u64);
+                }
+            }
+            ClaimValues::String(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 2);
+                    writer.utf16z(value);
+                }
+            }
+            ClaimValues::Fqbn(values) => {
+                let mut names = Vec::with_capacity(values.len());
+                for (index, (version, _)) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 8);
+                    writer.u64(*version);
+                    names.push(writer.len());
+                    writer.u32(0);
+                }
+                for ((_, name), patch) in values.iter().zip(names) {
+                    writer.align(2);
+                    let offset = writer.len() as u32;
+                    writer.utf16z(name);
+                    writer.patch_u32(patch, offset);
+                }
+            }
+            ClaimValues::Sid(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 4);
+                    writer.u32(value.len() as u32).bytes(&value.to_bytes());
+                }
+            }
+            ClaimValues::OctetString(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    value_offset(&mut writer, index, 4);
+                    writer.u32(value.len() as u32).bytes(value);
+                }
+            }
+        }
+        writer.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::DecodeError;
+
+    #[test]
+    fn round_trips_every_value_type() {
+        for values in [
+            ClaimValues::Int64(vec![-5, i64::MAX]),
+            ClaimValues::Uint64(vec![0, u64::MAX]),
+            ClaimValues::String(vec!["a".into(), String::new(), "Zürich".into()]),
+            ClaimValues::Fqbn(vec![
+                (1 << 32, "O=Contoso".into()),
+                (0, "O=Fabrikam".into()),
+            ]),
+            ClaimValues::Sid(vec![Sid::builtin(544), Sid::everyone()]),
+            ClaimValues::Boolean(vec![true, false]),
+            ClaimValues::OctetString(vec![vec![1, 2, 3], Vec::new()]),
+            ClaimValues::Int64(Vec::new()),
+        ] {
+            let claim = ClaimAttribute {
+                flags: 0x11,
+                ..ClaimAttribute::new("Dept", values)
+            };
+            let bytes = claim.to_relative();
+            assert_eq!(
+                u16::from_le_bytes([bytes[4], bytes[5]]),
+                claim.values.value_type()
+            );
+            assert_eq!(ClaimAttribute::from_relative(&bytes), Ok(claim));
+        }
+    }
+
+    #[test]
+    fn writes_relative_layout() {
+        let claim = ClaimAttribute::new("ab", ClaimValues::Int64(vec![7, -1]));
+        assert_eq!(claim.values.len(), 2);
+        let bytes = claim.to_relative();
+        #[rustfmt::skip]
+        let expected = [
+            24, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 32, 0, 0, 0, 40, 0, 0, 0,
+            b'a', 0, b'b', 0, 0, 0, 0, 0,
+            7, 0, 0, 0, 0, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        assert_eq!(bytes, expected);
+
+        // Booleans take a whole `ULONG64`.
+        let bytes = ClaimAttribute::new("", ClaimValues::Boolean(vec![true])).to_relative();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(&bytes[24..], &1u64.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_bad_claims() {
+        let mut bytes = ClaimAttribute::new("x", ClaimValues::Uint64(vec![1])).to_relative();
+        bytes[4] = 7;
+        assert_eq!(
+            ClaimAttribute::from_relative(&bytes),
+            Err(Error::UnsupportedClaimType(7))
+        );
+        bytes[4] = 2;
+        // The value offset points past the end.
+        bytes[16] = 0xf8;
+        assert_eq!(
+            ClaimAttribute::from_relative(&bytes),
+            Err(Error::Decode(DecodeError::Truncated {
+                offset: 0xf8,
+                needed: 8
+            }))
+        );
+        // More values than offsets.
+        bytes[12] = 9;
+        assert!(ClaimAttribute::from_relative(&bytes).is_err());
+        assert!(ClaimAttribute::from_relative(&[]).is_err());
+    }
+}
@@ -0,0 +1,688 @@
+//! Conditional ACE expressions, the `artx` application data carried by
+//! callback ACEs.
+//!
+//! The binary form is a postfix token stream; it is decoded here into an
+//! expression tree that can be evaluated against a set of claims, and encoded
+//! back. The SDDL text form is handled by the `sddl` module.
+
+use std::cmp::Ordering;
+
+use super::{ClaimAttribute, ClaimValues, Error, Sid};
+use crate::{
+    buffer::{DecodeError, Reader, Writer},
+    ntseapi::TOKEN_SECURITY_ATTRIBUTE_VALUE_CASE_SENSITIVE,
+};
+
+/// The `artx` signature that starts every conditional expression.
+pub const CONDITIONAL_ACE_SIGNATURE: [u8; 4] = *b"artx";
+
+pub const CONDITIONAL_TOKEN_PADDING: u8 = 0x00;
+pub const CONDITIONAL_TOKEN_INT8: u8 = 0x01;
+pub const CONDITIONAL_TOKEN_INT16: u8 = 0x02;
+pub const CONDITIONAL_TOKEN_INT32: u8 = 0x03;
+pub const CONDITIONAL_TOKEN_INT64: u8 = 0x04;
+pub const CONDITIONAL_TOKEN_UNICODE_STRING: u8 = 0x10;
+pub const CONDITIONAL_TOKEN_OCTET_STRING: u8 = 0x18;
+pub const CONDITIONAL_TOKEN_COMPOSITE: u8 = 0x50;
+pub const CONDITIONAL_TOKEN_SID: u8 = 0x51;
+pub const CONDITIONAL_TOKEN_LOCAL_ATTRIBUTE: u8 = 0xf8;
+pub const CONDITIONAL_TOKEN_USER_ATTRIBUTE: u8 = 0xf9;
+pub const CONDITIONAL_TOKEN_RESOURCE_ATTRIBUTE: u8 = 0xfa;
+pub const CONDITIONAL_TOKEN_DEVICE_ATTRIBUTE: u8 = 0xfb;
+
+/// Sign indicator stored with integer literals.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum IntSign {
+    Positive = 1,
+    Negative = 2,
+    None = 3,
+}
+
+/// Base indicator stored with integer literals, used only for display.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum IntBase {
+    Octal = 1,
+    Decimal = 2,
+    Hexadecimal = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Literal {
+    Int {
+        value: i64,
+        sign: IntSign,
+        base: IntBase,
+    },
+    String(String),
+    OctetString(Vec<u8>),
+    Sid(Sid),
+    Composite(Vec<Literal>),
+}
+
+impl Literal {
+    pub fn int(value: i64) -> Self {
+        Self::Int {
+            value,
+            sign: IntSign::None,
+            base: IntBase::Decimal,
+        }
+    }
+}
+
+/// Where an attribute reference is looked up.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum AttributeSource {
+    Local,
+    User,
+    Resource,
+    Device,
+}
+
+impl AttributeSource {
+    pub fn token(self) -> u8 {
+        match self {
+            Self::Local => CONDITIONAL_TOKEN_LOCAL_ATTRIBUTE,
+            Self::User => CONDITIONAL_TOKEN_USER_ATTRIBUTE,
+            Self::Resource => CONDITIONAL_TOKEN_RESOURCE_ATTRIBUTE,
+            Self::Device => CONDITIONAL_TOKEN_DEVICE_ATTRIBUTE,
+        }
+    }
+
+    fn from_token(token: u8) -> Option<Self> {
+        Some(match token {
+            CONDITIONAL_TOKEN_LOCAL_ATTRIBUTE => Self::Local,
+            CONDITIONAL_TOKEN_USER_ATTRIBUTE => Self::User,
+            CONDITIONAL_TOKEN_RESOURCE_ATTRIBUTE => Self::Resource,
+            CONDITIONAL_TOKEN_DEVICE_ATTRIBUTE => Self::Device,
+            _ => return None,
+        })
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum UnaryOp {
+    MemberOf = 0x89,
+    DeviceMemberOf = 0x8a,
+    MemberOfAny = 0x8b,
+    DeviceMemberOfAny = 0x8c,
+    NotMemberOf = 0x90,
+    NotDeviceMemberOf = 0x91,
+    NotMemberOfAny = 0x92,
+    NotDeviceMemberOfAny = 0x93,
+    Exists = 0x87,
+    NotExists = 0x8d,
+    Not = 0xa2,
+}
+
+impl UnaryOp {
+    pub fn from_token(token: u8) -> Option<Self> {
+        Some(match token {
+            0x89 => Self::MemberOf,
+            0x8a => Self::DeviceMemberOf,
+            0x8b => Self::MemberOfAny,
+            0x8c => Self::DeviceMemberOfAny,
+            0x90 => Self::NotMemberOf,
+            0x91 => Self::NotDeviceMemberOf,
+            0x92 => Self::NotMemberOfAny,
+            0x93 => Self::NotDeviceMemberOfAny,
+            0x87 => Self::Exists,
+            0x8d => Self::NotExists,
+            0xa2 => Self::Not,
+            _ => return None,
+        })
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum BinaryOp {
+    Equals = 0x80,
+    NotEquals = 0x81,
+    LessThan = 0x82,
+    LessThanOrEqual = 0x83,
+    GreaterThan = 0x84,
+    GreaterThanOrEqual = 0x85,
+    Contains = 0x86,
+    AnyOf = 0x88,
+    NotContains = 0x8e,
+    NotAnyOf = 0x8f,
+    And = 0xa0,
+    Or = 0xa1,
+}
+
+impl BinaryOp {
+    pub fn from_token(token: u8) -> Option<Self> {
+        Some(match token {
+            0x80 => Self::Equals,
+            0x81 => Self::NotEquals,
+            0x82 => Self::LessThan,
+            0x83 => Self::LessThanOrEqual,
+            0x84 => Self::GreaterThan,
+            0x85 => Self::GreaterThanOrEqual,
+            0x86 => Self::Contains,
+            0x88 => Self::AnyOf,
+            0x8e => Self::NotContains,
+            0x8f => Self::NotAnyOf,
+            0xa0 => Self::And,
+            0xa1 => Self::Or,
+            _ => return None,
+        })
+    }
+}
+
+/// A node of a conditional expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Literal(Literal),
+    Attribute(AttributeSource, String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// Three-valued result of evaluating a condition.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Tristate {
+    False,
+    True,
+    Unknown,
+}
+
+impl Tristate {
+    fn not(self) -> Self {
+        match self {
+            Self::False => Self::True,
+            Self::True => Self::False,
+            Self::Unknown => Self::Unknown,
+        }
+    }
+
+    fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::False, _) | (_, Self::False) => Self::False,
+            (Self::True, Self::True) => Self::True,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn or(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::True, _) | (_, Self::True) => Self::True,
+            (Self::False, Self::False) => Self::False,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Tristate {
+    fn from(value: bool) -> Self {
+        if value { Self::True } else { Self::False }
+    }
+}
+
+impl Expr {
+    /// Decodes the application data of a callback ACE. Trailing padding
+    /// after the last token is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        if reader.array::<4>()? != CONDITIONAL_ACE_SIGNATURE {
+            return Err(DecodeError::Invalid {
+                offset: 0,
+                what: "conditional expression signature",
+            }
+            .into());
+        }
+        let mut stack = Vec::new();
+        while !reader.is_empty() {
+            let offset = reader.position();
+            let token = reader.u8()?;
+            if token == CONDITIONAL_TOKEN_PADDING {
+                continue;
+            }
+            let underflow = DecodeError::Invalid {
+                offset,
+                what: "conditional expression operand",
+            };
+            let expr = if let Some(source) = AttributeSource::from_token(token) {
+                Expr::Attribute(source, read_string(&mut reader)?)
+            } else if let Some(op) = UnaryOp::from_token(token) {
+                let operand = stack.pop().ok_or(underflow)?;
+                Expr::Unary(op, Box::new(operand))
+            } else if let Some(op) = BinaryOp::from_token(token) {
+                let right = stack.pop().ok_or(underflow)?;
+                let left = stack.pop().ok_or(underflow)?;
+                Expr::Binary(op, Box::new(left), Box::new(right))
+            } else {
+                Expr::Literal(read_literal(token, &mut reader, offset)?)
+            };
+            stack.push(expr);
+        }
+        match (stack.pop(), stack.is_empty()) {
+            (Some(expr), true) => Ok(expr),
+            _ => Err(DecodeError::Invalid {
+                offset: bytes.len(),
+                what: "conditional expression",
+            }
+            .into()),
+        }
+    }
+
+    /// Encodes the expression as callback ACE application data, padded to a
+    /// multiple of four bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.bytes(&CONDITIONAL_ACE_SIGNATURE);
+        self.write(&mut writer);
+        writer.align(4);
+        writer.into_inner()
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        match self {
+            Expr::Literal(literal) => write_literal(literal, writer),
+            Expr::Attribute(source, name) => {
+                writer.u8(source.token());
+                write_string(name, writer);
+            }
+            Expr::Unary(op, operand) => {
+                operand.write(writer);
+                writer.u8(*op as u8);
+            }
+            Expr::Binary(op, left, right) => {
+                left.write(writer);
+                right.write(writer);
+                writer.u8(*op as u8);
+            }
+        }
+    }
+
+    /// Evaluates the expression with three-valued logic.
+    pub fn evaluate(&self, context: &EvaluationContext) -> Tristate {
+        match self.value(context) {
+            Value::Bool(result) => result,
+            Value::Set(values, _) => match values.as_slice() {
+                [Scalar::Int(value)] => (*value != 0).into(),
+                _ => Tristate::Unknown,
+            },
+            Value::Null => Tristate::Unknown,
+        }
+    }
+
+    fn value(&self, context: &EvaluationContext) -> Value {
+        match self {
+            Expr::Literal(literal) => Value::Set(literal_scalars(literal), false),
+            Expr::Attribute(source, name) => {
+                context
+                    .attribute(*source, name)
+                    .map_or(Value::Null, |claim| {
+                        Value::Set(
+                            claim_scalars(&claim.values),
+                            claim.flags & TOKEN_SECURITY_ATTRIBUTE_VALUE_CASE_SENSITIVE != 0,
+                        )
+                    })
+            }
+            Expr::Unary(op, operand) => Value::Bool(unary(*op, operand, context)),
+            Expr::Binary(op, left, right) => Value::Bool(binary(*op, left, right, context)),
+        }
+    }
+}
+
+fn read_string(reader: &mut Reader) -> Result<String, Error> {
+    let len = reader.u32()? as usize;
+    if !len.is_multiple_of(2) {
+        return Err(reader.invalid("conditional expression string").into());
+    }
+    Ok(reader.utf16(len / 2)?)
+}
+
+fn write_string(value: &str, writer: &mut Writer) {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    writer.u32(units.len() as u32 * 2);
+    for unit in units {
+        writer.u16(unit);
+    }
+}
+
+fn write_sized(token: u8, writer: &mut Writer, write: impl FnOnce(&mut Writer)) {
+    writer.u8(token).u32(0);
+    let start = writer.len();
+    write(writer);
+    writer.patch_u32(start - 4, (writer.len() - start) as u32);
+}
+
+// Integers are always encoded as 64-bit, as the system compiler does.
+fn write_literal(literal: &Literal, writer: &mut Writer) {
+    match literal {
+        Literal::Int { value, sign, base } => {
+            writer
+                .u8(CONDITIONAL_TOKEN_INT64)
+                .i64(*value)
+                .u8(*sign as u8)
+                .u8(*base as u8);
+        }
+        Literal::String(value) => {
+            writer.u8(CONDITIONAL_TOKEN_UNICODE_STRING);
+            write_string(value, writer);
+        }
+        Literal::OctetString(value) => {
+            write_sized(CONDITIONAL_TOKEN_OCTET_STRING, writer, |writer| {
+                writer.bytes(value);
+            });
+        }
+        Literal::Sid(sid) => {
+            write_sized(CONDITIONAL_TOKEN_SID, writer, |writer| sid.write(writer));
+        }
+        Literal::Composite(items) => {
+            write_sized(CONDITIONAL_TOKEN_COMPOSITE, writer, |writer| {
+                for item in items {
+                    write_literal(item, writer);
+                }
+            });
+        }
+    }
+}
+
+fn read_literal(token: u8, reader: &mut Reader, offset: usize) -> Result<Literal, Error> {
+    Ok(match token {
+        CONDITIONAL_TOKEN_INT8
+        | CONDITIONAL_TOKEN_INT16
+        | CONDITIONAL_TOKEN_INT32
+        | CONDITIONAL_TOKEN_INT64 => {
+            let value = reader.i64()?;
+            let sign = match reader.u8()? {
+                1 => IntSign::Positive,
+                2 => IntSign::Negative,
+                3 => IntSign::None,
+                _ => return Err(reader.invalid("integer sign").into()),
+            };
+            let base = match reader.u8()? {
+                1 => IntBase::Octal,
+                2 => IntBase::Decimal,
+                3 => IntBase::Hexadecimal,
+                _ => return Err(reader.invalid("integer base").into()),
+            };
+            Literal::Int { value, sign, base }
+        }
+        CONDITIONAL_TOKEN_UNICODE_STRING => Literal::String(read_string(reader)?),
+        CONDITIONAL_TOKEN_OCTET_STRING => {
+            let len = reader.u32()? as usize;
+            Literal::OctetString(reader.bytes(len)?.to_vec())
+        }
+        CONDITIONAL_TOKEN_SID => {
+            let len = reader.u32()? as usize;
+            Literal::Sid(Sid::from_bytes(reader.bytes(len)?)?)
+        }
+        CONDITIONAL_TOKEN_COMPOSITE => {
+            let len = reader.u32()? as usize;
+            let mut inner = Reader::new(reader.bytes(len)?);
+            let mut items = Vec::new();
+            while !inner.is_empty() {
+                let offset = inner.position();
+                let token = inner.u8()?;
+                items.push(read_literal(token, &mut inner, offset)?);
+            }
+            Literal::Composite(items)
+        }
+        _ => {
+            return Err(DecodeError::Invalid {
+                offset,
+                what: "conditional expression token",
+            }
+            .into());
+        }
+    })
+}
+
+/// The claims and group memberships a condition is evaluated against.
+#[derive(Debug, Clone, Default)]
+pub struct EvaluationContext<'a> {
+    pub user_claims: &'a [ClaimAttribute],
+    pub device_claims: &'a [ClaimAttribute],
+    pub resource_claims: &'a [ClaimAttribute],
+    pub local_claims: &'a [ClaimAttribute],
+    /// SIDs tested by `Member_of` and friends: the user and enabled groups.
+    pub member_sids: &'a [Sid],
+    /// SIDs tested by `Device_Member_of` and friends.
+    pub device_sids: &'a [Sid],
+}
+
+impl EvaluationContext<'_> {
+    fn attribute(&self, source: AttributeSource, name: &str) -> Option<&ClaimAttribute> {
+        let claims = match source {
+            AttributeSource::Local => self.local_claims,
+            AttributeSource::User => self.user_claims,
+            AttributeSource::Resource => self.resource_claims,
+            AttributeSource::Device => self.device_claims,
+        };
+        claims
+            .iter()
+            .find(|claim| claim.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scalar {
+    Int(i128),
+    String(String),
+    Sid(Sid),
+    Octets(Vec<u8>),
+}
+
+impl Scalar {
+    fn compare(&self, other: &Self, case_sensitive: bool) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => Some(a.cmp(b)),
+            (Self::String(a), Self::String(b)) if case_sensitive => Some(a.cmp(b)),
+            (Self::String(a), Self::String(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+            (Self::Sid(a), Self::Sid(b)) => (a == b).then_some(Ordering::Equal),
+            (Self::Octets(a), Self::Octets(b)) => (a == b).then_some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+enum Value {
+    Null,
+    Bool(Tristate),
+    /// Values plus whether string comparisons are case sensitive.
+    Set(Vec<Scalar>, bool),
+}
+
+fn literal_scalars(literal: &Literal) -> Vec<Scalar> {
+    match literal {
+        Literal::Int { value, .. } => vec![Scalar::Int(*value as i128)],
+        Literal::String(value) => vec![Scalar::String(value.clone())],
+        Literal::OctetString(value) => vec![Scalar::Octets(value.clone())],
+        Literal::Sid(value) => vec![Scalar::Sid(value.clone())],
+        Literal::Composite(items) => items.iter().flat_map(literal_scalars).collect(),
+    }
+}
+
+fn claim_scalars(values: &ClaimValues) -> Vec<Scalar> {
+    match values {
+        ClaimValues::Int64(values) => values.iter().map(|v| Scalar::Int(*v as i128)).collect(),
+        ClaimValues::Uint64(values) => values.iter().map(|v| Scalar::Int(*v as i128)).collect(),
+        ClaimValues::Boolean(values) => values.iter().map(|v| Scalar::Int(*v as i128)).collect(),
+        ClaimValues::String(values) => values.iter().cloned().map(Scalar::String).collect(),
+        ClaimValues::Fqbn(values) => values
+            .iter()
+            .map(|(_, name)| Scalar::String(name.clone()))
+            .collect(),
+        ClaimValues::Sid(values) => values.iter().cloned().map(Scalar::Sid).collect(),
+        ClaimValues::OctetString(values) => values.iter().cloned().map(Scalar::Octets).collect(),
+    }
+}
+
+fn unary(op: UnaryOp, operand: &Expr, context: &EvaluationContext) -> Tristate {
+    match op {
+        UnaryOp::Not => operand.evaluate(context).not(),
+        UnaryOp::Exists | UnaryOp::NotExists => {
+            let Expr::Attribute(source, name) = operand else {
+                return Tristate::Unknown;
+            };
+            let exists = context.attribute(*source, name).is_some();
+            (exists == (op == UnaryOp::Exists)).into()
+        }
+        _ => {
+            let sids = match operand.value(context) {
+                Value::Set(values, _) => values
+                    .into_iter()
+                    .map(|value| match value {
+                        Scalar::Sid(sid) => Some(sid),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>(),
+                _ => None,
+            };
+            let Some(sids) = sids else {
+                return Tristate::Unknown;
+            };
+            let (members, any, negate) = match op {
+                UnaryOp::MemberOf => (context.member_sids, false, false),
+                UnaryOp::MemberOfAny => (context.member_sids, true, false),
+                UnaryOp::NotMemberOf => (context.member_sids, false, true),
+                UnaryOp::NotMemberOfAny => (context.member_sids, true, true),
+                UnaryOp::DeviceMemberOf => (context.device_sids, false, false),
+                UnaryOp::DeviceMemberOfAny => (context.device_sids, true, false),
+                UnaryOp::NotDeviceMemberOf => (context.device_sids, false, true),
+                _ => (context.device_sids, true, true),
+            };
+            let result = if any {
+                sids.iter().any(|sid| members.contains(sid))
+            } else {
+                sids.iter().all(|sid| members.contains(sid))
+            };
+            (result != negate).into()
+        }
+    }
+}
+
+fn binary(op: BinaryOp, left: &Expr, right: &Expr, context: &EvaluationContext) -> Tristate {
+    match op {
+        BinaryOp::And => return left.evaluate(context).and(right.evaluate(context)),
+        BinaryOp::Or => return left.evaluate(context).or(right.evaluate(context)),
+        _ => {}
+    }
+    let (Value::Set(left, left_case), Value::Set(right, right_case)) =
+        (left.value(context), right.value(context))
+    else {
+        return Tristate::Unknown;
+    };
+    let case_sensitive = left_case || right_case;
+    let contains = |set: &[Scalar], value: &Scalar| {
+        set.iter()
+            .any(|item| item.compare(value, case_sensitive) == Some(Ordering::Equal))
+    };
+    match op {
+        BinaryOp::Equals | BinaryOp::NotEquals => {
+            let equal = left.len() == right.len()
+                && left.iter().all(|value| contains(&right, value))
+                && right.iter().all(|value| contains(&left, value));
+            (equal == (op == BinaryOp::Equals)).into()
+        }
+        BinaryOp::Contains | BinaryOp::NotContains => {
+            let result = right.iter().all(|value| contains(&left, value));
+            (result == (op == BinaryOp::Contains)).into()
+        }
+        BinaryOp::AnyOf | BinaryOp::NotAnyOf => {
+            let result = left.iter().any(|value| contains(&right, value));
+            (result == (op == BinaryOp::AnyOf)).into()
+        }
+        _ => {
+            let ([left], [right]) = (left.as_slice(), right.as_slice()) else {
+                return Tristate::Unknown;
+            };
+            let Some(ordering) = left.compare(right, case_sensitive) else {
+                return Tristate::Unknown;
+            };
+            match (left, right) {
+                (Scalar::Int(_), Scalar::Int(_)) | (Scalar::String(_), Scalar::String(_)) => {}
+                _ => return Tristate::Unknown,
+            }
+            match op {
+                BinaryOp::LessThan => ordering.is_lt(),
+                BinaryOp::LessThanOrEqual => ordering.is_le(),
+                BinaryOp::GreaterThan => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }
+            .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::security::{BinaryOp, Expr, SecurityDescriptor, Sid};
+
+    #[test]
+    fn round_trip() {
+        for sddl in [
+            "(@User.clearance >= 5)",
+            "((@User.Title == \"PM\") && (Member_of {SID(BA), SID(S-1-5-21-1-2-3-1001)}))",
+            "((@User.dept Any_of {\"a\", \"b\"}) || (!(Exists @Device.managed)))",
+            "(@Resource.Secrecy Contains {+3, -0x10, 017})",
+            "(@User.x Not_Any_of #0102ff)",
+            "(Title%0020x <= @User.y)",
+            "(Not_Device_Member_of_Any {SID(WD)})",
+            "(%0031st != -0)",
+            "@User.smartcard",
+        ] {
+            let expr = Expr::from_sddl(sddl).unwrap();
+            assert_eq!(expr.to_sddl(), sddl);
+            let bytes = expr.to_bytes();
+            assert_eq!(bytes.len() % 4, 0);
+            assert_eq!(&bytes[..4], b"artx");
+            assert_eq!(Expr::from_bytes(&bytes).unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn precedence_and_errors() {
+        let expr = Expr::from_sddl("@user.a == 1 || @user.b == 2 && !member_of SID(BA)").unwrap();
+        assert_eq!(
+            expr.to_sddl(),
+            "((@User.a == 1) || ((@User.b == 2) && (!(Member_of SID(BA)))))"
+        );
+        for sddl in [
+            "(@User.a ==)",
+            "(@Foo.a == 1)",
+            "(@User.a == \"x)",
+            "(@User.a == 1",
+            "(@User.a == 99999999999999999999)",
+        ] {
+            assert!(Expr::from_sddl(sddl).is_err(), "{sddl}");
+        }
+    }
+
+    #[test]
+    fn binary_form() {
+        let expr = Expr::from_sddl("(Member_of {SID(BA)})").unwrap();
+        let mut expected = b"artx".to_vec();
+        expected.extend([0x50, 0x15, 0, 0, 0, 0x51, 0x10, 0, 0, 0]);
+        expected.extend(Sid::builtin(544).to_bytes());
+        expected.push(0x89);
+        expected.resize(expected.len().next_multiple_of(4), 0);
+        assert_eq!(expr.to_bytes(), expected);
+    }
+
+    #[test]
+    fn in_descriptors() {
+        let sddl = "D:(XA;;FA;;;WD;(@User.clearance >= 5))(XD;;FW;;;AU;(!(Member_of {SID(BA)})))";
+        let sd = SecurityDescriptor::from_sddl(sddl).unwrap();
+        let bytes = sd.to_bytes().unwrap();
+        assert_eq!(
+            SecurityDescriptor::from_bytes(&bytes)
+                .unwrap()
+                .to_sddl()
+                .unwrap(),
+            sddl
+        );
+        let ace = &sd.dacl.as_ref().unwrap().aces[0];
+        assert!(matches!(
+            ace.condition().unwrap(),
+            Some(Expr::Binary(BinaryOp::GreaterThanOrEqual, ..))
+        ));
+    }
+}
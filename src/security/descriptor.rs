@@ -0,0 +1,413 @@
+use windows::Win32::{Foundation::PSID, Security::PSECURITY_DESCRIPTOR};
+
+use super::{Acl, Error, Sid};
+use crate::buffer::{Reader, Writer};
+
+pub const SECURITY_DESCRIPTOR_REVISION: u8 = 1;
+
+/// `SECURITY_DESCRIPTOR_CONTROL`.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct SdControl(pub u16);
+
+impl SdControl {
+    pub const OWNER_DEFAULTED: Self = Self(0x0001);
+    pub const GROUP_DEFAULTED: Self = Self(0x0002);
+    pub const DACL_PRESENT: Self = Self(0x0004);
+    pub const DACL_DEFAULTED: Self = Self(0x0008);
+    pub const SACL_PRESENT: Self = Self(0x0010);
+    pub const SACL_DEFAULTED: Self = Self(0x0020);
+    pub const DACL_UNTRUSTED: Self = Self(0x0040);
+    pub const SERVER_SECURITY: Self = Self(0x0080);
+    pub const DACL_AUTO_INHERIT_REQ: Self = Self(0x0100);
+    pub const SACL_AUTO_INHERIT_REQ: Self = Self(0x0200);
+    pub const DACL_AUTO_INHERITED: Self = Self(0x0400);
+    pub const SACL_AUTO_INHERITED: Self = Self(0x0800);
+    pub const DACL_PROTECTED: Self = Self(0x1000);
+    pub const SACL_PROTECTED: Self = Self(0x2000);
+    pub const RM_CONTROL_VALID: Self = Self(0x4000);
+    pub const SELF_RELATIVE: Self = Self(0x8000);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn set(&mut self, other: Self, value: bool) {
+        if value {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl std::ops::BitOr for SdControl {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for SdControl {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::fmt::Debug for SdControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SdControl({:#06x})", self.0)
+    }
+}
+
+/// An owned security descriptor.
+///
+/// A DACL or SACL that is present but NULL is represented by the matching
+/// `*_PRESENT` control bit with the list set to `None`. The present bits are
+/// always set on serialization when a list is `Some`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecurityDescriptor {
+    pub control: SdControl,
+    /// The resource manager control bits, valid when
+    /// [`SdControl::RM_CONTROL_VALID`] is set.
+    pub rm_control: u8,
+    pub owner: Option<Sid>,
+    pub group: Option<Sid>,
+    pub dacl: Option<Acl>,
+    pub sacl: Option<Acl>,
+}
+
+impl SecurityDescriptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_owner(mut self, owner: Sid) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn with_group(mut self, group: Sid) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    pub fn with_dacl(mut self, dacl: Acl) -> Self {
+        self.dacl = Some(dacl);
+        self.control |= SdControl::DACL_PRESENT;
+        self
+    }
+
+    pub fn with_sacl(mut self, sacl: Acl) -> Self {
+        self.sacl = Some(sacl);
+        self.control |= SdControl::SACL_PRESENT;
+        self
+    }
+
+    /// Marks the DACL as present but NULL, which grants everyone full access.
+    pub fn with_null_dacl(mut self) -> Self {
+        self.dacl = None;
+        self.control |= SdControl::DACL_PRESENT;
+        self
+    }
+
+    pub fn with_control(mut self, control: SdControl) -> Self {
+        self.control |= control;
+        self
+    }
+
+    pub fn dacl_present(&self) -> bool {
+        self.dacl.is_some() || self.control.contains(SdControl::DACL_PRESENT)
+    }
+
+    pub fn sacl_present(&self) -> bool {
+        self.sacl.is_some() || self.control.contains(SdControl::SACL_PRESENT)
+    }
+
+    /// The control word written to the self-relative form.
+    pub fn effective_control(&self) -> SdControl {
+        let mut control = self.control;
+        control.set(SdControl::DACL_PRESENT, self.dacl_present());
+        control.set(SdControl::SACL_PRESENT, self.sacl_present());
+        control |= SdControl::SELF_RELATIVE;
+        control
+    }
+
+    /// Length of the self-relative form, `RtlLengthSecurityDescriptor`.
+    pub fn len(&self) -> usize {
+        20 + self.owner.as_ref().map_or(0, Sid::len)
+            + self.group.as_ref().map_or(0, Sid::len)
+            + self.dacl.as_ref().map_or(0, Acl::len)
+            + self.sacl.as_ref().map_or(0, Acl::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Parses a self-relative security descriptor.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        let revision = reader.u8()?;
+        if revision != SECURITY_DESCRIPTOR_REVISION {
+            return Err(Error::InvalidRevision(revision));
+        }
+        let rm_control = reader.u8()?;
+        let control = SdControl(reader.u16()?);
+        if !control.contains(SdControl::SELF_RELATIVE) {
+            return Err(reader.invalid("self-relative security descriptor").into());
+        }
+        let owner = reader.u32()? as usize;
+        let group = reader.u32()? as usize;
+        let sacl = reader.u32()? as usize;
+        let dacl = reader.u32()? as usize;
+
+        let sid_at = |offset: usize| match offset {
+            0 => Ok(None),
+            offset => Sid::read(&mut Reader::at(bytes, offset)).map(Some),
+        };
+        let acl_at = |offset: usize, present: SdControl| match offset {
+            0 => Ok(None),
+            _ if !control.contains(present) => Ok(None),
+            offset => Acl::read(&mut Reader::at(bytes, offset)).map(Some),
+        };
+        Ok(Self {
+            control: SdControl(control.0 & !SdControl::SELF_RELATIVE.0),
+            rm_control,
+            owner: sid_at(owner)?,
+            group: sid_at(group)?,
+            dacl: acl_at(dacl, SdControl::DACL_PRESENT)?,
+            sacl: acl_at(sacl, SdControl::SACL_PRESENT)?,
+        })
+    }
+
+    /// Serializes to the self-relative form in the layout used by
+    /// `RtlMakeSelfRelativeSD`: header, SACL, DACL, owner, group.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut writer = Writer::new();
+        writer
+            .u8(SECURITY_DESCRIPTOR_REVISION)
+            .u8(self.rm_control)
+            .u16(self.effective_control().0)
+            .zeros(16);
+        if let Some(sacl) = &self.sacl {
+            writer.patch_u32(12, writer.len() as u32);
+            sacl.write(&mut writer)?;
+        }
+        if let Some(dacl) = &self.dacl {
+            writer.patch_u32(16, writer.len() as u32);
+            dacl.write(&mut writer)?;
+        }
+        if let Some(owner) = &self.owner {
+            writer.patch_u32(4, writer.len() as u32);
+            owner.write(&mut writer);
+        }
+        if let Some(group) = &self.group {
+            writer.patch_u32(8, writer.len() as u32);
+            group.write(&mut writer);
+        }
+        Ok(writer.into_inner())
+    }
+
+    /// Copies a security descriptor, self-relative or absolute, out of
+    /// native memory.
+    ///
+    /// # Safety
+    ///
+    /// `sd` must point to a valid security descriptor.
+    pub unsafe fn from_raw(sd: PSECURITY_DESCRIPTOR) -> Result<Self, Error> {
+        let base = sd.0 as *const u8;
+        let header = std::slice::from_raw_parts(base, 4);
+        let control = SdControl(u16::from_le_bytes([header[2], header[3]]));
+
+        if control.contains(SdControl::SELF_RELATIVE) {
+            let offsets = std::slice::from_raw_parts(base.add(4), 16);
+            let offset = |index: usize| {
+                u32::from_le_bytes(offsets[index * 4..index * 4 + 4].try_into().unwrap()) as usize
+            };
+            let mut end = 20;
+            for index in 0..2 {
+                if offset(index) != 0 {
+                    let count = *base.add(offset(index) + 1) as usize;
+                    end = end.max(offset(index) + 8 + 4 * count);
+                }
+            }
+            for index in 2..4 {
+                if offset(index) != 0 {
+                    let size = base.add(offset(index) + 2).cast::<u16>().read_unaligned();
+                    end = end.max(offset(index) + size as usize);
+                }
+            }
+            return Self::from_bytes(std::slice::from_raw_parts(base, end));
+        }
+
+        // SECURITY_DESCRIPTOR { Revision, Sbz1, Control, Owner, Group, Sacl, Dacl }
+        let pointers = base.add(std::mem::size_of::<usize>()).cast::<*const u8>();
+        let acl_at = |acl: *const u8| -> Result<Option<Acl>, Error> {
+            if acl.is_null() {
+                return Ok(None);
+            }
+            let size = acl.add(2).cast::<u16>().read_unaligned() as usize;
+            Acl::from_bytes(std::slice::from_raw_parts(acl, size)).map(Some)
+        };
+        let sid_at = |sid: *const u8| -> Result<Option<Sid>, Error> {
+            if sid.is_null() {
+                return Ok(None);
+            }
+            Sid::from_raw(PSID(sid as _)).map(Some)
+        };
+        Ok(Self {
+            control,
+            rm_control: header[1],
+            owner: sid_at(pointers.read_unaligned())?,
+            group: sid_at(pointers.add(1).read_unaligned())?,
+            sacl: if control.contains(SdControl::SACL_PRESENT) {
+                acl_at(pointers.add(2).read_unaligned())?
+            } else {
+                None
+            },
+            dacl: if control.contains(SdControl::DACL_PRESENT) {
+                acl_at(pointers.add(3).read_unaligned())?
+            } else {
+                None
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::DecodeError, security::Ace};
+
+    fn descriptor() -> SecurityDescriptor {
+        SecurityDescriptor::new()
+            .with_owner(Sid::builtin(544))
+            .with_group(Sid::local_system())
+            .with_dacl(
+                Acl::new()
+                    .with_ace(Ace::denied(Sid::new(5, &[7]), 0x1f01ff))
+                    .with_ace(Ace::allowed(Sid::everyone(), 0x1200a9)),
+            )
+            .with_sacl(Acl::new().with_ace(Ace::mandatory_label(0x2000, 1)))
+            .with_control(SdControl::DACL_PROTECTED | SdControl::RM_CONTROL_VALID)
+    }
+
+    #[test]
+    fn round_trips_self_relative() {
+        let sd = SecurityDescriptor {
+            rm_control: 0x5a,
+            ..descriptor()
+        };
+        let bytes = sd.to_bytes().unwrap();
+        assert_eq!(bytes.len(), sd.len());
+        assert_eq!(&bytes[..4], &[1, 0x5a, 0x14, 0xd0]);
+        // Owner, group, SACL and DACL, laid out SACL first.
+        let offsets = (0..4)
+            .map(|index| u32::from_le_bytes(bytes[4 + 4 * index..][..4].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [96, 112, 20, 48]);
+        assert_eq!(SecurityDescriptor::from_bytes(&bytes), Ok(sd.clone()));
+        let raw = PSECURITY_DESCRIPTOR(bytes.as_ptr() as _);
+        assert_eq!(unsafe { SecurityDescriptor::from_raw(raw) }, Ok(sd));
+
+        let empty = SecurityDescriptor::new();
+        let bytes = empty.to_bytes().unwrap();
+        assert_eq!(
+            bytes,
+            [
+                1, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+            ]
+        );
+        assert_eq!(SecurityDescriptor::from_bytes(&bytes), Ok(empty));
+    }
+
+    #[test]
+    fn keeps_null_and_absent_lists_apart() {
+        let null = SecurityDescriptor::new().with_null_dacl();
+        assert!(null.dacl_present() && !null.sacl_present());
+        let bytes = null.to_bytes().unwrap();
+        assert_eq!(&bytes[2..4], &[0x04, 0x80]);
+        assert_eq!(&bytes[16..20], &[0; 4]);
+        assert_eq!(SecurityDescriptor::from_bytes(&bytes), Ok(null));
+
+        // A list at an offset is ignored when its present bit is clear.
+        let mut sd = descriptor();
+        sd.control.set(SdControl::SACL_PRESENT, false);
+        let mut bytes = sd.to_bytes().unwrap();
+        bytes[2] &= !(SdControl::SACL_PRESENT.0 as u8);
+        let parsed = SecurityDescriptor::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.sacl, None);
+        assert_eq!(parsed.dacl, descriptor().dacl);
+
+        let mut control = SdControl::DACL_PRESENT;
+        control.set(SdControl::DACL_PRESENT, false);
+        assert_eq!(control, SdControl::default());
+        assert_eq!(
+            SecurityDescriptor::new()
+                .with_dacl(Acl::new())
+                .effective_control(),
+            SdControl::DACL_PRESENT | SdControl::SELF_RELATIVE
+        );
+    }
+
+    #[test]
+    fn reads_absolute_descriptors() {
+        #[repr(C)]
+        struct Absolute {
+            revision: u8,
+            sbz1: u8,
+            control: u16,
+            owner: *const u8,
+            group: *const u8,
+            sacl: *const u8,
+            dacl: *const u8,
+        }
+
+        let sd = descriptor();
+        let owner = sd.owner.as_ref().unwrap().to_bytes();
+        let dacl = sd.dacl.as_ref().unwrap().to_bytes().unwrap();
+        let sacl = sd.sacl.as_ref().unwrap().to_bytes().unwrap();
+        let absolute = Absolute {
+            revision: SECURITY_DESCRIPTOR_REVISION,
+            sbz1: 0,
+            control: (sd.effective_control().0 & !SdControl::SELF_RELATIVE.0),
+            owner: owner.as_ptr(),
+            group: std::ptr::null(),
+            sacl: sacl.as_ptr(),
+            dacl: dacl.as_ptr(),
+        };
+        let raw = PSECURITY_DESCRIPTOR(&absolute as *const Absolute as _);
+        assert_eq!(
+            unsafe { SecurityDescriptor::from_raw(raw) },
+            Ok(SecurityDescriptor { group: None, ..sd })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_descriptors() {
+        let mut bytes = descriptor().to_bytes().unwrap();
+        bytes[0] = 2;
+        assert_eq!(
+            SecurityDescriptor::from_bytes(&bytes),
+            Err(Error::InvalidRevision(2))
+        );
+        bytes[0] = 1;
+        bytes[3] &= 0x7f;
+        assert_eq!(
+            SecurityDescriptor::from_bytes(&bytes),
+            Err(Error::Decode(DecodeError::Invalid {
+                offset: 4,
+                what: "self-relative security descriptor"
+            }))
+        );
+        bytes[3] |= 0x80;
+        // The owner offset points past the end.
+        bytes[4] = 0xf0;
+        assert!(matches!(
+            SecurityDescriptor::from_bytes(&bytes),
+            Err(Error::Decode(DecodeError::Truncated { offset: 0xf0, .. }))
+        ));
+        assert!(SecurityDescriptor::from_bytes(&bytes[..19]).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! Owned security identifiers, access control lists and security descriptors.
+//!
+//! These types are pure Rust: they parse from and serialize to the same
+//! self-relative binary layout the `Rtl*` security functions and
+//! `NtAccessCheck*` consume, and convert to and from SDDL strings without
+//! calling into the system.
+//!
+//! ```
+//! use windows_native::security::SecurityDescriptor;
+//!
+//! let sd = SecurityDescriptor::from_sddl("O:BAG:SYD:PAI(A;OICI;FA;;;SY)(A;;FR;;;BU)").unwrap();
+//! let bytes = sd.to_bytes().unwrap();
+//! let parsed = SecurityDescriptor::from_bytes(&bytes).unwrap();
+//! assert_eq!(parsed.to_sddl().unwrap(), "O:BAG:SYD:PAI(A;OICI;FA;;;SY)(A;;FR;;;BU)");
+//! ```
+
+mod access_check;
+mod ace;
+mod acl;
+mod claim;
+mod conditional;
+mod descriptor;
+mod sddl;
+mod sid;
+
+pub use access_check::*;
+pub use ace::*;
+pub use acl::*;
+pub use claim::*;
+pub use conditional::*;
+pub use descriptor::*;
+pub use sid::*;
+
+use crate::buffer::DecodeError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Decode(DecodeError),
+    InvalidRevision(u8),
+    UnsupportedAceType(u8),
+    UnsupportedClaimType(u16),
+    InvalidSid(String),
+    /// The SDDL string is malformed at byte `offset`.
+    Sddl {
+        offset: usize,
+        message: String,
+    },
+    /// An ACE or ACL exceeds the 64 KiB limit of its size field.
+    TooLarge,
+    Unsupported(&'static str),
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(error) => error.fmt(f),
+            Self::InvalidRevision(revision) => write!(f, "unsupported revision {revision}"),
+            Self::UnsupportedAceType(ace_type) => write!(f, "unsupported ACE type {ace_type}"),
+            Self::UnsupportedClaimType(value_type) => {
+                write!(f, "unsupported claim value type {value_type}")
+            }
+            Self::InvalidSid(sid) => write!(f, "invalid SID `{sid}`"),
+            Self::Sddl { offset, message } => write!(f, "SDDL error at {offset}: {message}"),
+            Self::TooLarge => write!(f, "ACL or ACE exceeds 65535 bytes"),
+            Self::Unsupported(what) => write!(f, "unsupported {what}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,1193 @@
+//! Security Descriptor Definition Language conversion, the pure Rust
+//! equivalent of `ConvertStringSecurityDescriptorToSecurityDescriptorW` and
+//! `ConvertSecurityDescriptorToStringSecurityDescriptorW`.
+
+use std::fmt::Write;
+
+use super::{
+    Ace, AceFlags, AceType, Acl, AttributeSource, BinaryOp, ClaimAttribute, ClaimValues, Error,
+    Expr, IntBase, IntSign, Literal, SdControl, SecurityDescriptor, Sid, UnaryOp,
+};
+use crate::buffer::{format_guid, parse_guid};
+
+const ACE_TYPES: &[(&str, AceType)] = &[
+    ("A", AceType::AccessAllowed),
+    ("D", AceType::AccessDenied),
+    ("AU", AceType::SystemAudit),
+    ("AL", AceType::SystemAlarm),
+    ("OA", AceType::AccessAllowedObject),
+    ("OD", AceType::AccessDeniedObject),
+    ("OU", AceType::SystemAuditObject),
+    ("OL", AceType::SystemAlarmObject),
+    ("XA", AceType::AccessAllowedCallback),
+    ("XD", AceType::AccessDeniedCallback),
+    ("ZA", AceType::AccessAllowedCallbackObject),
+    ("XU", AceType::SystemAuditCallback),
+    ("ML", AceType::SystemMandatoryLabel),
+    ("RA", AceType::SystemResourceAttribute),
+    ("SP", AceType::SystemScopedPolicyId),
+    ("TL", AceType::SystemProcessTrustLabel),
+    ("FL", AceType::SystemAccessFilter),
+];
+
+const ACE_FLAGS: &[(&str, AceFlags)] = &[
+    ("OI", AceFlags::OBJECT_INHERIT),
+    ("CI", AceFlags::CONTAINER_INHERIT),
+    ("NP", AceFlags::NO_PROPAGATE_INHERIT),
+    ("IO", AceFlags::INHERIT_ONLY),
+    ("ID", AceFlags::INHERITED),
+    ("CR", AceFlags::CRITICAL),
+    ("SA", AceFlags::SUCCESSFUL_ACCESS),
+    ("FA", AceFlags::FAILED_ACCESS),
+];
+
+// Composite rights are emitted only on an exact match.
+const COMPOSITE_RIGHTS: &[(&str, u32)] = &[
+    ("FA", 0x001F_01FF),
+    ("FR", 0x0012_0089),
+    ("FW", 0x0012_0116),
+    ("FX", 0x0012_00A0),
+    ("KA", 0x000F_003F),
+    ("KR", 0x0002_0019),
+    ("KW", 0x0002_0006),
+    ("KX", 0x0002_0019),
+];
+
+// Single-bit rights, in the ascending bit order they are emitted in.
+const RIGHTS: &[(&str, u32)] = &[
+    ("CC", 0x0000_0001),
+    ("DC", 0x0000_0002),
+    ("LC", 0x0000_0004),
+    ("SW", 0x0000_0008),
+    ("RP", 0x0000_0010),
+    ("WP", 0x0000_0020),
+    ("DT", 0x0000_0040),
+    ("LO", 0x0000_0080),
+    ("CR", 0x0000_0100),
+    ("SD", 0x0001_0000),
+    ("RC", 0x0002_0000),
+    ("WD", 0x0004_0000),
+    ("WO", 0x0008_0000),
+    ("GA", 0x1000_0000),
+    ("GX", 0x2000_0000),
+    ("GW", 0x4000_0000),
+    ("GR", 0x8000_0000),
+];
+
+const LABEL_RIGHTS: &[(&str, u32)] = &[("NW", 0x1), ("NR", 0x2), ("NX", 0x4)];
+
+// `sddl.h` has no code for FQBN claims, so `TF`, with each value written as
+// `{version,"name"}`, is this crate's own and keeps them from reading back as
+// strings.
+const CLAIM_TYPES: &[(&str, u16)] = &[
+    ("TI", 1),
+    ("TU", 2),
+    ("TS", 3),
+    ("TF", 4),
+    ("TD", 5),
+    ("TB", 6),
+    ("TX", 16),
+];
+
+const CONDITION_UNARY_OPS: &[(&str, UnaryOp)] = &[
+    ("Member_of", UnaryOp::MemberOf),
+    ("Device_Member_of", UnaryOp::DeviceMemberOf),
+    ("Member_of_Any", UnaryOp::MemberOfAny),
+    ("Device_Member_of_Any", UnaryOp::DeviceMemberOfAny),
+    ("Not_Member_of", UnaryOp::NotMemberOf),
+    ("Not_Device_Member_of", UnaryOp::NotDeviceMemberOf),
+    ("Not_Member_of_Any", UnaryOp::NotMemberOfAny),
+    ("Not_Device_Member_of_Any", UnaryOp::NotDeviceMemberOfAny),
+    ("Exists", UnaryOp::Exists),
+    ("Not_Exists", UnaryOp::NotExists),
+    ("!", UnaryOp::Not),
+];
+
+// Longer operators come first so that `<=` is not read as `<`.
+const CONDITION_BINARY_OPS: &[(&str, BinaryOp)] = &[
+    ("==", BinaryOp::Equals),
+    ("!=", BinaryOp::NotEquals),
+    ("<=", BinaryOp::LessThanOrEqual),
+    ("<", BinaryOp::LessThan),
+    (">=", BinaryOp::GreaterThanOrEqual),
+    (">", BinaryOp::GreaterThan),
+    ("Contains", BinaryOp::Contains),
+    ("Any_of", BinaryOp::AnyOf),
+    ("Not_Contains", BinaryOp::NotContains),
+    ("Not_Any_of", BinaryOp::NotAnyOf),
+    ("&&", BinaryOp::And),
+    ("||", BinaryOp::Or),
+];
+
+const ATTRIBUTE_PREFIXES: &[(&str, AttributeSource)] = &[
+    ("@User.", AttributeSource::User),
+    ("@Device.", AttributeSource::Device),
+    ("@Resource.", AttributeSource::Resource),
+];
+
+impl SecurityDescriptor {
+    /// Parses an SDDL string. Domain-relative SID aliases such as `DA` are
+    /// rejected; use [`SecurityDescriptor::from_sddl_in_domain`] for those.
+    pub fn from_sddl(sddl: &str) -> Result<Self, Error> {
+        Parser::new(sddl, None).descriptor()
+    }
+
+    /// Parses an SDDL string, resolving domain-relative SID aliases against
+    /// `domain`.
+    pub fn from_sddl_in_domain(sddl: &str, domain: &Sid) -> Result<Self, Error> {
+        Parser::new(sddl, Some(domain)).descriptor()
+    }
+
+    /// Formats the descriptor as SDDL, using SID aliases where one exists.
+    pub fn to_sddl(&self) -> Result<String, Error> {
+        format_descriptor(self, None)
+    }
+
+    /// Formats the descriptor as SDDL, also using the domain-relative aliases
+    /// of accounts in `domain`.
+    pub fn to_sddl_in_domain(&self, domain: &Sid) -> Result<String, Error> {
+        format_descriptor(self, Some(domain))
+    }
+}
+
+impl Ace {
+    /// Parses a single parenthesized ACE string such as `(A;;GA;;;SY)`.
+    pub fn from_sddl(sddl: &str) -> Result<Self, Error> {
+        let mut parser = Parser::new(sddl, None);
+        let ace = parser.ace()?;
+        parser.expect_end()?;
+        Ok(ace)
+    }
+
+    pub fn to_sddl(&self) -> Result<String, Error> {
+        let mut out = String::new();
+        format_ace(&mut out, self, None)?;
+        Ok(out)
+    }
+}
+
+impl Expr {
+    /// Parses the SDDL form of a conditional expression, e.g.
+    /// `(@User.clearance >= 5 && Member_of {SID(BA)})`.
+    pub fn from_sddl(sddl: &str) -> Result<Self, Error> {
+        let mut parser = Parser::new(sddl, None);
+        let expr = parser.condition()?;
+        parser.expect_end()?;
+        Ok(expr)
+    }
+
+    /// Formats the expression as SDDL, parenthesizing every operation the way
+    /// `ConvertSecurityDescriptorToStringSecurityDescriptorW` does.
+    pub fn to_sddl(&self) -> String {
+        let mut out = String::new();
+        format_condition(&mut out, self, None);
+        out
+    }
+}
+
+fn format_descriptor(sd: &SecurityDescriptor, domain: Option<&Sid>) -> Result<String, Error> {
+    let mut out = String::new();
+    if let Some(owner) = &sd.owner {
+        write!(out, "O:{}", format_sid(owner, domain)).unwrap();
+    }
+    if let Some(group) = &sd.group {
+        write!(out, "G:{}", format_sid(group, domain)).unwrap();
+    }
+    if sd.dacl_present() {
+        out.push_str("D:");
+        format_acl_flags(
+            &mut out,
+            sd.control,
+            [
+                SdControl::DACL_PROTECTED,
+                SdControl::DACL_AUTO_INHERIT_REQ,
+                SdControl::DACL_AUTO_INHERITED,
+            ],
+        );
+        format_acl(&mut out, sd.dacl.as_ref(), domain)?;
+    }
+    if sd.sacl_present() {
+        out.push_str("S:");
+        format_acl_flags(
+            &mut out,
+            sd.control,
+            [
+                SdControl::SACL_PROTECTED,
+                SdControl::SACL_AUTO_INHERIT_REQ,
+                SdControl::SACL_AUTO_INHERITED,
+            ],
+        );
+        format_acl(&mut out, sd.sacl.as_ref(), domain)?;
+    }
+    Ok(out)
+}
+
+fn format_acl_flags(out: &mut String, control: SdControl, flags: [SdControl; 3]) {
+    for (flag, token) in flags.into_iter().zip(["P", "AR", "AI"]) {
+        if control.contains(flag) {
+            out.push_str(token);
+        }
+    }
+}
+
+fn format_acl(out: &mut String, acl: Option<&Acl>, domain: Option<&Sid>) -> Result<(), Error> {
+    match acl {
+        None => out.push_str("NO_ACCESS_CONTROL"),
+        Some(acl) => {
+            for ace in &acl.aces {
+                format_ace(out, ace, domain)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_sid(sid: &Sid, domain: Option<&Sid>) -> String {
+    match sid.alias(domain) {
+        Some(alias) => alias.to_owned(),
+        None => sid.to_string(),
+    }
+}
+
+fn format_ace(out: &mut String, ace: &Ace, domain: Option<&Sid>) -> Result<(), Error> {
+    let (token, _) = ACE_TYPES
+        .iter()
+        .find(|(_, ace_type)| *ace_type == ace.ace_type)
+        .ok_or(Error::Unsupported("ACE type without an SDDL form"))?;
+    write!(out, "({token};").unwrap();
+    for (token, flag) in ACE_FLAGS {
+        if ace.flags.contains(*flag) {
+            out.push_str(token);
+        }
+    }
+    out.push(';');
+    format_rights(out, ace.mask, ace.ace_type == AceType::SystemMandatoryLabel);
+    out.push(';');
+    if let Some(guid) = &ace.object_type {
+        out.push_str(&format_guid(guid));
+    }
+    out.push(';');
+    if let Some(guid) = &ace.inherited_object_type {
+        out.push_str(&format_guid(guid));
+    }
+    out.push(';');
+    out.push_str(&format_sid(&ace.sid, domain));
+
+    if ace.ace_type == AceType::SystemResourceAttribute {
+        out.push_str(";(");
+        format_claim(
+            out,
+            &ClaimAttribute::from_relative(&ace.application_data)?,
+            domain,
+        );
+        out.push(')');
+    } else if let Some(condition) = ace.condition()? {
+        out.push(';');
+        if matches!(condition, Expr::Unary(..) | Expr::Binary(..)) {
+            format_condition(out, &condition, domain);
+        } else {
+            out.push('(');
+            format_condition(out, &condition, domain);
+            out.push(')');
+        }
+    }
+    out.push(')');
+    Ok(())
+}
+
+fn format_condition(out: &mut String, expr: &Expr, domain: Option<&Sid>) {
+    match expr {
+        Expr::Literal(literal) => format_literal(out, literal, domain),
+        Expr::Attribute(source, name) => {
+            if let Some((prefix, _)) = ATTRIBUTE_PREFIXES
+                .iter()
+                .find(|(_, prefix_source)| prefix_source == source)
+            {
+                out.push_str(prefix);
+            }
+            // Escape everything outside the plain name characters, and a
+            // leading digit that would otherwise read as a literal.
+            for (index, c) in name.chars().enumerate() {
+                let plain = c.is_ascii_alphanumeric() || matches!(c, ':' | '.' | '/' | '_');
+                if plain && !(index == 0 && c.is_ascii_digit()) {
+                    out.push(c);
+                } else {
+                    for unit in c.encode_utf16(&mut [0; 2]) {
+                        write!(out, "%{unit:04x}").unwrap();
+                    }
+                }
+            }
+        }
+        Expr::Unary(op, operand) => {
+            let (token, _) = CONDITION_UNARY_OPS
+                .iter()
+                .find(|(_, value)| value == op)
+                .unwrap();
+            out.push('(');
+            out.push_str(token);
+            if *op != UnaryOp::Not {
+                out.push(' ');
+            }
+            format_condition(out, operand, domain);
+            out.push(')');
+        }
+        Expr::Binary(op, left, right) => {
+            let (token, _) = CONDITION_BINARY_OPS
+                .iter()
+                .find(|(_, value)| value == op)
+                .unwrap();
+            out.push('(');
+            format_condition(out, left, domain);
+            write!(out, " {token} ").unwrap();
+            format_condition(out, right, domain);
+            out.push(')');
+        }
+    }
+}
+
+fn format_literal(out: &mut String, literal: &Literal, domain: Option<&Sid>) {
+    match literal {
+        Literal::Int { value, sign, base } => {
+            let magnitude = value.unsigned_abs();
+            if *value < 0 || *sign == IntSign::Negative {
+                out.push('-');
+            } else if *sign == IntSign::Positive {
+                out.push('+');
+            }
+            match base {
+                IntBase::Octal => write!(out, "0{magnitude:o}"),
+                IntBase::Decimal => write!(out, "{magnitude}"),
+                IntBase::Hexadecimal => write!(out, "0x{magnitude:x}"),
+            }
+            .unwrap();
+        }
+        Literal::String(value) => write!(out, "\"{value}\"").unwrap(),
+        Literal::OctetString(value) => {
+            out.push('#');
+            for byte in value {
+                write!(out, "{byte:02x}").unwrap();
+            }
+        }
+        Literal::Sid(sid) => write!(out, "SID({})", format_sid(sid, domain)).unwrap(),
+        Literal::Composite(items) => {
+            out.push('{');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                format_literal(out, item, domain);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn format_rights(out: &mut String, mask: u32, label: bool) {
+    if mask == 0 {
+        return;
+    }
+    if !label {
+        if let Some((token, _)) = COMPOSITE_RIGHTS.iter().find(|(_, value)| *value == mask) {
+            out.push_str(token);
+            return;
+        }
+    }
+    let table = if label { LABEL_RIGHTS } else { RIGHTS };
+    let covered = table.iter().fold(0, |acc, (_, value)| acc | value);
+    if mask & !covered != 0 {
+        write!(out, "0x{mask:x}").unwrap();
+        return;
+    }
+    for (token, value) in table {
+        if mask & value != 0 {
+            out.push_str(token);
+        }
+    }
+}
+
+fn format_claim(out: &mut String, claim: &ClaimAttribute, domain: Option<&Sid>) {
+    let value_type = claim.values.value_type();
+    let token = CLAIM_TYPES
+        .iter()
+        .find(|(_, code)| *code == value_type)
+        .map_or("TS", |(token, _)| token);
+    write!(out, "\"{}\",{},0x{:x}", claim.name, token, claim.flags).unwrap();
+    match &claim.values {
+        ClaimValues::Int64(values) => values.iter().for_each(|v| write!(out, ",{v}").unwrap()),
+        ClaimValues::Uint64(values) => values.iter().for_each(|v| write!(out, ",{v}").unwrap()),
+        ClaimValues::Boolean(values) => values
+            .iter()
+            .for_each(|v| write!(out, ",{}", *v as u8).unwrap()),
+        ClaimValues::String(values) => values.iter().for_each(|v| write!(out, ",\"{v}\"").unwrap()),
+        ClaimValues::Fqbn(values) => values
+            .iter()
+            .for_each(|(version, v)| write!(out, ",{{{version},\"{v}\"}}").unwrap()),
+        ClaimValues::Sid(values) => values
+            .iter()
+            .for_each(|v| write!(out, ",SID({})", format_sid(v, domain)).unwrap()),
+        ClaimValues::OctetString(values) => {
+            for value in values {
+                out.push(',');
+                for byte in value {
+                    write!(out, "{byte:02x}").unwrap();
+                }
+            }
+        }
+    }
+}
+
+pub(crate) struct Parser<'a> {
+    pub(crate) input: &'a str,
+    pub(crate) pos: usize,
+    pub(crate) domain: Option<&'a Sid>,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(input: &'a str, domain: Option<&'a Sid>) -> Self {
+        Self {
+            input,
+            pos: 0,
+            domain,
+        }
+    }
+
+    pub(crate) fn error(&self, message: impl Into<String>) -> Error {
+        Error::Sddl {
+            offset: self.pos,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    pub(crate) fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    pub(crate) fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    pub(crate) fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn expect(&mut self, token: &str) -> Result<(), Error> {
+        self.skip_whitespace();
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{token}`")))
+        }
+    }
+
+    pub(crate) fn expect_end(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("unexpected trailing characters")),
+        }
+    }
+
+    /// Consumes characters up to, but not including, the first one matching
+    /// `end`.
+    pub(crate) fn take_until(&mut self, end: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(end).unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len].trim()
+    }
+
+    fn descriptor(&mut self) -> Result<SecurityDescriptor, Error> {
+        let mut sd = SecurityDescriptor::new();
+        loop {
+            self.skip_whitespace();
+            let Some(component) = self.bump() else {
+                return Ok(sd);
+            };
+            self.expect(":")?;
+            self.skip_whitespace();
+            match component.to_ascii_uppercase() {
+                'O' if sd.owner.is_none() => sd.owner = Some(self.sid()?),
+                'G' if sd.group.is_none() => sd.group = Some(self.sid()?),
+                'D' if !sd.dacl_present() => {
+                    sd.dacl = self.acl(
+                        &mut sd.control,
+                        [
+                            SdControl::DACL_PRESENT,
+                            SdControl::DACL_PROTECTED,
+                            SdControl::DACL_AUTO_INHERIT_REQ,
+                            SdControl::DACL_AUTO_INHERITED,
+                        ],
+                    )?
+                }
+                'S' if !sd.sacl_present() => {
+                    sd.sacl = self.acl(
+                        &mut sd.control,
+                        [
+                            SdControl::SACL_PRESENT,
+                            SdControl::SACL_PROTECTED,
+                            SdControl::SACL_AUTO_INHERIT_REQ,
+                            SdControl::SACL_AUTO_INHERITED,
+                        ],
+                    )?
+                }
+                _ => return Err(self.error("unknown or repeated component")),
+            }
+        }
+    }
+
+    pub(crate) fn sid(&mut self) -> Result<Sid, Error> {
+        let start = self.pos;
+        if self.eat("S-") || self.eat("s-") {
+            while let Some(c) = self.peek() {
+                if self.rest().starts_with("0x") || self.rest().starts_with("0X") {
+                    self.pos += 2;
+                    for _ in 0..12 {
+                        if !self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                            break;
+                        }
+                        self.bump();
+                    }
+                } else if c.is_ascii_digit() || c == '-' {
+                    self.bump();
+                } else {
+                    break;
+                }
+            }
+            return self.input[start..self.pos]
+                .parse()
+                .map_err(|_| Error::Sddl {
+                    offset: start,
+                    message: "invalid SID".to_owned(),
+                });
+        }
+        let alias = self
+            .rest()
+            .get(..2)
+            .ok_or_else(|| self.error("expected a SID"))?;
+        let sid = Sid::from_alias(alias, self.domain)
+            .ok_or_else(|| self.error(format!("unknown SID alias `{alias}`")))?;
+        self.pos += 2;
+        Ok(sid)
+    }
+
+    fn acl(
+        &mut self,
+        control: &mut SdControl,
+        flags: [SdControl; 4],
+    ) -> Result<Option<Acl>, Error> {
+        *control |= flags[0];
+        let mut null = false;
+        loop {
+            self.skip_whitespace();
+            if self.eat("NO_ACCESS_CONTROL") {
+                null = true;
+            } else if self.eat("P") {
+                *control |= flags[1];
+            } else if self.eat("AR") {
+                *control |= flags[2];
+            } else if self.eat("AI") {
+                *control |= flags[3];
+            } else {
+                break;
+            }
+        }
+        let mut acl = Acl::new();
+        while self.peek() == Some('(') {
+            acl.push(self.ace()?);
+            self.skip_whitespace();
+        }
+        if null {
+            if !acl.is_empty() {
+                return Err(self.error("NO_ACCESS_CONTROL with ACEs"));
+            }
+            return Ok(None);
+        }
+        acl.revision = acl.effective_revision();
+        Ok(Some(acl))
+    }
+
+    fn field(&mut self) -> &'a str {
+        self.take_until(|c| c == ';' || c == ')')
+    }
+
+    fn separator(&mut self) -> Result<(), Error> {
+        self.expect(";")
+    }
+
+    fn ace(&mut self) -> Result<Ace, Error> {
+        self.expect("(")?;
+        let start = self.pos;
+        let token = self.field();
+        let (_, ace_type) = ACE_TYPES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(token))
+            .ok_or_else(|| Error::Sddl {
+                offset: start,
+                message: format!("unknown ACE type `{token}`"),
+            })?;
+        self.separator()?;
+        let flags = self.ace_flags()?;
+        self.separator()?;
+        let mask = self.rights()?;
+        self.separator()?;
+        let object_type = self.guid()?;
+        self.separator()?;
+        let inherited_object_type = self.guid()?;
+        self.separator()?;
+        self.skip_whitespace();
+        let sid = self.sid()?;
+        if !ace_type.is_object() && (object_type.is_some() || inherited_object_type.is_some()) {
+            return Err(self.error("object GUID on a non-object ACE"));
+        }
+
+        let mut ace = Ace {
+            object_type,
+            inherited_object_type,
+            ..Ace::new(*ace_type, flags, mask, sid)
+        };
+        self.skip_whitespace();
+        if self.eat(";") {
+            self.skip_whitespace();
+            if *ace_type == AceType::SystemResourceAttribute {
+                self.expect("(")?;
+                ace.application_data = self.claim()?.to_relative();
+                self.expect(")")?;
+            } else if ace_type.is_callback() {
+                ace.application_data = self.condition()?.to_bytes();
+            } else {
+                return Err(self.error("unexpected application data"));
+            }
+        }
+        self.expect(")")?;
+        Ok(ace)
+    }
+
+    fn ace_flags(&mut self) -> Result<AceFlags, Error> {
+        let start = self.pos;
+        let field = self.field();
+        let mut flags = AceFlags::default();
+        for token in pairs(field).ok_or_else(|| self.error("invalid ACE flags"))? {
+            let (_, flag) = ACE_FLAGS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(token))
+                .ok_or_else(|| Error::Sddl {
+                    offset: start,
+                    message: format!("unknown ACE flag `{token}`"),
+                })?;
+            flags |= *flag;
+        }
+        Ok(flags)
+    }
+
+    fn rights(&mut self) -> Result<u32, Error> {
+        let start = self.pos;
+        let field = self.field();
+        let invalid = |message: String| Error::Sddl {
+            offset: start,
+            message,
+        };
+        if let Some(hex) = field
+            .strip_prefix("0x")
+            .or_else(|| field.strip_prefix("0X"))
+        {
+            return u32::from_str_radix(hex, 16).map_err(|_| invalid("invalid access mask".into()));
+        }
+        if !field.is_empty() && field.bytes().all(|b| b.is_ascii_digit()) {
+            return field
+                .parse()
+                .map_err(|_| invalid("invalid access mask".into()));
+        }
+        let mut mask = 0;
+        for token in pairs(field).ok_or_else(|| invalid("invalid access mask".into()))? {
+            let (_, value) = COMPOSITE_RIGHTS
+                .iter()
+                .chain(RIGHTS)
+                .chain(LABEL_RIGHTS)
+                .find(|(name, _)| name.eq_ignore_ascii_case(token))
+                .ok_or_else(|| invalid(format!("unknown access right `{token}`")))?;
+            mask |= value;
+        }
+        Ok(mask)
+    }
+
+    fn guid(&mut self) -> Result<Option<windows::core::GUID>, Error> {
+        let start = self.pos;
+        match self.field() {
+            "" => Ok(None),
+            field => parse_guid(field).map(Some).ok_or(Error::Sddl {
+                offset: start,
+                message: format!("invalid GUID `{field}`"),
+            }),
+        }
+    }
+
+    pub(crate) fn quoted(&mut self) -> Result<String, Error> {
+        self.expect("\"")?;
+        let value = self.take_until(|c| c == '"');
+        let value = value.to_owned();
+        self.expect("\"")?;
+        Ok(value)
+    }
+
+    pub(crate) fn integer(&mut self) -> Result<i128, Error> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let negative = self.eat("-");
+        if !negative {
+            self.eat("+");
+        }
+        let (radix, digits) = if self.eat("0x") || self.eat("0X") {
+            (16, self.take_until(|c| !c.is_ascii_hexdigit()))
+        } else if self.rest().starts_with('0') && self.rest().len() > 1 {
+            (8, self.take_until(|c| !c.is_ascii_digit()))
+        } else {
+            (10, self.take_until(|c| !c.is_ascii_digit()))
+        };
+        let value = i128::from_str_radix(digits, radix).map_err(|_| Error::Sddl {
+            offset: start,
+            message: "invalid integer".to_owned(),
+        })?;
+        Ok(if negative { -value } else { value })
+    }
+
+    fn claim(&mut self) -> Result<ClaimAttribute, Error> {
+        let name = self.quoted()?;
+        self.expect(",")?;
+        self.skip_whitespace();
+        let start = self.pos;
+        let token = self.take_until(|c| c == ',');
+        let (_, value_type) = CLAIM_TYPES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(token))
+            .ok_or_else(|| Error::Sddl {
+                offset: start,
+                message: format!("unknown claim type `{token}`"),
+            })?;
+        self.expect(",")?;
+        let flags = u32::try_from(self.integer()?).map_err(|_| self.error("invalid flags"))?;
+
+        let mut values = match value_type {
+            1 => ClaimValues::Int64(Vec::new()),
+            2 => ClaimValues::Uint64(Vec::new()),
+            3 => ClaimValues::String(Vec::new()),
+            4 => ClaimValues::Fqbn(Vec::new()),
+            5 => ClaimValues::Sid(Vec::new()),
+            6 => ClaimValues::Boolean(Vec::new()),
+            _ => ClaimValues::OctetString(Vec::new()),
+        };
+        while {
+            self.skip_whitespace();
+            self.eat(",")
+        } {
+            self.skip_whitespace();
+            match &mut values {
+                ClaimValues::Int64(values) => values.push(
+                    i64::try_from(self.integer()?).map_err(|_| self.error("integer overflow"))?,
+                ),
+                ClaimValues::Uint64(values) => values.push(
+                    u64::try_from(self.integer()?).map_err(|_| self.error("integer overflow"))?,
+                ),
+                ClaimValues::Boolean(values) => values.push(self.integer()? != 0),
+                ClaimValues::String(values) => values.push(self.quoted()?),
+                ClaimValues::Fqbn(values) => {
+                    self.expect("{")?;
+                    let version = u64::try_from(self.integer()?)
+                        .map_err(|_| self.error("invalid version"))?;
+                    self.expect(",")?;
+                    values.push((version, self.quoted()?));
+                    self.expect("}")?;
+                }
+                ClaimValues::Sid(values) => {
+                    self.expect("SID(")?;
+                    self.skip_whitespace();
+                    values.push(self.sid()?);
+                    self.expect(")")?;
+                }
+                ClaimValues::OctetString(values) => {
+                    let start = self.pos;
+                    self.eat("0x");
+                    let digits = self.take_until(|c| !c.is_ascii_hexdigit());
+                    values.push(parse_hex(digits).ok_or(Error::Sddl {
+                        offset: start,
+                        message: "invalid octet string".to_owned(),
+                    })?);
+                }
+            }
+        }
+        Ok(ClaimAttribute {
+            name,
+            flags,
+            values,
+        })
+    }
+}
+
+// Conditional expressions. `||` binds loosest, then `&&`, then `!`; every
+// other operator takes operands that are attributes or literals.
+impl Parser<'_> {
+    fn condition(&mut self) -> Result<Expr, Error> {
+        let mut left = self.condition_and()?;
+        while {
+            self.skip_whitespace();
+            self.eat("||")
+        } {
+            let right = self.condition_and()?;
+            left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn condition_and(&mut self) -> Result<Expr, Error> {
+        let mut left = self.condition_term()?;
+        while {
+            self.skip_whitespace();
+            self.eat("&&")
+        } {
+            let right = self.condition_term()?;
+            left = Expr::Binary(BinaryOp::And, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn condition_term(&mut self) -> Result<Expr, Error> {
+        self.skip_whitespace();
+        if !self.rest().starts_with("!=") && self.eat("!") {
+            let operand = self.condition_term()?;
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(operand)));
+        }
+        if self.eat("(") {
+            let expr = self.condition()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+
+        let word = self.word();
+        if let Some((_, op)) = CONDITION_UNARY_OPS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(word))
+        {
+            self.pos += word.len();
+            self.skip_whitespace();
+            let operand = match op {
+                UnaryOp::Exists | UnaryOp::NotExists => self.attribute()?,
+                _ => Expr::Literal(self.literal()?),
+            };
+            return Ok(Expr::Unary(*op, Box::new(operand)));
+        }
+
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let rest = self.rest();
+        let word = self.word();
+        let Some((token, op)) = CONDITION_BINARY_OPS.iter().find(|(token, op)| {
+            !matches!(op, BinaryOp::And | BinaryOp::Or)
+                && if token.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    token.eq_ignore_ascii_case(word)
+                } else {
+                    rest.starts_with(token)
+                }
+        }) else {
+            return Ok(left);
+        };
+        self.pos += token.len();
+        let right = self.operand()?;
+        Ok(Expr::Binary(*op, Box::new(left), Box::new(right)))
+    }
+
+    /// The keyword at the current position, without consuming it.
+    fn word(&self) -> &str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        &rest[..len]
+    }
+
+    fn operand(&mut self) -> Result<Expr, Error> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        match self.peek() {
+            Some('-' | '+' | '"' | '{' | '#') => Ok(Expr::Literal(self.literal()?)),
+            Some(c) if c.is_ascii_digit() => Ok(Expr::Literal(self.literal()?)),
+            _ if rest.len() >= 4 && rest[..4].eq_ignore_ascii_case("SID(") => {
+                Ok(Expr::Literal(self.literal()?))
+            }
+            Some(_) => self.attribute(),
+            None => Err(self.error("expected an operand")),
+        }
+    }
+
+    fn attribute(&mut self) -> Result<Expr, Error> {
+        self.skip_whitespace();
+        let source = if self.peek() == Some('@') {
+            let rest = self.rest();
+            let (prefix, source) = ATTRIBUTE_PREFIXES
+                .iter()
+                .find(|(prefix, _)| {
+                    rest.get(..prefix.len())
+                        .is_some_and(|rest| rest.eq_ignore_ascii_case(prefix))
+                })
+                .ok_or_else(|| self.error("unknown attribute prefix"))?;
+            self.pos += prefix.len();
+            *source
+        } else {
+            AttributeSource::Local
+        };
+
+        let mut units = Vec::new();
+        loop {
+            match self.peek() {
+                Some('%') => {
+                    let unit = self
+                        .rest()
+                        .get(1..5)
+                        .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                        .ok_or_else(|| self.error("invalid attribute name escape"))?;
+                    units.push(unit);
+                    self.pos += 5;
+                }
+                Some(c) if is_attribute_char(c) => {
+                    units.extend_from_slice(c.encode_utf16(&mut [0; 2]));
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        if units.is_empty() {
+            return Err(self.error("expected an attribute name"));
+        }
+        Ok(Expr::Attribute(source, String::from_utf16_lossy(&units)))
+    }
+
+    fn literal(&mut self) -> Result<Literal, Error> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.eat("\"") {
+            let rest = self.rest();
+            let len = rest
+                .find('"')
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += len + 1;
+            return Ok(Literal::String(rest[..len].to_owned()));
+        }
+        if self.eat("{") {
+            let mut items = Vec::new();
+            self.skip_whitespace();
+            if !self.eat("}") {
+                loop {
+                    items.push(self.literal()?);
+                    self.skip_whitespace();
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect("}")?;
+            }
+            return Ok(Literal::Composite(items));
+        }
+        if self.eat("#") {
+            let rest = self.rest();
+            let len = rest
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len());
+            self.pos += len;
+            return parse_hex(&rest[..len])
+                .map(Literal::OctetString)
+                .ok_or(Error::Sddl {
+                    offset: start,
+                    message: "invalid octet string".to_owned(),
+                });
+        }
+        if self
+            .rest()
+            .get(..4)
+            .is_some_and(|rest| rest.eq_ignore_ascii_case("SID("))
+        {
+            self.pos += 4;
+            self.skip_whitespace();
+            let sid = self.sid()?;
+            self.expect(")")?;
+            return Ok(Literal::Sid(sid));
+        }
+
+        let sign = if self.eat("-") {
+            IntSign::Negative
+        } else if self.eat("+") {
+            IntSign::Positive
+        } else {
+            IntSign::None
+        };
+        let (base, radix) = if self.eat("0x") || self.eat("0X") {
+            (IntBase::Hexadecimal, 16)
+        } else if self.rest().starts_with('0')
+            && self.rest()[1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            (IntBase::Octal, 8)
+        } else {
+            (IntBase::Decimal, 10)
+        };
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(rest.len());
+        self.pos += len;
+        let value = u64::from_str_radix(&rest[..len], radix)
+            .ok()
+            .map(|value| match sign {
+                IntSign::Negative => -i128::from(value),
+                _ => i128::from(value),
+            })
+            .and_then(|value| i64::try_from(value).ok())
+            .ok_or(Error::Sddl {
+                offset: start,
+                message: "invalid literal".to_owned(),
+            })?;
+        Ok(Literal::Int { value, sign, base })
+    }
+}
+
+fn is_attribute_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || matches!(
+            c,
+            ':' | '.'
+                | '/'
+                | '_'
+                | '#'
+                | '$'
+                | '\''
+                | '*'
+                | '?'
+                | '@'
+                | '['
+                | '\\'
+                | ']'
+                | '^'
+                | '`'
+                | '~'
+        )
+}
+
+fn pairs(field: &str) -> Option<Vec<&str>> {
+    if !field.len().is_multiple_of(2) || !field.is_ascii() {
+        return None;
+    }
+    Some(
+        (0..field.len())
+            .step_by(2)
+            .map(|i| &field[i..i + 2])
+            .collect(),
+    )
+}
+
+pub(crate) fn parse_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::security::{ClaimValues, SecurityDescriptor, Sid};
+
+    #[test]
+    fn round_trip() {
+        for sddl in [
+            "O:BAG:SYD:PAI(A;OICI;FA;;;SY)(A;;FR;;;BU)",
+            "O:BAG:SYD:(A;;GA;;;WD)",
+            "D:NO_ACCESS_CONTROL",
+            "O:S-1-5-21-1-2-3-1001D:(D;;GA;;;AN)(A;CIIO;GXGR;;;AU)S:AI(AU;SAFA;FA;;;WD)(ML;;NW;;;LW)",
+            "D:(OA;;RPWP;bf967aba-0de6-11d0-a285-00aa003049e2;;PS)(OA;CI;CCDCLCSWRPWPDTLOCRSDRCWDWO;;4828cc14-1437-45bc-9b07-ad6f015e5f28;BA)",
+            "S:(RA;;;;;WD;(\"Publisher\",TF,0x0,{4294967296,\"O=Contoso\"},{0,\"O=Fabrikam\"}))",
+            "S:(RA;CI;;;;WD;(\"Secrecy\",TU,0x0,3,4))(RA;;;;;WD;(\"Name\",TS,0x1,\"a\",\"bb\"))(RA;;;;;WD;(\"S\",TD,0x0,SID(BA),SID(S-1-5-21-9)))(RA;;;;;WD;(\"X\",TX,0x0,0102ff))(RA;;;;;WD;(\"I\",TI,0x0,-5,7))(RA;;;;;WD;(\"B\",TB,0x0,1,0))",
+            "D:(A;;0x1200a9;;;BU)",
+        ] {
+            let sd = SecurityDescriptor::from_sddl(sddl).unwrap();
+            assert_eq!(sd.to_sddl().unwrap(), sddl);
+            let bytes = sd.to_bytes().unwrap();
+            assert_eq!(bytes.len(), sd.len());
+            let parsed = SecurityDescriptor::from_bytes(&bytes).unwrap();
+            assert_eq!(parsed.to_sddl().unwrap(), sddl);
+            assert_eq!(parsed.to_bytes().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn fqbn_claims() {
+        let sddl = "S:(RA;;;;;WD;(\"Publisher\",TF,0x0,{0x100000000, \"O=Contoso\"}))";
+        let sd = SecurityDescriptor::from_sddl(sddl).unwrap();
+        let claim = sd.sacl.as_ref().unwrap().aces[0].claim().unwrap().unwrap();
+        assert_eq!(
+            claim.values,
+            ClaimValues::Fqbn(vec![(1 << 32, "O=Contoso".to_owned())])
+        );
+        assert_eq!(
+            sd.to_sddl().unwrap(),
+            "S:(RA;;;;;WD;(\"Publisher\",TF,0x0,{4294967296,\"O=Contoso\"}))"
+        );
+        for sddl in [
+            "S:(RA;;;;;WD;(\"P\",TF,0x0,\"O=Contoso\"))",
+            "S:(RA;;;;;WD;(\"P\",TF,0x0,{-1,\"O=Contoso\"}))",
+            "S:(RA;;;;;WD;(\"P\",TF,0x0,{1,\"O=Contoso\"))",
+        ] {
+            assert!(SecurityDescriptor::from_sddl(sddl).is_err(), "{sddl}");
+        }
+    }
+
+    #[test]
+    fn self_relative_layout() {
+        let sd = SecurityDescriptor::from_sddl("O:BAG:SYD:(A;;GA;;;WD)").unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            1, 0, 4, 0x80, 0x30, 0, 0, 0, 0x40, 0, 0, 0, 0, 0, 0, 0, 0x14, 0, 0, 0,
+            2, 0, 0x1c, 0, 1, 0, 0, 0,
+            0, 0, 0x14, 0, 0, 0, 0, 0x10, 1, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0,
+            1, 2, 0, 0, 0, 0, 0, 5, 0x20, 0, 0, 0, 0x20, 2, 0, 0,
+            1, 1, 0, 0, 0, 0, 0, 5, 0x12, 0, 0, 0,
+        ];
+        assert_eq!(sd.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn domain_aliases() {
+        let sid = "S-1-5-21-1-2-3-500".parse::<Sid>().unwrap();
+        let domain = sid.domain().unwrap();
+        assert_eq!(sid.alias(Some(&domain)), Some("LA"));
+        let sd = SecurityDescriptor::from_sddl_in_domain("O:DAG:DU", &domain).unwrap();
+        assert_eq!(sd.to_sddl_in_domain(&domain).unwrap(), "O:DAG:DU");
+        assert_eq!(
+            sd.to_sddl().unwrap(),
+            format!("O:{}G:{}", domain.with_rid(512), domain.with_rid(513))
+        );
+        assert!(SecurityDescriptor::from_sddl("O:DA").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        assert!(SecurityDescriptor::from_sddl("D:(A;;QQ;;;WD)").is_err());
+        assert!(SecurityDescriptor::from_sddl("D:(XA;;FA;;;WD;(x ==))").is_err());
+        assert!(SecurityDescriptor::from_sddl("D:(A;;FA;;WD)").is_err());
+        assert!(SecurityDescriptor::from_sddl("X:").is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use std::{fmt, str::FromStr};
+
+use windows::Win32::Foundation::PSID;
+
+use super::Error;
+use crate::buffer::{Reader, Writer};
+
+pub const SID_REVISION: u8 = 1;
+pub const SID_MAX_SUB_AUTHORITIES: usize = 15;
+
+pub const SECURITY_NULL_SID_AUTHORITY: u64 = 0;
+pub const SECURITY_WORLD_SID_AUTHORITY: u64 = 1;
+pub const SECURITY_LOCAL_SID_AUTHORITY: u64 = 2;
+pub const SECURITY_CREATOR_SID_AUTHORITY: u64 = 3;
+pub const SECURITY_NT_AUTHORITY: u64 = 5;
+pub const SECURITY_APP_PACKAGE_AUTHORITY: u64 = 15;
+pub const SECURITY_MANDATORY_LABEL_AUTHORITY: u64 = 16;
+pub const SECURITY_AUTHENTICATION_AUTHORITY: u64 = 18;
+pub const SECURITY_PROCESS_TRUST_AUTHORITY: u64 = 19;
+
+pub const SECURITY_BUILTIN_DOMAIN_RID: u32 = 32;
+pub const SECURITY_NT_NON_UNIQUE: u32 = 21;
+pub const SECURITY_APP_PACKAGE_BASE_RID: u32 = 2;
+pub const SECURITY_CAPABILITY_BASE_RID: u32 = 3;
+
+/// An owned security identifier.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Sid {
+    authority: [u8; 6],
+    sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    /// Creates a SID from its identifier authority and sub-authorities.
+    ///
+    /// Panics if more than [`SID_MAX_SUB_AUTHORITIES`] sub-authorities are
+    /// given or if `authority` does not fit in 48 bits.
+    pub fn new(authority: u64, sub_authorities: &[u32]) -> Self {
+        assert!(sub_authorities.len() <= SID_MAX_SUB_AUTHORITIES);
+        assert!(authority < 1 << 48);
+        let mut bytes = [0; 6];
+        bytes.copy_from_slice(&authority.to_be_bytes()[2..]);
+        Self {
+            authority: bytes,
+            sub_authorities: sub_authorities.to_vec(),
+        }
+    }
+
+    pub fn authority(&self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes[2..].copy_from_slice(&self.authority);
+        u64::from_be_bytes(bytes)
+    }
+
+    pub fn sub_authorities(&self) -> &[u32] {
+        &self.sub_authorities
+    }
+
+    /// The last sub-authority, which is the relative identifier for account
+    /// SIDs.
+    pub fn rid(&self) -> Option<u32> {
+        self.sub_authorities.last().copied()
+    }
+
+    /// Returns the SID of the account `rid` in the domain identified by `self`.
+    pub fn with_rid(&self, rid: u32) -> Self {
+        let mut sub_authorities = self.sub_authorities.clone();
+        sub_authorities.push(rid);
+        Self::new(self.authority(), &sub_authorities)
+    }
+
+    /// Returns the domain part of an account SID, i.e. the SID without its
+    /// relative identifier.
+    pub fn domain(&self) -> Option<Self> {
+        let (_, domain) = self.sub_authorities.split_last()?;
+        Some(Self::new(self.authority(), domain))
+    }
+
+    /// Length of the binary form, `RtlLengthSid`.
+    pub fn len(&self) -> usize {
+        8 + 4 * self.sub_authorities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn everyone() -> Self {
+        Self::new(SECURITY_WORLD_SID_AUTHORITY, &[0])
+    }
+
+    pub fn local_system() -> Self {
+        Self::new(SECURITY_NT_AUTHORITY, &[18])
+    }
+
+    pub fn builtin(rid: u32) -> Self {
+        Self::new(SECURITY_NT_AUTHORITY, &[SECURITY_BUILTIN_DOMAIN_RID, rid])
+    }
+
+    pub fn integrity_level(level: u32) -> Self {
+        Self::new(SECURITY_MANDATORY_LABEL_AUTHORITY, &[level])
+    }
+
+    pub fn is_integrity_level(&self) -> bool {
+        self.authority() == SECURITY_MANDATORY_LABEL_AUTHORITY && self.sub_authorities.len() == 1
+    }
+
+    /// Whether this is an AppContainer package SID (`S-1-15-2-...`).
+    pub fn is_package(&self) -> bool {
+        self.authority() == SECURITY_APP_PACKAGE_AUTHORITY
+            && self.sub_authorities.first() == Some(&SECURITY_APP_PACKAGE_BASE_RID)
+    }
+
+    /// Whether this is a capability SID (`S-1-15-3-...`).
+    pub fn is_capability(&self) -> bool {
+        self.authority() == SECURITY_APP_PACKAGE_AUTHORITY
+            && self.sub_authorities.first() == Some(&SECURITY_CAPABILITY_BASE_RID)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::read(&mut Reader::new(bytes))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.write(&mut writer);
+        writer.into_inner()
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self, Error> {
+        let revision = reader.u8()?;
+        if revision != SID_REVISION {
+            return Err(Error::InvalidRevision(revision));
+        }
+        let count = reader.u8()? as usize;
+        if count > SID_MAX_SUB_AUTHORITIES {
+            return Err(reader.invalid("SID sub-authority count").into());
+        }
+        let authority = reader.array()?;
+        let sub_authorities = (0..count).map(|_| reader.u32()).collect::<Result<_, _>>()?;
+        Ok(Self {
+            authority,
+            sub_authorities,
+        })
+    }
+
+    pub(crate) fn write(&self, writer: &mut Writer) {
+        writer
+            .u8(SID_REVISION)
+            .u8(self.sub_authorities.len() as u8)
+            .bytes(&self.authority);
+        for sub_authority in &self.sub_authorities {
+            writer.u32(*sub_authority);
+        }
+    }
+
+    /// Copies a SID out of native memory.
+    ///
+    /// # Safety
+    ///
+    /// `sid` must point to a valid SID.
+    pub unsafe fn from_raw(sid: PSID) -> Result<Self, Error> {
+        let header = std::slice::from_raw_parts(sid.0 as *const u8, 2);
+        let len = 8 + 4 * header[1] as usize;
+        Self::from_bytes(std::slice::from_raw_parts(sid.0 as *const u8, len))
+    }
+
+    /// Resolves a two-letter SDDL alias such as `BA` or `WD`. Domain-relative
+    /// aliases such as `DA` resolve only when `domain` is given.
+    pub fn from_alias(alias: &str, domain: Option<&Sid>) -> Option<Self> {
+        if let Some((_, authority, sub_authorities)) =
+            SID_ALIASES.iter().find(|(name, ..)| *name == alias)
+        {
+            return Some(Self::new(*authority, sub_authorities));
+        }
+        let (_, rid) = DOMAIN_ALIASES.iter().find(|(name, _)| *name == alias)?;
+        domain.map(|domain| domain.with_rid(*rid))
+    }
+
+    /// Returns the SDDL alias for this SID, if it has one.
+    pub fn alias(&self, domain: Option<&Sid>) -> Option<&'static str> {
+        if let Some((name, ..)) = SID_ALIASES.iter().find(|(_, authority, sub_authorities)| {
+            *authority == self.authority() && *sub_authorities == self.sub_authorities.as_slice()
+        }) {
+            return Some(name);
+        }
+        let domain = domain?;
+        if self.domain().as_ref() != Some(domain) {
+            return None;
+        }
+        let rid = self.rid()?;
+        DOMAIN_ALIASES
+            .iter()
+            .find(|(_, alias_rid)| *alias_rid == rid)
+            .map(|(name, _)| *name)
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S-{}-", SID_REVISION)?;
+        if self.authority[0] == 0 && self.authority[1] == 0 {
+            write!(f, "{}", self.authority())?;
+        } else {
+            write!(f, "0x{:012X}", self.authority())?;
+        }
+        for sub_authority in &self.sub_authorities {
+            write!(f, "-{sub_authority}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sid({self})")
+    }
+}
+
+impl FromStr for Sid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidSid(s.to_owned());
+        let mut parts = s
+            .strip_prefix("S-")
+            .or_else(|| s.strip_prefix("s-"))
+            .ok_or_else(invalid)?
+            .split('-');
+        if parts.next() != Some("1") {
+            return Err(invalid());
+        }
+        let authority = parts.next().ok_or_else(invalid)?;
+        let authority = match authority
+            .strip_prefix("0x")
+            .or_else(|| authority.strip_prefix("0X"))
+        {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => authority.parse(),
+        }
+        .ok()
+        .filter(|authority| *authority < 1 << 48)
+        .ok_or_else(invalid)?;
+        let sub_authorities: Vec<u32> = parts
+            .map(|part| part.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        if sub_authorities.len() > SID_MAX_SUB_AUTHORITIES {
+            return Err(invalid());
+        }
+        Ok(Self::new(authority, &sub_authorities))
+    }
+}
+
+const SID_ALIASES: &[(&str, u64, &[u32])] = &[
+    ("AA", 5, &[32, 579]),
+    ("AC", 15, &[2, 1]),
+    ("AN", 5, &[7]),
+    ("AO", 5, &[32, 548]),
+    ("AS", 18, &[1]),
+    ("AU", 5, &[11]),
+    ("BA", 5, &[32, 544]),
+    ("BG", 5, &[32, 546]),
+    ("BO", 5, &[32, 551]),
+    ("BU", 5, &[32, 545]),
+    ("CD", 5, &[32, 574]),
+    ("CG", 3, &[1]),
+    ("CO", 3, &[0]),
+    ("CY", 5, &[32, 569]),
+    ("ED", 5, &[9]),
+    ("ER", 5, &[32, 573]),
+    ("ES", 5, &[32, 576]),
+    ("HA", 5, &[32, 578]),
+    ("HI", 16, &[12288]),
+    ("IS", 5, &[32, 568]),
+    ("IU", 5, &[4]),
+    ("LS", 5, &[19]),
+    ("LU", 5, &[32, 559]),
+    ("LW", 16, &[4096]),
+    ("ME", 16, &[8192]),
+    ("MP", 16, &[8448]),
+    ("MU", 5, &[32, 558]),
+    ("NO", 5, &[32, 556]),
+    ("NS", 5, &[20]),
+    ("NU", 5, &[2]),
+    ("OW", 3, &[4]),
+    ("PO", 5, &[32, 550]),
+    ("PS", 5, &[10]),
+    ("PU", 5, &[32, 547]),
+    ("RA", 5, &[32, 575]),
+    ("RC", 5, &[12]),
+    ("RD", 5, &[32, 555]),
+    ("RE", 5, &[32, 552]),
+    ("RM", 5, &[32, 580]),
+    ("RU", 5, &[32, 554]),
+    ("SI", 16, &[16384]),
+    ("SO", 5, &[32, 549]),
+    ("SS", 18, &[2]),
+    ("SU", 5, &[6]),
+    ("SY", 5, &[18]),
+    ("UD", 5, &[84, 0, 0, 0, 0, 0]),
+    ("WD", 1, &[0]),
+    ("WR", 5, &[33]),
+];
+
+// EA, EK, RO and SA are relative to the forest root domain, which is assumed
+// to be the domain passed in.
+const DOMAIN_ALIASES: &[(&str, u32)] = &[
+    ("RO", 498),
+    ("LA", 500),
+    ("LG", 501),
+    ("DA", 512),
+    ("DU", 513),
+    ("DG", 514),
+    ("DC", 515),
+    ("DD", 516),
+    ("CA", 517),
+    ("SA", 518),
+    ("EA", 519),
+    ("PA", 520),
+    ("CN", 522),
+    ("AP", 525),
+    ("KA", 526),
+    ("EK", 527),
+    ("RS", 553),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::DecodeError;
+
+    #[test]
+    fn round_trips_text() {
+        for text in [
+            "S-1-5-32-544",
+            "S-1-5-21-3623811015-3361044348-30300820-1013",
+            "S-1-0",
+            "S-1-0x123456789ABC-7",
+            "S-1-15-2-1-2-3-4-5-6-7-8-9-10-11-12-13-14",
+        ] {
+            let sid = text.parse::<Sid>().unwrap();
+            assert_eq!(sid.to_string(), text);
+            assert_eq!(Sid::from_bytes(&sid.to_bytes()), Ok(sid));
+        }
+        assert_eq!("s-1-0x12-1".parse::<Sid>().unwrap().to_string(), "S-1-18-1");
+        assert_eq!(format!("{:?}", Sid::everyone()), "Sid(S-1-1-0)");
+        for text in [
+            "",
+            "S-1",
+            "S-2-5-18",
+            "S-1-5-18-",
+            "S-1-5-x",
+            "S-1-281474976710656-1",
+            "S-1-5-4294967296",
+            "S-1-5-1-2-3-4-5-6-7-8-9-10-11-12-13-14-15-16",
+        ] {
+            assert_eq!(
+                text.parse::<Sid>(),
+                Err(Error::InvalidSid(text.to_owned())),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_bytes() {
+        let sid = Sid::builtin(544);
+        let bytes = sid.to_bytes();
+        assert_eq!(bytes, [1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x20, 2, 0, 0]);
+        assert_eq!(bytes.len(), sid.len());
+        assert_eq!(Sid::from_bytes(&bytes), Ok(sid.clone()));
+        assert_eq!(unsafe { Sid::from_raw(PSID(bytes.as_ptr() as _)) }, Ok(sid));
+
+        let authority = Sid::new(0x1234_5678_9abc, &[]);
+        assert_eq!(
+            authority.to_bytes(),
+            [1, 0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]
+        );
+        assert_eq!(authority.authority(), 0x1234_5678_9abc);
+
+        assert_eq!(
+            Sid::from_bytes(&[2, 0, 0, 0, 0, 0, 0, 1]),
+            Err(Error::InvalidRevision(2))
+        );
+        assert!(matches!(
+            Sid::from_bytes(&[1, 16, 0, 0, 0, 0, 0, 5]),
+            Err(Error::Decode(DecodeError::Invalid { offset: 2, .. }))
+        ));
+        assert!(matches!(
+            Sid::from_bytes(&bytes[..15]),
+            Err(Error::Decode(DecodeError::Truncated { offset: 12, .. }))
+        ));
+    }
+
+    #[test]
+    fn splits_domains() {
+        let user = "S-1-5-21-1-2-3-1001".parse::<Sid>().unwrap();
+        let domain = user.domain().unwrap();
+        assert_eq!(domain.to_string(), "S-1-5-21-1-2-3");
+        assert_eq!(user.rid(), Some(1001));
+        assert_eq!(domain.with_rid(1001), user);
+        assert_eq!(Sid::new(5, &[]).domain(), None);
+        assert_eq!(Sid::new(5, &[]).rid(), None);
+
+        assert_eq!(Sid::from_alias("SY", None), Some(Sid::local_system()));
+        assert_eq!(Sid::local_system().alias(None), Some("SY"));
+        assert_eq!(Sid::from_alias("DA", None), None);
+        assert_eq!(
+            Sid::from_alias("DA", Some(&domain)),
+            Some(domain.with_rid(512))
+        );
+        assert_eq!(domain.with_rid(512).alias(Some(&domain)), Some("DA"));
+        assert_eq!(domain.with_rid(512).alias(None), None);
+        assert_eq!(user.alias(Some(&domain)), None);
+    }
+
+    #[test]
+    fn classifies_sids() {
+        assert!(Sid::integrity_level(0x2000).is_integrity_level());
+        assert!(!Sid::new(SECURITY_MANDATORY_LABEL_AUTHORITY, &[1, 2]).is_integrity_level());
+        assert!("S-1-15-2-1".parse::<Sid>().unwrap().is_package());
+        assert!("S-1-15-3-1024-1".parse::<Sid>().unwrap().is_capability());
+        assert!(!"S-1-15-3-1".parse::<Sid>().unwrap().is_package());
+        assert!(!Sid::everyone().is_capability());
+    }
+}
@@ -0,0 +1,1227 @@
+//! Terminal Services session management over the `WinStation*` functions
+//! bound in [`crate::winsta`].
+//!
+//! [`SessionServer`] owns a server handle and exposes enumeration, typed
+//! information queries and session control over a [`SessionBackend`],
+//! [`NativeSessions`] unless one is given. The information types decode the
+//! native structures, with their fixed-size `WCHAR` arrays and bitfields, in
+//! plain Rust through [`SessionInformation::decode`], independently of any
+//! server.
+//!
+//! ```no_run
+//! use windows_native::session::*;
+//!
+//! let server = SessionServer::local();
+//! for session in server.sessions()? {
+//!     let info = server.query::<ConnectInfo>(session.session_id)?;
+//!     println!("{} {:?} {}\\{}", session.name, session.state, info.domain, info.user_name);
+//! }
+//! # Ok::<(), windows::Win32::Foundation::WIN32_ERROR>(())
+//! ```
+
+use std::{
+    ffi::c_void,
+    mem,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    ptr,
+};
+
+use windows::{
+    Win32::Foundation::{BOOLEAN, ERROR_INVALID_DATA, HANDLE, WIN32_ERROR},
+    core::PWSTR,
+};
+
+use crate::{
+    buffer::{from_wide, to_wide},
+    ntrtl::RtlGetLastWin32Error,
+    winsta::*,
+};
+
+const AF_INET: u16 = 2;
+const AF_INET6: u16 = 23;
+
+fn check_bool(success: BOOLEAN) -> Result<(), WIN32_ERROR> {
+    if success.as_bool() {
+        Ok(())
+    } else {
+        Err(WIN32_ERROR(unsafe { RtlGetLastWin32Error() } as u32))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum SessionState {
+    Active,
+    Connected,
+    ConnectQuery,
+    Shadow,
+    Disconnected,
+    Idle,
+    Listen,
+    Reset,
+    Down,
+    Init,
+}
+
+impl From<WINSTATIONSTATECLASS> for SessionState {
+    fn from(value: WINSTATIONSTATECLASS) -> Self {
+        match value {
+            WINSTATIONSTATECLASS::State_Active => Self::Active,
+            WINSTATIONSTATECLASS::State_Connected => Self::Connected,
+            WINSTATIONSTATECLASS::State_ConnectQuery => Self::ConnectQuery,
+            WINSTATIONSTATECLASS::State_Shadow => Self::Shadow,
+            WINSTATIONSTATECLASS::State_Disconnected => Self::Disconnected,
+            WINSTATIONSTATECLASS::State_Idle => Self::Idle,
+            WINSTATIONSTATECLASS::State_Listen => Self::Listen,
+            WINSTATIONSTATECLASS::State_Reset => Self::Reset,
+            WINSTATIONSTATECLASS::State_Down => Self::Down,
+            WINSTATIONSTATECLASS::State_Init => Self::Init,
+        }
+    }
+}
+
+// The native structures hold these enumerations as plain `LONG`s so that
+// any value the server writes is sound to read; unknown values are
+// rejected while decoding.
+
+impl TryFrom<i32> for WINSTATIONSTATECLASS {
+    type Error = WIN32_ERROR;
+
+    fn try_from(value: i32) -> Result<Self, WIN32_ERROR> {
+        Ok(match value {
+            0 => Self::State_Active,
+            1 => Self::State_Connected,
+            2 => Self::State_ConnectQuery,
+            3 => Self::State_Shadow,
+            4 => Self::State_Disconnected,
+            5 => Self::State_Idle,
+            6 => Self::State_Listen,
+            7 => Self::State_Reset,
+            8 => Self::State_Down,
+            9 => Self::State_Init,
+            _ => return Err(ERROR_INVALID_DATA),
+        })
+    }
+}
+
+impl TryFrom<i32> for CALLBACKCLASS {
+    type Error = WIN32_ERROR;
+
+    fn try_from(value: i32) -> Result<Self, WIN32_ERROR> {
+        Ok(match value {
+            0 => Self::Callback_Disable,
+            1 => Self::Callback_Roving,
+            2 => Self::Callback_Fixed,
+            _ => return Err(ERROR_INVALID_DATA),
+        })
+    }
+}
+
+impl TryFrom<i32> for SHADOWCLASS {
+    type Error = WIN32_ERROR;
+
+    fn try_from(value: i32) -> Result<Self, WIN32_ERROR> {
+        Ok(match value {
+            0 => Self::Shadow_Disable,
+            1 => Self::Shadow_EnableInputNotify,
+            2 => Self::Shadow_EnableInputNoNotify,
+            3 => Self::Shadow_EnableNoInputNotify,
+            4 => Self::Shadow_EnableNoInputNoNotify,
+            _ => return Err(ERROR_INVALID_DATA),
+        })
+    }
+}
+
+/// An entry returned by `WinStationEnumerateW`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionEntry {
+    pub session_id: u32,
+    /// The WinStation name, e.g. `Console` or `RDP-Tcp#0`.
+    pub name: String,
+    pub state: SessionState,
+}
+
+impl TryFrom<&SESSIONIDW> for SessionEntry {
+    type Error = WIN32_ERROR;
+
+    fn try_from(value: &SESSIONIDW) -> Result<Self, WIN32_ERROR> {
+        Ok(Self {
+            session_id: unsafe { *value.Anonymous1.SessionId.as_ref() },
+            name: from_wide(&value.WinStationName),
+            state: WINSTATIONSTATECLASS::try_from(value.State)?.into(),
+        })
+    }
+}
+
+/// An information class that can be queried with
+/// [`SessionServer::query`].
+pub trait SessionInformation: Sized {
+    const CLASS: WINSTATIONINFOCLASS;
+    type Raw: Default;
+
+    /// Decodes `raw`, failing with `ERROR_INVALID_DATA` on an enumeration
+    /// value this crate does not know.
+    fn decode(raw: &Self::Raw) -> Result<Self, WIN32_ERROR>;
+}
+
+/// `WinStationInformation`: connection state, times and logged on user.
+/// Times are `FILETIME` values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectInfo {
+    pub state: SessionState,
+    pub name: String,
+    pub session_id: u32,
+    pub connect_time: i64,
+    pub disconnect_time: i64,
+    pub last_input_time: i64,
+    pub logon_time: i64,
+    pub current_time: i64,
+    pub domain: String,
+    pub user_name: String,
+}
+
+impl SessionInformation for ConnectInfo {
+    const CLASS: WINSTATIONINFOCLASS = WINSTATIONINFOCLASS::WinStationInformation;
+    type Raw = WINSTATIONINFORMATION;
+
+    fn decode(raw: &WINSTATIONINFORMATION) -> Result<Self, WIN32_ERROR> {
+        Ok(Self {
+            state: WINSTATIONSTATECLASS::try_from(raw.ConnectState)?.into(),
+            name: from_wide(&raw.WinStationName),
+            session_id: raw.LogonId,
+            connect_time: raw.ConnectTime,
+            disconnect_time: raw.DisconnectTime,
+            last_input_time: raw.LastInputTime,
+            logon_time: raw.LogonTime,
+            current_time: raw.CurrentTime,
+            domain: from_wide(&raw.Domain),
+            user_name: from_wide(&raw.UserName),
+        })
+    }
+}
+
+/// The bitfield flags of `WINSTATIONCLIENT`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct ClientFlags {
+    pub text_only: bool,
+    pub disable_ctrl_alt_del: bool,
+    pub mouse: bool,
+    pub double_click_detect: bool,
+    pub inet_client: bool,
+    pub prompt_for_password: bool,
+    pub maximize_shell: bool,
+    pub enable_windows_key: bool,
+    pub remote_console_audio: bool,
+    pub password_is_sc_pin: bool,
+    pub no_audio_playback: bool,
+    pub using_saved_creds: bool,
+}
+
+/// `WinStationClient`: what the remote client reported when it connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub flags: ClientFlags,
+    pub client_name: String,
+    pub domain: String,
+    pub user_name: String,
+    pub work_directory: String,
+    pub initial_program: String,
+    pub serial_number: u32,
+    pub encryption_level: u8,
+    pub address_family: u32,
+    /// The client address in text form, as the client sent it.
+    pub address: String,
+    pub horizontal_resolution: u16,
+    pub vertical_resolution: u16,
+    pub color_depth: u16,
+    /// One of the `PROTOCOL_*` constants.
+    pub protocol_type: u16,
+    pub keyboard_layout: u32,
+    pub keyboard_type: u32,
+    pub keyboard_sub_type: u32,
+    pub keyboard_function_key: u32,
+    pub ime_file_name: String,
+    pub client_directory: String,
+    pub client_license: String,
+    pub client_modem: String,
+    pub build_number: u32,
+    pub hardware_id: u32,
+    pub product_id: u16,
+    pub audio_driver_name: String,
+    pub client_session_id: u32,
+    pub dig_product_id: String,
+    pub performance_flags: u32,
+    pub active_input_locale: u32,
+}
+
+impl SessionInformation for ClientInfo {
+    const CLASS: WINSTATIONINFOCLASS = WINSTATIONINFOCLASS::WinStationClient;
+    type Raw = WINSTATIONCLIENT;
+
+    fn decode(raw: &WINSTATIONCLIENT) -> Result<Self, WIN32_ERROR> {
+        Ok(Self {
+            flags: ClientFlags {
+                text_only: raw.fTextOnly() != 0,
+                disable_ctrl_alt_del: raw.fDisableCtrlAltDel() != 0,
+                mouse: raw.fMouse() != 0,
+                double_click_detect: raw.fDoubleClickDetect() != 0,
+                inet_client: raw.fINetClient() != 0,
+                prompt_for_password: raw.fPromptForPassword() != 0,
+                maximize_shell: raw.fMaximizeShell() != 0,
+                enable_windows_key: raw.fEnableWindowsKey() != 0,
+                remote_console_audio: raw.fRemoteConsoleAudio() != 0,
+                password_is_sc_pin: raw.fPasswordIsScPin() != 0,
+                no_audio_playback: raw.fNoAudioPlayback() != 0,
+                using_saved_creds: raw.fUsingSavedCreds() != 0,
+            },
+            client_name: from_wide(&raw.ClientName),
+            domain: from_wide(&raw.Domain),
+            user_name: from_wide(&raw.UserName),
+            work_directory: from_wide(&raw.WorkDirectory),
+            initial_program: from_wide(&raw.InitialProgram),
+            serial_number: raw.SerialNumber,
+            encryption_level: raw.EncryptionLevel,
+            address_family: raw.ClientAddressFamily,
+            address: from_wide(&raw.ClientAddress),
+            horizontal_resolution: raw.HRes,
+            vertical_resolution: raw.VRes,
+            color_depth: raw.ColorDepth,
+            protocol_type: raw.ProtocolType,
+            keyboard_layout: raw.KeyboardLayout,
+            keyboard_type: raw.KeyboardType,
+            keyboard_sub_type: raw.KeyboardSubType,
+            keyboard_function_key: raw.KeyboardFunctionKey,
+            ime_file_name: from_wide(&raw.ImeFileName),
+            client_directory: from_wide(&raw.ClientDirectory),
+            client_license: from_wide(&raw.ClientLicense),
+            client_modem: from_wide(&raw.ClientModem),
+            build_number: raw.ClientBuildNumber,
+            hardware_id: raw.ClientHardwareId,
+            product_id: raw.ClientProductId,
+            audio_driver_name: from_wide(&raw.AudioDriverName),
+            client_session_id: raw.ClientSessionId,
+            dig_product_id: from_wide(&raw.ClientDigProductId),
+            performance_flags: raw.PerformanceFlags,
+            active_input_locale: raw.ActiveInputLocale,
+        })
+    }
+}
+
+/// The bitfield flags of `USERCONFIG`. The `inherit_*` flags select whether
+/// the corresponding setting comes from the client rather than the user
+/// configuration.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct UserConfigFlags {
+    pub inherit_auto_logon: bool,
+    pub inherit_reset_broken: bool,
+    pub inherit_reconnect_same: bool,
+    pub inherit_initial_program: bool,
+    pub inherit_callback: bool,
+    pub inherit_callback_number: bool,
+    pub inherit_shadow: bool,
+    pub inherit_max_session_time: bool,
+    pub inherit_max_disconnection_time: bool,
+    pub inherit_max_idle_time: bool,
+    pub inherit_auto_client: bool,
+    pub inherit_security: bool,
+    pub inherit_color_depth: bool,
+    pub prompt_for_password: bool,
+    pub reset_broken: bool,
+    pub reconnect_same: bool,
+    pub logon_disabled: bool,
+    pub wallpaper_disabled: bool,
+    pub auto_client_drives: bool,
+    pub auto_client_lpts: bool,
+    pub force_client_lpt_default: bool,
+    pub require_encryption: bool,
+    pub disable_encryption: bool,
+    pub home_directory_map_root: bool,
+    pub use_default_gina: bool,
+    pub cursor_blink_disabled: bool,
+    pub published_app: bool,
+    pub hide_title_bar: bool,
+    pub maximize: bool,
+    pub disable_printer_mapping: bool,
+    pub disable_drive_mapping: bool,
+    pub disable_com_port_mapping: bool,
+    pub disable_lpt_port_mapping: bool,
+    pub disable_clipboard: bool,
+    pub disable_exe: bool,
+    pub disable_audio: bool,
+    pub disable_auto_reconnect: bool,
+    pub error_invalid_profile: bool,
+    pub password_is_sc_pin: bool,
+    pub disable_pnp_redirection: bool,
+}
+
+impl From<&USERCONFIG> for UserConfigFlags {
+    fn from(raw: &USERCONFIG) -> Self {
+        Self {
+            inherit_auto_logon: raw.fInheritAutoLogon() != 0,
+            inherit_reset_broken: raw.fInheritResetBroken() != 0,
+            inherit_reconnect_same: raw.fInheritReconnectSame() != 0,
+            inherit_initial_program: raw.fInheritInitialProgram() != 0,
+            inherit_callback: raw.fInheritCallback() != 0,
+            inherit_callback_number: raw.fInheritCallbackNumber() != 0,
+            inherit_shadow: raw.fInheritShadow() != 0,
+            inherit_max_session_time: raw.fInheritMaxSessionTime() != 0,
+            inherit_max_disconnection_time: raw.fInheritMaxDisconnectionTime() != 0,
+            inherit_max_idle_time: raw.fInheritMaxIdleTime() != 0,
+            inherit_auto_client: raw.fInheritAutoClient() != 0,
+            inherit_security: raw.fInheritSecurity() != 0,
+            inherit_color_depth: raw.fInheritColorDepth() != 0,
+            prompt_for_password: raw.fPromptForPassword() != 0,
+            reset_broken: raw.fResetBroken() != 0,
+            reconnect_same: raw.fReconnectSame() != 0,
+            logon_disabled: raw.fLogonDisabled() != 0,
+            wallpaper_disabled: raw.fWallPaperDisabled() != 0,
+            auto_client_drives: raw.fAutoClientDrives() != 0,
+            auto_client_lpts: raw.fAutoClientLpts() != 0,
+            force_client_lpt_default: raw.fForceClientLptDef() != 0,
+            require_encryption: raw.fRequireEncryption() != 0,
+            disable_encryption: raw.fDisableEncryption() != 0,
+            home_directory_map_root: raw.fHomeDirectoryMapRoot() != 0,
+            use_default_gina: raw.fUseDefaultGina() != 0,
+            cursor_blink_disabled: raw.fCursorBlinkDisabled() != 0,
+            published_app: raw.fPublishedApp() != 0,
+            hide_title_bar: raw.fHideTitleBar() != 0,
+            maximize: raw.fMaximize() != 0,
+            disable_printer_mapping: raw.fDisableCpm() != 0,
+            disable_drive_mapping: raw.fDisableCdm() != 0,
+            disable_com_port_mapping: raw.fDisableCcm() != 0,
+            disable_lpt_port_mapping: raw.fDisableLPT() != 0,
+            disable_clipboard: raw.fDisableClip() != 0,
+            disable_exe: raw.fDisableExe() != 0,
+            disable_audio: raw.fDisableCam() != 0,
+            disable_auto_reconnect: raw.fDisableAutoReconnect() != 0,
+            error_invalid_profile: raw.fErrorInvalidProfile() != 0,
+            password_is_sc_pin: raw.fPasswordIsScPin() != 0,
+            disable_pnp_redirection: raw.fDisablePNPRedir() != 0,
+        }
+    }
+}
+
+/// `WinStationConfiguration`: the user configuration the session runs
+/// with. Time limits are in milliseconds, zero meaning none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserConfig {
+    pub comment: String,
+    pub flags: UserConfigFlags,
+    pub color_depth: u32,
+    pub user_name: String,
+    pub domain: String,
+    pub work_directory: String,
+    pub initial_program: String,
+    pub callback_number: String,
+    pub callback: CALLBACKCLASS,
+    pub shadow: SHADOWCLASS,
+    pub max_connection_time: u32,
+    pub max_disconnection_time: u32,
+    pub max_idle_time: u32,
+    pub keyboard_layout: u32,
+    pub min_encryption_level: u8,
+    pub published_name: String,
+    pub profile_path: String,
+    pub home_directory: String,
+    pub home_directory_drive: String,
+}
+
+impl SessionInformation for UserConfig {
+    const CLASS: WINSTATIONINFOCLASS = WINSTATIONINFOCLASS::WinStationConfiguration;
+    type Raw = WINSTATIONCONFIG;
+
+    fn decode(raw: &WINSTATIONCONFIG) -> Result<Self, WIN32_ERROR> {
+        let user = &raw.User;
+        Ok(Self {
+            comment: from_wide(&raw.Comment),
+            flags: user.into(),
+            color_depth: user.ColorDepth(),
+            user_name: from_wide(&user.UserName),
+            domain: from_wide(&user.Domain),
+            work_directory: from_wide(&user.WorkDirectory),
+            initial_program: from_wide(&user.InitialProgram),
+            callback_number: from_wide(&user.CallbackNumber),
+            callback: user.Callback.try_into()?,
+            shadow: user.Shadow.try_into()?,
+            max_connection_time: user.MaxConnectionTime,
+            max_disconnection_time: user.MaxDisconnectionTime,
+            max_idle_time: user.MaxIdleTime,
+            keyboard_layout: user.KeyboardLayout,
+            min_encryption_level: user.MinEncryptionLevel,
+            published_name: from_wide(&user.PublishedName),
+            profile_path: from_wide(&user.WFProfilePath),
+            home_directory: from_wide(&user.WFHomeDir),
+            home_directory_drive: from_wide(&user.WFHomeDirDrive),
+        })
+    }
+}
+
+/// `WinStationRemoteAddress`: the client's socket address, `None` for
+/// sessions without one such as the console.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct RemoteAddress(pub Option<SocketAddr>);
+
+impl SessionInformation for RemoteAddress {
+    const CLASS: WINSTATIONINFOCLASS = WINSTATIONINFOCLASS::WinStationRemoteAddress;
+    type Raw = WINSTATIONREMOTEADDRESS;
+
+    fn decode(raw: &WINSTATIONREMOTEADDRESS) -> Result<Self, WIN32_ERROR> {
+        // Ports and addresses are stored in network byte order.
+        Ok(Self(match raw.sin_family {
+            AF_INET => {
+                let ipv4 = unsafe { raw.Anonymous1.ipv4.as_ref() };
+                Some(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(ipv4.sin_addr.to_ne_bytes()),
+                    u16::from_be(ipv4.sin_port),
+                )))
+            }
+            AF_INET6 => {
+                let ipv6 = unsafe { raw.Anonymous1.ipv6.as_ref() };
+                let mut octets = [0; 16];
+                for (chunk, word) in octets.chunks_exact_mut(2).zip(ipv6.sin6_addr) {
+                    chunk.copy_from_slice(&word.to_ne_bytes());
+                }
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(octets),
+                    u16::from_be(ipv6.sin6_port),
+                    u32::from_be(ipv6.sin6_flowinfo),
+                    ipv6.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }))
+    }
+}
+
+/// The calls [`SessionServer`] is built on.
+pub trait SessionBackend {
+    fn open_server(&self, name: &str) -> Result<HANDLE, WIN32_ERROR>;
+
+    fn close_server(&self, server: HANDLE);
+
+    fn ping(&self, server: HANDLE) -> Result<(), WIN32_ERROR>;
+
+    fn enumerate(&self, server: HANDLE) -> Result<Vec<SessionEntry>, WIN32_ERROR>;
+
+    /// Queries `class` of `session_id` into `buffer`, which is sized for the
+    /// class structure.
+    fn query(
+        &self,
+        server: HANDLE,
+        session_id: u32,
+        class: WINSTATIONINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<(), WIN32_ERROR>;
+
+    fn session_name(&self, server: HANDLE, session_id: u32) -> Result<String, WIN32_ERROR>;
+
+    fn session_id(&self, server: HANDLE, name: &str) -> Result<u32, WIN32_ERROR>;
+
+    fn send_message(
+        &self,
+        server: HANDLE,
+        session_id: u32,
+        title: &str,
+        message: &str,
+        style: u32,
+        timeout: u32,
+        wait: bool,
+    ) -> Result<u32, WIN32_ERROR>;
+
+    fn connect(
+        &self,
+        server: HANDLE,
+        session_id: u32,
+        target_session_id: u32,
+        password: &str,
+        wait: bool,
+    ) -> Result<(), WIN32_ERROR>;
+
+    fn disconnect(&self, server: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR>;
+
+    fn reset(&self, server: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR>;
+
+    fn shadow(
+        &self,
+        server: HANDLE,
+        target_server: Option<&str>,
+        target_session_id: u32,
+        hotkey_vk: u8,
+        hotkey_modifiers: u16,
+    ) -> Result<(), WIN32_ERROR>;
+
+    fn stop_shadow(&self, server: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR>;
+}
+
+/// The [`SessionBackend`] that calls the `WinStation*` functions.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeSessions;
+
+impl SessionBackend for NativeSessions {
+    fn open_server(&self, name: &str) -> Result<HANDLE, WIN32_ERROR> {
+        let mut name = to_wide(name);
+        let handle = unsafe { WinStationOpenServerW(PWSTR(name.as_mut_ptr())) };
+        if handle.is_invalid() {
+            return Err(WIN32_ERROR(unsafe { RtlGetLastWin32Error() } as u32));
+        }
+        Ok(handle)
+    }
+
+    fn close_server(&self, server: HANDLE) {
+        unsafe { WinStationCloseServer(server) };
+    }
+
+    fn ping(&self, server: HANDLE) -> Result<(), WIN32_ERROR> {
+        check_bool(unsafe { WinStationServerPing(server) })
+    }
+
+    fn enumerate(&self, server: HANDLE) -> Result<Vec<SessionEntry>, WIN32_ERROR> {
+        let mut buffer = ptr::null_mut();
+        let mut count = 0;
+        check_bool(unsafe { WinStationEnumerateW(server, &mut buffer, &mut count) })?;
+        if buffer.is_null() {
+            return Ok(Vec::new());
+        }
+        let sessions = unsafe { std::slice::from_raw_parts(buffer, count as usize) }
+            .iter()
+            .map(SessionEntry::try_from)
+            .collect();
+        unsafe { WinStationFreeMemory(buffer.cast()) };
+        sessions
+    }
+
+    fn query(
+        &self,
+        server: HANDLE,
+        session_id: u32,
+        class: WINSTATIONINFOCLASS,
+        buffer: &mut [u8],
+    ) -> Result<(), WIN32_ERROR> {
+        let mut return_length = 0;
+        check_bool(unsafe {
+            WinStationQueryInformationW(
+                server,
+                session_id,
+                class,
+                buffer.as_mut_ptr().cast::<c_void>(),
+                buffer.len() as u32,
+                &mut return_length,
+            )
+        })
+    }
+
+    fn session_name(&self, server: HANDLE, session_id: u32) -> Result<String, WIN32_ERROR> {
+        let mut name = [0u16; 33];
+        check_bool(unsafe {
+            WinStationNameFromLogonIdW(server, session_id, PWSTR(name.as_mut_ptr()))
+        })?;
+        Ok(from_wide(&name))
+    }
+
+    fn session_id(&self, server: HANDLE, name: &str) -> Result<u32, WIN32_ERROR> {
+        let mut name = to_wide(name);
+        let mut session_id = 0;
+        check_bool(unsafe {
+            LogonIdFromWinStationNameW(server, PWSTR(name.as_mut_ptr()), &mut session_id)
+        })?;
+        Ok(session_id)
+    }
+
+    fn send_message(
+        &self,
+        server: HANDLE,
+        session_id: u32,
+        title: &str,
+        message: &str,
+        style: u32,
+        timeout: u32,
+        wait: bool,
+    ) -> Result<u32, WIN32_ERROR> {
+        let mut title = to_wide(title);
+        let mut message = to_wide(message);
+        let mut response = 0;
+        check_bool(unsafe {
+            WinStationSendMessageW(
+                server,
+                session_id,
+                PWSTR(title.as_mut_ptr()),
+                ((title.len() - 1) * 2) as u32,
+                PWSTR(message.as_mut_ptr()),
+                ((message.len() - 1) * 2) as u32,
+                style,
+                timeout,
+                &mut response,
+                BOOLEAN(!wait as u8),
+            )
+        })?;
+        Ok(response)
+    }
+
+    fn connect(
+        &self,
+        server: HANDLE,
+        session_id: u32,
+        target_session_id: u32,
+        password: &str,
+        wait: bool,
+    ) -> Result<(), WIN32_ERROR> {
+        let mut password = to_wide(password);
+        check_bool(unsafe {
+            WinStationConnectW(
+                server,
+                session_id,
+                target_session_id,
+                PWSTR(password.as_mut_ptr()),
+                BOOLEAN(wait as u8),
+            )
+        })
+    }
+
+    fn disconnect(&self, server: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+        check_bool(unsafe { WinStationDisconnect(server, session_id, BOOLEAN(wait as u8)) })
+    }
+
+    fn reset(&self, server: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+        check_bool(unsafe { WinStationReset(server, session_id, BOOLEAN(wait as u8)) })
+    }
+
+    fn shadow(
+        &self,
+        server: HANDLE,
+        target_server: Option<&str>,
+        target_session_id: u32,
+        hotkey_vk: u8,
+        hotkey_modifiers: u16,
+    ) -> Result<(), WIN32_ERROR> {
+        let mut target_server = target_server.map(to_wide);
+        check_bool(unsafe {
+            WinStationShadow(
+                server,
+                target_server
+                    .as_mut()
+                    .map_or(PWSTR::null(), |name| PWSTR(name.as_mut_ptr())),
+                target_session_id,
+                hotkey_vk,
+                hotkey_modifiers,
+            )
+        })
+    }
+
+    fn stop_shadow(&self, server: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+        check_bool(unsafe { WinStationShadowStop(server, session_id, BOOLEAN(wait as u8)) })
+    }
+}
+
+/// A Terminal Services server handle, closed on drop.
+#[derive(Debug)]
+pub struct SessionServer<B: SessionBackend = NativeSessions> {
+    backend: B,
+    handle: HANDLE,
+}
+
+impl SessionServer {
+    /// The server the caller is running on. No handle is opened.
+    pub fn local() -> Self {
+        Self::local_with(NativeSessions)
+    }
+
+    /// Opens the Terminal Services server on the machine called `name`.
+    pub fn open(name: &str) -> Result<Self, WIN32_ERROR> {
+        Self::open_with(NativeSessions, name)
+    }
+}
+
+impl<B: SessionBackend> SessionServer<B> {
+    pub fn local_with(backend: B) -> Self {
+        Self {
+            backend,
+            handle: WINSTATION_CURRENT_SERVER,
+        }
+    }
+
+    pub fn open_with(backend: B, name: &str) -> Result<Self, WIN32_ERROR> {
+        let handle = backend.open_server(name)?;
+        Ok(Self { backend, handle })
+    }
+
+    pub fn handle(&self) -> HANDLE {
+        self.handle
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Checks that the server is reachable.
+    pub fn ping(&self) -> Result<(), WIN32_ERROR> {
+        self.backend.ping(self.handle)
+    }
+
+    pub fn sessions(&self) -> Result<Vec<SessionEntry>, WIN32_ERROR> {
+        self.backend.enumerate(self.handle)
+    }
+
+    /// Queries `T` for `session_id`, which may be
+    /// [`WINSTATION_CURRENT_SESSION`].
+    pub fn query<T: SessionInformation>(&self, session_id: u32) -> Result<T, WIN32_ERROR> {
+        let mut raw = Box::<T::Raw>::default();
+        // The class structures hold only integers and arrays of them, with
+        // enumerations as `LONG`s, so any bytes the server writes are valid.
+        let buffer = unsafe {
+            std::slice::from_raw_parts_mut(
+                ptr::addr_of_mut!(*raw).cast::<u8>(),
+                mem::size_of::<T::Raw>(),
+            )
+        };
+        self.backend
+            .query(self.handle, session_id, T::CLASS, buffer)?;
+        T::decode(&raw)
+    }
+
+    pub fn session_name(&self, session_id: u32) -> Result<String, WIN32_ERROR> {
+        self.backend.session_name(self.handle, session_id)
+    }
+
+    pub fn session_id(&self, name: &str) -> Result<u32, WIN32_ERROR> {
+        self.backend.session_id(self.handle, name)
+    }
+
+    /// Shows a message box in `session_id` and returns the button pressed,
+    /// an `ID*` value, or `IDTIMEOUT`. `style` takes `MB_*` flags and
+    /// `timeout` is in seconds, zero waiting forever. With `wait` false the
+    /// call returns `IDASYNC` immediately.
+    pub fn send_message(
+        &self,
+        session_id: u32,
+        title: &str,
+        message: &str,
+        style: u32,
+        timeout: u32,
+        wait: bool,
+    ) -> Result<u32, WIN32_ERROR> {
+        self.backend.send_message(
+            self.handle,
+            session_id,
+            title,
+            message,
+            style,
+            timeout,
+            wait,
+        )
+    }
+
+    /// Connects `session_id` to `target_session_id`, the current session if
+    /// [`WINSTATION_CURRENT_SESSION`].
+    pub fn connect(
+        &self,
+        session_id: u32,
+        target_session_id: u32,
+        password: &str,
+        wait: bool,
+    ) -> Result<(), WIN32_ERROR> {
+        self.backend
+            .connect(self.handle, session_id, target_session_id, password, wait)
+    }
+
+    pub fn disconnect(&self, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+        self.backend.disconnect(self.handle, session_id, wait)
+    }
+
+    /// Resets the session, ending its logon and everything running in it.
+    pub fn reset(&self, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+        self.backend.reset(self.handle, session_id, wait)
+    }
+
+    /// Logs the session off and waits for it to finish, which is a reset as
+    /// `WTSLogoffSession` does it.
+    pub fn logoff(&self, session_id: u32) -> Result<(), WIN32_ERROR> {
+        self.reset(session_id, true)
+    }
+
+    /// Starts shadowing `target_session_id` on `target_server`, or this
+    /// server, from the current session. `hotkey_vk` with `hotkey_modifiers`
+    /// (`KBD*` flags) ends the shadow.
+    pub fn shadow(
+        &self,
+        target_server: Option<&str>,
+        target_session_id: u32,
+        hotkey_vk: u8,
+        hotkey_modifiers: u16,
+    ) -> Result<(), WIN32_ERROR> {
+        self.backend.shadow(
+            self.handle,
+            target_server,
+            target_session_id,
+            hotkey_vk,
+            hotkey_modifiers,
+        )
+    }
+
+    pub fn stop_shadow(&self, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+        self.backend.stop_shadow(self.handle, session_id, wait)
+    }
+}
+
+impl<B: SessionBackend> Drop for SessionServer<B> {
+    fn drop(&mut self) {
+        if !self.handle.is_invalid() {
+            self.backend.close_server(self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_CTX_WINSTATION_NOT_FOUND};
+
+    use super::*;
+    use crate::buffer::to_wide_array;
+
+    #[derive(Debug, Default)]
+    struct Mock {
+        calls: RefCell<Vec<String>>,
+        information: WINSTATIONINFORMATION,
+    }
+
+    impl Mock {
+        fn record(&self, call: String) {
+            self.calls.borrow_mut().push(call);
+        }
+    }
+
+    impl SessionBackend for &Mock {
+        fn open_server(&self, name: &str) -> Result<HANDLE, WIN32_ERROR> {
+            self.record(format!("open {name}"));
+            match name {
+                "missing" => Err(ERROR_ACCESS_DENIED),
+                _ => Ok(HANDLE(0x40)),
+            }
+        }
+
+        fn close_server(&self, server: HANDLE) {
+            self.record(format!("close {:x}", server.0));
+        }
+
+        fn ping(&self, server: HANDLE) -> Result<(), WIN32_ERROR> {
+            self.record(format!("ping {:x}", server.0));
+            Ok(())
+        }
+
+        fn enumerate(&self, _: HANDLE) -> Result<Vec<SessionEntry>, WIN32_ERROR> {
+            let mut console = SESSIONIDW {
+                WinStationName: to_wide_array("Console"),
+                State: WINSTATIONSTATECLASS::State_Active as i32,
+                ..Default::default()
+            };
+            unsafe { *console.Anonymous1.SessionId.as_mut() = 1 };
+            Ok(vec![SessionEntry::try_from(&console)?])
+        }
+
+        fn query(
+            &self,
+            server: HANDLE,
+            session_id: u32,
+            class: WINSTATIONINFOCLASS,
+            buffer: &mut [u8],
+        ) -> Result<(), WIN32_ERROR> {
+            self.record(format!(
+                "query {:x} {session_id} {class:?} {}",
+                server.0,
+                buffer.len()
+            ));
+            if class != WINSTATIONINFOCLASS::WinStationInformation {
+                return Err(ERROR_CTX_WINSTATION_NOT_FOUND);
+            }
+            let raw = unsafe {
+                std::slice::from_raw_parts(
+                    ptr::addr_of!(self.information).cast::<u8>(),
+                    mem::size_of::<WINSTATIONINFORMATION>(),
+                )
+            };
+            buffer.copy_from_slice(raw);
+            Ok(())
+        }
+
+        fn session_name(&self, _: HANDLE, session_id: u32) -> Result<String, WIN32_ERROR> {
+            Ok(format!("RDP-Tcp#{session_id}"))
+        }
+
+        fn session_id(&self, _: HANDLE, name: &str) -> Result<u32, WIN32_ERROR> {
+            self.record(format!("session_id {name}"));
+            Ok(3)
+        }
+
+        fn send_message(
+            &self,
+            _: HANDLE,
+            session_id: u32,
+            title: &str,
+            message: &str,
+            style: u32,
+            timeout: u32,
+            wait: bool,
+        ) -> Result<u32, WIN32_ERROR> {
+            self.record(format!(
+                "message {session_id} {title}: {message} {style} {timeout} {wait}"
+            ));
+            Ok(1)
+        }
+
+        fn connect(
+            &self,
+            _: HANDLE,
+            session_id: u32,
+            target_session_id: u32,
+            _: &str,
+            wait: bool,
+        ) -> Result<(), WIN32_ERROR> {
+            self.record(format!("connect {session_id} {target_session_id} {wait}"));
+            Ok(())
+        }
+
+        fn disconnect(&self, _: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+            self.record(format!("disconnect {session_id} {wait}"));
+            Ok(())
+        }
+
+        fn reset(&self, _: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+            self.record(format!("reset {session_id} {wait}"));
+            Ok(())
+        }
+
+        fn shadow(
+            &self,
+            _: HANDLE,
+            target_server: Option<&str>,
+            target_session_id: u32,
+            hotkey_vk: u8,
+            hotkey_modifiers: u16,
+        ) -> Result<(), WIN32_ERROR> {
+            self.record(format!(
+                "shadow {target_server:?} {target_session_id} {hotkey_vk} {hotkey_modifiers}"
+            ));
+            Ok(())
+        }
+
+        fn stop_shadow(&self, _: HANDLE, session_id: u32, wait: bool) -> Result<(), WIN32_ERROR> {
+            self.record(format!("stop_shadow {session_id} {wait}"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn information_layout() {
+        assert_eq!(mem::offset_of!(WINSTATIONINFORMATION, WinStationName), 4);
+        assert_eq!(mem::offset_of!(WINSTATIONINFORMATION, LogonId), 72);
+        assert_eq!(mem::offset_of!(WINSTATIONINFORMATION, ConnectTime), 80);
+        assert_eq!(mem::offset_of!(WINSTATIONINFORMATION, LogonTime), 104);
+        assert_eq!(mem::offset_of!(WINSTATIONINFORMATION, Status), 112);
+        let domain = 112 + mem::size_of::<PROTOCOLSTATUS>();
+        assert_eq!(mem::offset_of!(WINSTATIONINFORMATION, Domain), domain);
+        assert_eq!(
+            mem::offset_of!(WINSTATIONINFORMATION, UserName),
+            domain + 36
+        );
+        assert_eq!(
+            mem::offset_of!(WINSTATIONINFORMATION, CurrentTime),
+            (domain + 36 + 42).next_multiple_of(8)
+        );
+
+        let config = WINSTATIONCONFIG::default();
+        assert_eq!(config.Comment.len(), 61);
+        assert_eq!(mem::offset_of!(WINSTATIONCONFIG, Comment), 0);
+        assert_eq!(mem::offset_of!(WINSTATIONCONFIG, User), 124);
+        let oem_id = 124 + mem::size_of::<USERCONFIG>();
+        assert_eq!(mem::offset_of!(WINSTATIONCONFIG, OEMId), oem_id);
+        assert_eq!(
+            mem::size_of::<WINSTATIONCONFIG>(),
+            (oem_id + 4).next_multiple_of(mem::align_of::<USERCONFIG>())
+        );
+    }
+
+    #[test]
+    fn decodes_connect_info() {
+        let information = WINSTATIONINFORMATION {
+            ConnectState: WINSTATIONSTATECLASS::State_Disconnected as i32,
+            WinStationName: to_wide_array("RDP-Tcp#0"),
+            LogonId: 2,
+            Domain: to_wide_array("CONTOSO"),
+            UserName: to_wide_array("bob"),
+            ..Default::default()
+        };
+        let info = ConnectInfo::decode(&information).unwrap();
+        assert_eq!(info.session_id, 2);
+        assert_eq!(info.state, SessionState::Disconnected);
+        assert_eq!(info.name, "RDP-Tcp#0");
+        assert_eq!(
+            (info.domain.as_str(), info.user_name.as_str()),
+            ("CONTOSO", "bob")
+        );
+        // A state newer than this crate is an error rather than an invalid
+        // enumeration value.
+        let information = WINSTATIONINFORMATION {
+            ConnectState: 10,
+            ..Default::default()
+        };
+        assert_eq!(ConnectInfo::decode(&information), Err(ERROR_INVALID_DATA));
+    }
+
+    #[test]
+    fn decodes_client_and_user_config() {
+        let mut client = WINSTATIONCLIENT::default();
+        client.ClientName = to_wide_array("LAPTOP");
+        client.HRes = 1920;
+        client.set_fMouse(1);
+        client.set_fUsingSavedCreds(1);
+        let info = ClientInfo::decode(&client).unwrap();
+        assert_eq!(info.client_name, "LAPTOP");
+        assert!(info.flags.mouse && info.flags.using_saved_creds && !info.flags.text_only);
+        assert_eq!(info.horizontal_resolution, 1920);
+
+        let mut config = WINSTATIONCONFIG {
+            Comment: to_wide_array("hello"),
+            ..Default::default()
+        };
+        config.User.set_fDisableClip(1);
+        config.User.set_ColorDepth(3);
+        config.User.MaxIdleTime = 60000;
+        config.User.Shadow = SHADOWCLASS::Shadow_EnableInputNotify as i32;
+        let decoded = UserConfig::decode(&config).unwrap();
+        assert!(decoded.flags.disable_clipboard && !decoded.flags.disable_exe);
+        assert_eq!(decoded.color_depth, 3);
+        assert_eq!(decoded.max_idle_time, 60000);
+        assert_eq!(decoded.comment, "hello");
+        assert_eq!(decoded.callback, CALLBACKCLASS::Callback_Disable);
+        assert_eq!(decoded.shadow, SHADOWCLASS::Shadow_EnableInputNotify);
+        config.User.Callback = 3;
+        assert!(UserConfig::decode(&config).is_err());
+        config.User.Callback = 0;
+        config.User.Shadow = -1;
+        assert!(UserConfig::decode(&config).is_err());
+    }
+
+    #[test]
+    fn decodes_remote_addresses() {
+        let mut raw = WINSTATIONREMOTEADDRESS {
+            sin_family: AF_INET,
+            ..Default::default()
+        };
+        unsafe {
+            let ipv4 = raw.Anonymous1.ipv4.as_mut();
+            ipv4.sin_port = 3389u16.to_be();
+            ipv4.sin_addr = u32::from_ne_bytes([10, 0, 0, 5]);
+        }
+        assert_eq!(
+            RemoteAddress::decode(&raw).unwrap().0,
+            Some("10.0.0.5:3389".parse().unwrap())
+        );
+
+        let mut raw = WINSTATIONREMOTEADDRESS {
+            sin_family: AF_INET6,
+            ..Default::default()
+        };
+        unsafe {
+            let ipv6 = raw.Anonymous1.ipv6.as_mut();
+            ipv6.sin6_port = 443u16.to_be();
+            ipv6.sin6_addr = [0xfe80u16.to_be(), 0, 0, 0, 0, 0, 0, 1u16.to_be()];
+        }
+        assert_eq!(
+            RemoteAddress::decode(&raw).unwrap().0,
+            Some("[fe80::1]:443".parse().unwrap())
+        );
+        assert_eq!(RemoteAddress::decode(&Default::default()).unwrap().0, None);
+    }
+
+    #[test]
+    fn queries_through_the_backend() {
+        let mock = Mock {
+            information: WINSTATIONINFORMATION {
+                LogonId: 5,
+                UserName: to_wide_array("alice"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let server = SessionServer::open_with(&mock, "host").unwrap();
+        assert_eq!(server.handle(), HANDLE(0x40));
+        let sessions = server.sessions().unwrap();
+        assert_eq!(
+            sessions,
+            [SessionEntry {
+                session_id: 1,
+                name: "Console".into(),
+                state: SessionState::Active,
+            }]
+        );
+        let info = server.query::<ConnectInfo>(5).unwrap();
+        assert_eq!((info.session_id, info.user_name.as_str()), (5, "alice"));
+        assert_eq!(
+            server.query::<ClientInfo>(5).unwrap_err(),
+            ERROR_CTX_WINSTATION_NOT_FOUND
+        );
+        drop(server);
+        assert_eq!(
+            *mock.calls.borrow(),
+            [
+                "open host".to_string(),
+                format!(
+                    "query 40 5 WinStationInformation {}",
+                    mem::size_of::<WINSTATIONINFORMATION>()
+                ),
+                format!(
+                    "query 40 5 WinStationClient {}",
+                    mem::size_of::<WINSTATIONCLIENT>()
+                ),
+                "close 40".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn closes_only_opened_servers() {
+        let mock = Mock::default();
+        let server = SessionServer::local_with(&mock);
+        assert_eq!(server.handle(), WINSTATION_CURRENT_SERVER);
+        server.ping().unwrap();
+        drop(server);
+        assert_eq!(
+            SessionServer::open_with(&mock, "missing").unwrap_err(),
+            ERROR_ACCESS_DENIED
+        );
+        assert_eq!(*mock.calls.borrow(), ["ping 0", "open missing"]);
+    }
+
+    #[test]
+    fn forwards_session_control() {
+        let mock = Mock::default();
+        let server = SessionServer::local_with(&mock);
+        assert_eq!(server.session_name(2).unwrap(), "RDP-Tcp#2");
+        assert_eq!(server.session_id("RDP-Tcp#3").unwrap(), 3);
+        assert_eq!(
+            server
+                .send_message(2, "Title", "Body", 0x40, 30, false)
+                .unwrap(),
+            1
+        );
+        server
+            .connect(2, WINSTATION_CURRENT_SESSION, "", true)
+            .unwrap();
+        server.disconnect(2, false).unwrap();
+        server.logoff(2).unwrap();
+        server.shadow(Some("other"), 4, 0x6a, 2).unwrap();
+        server.stop_shadow(4, true).unwrap();
+        assert_eq!(
+            *mock.calls.borrow(),
+            [
+                "session_id RDP-Tcp#3",
+                "message 2 Title: Body 64 30 false",
+                "connect 2 4294967295 true",
+                "disconnect 2 false",
+                "reset 2 true",
+                "shadow Some(\"other\") 4 106 2",
+                "stop_shadow 4 true",
+            ]
+        );
+    }
+}
@@ -0,0 +1,857 @@
+//! Physical memory queries over `SystemSuperfetchInformation`.
+//!
+//! Every request is a [`SUPERFETCH_INFORMATION`] header carrying
+//! [`SUPERFETCH_INFORMATION_VERSION`], the `kuhC` magic and a pointer to a
+//! class specific, itself versioned, buffer. [`superfetch_information`] and
+//! the `*_request` functions build those, and a [`SuperfetchBackend`] issues
+//! them; [`NativeSuperfetch`] does so through `NtQuerySystemInformation`,
+//! which requires `SeProfileSingleProcessPrivilege`.
+//!
+//! ```no_run
+//! use windows_native::superfetch::{
+//!     physical_ranges, query_ranges, NativeSuperfetch, PageList, PfnSummary,
+//! };
+//!
+//! let ranges = physical_ranges(&NativeSuperfetch)?;
+//! let mut summary = PfnSummary::default();
+//! for batch in query_ranges(&NativeSuperfetch, &ranges) {
+//!     batch?.iter().for_each(|entry| summary.add(entry));
+//! }
+//! let active = summary.per_list.get(&PageList::Active).copied().unwrap_or(0);
+//! println!("{active} of {} pages active", summary.pages);
+//! # Ok::<(), windows_native::system::Error>(())
+//! ```
+
+use std::{collections::BTreeMap, ffi::c_void, mem, ops::Range, ptr, slice};
+
+use windows::{
+    Wdk::System::SystemInformation::{
+        NtQuerySystemInformation, SYSTEM_INFORMATION_CLASS as INFORMATION_CLASS,
+    },
+    Win32::Foundation::{NTSTATUS, STATUS_BUFFER_TOO_SMALL, STATUS_INFO_LENGTH_MISMATCH},
+};
+
+use crate::{
+    buffer::{DecodeError, Reader},
+    check,
+    memory::PAGE_SIZE,
+    ntexapi::SYSTEM_INFORMATION_CLASS,
+    ntmmapi::{
+        MMPFN_IDENTITY, MMPFNLIST_ACTIVE, MMPFNLIST_BAD, MMPFNLIST_FREE, MMPFNLIST_MODIFIED,
+        MMPFNLIST_MODIFIEDNOWRITE, MMPFNLIST_STANDBY, MMPFNLIST_TRANSITION, MMPFNLIST_ZERO,
+        MMPFNUSE_AWEPAGE, MMPFNUSE_DRIVERLOCKPAGE, MMPFNUSE_FILE, MMPFNUSE_KERNELSTACK,
+        MMPFNUSE_METAFILE, MMPFNUSE_NONPAGEDPOOL, MMPFNUSE_PAGEDPOOL, MMPFNUSE_PAGEFILEMAPPED,
+        MMPFNUSE_PAGETABLE, MMPFNUSE_PROCESSPRIVATE, MMPFNUSE_SESSIONPRIVATE, MMPFNUSE_SYSTEMPTE,
+    },
+    ntpfapi::{
+        PF_MEMORY_LIST_INFO, PF_MEMORY_LIST_INFO_VERSION, PF_MEMORY_LIST_NODE, PF_PFN_PRIO_REQUEST,
+        PF_PFN_PRIO_REQUEST_VERSION, PF_PHYSICAL_MEMORY_RANGE, PF_PHYSICAL_MEMORY_RANGE_INFO_V1,
+        PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION, PF_PHYSICAL_MEMORY_RANGE_INFO_V2,
+        PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION, SUPERFETCH_INFORMATION,
+        SUPERFETCH_INFORMATION_CLASS, SUPERFETCH_INFORMATION_MAGIC, SUPERFETCH_INFORMATION_VERSION,
+    },
+    system::Error,
+};
+
+/// [`SUPERFETCH_INFORMATION_MAGIC`] as stored in
+/// `SUPERFETCH_INFORMATION::Magic`.
+pub const SUPERFETCH_MAGIC: u32 = u32::from_le_bytes(*SUPERFETCH_INFORMATION_MAGIC);
+
+/// Page frames described by a single `SuperfetchPfnQuery`, the capacity of
+/// `PF_PFN_PRIO_REQUEST::PageData`.
+pub const PFN_BATCH: usize = 256;
+
+/// Largest buffer a query is grown to before giving up, in bytes.
+const MAX_BUFFER: usize = 0x100_0000;
+
+/// The header for a query of `class` over `buffer`.
+///
+/// The header points into `buffer`, which has to outlive it.
+pub fn superfetch_information(
+    class: SUPERFETCH_INFORMATION_CLASS,
+    buffer: &mut [u64],
+) -> SUPERFETCH_INFORMATION {
+    SUPERFETCH_INFORMATION {
+        Version: SUPERFETCH_INFORMATION_VERSION,
+        Magic: SUPERFETCH_MAGIC,
+        SuperfetchInformationClass: class,
+        SuperfetchInformation: buffer.as_mut_ptr().cast(),
+        SuperfetchInformationLength: mem::size_of_val(buffer) as u32,
+    }
+}
+
+/// A `SuperfetchPfnQuery` request for up to [`PFN_BATCH`] page frames.
+///
+/// # Panics
+///
+/// If `pfns` holds more than [`PFN_BATCH`] entries.
+pub fn pfn_request(pfns: &[usize], flags: u32) -> Box<PF_PFN_PRIO_REQUEST> {
+    assert!(
+        pfns.len() <= PFN_BATCH,
+        "at most {PFN_BATCH} PFNs per request"
+    );
+    let mut request = Box::<PF_PFN_PRIO_REQUEST>::default();
+    request.Version = PF_PFN_PRIO_REQUEST_VERSION;
+    request.RequestFlags = flags;
+    request.PfnCount = pfns.len();
+    for (identity, &pfn) in request.PageData.iter_mut().zip(pfns) {
+        identity.PageFrameIndex = pfn;
+    }
+    request
+}
+
+/// A `SuperfetchMemoryRangesQuery` request of `bytes` bytes, in the
+/// `PF_PHYSICAL_MEMORY_RANGE_INFO` layout of `version`.
+pub fn ranges_request(version: u32, bytes: usize) -> Vec<u64> {
+    let minimum = match version {
+        PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION => {
+            mem::size_of::<PF_PHYSICAL_MEMORY_RANGE_INFO_V1>()
+        }
+        _ => mem::size_of::<PF_PHYSICAL_MEMORY_RANGE_INFO_V2>(),
+    };
+    let mut buffer = vec![0u64; bytes.max(minimum).div_ceil(8)];
+    // Version is the first field of both layouts.
+    buffer[0] = version as u64;
+    buffer
+}
+
+/// A `SuperfetchMemoryListQuery` request of `bytes` bytes.
+pub fn memory_list_request(bytes: usize) -> Vec<u64> {
+    let mut buffer = vec![0u64; bytes.max(mem::size_of::<PF_MEMORY_LIST_INFO>()).div_ceil(8)];
+    let info = buffer.as_mut_ptr().cast::<PF_MEMORY_LIST_INFO>();
+    unsafe {
+        (*info).Version = PF_MEMORY_LIST_INFO_VERSION;
+        (*info).Size = mem::size_of_val(buffer.as_slice()) as u32;
+    }
+    buffer
+}
+
+/// Issues Superfetch queries.
+pub trait SuperfetchBackend {
+    /// Runs a query of `class` with `buffer` holding the request on input
+    /// and the result on output.
+    fn query(
+        &self,
+        class: SUPERFETCH_INFORMATION_CLASS,
+        buffer: &mut [u64],
+    ) -> Result<(), NTSTATUS>;
+}
+
+/// The [`SuperfetchBackend`] that queries the running system.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeSuperfetch;
+
+impl SuperfetchBackend for NativeSuperfetch {
+    fn query(
+        &self,
+        class: SUPERFETCH_INFORMATION_CLASS,
+        buffer: &mut [u64],
+    ) -> Result<(), NTSTATUS> {
+        let mut information = superfetch_information(class, buffer);
+        let mut return_length = 0;
+        let status = unsafe {
+            NtQuerySystemInformation(
+                INFORMATION_CLASS(SYSTEM_INFORMATION_CLASS::SystemSuperfetchInformation as i32),
+                ptr::addr_of_mut!(information).cast::<c_void>(),
+                mem::size_of::<SUPERFETCH_INFORMATION>() as u32,
+                &mut return_length,
+            )
+        };
+        check(status)
+    }
+}
+
+/// A run of physical pages.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PhysicalRange {
+    pub base_pfn: usize,
+    pub page_count: usize,
+}
+
+impl PhysicalRange {
+    /// Physical address of the first page.
+    pub fn base(&self) -> u64 {
+        self.base_pfn as u64 * PAGE_SIZE as u64
+    }
+
+    pub fn size(&self) -> u64 {
+        self.page_count as u64 * PAGE_SIZE as u64
+    }
+
+    pub fn pfns(&self) -> Range<usize> {
+        self.base_pfn..self.base_pfn.saturating_add(self.page_count)
+    }
+}
+
+impl From<&PF_PHYSICAL_MEMORY_RANGE> for PhysicalRange {
+    fn from(value: &PF_PHYSICAL_MEMORY_RANGE) -> Self {
+        Self {
+            base_pfn: value.BasePfn,
+            page_count: value.PageCount,
+        }
+    }
+}
+
+fn as_bytes(buffer: &[u64]) -> &[u8] {
+    unsafe { slice::from_raw_parts(buffer.as_ptr().cast(), mem::size_of_val(buffer)) }
+}
+
+/// The physical memory ranges known to the memory manager.
+///
+/// Asks for the version 2 layout first and falls back to version 1 on
+/// systems that reject it.
+pub fn physical_ranges(backend: &impl SuperfetchBackend) -> Result<Vec<PhysicalRange>, Error> {
+    query_ranges_version(backend, PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION)
+        .or_else(|_| query_ranges_version(backend, PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION))
+}
+
+fn query_ranges_version(
+    backend: &impl SuperfetchBackend,
+    version: u32,
+) -> Result<Vec<PhysicalRange>, Error> {
+    let mut bytes = 0x400;
+    loop {
+        let mut buffer = ranges_request(version, bytes);
+        match backend.query(
+            SUPERFETCH_INFORMATION_CLASS::SuperfetchMemoryRangesQuery,
+            &mut buffer,
+        ) {
+            Ok(()) => return Ok(decode_ranges(version, as_bytes(&buffer))?),
+            Err(STATUS_BUFFER_TOO_SMALL | STATUS_INFO_LENGTH_MISMATCH) if bytes < MAX_BUFFER => {
+                bytes *= 2
+            }
+            Err(status) => return Err(status.into()),
+        }
+    }
+}
+
+/// Decodes a completed `SuperfetchMemoryRangesQuery` buffer in the
+/// `PF_PHYSICAL_MEMORY_RANGE_INFO` layout of `version`.
+pub fn decode_ranges(version: u32, bytes: &[u8]) -> Result<Vec<PhysicalRange>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let (count, offset) = match version {
+        PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION => {
+            reader.skip(mem::offset_of!(
+                PF_PHYSICAL_MEMORY_RANGE_INFO_V1,
+                RangeCount
+            ))?;
+            (
+                reader.u32()? as usize,
+                mem::offset_of!(PF_PHYSICAL_MEMORY_RANGE_INFO_V1, Ranges),
+            )
+        }
+        _ => {
+            reader.skip(mem::offset_of!(
+                PF_PHYSICAL_MEMORY_RANGE_INFO_V2,
+                RangeCount
+            ))?;
+            (
+                reader.u32()? as usize,
+                mem::offset_of!(PF_PHYSICAL_MEMORY_RANGE_INFO_V2, Ranges),
+            )
+        }
+    };
+    reader.seek(offset)?;
+    reader.check_count(count, mem::size_of::<PF_PHYSICAL_MEMORY_RANGE>())?;
+    (0..count)
+        .map(|_| {
+            Ok(PhysicalRange {
+                base_pfn: reader.usize()?,
+                page_count: reader.usize()?,
+            })
+        })
+        .collect()
+}
+
+/// Page counts of one NUMA node.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct NodeMemoryLists {
+    pub node: u8,
+    /// Standby pages at low, medium and high priority.
+    pub standby: [u64; 3],
+    pub free: u64,
+    pub modified: u64,
+}
+
+impl From<&PF_MEMORY_LIST_NODE> for NodeMemoryLists {
+    fn from(value: &PF_MEMORY_LIST_NODE) -> Self {
+        Self {
+            node: value.Node() as u8,
+            standby: [
+                value.StandbyLowPageCount,
+                value.StandbyMediumPageCount,
+                value.StandbyHighPageCount,
+            ],
+            free: value.FreePageCount,
+            modified: value.ModifiedPageCount,
+        }
+    }
+}
+
+/// Per node page list counts.
+pub fn memory_lists(backend: &impl SuperfetchBackend) -> Result<Vec<NodeMemoryLists>, Error> {
+    let mut bytes = 0x800;
+    loop {
+        let mut buffer = memory_list_request(bytes);
+        match backend.query(
+            SUPERFETCH_INFORMATION_CLASS::SuperfetchMemoryListQuery,
+            &mut buffer,
+        ) {
+            Ok(()) => break Ok(decode_memory_lists(as_bytes(&buffer))?),
+            Err(STATUS_BUFFER_TOO_SMALL | STATUS_INFO_LENGTH_MISMATCH) if bytes < MAX_BUFFER => {
+                bytes *= 2
+            }
+            Err(status) => break Err(status.into()),
+        }
+    }
+}
+
+/// Decodes a completed `SuperfetchMemoryListQuery` buffer.
+pub fn decode_memory_lists(bytes: &[u8]) -> Result<Vec<NodeMemoryLists>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    reader.skip(mem::offset_of!(PF_MEMORY_LIST_INFO, NodeCount))?;
+    let count = reader.u32()? as usize;
+    reader.seek(mem::offset_of!(PF_MEMORY_LIST_INFO, Nodes))?;
+    reader.check_count(count, mem::size_of::<PF_MEMORY_LIST_NODE>())?;
+    (0..count)
+        .map(|_| {
+            // Node is the low byte of the first word, the rest is spare.
+            let node = reader.u64()? as u8;
+            let standby = [reader.u64()?, reader.u64()?, reader.u64()?];
+            Ok(NodeMemoryLists {
+                node,
+                standby,
+                free: reader.u64()?,
+                modified: reader.u64()?,
+            })
+        })
+        .collect()
+}
+
+/// The list a physical page is on, `MMPFNLIST_*`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PageList {
+    Zero,
+    Free,
+    Standby,
+    Modified,
+    ModifiedNoWrite,
+    Bad,
+    Active,
+    Transition,
+}
+
+impl From<u32> for PageList {
+    fn from(value: u32) -> Self {
+        match value & 7 {
+            MMPFNLIST_ZERO => Self::Zero,
+            MMPFNLIST_FREE => Self::Free,
+            MMPFNLIST_STANDBY => Self::Standby,
+            MMPFNLIST_MODIFIED => Self::Modified,
+            MMPFNLIST_MODIFIEDNOWRITE => Self::ModifiedNoWrite,
+            MMPFNLIST_BAD => Self::Bad,
+            MMPFNLIST_ACTIVE => Self::Active,
+            MMPFNLIST_TRANSITION => Self::Transition,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// What a physical page is used for, `MMPFNUSE_*`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PageUse {
+    ProcessPrivate,
+    File,
+    PagefileMapped,
+    PageTable,
+    PagedPool,
+    NonPagedPool,
+    SystemPte,
+    SessionPrivate,
+    Metafile,
+    AwePage,
+    DriverLockPage,
+    KernelStack,
+    Unknown(u8),
+}
+
+impl From<u32> for PageUse {
+    fn from(value: u32) -> Self {
+        match value {
+            MMPFNUSE_PROCESSPRIVATE => Self::ProcessPrivate,
+            MMPFNUSE_FILE => Self::File,
+            MMPFNUSE_PAGEFILEMAPPED => Self::PagefileMapped,
+            MMPFNUSE_PAGETABLE => Self::PageTable,
+            MMPFNUSE_PAGEDPOOL => Self::PagedPool,
+            MMPFNUSE_NONPAGEDPOOL => Self::NonPagedPool,
+            MMPFNUSE_SYSTEMPTE => Self::SystemPte,
+            MMPFNUSE_SESSIONPRIVATE => Self::SessionPrivate,
+            MMPFNUSE_METAFILE => Self::Metafile,
+            MMPFNUSE_AWEPAGE => Self::AwePage,
+            MMPFNUSE_DRIVERLOCKPAGE => Self::DriverLockPage,
+            MMPFNUSE_KERNELSTACK => Self::KernelStack,
+            other => Self::Unknown(other as u8),
+        }
+    }
+}
+
+/// One physical page as described by `SuperfetchPfnQuery`.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct PfnEntry {
+    pub pfn: usize,
+    pub list: PageList,
+    pub usage: PageUse,
+    /// Memory priority, 0 to 7.
+    pub priority: u8,
+    pub cold: bool,
+    pub pinned: bool,
+    pub non_tradeable: bool,
+    /// The `UniqueProcessKey` of the owning process, for process private
+    /// pages.
+    pub process_key: Option<u64>,
+    /// Where the page is mapped in its owner, for process private pages.
+    pub virtual_address: Option<usize>,
+}
+
+impl PfnEntry {
+    pub fn address(&self) -> u64 {
+        self.pfn as u64 * PAGE_SIZE as u64
+    }
+}
+
+impl From<&MMPFN_IDENTITY> for PfnEntry {
+    fn from(value: &MMPFN_IDENTITY) -> Self {
+        let frame = unsafe { value.u1.e1.as_ref() };
+        let usage = PageUse::from(frame.UseDescription() as u32);
+        let (process_key, virtual_address) = if usage == PageUse::ProcessPrivate {
+            unsafe {
+                (
+                    Some(value.u1.e4.as_ref().UniqueProcessKey()),
+                    Some(*value.u2.VirtualAddress.as_ref()),
+                )
+            }
+        } else {
+            (None, None)
+        };
+        Self {
+            pfn: value.PageFrameIndex,
+            list: PageList::from(frame.ListDescription() as u32),
+            usage,
+            priority: frame.Priority() as u8,
+            cold: frame.Cold() != 0,
+            pinned: frame.Pinned() != 0,
+            non_tradeable: frame.NonTradeable() != 0,
+            process_key,
+            virtual_address,
+        }
+    }
+}
+
+fn query_batch(
+    backend: &impl SuperfetchBackend,
+    batch: &[usize],
+) -> Result<Vec<PfnEntry>, NTSTATUS> {
+    let mut request = pfn_request(batch, 0);
+    let words = unsafe {
+        slice::from_raw_parts_mut(
+            ptr::addr_of_mut!(*request).cast::<u64>(),
+            mem::size_of::<PF_PFN_PRIO_REQUEST>() / 8,
+        )
+    };
+    backend.query(SUPERFETCH_INFORMATION_CLASS::SuperfetchPfnQuery, words)?;
+    let count = request.PfnCount.min(batch.len());
+    Ok(request.PageData[..count]
+        .iter()
+        .map(PfnEntry::from)
+        .collect())
+}
+
+/// Describes each of `pfns`, [`PFN_BATCH`] frames per query.
+pub fn query_pfns(
+    backend: &impl SuperfetchBackend,
+    pfns: &[usize],
+) -> Result<Vec<PfnEntry>, NTSTATUS> {
+    let mut entries = Vec::with_capacity(pfns.len());
+    for batch in pfns.chunks(PFN_BATCH) {
+        entries.extend(query_batch(backend, batch)?);
+    }
+    Ok(entries)
+}
+
+/// Describes every page of `ranges`, one query of up to [`PFN_BATCH`] frames
+/// per item. See [`query_ranges`].
+#[derive(Debug, Clone)]
+pub struct RangePfns<'a, B> {
+    backend: &'a B,
+    ranges: slice::Iter<'a, PhysicalRange>,
+    pfns: Range<usize>,
+    failed: bool,
+}
+
+impl<B: SuperfetchBackend> Iterator for RangePfns<'_, B> {
+    type Item = Result<Vec<PfnEntry>, NTSTATUS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mut batch = Vec::with_capacity(PFN_BATCH);
+        while batch.len() < PFN_BATCH {
+            if let Some(pfn) = self.pfns.next() {
+                batch.push(pfn);
+            } else if let Some(range) = self.ranges.next() {
+                self.pfns = range.pfns();
+            } else {
+                break;
+            }
+        }
+        if batch.is_empty() {
+            return None;
+        }
+        let entries = query_batch(self.backend, &batch);
+        self.failed = entries.is_err();
+        Some(entries)
+    }
+}
+
+/// Describes every page of `ranges`, querying a batch of page frames at a
+/// time as the iterator is advanced. Iteration stops after the first error.
+pub fn query_ranges<'a, B: SuperfetchBackend>(
+    backend: &'a B,
+    ranges: &'a [PhysicalRange],
+) -> RangePfns<'a, B> {
+    RangePfns {
+        backend,
+        ranges: ranges.iter(),
+        pfns: 0..0,
+        failed: false,
+    }
+}
+
+/// Page counts over a set of [`PfnEntry`]s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PfnSummary {
+    pub pages: usize,
+    pub per_list: BTreeMap<PageList, usize>,
+    pub per_use: BTreeMap<PageUse, usize>,
+    pub per_priority: [usize; 8],
+    /// Process private pages per `UniqueProcessKey`.
+    pub per_process: BTreeMap<u64, usize>,
+}
+
+impl PfnSummary {
+    pub fn from_entries<'a>(entries: impl IntoIterator<Item = &'a PfnEntry>) -> Self {
+        let mut summary = Self::default();
+        for entry in entries {
+            summary.add(entry);
+        }
+        summary
+    }
+
+    pub fn add(&mut self, entry: &PfnEntry) {
+        self.pages += 1;
+        *self.per_list.entry(entry.list).or_default() += 1;
+        *self.per_use.entry(entry.usage).or_default() += 1;
+        self.per_priority[entry.priority as usize & 7] += 1;
+        if let Some(key) = entry.process_key {
+            *self.per_process.entry(key).or_default() += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::Foundation::STATUS_INVALID_PARAMETER;
+
+    use super::*;
+    use crate::buffer::Writer;
+
+    /// Answers range queries with two ranges in the version 1 layout and
+    /// describes page frames as process private if even and file backed if
+    /// odd, all active at priority 5. Fails PFN queries past `fail_after`.
+    #[derive(Default)]
+    struct Mock {
+        calls: RefCell<Vec<(SUPERFETCH_INFORMATION_CLASS, usize, u32)>>,
+        fail_after: Option<usize>,
+    }
+
+    impl SuperfetchBackend for Mock {
+        fn query(
+            &self,
+            class: SUPERFETCH_INFORMATION_CLASS,
+            buffer: &mut [u64],
+        ) -> Result<(), NTSTATUS> {
+            let version = buffer[0] as u32;
+            self.calls
+                .borrow_mut()
+                .push((class, mem::size_of_val(buffer), version));
+            match class {
+                SUPERFETCH_INFORMATION_CLASS::SuperfetchMemoryRangesQuery => {
+                    if version == PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION {
+                        return Err(STATUS_INVALID_PARAMETER);
+                    }
+                    if mem::size_of_val(buffer) < 0x800 {
+                        return Err(STATUS_BUFFER_TOO_SMALL);
+                    }
+                    buffer[..5].copy_from_slice(&[1 | 2 << 32, 0x10, 4, 0x100, 300]);
+                    Ok(())
+                }
+                SUPERFETCH_INFORMATION_CLASS::SuperfetchPfnQuery => {
+                    let pfn_queries = self
+                        .calls
+                        .borrow()
+                        .iter()
+                        .filter(|call| call.0 == class)
+                        .count();
+                    if self.fail_after.is_some_and(|limit| pfn_queries > limit) {
+                        return Err(STATUS_INVALID_PARAMETER);
+                    }
+                    let request =
+                        unsafe { &mut *buffer.as_mut_ptr().cast::<PF_PFN_PRIO_REQUEST>() };
+                    for identity in &mut request.PageData[..request.PfnCount] {
+                        let pfn = identity.PageFrameIndex as u64;
+                        identity.u1.union_field = (pfn % 2) | (6 << 4) | (0x42 << 9) | (5 << 57);
+                        identity.u2.union_field = 0x7ff0_0000 + pfn * 0x1000;
+                    }
+                    Ok(())
+                }
+                _ => Err(STATUS_INVALID_PARAMETER),
+            }
+        }
+    }
+
+    fn ranges_v2(flags: u32, ranges: &[(usize, usize)], count: u32) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer
+            .u32(PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION)
+            .u32(flags)
+            .u32(count)
+            .align(8);
+        for &(base_pfn, page_count) in ranges {
+            writer.u64(base_pfn as u64).u64(page_count as u64);
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn builds_requests() {
+        let mut buffer = vec![0u64; 4];
+        let header = superfetch_information(
+            SUPERFETCH_INFORMATION_CLASS::SuperfetchPfnQuery,
+            &mut buffer,
+        );
+        assert_eq!(header.Version, SUPERFETCH_INFORMATION_VERSION);
+        assert_eq!(header.Magic, 0x4368_756b);
+        assert_eq!(header.SuperfetchInformationLength, 32);
+        assert_eq!(
+            header.SuperfetchInformation as usize,
+            buffer.as_ptr() as usize
+        );
+
+        let request = pfn_request(&[5, 9, 11], 1);
+        assert_eq!(
+            (request.Version, request.RequestFlags, request.PfnCount),
+            (PF_PFN_PRIO_REQUEST_VERSION, 1, 3)
+        );
+        assert_eq!(request.PageData[2].PageFrameIndex, 11);
+        assert_eq!(mem::size_of::<PF_PFN_PRIO_REQUEST>() % 8, 0);
+
+        let buffer = ranges_request(PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION, 0);
+        assert_eq!(buffer[0], PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION as u64);
+        assert_eq!(
+            buffer.len() * 8,
+            mem::size_of::<PF_PHYSICAL_MEMORY_RANGE_INFO_V1>()
+        );
+        let buffer = memory_list_request(0);
+        assert_eq!(buffer[0] as u32, PF_MEMORY_LIST_INFO_VERSION);
+        assert_eq!(buffer[0] >> 32, (buffer.len() * 8) as u64);
+    }
+
+    #[test]
+    fn decodes_ranges() {
+        let bytes = ranges_v2(0, &[(0x10, 4), (0x100, 300)], 2);
+        assert_eq!(
+            decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION, &bytes).unwrap(),
+            [
+                PhysicalRange {
+                    base_pfn: 0x10,
+                    page_count: 4,
+                },
+                PhysicalRange {
+                    base_pfn: 0x100,
+                    page_count: 300,
+                },
+            ]
+        );
+        let v1 = [&[1, 0, 0, 0, 1, 0, 0, 0][..], &bytes[16..32]].concat();
+        assert_eq!(
+            decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION, &v1).unwrap(),
+            [PhysicalRange {
+                base_pfn: 0x10,
+                page_count: 4,
+            }]
+        );
+        let range = PhysicalRange {
+            base_pfn: 0x100,
+            page_count: 2,
+        };
+        assert_eq!((range.base(), range.size()), (0x10_0000, 0x2000));
+        assert_eq!(range.pfns(), 0x100..0x102);
+    }
+
+    #[test]
+    fn rejects_truncated_ranges() {
+        assert!(matches!(
+            decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V1_VERSION, &[]),
+            Err(DecodeError::Truncated { .. })
+        ));
+        assert!(decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION, &[2, 0, 0, 0]).is_err());
+        // A count larger than the buffer.
+        let bytes = ranges_v2(0, &[(0x10, 4)], 2);
+        assert_eq!(
+            decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION, &bytes),
+            Err(DecodeError::Truncated {
+                offset: 16,
+                needed: 32,
+            })
+        );
+        let bytes = ranges_v2(0, &[], u32::MAX);
+        assert!(decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION, &bytes).is_err());
+        // No ranges at all, with nothing after the header.
+        let bytes = ranges_v2(0, &[], 0);
+        assert_eq!(
+            decode_ranges(PF_PHYSICAL_MEMORY_RANGE_INFO_V2_VERSION, &bytes),
+            Ok(Vec::new())
+        );
+    }
+
+    #[test]
+    fn decodes_memory_lists() {
+        let mut writer = Writer::default();
+        writer
+            .u32(PF_MEMORY_LIST_INFO_VERSION)
+            .u32(0)
+            .u32(2)
+            .align(8);
+        for node in 0..2u64 {
+            writer
+                .u64(node | 0xff00)
+                .u64(10)
+                .u64(20)
+                .u64(30)
+                .u64(40 + node)
+                .u64(50);
+        }
+        let bytes = writer.into_inner();
+        let nodes = decode_memory_lists(&bytes).unwrap();
+        assert_eq!(
+            nodes,
+            [
+                NodeMemoryLists {
+                    node: 0,
+                    standby: [10, 20, 30],
+                    free: 40,
+                    modified: 50,
+                },
+                NodeMemoryLists {
+                    node: 1,
+                    standby: [10, 20, 30],
+                    free: 41,
+                    modified: 50,
+                },
+            ]
+        );
+
+        assert!(decode_memory_lists(&[]).is_err());
+        assert!(decode_memory_lists(&bytes[..8]).is_err());
+        assert_eq!(
+            decode_memory_lists(&bytes[..bytes.len() - 8]),
+            Err(DecodeError::Truncated {
+                offset: 16,
+                needed: 96,
+            })
+        );
+    }
+
+    #[test]
+    fn queries_ranges_with_fallback() {
+        let mock = Mock::default();
+        let ranges = physical_ranges(&mock).unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[1].page_count, 300);
+        let calls = mock
+            .calls
+            .borrow()
+            .iter()
+            .map(|&(_, size, version)| (size, version))
+            .collect::<Vec<_>>();
+        // Version 2 is rejected, then version 1 grows to the size needed.
+        assert_eq!(calls, [(0x400, 2), (0x400, 1), (0x800, 1)]);
+    }
+
+    #[test]
+    fn queries_pfns_lazily() {
+        let mock = Mock::default();
+        let ranges = physical_ranges(&mock).unwrap();
+        mock.calls.borrow_mut().clear();
+
+        let mut batches = query_ranges(&mock, &ranges);
+        let first = batches.next().unwrap().unwrap();
+        assert_eq!(mock.calls.borrow().len(), 1);
+        // The first batch spans both ranges.
+        assert_eq!(first.len(), PFN_BATCH);
+        assert_eq!(first[3].pfn, 0x13);
+        assert_eq!(first[4].pfn, 0x100);
+        let rest = batches.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rest.iter().map(Vec::len).collect::<Vec<_>>(), [48]);
+        assert_eq!(rest[0].last().unwrap().pfn, 0x100 + 299);
+
+        let entry = first[0];
+        assert_eq!(entry.usage, PageUse::ProcessPrivate);
+        assert_eq!(entry.list, PageList::Active);
+        assert_eq!(entry.priority, 5);
+        assert_eq!(entry.process_key, Some(0x42));
+        assert_eq!(entry.virtual_address, Some(0x7ff0_0000 + 0x10 * 0x1000));
+        assert_eq!(entry.address(), 0x10_000);
+        assert_eq!(first[1].usage, PageUse::File);
+        assert_eq!(first[1].process_key, None);
+
+        let summary = PfnSummary::from_entries(first.iter().chain(&rest[0]));
+        assert_eq!(summary.pages, 304);
+        assert_eq!(summary.per_process[&0x42], 152);
+        assert_eq!(summary.per_priority[5], 304);
+        assert_eq!(summary.per_list[&PageList::Active], 304);
+
+        assert_eq!(query_ranges(&mock, &[]).count(), 0);
+        assert_eq!(
+            query_pfns(&mock, &(0..600).collect::<Vec<_>>())
+                .unwrap()
+                .len(),
+            600
+        );
+    }
+
+    #[test]
+    fn stops_after_an_error() {
+        let mock = Mock {
+            fail_after: Some(1),
+            ..Default::default()
+        };
+        let ranges = [PhysicalRange {
+            base_pfn: 0,
+            page_count: 4 * PFN_BATCH,
+        }];
+        let results = query_ranges(&mock, &ranges).collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1], Err(STATUS_INVALID_PARAMETER));
+        // A huge range is only walked as far as it is queried.
+        let ranges = [PhysicalRange {
+            base_pfn: 0,
+            page_count: usize::MAX,
+        }];
+        assert_eq!(query_ranges(&mock, &ranges).count(), 1);
+    }
+}
@@ -0,0 +1,498 @@
+//! The typed information classes. These cover a subset of
+//! `SYSTEM_INFORMATION_CLASS`; the others are read with
+//! [`query_class`](super::query_class).
+
+use std::{mem, ptr};
+
+use windows::Win32::Foundation::HANDLE;
+
+use crate::{
+    buffer::{DecodeError, Reader, from_ansi},
+    ntexapi::{
+        SYSTEM_BASIC_INFORMATION, SYSTEM_CONTEXT_SWITCH_INFORMATION, SYSTEM_DEVICE_INFORMATION,
+        SYSTEM_DPC_BEHAVIOR_INFORMATION, SYSTEM_EXCEPTION_INFORMATION,
+        SYSTEM_FILECACHE_INFORMATION, SYSTEM_FLAGS_INFORMATION,
+        SYSTEM_HYPERVISOR_PROCESSOR_COUNT_INFORMATION, SYSTEM_INFORMATION_CLASS,
+        SYSTEM_INTERRUPT_INFORMATION, SYSTEM_KERNEL_DEBUGGER_INFORMATION,
+        SYSTEM_KERNEL_DEBUGGER_INFORMATION_EX, SYSTEM_LOOKASIDE_INFORMATION,
+        SYSTEM_MEMORY_USAGE_INFORMATION, SYSTEM_PERFORMANCE_INFORMATION,
+        SYSTEM_PROCESSOR_FEATURES_INFORMATION, SYSTEM_PROCESSOR_IDLE_INFORMATION,
+        SYSTEM_PROCESSOR_INFORMATION, SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION,
+        SYSTEM_PROCESSOR_POWER_INFORMATION, SYSTEM_QUERY_TIME_ADJUST_INFORMATION,
+        SYSTEM_REGISTRY_QUOTA_INFORMATION, SYSTEM_SECUREBOOT_INFORMATION,
+        SYSTEM_TIMEOFDAY_INFORMATION,
+    },
+};
+
+/// How the output buffer of a class is sized.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Sizing {
+    /// Exactly this many bytes.
+    Fixed(usize),
+    /// Starts at `initial` bytes and grows when the system asks for more.
+    Variable { initial: usize },
+}
+
+/// The input of a query.
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+pub enum InputBuffer {
+    #[default]
+    None,
+    /// Passed to `NtQuerySystemInformationEx` as its input buffer.
+    Separate(Vec<u8>),
+    /// Copied to the start of the output buffer, for classes that read their
+    /// request from it.
+    InPlace(Vec<u8>),
+}
+
+/// A value a class takes as input.
+pub trait QueryInput {
+    fn to_input(&self) -> InputBuffer;
+}
+
+impl QueryInput for () {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::None
+    }
+}
+
+/// A processor group.
+impl QueryInput for u16 {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::Separate(self.to_le_bytes().to_vec())
+    }
+}
+
+/// A 32-bit selector, such as an information sub-class.
+impl QueryInput for u32 {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::Separate(self.to_le_bytes().to_vec())
+    }
+}
+
+/// A process handle.
+impl QueryInput for HANDLE {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::Separate(self.0.to_le_bytes().to_vec())
+    }
+}
+
+/// A `SYSTEM_INFORMATION_CLASS` and what it returns.
+pub trait SystemInfo {
+    const CLASS: SYSTEM_INFORMATION_CLASS;
+    const SIZING: Sizing;
+    type Input: QueryInput;
+    type Output;
+
+    fn decode(bytes: &[u8]) -> Result<Self::Output, DecodeError>;
+}
+
+/// Copies a `T` out of the start of `bytes`.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid `T`.
+pub unsafe fn read_raw<T>(bytes: &[u8]) -> Result<T, DecodeError> {
+    if bytes.len() < mem::size_of::<T>() {
+        return Err(DecodeError::Truncated {
+            offset: 0,
+            needed: mem::size_of::<T>(),
+        });
+    }
+    Ok(ptr::read_unaligned(bytes.as_ptr().cast()))
+}
+
+/// Copies as many whole `T` as fit in `bytes`.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid `T`.
+pub unsafe fn read_array<T>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(mem::size_of::<T>())
+        .map(|chunk| ptr::read_unaligned(chunk.as_ptr().cast()))
+        .collect()
+}
+
+/// Declares classes returning one plain-data structure.
+macro_rules! fixed_classes {
+    ($($(#[$meta:meta])* $name:ident => $raw:ty;)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl SystemInfo for $name {
+            const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::$name;
+            const SIZING: Sizing = Sizing::Fixed(mem::size_of::<$raw>());
+            type Input = ();
+            type Output = $raw;
+
+            fn decode(bytes: &[u8]) -> Result<$raw, DecodeError> {
+                unsafe { read_raw(bytes) }
+            }
+        }
+    )*};
+}
+
+/// Declares classes returning an array of plain-data records, typically one
+/// per processor.
+macro_rules! array_classes {
+    ($($(#[$meta:meta])* $name:ident => $raw:ty;)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl SystemInfo for $name {
+            const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::$name;
+            const SIZING: Sizing = Sizing::Variable { initial: 64 * mem::size_of::<$raw>() };
+            type Input = ();
+            type Output = Vec<$raw>;
+
+            fn decode(bytes: &[u8]) -> Result<Vec<$raw>, DecodeError> {
+                Ok(unsafe { read_array(bytes) })
+            }
+        }
+    )*};
+}
+
+/// Declares variable-length classes without a decoder of their own, read
+/// as bytes.
+macro_rules! buffer_classes {
+    ($($(#[$meta:meta])* $name:ident $(($input:ty))?;)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl SystemInfo for $name {
+            const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::$name;
+            const SIZING: Sizing = Sizing::Variable { initial: 0x1_0000 };
+            type Input = buffer_classes!(@input $($input)?);
+            type Output = Vec<u8>;
+
+            fn decode(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+                Ok(bytes.to_vec())
+            }
+        }
+    )*};
+    (@input) => { () };
+    (@input $input:ty) => { $input };
+}
+
+fixed_classes! {
+    SystemProcessorInformation => SYSTEM_PROCESSOR_INFORMATION;
+    SystemPerformanceInformation => SYSTEM_PERFORMANCE_INFORMATION;
+    SystemTimeOfDayInformation => SYSTEM_TIMEOFDAY_INFORMATION;
+    SystemDeviceInformation => SYSTEM_DEVICE_INFORMATION;
+    SystemFlagsInformation => SYSTEM_FLAGS_INFORMATION;
+    SystemFileCacheInformation => SYSTEM_FILECACHE_INFORMATION;
+    SystemFileCacheInformationEx => SYSTEM_FILECACHE_INFORMATION;
+    SystemDpcBehaviorInformation => SYSTEM_DPC_BEHAVIOR_INFORMATION;
+    SystemTimeAdjustmentInformation => SYSTEM_QUERY_TIME_ADJUST_INFORMATION;
+    SystemExceptionInformation => SYSTEM_EXCEPTION_INFORMATION;
+    SystemContextSwitchInformation => SYSTEM_CONTEXT_SWITCH_INFORMATION;
+    SystemRegistryQuotaInformation => SYSTEM_REGISTRY_QUOTA_INFORMATION;
+    SystemKernelDebuggerInformationEx => SYSTEM_KERNEL_DEBUGGER_INFORMATION_EX;
+    SystemSecureBootInformation => SYSTEM_SECUREBOOT_INFORMATION;
+    SystemHypervisorProcessorCountInformation => SYSTEM_HYPERVISOR_PROCESSOR_COUNT_INFORMATION;
+    SystemProcessorFeaturesInformation => SYSTEM_PROCESSOR_FEATURES_INFORMATION;
+    SystemMemoryUsageInformation => SYSTEM_MEMORY_USAGE_INFORMATION;
+}
+
+array_classes! {
+    SystemProcessorPerformanceInformation => SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION;
+    SystemInterruptInformation => SYSTEM_INTERRUPT_INFORMATION;
+    SystemProcessorIdleInformation => SYSTEM_PROCESSOR_IDLE_INFORMATION;
+    SystemLookasideInformation => SYSTEM_LOOKASIDE_INFORMATION;
+    SystemProcessorPowerInformation => SYSTEM_PROCESSOR_POWER_INFORMATION;
+}
+
+buffer_classes! {
+    SystemProcessInformation;
+    SystemExtendedProcessInformation;
+    SystemFullProcessInformation;
+    SystemCallCountInformation;
+    SystemHandleInformation;
+    SystemExtendedHandleInformation;
+    SystemObjectInformation;
+    SystemPageFileInformation;
+    SystemPageFileInformationEx;
+    SystemSessionProcessInformation;
+    SystemLegacyDriverInformation;
+    /// Takes a process handle.
+    SystemSupportedProcessorArchitectures(HANDLE);
+}
+
+/// `SystemBasicInformation`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct BasicInformation {
+    /// The clock interrupt interval, in 100ns units.
+    pub timer_resolution: u32,
+    pub page_size: u32,
+    pub physical_pages: u32,
+    pub lowest_physical_page: u32,
+    pub highest_physical_page: u32,
+    pub allocation_granularity: u32,
+    pub minimum_user_address: usize,
+    pub maximum_user_address: usize,
+    pub active_processors: usize,
+    pub processors: u8,
+}
+
+impl From<&SYSTEM_BASIC_INFORMATION> for BasicInformation {
+    fn from(raw: &SYSTEM_BASIC_INFORMATION) -> Self {
+        Self {
+            timer_resolution: raw.TimerResolution,
+            page_size: raw.PageSize,
+            physical_pages: raw.NumberOfPhysicalPages,
+            lowest_physical_page: raw.LowestPhysicalPageNumber,
+            highest_physical_page: raw.HighestPhysicalPageNumber,
+            allocation_granularity: raw.AllocationGranularity,
+            minimum_user_address: raw.MinimumUserModeAddress,
+            maximum_user_address: raw.MaximumUserModeAddress,
+            active_processors: raw.ActiveProcessorsAffinityMask,
+            processors: raw.NumberOfProcessors as u8,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemBasicInformation {}
+
+impl SystemInfo for SystemBasicInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemBasicInformation;
+    const SIZING: Sizing = Sizing::Fixed(mem::size_of::<SYSTEM_BASIC_INFORMATION>());
+    type Input = ();
+    type Output = BasicInformation;
+
+    fn decode(bytes: &[u8]) -> Result<BasicInformation, DecodeError> {
+        let raw = unsafe { read_raw::<SYSTEM_BASIC_INFORMATION>(bytes)? };
+        Ok(BasicInformation::from(&raw))
+    }
+}
+
+/// `SystemNativeBasicInformation`: [`SystemBasicInformation`] as seen by
+/// native processes, for WOW64 callers.
+#[derive(Debug)]
+pub enum SystemNativeBasicInformation {}
+
+impl SystemInfo for SystemNativeBasicInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemNativeBasicInformation;
+    const SIZING: Sizing = SystemBasicInformation::SIZING;
+    type Input = ();
+    type Output = BasicInformation;
+
+    fn decode(bytes: &[u8]) -> Result<BasicInformation, DecodeError> {
+        SystemBasicInformation::decode(bytes)
+    }
+}
+
+/// `SystemKernelDebuggerInformation`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct KernelDebugger {
+    pub enabled: bool,
+    pub present: bool,
+}
+
+#[derive(Debug)]
+pub enum SystemKernelDebuggerInformation {}
+
+impl SystemInfo for SystemKernelDebuggerInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemKernelDebuggerInformation;
+    const SIZING: Sizing = Sizing::Fixed(mem::size_of::<SYSTEM_KERNEL_DEBUGGER_INFORMATION>());
+    type Input = ();
+    type Output = KernelDebugger;
+
+    fn decode(bytes: &[u8]) -> Result<KernelDebugger, DecodeError> {
+        let raw = unsafe { read_raw::<SYSTEM_KERNEL_DEBUGGER_INFORMATION>(bytes)? };
+        Ok(KernelDebugger {
+            enabled: raw.KernelDebuggerEnabled.as_bool(),
+            present: !raw.KernelDebuggerNotPresent.as_bool(),
+        })
+    }
+}
+
+/// `SystemRangeStartInformation`: the lowest kernel address.
+#[derive(Debug)]
+pub enum SystemRangeStartInformation {}
+
+impl SystemInfo for SystemRangeStartInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemRangeStartInformation;
+    const SIZING: Sizing = Sizing::Fixed(mem::size_of::<usize>());
+    type Input = ();
+    type Output = usize;
+
+    fn decode(bytes: &[u8]) -> Result<usize, DecodeError> {
+        unsafe { read_raw(bytes) }
+    }
+}
+
+/// `SystemProcessorBrandString`: the CPUID brand string.
+#[derive(Debug)]
+pub enum SystemProcessorBrandString {}
+
+impl SystemInfo for SystemProcessorBrandString {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemProcessorBrandString;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x40 };
+    type Input = ();
+    type Output = String;
+
+    fn decode(bytes: &[u8]) -> Result<String, DecodeError> {
+        let bytes = Reader::new(bytes).cstr().unwrap_or(bytes);
+        Ok(from_ansi(bytes).trim().to_owned())
+    }
+}
+
+/// `SystemProcessorCycleTimeInformation`: cycles spent by each processor of
+/// a group.
+#[derive(Debug)]
+pub enum SystemProcessorCycleTimeInformation {}
+
+impl SystemInfo for SystemProcessorCycleTimeInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemProcessorCycleTimeInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 64 * 8 };
+    /// The processor group.
+    type Input = u16;
+    type Output = Vec<u64>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<u64>, DecodeError> {
+        Ok(unsafe { read_array(bytes) })
+    }
+}
+
+/// `SystemProcessorIdleCycleTimeInformation`: idle cycles of each processor
+/// of a group.
+#[derive(Debug)]
+pub enum SystemProcessorIdleCycleTimeInformation {}
+
+impl SystemInfo for SystemProcessorIdleCycleTimeInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemProcessorIdleCycleTimeInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 64 * 8 };
+    /// The processor group.
+    type Input = u16;
+    type Output = Vec<u64>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<u64>, DecodeError> {
+        Ok(unsafe { read_array(bytes) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::from_hex;
+
+    #[test]
+    fn names_classes_and_sizes() {
+        assert_eq!(
+            SystemBasicInformation::CLASS,
+            SYSTEM_INFORMATION_CLASS::SystemBasicInformation
+        );
+        assert_eq!(SystemBasicInformation::SIZING, Sizing::Fixed(64));
+        assert_eq!(
+            SystemNativeBasicInformation::CLASS,
+            SYSTEM_INFORMATION_CLASS::SystemNativeBasicInformation
+        );
+        assert_eq!(SystemNativeBasicInformation::SIZING, Sizing::Fixed(64));
+        assert_eq!(SystemKernelDebuggerInformation::SIZING, Sizing::Fixed(2));
+        assert_eq!(
+            SystemProcessorPerformanceInformation::SIZING,
+            Sizing::Variable {
+                initial: 64 * mem::size_of::<SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION>(),
+            }
+        );
+        assert_eq!(
+            SystemExtendedHandleInformation::CLASS,
+            SYSTEM_INFORMATION_CLASS::SystemExtendedHandleInformation
+        );
+        assert_eq!(
+            SystemSupportedProcessorArchitectures::CLASS,
+            SYSTEM_INFORMATION_CLASS::SystemSupportedProcessorArchitectures
+        );
+    }
+
+    #[test]
+    fn encodes_inputs() {
+        assert_eq!(().to_input(), InputBuffer::None);
+        assert_eq!(2u16.to_input(), InputBuffer::Separate(vec![2, 0]));
+        assert_eq!(
+            0x0102_0304u32.to_input(),
+            InputBuffer::Separate(vec![4, 3, 2, 1])
+        );
+        assert_eq!(
+            HANDLE(-1).to_input(),
+            InputBuffer::Separate(vec![0xff; mem::size_of::<HANDLE>()])
+        );
+    }
+
+    #[test]
+    fn decodes_basic_information() {
+        let bytes = from_hex(
+            "
+            00 00 00 00 10 27 00 00 00 10 00 00 a5 f3 3f 00
+            01 00 00 00 ff 7f 48 00 00 00 01 00 00 00 00 00
+            00 00 01 00 00 00 00 00 ff ff fe ff ff 7f 00 00
+            ff 00 00 00 00 00 00 00 08 00 00 00 00 00 00 00
+            ",
+        );
+        assert_eq!(
+            SystemBasicInformation::decode(&bytes).unwrap(),
+            BasicInformation {
+                timer_resolution: 10000,
+                page_size: 0x1000,
+                physical_pages: 0x3f_f3a5,
+                lowest_physical_page: 1,
+                highest_physical_page: 0x48_7fff,
+                allocation_granularity: 0x1_0000,
+                minimum_user_address: 0x1_0000,
+                maximum_user_address: 0x7fff_fffe_ffff,
+                active_processors: 0xff,
+                processors: 8,
+            }
+        );
+        assert_eq!(
+            SystemBasicInformation::decode(&bytes[..63]),
+            Err(DecodeError::Truncated {
+                offset: 0,
+                needed: 64,
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_small_classes() {
+        assert_eq!(
+            SystemKernelDebuggerInformation::decode(&[1, 0]).unwrap(),
+            KernelDebugger {
+                enabled: true,
+                present: true,
+            }
+        );
+        assert_eq!(
+            SystemKernelDebuggerInformation::decode(&[0, 1]).unwrap(),
+            KernelDebugger::default()
+        );
+        assert_eq!(
+            SystemRangeStartInformation::decode(&from_hex("00 00 00 00 00 80 ff ff")).unwrap(),
+            0xffff_8000_0000_0000
+        );
+        assert_eq!(
+            SystemProcessorBrandString::decode(b"       Intel(R) Core(TM) i7\0\0\0\0").unwrap(),
+            "Intel(R) Core(TM) i7"
+        );
+        assert_eq!(
+            SystemProcessorBrandString::decode(b"AMD Ryzen 7").unwrap(),
+            "AMD Ryzen 7"
+        );
+        // A trailing partial record is dropped.
+        assert_eq!(
+            SystemProcessorIdleCycleTimeInformation::decode(&from_hex(
+                "0100000000000000 0200000000000000 03"
+            ))
+            .unwrap(),
+            [1, 2]
+        );
+    }
+}
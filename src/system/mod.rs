@@ -0,0 +1,502 @@
+//! Typed `NtQuerySystemInformation` queries.
+//!
+//! Each information class is a type implementing [`SystemInfo`], which names
+//! the class, how its buffer is sized, what input it takes and what it
+//! decodes to. [`query`] runs a class without input and [`query_ex`] one that
+//! takes input through `NtQuerySystemInformationEx`, such as a processor
+//! group. Variable-length classes are retried with a larger buffer until the
+//! output fits.
+//!
+//! The queries go through a [`SystemBackend`], so the sizing and decoding can
+//! be driven by recorded buffers; [`NativeSystem`] issues the real calls.
+//!
+//! Not every `SYSTEM_INFORMATION_CLASS` has a type: only the classes listed
+//! below and in [`SystemInfo`]'s implementors are decoded, about a quarter of
+//! the enumeration. The rest, including undocumented and write-only classes,
+//! are reachable only as raw bytes through [`query_class`], and the caller
+//! decodes them.
+//!
+//! The pool classes decode to [`PoolTagUsage`] and [`BigPoolAllocation`]
+//! records; [`diff_pool_tags`] and [`top_growers`] compare two pool tag
+//! snapshots to find the tags behind a kernel memory leak.
+//!
+//! The module classes decode to [`KernelModules`], the drivers loaded in the
+//! kernel with their paths and image ranges, which can be searched by
+//! address.
+//!
+//! The CPU set and logical processor classes build a [`Topology`] of groups,
+//! NUMA nodes, cores with their SMT siblings and efficiency classes, caches
+//! and CPU set IDs. [`set_process_default_cpu_sets`] and
+//! [`set_thread_selected_cpu_sets`] pin work to the sets found there.
+//!
+//! [`SecurityPosture`] gathers the speculation control, KVA shadow, code
+//! integrity, isolated user mode, Secure Boot, DMA guard and hypervisor
+//! classes into one report, decoding their flags through tables such as
+//! [`SPECULATION_CONTROL_FIELDS`].
+//!
+//! ```no_run
+//! use windows_native::system::{
+//!     query, query_ex, SystemBasicInformation, SystemProcessorCycleTimeInformation,
+//! };
+//!
+//! let basic = query::<SystemBasicInformation>()?;
+//! println!("{} processors, {:#x} byte pages", basic.processors, basic.page_size);
+//! let cycles = query_ex::<SystemProcessorCycleTimeInformation>(&0)?;
+//! println!("group 0: {cycles:?}");
+//! # Ok::<(), windows_native::system::Error>(())
+//! ```
+
+mod class;
+mod module;
+mod pool;
+mod posture;
+mod topology;
+
+use std::{ffi::c_void, mem};
+
+pub use class::*;
+pub use module::*;
+pub use pool::*;
+pub use posture::*;
+pub use topology::*;
+use windows::{
+    Wdk::System::SystemInformation::{
+        NtQuerySystemInformation, SYSTEM_INFORMATION_CLASS as INFORMATION_CLASS,
+    },
+    Win32::Foundation::{
+        NTSTATUS, STATUS_BUFFER_OVERFLOW, STATUS_BUFFER_TOO_SMALL, STATUS_INFO_LENGTH_MISMATCH,
+    },
+};
+
+use crate::{
+    buffer::DecodeError,
+    ntexapi::{NtQuerySystemInformationEx, SYSTEM_INFORMATION_CLASS},
+};
+
+/// Largest buffer a variable-length query is grown to before giving up, in
+/// bytes.
+pub const MAX_BUFFER: usize = 0x1000_0000;
+
+/// An error from a typed query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Status(NTSTATUS),
+    Decode(DecodeError),
+}
+
+impl From<NTSTATUS> for Error {
+    fn from(status: NTSTATUS) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "NTSTATUS {:#010x}", status.0),
+            Self::Decode(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A failed query, with the buffer length the system asked for, zero if it
+/// did not say.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QueryFailure {
+    pub status: NTSTATUS,
+    pub required: usize,
+}
+
+/// The calls the typed queries are built on.
+pub trait SystemBackend {
+    /// Queries `class` into `buffer`, through `NtQuerySystemInformationEx`
+    /// when there is `input`, returning the length written.
+    fn query(
+        &self,
+        class: SYSTEM_INFORMATION_CLASS,
+        input: Option<&[u8]>,
+        buffer: &mut [u64],
+    ) -> Result<usize, QueryFailure>;
+
+    /// Queries `T` with `input`, growing the buffer as needed.
+    fn query_info<T: SystemInfo>(&self, input: &T::Input) -> Result<T::Output, Error> {
+        let bytes = query_buffer(self, T::CLASS, T::SIZING, &input.to_input())?;
+        Ok(T::decode(&bytes)?)
+    }
+}
+
+/// Whether `status` asks for a larger buffer.
+fn is_too_small(status: NTSTATUS) -> bool {
+    matches!(
+        status,
+        STATUS_INFO_LENGTH_MISMATCH | STATUS_BUFFER_TOO_SMALL | STATUS_BUFFER_OVERFLOW
+    )
+}
+
+/// Runs `class` through `backend`, returning the output bytes, sized as
+/// [`query_with`] does.
+pub fn query_buffer<B: SystemBackend + ?Sized>(
+    backend: &B,
+    class: SYSTEM_INFORMATION_CLASS,
+    sizing: Sizing,
+    input: &InputBuffer,
+) -> Result<Vec<u8>, NTSTATUS> {
+    let separate = match input {
+        InputBuffer::Separate(bytes) => Some(bytes.as_slice()),
+        _ => None,
+    };
+    let prefix = match input {
+        InputBuffer::InPlace(prefix) => prefix.as_slice(),
+        _ => &[],
+    };
+    query_with(sizing, prefix, |buffer| {
+        backend.query(class, separate, buffer)
+    })
+}
+
+/// Runs a query through `query`, which fills the buffer it is given and
+/// returns the length written, and returns the output bytes. Each buffer
+/// starts with `prefix`, for classes that read their request from it.
+///
+/// A [`Sizing::Fixed`] class gets exactly its length and all of it is
+/// returned, zero-padded when the system wrote less. A [`Sizing::Variable`]
+/// class is retried with the length the system asked for, or twice the last
+/// one, up to [`MAX_BUFFER`], and only the written bytes are returned.
+pub fn query_with(
+    sizing: Sizing,
+    prefix: &[u8],
+    mut query: impl FnMut(&mut [u64]) -> Result<usize, QueryFailure>,
+) -> Result<Vec<u8>, NTSTATUS> {
+    let (length, fixed) = match sizing {
+        Sizing::Fixed(length) => (length, true),
+        Sizing::Variable { initial } => (initial.max(1), false),
+    };
+    let mut length = length.max(prefix.len());
+    loop {
+        let mut buffer = vec![0u64; length.div_ceil(mem::size_of::<u64>())];
+        as_bytes_mut(&mut buffer)[..prefix.len()].copy_from_slice(prefix);
+        match query(&mut buffer) {
+            Ok(written) => {
+                let bytes = &as_bytes_mut(&mut buffer)[..length];
+                let end = if fixed || written == 0 || written > length {
+                    length
+                } else {
+                    written
+                };
+                return Ok(bytes[..end].to_vec());
+            }
+            Err(failure) if !fixed && is_too_small(failure.status) && length < MAX_BUFFER => {
+                length = failure.required.max(length * 2).min(MAX_BUFFER);
+            }
+            Err(failure) => return Err(failure.status),
+        }
+    }
+}
+
+fn as_bytes_mut(buffer: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr().cast(), mem::size_of_val(buffer)) }
+}
+
+/// The [`SystemBackend`] that queries the running system.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct NativeSystem;
+
+impl SystemBackend for NativeSystem {
+    fn query(
+        &self,
+        class: SYSTEM_INFORMATION_CLASS,
+        input: Option<&[u8]>,
+        buffer: &mut [u64],
+    ) -> Result<usize, QueryFailure> {
+        let length = mem::size_of_val(buffer) as u32;
+        let mut return_length = 0;
+        let status = unsafe {
+            match input {
+                None => NtQuerySystemInformation(
+                    INFORMATION_CLASS(class as i32),
+                    buffer.as_mut_ptr().cast::<c_void>(),
+                    length,
+                    &mut return_length,
+                ),
+                Some(input) => NtQuerySystemInformationEx(
+                    class,
+                    input.as_ptr().cast_mut().cast(),
+                    input.len() as u32,
+                    buffer.as_mut_ptr().cast(),
+                    length,
+                    &mut return_length,
+                ),
+            }
+        };
+        if status.is_err() {
+            Err(QueryFailure {
+                status,
+                required: return_length as usize,
+            })
+        } else {
+            Ok(return_length as usize)
+        }
+    }
+}
+
+/// Queries `T`, which takes no input, from the running system.
+pub fn query<T: SystemInfo<Input = ()>>() -> Result<T::Output, Error> {
+    NativeSystem.query_info::<T>(&())
+}
+
+/// Queries `T` with `input` from the running system.
+pub fn query_ex<T: SystemInfo>(input: &T::Input) -> Result<T::Output, Error> {
+    NativeSystem.query_info::<T>(input)
+}
+
+/// Reads any class as bytes, growing the buffer as needed; `input`, when
+/// set, goes through `NtQuerySystemInformationEx`.
+pub fn query_class(
+    class: SYSTEM_INFORMATION_CLASS,
+    input: Option<&[u8]>,
+) -> Result<Vec<u8>, NTSTATUS> {
+    let input = input.map_or(InputBuffer::None, |input| {
+        InputBuffer::Separate(input.to_vec())
+    });
+    query_buffer(
+        &NativeSystem,
+        class,
+        Sizing::Variable { initial: 0x1000 },
+        &input,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::Foundation::STATUS_ACCESS_DENIED;
+
+    use super::*;
+
+    /// A query as the backend saw it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Call {
+        class: SYSTEM_INFORMATION_CLASS,
+        input: Option<Vec<u8>>,
+        length: usize,
+        start: Vec<u8>,
+    }
+
+    /// Answers `STATUS_INFO_LENGTH_MISMATCH` until the buffer holds `need`
+    /// bytes, then writes `output` after whatever the buffer starts with.
+    #[derive(Default)]
+    struct Recorded {
+        need: usize,
+        /// Whether failures report `need` as the required length.
+        report: bool,
+        output: Vec<u8>,
+        /// Where `output` is written, after an in-place request.
+        at: usize,
+        calls: RefCell<Vec<Call>>,
+    }
+
+    impl Recorded {
+        fn lengths(&self) -> Vec<usize> {
+            self.calls.borrow().iter().map(|call| call.length).collect()
+        }
+    }
+
+    impl SystemBackend for Recorded {
+        fn query(
+            &self,
+            class: SYSTEM_INFORMATION_CLASS,
+            input: Option<&[u8]>,
+            buffer: &mut [u64],
+        ) -> Result<usize, QueryFailure> {
+            let buffer = as_bytes_mut(buffer);
+            self.calls.borrow_mut().push(Call {
+                class,
+                input: input.map(<[u8]>::to_vec),
+                length: buffer.len(),
+                start: buffer[..buffer.len().min(8)].to_vec(),
+            });
+            if buffer.len() < self.need {
+                return Err(QueryFailure {
+                    status: STATUS_INFO_LENGTH_MISMATCH,
+                    required: if self.report { self.need } else { 0 },
+                });
+            }
+            let end = self.at + self.output.len();
+            buffer[self.at..end].copy_from_slice(&self.output);
+            Ok(end)
+        }
+    }
+
+    #[test]
+    fn grows_to_the_required_length() {
+        let backend = Recorded {
+            need: 5000,
+            report: true,
+            output: (0..3u64).flat_map(u64::to_le_bytes).collect(),
+            ..Recorded::default()
+        };
+        assert_eq!(
+            backend
+                .query_info::<SystemProcessorCycleTimeInformation>(&2)
+                .unwrap(),
+            [0, 1, 2]
+        );
+        assert_eq!(backend.lengths(), [64 * 8, 5000]);
+        let calls = backend.calls.borrow();
+        assert!(calls.iter().all(|call| {
+            call.class == SYSTEM_INFORMATION_CLASS::SystemProcessorCycleTimeInformation
+                && call.input.as_deref() == Some(&[2, 0][..])
+        }));
+    }
+
+    #[test]
+    fn doubles_without_a_required_length() {
+        let backend = Recorded {
+            need: 0x180,
+            output: vec![0xaa; 0x10],
+            ..Recorded::default()
+        };
+        let bytes = query_buffer(
+            &backend,
+            SYSTEM_INFORMATION_CLASS::SystemHandleInformation,
+            Sizing::Variable { initial: 0x40 },
+            &InputBuffer::None,
+        )
+        .unwrap();
+        // Only the written bytes are returned.
+        assert_eq!(bytes, [0xaa; 0x10]);
+        assert_eq!(backend.lengths(), [0x40, 0x80, 0x100, 0x200]);
+        assert_eq!(backend.calls.borrow()[0].input, None);
+
+        // A length the system asks for that is smaller than twice the last
+        // one does not slow the growth down.
+        let backend = Recorded {
+            need: 0x48,
+            report: true,
+            ..Recorded::default()
+        };
+        query_with(Sizing::Variable { initial: 0x40 }, &[], |buffer| {
+            backend.query(
+                SYSTEM_INFORMATION_CLASS::SystemHandleInformation,
+                None,
+                buffer,
+            )
+        })
+        .unwrap();
+        assert_eq!(backend.lengths(), [0x40, 0x80]);
+    }
+
+    #[test]
+    fn stops_growing_at_max_buffer() {
+        let backend = Recorded {
+            need: usize::MAX,
+            report: true,
+            ..Recorded::default()
+        };
+        assert_eq!(
+            backend.query_info::<SystemProcessInformation>(&()),
+            Err(Error::Status(STATUS_INFO_LENGTH_MISMATCH))
+        );
+        assert_eq!(backend.lengths(), [0x1_0000, MAX_BUFFER]);
+
+        // Other failures are not retried.
+        let status = query_with(Sizing::Variable { initial: 0x40 }, &[], |_| {
+            Err(QueryFailure {
+                status: STATUS_ACCESS_DENIED,
+                required: 0x1000,
+            })
+        });
+        assert_eq!(status, Err(STATUS_ACCESS_DENIED));
+    }
+
+    #[test]
+    fn pads_fixed_classes() {
+        let backend = Recorded {
+            output: from_le(&[0, 0x2710, 0x1000]),
+            ..Recorded::default()
+        };
+        let bytes = query_buffer(
+            &backend,
+            SYSTEM_INFORMATION_CLASS::SystemBasicInformation,
+            SystemBasicInformation::SIZING,
+            &InputBuffer::None,
+        )
+        .unwrap();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[..12], backend.output);
+        assert!(bytes[12..].iter().all(|byte| *byte == 0));
+        let basic = SystemBasicInformation::decode(&bytes).unwrap();
+        assert_eq!((basic.timer_resolution, basic.page_size), (0x2710, 0x1000));
+
+        // A fixed class is asked once, with its own length.
+        let backend = Recorded {
+            need: 0x100,
+            report: true,
+            ..Recorded::default()
+        };
+        assert_eq!(
+            backend.query_info::<SystemRangeStartInformation>(&()),
+            Err(Error::Status(STATUS_INFO_LENGTH_MISMATCH))
+        );
+        assert_eq!(backend.lengths(), [8]);
+    }
+
+    #[test]
+    fn prefixes_in_place_requests() {
+        let backend = Recorded {
+            need: 8,
+            output: 3u16.to_le_bytes().to_vec(),
+            at: 4,
+            ..Recorded::default()
+        };
+        assert_eq!(
+            backend
+                .query_info::<SystemNumaProximityNodeInformation>(&ProximityId(0x11))
+                .unwrap(),
+            3
+        );
+        let calls = backend.calls.borrow();
+        assert_eq!(calls[0].input, None);
+        assert_eq!(calls[0].start, [0x11, 0, 0, 0, 0, 0, 0, 0]);
+
+        // The request is copied again into every grown buffer, and the
+        // buffer is never smaller than it.
+        let backend = Recorded {
+            need: 0x30,
+            at: 0x10,
+            ..Recorded::default()
+        };
+        let bytes = query_buffer(
+            &backend,
+            SYSTEM_INFORMATION_CLASS::SystemFirmwareTableInformation,
+            Sizing::Variable { initial: 4 },
+            &InputBuffer::InPlace(vec![7; 0x10]),
+        )
+        .unwrap();
+        assert_eq!(bytes, [7; 0x10]);
+        assert_eq!(backend.lengths(), [0x10, 0x20, 0x40]);
+        assert!(
+            backend
+                .calls
+                .borrow()
+                .iter()
+                .all(|call| call.start == [7; 8])
+        );
+    }
+
+    fn from_le(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+}
@@ -0,0 +1,477 @@
+use std::mem;
+
+use crate::{
+    buffer::{DecodeError, Reader, from_ansi},
+    ntexapi::SYSTEM_INFORMATION_CLASS,
+    ntldr::{RTL_PROCESS_MODULE_INFORMATION, RTL_PROCESS_MODULE_INFORMATION_EX},
+};
+
+use super::{Sizing, SystemInfo};
+
+/// The image details only `SystemModuleInformationEx` reports.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct ModuleImage {
+    pub checksum: u32,
+    pub time_date_stamp: u32,
+    /// The preferred base from the image headers.
+    pub default_base: usize,
+}
+
+/// A loaded kernel module, from an `RTL_PROCESS_MODULE_INFORMATION`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelModule {
+    pub mapped_base: usize,
+    pub image_base: usize,
+    pub image_size: u32,
+    pub flags: u32,
+    pub load_order: u16,
+    pub init_order: u16,
+    pub load_count: u16,
+    /// The NT path, e.g. `\SystemRoot\system32\ntoskrnl.exe`.
+    pub full_path: String,
+    /// Where the file name starts in the undecoded path, in bytes.
+    pub file_name_offset: u16,
+    pub file_name: String,
+    pub image: Option<ModuleImage>,
+}
+
+impl KernelModule {
+    /// One past the last byte of the image, clamped to the address space
+    /// for a size that would run past it.
+    pub fn end(&self) -> usize {
+        self.image_base.saturating_add(self.image_size as usize)
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.image_base..self.end()).contains(&address)
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        reader.align(mem::align_of::<usize>())?;
+        let _section = reader.usize()?;
+        let mapped_base = reader.usize()?;
+        let image_base = reader.usize()?;
+        let image_size = reader.u32()?;
+        let flags = reader.u32()?;
+        let load_order = reader.u16()?;
+        let init_order = reader.u16()?;
+        let load_count = reader.u16()?;
+        let file_name_offset = reader.u16()?;
+        let path = reader.array::<256>()?;
+        reader.align(mem::align_of::<usize>())?;
+        let file_name = path
+            .get(file_name_offset as usize..)
+            .map(from_ansi)
+            .unwrap_or_default();
+        Ok(Self {
+            mapped_base,
+            image_base,
+            image_size,
+            flags,
+            load_order,
+            init_order,
+            load_count,
+            full_path: from_ansi(&path),
+            file_name_offset,
+            file_name,
+            image: None,
+        })
+    }
+}
+
+/// Iterates an `RTL_PROCESS_MODULES` buffer from `SystemModuleInformation`.
+#[derive(Debug, Clone)]
+pub struct Modules<'a> {
+    reader: Reader<'a>,
+    remaining: usize,
+}
+
+impl<'a> Modules<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let count = reader.u32()? as usize;
+        reader.align(mem::align_of::<usize>())?;
+        reader.check_count(count, mem::size_of::<RTL_PROCESS_MODULE_INFORMATION>())?;
+        Ok(Self {
+            reader,
+            remaining: count,
+        })
+    }
+}
+
+impl Iterator for Modules<'_> {
+    type Item = Result<KernelModule, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let module = KernelModule::read(&mut self.reader);
+        if module.is_err() {
+            self.remaining = 0;
+        }
+        Some(module)
+    }
+}
+
+/// Iterates the chained `RTL_PROCESS_MODULE_INFORMATION_EX` entries from
+/// `SystemModuleInformationEx`. The chain ends at an entry whose next offset
+/// is zero.
+#[derive(Debug, Clone)]
+pub struct ModulesEx<'a> {
+    bytes: &'a [u8],
+    offset: Option<usize>,
+}
+
+impl<'a> ModulesEx<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: (!bytes.is_empty()).then_some(0),
+        }
+    }
+
+    fn read(&self, offset: usize) -> Result<(KernelModule, usize), DecodeError> {
+        let mut reader = Reader::at(self.bytes, offset);
+        let next = reader.u16()? as usize;
+        let mut module = KernelModule::read(&mut reader)?;
+        let checksum = reader.u32()?;
+        let time_date_stamp = reader.u32()?;
+        let default_base = reader.usize()?;
+        module.image = Some(ModuleImage {
+            checksum,
+            time_date_stamp,
+            default_base,
+        });
+        Ok((module, next))
+    }
+}
+
+impl Iterator for ModulesEx<'_> {
+    type Item = Result<KernelModule, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset.take()?;
+        match self.read(offset) {
+            Ok((module, next)) => {
+                // An entry needs its whole structure; a shorter offset would
+                // loop or overlap.
+                if next >= mem::size_of::<RTL_PROCESS_MODULE_INFORMATION_EX>()
+                    && offset + next < self.bytes.len()
+                {
+                    self.offset = Some(offset + next);
+                } else if next != 0 {
+                    return Some(Err(DecodeError::Invalid {
+                        offset,
+                        what: "next module offset",
+                    }));
+                }
+                Some(Ok(module))
+            }
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+/// The loaded kernel modules, in load order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelModules(pub Vec<KernelModule>);
+
+impl KernelModules {
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Modules::new(bytes)?.collect::<Result<_, _>>().map(Self)
+    }
+
+    pub fn parse_ex(bytes: &[u8]) -> Result<Self, DecodeError> {
+        ModulesEx::new(bytes).collect::<Result<_, _>>().map(Self)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, KernelModule> {
+        self.0.iter()
+    }
+
+    /// The module whose image contains `address`.
+    pub fn by_address(&self, address: usize) -> Option<&KernelModule> {
+        self.0.iter().find(|module| module.contains(address))
+    }
+
+    /// The module with the file name `name`, ignoring ASCII case.
+    pub fn by_name(&self, name: &str) -> Option<&KernelModule> {
+        self.0
+            .iter()
+            .find(|module| module.file_name.eq_ignore_ascii_case(name))
+    }
+
+    /// The kernel itself, always loaded first.
+    pub fn kernel(&self) -> Option<&KernelModule> {
+        self.0.first()
+    }
+}
+
+impl<'a> IntoIterator for &'a KernelModules {
+    type Item = &'a KernelModule;
+    type IntoIter = std::slice::Iter<'a, KernelModule>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemModuleInformation {}
+
+impl SystemInfo for SystemModuleInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemModuleInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x4_0000 };
+    type Input = ();
+    type Output = KernelModules;
+
+    fn decode(bytes: &[u8]) -> Result<KernelModules, DecodeError> {
+        KernelModules::parse(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemModuleInformationEx {}
+
+impl SystemInfo for SystemModuleInformationEx {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemModuleInformationEx;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x4_0000 };
+    type Input = ();
+    type Output = KernelModules;
+
+    fn decode(bytes: &[u8]) -> Result<KernelModules, DecodeError> {
+        KernelModules::parse_ex(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Writer;
+
+    /// Writes an `RTL_PROCESS_MODULE_INFORMATION` for an image at `base`.
+    fn write_module(
+        writer: &mut Writer,
+        base: usize,
+        size: u32,
+        order: u16,
+        path: &[u8],
+        file_name_offset: u16,
+    ) {
+        let mut full_path = [0; 256];
+        full_path[..path.len()].copy_from_slice(path);
+        writer
+            .u64(0)
+            .u64(base as u64)
+            .u64(base as u64)
+            .u32(size)
+            .u32(0x0804_9000)
+            .u16(order)
+            .u16(order)
+            .u16(1)
+            .u16(file_name_offset)
+            .bytes(&full_path);
+    }
+
+    /// `SystemModuleInformationEx` output: the kernel, the HAL right after it
+    /// and a driver with a non-ASCII path, each entry followed by its image
+    /// details. The second entry leaves 8 bytes of padding before the next.
+    fn modules_ex() -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.u16(320).zeros(6);
+        write_module(
+            &mut writer,
+            0xfffff806_1a000000,
+            0x104_6000,
+            0,
+            br"\SystemRoot\system32\ntoskrnl.exe",
+            21,
+        );
+        writer.u32(0xa8d_b1c4).u32(0x6f2a_11d0).u64(0x1_4000_0000);
+        writer.u16(328).zeros(6);
+        write_module(
+            &mut writer,
+            0xfffff806_1b046000,
+            0x6000,
+            1,
+            br"\SystemRoot\system32\hal.dll",
+            21,
+        );
+        writer.u32(0x1_4f2c).u32(0x3b1a_0cde).u64(0x1_c000_0000);
+        writer.zeros(8);
+        writer.u16(0).zeros(6);
+        write_module(
+            &mut writer,
+            0xfffff806_20000000,
+            0x1_0000,
+            2,
+            b"\\??\\C:\\Pilotes\\r\xe9seau.sys",
+            15,
+        );
+        writer.u32(0).u32(0x5000_0000).u64(0x1_4000_0000);
+        writer.into_inner()
+    }
+
+    #[test]
+    fn walks_next_offsets() {
+        let bytes = modules_ex();
+        assert_eq!(bytes.len(), 3 * 320 + 8);
+        let modules = KernelModules::parse_ex(&bytes).unwrap();
+        assert_eq!(modules.0.len(), 3);
+
+        let kernel = modules.kernel().unwrap();
+        assert_eq!(kernel.full_path, r"\SystemRoot\system32\ntoskrnl.exe");
+        assert_eq!(kernel.file_name, "ntoskrnl.exe");
+        assert_eq!(kernel.mapped_base, 0xfffff806_1a000000);
+        assert_eq!(kernel.image_size, 0x104_6000);
+        assert_eq!(kernel.flags, 0x0804_9000);
+        assert_eq!(
+            kernel.image,
+            Some(ModuleImage {
+                checksum: 0xa8d_b1c4,
+                time_date_stamp: 0x6f2a_11d0,
+                default_base: 0x1_4000_0000,
+            })
+        );
+        assert_eq!(modules.0[1].file_name, "hal.dll");
+        assert_eq!(modules.0[1].image.unwrap().checksum, 0x1_4f2c);
+        assert_eq!(modules.0[2].load_order, 2);
+        assert_eq!(modules.by_name("HAL.DLL"), Some(&modules.0[1]));
+        assert_eq!(
+            KernelModules::parse_ex(&[]).unwrap(),
+            KernelModules::default()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_next_offsets() {
+        // Shorter than an entry, which would overlap the next.
+        let mut bytes = modules_ex();
+        bytes[0] = 8;
+        bytes[1] = 0;
+        assert_eq!(
+            KernelModules::parse_ex(&bytes),
+            Err(DecodeError::Invalid {
+                offset: 0,
+                what: "next module offset",
+            })
+        );
+        // Past the end of the buffer.
+        let mut bytes = modules_ex();
+        bytes[320..322].copy_from_slice(&0x1000u16.to_le_bytes());
+        assert_eq!(
+            KernelModules::parse_ex(&bytes),
+            Err(DecodeError::Invalid {
+                offset: 320,
+                what: "next module offset",
+            })
+        );
+        // The last entry cut short.
+        let bytes = modules_ex();
+        assert!(KernelModules::parse_ex(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn decodes_ansi_paths() {
+        let modules = KernelModules::parse_ex(&modules_ex()).unwrap();
+        // The path is in the ANSI code page; bytes that are not UTF-8 are
+        // replaced rather than failing the whole list.
+        assert_eq!(
+            modules.0[2].full_path,
+            "\\??\\C:\\Pilotes\\r\u{fffd}seau.sys"
+        );
+        assert_eq!(modules.0[2].file_name, "r\u{fffd}seau.sys");
+        assert_eq!(modules.0[2].file_name_offset, 15);
+    }
+
+    #[test]
+    fn bounds_file_name_offsets() {
+        let path = br"\SystemRoot\System32\drivers\ACPI.sys";
+        let file_name = |offset: u16| {
+            let mut writer = Writer::new();
+            writer.u32(1).u32(0);
+            write_module(&mut writer, 0xfffff806_1c000000, 0x1000, 0, path, offset);
+            KernelModules::parse(writer.as_slice()).unwrap().0[0]
+                .file_name
+                .clone()
+        };
+        assert_eq!(file_name(29), "ACPI.sys");
+        assert_eq!(file_name(0), r"\SystemRoot\System32\drivers\ACPI.sys");
+        // Past the path but inside the array, and past the array.
+        assert_eq!(file_name(200), "");
+        assert_eq!(file_name(256), "");
+        assert_eq!(file_name(0xffff), "");
+
+        // A path filling the whole array has no terminator.
+        let mut writer = Writer::new();
+        writer.u32(1).u32(0);
+        write_module(&mut writer, 0, 0x1000, 0, &[b'a'; 256], 250);
+        let modules = KernelModules::parse(writer.as_slice()).unwrap();
+        assert_eq!(modules.0[0].full_path.len(), 256);
+        assert_eq!(modules.0[0].file_name, "aaaaaa");
+    }
+
+    #[test]
+    fn finds_modules_by_address() {
+        let modules = KernelModules::parse_ex(&modules_ex()).unwrap();
+        let kernel = &modules.0[0];
+        let hal = &modules.0[1];
+        assert_eq!(kernel.end(), hal.image_base);
+        assert_eq!(modules.by_address(kernel.image_base), Some(kernel));
+        assert_eq!(modules.by_address(kernel.end() - 1), Some(kernel));
+        // One past the end of the kernel is the start of the HAL.
+        assert_eq!(modules.by_address(kernel.end()), Some(hal));
+        assert_eq!(modules.by_address(hal.end() - 1), Some(hal));
+        assert_eq!(modules.by_address(hal.end()), None);
+        assert_eq!(modules.by_address(kernel.image_base - 1), None);
+        assert!(!modules.0[2].contains(modules.0[2].end()));
+    }
+
+    #[test]
+    fn clamps_the_end_of_images_at_the_top_of_memory() {
+        let module = KernelModule {
+            image_base: usize::MAX - 0xfff,
+            image_size: 0x2000,
+            ..Default::default()
+        };
+        assert_eq!(module.end(), usize::MAX);
+        assert!(module.contains(usize::MAX - 1));
+    }
+
+    #[test]
+    fn decodes_module_arrays() {
+        let mut writer = Writer::new();
+        writer.u32(2).u32(0);
+        write_module(
+            &mut writer,
+            0xfffff806_1a000000,
+            0x104_6000,
+            0,
+            br"\SystemRoot\system32\ntoskrnl.exe",
+            21,
+        );
+        write_module(
+            &mut writer,
+            0xfffff806_1b046000,
+            0x6000,
+            1,
+            br"\SystemRoot\system32\hal.dll",
+            21,
+        );
+        let bytes = writer.into_inner();
+        let modules = KernelModules::parse(&bytes).unwrap();
+        assert_eq!(modules.0.len(), 2);
+        assert_eq!(modules.0[1].file_name, "hal.dll");
+        assert!(modules.iter().all(|module| module.image.is_none()));
+
+        assert_eq!(
+            KernelModules::parse(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::Truncated {
+                offset: 8,
+                needed: 2 * 296,
+            })
+        );
+    }
+}
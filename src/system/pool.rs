@@ -0,0 +1,541 @@
+use std::{collections::BTreeMap, fmt, mem};
+
+use crate::{
+    buffer::{DecodeError, Reader},
+    ntexapi::{SYSTEM_BIGPOOL_ENTRY, SYSTEM_INFORMATION_CLASS, SYSTEM_POOLTAG},
+};
+
+use super::{Sizing, SystemInfo};
+
+/// A four-character pool tag, as stored by the pool allocator.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PoolTag(pub u32);
+
+impl PoolTag {
+    pub const fn new(tag: &[u8; 4]) -> Self {
+        Self(u32::from_le_bytes(*tag))
+    }
+
+    pub const fn bytes(self) -> [u8; 4] {
+        self.0.to_le_bytes()
+    }
+}
+
+impl fmt::Display for PoolTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.bytes() {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            write!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PoolTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PoolTag(\"{self}\")")
+    }
+}
+
+/// A `SYSTEM_POOLTAG`: the allocations made with one tag.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct PoolTagUsage {
+    pub tag: PoolTag,
+    pub paged_allocs: u32,
+    pub paged_frees: u32,
+    pub paged_bytes: usize,
+    pub non_paged_allocs: u32,
+    pub non_paged_frees: u32,
+    pub non_paged_bytes: usize,
+}
+
+impl PoolTagUsage {
+    /// Paged allocations not freed yet. The counters wrap, so this does too.
+    pub fn paged_outstanding(&self) -> u32 {
+        self.paged_allocs.wrapping_sub(self.paged_frees)
+    }
+
+    pub fn non_paged_outstanding(&self) -> u32 {
+        self.non_paged_allocs.wrapping_sub(self.non_paged_frees)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.paged_bytes + self.non_paged_bytes
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let tag = PoolTag(reader.u32()?);
+        let paged_allocs = reader.u32()?;
+        let paged_frees = reader.u32()?;
+        reader.align(mem::align_of::<usize>())?;
+        let paged_bytes = reader.usize()?;
+        let non_paged_allocs = reader.u32()?;
+        let non_paged_frees = reader.u32()?;
+        let non_paged_bytes = reader.usize()?;
+        Ok(Self {
+            tag,
+            paged_allocs,
+            paged_frees,
+            paged_bytes,
+            non_paged_allocs,
+            non_paged_frees,
+            non_paged_bytes,
+        })
+    }
+}
+
+/// Reads `count` `SYSTEM_POOLTAG` entries, the first one at an aligned
+/// offset.
+fn read_tags(reader: &mut Reader<'_>, count: usize) -> Result<Vec<PoolTagUsage>, DecodeError> {
+    reader.align(mem::align_of::<usize>())?;
+    reader.check_count(count, mem::size_of::<SYSTEM_POOLTAG>())?;
+    (0..count).map(|_| PoolTagUsage::read(reader)).collect()
+}
+
+/// Decodes a `SYSTEM_POOLTAG_INFORMATION` buffer.
+pub fn parse_pool_tags(bytes: &[u8]) -> Result<Vec<PoolTagUsage>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let count = reader.u32()? as usize;
+    read_tags(&mut reader, count)
+}
+
+/// The pool tags of one session, from `SystemSessionPoolTagInformation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionPoolTags {
+    pub session_id: u32,
+    pub tags: Vec<PoolTagUsage>,
+}
+
+/// Decodes a chain of `SYSTEM_SESSION_POOLTAG_INFORMATION` entries.
+pub fn parse_session_pool_tags(bytes: &[u8]) -> Result<Vec<SessionPoolTags>, DecodeError> {
+    let mut sessions = Vec::new();
+    let mut offset = 0;
+    loop {
+        let mut reader = Reader::at(bytes, offset);
+        let next = reader.usize()?;
+        let session_id = reader.u32()?;
+        let count = reader.u32()? as usize;
+        let tags = read_tags(&mut reader, count)?;
+        sessions.push(SessionPoolTags { session_id, tags });
+        if next == 0 {
+            return Ok(sessions);
+        }
+        offset = offset
+            .checked_add(next)
+            .filter(|offset| *offset < bytes.len())
+            .ok_or_else(|| reader.invalid("next entry offset"))?;
+    }
+}
+
+/// A `SYSTEM_BIGPOOL_ENTRY`: a pool allocation of a page or more.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct BigPoolAllocation {
+    pub address: usize,
+    pub size: usize,
+    pub tag: PoolTag,
+    pub non_paged: bool,
+}
+
+/// Decodes a `SYSTEM_BIGPOOL_INFORMATION` buffer.
+pub fn parse_big_pool(bytes: &[u8]) -> Result<Vec<BigPoolAllocation>, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let count = reader.u32()? as usize;
+    reader.align(mem::align_of::<usize>())?;
+    reader.check_count(count, mem::size_of::<SYSTEM_BIGPOOL_ENTRY>())?;
+    (0..count)
+        .map(|_| {
+            reader.align(mem::align_of::<usize>())?;
+            // The low bit of the address marks non-paged pool.
+            let address = reader.usize()?;
+            let size = reader.usize()?;
+            let tag = PoolTag(reader.u32()?);
+            Ok(BigPoolAllocation {
+                address: address & !1,
+                size,
+                tag,
+                non_paged: address & 1 != 0,
+            })
+        })
+        .collect()
+}
+
+/// The change of one tag between two pool tag snapshots.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct PoolTagDelta {
+    pub tag: PoolTag,
+    pub paged_bytes: i64,
+    pub non_paged_bytes: i64,
+    pub paged_outstanding: i64,
+    pub non_paged_outstanding: i64,
+}
+
+impl PoolTagDelta {
+    pub fn bytes(&self) -> i64 {
+        self.paged_bytes + self.non_paged_bytes
+    }
+
+    pub fn outstanding(&self) -> i64 {
+        self.paged_outstanding + self.non_paged_outstanding
+    }
+}
+
+/// Compares two pool tag snapshots. Every tag whose usage changed is
+/// reported, largest byte growth first; a tag missing from one side counts as
+/// unused there.
+pub fn diff_pool_tags(before: &[PoolTagUsage], after: &[PoolTagUsage]) -> Vec<PoolTagDelta> {
+    let empty = PoolTagUsage::default();
+    let mut tags = BTreeMap::<PoolTag, (&PoolTagUsage, &PoolTagUsage)>::new();
+    for usage in before {
+        tags.entry(usage.tag).or_insert((&empty, &empty)).0 = usage;
+    }
+    for usage in after {
+        tags.entry(usage.tag).or_insert((&empty, &empty)).1 = usage;
+    }
+    let mut deltas = tags
+        .into_iter()
+        .map(|(tag, (before, after))| PoolTagDelta {
+            tag,
+            paged_bytes: after.paged_bytes as i64 - before.paged_bytes as i64,
+            non_paged_bytes: after.non_paged_bytes as i64 - before.non_paged_bytes as i64,
+            paged_outstanding: after.paged_outstanding() as i64 - before.paged_outstanding() as i64,
+            non_paged_outstanding: after.non_paged_outstanding() as i64
+                - before.non_paged_outstanding() as i64,
+        })
+        .filter(|delta| {
+            delta.paged_bytes != 0
+                || delta.non_paged_bytes != 0
+                || delta.paged_outstanding != 0
+                || delta.non_paged_outstanding != 0
+        })
+        .collect::<Vec<_>>();
+    deltas.sort_by(|a, b| b.bytes().cmp(&a.bytes()).then(a.tag.cmp(&b.tag)));
+    deltas
+}
+
+/// The `count` tags that grew the most in bytes between two snapshots.
+pub fn top_growers(
+    before: &[PoolTagUsage],
+    after: &[PoolTagUsage],
+    count: usize,
+) -> Vec<PoolTagDelta> {
+    diff_pool_tags(before, after)
+        .into_iter()
+        .filter(|delta| delta.bytes() > 0)
+        .take(count)
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum SystemPoolTagInformation {}
+
+impl SystemInfo for SystemPoolTagInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemPoolTagInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x4_0000 };
+    type Input = ();
+    type Output = Vec<PoolTagUsage>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<PoolTagUsage>, DecodeError> {
+        parse_pool_tags(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemSessionPoolTagInformation {}
+
+impl SystemInfo for SystemSessionPoolTagInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemSessionPoolTagInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x4_0000 };
+    type Input = ();
+    type Output = Vec<SessionPoolTags>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<SessionPoolTags>, DecodeError> {
+        parse_session_pool_tags(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemBigPoolInformation {}
+
+impl SystemInfo for SystemBigPoolInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemBigPoolInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x10_0000 };
+    type Input = ();
+    type Output = Vec<BigPoolAllocation>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<BigPoolAllocation>, DecodeError> {
+        parse_big_pool(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::from_hex;
+
+    /// `SYSTEM_POOLTAG_INFORMATION` with a paged, a non-paged and a mixed
+    /// tag.
+    fn pool_tags() -> Vec<u8> {
+        from_hex(
+            "
+            03 00 00 00 00 00 00 00 46 69 6c 65 b0 04 00 00
+            4c 04 00 00 00 00 00 00 00 90 01 00 00 00 00 00
+            2c 01 00 00 18 01 00 00 00 28 00 00 00 00 00 00
+            50 72 6f 63 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 fa 00 00 00 c8 00 00 00
+            00 e8 03 00 00 00 00 00 54 6f 6b 65 84 03 00 00
+            52 03 00 00 00 00 00 00 00 c0 01 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            ",
+        )
+    }
+
+    fn usage(tag: &[u8; 4], paged_bytes: usize, non_paged_bytes: usize) -> PoolTagUsage {
+        PoolTagUsage {
+            tag: PoolTag::new(tag),
+            paged_allocs: 10,
+            paged_bytes,
+            non_paged_allocs: 10,
+            non_paged_bytes,
+            ..PoolTagUsage::default()
+        }
+    }
+
+    #[test]
+    fn decodes_pool_tags() {
+        let tags = parse_pool_tags(&pool_tags()).unwrap();
+        assert_eq!(
+            tags,
+            [
+                PoolTagUsage {
+                    tag: PoolTag::new(b"File"),
+                    paged_allocs: 1200,
+                    paged_frees: 1100,
+                    paged_bytes: 0x1_9000,
+                    non_paged_allocs: 300,
+                    non_paged_frees: 280,
+                    non_paged_bytes: 0x2800,
+                },
+                PoolTagUsage {
+                    tag: PoolTag::new(b"Proc"),
+                    paged_allocs: 0,
+                    paged_frees: 0,
+                    paged_bytes: 0,
+                    non_paged_allocs: 250,
+                    non_paged_frees: 200,
+                    non_paged_bytes: 0x3_e800,
+                },
+                PoolTagUsage {
+                    tag: PoolTag::new(b"Toke"),
+                    paged_allocs: 900,
+                    paged_frees: 850,
+                    paged_bytes: 0x1_c000,
+                    non_paged_allocs: 0,
+                    non_paged_frees: 0,
+                    non_paged_bytes: 0,
+                },
+            ]
+        );
+        assert_eq!(tags[0].paged_outstanding(), 100);
+        assert_eq!(tags[0].non_paged_outstanding(), 20);
+        assert_eq!(tags[0].bytes(), 0x1_b800);
+        assert_eq!(tags[1].tag.to_string(), "Proc");
+
+        let mut bytes = pool_tags();
+        bytes[0] = 4;
+        assert_eq!(
+            parse_pool_tags(&bytes),
+            Err(DecodeError::Truncated {
+                offset: 8,
+                needed: 4 * 40,
+            })
+        );
+        assert_eq!(
+            parse_pool_tags(&pool_tags()[..8]),
+            Err(DecodeError::Truncated {
+                offset: 8,
+                needed: 3 * 40,
+            })
+        );
+    }
+
+    #[test]
+    fn formats_tags() {
+        assert_eq!(PoolTag::new(b"Ntf\0").to_string(), "Ntf.");
+        assert_eq!(PoolTag::new(b"Mm  ").to_string(), "Mm  ");
+        assert_eq!(format!("{:?}", PoolTag::new(b"Proc")), "PoolTag(\"Proc\")");
+        // Outstanding counts wrap with the counters.
+        let wrapped = PoolTagUsage {
+            paged_allocs: 2,
+            paged_frees: u32::MAX,
+            ..PoolTagUsage::default()
+        };
+        assert_eq!(wrapped.paged_outstanding(), 3);
+    }
+
+    #[test]
+    fn decodes_big_pool() {
+        // The low bit of the address marks the non-paged allocations.
+        let bytes = from_hex(
+            "
+            03 00 00 00 00 00 00 00 01 00 34 12 0c a7 ff ff
+            00 20 00 00 00 00 00 00 43 4d 33 31 00 00 00 00
+            00 00 40 12 0c a7 ff ff 00 10 01 00 00 00 00 00
+            4d 6d 53 74 00 00 00 00 01 00 60 12 0c a7 ff ff
+            00 10 00 00 00 00 00 00 4e 74 66 00 00 00 00 00
+            ",
+        );
+        let allocations = parse_big_pool(&bytes).unwrap();
+        assert_eq!(
+            allocations,
+            [
+                BigPoolAllocation {
+                    address: 0xffff_a70c_1234_0000,
+                    size: 0x2000,
+                    tag: PoolTag::new(b"CM31"),
+                    non_paged: true,
+                },
+                BigPoolAllocation {
+                    address: 0xffff_a70c_1240_0000,
+                    size: 0x1_1000,
+                    tag: PoolTag::new(b"MmSt"),
+                    non_paged: false,
+                },
+                BigPoolAllocation {
+                    address: 0xffff_a70c_1260_0000,
+                    size: 0x1000,
+                    tag: PoolTag::new(b"Ntf\0"),
+                    non_paged: true,
+                },
+            ]
+        );
+        assert_eq!(
+            parse_big_pool(&bytes[..bytes.len() - 4]),
+            Err(DecodeError::Truncated {
+                offset: 8,
+                needed: 3 * 24,
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_session_pool_tags() {
+        // Session 1 with one tag, then session 2 without any.
+        let bytes = from_hex(
+            "
+            38 00 00 00 00 00 00 00 01 00 00 00 01 00 00 00
+            47 64 69 20 02 00 00 00 01 00 00 00 00 00 00 00
+            00 02 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+            02 00 00 00 00 00 00 00
+            ",
+        );
+        let sessions = parse_session_pool_tags(&bytes).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].session_id, 1);
+        assert_eq!(sessions[0].tags[0].tag, PoolTag::new(b"Gdi "));
+        assert_eq!(sessions[0].tags[0].paged_outstanding(), 1);
+        assert_eq!(sessions[0].tags[0].paged_bytes, 0x200);
+        assert_eq!(
+            sessions[1],
+            SessionPoolTags {
+                session_id: 2,
+                tags: Vec::new(),
+            }
+        );
+
+        // A next offset past the buffer.
+        let mut bytes = bytes;
+        bytes[0] = 0x80;
+        assert!(parse_session_pool_tags(&bytes).is_err());
+    }
+
+    #[test]
+    fn diffs_pool_tags() {
+        let before = parse_pool_tags(&pool_tags()).unwrap();
+        let mut after = before.clone();
+        // "File" shrinks, "Proc" leaks, "Toke" goes away and "Leak" is new.
+        after[0].paged_bytes -= 0x1000;
+        after[0].paged_frees += 4;
+        after[1].non_paged_bytes += 0x10_0000;
+        after[1].non_paged_allocs += 64;
+        after.remove(2);
+        after.push(usage(b"Leak", 0x8000, 0));
+
+        let deltas = diff_pool_tags(&before, &after);
+        assert_eq!(
+            deltas,
+            [
+                PoolTagDelta {
+                    tag: PoolTag::new(b"Proc"),
+                    paged_bytes: 0,
+                    non_paged_bytes: 0x10_0000,
+                    paged_outstanding: 0,
+                    non_paged_outstanding: 64,
+                },
+                PoolTagDelta {
+                    tag: PoolTag::new(b"Leak"),
+                    paged_bytes: 0x8000,
+                    non_paged_bytes: 0,
+                    paged_outstanding: 10,
+                    non_paged_outstanding: 10,
+                },
+                PoolTagDelta {
+                    tag: PoolTag::new(b"File"),
+                    paged_bytes: -0x1000,
+                    non_paged_bytes: 0,
+                    paged_outstanding: -4,
+                    non_paged_outstanding: 0,
+                },
+                PoolTagDelta {
+                    tag: PoolTag::new(b"Toke"),
+                    paged_bytes: -0x1_c000,
+                    non_paged_bytes: 0,
+                    paged_outstanding: -50,
+                    non_paged_outstanding: 0,
+                },
+            ]
+        );
+        assert_eq!(deltas[2].bytes(), -0x1000);
+        assert_eq!(deltas[3].outstanding(), -50);
+
+        // Unchanged tags are left out.
+        assert!(diff_pool_tags(&before, &before).is_empty());
+        // A tag whose bytes stay put but whose allocations change is kept.
+        let mut churned = before.clone();
+        churned[2].paged_allocs += 1;
+        churned[2].paged_frees += 2;
+        let deltas = diff_pool_tags(&before, &churned);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!((deltas[0].bytes(), deltas[0].outstanding()), (0, -1));
+    }
+
+    #[test]
+    fn orders_top_growers() {
+        let before = [usage(b"Aaaa", 0x1000, 0), usage(b"Keep", 0x1000, 0)];
+        let after = [
+            usage(b"Aaaa", 0x3000, 0),
+            usage(b"Zzzz", 0, 0x2000),
+            usage(b"Midl", 0x1000, 0x1000),
+            usage(b"Huge", 0x10_0000, 0),
+        ];
+        let tags = |deltas: Vec<PoolTagDelta>| {
+            deltas
+                .iter()
+                .map(|delta| delta.tag.to_string())
+                .collect::<Vec<_>>()
+        };
+        // Equal growth is ordered by tag; "Keep" only shrank.
+        assert_eq!(
+            tags(top_growers(&before, &after, 10)),
+            ["Huge", "Aaaa", "Midl", "Zzzz"]
+        );
+        assert_eq!(tags(top_growers(&before, &after, 2)), ["Huge", "Aaaa"]);
+        assert!(top_growers(&before, &after, 0).is_empty());
+        assert_eq!(tags(top_growers(&after, &before, 10)), ["Keep"]);
+    }
+}
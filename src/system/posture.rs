@@ -0,0 +1,858 @@
+use std::fmt;
+
+use crate::{
+    buffer::{DecodeError, Reader},
+    ntexapi::SYSTEM_INFORMATION_CLASS,
+};
+
+use super::{
+    Error, InputBuffer, QueryInput, Sizing, SystemBackend, SystemInfo, SystemSecureBootInformation,
+};
+
+/// A named bit field of a flags word.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct FlagField {
+    pub name: &'static str,
+    pub shift: u8,
+    pub width: u8,
+}
+
+impl FlagField {
+    pub const fn bit(name: &'static str, shift: u8) -> Self {
+        Self {
+            name,
+            shift,
+            width: 1,
+        }
+    }
+
+    pub const fn field(name: &'static str, shift: u8, width: u8) -> Self {
+        Self { name, shift, width }
+    }
+
+    pub const fn extract(&self, value: u64) -> u32 {
+        ((value >> self.shift) & ((1 << self.width) - 1)) as u32
+    }
+}
+
+/// `SYSTEM_SPECULATION_CONTROL_INFORMATION`, with the second flags word in
+/// the high 32 bits.
+pub const SPECULATION_CONTROL_FIELDS: &[FlagField] = &[
+    FlagField::bit("bpb_enabled", 0),
+    FlagField::bit("bpb_disabled_system_policy", 1),
+    FlagField::bit("bpb_disabled_no_hardware_support", 2),
+    FlagField::bit("spec_ctrl_enumerated", 3),
+    FlagField::bit("spec_cmd_enumerated", 4),
+    FlagField::bit("ibrs_present", 5),
+    FlagField::bit("stibp_present", 6),
+    FlagField::bit("smep_present", 7),
+    FlagField::bit("ssbd_available", 8),
+    FlagField::bit("ssbd_supported", 9),
+    FlagField::bit("ssbd_system_wide", 10),
+    FlagField::bit("ssbd_kernel", 11),
+    FlagField::bit("ssbd_required", 12),
+    FlagField::bit("bpb_disabled_kernel_to_user", 13),
+    FlagField::bit("retpoline_enabled", 14),
+    FlagField::bit("import_optimization_enabled", 15),
+    FlagField::bit("enhanced_ibrs", 16),
+    FlagField::bit("hv_l1tf_status_available", 17),
+    FlagField::bit("hv_l1tf_processor_not_affected", 18),
+    FlagField::bit("hv_l1tf_mitigation_enabled", 19),
+    FlagField::bit("hv_l1tf_mitigation_not_enabled_hardware", 20),
+    FlagField::bit("hv_l1tf_mitigation_not_enabled_load_option", 21),
+    FlagField::bit("hv_l1tf_mitigation_not_enabled_core_scheduler", 22),
+    FlagField::bit("enhanced_ibrs_reported", 23),
+    FlagField::bit("mds_hardware_protected", 24),
+    FlagField::bit("mb_clear_enabled", 25),
+    FlagField::bit("mb_clear_reported", 26),
+    FlagField::bit("bhb_enabled", 37),
+    FlagField::bit("bhb_disabled_system_policy", 38),
+    FlagField::bit("bhb_disabled_no_hardware_support", 39),
+    FlagField::bit("rdcl_hardware_protected_reported", 43),
+    FlagField::bit("rdcl_hardware_protected", 44),
+];
+
+/// `SYSTEM_KERNEL_VA_SHADOW_INFORMATION`.
+pub const KVA_SHADOW_FIELDS: &[FlagField] = &[
+    FlagField::bit("enabled", 0),
+    FlagField::bit("user_global", 1),
+    FlagField::bit("pcid", 2),
+    FlagField::bit("invpcid", 3),
+    FlagField::bit("required", 4),
+    FlagField::bit("required_available", 5),
+    FlagField::field("invalid_pte_bit", 6, 6),
+    FlagField::bit("l1d_flush_supported", 12),
+    FlagField::bit("l1tf_mitigation_present", 13),
+];
+
+/// The `CODEINTEGRITY_OPTION_*` bits of `SYSTEM_CODEINTEGRITY_INFORMATION`.
+pub const CODE_INTEGRITY_FIELDS: &[FlagField] = &[
+    FlagField::bit("enabled", 0),
+    FlagField::bit("testsign", 1),
+    FlagField::bit("umci_enabled", 2),
+    FlagField::bit("umci_audit_mode", 3),
+    FlagField::bit("umci_exclusion_paths", 4),
+    FlagField::bit("test_build", 5),
+    FlagField::bit("preproduction_build", 6),
+    FlagField::bit("debug_mode", 7),
+    FlagField::bit("flight_build", 8),
+    FlagField::bit("flighting_enabled", 9),
+    FlagField::bit("hvci_kmci_enabled", 10),
+    FlagField::bit("hvci_kmci_audit_mode", 11),
+    FlagField::bit("hvci_kmci_strict_mode", 12),
+    FlagField::bit("hvci_ium_enabled", 13),
+    FlagField::bit("whql_enforcement", 14),
+    FlagField::bit("whql_audit_mode", 15),
+];
+
+/// `SYSTEM_ISOLATED_USER_MODE_INFORMATION`.
+pub const ISOLATED_USER_MODE_FIELDS: &[FlagField] = &[
+    FlagField::bit("secure_kernel_running", 0),
+    FlagField::bit("hvci_enabled", 1),
+    FlagField::bit("hvci_strict_mode", 2),
+    FlagField::bit("debug_enabled", 3),
+    FlagField::bit("firmware_page_protection", 4),
+    FlagField::bit("encryption_key_available", 5),
+    FlagField::bit("trustlet_running", 8),
+    FlagField::bit("hvci_disable_allowed", 9),
+];
+
+/// A flags word split into the fields of a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodedFlags {
+    pub raw: u64,
+    pub fields: Vec<(&'static str, u32)>,
+}
+
+impl DecodedFlags {
+    pub fn new(raw: u64, table: &[FlagField]) -> Self {
+        Self {
+            raw,
+            fields: table
+                .iter()
+                .map(|field| (field.name, field.extract(raw)))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn is_set(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|value| value != 0)
+    }
+}
+
+/// `SYSTEM_SECUREBOOT_INFORMATION`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct SecureBoot {
+    pub enabled: bool,
+    pub capable: bool,
+}
+
+/// `SYSTEM_HYPERVISOR_DETAIL_INFORMATION`: the hypervisor CPUID leaves
+/// 0x40000000 to 0x40000006, as EAX, EBX, ECX and EDX.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HypervisorDetail {
+    pub leaves: [[u32; 4]; 7],
+}
+
+impl HypervisorDetail {
+    /// The vendor signature, e.g. `Microsoft Hv`.
+    pub fn vendor(&self) -> String {
+        let bytes = self.leaves[0][1..]
+            .iter()
+            .flat_map(|register| register.to_le_bytes())
+            .collect::<Vec<_>>();
+        crate::buffer::from_ansi(&bytes)
+    }
+
+    /// The interface signature, e.g. `Hv#1`.
+    pub fn interface(&self) -> String {
+        crate::buffer::from_ansi(&self.leaves[1][0].to_le_bytes())
+    }
+
+    /// The major version, minor version and build number.
+    pub fn version(&self) -> (u16, u16, u32) {
+        let [build, version, ..] = self.leaves[2];
+        ((version >> 16) as u16, version as u16, build)
+    }
+
+    /// The partition privilege mask of leaf 0x40000003.
+    pub fn privileges(&self) -> u64 {
+        self.leaves[3][0] as u64 | (self.leaves[3][1] as u64) << 32
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.leaves[0][0] >= 0x4000_0001
+    }
+}
+
+/// The input of `SystemCodeIntegrityInformation`: the structure length,
+/// written in place.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct CodeIntegrityRequest;
+
+impl QueryInput for CodeIntegrityRequest {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::InPlace(vec![8, 0, 0, 0, 0, 0, 0, 0])
+    }
+}
+
+/// Declares classes whose output is one flags word, read at an offset and
+/// decoded by a table.
+macro_rules! flag_classes {
+    ($($(#[$meta:meta])* $name:ident($input:ty, $size:expr, $offset:expr, $word:ident, $table:expr);)*) => {$(
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub enum $name {}
+
+        impl SystemInfo for $name {
+            const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::$name;
+            const SIZING: Sizing = Sizing::Fixed($size);
+            type Input = $input;
+            type Output = DecodedFlags;
+
+            fn decode(bytes: &[u8]) -> Result<DecodedFlags, DecodeError> {
+                let raw = Reader::at(bytes, $offset).$word()?;
+                Ok(DecodedFlags::new(raw as u64, $table))
+            }
+        }
+    )*};
+}
+
+flag_classes! {
+    /// Both flags words, the second in the high half.
+    SystemSpeculationControlInformation((), 8, 0, u64, SPECULATION_CONTROL_FIELDS);
+    SystemKernelVaShadowInformation((), 4, 0, u32, KVA_SHADOW_FIELDS);
+    /// The options after the in-place `Length`.
+    SystemCodeIntegrityInformation(CodeIntegrityRequest, 8, 4, u32, CODE_INTEGRITY_FIELDS);
+    SystemIsolatedUserModeInformation((), 16, 0, u16, ISOLATED_USER_MODE_FIELDS);
+}
+
+#[derive(Debug)]
+pub enum SystemDmaGuardPolicyInformation {}
+
+impl SystemInfo for SystemDmaGuardPolicyInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemDmaGuardPolicyInformation;
+    const SIZING: Sizing = Sizing::Fixed(1);
+    type Input = ();
+    type Output = bool;
+
+    fn decode(bytes: &[u8]) -> Result<bool, DecodeError> {
+        Ok(Reader::new(bytes).u8()? != 0)
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemHypervisorDetailInformation {}
+
+impl SystemInfo for SystemHypervisorDetailInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemHypervisorDetailInformation;
+    const SIZING: Sizing = Sizing::Fixed(7 * 16);
+    type Input = ();
+    type Output = HypervisorDetail;
+
+    fn decode(bytes: &[u8]) -> Result<HypervisorDetail, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let mut detail = HypervisorDetail::default();
+        for register in detail.leaves.iter_mut().flatten() {
+            *register = reader.u32()?;
+        }
+        Ok(detail)
+    }
+}
+
+/// A report value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Number(u64),
+    Text(String),
+    /// The class could not be queried.
+    Missing,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => value.fmt(f),
+            Self::Number(value) => value.fmt(f),
+            Self::Text(value) => f.write_str(&json_string(value)),
+            Self::Missing => f.write_str("null"),
+        }
+    }
+}
+
+/// Quotes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The hardware security and mitigation state of the system. A class the
+/// system does not support leaves its part `None` and is listed in
+/// `unavailable`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecurityPosture {
+    pub speculation_control: Option<DecodedFlags>,
+    pub kva_shadow: Option<DecodedFlags>,
+    pub code_integrity: Option<DecodedFlags>,
+    pub isolated_user_mode: Option<DecodedFlags>,
+    pub secure_boot: Option<SecureBoot>,
+    pub dma_guard_policy: Option<bool>,
+    pub hypervisor: Option<HypervisorDetail>,
+    pub unavailable: Vec<(SYSTEM_INFORMATION_CLASS, Error)>,
+}
+
+impl SecurityPosture {
+    /// Queries every class through `backend`.
+    pub fn query<B: SystemBackend + ?Sized>(backend: &B) -> Self {
+        fn take<T: SystemInfo>(
+            result: Result<T::Output, Error>,
+            unavailable: &mut Vec<(SYSTEM_INFORMATION_CLASS, Error)>,
+        ) -> Option<T::Output> {
+            result
+                .map_err(|error| unavailable.push((T::CLASS, error)))
+                .ok()
+        }
+        let mut posture = Self::default();
+        let missing = &mut posture.unavailable;
+        posture.speculation_control = take::<SystemSpeculationControlInformation>(
+            backend.query_info::<SystemSpeculationControlInformation>(&()),
+            missing,
+        );
+        posture.kva_shadow = take::<SystemKernelVaShadowInformation>(
+            backend.query_info::<SystemKernelVaShadowInformation>(&()),
+            missing,
+        );
+        posture.code_integrity = take::<SystemCodeIntegrityInformation>(
+            backend.query_info::<SystemCodeIntegrityInformation>(&CodeIntegrityRequest),
+            missing,
+        );
+        posture.isolated_user_mode = take::<SystemIsolatedUserModeInformation>(
+            backend.query_info::<SystemIsolatedUserModeInformation>(&()),
+            missing,
+        );
+        posture.secure_boot = take::<SystemSecureBootInformation>(
+            backend.query_info::<SystemSecureBootInformation>(&()),
+            missing,
+        )
+        .map(|raw| SecureBoot {
+            enabled: raw.SecureBootEnabled.as_bool(),
+            capable: raw.SecureBootCapable.as_bool(),
+        });
+        posture.dma_guard_policy = take::<SystemDmaGuardPolicyInformation>(
+            backend.query_info::<SystemDmaGuardPolicyInformation>(&()),
+            missing,
+        );
+        posture.hypervisor = take::<SystemHypervisorDetailInformation>(
+            backend.query_info::<SystemHypervisorDetailInformation>(&()),
+            missing,
+        );
+        posture
+    }
+
+    /// Whether kernel page table isolation is in use.
+    pub fn kva_shadow_active(&self) -> Option<bool> {
+        Some(self.kva_shadow.as_ref()?.is_set("enabled"))
+    }
+
+    pub fn retpoline_enabled(&self) -> Option<bool> {
+        Some(
+            self.speculation_control
+                .as_ref()?
+                .is_set("retpoline_enabled"),
+        )
+    }
+
+    /// Whether hypervisor-enforced code integrity is running, from either
+    /// class that reports it.
+    pub fn hvci_running(&self) -> Option<bool> {
+        let code_integrity = self
+            .code_integrity
+            .as_ref()
+            .map(|flags| flags.is_set("hvci_kmci_enabled"));
+        let isolated = self
+            .isolated_user_mode
+            .as_ref()
+            .map(|flags| flags.is_set("hvci_enabled"));
+        match (code_integrity, isolated) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or_default() || b.unwrap_or_default()),
+        }
+    }
+
+    pub fn secure_boot_enabled(&self) -> Option<bool> {
+        self.secure_boot.map(|secure_boot| secure_boot.enabled)
+    }
+
+    /// Whether kernel DMA protection is on.
+    pub fn dma_protection(&self) -> Option<bool> {
+        self.dma_guard_policy
+    }
+
+    /// The report as flat `section.field` entries, in a fixed order. Parts
+    /// that could not be queried give a single [`Value::Missing`] entry.
+    pub fn fields(&self) -> Vec<(String, Value)> {
+        let option = |value: Option<bool>| value.map_or(Value::Missing, Value::Bool);
+        let mut fields = vec![
+            (
+                "kva_shadow_active".to_owned(),
+                option(self.kva_shadow_active()),
+            ),
+            (
+                "retpoline_enabled".to_owned(),
+                option(self.retpoline_enabled()),
+            ),
+            ("hvci_running".to_owned(), option(self.hvci_running())),
+            (
+                "secure_boot_enabled".to_owned(),
+                option(self.secure_boot_enabled()),
+            ),
+            ("dma_protection".to_owned(), option(self.dma_protection())),
+        ];
+        let sections = [
+            ("speculation_control", &self.speculation_control),
+            ("kva_shadow", &self.kva_shadow),
+            ("code_integrity", &self.code_integrity),
+            ("isolated_user_mode", &self.isolated_user_mode),
+        ];
+        for (section, flags) in sections {
+            match flags {
+                Some(flags) => {
+                    for (name, value) in &flags.fields {
+                        let value = if *value > 1 {
+                            Value::Number(*value as u64)
+                        } else {
+                            Value::Bool(*value != 0)
+                        };
+                        fields.push((format!("{section}.{name}"), value));
+                    }
+                }
+                None => fields.push((section.to_owned(), Value::Missing)),
+            }
+        }
+        match self.secure_boot {
+            Some(secure_boot) => {
+                fields.push((
+                    "secure_boot.enabled".to_owned(),
+                    Value::Bool(secure_boot.enabled),
+                ));
+                fields.push((
+                    "secure_boot.capable".to_owned(),
+                    Value::Bool(secure_boot.capable),
+                ));
+            }
+            None => fields.push(("secure_boot".to_owned(), Value::Missing)),
+        }
+        match &self.hypervisor {
+            Some(hypervisor) if hypervisor.is_present() => {
+                let (major, minor, build) = hypervisor.version();
+                fields.push((
+                    "hypervisor.vendor".to_owned(),
+                    Value::Text(hypervisor.vendor()),
+                ));
+                fields.push((
+                    "hypervisor.interface".to_owned(),
+                    Value::Text(hypervisor.interface()),
+                ));
+                fields.push((
+                    "hypervisor.version".to_owned(),
+                    Value::Text(format!("{major}.{minor}.{build}")),
+                ));
+                fields.push((
+                    "hypervisor.privileges".to_owned(),
+                    Value::Number(hypervisor.privileges()),
+                ));
+            }
+            Some(_) => fields.push(("hypervisor.vendor".to_owned(), Value::Text(String::new()))),
+            None => fields.push(("hypervisor".to_owned(), Value::Missing)),
+        }
+        fields
+    }
+
+    /// The report as a flat JSON object of [`fields`](Self::fields).
+    pub fn to_json(&self) -> String {
+        let entries = self
+            .fields()
+            .into_iter()
+            .map(|(name, value)| format!("{}:{value}", json_string(&name)))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", entries.join(","))
+    }
+}
+
+/// One `field = value` line per entry of [`SecurityPosture::fields`].
+impl fmt::Display for SecurityPosture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.fields() {
+            writeln!(f, "{name} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::Foundation::{NTSTATUS, STATUS_INVALID_INFO_CLASS, STATUS_NOT_SUPPORTED};
+
+    use super::{super::QueryFailure, *};
+    use crate::buffer::Writer;
+
+    /// The names of the set fields, in table order.
+    fn set_fields(flags: &DecodedFlags) -> Vec<&'static str> {
+        flags
+            .fields
+            .iter()
+            .filter(|(_, value)| *value != 0)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    #[test]
+    fn tables_have_distinct_non_overlapping_fields() {
+        for table in [
+            SPECULATION_CONTROL_FIELDS,
+            KVA_SHADOW_FIELDS,
+            CODE_INTEGRITY_FIELDS,
+            ISOLATED_USER_MODE_FIELDS,
+        ] {
+            let mut used = 0_u64;
+            for (index, field) in table.iter().enumerate() {
+                assert!(field.shift + field.width <= 64, "{}", field.name);
+                let mask = ((1_u64 << field.width) - 1) << field.shift;
+                assert_eq!(used & mask, 0, "{} overlaps", field.name);
+                used |= mask;
+                assert!(
+                    table[..index].iter().all(|other| other.name != field.name),
+                    "{} repeats",
+                    field.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn extracts_fields() {
+        assert_eq!(FlagField::bit("low", 0).extract(0b10), 0);
+        assert_eq!(FlagField::bit("high", 63).extract(1 << 63), 1);
+        assert_eq!(FlagField::field("nibble", 4, 4).extract(0xab), 0xa);
+        assert_eq!(FlagField::field("pte", 6, 6).extract(u64::MAX), 0x3f);
+    }
+
+    #[test]
+    fn decodes_speculation_control() {
+        // An Intel part with enhanced IBRS and no Meltdown exposure: BPB
+        // enabled, SPEC_CTRL and PRED_CMD enumerated, IBRS, STIBP and SMEP
+        // present, SSBD available and supported, enhanced IBRS in use and
+        // reported, MDS hardware protected, then BHB enabled and RDCL
+        // hardware protection (reported) in the second word.
+        let raw = 0b11_u64 << 43 | 1 << 37 | 0x0181_03f9;
+        let flags = SystemSpeculationControlInformation::decode(&raw.to_le_bytes()).unwrap();
+        assert_eq!(flags.raw, raw);
+        assert_eq!(flags.fields.len(), SPECULATION_CONTROL_FIELDS.len());
+        assert_eq!(
+            set_fields(&flags),
+            [
+                "bpb_enabled",
+                "spec_ctrl_enumerated",
+                "spec_cmd_enumerated",
+                "ibrs_present",
+                "stibp_present",
+                "smep_present",
+                "ssbd_available",
+                "ssbd_supported",
+                "enhanced_ibrs",
+                "enhanced_ibrs_reported",
+                "mds_hardware_protected",
+                "bhb_enabled",
+                "rdcl_hardware_protected_reported",
+                "rdcl_hardware_protected",
+            ]
+        );
+        assert!(!flags.is_set("retpoline_enabled"));
+        assert_eq!(flags.get("no_such_field"), None);
+        // Only the first word.
+        assert!(SystemSpeculationControlInformation::decode(&[0; 4]).is_err());
+    }
+
+    #[test]
+    fn decodes_kva_shadow() {
+        // An affected Intel part: shadowing enabled with PCID and INVPCID,
+        // required, invalid PTE bit 45 and the L1TF mitigation present.
+        let raw = 0x3b5d_u32;
+        let flags = SystemKernelVaShadowInformation::decode(&raw.to_le_bytes()).unwrap();
+        assert_eq!(
+            flags.fields,
+            [
+                ("enabled", 1),
+                ("user_global", 0),
+                ("pcid", 1),
+                ("invpcid", 1),
+                ("required", 1),
+                ("required_available", 0),
+                ("invalid_pte_bit", 45),
+                ("l1d_flush_supported", 1),
+                ("l1tf_mitigation_present", 1),
+            ]
+        );
+        // An unaffected part reports only that the requirement is known.
+        let flags = SystemKernelVaShadowInformation::decode(&0x20_u32.to_le_bytes()).unwrap();
+        assert_eq!(set_fields(&flags), ["required_available"]);
+    }
+
+    #[test]
+    fn decodes_code_integrity() {
+        // The options follow the in-place length: enabled, with HVCI and the
+        // secure kernel running.
+        let bytes = [8, 0, 0, 0, 0x01, 0x24, 0, 0];
+        let flags = SystemCodeIntegrityInformation::decode(&bytes).unwrap();
+        assert_eq!(flags.raw, 0x2401);
+        assert_eq!(
+            set_fields(&flags),
+            ["enabled", "hvci_kmci_enabled", "hvci_ium_enabled"]
+        );
+        // Test signing on a flighted debug build; bits past the table are
+        // kept in the raw word only.
+        let bytes = [8, 0, 0, 0, 0x83, 0x03, 0x01, 0];
+        let flags = SystemCodeIntegrityInformation::decode(&bytes).unwrap();
+        assert_eq!(flags.raw, 0x1_0383);
+        assert_eq!(
+            set_fields(&flags),
+            [
+                "enabled",
+                "testsign",
+                "debug_mode",
+                "flight_build",
+                "flighting_enabled",
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_isolated_user_mode() {
+        let mut bytes = [0; 16];
+        bytes[..2].copy_from_slice(&0x0103_u16.to_le_bytes());
+        let flags = SystemIsolatedUserModeInformation::decode(&bytes).unwrap();
+        assert_eq!(
+            set_fields(&flags),
+            ["secure_kernel_running", "hvci_enabled", "trustlet_running"]
+        );
+    }
+
+    /// Leaves 0x40000000 to 0x40000006 of Hyper-V 10.0.22631 with `vendor`
+    /// in the signature registers.
+    fn hypervisor_leaves(vendor: &[u8; 12]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.u32(0x4000_0006).bytes(vendor);
+        writer.bytes(b"Hv#1").zeros(12);
+        writer.u32(22631).u32(10 << 16).zeros(8);
+        writer.u32(0x2bfe).u32(0x3_b8b0).zeros(8);
+        writer.zeros(3 * 16);
+        writer.into_inner()
+    }
+
+    #[test]
+    fn decodes_hypervisor_detail() {
+        let detail =
+            SystemHypervisorDetailInformation::decode(&hypervisor_leaves(b"Microsoft Hv")).unwrap();
+        assert!(detail.is_present());
+        assert_eq!(detail.vendor(), "Microsoft Hv");
+        assert_eq!(detail.interface(), "Hv#1");
+        assert_eq!(detail.version(), (10, 0, 22631));
+        assert_eq!(detail.privileges(), 0x3_b8b0_0000_2bfe);
+        assert!(!HypervisorDetail::default().is_present());
+    }
+
+    fn posture() -> SecurityPosture {
+        SecurityPosture {
+            kva_shadow: Some(DecodedFlags::new(0x3b5d, KVA_SHADOW_FIELDS)),
+            isolated_user_mode: Some(DecodedFlags::new(0x2, ISOLATED_USER_MODE_FIELDS)),
+            secure_boot: Some(SecureBoot {
+                enabled: false,
+                capable: true,
+            }),
+            dma_guard_policy: Some(true),
+            ..SecurityPosture::default()
+        }
+    }
+
+    #[test]
+    fn summarizes_posture() {
+        let posture = posture();
+        assert_eq!(posture.kva_shadow_active(), Some(true));
+        assert_eq!(posture.retpoline_enabled(), None);
+        // Reported by the isolated user mode class alone.
+        assert_eq!(posture.hvci_running(), Some(true));
+        assert_eq!(posture.secure_boot_enabled(), Some(false));
+        assert_eq!(posture.dma_protection(), Some(true));
+        assert_eq!(SecurityPosture::default().hvci_running(), None);
+    }
+
+    #[test]
+    fn writes_null_for_unavailable_classes() {
+        assert_eq!(
+            SecurityPosture::default().to_json(),
+            concat!(
+                r#"{"kva_shadow_active":null,"retpoline_enabled":null,"#,
+                r#""hvci_running":null,"secure_boot_enabled":null,"#,
+                r#""dma_protection":null,"speculation_control":null,"#,
+                r#""kva_shadow":null,"code_integrity":null,"#,
+                r#""isolated_user_mode":null,"secure_boot":null,"#,
+                r#""hypervisor":null}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let json = posture().to_json();
+        assert!(json.starts_with(concat!(
+            r#"{"kva_shadow_active":true,"retpoline_enabled":null,"#,
+            r#""hvci_running":true,"secure_boot_enabled":false,"#,
+            r#""dma_protection":true,"speculation_control":null,"#,
+            r#""kva_shadow.enabled":true,"kva_shadow.user_global":false,"#,
+        )));
+        assert!(json.contains(r#","kva_shadow.invalid_pte_bit":45,"#));
+        assert!(json.contains(concat!(
+            r#","code_integrity":null,"#,
+            r#""isolated_user_mode.secure_kernel_running":false,"#,
+            r#""isolated_user_mode.hvci_enabled":true,"#,
+        )));
+        assert!(json.ends_with(
+            r#","secure_boot.enabled":false,"secure_boot.capable":true,"hypervisor":null}"#
+        ));
+        assert_eq!(
+            posture().to_string().lines().nth(7),
+            Some("kva_shadow.user_global = false")
+        );
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string(""), r#""""#);
+        assert_eq!(json_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(json_string("\n\t\u{1f}"), r#""\u000a\u0009\u001f""#);
+        assert_eq!(json_string("r\u{e9}seau \u{7f}"), "\"r\u{e9}seau \u{7f}\"");
+        assert_eq!(Value::Text("\"".to_owned()).to_string(), r#""\"""#);
+        assert_eq!(Value::Number(45).to_string(), "45");
+        assert_eq!(Value::Missing.to_string(), "null");
+
+        // A hypervisor vendor is whatever the CPUID registers hold.
+        let hypervisor =
+            SystemHypervisorDetailInformation::decode(&hypervisor_leaves(b"Odd\"\\Vendo\x01r"))
+                .unwrap();
+        let posture = SecurityPosture {
+            hypervisor: Some(hypervisor),
+            ..SecurityPosture::default()
+        };
+        assert!(posture.to_json().ends_with(concat!(
+            r#","hypervisor.vendor":"Odd\"\\Vendo\u0001r","#,
+            r#""hypervisor.interface":"Hv#1","hypervisor.version":"10.0.22631","#,
+            r#""hypervisor.privileges":1047490983898110}"#,
+        )));
+    }
+
+    /// Answers each class with a fixture, failing `missing`.
+    struct Fixtures {
+        missing: SYSTEM_INFORMATION_CLASS,
+        status: NTSTATUS,
+        code_integrity_length: RefCell<Option<u32>>,
+    }
+
+    impl SystemBackend for Fixtures {
+        fn query(
+            &self,
+            class: SYSTEM_INFORMATION_CLASS,
+            _input: Option<&[u8]>,
+            buffer: &mut [u64],
+        ) -> Result<usize, QueryFailure> {
+            if class == self.missing {
+                return Err(QueryFailure {
+                    status: self.status,
+                    required: 0,
+                });
+            }
+            let buffer = super::super::as_bytes_mut(buffer);
+            let output = match class {
+                SYSTEM_INFORMATION_CLASS::SystemSpeculationControlInformation => {
+                    (1_u64 << 14).to_le_bytes().to_vec()
+                }
+                SYSTEM_INFORMATION_CLASS::SystemKernelVaShadowInformation => {
+                    0x20_u32.to_le_bytes().to_vec()
+                }
+                SYSTEM_INFORMATION_CLASS::SystemCodeIntegrityInformation => {
+                    let length = u32::from_le_bytes(buffer[..4].try_into().unwrap());
+                    *self.code_integrity_length.borrow_mut() = Some(length);
+                    let mut output = buffer[..4].to_vec();
+                    output.extend(0x401_u32.to_le_bytes());
+                    output
+                }
+                SYSTEM_INFORMATION_CLASS::SystemIsolatedUserModeInformation => vec![0; 16],
+                SYSTEM_INFORMATION_CLASS::SystemSecureBootInformation => vec![1, 1],
+                SYSTEM_INFORMATION_CLASS::SystemDmaGuardPolicyInformation => vec![0],
+                SYSTEM_INFORMATION_CLASS::SystemHypervisorDetailInformation => {
+                    hypervisor_leaves(b"Microsoft Hv")
+                }
+                _ => unreachable!("unexpected class {class:?}"),
+            };
+            buffer[..output.len()].copy_from_slice(&output);
+            Ok(output.len())
+        }
+    }
+
+    #[test]
+    fn queries_every_class() {
+        let backend = Fixtures {
+            missing: SYSTEM_INFORMATION_CLASS::SystemIsolatedUserModeInformation,
+            status: STATUS_INVALID_INFO_CLASS,
+            code_integrity_length: RefCell::default(),
+        };
+        let posture = SecurityPosture::query(&backend);
+        assert_eq!(*backend.code_integrity_length.borrow(), Some(8));
+        assert_eq!(posture.retpoline_enabled(), Some(true));
+        assert_eq!(posture.kva_shadow_active(), Some(false));
+        assert_eq!(posture.hvci_running(), Some(true));
+        assert_eq!(posture.secure_boot_enabled(), Some(true));
+        assert_eq!(posture.dma_protection(), Some(false));
+        assert_eq!(posture.isolated_user_mode, None);
+        assert_eq!(
+            posture.unavailable,
+            [(
+                SYSTEM_INFORMATION_CLASS::SystemIsolatedUserModeInformation,
+                Error::Status(STATUS_INVALID_INFO_CLASS),
+            )]
+        );
+        let json = posture.to_json();
+        assert!(json.contains(r#","isolated_user_mode":null,"#), "{json}");
+        assert!(json.contains(r#""hypervisor.vendor":"Microsoft Hv""#));
+
+        let backend = Fixtures {
+            missing: SYSTEM_INFORMATION_CLASS::SystemHypervisorDetailInformation,
+            status: STATUS_NOT_SUPPORTED,
+            code_integrity_length: RefCell::default(),
+        };
+        let posture = SecurityPosture::query(&backend);
+        assert_eq!(posture.unavailable.len(), 1);
+        assert!(posture.to_json().ends_with(r#","hypervisor":null}"#));
+    }
+}
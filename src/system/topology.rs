@@ -0,0 +1,1306 @@
+use std::{collections::BTreeSet, mem};
+
+use windows::Win32::{
+    Foundation::{HANDLE, WIN32_ERROR},
+    System::{
+        SystemInformation::{
+            CPU_SET_INFORMATION_TYPE, CpuSetInformation, LOGICAL_PROCESSOR_RELATIONSHIP,
+            PROCESSOR_CACHE_TYPE, RelationAll, RelationCache, RelationGroup, RelationNumaNode,
+            RelationNumaNodeEx, RelationProcessorCore, RelationProcessorDie,
+            RelationProcessorModule, RelationProcessorPackage,
+        },
+        Threading::{
+            GetProcessDefaultCpuSets, GetThreadSelectedCpuSets, SetProcessDefaultCpuSets,
+            SetThreadSelectedCpuSets,
+        },
+    },
+};
+
+use crate::{
+    buffer::{DecodeError, Reader},
+    ntexapi::SYSTEM_INFORMATION_CLASS,
+    ntkeapi::KHETERO_CPU_POLICY,
+    ntrtl::RtlGetLastWin32Error,
+};
+
+use super::{Error, InputBuffer, QueryInput, Sizing, SystemBackend, SystemInfo};
+
+/// `SYSTEM_CPU_SET_INFORMATION::CpuSet::AllFlags`.
+#[derive(Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct CpuSetFlags(pub u8);
+
+impl CpuSetFlags {
+    pub const PARKED: Self = Self(0x1);
+    pub const ALLOCATED: Self = Self(0x2);
+    pub const ALLOCATED_TO_TARGET_PROCESS: Self = Self(0x4);
+    pub const REAL_TIME: Self = Self(0x8);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for CpuSetFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for CpuSetFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::fmt::Debug for CpuSetFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CpuSetFlags({:#04x})", self.0)
+    }
+}
+
+/// A logical processor as a group and a number within it.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProcessorNumber {
+    pub group: u16,
+    pub number: u8,
+}
+
+/// A CPU set, from a `SYSTEM_CPU_SET_INFORMATION` record.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct CpuSet {
+    pub id: u32,
+    pub group: u16,
+    pub logical_processor_index: u8,
+    pub core_index: u8,
+    pub last_level_cache_index: u8,
+    pub numa_node_index: u8,
+    /// Higher classes are faster and draw more power.
+    pub efficiency_class: u8,
+    pub flags: CpuSetFlags,
+    pub scheduling_class: u8,
+    pub allocation_tag: u64,
+}
+
+impl CpuSet {
+    pub fn processor(&self) -> ProcessorNumber {
+        ProcessorNumber {
+            group: self.group,
+            number: self.logical_processor_index,
+        }
+    }
+}
+
+/// Decodes the `SYSTEM_CPU_SET_INFORMATION` records of
+/// `SystemCpuSetInformation`. Each record gives its own size; records of
+/// other types are skipped.
+pub fn parse_cpu_sets(bytes: &[u8]) -> Result<Vec<CpuSet>, DecodeError> {
+    let mut sets = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let mut reader = Reader::at(bytes, offset);
+        let size = reader.u32()? as usize;
+        let kind = CPU_SET_INFORMATION_TYPE(reader.i32()?);
+        if size < 8 {
+            return Err(reader.invalid("CPU set record size"));
+        }
+        let mut reader = Reader::new(Reader::at(bytes, offset).bytes(size)?);
+        reader.skip(8)?;
+        if kind == CpuSetInformation {
+            let id = reader.u32()?;
+            let group = reader.u16()?;
+            let logical_processor_index = reader.u8()?;
+            let core_index = reader.u8()?;
+            let last_level_cache_index = reader.u8()?;
+            let numa_node_index = reader.u8()?;
+            let efficiency_class = reader.u8()?;
+            let flags = CpuSetFlags(reader.u8()?);
+            let scheduling_class = reader.u8()?;
+            reader.seek(24)?;
+            let allocation_tag = reader.u64()?;
+            sets.push(CpuSet {
+                id,
+                group,
+                logical_processor_index,
+                core_index,
+                last_level_cache_index,
+                numa_node_index,
+                efficiency_class,
+                flags,
+                scheduling_class,
+                allocation_tag,
+            });
+        }
+        offset += size;
+    }
+    Ok(sets)
+}
+
+/// A `GROUP_AFFINITY`: a set of processors in one group.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct GroupAffinity {
+    pub group: u16,
+    pub mask: u64,
+}
+
+impl GroupAffinity {
+    pub fn processors(&self) -> impl Iterator<Item = ProcessorNumber> + '_ {
+        (0..64)
+            .filter(|bit| self.mask & (1 << bit) != 0)
+            .map(|number| ProcessorNumber {
+                group: self.group,
+                number,
+            })
+    }
+
+    pub fn contains(&self, processor: ProcessorNumber) -> bool {
+        processor.group == self.group
+            && processor.number < 64
+            && self.mask & (1 << processor.number) != 0
+    }
+
+    fn read(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let mask = reader.usize()? as u64;
+        let group = reader.u16()?;
+        reader.skip(6)?;
+        Ok(Self { group, mask })
+    }
+
+    fn read_all(reader: &mut Reader<'_>, count: usize) -> Result<Vec<Self>, DecodeError> {
+        // A GROUP_AFFINITY: the mask, the group and three reserved words.
+        reader.check_count(count, mem::size_of::<usize>() + 8)?;
+        (0..count).map(|_| Self::read(reader)).collect()
+    }
+}
+
+/// A `PROCESSOR_RELATIONSHIP`: a core, package, die or module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessorRelationship {
+    /// Set when a core runs more than one logical processor.
+    pub smt: bool,
+    pub efficiency_class: u8,
+    pub groups: Vec<GroupAffinity>,
+}
+
+/// A `NUMA_NODE_RELATIONSHIP`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NumaNode {
+    pub number: u32,
+    pub groups: Vec<GroupAffinity>,
+}
+
+/// A `CACHE_RELATIONSHIP`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cache {
+    pub level: u8,
+    /// 0xff for a fully associative cache.
+    pub associativity: u8,
+    pub line_size: u16,
+    pub size: u32,
+    pub cache_type: PROCESSOR_CACHE_TYPE,
+    pub groups: Vec<GroupAffinity>,
+}
+
+/// A `PROCESSOR_GROUP_INFO`.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct GroupInfo {
+    pub maximum_processors: u8,
+    pub active_processors: u8,
+    pub active_mask: u64,
+}
+
+/// A `SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX` record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessorRecord {
+    Core(ProcessorRelationship),
+    Package(ProcessorRelationship),
+    Die(ProcessorRelationship),
+    Module(ProcessorRelationship),
+    NumaNode(NumaNode),
+    Cache(Cache),
+    /// The active and maximum group counts, and each group.
+    Group {
+        maximum_groups: u16,
+        active_groups: u16,
+        groups: Vec<GroupInfo>,
+    },
+    /// A relationship this version does not know, with the bytes after the
+    /// record header.
+    Other {
+        relationship: LOGICAL_PROCESSOR_RELATIONSHIP,
+        data: Vec<u8>,
+    },
+}
+
+impl ProcessorRecord {
+    fn read(
+        relationship: LOGICAL_PROCESSOR_RELATIONSHIP,
+        body: &[u8],
+    ) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(body);
+        let processor = |reader: &mut Reader<'_>| -> Result<_, DecodeError> {
+            let flags = reader.u8()?;
+            let efficiency_class = reader.u8()?;
+            reader.skip(20)?;
+            let count = reader.u16()? as usize;
+            Ok(ProcessorRelationship {
+                smt: flags & 1 != 0,
+                efficiency_class,
+                groups: GroupAffinity::read_all(reader, count)?,
+            })
+        };
+        Ok(match relationship {
+            RelationProcessorCore => Self::Core(processor(&mut reader)?),
+            RelationProcessorPackage => Self::Package(processor(&mut reader)?),
+            RelationProcessorDie => Self::Die(processor(&mut reader)?),
+            RelationProcessorModule => Self::Module(processor(&mut reader)?),
+            RelationNumaNode | RelationNumaNodeEx => {
+                let number = reader.u32()?;
+                reader.skip(18)?;
+                // Before Windows 11 the count is zero and one mask follows.
+                let count = reader.u16()?.max(1) as usize;
+                Self::NumaNode(NumaNode {
+                    number,
+                    groups: GroupAffinity::read_all(&mut reader, count)?,
+                })
+            }
+            RelationCache => {
+                let level = reader.u8()?;
+                let associativity = reader.u8()?;
+                let line_size = reader.u16()?;
+                let size = reader.u32()?;
+                let cache_type = PROCESSOR_CACHE_TYPE(reader.i32()?);
+                reader.skip(18)?;
+                let count = reader.u16()?.max(1) as usize;
+                Self::Cache(Cache {
+                    level,
+                    associativity,
+                    line_size,
+                    size,
+                    cache_type,
+                    groups: GroupAffinity::read_all(&mut reader, count)?,
+                })
+            }
+            RelationGroup => {
+                let maximum_groups = reader.u16()?;
+                let active_groups = reader.u16()?;
+                reader.skip(20)?;
+                let groups = (0..active_groups)
+                    .map(|_| {
+                        let maximum_processors = reader.u8()?;
+                        let active_processors = reader.u8()?;
+                        reader.skip(38)?;
+                        let active_mask = reader.usize()? as u64;
+                        Ok(GroupInfo {
+                            maximum_processors,
+                            active_processors,
+                            active_mask,
+                        })
+                    })
+                    .collect::<Result<_, DecodeError>>()?;
+                Self::Group {
+                    maximum_groups,
+                    active_groups,
+                    groups,
+                }
+            }
+            relationship => Self::Other {
+                relationship,
+                data: body.to_vec(),
+            },
+        })
+    }
+}
+
+/// Decodes the `SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX` records of
+/// `SystemLogicalProcessorAndGroupInformation`, each sized by its header.
+pub fn parse_logical_processors(bytes: &[u8]) -> Result<Vec<ProcessorRecord>, DecodeError> {
+    let mut records = Vec::new();
+    let mut reader = Reader::new(bytes);
+    while !reader.is_empty() {
+        let relationship = LOGICAL_PROCESSOR_RELATIONSHIP(reader.i32()?);
+        let size = reader.u32()? as usize;
+        let body = size
+            .checked_sub(8)
+            .ok_or_else(|| reader.invalid("logical processor record size"))?;
+        records.push(ProcessorRecord::read(relationship, reader.bytes(body)?)?);
+    }
+    Ok(records)
+}
+
+/// A processor core and the logical processors it runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Core {
+    pub efficiency_class: u8,
+    /// The SMT siblings, in order.
+    pub processors: Vec<ProcessorNumber>,
+    /// The CPU set of each processor, where the CPU set records name one.
+    pub cpu_set_ids: Vec<u32>,
+}
+
+/// The processor topology: groups, NUMA nodes, packages, cores, caches and
+/// CPU sets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub groups: Vec<GroupInfo>,
+    pub nodes: Vec<NumaNode>,
+    pub packages: Vec<ProcessorRelationship>,
+    pub cores: Vec<Core>,
+    pub caches: Vec<Cache>,
+    pub cpu_sets: Vec<CpuSet>,
+}
+
+impl Topology {
+    /// Builds the topology from decoded logical processor and CPU set
+    /// records.
+    pub fn new(records: Vec<ProcessorRecord>, cpu_sets: Vec<CpuSet>) -> Self {
+        let mut topology = Self {
+            cpu_sets,
+            ..Self::default()
+        };
+        for record in records {
+            match record {
+                ProcessorRecord::Core(core) => {
+                    let processors = core
+                        .groups
+                        .iter()
+                        .flat_map(|affinity| affinity.processors().collect::<Vec<_>>())
+                        .collect::<Vec<_>>();
+                    let cpu_set_ids = processors
+                        .iter()
+                        .filter_map(|processor| topology.cpu_set(*processor).map(|set| set.id))
+                        .collect();
+                    topology.cores.push(Core {
+                        efficiency_class: core.efficiency_class,
+                        processors,
+                        cpu_set_ids,
+                    });
+                }
+                ProcessorRecord::Package(package) => topology.packages.push(package),
+                // Windows 11 reports each node under both relationships.
+                ProcessorRecord::NumaNode(node) if !topology.nodes.contains(&node) => {
+                    topology.nodes.push(node)
+                }
+                ProcessorRecord::Cache(cache) => topology.caches.push(cache),
+                ProcessorRecord::Group { groups, .. } => topology.groups = groups,
+                _ => {}
+            }
+        }
+        topology
+    }
+
+    /// Queries both record kinds through `backend`, with the CPU sets seen
+    /// by every process.
+    pub fn query<B: SystemBackend + ?Sized>(backend: &B) -> Result<Self, Error> {
+        let records =
+            backend.query_info::<SystemLogicalProcessorAndGroupInformation>(&RelationAll)?;
+        let cpu_sets = backend.query_info::<SystemCpuSetInformation>(&HANDLE::default())?;
+        Ok(Self::new(records, cpu_sets))
+    }
+
+    pub fn logical_processors(&self) -> impl Iterator<Item = ProcessorNumber> + '_ {
+        self.cores
+            .iter()
+            .flat_map(|core| core.processors.iter().copied())
+    }
+
+    /// The CPU set of `processor`.
+    pub fn cpu_set(&self, processor: ProcessorNumber) -> Option<&CpuSet> {
+        self.cpu_sets
+            .iter()
+            .find(|set| set.processor() == processor)
+    }
+
+    /// The core running `processor`.
+    pub fn core_of(&self, processor: ProcessorNumber) -> Option<&Core> {
+        self.cores
+            .iter()
+            .find(|core| core.processors.contains(&processor))
+    }
+
+    /// The other logical processors on the core of `processor`.
+    pub fn siblings(&self, processor: ProcessorNumber) -> Vec<ProcessorNumber> {
+        self.core_of(processor)
+            .map(|core| {
+                core.processors
+                    .iter()
+                    .copied()
+                    .filter(|sibling| *sibling != processor)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The NUMA node `processor` belongs to.
+    pub fn node_of(&self, processor: ProcessorNumber) -> Option<&NumaNode> {
+        self.nodes.iter().find(|node| {
+            node.groups
+                .iter()
+                .any(|affinity| affinity.contains(processor))
+        })
+    }
+
+    /// The caches shared by `processor`, lowest level first.
+    pub fn caches_of(&self, processor: ProcessorNumber) -> Vec<&Cache> {
+        let mut caches = self
+            .caches
+            .iter()
+            .filter(|cache| {
+                cache
+                    .groups
+                    .iter()
+                    .any(|affinity| affinity.contains(processor))
+            })
+            .collect::<Vec<_>>();
+        caches.sort_by_key(|cache| (cache.level, cache.cache_type.0));
+        caches
+    }
+
+    /// The efficiency classes present, lowest first. A system with one class
+    /// is not heterogeneous.
+    pub fn efficiency_classes(&self) -> Vec<u8> {
+        self.cores
+            .iter()
+            .map(|core| core.efficiency_class)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// The CPU set IDs of the cores in efficiency class `class`.
+    pub fn cpu_set_ids_of_class(&self, class: u8) -> Vec<u32> {
+        self.cores
+            .iter()
+            .filter(|core| core.efficiency_class == class)
+            .flat_map(|core| core.cpu_set_ids.iter().copied())
+            .collect()
+    }
+
+    /// The CPU set IDs a thread under `policy` is steered towards: the
+    /// highest efficiency class for the large policies, the lowest for the
+    /// small ones, and every set otherwise.
+    pub fn cpu_set_ids_for_policy(&self, policy: KHETERO_CPU_POLICY) -> Vec<u32> {
+        let classes = self.efficiency_classes();
+        let class = match policy {
+            KHETERO_CPU_POLICY::KHeteroCpuPolicyLarge
+            | KHETERO_CPU_POLICY::KHeteroCpuPolicyLargeOrIdle
+            | KHETERO_CPU_POLICY::KHeteroCpuPolicyBiasedLarge => classes.last(),
+            KHETERO_CPU_POLICY::KHeteroCpuPolicySmall
+            | KHETERO_CPU_POLICY::KHeteroCpuPolicySmallOrIdle
+            | KHETERO_CPU_POLICY::KHeteroCpuPolicyBiasedSmall => classes.first(),
+            _ => None,
+        };
+        match class {
+            Some(class) => self.cpu_set_ids_of_class(*class),
+            None => self.cpu_sets.iter().map(|set| set.id).collect(),
+        }
+    }
+}
+
+/// A relationship selects which records are returned.
+impl QueryInput for LOGICAL_PROCESSOR_RELATIONSHIP {
+    fn to_input(&self) -> InputBuffer {
+        InputBuffer::Separate(self.0.to_le_bytes().to_vec())
+    }
+}
+
+/// A NUMA proximity domain, as the firmware numbers it.
+#[derive(Debug, Copy, Clone, Default, Hash, PartialEq, Eq)]
+pub struct ProximityId(pub u32);
+
+/// Written to the `SYSTEM_NUMA_PROXIMITY_MAP` the node number is returned
+/// in.
+impl QueryInput for ProximityId {
+    fn to_input(&self) -> InputBuffer {
+        let mut map = [0; 8];
+        map[..4].copy_from_slice(&self.0.to_le_bytes());
+        InputBuffer::InPlace(map.to_vec())
+    }
+}
+
+/// The CPU masks and tag of `SystemCpuSetTagInformation`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuSetTag {
+    pub tag: u64,
+    pub cpu_sets: Vec<u64>,
+}
+
+fn read_u64s(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum SystemCpuSetInformation {}
+
+impl SystemInfo for SystemCpuSetInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemCpuSetInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x2000 };
+    /// The process whose allocated sets are flagged, or a null handle.
+    type Input = HANDLE;
+    type Output = Vec<CpuSet>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<CpuSet>, DecodeError> {
+        parse_cpu_sets(bytes)
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemLogicalProcessorAndGroupInformation {}
+
+impl SystemInfo for SystemLogicalProcessorAndGroupInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemLogicalProcessorAndGroupInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x2000 };
+    type Input = LOGICAL_PROCESSOR_RELATIONSHIP;
+    type Output = Vec<ProcessorRecord>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<ProcessorRecord>, DecodeError> {
+        parse_logical_processors(bytes)
+    }
+}
+
+/// `SystemNumaProximityNodeInformation`: the node number of a proximity
+/// domain.
+#[derive(Debug)]
+pub enum SystemNumaProximityNodeInformation {}
+
+impl SystemInfo for SystemNumaProximityNodeInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemNumaProximityNodeInformation;
+    const SIZING: Sizing = Sizing::Fixed(8);
+    type Input = ProximityId;
+    type Output = u16;
+
+    fn decode(bytes: &[u8]) -> Result<u16, DecodeError> {
+        Reader::at(bytes, 4).u16()
+    }
+}
+
+/// `SystemNodeDistanceInformation`: the distance from a node to every node,
+/// by node number.
+#[derive(Debug)]
+pub enum SystemNodeDistanceInformation {}
+
+impl SystemInfo for SystemNodeDistanceInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemNodeDistanceInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x100 };
+    /// The node number.
+    type Input = u16;
+    type Output = Vec<u16>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<u16>, DecodeError> {
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect())
+    }
+}
+
+/// `SystemAllowedCpuSetsInformation`: the system-wide allowed CPU set
+/// masks.
+#[derive(Debug)]
+pub enum SystemAllowedCpuSetsInformation {}
+
+impl SystemInfo for SystemAllowedCpuSetsInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS =
+        SYSTEM_INFORMATION_CLASS::SystemAllowedCpuSetsInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x100 };
+    type Input = ();
+    type Output = Vec<u64>;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<u64>, DecodeError> {
+        Ok(read_u64s(bytes))
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemCpuSetTagInformation {}
+
+impl SystemInfo for SystemCpuSetTagInformation {
+    const CLASS: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS::SystemCpuSetTagInformation;
+    const SIZING: Sizing = Sizing::Variable { initial: 0x100 };
+    type Input = ();
+    type Output = CpuSetTag;
+
+    fn decode(bytes: &[u8]) -> Result<CpuSetTag, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let tag = reader.u64()?;
+        Ok(CpuSetTag {
+            tag,
+            cpu_sets: read_u64s(reader.bytes(reader.remaining())?),
+        })
+    }
+}
+
+fn check_win32(ok: bool) -> Result<(), WIN32_ERROR> {
+    if ok {
+        Ok(())
+    } else {
+        Err(WIN32_ERROR(unsafe { RtlGetLastWin32Error() } as u32))
+    }
+}
+
+/// Reads a CPU set ID list, sized by a first call.
+fn read_ids(get: impl Fn(Option<&mut [u32]>, &mut u32) -> bool) -> Result<Vec<u32>, WIN32_ERROR> {
+    let mut count = 0;
+    loop {
+        let mut ids = vec![0; count as usize];
+        let slice = (!ids.is_empty()).then_some(ids.as_mut_slice());
+        if get(slice, &mut count) {
+            ids.truncate(count as usize);
+            return Ok(ids);
+        }
+        // Too small a buffer reports the count needed; anything else is an
+        // error of its own.
+        if count as usize <= ids.len() {
+            return check_win32(false).map(|_| Vec::new());
+        }
+    }
+}
+
+/// Sets the CPU sets new threads of `process` run on by default. An empty
+/// list clears them.
+pub fn set_process_default_cpu_sets(process: HANDLE, ids: &[u32]) -> Result<(), WIN32_ERROR> {
+    let ids = (!ids.is_empty()).then_some(ids);
+    check_win32(unsafe { SetProcessDefaultCpuSets(process, ids) }.as_bool())
+}
+
+/// The default CPU sets of `process`, empty when none are set.
+pub fn process_default_cpu_sets(process: HANDLE) -> Result<Vec<u32>, WIN32_ERROR> {
+    read_ids(|ids, count| unsafe { GetProcessDefaultCpuSets(process, ids, count) }.as_bool())
+}
+
+/// Sets the CPU sets `thread` runs on. An empty list clears them.
+pub fn set_thread_selected_cpu_sets(thread: HANDLE, ids: &[u32]) -> Result<(), WIN32_ERROR> {
+    check_win32(unsafe { SetThreadSelectedCpuSets(thread, ids) }.as_bool())
+}
+
+/// The CPU sets selected for `thread`, empty when none are.
+pub fn thread_selected_cpu_sets(thread: HANDLE) -> Result<Vec<u32>, WIN32_ERROR> {
+    read_ids(|ids, count| unsafe { GetThreadSelectedCpuSets(thread, ids, count) }.as_bool())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use windows::Win32::System::SystemInformation::{CacheData, CacheUnified};
+
+    use super::{super::QueryFailure, *};
+    use crate::buffer::Writer;
+
+    /// Writes a `SYSTEM_CPU_SET_INFORMATION` record of `size` bytes, at
+    /// least the 32 this version reads.
+    fn write_cpu_set(
+        writer: &mut Writer,
+        size: u32,
+        id: u32,
+        processor: ProcessorNumber,
+        core_index: u8,
+        efficiency_class: u8,
+        flags: CpuSetFlags,
+    ) {
+        writer
+            .u32(size)
+            .u32(CpuSetInformation.0 as u32)
+            .u32(id)
+            .u16(processor.group)
+            .u8(processor.number)
+            .u8(core_index)
+            .u8(0)
+            .u8(processor.group as u8)
+            .u8(efficiency_class)
+            .u8(flags.0)
+            .u32(u32::from(efficiency_class))
+            .u64(0x5a5a_0000 | u64::from(id))
+            .zeros(size as usize - 32);
+    }
+
+    fn processor(group: u16, number: u8) -> ProcessorNumber {
+        ProcessorNumber { group, number }
+    }
+
+    /// `SystemCpuSetInformation` output for a hybrid part: two P-cores with
+    /// two threads each in efficiency class 1, then four E-cores in class 0.
+    /// A record of an unknown type sits between them, and the last record is
+    /// larger than this version knows.
+    fn hybrid_cpu_sets() -> Vec<u8> {
+        let mut writer = Writer::new();
+        for number in 0..4 {
+            let flags = if number == 0 {
+                CpuSetFlags::ALLOCATED | CpuSetFlags::ALLOCATED_TO_TARGET_PROCESS
+            } else {
+                CpuSetFlags::default()
+            };
+            write_cpu_set(
+                &mut writer,
+                32,
+                0x100 + u32::from(number),
+                processor(0, number),
+                number & !1,
+                1,
+                flags,
+            );
+        }
+        writer.u32(24).u32(7).bytes(&[0xcc; 16]);
+        for number in 4..8 {
+            let (size, flags) = if number == 7 {
+                (48, CpuSetFlags::PARKED)
+            } else {
+                (32, CpuSetFlags::default())
+            };
+            write_cpu_set(
+                &mut writer,
+                size,
+                0x100 + u32::from(number),
+                processor(0, number),
+                number,
+                0,
+                flags,
+            );
+        }
+        writer.into_inner()
+    }
+
+    /// Writes a `GROUP_AFFINITY`.
+    fn write_affinity(writer: &mut Writer, group: u16, mask: u64) {
+        writer.u64(mask).u16(group).zeros(6);
+    }
+
+    /// Writes a `SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX` header sized for
+    /// `body` and `padding` bytes after it.
+    fn write_record(
+        writer: &mut Writer,
+        relationship: LOGICAL_PROCESSOR_RELATIONSHIP,
+        body: &Writer,
+        padding: usize,
+    ) {
+        writer
+            .u32(relationship.0 as u32)
+            .u32((8 + body.len() + padding) as u32)
+            .bytes(body.as_slice())
+            .bytes(&vec![0xee; padding]);
+    }
+
+    fn write_processor(
+        writer: &mut Writer,
+        relationship: LOGICAL_PROCESSOR_RELATIONSHIP,
+        smt: bool,
+        efficiency_class: u8,
+        affinities: &[(u16, u64)],
+    ) {
+        let mut body = Writer::new();
+        body.u8(smt as u8)
+            .u8(efficiency_class)
+            .zeros(20)
+            .u16(affinities.len() as u16);
+        for (group, mask) in affinities {
+            write_affinity(&mut body, *group, *mask);
+        }
+        write_record(writer, relationship, &body, 0);
+    }
+
+    fn write_node(
+        writer: &mut Writer,
+        relationship: LOGICAL_PROCESSOR_RELATIONSHIP,
+        number: u32,
+        group_count: u16,
+        affinities: &[(u16, u64)],
+    ) {
+        let mut body = Writer::new();
+        body.u32(number).zeros(18).u16(group_count);
+        for (group, mask) in affinities {
+            write_affinity(&mut body, *group, *mask);
+        }
+        write_record(writer, relationship, &body, 0);
+    }
+
+    fn write_cache(
+        writer: &mut Writer,
+        level: u8,
+        size: u32,
+        cache_type: PROCESSOR_CACHE_TYPE,
+        affinity: (u16, u64),
+    ) {
+        let mut body = Writer::new();
+        body.u8(level)
+            .u8(if level == 3 { 12 } else { 8 })
+            .u16(64)
+            .u32(size)
+            .u32(cache_type.0 as u32)
+            .zeros(18)
+            .u16(1);
+        write_affinity(&mut body, affinity.0, affinity.1);
+        write_record(writer, RelationCache, &body, 0);
+    }
+
+    fn write_groups(writer: &mut Writer, maximum_groups: u16, groups: &[(u8, u8, u64)]) {
+        let mut body = Writer::new();
+        body.u16(maximum_groups).u16(groups.len() as u16).zeros(20);
+        for (maximum, active, mask) in groups {
+            body.u8(*maximum).u8(*active).zeros(38).u64(*mask);
+        }
+        write_record(writer, RelationGroup, &body, 0);
+    }
+
+    /// `SystemLogicalProcessorAndGroupInformation` output matching
+    /// [`hybrid_cpu_sets`], with a shared L3, per-core L1 data caches on the
+    /// P-cores and one NUMA node reported under both relationships.
+    fn hybrid_records() -> Vec<u8> {
+        let mut writer = Writer::new();
+        write_processor(&mut writer, RelationProcessorCore, true, 1, &[(0, 0b11)]);
+        write_processor(&mut writer, RelationProcessorCore, true, 1, &[(0, 0b1100)]);
+        for number in 4..8 {
+            write_processor(
+                &mut writer,
+                RelationProcessorCore,
+                false,
+                0,
+                &[(0, 1 << number)],
+            );
+        }
+        write_processor(
+            &mut writer,
+            RelationProcessorPackage,
+            false,
+            0,
+            &[(0, 0xff)],
+        );
+        write_node(&mut writer, RelationNumaNode, 0, 0, &[(0, 0xff)]);
+        write_node(&mut writer, RelationNumaNodeEx, 0, 1, &[(0, 0xff)]);
+        write_cache(&mut writer, 1, 48 << 10, CacheData, (0, 0b11));
+        write_cache(&mut writer, 1, 48 << 10, CacheData, (0, 0b1100));
+        write_cache(&mut writer, 3, 24 << 20, CacheUnified, (0, 0xff));
+        write_groups(&mut writer, 1, &[(8, 8, 0xff)]);
+        writer.into_inner()
+    }
+
+    /// Two groups of four processors, each its own NUMA node. The package
+    /// spans both groups, the cores of group 1 run two threads each, one
+    /// core record is padded past its last mask, and a relationship this
+    /// version does not know comes before the groups.
+    fn multi_group_records() -> Vec<u8> {
+        let mut writer = Writer::new();
+        for number in 0..4 {
+            write_processor(
+                &mut writer,
+                RelationProcessorCore,
+                false,
+                0,
+                &[(0, 1 << number)],
+            );
+        }
+        let mut body = Writer::new();
+        body.u8(1).u8(0).zeros(20).u16(1);
+        write_affinity(&mut body, 1, 0b11);
+        write_record(&mut writer, RelationProcessorCore, &body, 16);
+        write_processor(&mut writer, RelationProcessorCore, true, 0, &[(1, 0b1100)]);
+        write_processor(
+            &mut writer,
+            RelationProcessorPackage,
+            false,
+            0,
+            &[(0, 0xf), (1, 0xf)],
+        );
+        write_node(&mut writer, RelationNumaNodeEx, 0, 1, &[(0, 0xf)]);
+        write_node(&mut writer, RelationNumaNodeEx, 1, 1, &[(1, 0xf)]);
+        let mut unknown = Writer::new();
+        unknown.u32(0xdead_beef).u32(2);
+        write_record(
+            &mut writer,
+            LOGICAL_PROCESSOR_RELATIONSHIP(0x42),
+            &unknown,
+            0,
+        );
+        write_groups(&mut writer, 4, &[(4, 4, 0xf), (4, 4, 0xf)]);
+        writer.into_inner()
+    }
+
+    /// `SystemCpuSetInformation` output for [`multi_group_records`]: the
+    /// IDs restart their low byte in each group.
+    fn multi_group_cpu_sets() -> Vec<u8> {
+        let mut writer = Writer::new();
+        for group in 0..2 {
+            for number in 0..4 {
+                let core = if group == 0 { number } else { number & !1 };
+                write_cpu_set(
+                    &mut writer,
+                    32,
+                    0x100 * (u32::from(group) + 1) + u32::from(number),
+                    processor(group, number),
+                    core,
+                    0,
+                    CpuSetFlags::default(),
+                );
+            }
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn decodes_hybrid_cpu_sets() {
+        let sets = parse_cpu_sets(&hybrid_cpu_sets()).unwrap();
+        assert_eq!(sets.len(), 8);
+        assert_eq!(
+            sets[1],
+            CpuSet {
+                id: 0x101,
+                group: 0,
+                logical_processor_index: 1,
+                core_index: 0,
+                last_level_cache_index: 0,
+                numa_node_index: 0,
+                efficiency_class: 1,
+                flags: CpuSetFlags::default(),
+                scheduling_class: 1,
+                allocation_tag: 0x5a5a_0101,
+            }
+        );
+        assert!(
+            sets[0]
+                .flags
+                .contains(CpuSetFlags::ALLOCATED_TO_TARGET_PROCESS)
+        );
+        assert!(!sets[0].flags.contains(CpuSetFlags::PARKED));
+        assert_eq!(
+            sets.iter().map(|set| set.core_index).collect::<Vec<_>>(),
+            [0, 0, 2, 2, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            sets.iter()
+                .map(|set| set.efficiency_class)
+                .collect::<Vec<_>>(),
+            [1, 1, 1, 1, 0, 0, 0, 0]
+        );
+        assert_eq!(sets[7].id, 0x107);
+        assert!(sets[7].flags.contains(CpuSetFlags::PARKED));
+        assert_eq!(sets[7].allocation_tag, 0x5a5a_0107);
+    }
+
+    #[test]
+    fn skips_cpu_set_records_by_size() {
+        let bytes = hybrid_cpu_sets();
+        // A record of another type is read no further than its header.
+        let mut unknown = Writer::new();
+        unknown.u32(16).u32(1).u64(u64::MAX);
+        assert!(parse_cpu_sets(unknown.as_slice()).unwrap().is_empty());
+        // The 48-byte record ends the buffer: cutting any of its tail off
+        // leaves its size pointing past the end.
+        assert!(matches!(
+            parse_cpu_sets(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::Truncated {
+                offset: 248,
+                needed: 48
+            })
+        ));
+        // A CPU set record shorter than the fields it holds.
+        let mut short = Writer::new();
+        short.u32(24).u32(0).zeros(16);
+        assert!(matches!(
+            parse_cpu_sets(short.as_slice()),
+            Err(DecodeError::Truncated { .. })
+        ));
+        // A size too small for the header would never advance.
+        let mut stuck = Writer::new();
+        stuck.u32(4).u32(7);
+        assert!(matches!(
+            parse_cpu_sets(stuck.as_slice()),
+            Err(DecodeError::Invalid { offset: 8, .. })
+        ));
+        assert!(parse_cpu_sets(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decodes_smt_and_hybrid_cores() {
+        let records = parse_logical_processors(&hybrid_records()).unwrap();
+        assert_eq!(records.len(), 13);
+        assert_eq!(
+            records[0],
+            ProcessorRecord::Core(ProcessorRelationship {
+                smt: true,
+                efficiency_class: 1,
+                groups: vec![GroupAffinity {
+                    group: 0,
+                    mask: 0b11,
+                }],
+            })
+        );
+        assert_eq!(
+            records[5],
+            ProcessorRecord::Core(ProcessorRelationship {
+                smt: false,
+                efficiency_class: 0,
+                groups: vec![GroupAffinity {
+                    group: 0,
+                    mask: 0x80,
+                }],
+            })
+        );
+        // The pre-Windows 11 node has a zero group count and one mask.
+        let node = NumaNode {
+            number: 0,
+            groups: vec![GroupAffinity {
+                group: 0,
+                mask: 0xff,
+            }],
+        };
+        assert_eq!(records[7], ProcessorRecord::NumaNode(node.clone()));
+        assert_eq!(records[8], ProcessorRecord::NumaNode(node));
+        assert_eq!(
+            records[11],
+            ProcessorRecord::Cache(Cache {
+                level: 3,
+                associativity: 12,
+                line_size: 64,
+                size: 24 << 20,
+                cache_type: CacheUnified,
+                groups: vec![GroupAffinity {
+                    group: 0,
+                    mask: 0xff,
+                }],
+            })
+        );
+        assert_eq!(
+            records[12],
+            ProcessorRecord::Group {
+                maximum_groups: 1,
+                active_groups: 1,
+                groups: vec![GroupInfo {
+                    maximum_processors: 8,
+                    active_processors: 8,
+                    active_mask: 0xff,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_multi_group_affinity() {
+        let records = parse_logical_processors(&multi_group_records()).unwrap();
+        assert_eq!(records.len(), 11);
+        // The padded core is read from its own bytes and the next record
+        // starts after the padding.
+        assert_eq!(
+            records[4],
+            ProcessorRecord::Core(ProcessorRelationship {
+                smt: true,
+                efficiency_class: 0,
+                groups: vec![GroupAffinity {
+                    group: 1,
+                    mask: 0b11,
+                }],
+            })
+        );
+        let ProcessorRecord::Package(package) = &records[6] else {
+            panic!("expected a package, got {:?}", records[6]);
+        };
+        assert_eq!(
+            package.groups,
+            [
+                GroupAffinity {
+                    group: 0,
+                    mask: 0xf,
+                },
+                GroupAffinity {
+                    group: 1,
+                    mask: 0xf,
+                },
+            ]
+        );
+        assert!(package.groups[1].contains(processor(1, 3)));
+        assert!(!package.groups[1].contains(processor(0, 3)));
+        assert!(!package.groups[1].contains(processor(1, 4)));
+        assert_eq!(
+            package.groups[1].processors().collect::<Vec<_>>(),
+            (0..4)
+                .map(|number| processor(1, number))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            records[9],
+            ProcessorRecord::Other {
+                relationship: LOGICAL_PROCESSOR_RELATIONSHIP(0x42),
+                data: vec![0xef, 0xbe, 0xad, 0xde, 2, 0, 0, 0],
+            }
+        );
+        let ProcessorRecord::Group {
+            maximum_groups: 4,
+            active_groups: 2,
+            groups,
+        } = &records[10]
+        else {
+            panic!("expected two active groups, got {:?}", records[10]);
+        };
+        assert_eq!(groups[1].active_mask, 0xf);
+    }
+
+    #[test]
+    fn rejects_bad_record_sizes() {
+        let bytes = hybrid_records();
+        assert!(matches!(
+            parse_logical_processors(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::Truncated { .. })
+        ));
+        // A size smaller than the header.
+        let mut small = Writer::new();
+        small.u32(RelationProcessorCore.0 as u32).u32(4);
+        assert!(matches!(
+            parse_logical_processors(small.as_slice()),
+            Err(DecodeError::Invalid { offset: 8, .. })
+        ));
+        // A group count with more masks than the record holds.
+        let mut body = Writer::new();
+        body.u8(0).u8(0).zeros(20).u16(2);
+        write_affinity(&mut body, 0, 1);
+        let mut overlong = Writer::new();
+        write_record(&mut overlong, RelationProcessorCore, &body, 0);
+        assert!(matches!(
+            parse_logical_processors(overlong.as_slice()),
+            Err(DecodeError::Truncated {
+                offset: 24,
+                needed: 32
+            })
+        ));
+        // Active groups past the end of the record.
+        let mut groups = Writer::new();
+        groups
+            .u16(2)
+            .u16(2)
+            .zeros(20)
+            .u8(4)
+            .u8(4)
+            .zeros(38)
+            .u64(0xf);
+        let mut record = Writer::new();
+        write_record(&mut record, RelationGroup, &groups, 0);
+        assert!(matches!(
+            parse_logical_processors(record.as_slice()),
+            Err(DecodeError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn builds_hybrid_topology() {
+        let topology = Topology::new(
+            parse_logical_processors(&hybrid_records()).unwrap(),
+            parse_cpu_sets(&hybrid_cpu_sets()).unwrap(),
+        );
+        assert_eq!(topology.cores.len(), 6);
+        assert_eq!(topology.packages.len(), 1);
+        assert_eq!(topology.nodes.len(), 1);
+        assert_eq!(topology.caches.len(), 3);
+        assert_eq!(topology.groups.len(), 1);
+        assert_eq!(topology.logical_processors().count(), 8);
+        assert_eq!(
+            topology.cores[1],
+            Core {
+                efficiency_class: 1,
+                processors: vec![processor(0, 2), processor(0, 3)],
+                cpu_set_ids: vec![0x102, 0x103],
+            }
+        );
+        assert_eq!(topology.siblings(processor(0, 3)), [processor(0, 2)]);
+        assert!(topology.siblings(processor(0, 5)).is_empty());
+        assert_eq!(topology.cpu_set(processor(0, 6)).unwrap().id, 0x106);
+        assert_eq!(topology.node_of(processor(0, 7)).unwrap().number, 0);
+        let caches = topology.caches_of(processor(0, 1));
+        assert_eq!(
+            caches.iter().map(|cache| cache.level).collect::<Vec<_>>(),
+            [1, 3]
+        );
+        assert_eq!(topology.caches_of(processor(0, 4)).len(), 1);
+        assert_eq!(topology.efficiency_classes(), [0, 1]);
+        assert_eq!(
+            topology.cpu_set_ids_for_policy(KHETERO_CPU_POLICY::KHeteroCpuPolicyLarge),
+            [0x100, 0x101, 0x102, 0x103]
+        );
+        assert_eq!(
+            topology.cpu_set_ids_for_policy(KHETERO_CPU_POLICY::KHeteroCpuPolicyBiasedSmall),
+            [0x104, 0x105, 0x106, 0x107]
+        );
+        assert_eq!(
+            topology
+                .cpu_set_ids_for_policy(KHETERO_CPU_POLICY::KHeteroCpuPolicyAll)
+                .len(),
+            8
+        );
+    }
+
+    #[test]
+    fn builds_multi_group_topology() {
+        let topology = Topology::new(
+            parse_logical_processors(&multi_group_records()).unwrap(),
+            parse_cpu_sets(&multi_group_cpu_sets()).unwrap(),
+        );
+        assert_eq!(topology.cores.len(), 6);
+        assert_eq!(topology.nodes.len(), 2);
+        assert_eq!(topology.groups.len(), 2);
+        assert_eq!(topology.logical_processors().count(), 8);
+        // The same processor number in each group is a different processor.
+        assert!(topology.siblings(processor(0, 1)).is_empty());
+        assert_eq!(topology.siblings(processor(1, 1)), [processor(1, 0)]);
+        assert_eq!(topology.node_of(processor(0, 2)).unwrap().number, 0);
+        assert_eq!(topology.node_of(processor(1, 2)).unwrap().number, 1);
+        assert!(topology.node_of(processor(2, 0)).is_none());
+        assert_eq!(topology.cores[0].cpu_set_ids, [0x100]);
+        assert_eq!(topology.cores[5].cpu_set_ids, [0x202, 0x203]);
+        // One efficiency class steers every policy to every set.
+        assert_eq!(
+            topology.cpu_set_ids_for_policy(KHETERO_CPU_POLICY::KHeteroCpuPolicySmall),
+            [0x100, 0x101, 0x102, 0x103, 0x200, 0x201, 0x202, 0x203]
+        );
+    }
+
+    /// Answers each class with a fixture, recording the inputs.
+    #[derive(Default)]
+    struct Fixtures {
+        inputs: RefCell<Vec<(SYSTEM_INFORMATION_CLASS, Option<Vec<u8>>)>>,
+    }
+
+    impl SystemBackend for Fixtures {
+        fn query(
+            &self,
+            class: SYSTEM_INFORMATION_CLASS,
+            input: Option<&[u8]>,
+            buffer: &mut [u64],
+        ) -> Result<usize, QueryFailure> {
+            self.inputs
+                .borrow_mut()
+                .push((class, input.map(<[u8]>::to_vec)));
+            let output = match class {
+                SYSTEM_INFORMATION_CLASS::SystemLogicalProcessorAndGroupInformation => {
+                    multi_group_records()
+                }
+                SYSTEM_INFORMATION_CLASS::SystemCpuSetInformation => multi_group_cpu_sets(),
+                _ => unreachable!("unexpected class {class:?}"),
+            };
+            let buffer = super::super::as_bytes_mut(buffer);
+            buffer[..output.len()].copy_from_slice(&output);
+            Ok(output.len())
+        }
+    }
+
+    #[test]
+    fn queries_every_relationship() {
+        let backend = Fixtures::default();
+        let topology = Topology::query(&backend).unwrap();
+        assert_eq!(topology.cpu_sets.len(), 8);
+        assert_eq!(
+            *backend.inputs.borrow(),
+            [
+                (
+                    SYSTEM_INFORMATION_CLASS::SystemLogicalProcessorAndGroupInformation,
+                    Some(RelationAll.0.to_le_bytes().to_vec()),
+                ),
+                (
+                    SYSTEM_INFORMATION_CLASS::SystemCpuSetInformation,
+                    Some(vec![0; mem::size_of::<HANDLE>()]),
+                ),
+            ]
+        );
+    }
+}
@@ -23,6 +23,7 @@ pub const WINSTATION_CURRENT_GUEST_ACCESS: u32 = 72;
 pub const WINSTATION_USER_ACCESS: u32 = 289;
 pub const WINSTATION_CURRENT_USER_ACCESS: u32 = 590;
 pub const WINSTATION_ALL_ACCESS: u32 = 983999;
+pub const WINSTATIONCOMMENT_LENGTH: u32 = 60;
 pub const WDPREFIX_LENGTH: u32 = 12;
 pub const CALLBACK_LENGTH: u32 = 50;
 pub const DLLNAME_LENGTH: u32 = 32;
@@ -128,7 +129,7 @@ pub enum WINSTATIONSTATECLASS {
 pub struct SESSIONIDW {
     pub Anonymous1: SESSIONIDW_1,
     pub WinStationName: [u16; 33],
-    pub State: WINSTATIONSTATECLASS,
+    pub State: i32,
 }
 
 #[repr(C)]
@@ -310,8 +311,8 @@ pub struct USERCONFIG {
     pub WorkDirectory: [u16; 257],
     pub InitialProgram: [u16; 257],
     pub CallbackNumber: [u16; 51],
-    pub Callback: CALLBACKCLASS,
-    pub Shadow: SHADOWCLASS,
+    pub Callback: i32,
+    pub Shadow: i32,
     pub MaxConnectionTime: u32,
     pub MaxDisconnectionTime: u32,
     pub MaxIdleTime: u32,
@@ -950,6 +951,29 @@ impl USERCONFIG {
     }
 }
 
+#[repr(C)]
+pub struct WINSTATIONCONFIG {
+    pub Comment: [u16; WINSTATIONCOMMENT_LENGTH as usize + 1],
+    pub User: USERCONFIG,
+    pub OEMId: [i8; 4],
+}
+
+impl Default for WINSTATIONCONFIG {
+    fn default() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+}
+
+impl std::fmt::Debug for WINSTATIONCONFIG {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "WINSTATIONCONFIG {{ Comment: {:?}, User: {:?}, OEMId: {:?} }}",
+            self.Comment, self.User, self.OEMId
+        )
+    }
+}
+
 #[repr(i32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub enum SDCLASS {
@@ -1836,7 +1860,7 @@ impl std::fmt::Debug for PROTOCOLSTATUS {
 
 #[repr(C)]
 pub struct WINSTATIONINFORMATION {
-    pub ConnectState: WINSTATIONSTATECLASS,
+    pub ConnectState: i32,
     pub WinStationName: [u16; 33],
     pub LogonId: u32,
     pub ConnectTime: i64,